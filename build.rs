@@ -0,0 +1,22 @@
+use std::process::Command;
+
+/// Record the git commit the binary is built from as HULIOS_GIT_COMMIT, for
+/// `hulios version`. Packagers building from a tarball can set it themselves.
+fn main() {
+    println!("cargo:rerun-if-env-changed=HULIOS_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    if std::env::var("HULIOS_GIT_COMMIT").is_ok() {
+        return;
+    }
+    let git = |args: &[&str]| Command::new("git").args(args).output().ok().filter(|output| output.status.success());
+    let Some(head) = git(&["rev-parse", "--short=12", "HEAD"]) else {
+        return;
+    };
+    let mut commit = String::from_utf8_lossy(&head.stdout).trim().to_string();
+    // Uncommitted changes to tracked files
+    if git(&["diff", "--quiet", "HEAD"]).is_none() {
+        commit.push_str("-dirty");
+    }
+    println!("cargo:rustc-env=HULIOS_GIT_COMMIT={}", commit);
+}
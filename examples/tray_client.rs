@@ -0,0 +1,95 @@
+//! Example client of the HULIOS tray protocol, usable as a waybar module.
+//!
+//! Without arguments it subscribes and prints one waybar JSON line per
+//! state change:
+//!
+//! ```json
+//! "custom/hulios": {
+//!     "exec": "/usr/local/bin/hulios-tray-client",
+//!     "return-type": "json",
+//!     "on-click": "/usr/local/bin/hulios-tray-client newnym"
+//! }
+//! ```
+//!
+//! With an argument (`start`, `stop`, `restart`, `reload`, `newnym`, or a
+//! mode: `off`, `full`, `dns-only`) it performs that action and exits.
+//!
+//! Build with `cargo build --release --example tray_client`. The user
+//! needs to be in the `hulios` group (`sudo hulios helper install`).
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+const SOCKET: &str = "/run/hulios-tray.sock";
+
+/// The protocol version this client understands
+const PROTOCOL: u64 = 1;
+
+fn main() -> ExitCode {
+    let action = std::env::args().nth(1);
+    let request = match action.as_deref() {
+        None => json!({ "request": "subscribe" }),
+        Some(mode @ ("off" | "full" | "dns-only")) => json!({ "request": "set_mode", "mode": mode }),
+        Some(action) => json!({ "request": action }),
+    };
+
+    let mut stream = match UnixStream::connect(SOCKET) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Cannot connect to {}: {}", SOCKET, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if writeln!(stream, "{}", request).is_err() {
+        return ExitCode::FAILURE;
+    }
+
+    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        match message["event"].as_str() {
+            Some("hello") if message["protocol"].as_u64() != Some(PROTOCOL) => {
+                eprintln!("Unsupported tray protocol {}", message["protocol"]);
+                return ExitCode::FAILURE;
+            }
+            Some("state") => println!("{}", waybar(&message)),
+            Some("notification") => eprintln!("{}: {}", message["title"].as_str().unwrap_or_default(), message["body"].as_str().unwrap_or_default()),
+            Some("done") => {
+                if let Some(error) = message["error"].as_str() {
+                    eprintln!("{}", error);
+                }
+                return if message["ok"] == true { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+            }
+            Some("error") => {
+                eprintln!("{}", message["message"].as_str().unwrap_or_default());
+                return ExitCode::FAILURE;
+            }
+            // Anything newer than this client
+            _ => {}
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// A state message as waybar's custom module output
+fn waybar(state: &Value) -> Value {
+    let mode = state["mode"].as_str().unwrap_or("off");
+    let health = state["health"].as_str().unwrap_or("stopped");
+    let text = match (mode, state["bootstrap"].as_u64()) {
+        ("off", _) => "Tor off".to_string(),
+        ("full", Some(pct)) if pct < 100 => format!("Tor {}%", pct),
+        ("full", _) => "Tor".to_string(),
+        (mode, _) => mode.to_string(),
+    };
+    let mut tooltip = format!("HULIOS: {} ({})", mode, health);
+    if let Some(profile) = state["network_profile"].as_str() {
+        tooltip.push_str(&format!("\nNetwork profile: {}", profile));
+    }
+    for problem in state["problems"].as_array().into_iter().flatten() {
+        tooltip.push_str(&format!("\n{}", problem.as_str().unwrap_or_default()));
+    }
+    json!({ "text": text, "alt": mode, "class": health, "tooltip": tooltip })
+}
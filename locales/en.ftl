@@ -0,0 +1,1313 @@
+# HULIOS message catalog: English, the source every translation starts from.
+#
+# One message per line as `id = text`; indented lines continue the message
+# above on a new line. `{ $name }` is replaced with a value when the message
+# is shown. Copy this file to `<language>.ftl` (e.g. `fa.ftl`, `pt_BR.ftl`)
+# and translate the text after each `=`, leaving ids and `{ $name }` as they
+# are. `hulios locale missing <language>` lists what a translation lacks.
+
+## Commands
+
+cli-starting = Starting HULIOS...
+cli-started = HULIOS started successfully.
+cli-stopping = Stopping HULIOS...
+cli-stopped = HULIOS stopped.
+cli-restarting = Restarting HULIOS...
+cli-restarted = HULIOS restarted.
+cli-flushing = Flushing IPTables rules...
+cli-flushed = Rules flushed.
+cli-dns-localhost = DNS now points to localhost (Tor DNSPort)
+cli-override-syntax = expected KEY=VALUE, e.g. tor.bootstrap_timeout=120
+cli-error-checking = Error checking:
+cli-check-nothing = hulios { $command }: nothing to change
+cli-check-would = hulios { $command } would:
+cli-error = Error:
+cli-error-starting = Error starting HULIOS:
+cli-error-stopping = Error stopping HULIOS:
+cli-error-restarting = Error restarting HULIOS:
+cli-error-confirming-session = Error confirming the session:
+cli-error-reloading = Error reloading HULIOS:
+cli-error-flushing-rules = Error flushing rules:
+cli-recovering = Recovering from unclean shutdown...
+cli-error-recovering = Error recovering:
+cli-uninstalling = Uninstalling HULIOS...
+cli-error-uninstalling = Error uninstalling:
+cli-update-error = Update error:
+cli-error-applying-limit = Error applying limit:
+cli-error-showing-config = Error showing config:
+cli-error-editing-config = Error editing config:
+cli-error-setting-config = Error setting config:
+cli-invalid-config = Invalid config:
+cli-render-error = Render error:
+cli-error-loading-config = Error loading config:
+cli-debug-bundle-error = Debug bundle error:
+cli-leak-test-failed = Leak test failed:
+cli-no-leaks = No leaks found.
+cli-error-hardening-browsers = Error hardening browsers:
+cli-updating-exit-list = Updating Tor exit list...
+cli-exit-list-updated = Exit list updated: { $count } addresses
+cli-error-updating-exit-list = Error updating exit list:
+cli-error-configuring-package-manager = Error configuring the package manager proxy:
+cli-error-configuring-remotes = Error configuring remotes:
+cli-connect = hulios connect:
+cli-daemon-error = Daemon error:
+cli-error-rolling-back = Error rolling back:
+cli-overlay-router-error = Overlay router error:
+cli-network-event-error = Network event error:
+cli-doh-client-error = DoH client error:
+cli-tray-server-error = Tray server error:
+cli-error-running-isolated-app = Error running isolated app:
+cli-onion-service-error = Onion service error:
+cli-relay-error = Relay error:
+cli-appliance-error = Appliance error:
+cli-gateway-error = Gateway error:
+cli-attach-error = Attach error:
+cli-detach-error = Detach error:
+cli-dns-only-error = dns-only error:
+cli-autostart-error = Autostart error:
+cli-network-profile-error = Network profile error:
+cli-power-error = Power error:
+cli-mode-error = Mode error:
+cli-error-managing-aliases = Error managing aliases:
+cli-locale-error = Locale error:
+cli-error-installing-helper = Error installing the helper:
+cli-helper-error = Helper error:
+
+## Errors shared by several commands
+
+error-parse = Failed to parse { $path }
+error-not-root = HULIOS must be run as root.
+error-remove = Failed to remove { $path }
+error-no-iproute2 = Failed to run ip (is iproute2 installed?)
+error-no-binary = Cannot locate the hulios binary
+error-write = Failed to write { $path }
+error-no-cgroup2 = No cgroup v2 hierarchy mounted
+error-create = Failed to create { $path }
+error-not-running = HULIOS is not running; use `hulios start`
+error-control-unreachable = Cannot reach Tor's control port
+error-not-ip = '{ $address }' is not an IP address
+error-read = Failed to read { $path }
+error-open = Failed to open { $path }
+error-control-closed = Tor closed the control connection
+error-rolling-back = Rolling back...
+error-no-user = User '{ $user }' does not exist
+error-not-url = { $url } is not a URL
+error-no-host = { $url } has no host
+error-unmonitored = { $error }; the session runs unmonitored
+error-no-tor-user = User '{ $user }' does not exist (is tor installed?)
+error-stat = Cannot stat { $path }
+error-download = Failed to download { $url }
+error-unknown-uid = Unknown uid { $uid }
+error-nftables = Failed to program nftables
+error-tor-refused = Tor refused { $name }={ $value }
+error-truncated-dns = Truncated DNS reply
+error-unexpected = unexpected error: { $error }
+
+## Confirmation prompts
+
+confirm-will = { $action } will:
+confirm-no-terminal = Refusing to continue without confirmation; pass --yes to proceed non-interactively
+confirm-continue = Continue? [y/N]
+confirm-aborted = Aborted
+confirm-start-stop-any-tor = stop any tor started by HULIOS (the system tor.service only with --stop-system-tor)
+confirm-start-mask-stop-systemd = mask and stop systemd-resolved (or, under systemd-networkd, restrict it to Tor) and dnsmasq
+confirm-start-replace-etc-resolv = replace /etc/resolv.conf with a Tor-only version and make it immutable
+confirm-start-set-firewall-output = set the firewall OUTPUT policy to DROP and block all IPv6
+confirm-start-enable-net-ipv4 = enable net.ipv4.conf.all.route_localnet
+confirm-flush-reset-all-iptables = reset all iptables/ip6tables policies to ACCEPT
+confirm-flush-flush-nat-output = flush the nat OUTPUT, filter OUTPUT and filter INPUT chains, including rules not created by HULIOS
+confirm-flush-restore-etc-resolv = restore /etc/resolv.conf and unmask systemd-resolved
+confirm-uninstall-stop-restore-networking = stop HULIOS and restore networking (as `hulios recover`)
+confirm-uninstall-delete-installed-systemd = delete installed systemd units, dispatcher hooks and rule files
+confirm-uninstall-remove-selinux-module = remove the SELinux module and AppArmor rules of `hulios lsm install`
+confirm-uninstall-delete-var-lib = delete /var/lib/hulios (statistics, exit list, GeoIP databases)
+confirm-relay-run-second-tor = run a second tor, as the tor user, relaying traffic for other Tor users (never as an exit)
+confirm-relay-accept-connections-anywhere = accept connections from anywhere on relay.or_port (and relay.transport_port)
+confirm-relay-publish-machine-address = publish this machine's address in the Tor consensus, or hand it out through BridgeDB with relay.bridge
+confirm-relay-keep-relay-keys = keep the relay's keys in /var/lib/hulios/relay, so it keeps its identity across restarts
+confirm-dns-only-run-encrypted-dns = run an encrypted DNS resolver (DoH client or dnscrypt-proxy) on 127.0.0.1:9064 as nobody
+confirm-dns-only-mask-stop-systemd = mask and stop systemd-resolved (or, under systemd-networkd, restrict it) and dnsmasq
+confirm-dns-only-replace-etc-resolv = replace /etc/resolv.conf with a localhost-only version and make it immutable
+confirm-dns-only-redirect-all-dns = redirect all DNS to the resolver and block DNS over TLS and all IPv6 DNS; other traffic is NOT torified
+confirm-dns-only-enable-net-ipv4 = enable net.ipv4.conf.all.route_localnet
+confirm-switch-to-dns-only-stop-tor-daemon = stop HULIOS's Tor and its daemon; TCP will leave directly, NOT through Tor
+confirm-switch-to-dns-only-run-encrypted-dns = run an encrypted DNS resolver (DoH client or dnscrypt-proxy) on 127.0.0.1:9064 as nobody
+confirm-switch-to-dns-only-replace-tor-firewall = replace the Tor firewall rules with the dns-only ones: all DNS to the resolver, DNS over TLS and IPv6 DNS blocked
+confirm-switch-to-dns-only-point-etc-resolv = point /etc/resolv.conf at the resolver; the system resolver stays stopped
+confirm-switch-to-full-start-tor-system = start HULIOS's Tor (the system tor.service must not hold Tor's ports)
+confirm-switch-to-full-replace-dns-only = replace the dns-only firewall rules with Tor's: OUTPUT policy DROP and all IPv6 blocked
+confirm-switch-to-full-point-etc-resolv = point /etc/resolv.conf at Tor's DNSPort and stop the encrypted resolver
+confirm-autostart-set-autostart-enabled = set autostart.enabled in the config
+confirm-autostart-install-etc-networkmanager = install /etc/NetworkManager/dispatcher.d/90-hulios, which runs hulios whenever an interface comes up
+confirm-autostart-start-full-torification = start HULIOS (full torification) on every Wi-Fi network not in autostart.trusted_ssids
+confirm-autostart-trusted-network-ask = on a trusted network, ask on the desktop whether to stop a running session
+confirm-appliance-set-profile-appliance = set profile = "appliance" and gateway.lan_interface in the config
+confirm-appliance-torify-dns-tcp = torify the DNS and TCP of every machine on that interface, and forward nothing of theirs
+confirm-appliance-install-enable-service = install and enable hulios.service, so HULIOS starts at every boot
+confirm-appliance-never-show-desktop = never show desktop notifications; alerts go to the journal and notifications.webhook
+confirm-appliance-let-daemon-run = let the daemon run `hulios restart` by itself when the session breaks
+confirm-update-download-new-binary = download the new hulios binary and its minisign signature
+confirm-update-replace-binary-place = replace this hulios binary in place once the signature checks out
+confirm-debug-bundle-include-version-details = include the version details and `hulios doctor` results
+confirm-debug-bundle-include-last-lines = include the last lines of the HULIOS daemon log and Tor's log
+confirm-debug-bundle-include-firewall-rules = include the firewall rules (iptables-save, ip6tables-save, nft list ruleset) and /etc/resolv.conf
+confirm-debug-bundle-replace-public-ip = replace public IP addresses, URLs, onion names and the hostname with placeholders
+confirm-attach-route-all-ipv4 = route all IPv4 traffic through the gateway and point /etc/resolv.conf at it (made immutable)
+confirm-attach-set-firewall-output = set the firewall OUTPUT policy to DROP: only the gateway and TCP or DNS routed through it are allowed
+confirm-attach-block-all-ipv6 = block all IPv6, and every other host on the gateway's network
+confirm-setting-up-appliance = Setting up the appliance profile
+confirm-enabling-autostart = Enabling autostart
+confirm-harden-browser-policy = install a policy for { $name } ({ $policy_dir })
+confirm-harden-browser-disable = disable DNS-over-HTTPS, non-proxied WebRTC UDP and prefetching until `hulios stop`
+confirm-hardening-browsers = Hardening browsers
+confirm-writing = Writing { $path }
+confirm-starting-dns-only = Starting dns-only mode
+confirm-starting = Starting HULIOS
+confirm-flushing = Flushing
+confirm-uninstalling = Uninstalling
+confirm-switching-dns-only = Switching to dns-only mode
+confirm-switching-full = Switching to full torification
+confirm-localhost-make-sshd-listen = make sshd listen on 127.0.0.1 only, so it is reachable through the onion service and from this machine alone
+confirm-localhost-while-stopped-onion = while HULIOS is stopped, the onion service is down too: keep console access or another way in
+confirm-locking-sshd = Locking sshd to localhost
+confirm-starting-relay = Starting the relay
+confirm-updating = Updating { $path } to { $version }
+confirm-attaching = Attaching to the gateway { $gateway }
+
+## Start, stop and recovery
+
+engine-attached-to-gateway = This machine is attached to a gateway; run `sudo hulios detach-gateway` first
+engine-dns-only-mode-active = dns-only mode is active; switch with `sudo hulios mode set full`
+engine-system-prepared = System prepared in { $seconds }s
+engine-failed-start-async-runtime = Failed to start the async runtime
+engine-fix = Fix: { $fix }
+engine-retrying = Retrying in { $seconds }s (attempt { $attempt } of { $attempts })
+engine-retrying-bridges = Retrying in { $seconds }s (attempt { $attempt } of { $attempts }) over the configured bridges
+engine-failed-create-data-dir = Failed to create data dir
+engine-failed-chown-data-dir = Failed to chown data dir
+engine-tor-starts-without-kept = { $error }; Tor starts without the kept data
+engine-tor-starting-pid = Tor starting (PID: { $tor_pid })...
+engine-could-not-remove-package = Could not remove the package manager proxy: { $error }
+engine-could-not-remove-browser = Could not remove the browser policies: { $error }
+engine-could-not-securely-delete = Could not securely delete { $path }: { $error }
+engine-session-artifacts-zeroed-deleted = Session artifacts zeroed and deleted
+engine-rollback-incomplete-run-sudo = Rollback incomplete ({ $error }), run: sudo hulios recover
+engine-not-running-starting = HULIOS is not running, starting it
+engine-firewall-stays-closed-run = The firewall stays closed; run `hulios stop` to restore the normal network
+engine-restarted-firewall-stayed-closed = HULIOS restarted, the firewall stayed closed throughout.
+engine-firewall-rules-unchanged = Firewall rules unchanged
+engine-nothing-reload = Nothing to reload
+engine-configuration-reloaded-without-restarting = Configuration reloaded without restarting Tor.
+engine-tor-refused-new-configuration = Tor refused the new configuration and keeps the old one (some options need `hulios restart`)
+engine-tor-configuration-reloaded-lines = Tor configuration reloaded ({ $added } lines added, { $removed } removed)
+engine-tor-configuration-unchanged = Tor configuration unchanged
+engine-tor-resource-limits-updated = Tor resource limits updated
+engine-firewall-rules-flushed-dns = Firewall rules flushed and DNS restored.
+engine-found-session-state-backend = Found session state from { $started } (backend: { $backend })
+engine-no-session-state-found = No session state found, inspecting artifacts anyway
+engine-leftover-unconfirmed = Unconfirmed --confirm-within session, rolling it back
+engine-leftover-tor = HULIOS tor process still running
+engine-leftover-rules = Firewall rules still installed
+engine-leftover-policy-routing = TPROXY policy routing still installed
+engine-leftover-attached = Still attached to a gateway
+engine-leftover-masked = Masked resolver services: { $masked }
+engine-leftover-resolved-dropin = systemd-resolved drop-in still installed
+engine-leftover-resolver-change = Resolver change left behind: { $leftover }
+engine-leftover-resolv-owned = /etc/resolv.conf still points at Tor
+engine-leftover-resolv-backup = Stale resolv.conf backup left behind
+engine-leftover-pkgproxy = Package manager proxy still configured
+engine-leftover-browser = Browser hardening policies still installed
+engine-leftover-route-localnet = route_localnet still enabled
+engine-leftover-removed = Removed stale { $path }
+engine-leftover-amnesic = Amnesic tmpfs still mounted
+engine-nothing-recover-system-clean = Nothing to recover, system is at its clean baseline.
+engine-recovered-leftover-normal-networking = Recovered { $repaired } leftover(s), normal networking restored.
+engine-tor-did-not-start = Tor did not start; still in dns-only mode
+engine-cannot-read-firewall-rules = Cannot read the firewall rules; check mode must be run as root.
+engine-check-apply-rules = apply the { $name } firewall rules
+engine-check-route-tproxy-marked-traffic = route TPROXY-marked traffic to the local stack (ip rule)
+engine-check-start-tor-instance = start the HULIOS tor instance
+engine-check-point-etc-resolv-conf = point /etc/resolv.conf at the Tor DNSPort
+engine-check-constrain-resolved = constrain systemd-resolved to the Tor DNSPort ({ $path })
+engine-check-mask-systemd-resolved = mask systemd-resolved
+engine-check-mask-dnsmasq = mask dnsmasq
+engine-check-forward-unbound = forward unbound to the Tor DNSPort ({ $path })
+engine-check-flush-nscd-hosts-cache = flush the nscd hosts cache
+engine-check-enable = enable { $key }
+engine-check-flush-firewall-rules = flush the firewall rules
+engine-check-remove-tproxy-policy-routing = remove the TPROXY policy routing rule
+engine-check-stop-tor-instance = stop the HULIOS tor instance
+engine-check-restore-etc-resolv-conf = restore /etc/resolv.conf
+engine-check-unmask = unmask { $unit }
+engine-check-remove-dropin = remove { $path } and restart systemd-resolved
+engine-check-remove-browser-hardening-policies = remove the browser hardening policies
+engine-check-restore = restore { $key } to { $previous }
+engine-check-destroy-amnesic-tmpfs = destroy the amnesic tmpfs
+engine-check-clear-recorded-session-state = clear the recorded session state
+engine-bandwidth-limits-removed = Bandwidth limits removed
+engine-tor-bandwidth-limited = Tor bandwidth limited to { $rate }/s
+engine-set-tor-bandwidth-rate = Set tor.bandwidth_rate in { $path } to keep it across restarts
+engine-set-gateway-listen-address = Set gateway.listen_address: { $interface }'s address is otherwise read from the interface at start
+engine-invalid-bandwidth-expected-e = Invalid bandwidth '{ $rate }', expected e.g. 2MB
+engine-unknown-bandwidth-unit-use = Unknown bandwidth unit '{ $unit }', use KB, MB or GB
+engine-tor-binary-must-absolute = tor.binary must be an absolute path, got '{ $path }'
+engine-tor-not-found = tor not found in { $candidates }
+engine-not-executable-file = { $path } is not an executable file
+engine-sha-256-mismatch-expected = { $path } SHA-256 mismatch: expected { $expected }, got { $actual }
+engine-not-owned-root-refusing = { $path } is not owned by root, refusing to execute tor
+engine-group-world-writable-refusing = { $path } is group/world writable, refusing to execute tor
+engine-daemon-could-not-start = The HULIOS daemon could not start tor
+engine-failed-run-systemd-run = Failed to run systemd-run (is systemd available?)
+engine-systemd-run-failed-start = systemd-run failed to start the confined tor unit
+engine-failed-query-tor-unit = Failed to query tor unit PID
+engine-confined-tor-unit-exited = Confined tor unit exited immediately, check { $path }
+engine-bootstrapping-bar = Bootstrapping
+engine-bootstrapping = Bootstrapping { $progress }% { $summary }
+engine-tor-bootstrapped = Tor bootstrapped in { $seconds }s
+engine-tor-process-died-during = Tor process died during bootstrap at { $progress }% ({ $summary }), check { $path }
+engine-tor-bootstrap-timed-out = Tor bootstrap timed out after { $seconds }s at { $progress }% ({ $summary }), check { $path }
+engine-tor-browser-refused = Tor Browser is running (PID { $pid }, user { $user }); close it first, or set tor.browser = "warn"
+engine-tor-browser-warning = Tor Browser is running (PID { $pid }, user { $user }). It is left alone, but its circuits will be built through HULIOS's Tor (Tor over Tor: slower, and no more anonymous).
+engine-tor-browser-instead = To use HULIOS's Tor instead, start it with TOR_SKIP_LAUNCH=1 TOR_SOCKS_PORT=9050.
+engine-neutralizing-system-resolver-treating = Neutralizing system resolver (treating as hostile)...
+engine-step-neutralizing-systemd-resolved = Neutralizing systemd-resolved
+engine-step-stopping-networkmanager-dispatcher = Stopping NetworkManager-dispatcher
+engine-step-masking-dnsmasq = Masking dnsmasq
+engine-step-adapting-local-resolvers = Adapting local resolvers
+engine-step-failed = { $name } failed: { $error }
+engine-step-failed-short = { $name } failed
+engine-systemd-resolved-failed-restart = systemd-resolved failed to restart with { $path }
+engine-systemd-resolved-constrained-tor = systemd-resolved constrained to the Tor DNSPort
+engine-restoring-system-resolver = Restoring system resolver...
+engine-taking-dns-ownership = Taking DNS ownership...
+engine-failed-write-resolv-conf = Failed to write resolv.conf
+engine-restoring-dns-configuration = Restoring DNS configuration...
+engine-could-not-keep-tor = Could not keep Tor's data: { $error }
+engine-stopping-system-tor-service = Stopping the system tor.service
+engine-system-tor-service-running = The system tor.service is running and holds port { $port }; stop it (`systemctl stop tor`) or use `hulios start --stop-system-tor`
+engine-system-tor-service-keeps = The system tor.service keeps running alongside HULIOS
+engine-step-stopping-tor = Stopping tor
+engine-step-neutralizing-system-resolver = Neutralizing the system resolver
+engine-step-enabling-route-localnet = Enabling route_localnet
+
+## Environment checks (`hulios doctor`)
+
+preflight-running-under-wsl2 = Running under WSL2
+preflight-wsl2-guidance = Only the WSL VM's traffic goes through Tor; Windows applications are not affected. With networkingMode=mirrored, set it back to NAT in .wslconfig.
+preflight-running-under-wsl1-which = Running under WSL1, which has no netfilter
+preflight-convert-distribution-wsl-set = Convert the distribution with `wsl --set-version <distro> 2`.
+preflight-running-container-without-cap = Running in a { $kind } container without CAP_NET_ADMIN
+preflight-container-without-cap-guidance = Start the container with --cap-add=NET_ADMIN --cap-add=NET_RAW (LXC: lxc.cap.keep), or run HULIOS on the host.
+preflight-running-container = Running in a { $kind } container
+preflight-only-container-network-namespace = Only this container's network namespace goes through Tor; the host and other containers do not.
+preflight-cap-net-admin-not = CAP_NET_ADMIN is not available
+preflight-run-root-without-capability = Run HULIOS as root without a capability bounding set that drops NET_ADMIN.
+preflight-kernel-module-not-available = Kernel module { $module } is not available
+preflight-load-host-modprobe-containers = Load it on the host (modprobe); containers cannot load kernel modules.
+preflight-kernel-module-guidance = Install your distribution's extra kernel modules (e.g. linux-modules-extra-$(uname -r)), or switch firewall.backend.
+preflight-cannot-verify-kernel-modules = Cannot verify kernel modules, /lib/modules/{ $release } is missing
+preflight-reboot-into-installed-kernel = Reboot into the installed kernel if it was just upgraded.
+preflight-iptables-not-installed = iptables is not installed
+preflight-install-iptables-set-firewall = Install iptables, or set firewall.backend = "nftables", which needs no userspace tools.
+preflight-see-doctor = { $problem }. { $guidance } (see `hulios doctor`)
+preflight-kernel-features-could-not = Kernel features could not be probed
+preflight-checking-environment = Checking the environment...
+preflight-works = { $feature }: works
+preflight-not-supported = { $feature }: not supported
+preflight-no-problems-found = No problems found
+preflight-can-run-here-caveats = HULIOS can run here, with the caveats above
+
+## Kernel feature probes
+
+features-owner-rejected = The kernel rejected the owner match probe rule
+features-owner-guidance = Tor's own traffic can't be told apart from everything else. Load xt_owner (nftables: a kernel with meta skuid support).
+features-redirect-rejected = The kernel rejected the REDIRECT probe rule
+features-redirect-guidance = Traffic can't be sent to Tor's TransPort and DNSPort. Load xt_REDIRECT (nftables: nft_redir).
+features-tproxy-rejected = The kernel rejected the TPROXY probe rule or policy routing is unavailable
+features-tproxy-guidance = Falling back to { $redirect } for firewall.redirect. Load xt_TPROXY (nftables: nft_tproxy) and install iproute2 to use TPROXY.
+features-state-unavailable = The state match is unavailable
+features-state-guidance = Using the conntrack match (xt_conntrack) instead; the ruleset is otherwise unchanged.
+features-connection-state-unavailable = Connection state matching is unavailable
+features-connection-state-guidance = The established-connection rules are left out: replies from local servers (e.g. sshd) are dropped. Load xt_conntrack (nftables: nft_ct) to keep them.
+features-route-localnet-unavailable = route_localnet can't be enabled
+features-route-localnet-guidance = DNS redirected to 127.0.0.1 may not be routed; the leak check after start will catch it. Make /proc/sys writable (containers: --sysctl net.ipv4.conf.all.route_localnet=1).
+features-owner-match = owner match
+features-connection-state-match = connection state match
+
+## SELinux and AppArmor
+
+lsm-selinux-enforcing-policy-confines = SELinux is enforcing and its policy confines tor
+lsm-policy-keeps-tor-away = The policy keeps tor away from { $path } and ports 9052/9061, so Tor fails to start; install HULIOS's policy module with `sudo hulios lsm install`.
+lsm-apparmor-profile-confines-tor = The AppArmor profile { $name } ({ $mode }) confines tor
+lsm-does-not-allow-so = It does not allow { $path }, so Tor fails to start; add HULIOS's rules to it with `sudo hulios lsm install`.
+lsm-port = port { $port }
+lsm-capability = capability { $capability }
+lsm-denied-tor-keeps-tor = { $lsm } denied tor { $operation } on { $target }: HULIOS keeps Tor's files under /tmp, outside the directories the policy allows
+lsm-denied-tor-binding-policy = { $lsm } denied tor binding { $target }: the policy only allows the standard tor ports, not HULIOS's control (9052) and DNS (9061) ports
+lsm-denied-tor-net-admin = { $lsm } denied tor net_admin, which the TPROXY TransPort needs (firewall.redirect = "tproxy")
+lsm-denied-tor = { $lsm } denied tor { $operation } on { $target }
+lsm-fix-sudo-lsm-install = Fix: `sudo hulios lsm install` adds what HULIOS's Tor needs to the policy, then start again
+lsm-tor-was-denied-access = tor was denied access in the last hour:
+lsm-could-not-relabel-tor = Could not relabel Tor's files (restorecon); SELinux may deny Tor access to them
+lsm-selinux = SELinux: { $mode } ({ $binary } is { $label })
+lsm-policy-module-installed = The HULIOS policy module is installed
+lsm-policy-module-not-installed = The HULIOS policy module is not installed
+lsm-selinux-not-enabled = SELinux: not enabled
+lsm-apparmor-confined-profile = AppArmor: { $binary } is confined by the profile { $name } ({ $mode })
+lsm-rules-local-include = HULIOS's rules are in its local include
+lsm-rules-not-local-include = HULIOS's rules are not in its local include
+lsm-apparmor-no-profile-confines = AppArmor: no profile confines { $binary }
+lsm-neither-selinux-nor-apparmor = Neither SELinux nor an AppArmor profile confines { $binary }; nothing to install
+lsm-failed-run-semodule-policycoreutils = Failed to run semodule (is policycoreutils installed?)
+lsm-semodule-could-not-install = semodule could not install the HULIOS policy module
+lsm-installed-selinux-policy-module = Installed the SELinux policy module hulios
+lsm-profile-not-add-rules = The profile { $name } is not in { $file }; add the rules to it by hand
+lsm-include-missing = { $file } does not include <{ $include }>; add the rules to it by hand:
+lsm-added-rules = Added HULIOS's rules to { $local }
+lsm-failed-run-apparmor-parser = Failed to run apparmor_parser
+lsm-apparmor-parser-could-not = apparmor_parser could not reload { $file }
+lsm-failed-run-semodule = Failed to run semodule
+lsm-semodule-could-not-remove = semodule could not remove the HULIOS policy module
+lsm-removed-selinux-policy-module = Removed the SELinux policy module hulios
+lsm-removed-rules = Removed HULIOS's rules from { $local }
+
+## Bootstrap failures
+
+diagnose-clock-skew = The system clock is wrong, so Tor rejects the consensus and relay certificates
+diagnose-clock-skew-fix = Set the correct time and time zone (`timedatectl set-ntp true`, or `date -s`), then start again
+diagnose-permission-denied = Tor could not access its DataDirectory, log or torrc (permission denied)
+diagnose-permission-denied-fix = Run `sudo hulios recover`; if SELinux or AppArmor confines tor, run `sudo hulios lsm install`
+diagnose-port-in-use = One of Tor's ports (9050, 9051, 9052, 9061) is taken by another program
+diagnose-port-in-use-fix = Find it with `ss -ltnup | grep -E ':(905[0-2]|9061)'` and stop it (often the system tor: `systemctl stop tor`)
+diagnose-network-unreachable = The machine has no route to the internet
+diagnose-network-unreachable-fix = Check the network connection (`ip route`), then start again
+diagnose-refused = Connections to the directory authorities and relays are refused: the network probably blocks Tor
+diagnose-bridges-fix = Configure tor.bridges (obfs4 or snowflake with tor.transport_plugin) and start again
+diagnose-timeout = Connections to the directory authorities and relays time out: the network probably blocks Tor
+diagnose-no-route = There is no route to the directory authorities and relays
+diagnose-no-route-fix = Check the network connection, or configure tor.bridges if the network blocks Tor
+
+## Leak check
+
+verify-verifying-tor-routing = Verifying Tor routing...
+verify-check-direct-blocked = Direct non-Tor traffic is blocked
+verify-check-clearnet-blocked = Clearnet TCP is blocked
+verify-dns-http-exit-checks = DNS and HTTP exit checks skipped: onion-only mode never uses an exit
+verify-check-dns-through-tor = DNS resolves through Tor DNSPort
+verify-check-outside-dns-redirected = Outside DNS is redirected to Tor
+verify-check-http-through-tor = HTTP exits through Tor
+verify-exit-ip-not-tor = exit IP { $ip } is not a Tor exit
+verify-http-exit-check-skipped = HTTP exit check skipped: the check endpoints' ports are not torified
+verify-not-running-start-first = HULIOS is not running, start it first
+verify-check-doh-blocked = DNS-over-HTTPS providers are blocked
+verify-their-lookups-skip-dns = Their lookups skip the DNS redirect; `hulios doh install-policies` turns DoH off
+verify-ping-traceroute-leave-directly = ping and traceroute leave directly, outside Tor: Tor carries only TCP
+verify-icmp-dropped-count = ping and traceroute won't work: Tor carries only TCP, so ICMP is dropped ({ $packets } packets so far)
+verify-icmp-dropped = ping and traceroute won't work: Tor carries only TCP, so ICMP is dropped
+verify-test-reachability-over-tcp = Test reachability over TCP instead, e.g. `curl -I https://example.com`
+verify-ok = { $name }: ok
+verify-failed = { $name }: FAILED ({ $error })
+verify-verification-failed = Verification failed: { $name }
+verify-failed-query = Failed to query { $server }
+verify-no-dns-answer = No DNS answer from { $server }
+verify-malformed-dns-reply = Malformed DNS reply from { $server }
+verify-returned-no-records-rcode = { $server } returned no records for { $host } (rcode { $rcode })
+verify-malformed-dns-reply-answer = Malformed DNS reply
+verify-tcp-connection-left-machine = TCP connection left the machine outside Tor
+verify-udp-packet-left-machine = UDP packet left the machine outside Tor
+
+## hulios status
+
+status-status = Status
+status-shadows-calm = The shadows are calm
+status-shadows-whisper = The shadows whisper
+status-ip = Ip
+status-ip-cached = cached { $seconds }s ago
+status-location = Location
+status-firewall-rules = Firewall rules ({ $backend })
+status-present = present
+status-absent = absent
+status-unknown-not-root = unknown (run as root)
+status-tor-process = Tor process
+status-running = running
+status-running-pid = running (PID: { $pid })
+status-not-running = not running
+status-dns = DNS
+status-dns-owned = owned by HULIOS
+status-dns-system = system default
+status-bootstrap = Bootstrap
+status-unknown = unknown
+status-daemon = Daemon
+status-session-traffic = Session traffic
+status-state = State
+status-fix = fix: { $remedy }
+status-ignoring-invalid-config = Ignoring invalid config:
+status-geoip-databases-updated = GeoIP databases updated.
+status-error-updating-geoip-databases = Error updating GeoIP databases:
+status-onion-only-skip = Onion-only mode: traffic never leaves through an exit, skipping the exit check
+status-checking-tor-browser = HULIOS is off; checking Tor Browser's Tor through 127.0.0.1:{ $socks_port }
+status-error-checking-tor-browser = Error checking Tor Browser's Tor:
+status-error-checking-status = Error checking status:
+status-trying-ifconfig = Trying simple IP check via ifconfig.me...
+status-blocklist-rejected = { $packets } packets rejected
+status-counter-unavailable = counter unavailable
+status-blocklist = Blocklist: { $count } entries, { $rejected }
+status-icmp-none-allowed = no types allowed
+status-icmp-allowed = allowed: { $types }
+status-icmp-dropped = ICMP: { $dropped } packets dropped ({ $allowed })
+status-tor-bootstrapped = Tor bootstrapped in { $duration }
+status-cgroup-process = { $count } process
+status-cgroup-processes = { $count } processes
+status-cgroup-memory = { $memory } of { $max } max
+status-cgroup-cpu = { $seconds }s CPU
+status-cgroup-oom-kills = { $kills } process(es) killed out of memory
+status-tor-cgroup = Tor cgroup: { $group } ({ $detail })
+status-isolated-app = Isolated app: { $command } (user { $user }, SOCKS 127.0.0.1:{ $socks_port }, TransPort { $trans_port }, DNSPort { $dns_port })
+status-browser-tor-over-tor = Tor over Tor through HULIOS
+status-browser-own-tor = its own Tor
+status-browser-running = running (PID: { $pid }, user { $user }, { $route })
+status-tor-browser = Tor Browser
+status-daemon-watching-circuits = watching (PID: { $pid }, up { $uptime }m, last check { $health }, circuits established)
+status-daemon-watching-no-circuits = watching (PID: { $pid }, up { $uptime }m, last check { $health }, no circuits yet)
+status-daemon-watching = watching (PID: { $pid }, up { $uptime }m, last check { $health })
+status-unconfirmed-rollback = rolled back in { $seconds }s unless `sudo hulios confirm` runs
+status-unconfirmed = Unconfirmed
+status-endpoint-failed = { $url } failed: { $error }
+status-no-endpoint-answered = No verification endpoint answered
+status-failed-connect = Failed to connect to { $url }
+status-failed-parse-json = Failed to parse JSON
+status-failed-read-response = Failed to read response
+
+## Session health
+
+health-redirect-gone = The redirect to Tor's DNSPort and TransPort is gone: DNS and TCP are dropped
+health-drop-gone = The firewall's default DROP is gone: traffic Tor doesn't carry leaves in the clear
+health-resolv-conf-changed = /etc/resolv.conf no longer points at 127.0.0.1: DNS may not go through Tor
+health-route-localnet-reset = route_localnet was reset to 0: DNS redirected to 127.0.0.1 is dropped
+health-unprotected = Tor is not running and the firewall rules are gone: traffic is NOT protected
+health-tor-down-blocked = Tor is not running but the firewall rules are active: all traffic is blocked
+health-rules-missing = Firewall rules are missing: traffic bypasses Tor
+health-resolv-conf-overwritten = /etc/resolv.conf was overwritten: DNS may not go through Tor
+health-leftovers = Leftovers from a session that is no longer recorded
+health-dns-not-through-tor = DNS does not resolve through Tor's DNSPort
+
+## Notifications
+
+notify-started-title = HULIOS Started
+notify-started-body = All traffic now routed through Tor 🧅
+notify-stopped-title = HULIOS Stopped
+notify-stopped-body = Normal network restored
+notify-restarted-title = HULIOS Restarted
+notify-restarted-body = Tor connection refreshed 🔄
+notify-flushed-title = HULIOS Flushed
+notify-flushed-body = Firewall rules cleared
+notify-error-title = HULIOS Error
+notify-tor-failed-body = Tor failed to start! Check { $log }
+notify-leak-check-failed-body = Leak check failed, normal network restored
+notify-rolled-back-title = HULIOS Rolled Back
+notify-rolled-back-body = The session was not confirmed in time, normal network restored
+notify-problem-title = ⚠️ HULIOS { $health }
+notify-problem-body = { $problem }. Run: { $remedy }
+notify-self-restart-title = ⚠️ HULIOS restarting itself
+notify-self-restart-body = The session was { $health }; running hulios restart
+notify-oom-restart-body = Tor was killed, likely out of memory; running hulios restart
+notify-sysctl-reverted-title = ⚠️ HULIOS settings keep being reverted
+notify-sysctl-reverted-body = { $key } was reset { $count } times; check /etc/sysctl.d and NetworkManager
+notify-broken-app-title = App not working through Tor
+notify-broken-apps-title = Apps not working through Tor
+notify-trusted-network-title = HULIOS: trusted network
+notify-trusted-network-body = Connected to the trusted network '{ $ssid }'. Stop HULIOS ({ $mode }) and use the normal network?
+notify-trusted-network-action = Stop HULIOS
+
+## Apps not working through Tor
+
+breakage-quic = { $app } is attempting QUIC (HTTP/3); it will not work through Tor and should fall back to TCP
+breakage-webrtc = { $app } is attempting WebRTC calls over UDP; they will not work through Tor
+breakage-udp-voice = { $app } is attempting UDP voice; it will not work through Tor
+breakage-udp = { $app } is sending UDP to port { $port }; it will not work through Tor
+breakage-connect = { $app } keeps failing to connect to TCP port { $port }; the firewall drops it instead of sending it through Tor
+breakage-blocklist = Something keeps trying to reach an address in firewall.block ({ $packets } packets rejected so far)
+breakage-icmp = Something keeps sending ICMP, e.g. ping ({ $packets } packets dropped so far); it will not work through Tor
+
+## Statistics
+
+stats-invalid-traffic-read = Invalid traffic/read
+stats-invalid-traffic-written = Invalid traffic/written
+stats-bootstrap-kept = { $secs }s, from the kept consensus
+stats-bootstrap = { $secs }s
+stats-no-sessions-recorded-yet = No sessions recorded yet.
+stats-current-session-since = Current session (since { $since }):
+stats-bootstrapped = Bootstrapped in { $bootstrap }
+stats-last-7-days = Last 7 days:
+stats-week = week
+stats-all-time-sessions = All time ({ $count } sessions): ↓ { $read }  ↑ { $written }
+stats-bootstrap-median = Bootstrap (median):
+stats-kept-consensus = kept consensus
+stats-downloaded-consensus = downloaded consensus
+stats-median-session = { $secs }s  ({ $count } session)
+stats-median-sessions = { $secs }s  ({ $count } sessions)
+stats-sessions-last-since = Sessions in the last { $period } (since { $since }): { $count }
+stats-all-sessions = All sessions: { $count }
+stats-running = running
+stats-session-blocked-exits = blocked { $blocked }  exits { $exits }
+stats-crashed = crashed
+stats-total-crash = Total: { $time } under Tor, ↓ { $read }  ↑ { $written }, { $blocked } packets blocked, { $crashes } crash
+stats-total-crashes = Total: { $time } under Tor, ↓ { $read }  ↑ { $written }, { $blocked } packets blocked, { $crashes } crashes
+stats-exit-countries = Exit countries: { $countries }
+stats-invalid-period-expected-e = Invalid period '{ $period }', expected e.g. 7d
+stats-unknown-period-unit-use = Unknown period unit '{ $unit }', use m, h, d or w
+
+## hulios top
+
+top-failed-start-runtime = Failed to start the runtime
+top-connection = { $count } connection through Tor, down { $down }, up { $up } (Ctrl-C to quit)
+top-connections = { $count } connections through Tor, down { $down }, up { $up } (Ctrl-C to quit)
+top-title = HULIOS top:
+top-process = PROCESS
+top-pid = PID
+top-destination = DESTINATION
+top-circuit = CIRCUIT
+top-down = DOWN
+top-up = UP
+top-total = TOTAL
+top-no-connections = (no connections)
+
+## Circuit tuning
+
+tuning-learn-timeout = the fixed tor.circuit_build_timeout makes many circuits time out; let Tor learn the timeout
+tuning-more-guards = circuits are slow or failing through the current guard; use two entry guards
+tuning-bridges = most circuits fail on the direct connection; switch to the configured tor.bridges
+tuning-learn-timeout-hint = remove tor.circuit_build_timeout and run `sudo hulios reload`
+tuning-more-guards-hint = sudo hulios setconf NumEntryGuards=2
+tuning-bridges-hint = set tor.auto_tune = true to switch live when this happens
+tuning-no-tor-session = No Tor in this session
+tuning-circuits-built-failed-timed = Circuits: { $built } built, { $failed } failed ({ $timed_out } timed out)
+tuning-build-time-timeout = Build time: median { $median }s, 90% within { $slowest }s, timeout { $timeout }s
+tuning-build-time = Build time: median { $median }s, 90% within { $slowest }s
+tuning-suggestion = Tuning:
+tuning-fix = Fix: { $hint }
+tuning-set-tor-auto-tune = Set tor.auto_tune = true to have the daemon make these changes itself
+tuning-tuned = Tuned: { $tuning }
+
+## Circuits and exits
+
+circuits-no-host = No host in '{ $target }'
+circuits-no-open-connections-go = No open connections to { $host } go through Tor right now
+circuits-exit = { $count } exit
+circuits-exits = { $count } exits
+circuits-closed-circuit-through = Closed { $closed } circuit through { $exits }; the next connection to { $host } gets a fresh one
+circuits-closed-circuits-through = Closed { $closed } circuits through { $exits }; the next connection to { $host } gets a fresh one
+circuits-no-session = No HULIOS session
+circuits-already-excluded-session = { $address } is already excluded for this session
+circuits-no-current-exit-address = No current exit has address { $address }; use `hulios newcircuit <host>` instead
+circuits-no-current-exit-excluding-address = No current exit has address { $address }; excluding the address itself
+circuits-tor-refused-exclusion = Tor refused the exclusion
+circuits-excluded-closed-circuit = Excluded { $nodes } as an exit for this session; closed { $closed } circuit through it
+circuits-excluded-closed-circuits = Excluded { $nodes } as an exit for this session; closed { $closed } circuits through it
+circuits-no-exits-reported-session = No exits reported this session
+circuits-reported = { $node } ({ $address }), reported { $reported }
+
+## Tor control port
+
+control-failed-connect-tor-control = Failed to connect to Tor control port { $addr }
+control-tor-control-port-authentication = Tor control port authentication failed
+control-empty-getinfo-reply = Empty GETINFO reply
+control-timed-out-connecting-tor = Timed out connecting to the Tor control port
+control-failed-connect-tor-control-port = Failed to connect to Tor control port { $addr }
+control-timed-out-waiting-tor = Timed out waiting for Tor's control reply
+control-failed-read-you-root = Failed to read { $cookie_path } (are you root?)
+control-malformed-control-reply = Malformed control reply: { $raw }
+control-tor-replied = Tor replied { $code } { $text }
+control-no-control-password-session = No control password for this session
+control-failed-get-random-bytes = Failed to get random bytes
+control-not-running-tor-no = HULIOS is not running; its Tor has no control port
+control-cannot-read-you-root = Cannot read { $path } (are you root?)
+
+## Tor options
+
+setconf-bool = 0 or 1
+setconf-auto-bool = 0, 1 or auto
+setconf-count = { $min } to { $max }
+setconf-interval = seconds, or e.g. "10 minutes"
+setconf-nodes = relays, e.g. {de},$FINGERPRINT
+setconf-ports = ports, e.g. 22,6667
+setconf-unknown-option = '{ $name }' is not an option `hulios setconf` may change (run it without arguments for the list)
+setconf-must-not = { $name } must be { $expected }, not '{ $value }'
+setconf-tor-default = { $name } (Tor's default)
+setconf-back-tor-default = { $name } back to Tor's default
+setconf-tor-not-table = 'tor' is not a table
+setconf-tor-options-not-table = 'tor.options' is not a table
+
+## Power profiles
+
+power-on-battery = on battery
+power-on-ac = on AC power
+power-metered = metered connection
+power-unmetered = unmetered connection
+power-state = Power: { $state }
+power-not-configured = No [power.battery] or [power.metered] settings configured
+power-bandwidth-limited-burst = bandwidth limited to { $rate }/s (burst { $burst })
+power-bandwidth-limited = bandwidth limited to { $rate }/s
+power-rotation-paused = identity rotation paused
+power-health-checks-every = health checks every { $seconds }s
+power-adjustments-none = Adjustments: none
+power-adjustments = Adjustments: { $changes }
+
+## Modes
+
+mode-mode = Mode: { $mode }
+mode-already-mode = Already in { $target } mode
+mode-switched = Mode switched from { $from } to { $target }
+mode-changed-meanwhile-run-command = The mode changed to { $mode } meanwhile; run the command again
+
+## DNS-only mode
+
+dnsonly-dns-only-mode-already = dns-only mode is already active
+dnsonly-session-active-switch-sudo = A HULIOS session is active; switch it with `sudo hulios mode set dns-only`
+dnsonly-dns-only-mode-started = dns-only mode started: DNS is encrypted, other traffic is NOT torified
+dnsonly-undo-sudo-dns-only = Undo it with `sudo hulios dns-only stop`.
+dnsonly-dns-now-points-localhost = DNS now points to localhost (encrypted resolver)
+dnsonly-dns-does-not-work = DNS does not work through the encrypted resolver
+dnsonly-verified-dns-answered-encrypted = Verified: DNS is answered by the encrypted resolver
+dnsonly-dns-only-mode-not = dns-only mode is not active
+dnsonly-dns-only-mode-stopped = dns-only mode stopped, normal DNS restored
+dnsonly-dns-only-mode-active = dns-only mode is active
+dnsonly-resolver = Resolver: { $resolver }
+dnsonly-resolver-answering = Resolver answering on 127.0.0.1:{ $port }
+dnsonly-resolver-not-answering = Resolver not answering: { $error }
+dnsonly-etc-resolv-conf-no = /etc/resolv.conf no longer points at the resolver
+dnsonly-failed-start = Failed to start the { $name }
+dnsonly-did-not-start-check = The { $name } did not start, check { $log }
+dnsonly-listening = { $name } listening on 127.0.0.1:{ $port }
+dnsonly-dnscrypt-proxy-not-found = dnscrypt-proxy not found in { $dnscrypt_candidates } (is it installed?)
+dnsonly-failed-chown-dnscrypt-proxy = Failed to chown the dnscrypt-proxy cache dir
+
+## Gateway
+
+gateway-cannot-resolve = Cannot resolve { $host }
+gateway-no-address = No address for { $host }
+gateway-cannot-connect-gateway = Cannot connect to the gateway at { $host }
+gateway-malformed-reply-gateway = Malformed reply from the gateway
+gateway-does-not-hold-same = { $host } does not hold the same key; not sending the request
+gateway-refused-request = The gateway refused the request
+gateway-reply-failed-verification = The gateway's reply failed verification
+gateway-closed-connection = The gateway closed the connection
+gateway-already-exists-delete-first = { $path } already exists; delete it first to make a new key
+gateway-key-written = Gateway key written to { $path }
+gateway-copy-each-client-same = Copy it to each client, to the same path (root only) or a --key-file.
+gateway-then-set-daemon-remote = Then set daemon.remote_control, e.g. `hulios config set daemon.remote_control 192.168.1.1:9300`,
+gateway-run-sudo-restart = and run `sudo hulios restart`.
+gateway-malformed-status-gateway = Malformed status from the gateway
+gateway-session = { $host }: session { $health }
+gateway-daemon-pid-up-since = Daemon PID { $pid }, up since { $started }
+gateway-tor-circuits = Tor has circuits
+gateway-tor-no-circuits = Tor has no circuits
+gateway-tor-control-port-did = Tor's control port did not answer
+gateway-new-connections-use-new = { $host }: new connections use new circuits
+gateway-restarting-check-back-gateway = { $host }: restarting; check back with `hulios gateway status { $host }`
+gateway-cannot-read-gateway-key = Cannot read the gateway key { $path } (are you root? did you run `hulios gateway init`?)
+gateway-does-not-hold-gateway = { $path } does not hold a gateway key
+
+## Workstation
+
+workstation-not-ipv4-address = { $gateway } is not an IPv4 address
+workstation-already-attached-gateway-run = Already attached to a gateway; run `sudo hulios detach-gateway` first
+workstation-session-active-here-stop = A HULIOS session is active here; stop it first, the gateway torifies this machine instead
+workstation-failed-route-through-gateway = Failed to route through the gateway
+workstation-default-route-through = Default route through { $gateway } on { $interface }
+workstation-dns-now-points-gateway = DNS now points to the gateway
+workstation-attached-gateway = Attached to the HULIOS gateway { $gateway }
+workstation-check-or-undo = Check it with `hulios check`; undo it with `sudo hulios detach-gateway`.
+workstation-not-attached-gateway = Not attached to a gateway
+workstation-detached-gateway-networking-restored = Detached from the gateway, networking restored
+workstation-default-route-restored = Default route restored
+workstation-dns-configuration-restored = DNS configuration restored
+workstation-not-machine-network-reached = { $gateway } is not on this machine's network (it is reached through a router)
+workstation-no-route = No route to { $gateway }
+workstation-no-ipv4-address = { $interface } has no IPv4 address
+
+## Appliance
+
+appliance-there-no-network-interface = There is no network interface '{ $lan }'
+appliance-enabled-starts-every-boot = { $unit } enabled, HULIOS starts at every boot
+appliance-no-systemd-here-start = No systemd here: start `hulios --yes start` at boot with your init system
+appliance-profile-enabled = Appliance profile enabled
+appliance-point-lan-clients-their = Point the LAN's clients at { $address } as their gateway and DNS server (e.g. in the DHCP server).
+appliance-run-sudo-restart-reboot = Run `sudo hulios restart` (or reboot) to apply it now.
+appliance-removed-no-longer-starts = { $unit } removed, HULIOS no longer starts at boot
+appliance-profile-disabled = Appliance profile disabled
+appliance-gateway-lan-interface-still = gateway.lan_interface is still set; remove it from the config to stop torifying the LAN.
+appliance-profile = Appliance profile
+appliance-desktop-profile = Desktop profile
+appliance-gateway-mode = Gateway mode on { $lan } ({ $address })
+appliance-gateway-mode-which-no = Gateway mode on { $lan }, which has no IPv4 address
+appliance-gateway-mode-off = Gateway mode off
+appliance-started-at-boot = Started at boot: yes
+appliance-not-started-at-boot = Started at boot: no
+appliance-alerts-also-go = Alerts also go to { $url }
+appliance-daemon-restarts-broken-session = The daemon restarts a broken session by itself
+appliance-gateway-listen-address-not = gateway.listen_address { $address } is not an IPv4 address
+appliance-no-ipv4-address-gateway = { $interface } has no IPv4 address; gateway mode needs one
+appliance-systemctl-failed-enable = systemctl failed to enable { $unit }
+
+## Relay and bridge
+
+relay-already-running-pid = The relay is already running (PID { $pid })
+relay-mode-separate-anonymity-gives = Relay mode is separate from the anonymity HULIOS gives this machine:
+relay-note-second-tor = the relay is a second Tor; your traffic never goes through it, and the session's Tor never relays for others
+relay-note-public-address = a relay's address is public in the Tor consensus; a bridge's is handed out through BridgeDB. Sites and networks may treat it as Tor.
+relay-note-not-anonymous = relaying does not make your own traffic more anonymous
+relay-starting-pid = Relay starting (PID: { $pid })...
+relay-bridge-running-orport = Bridge running on ORPort { $port }
+relay-running-orport = Relay running on ORPort { $port }
+relay-reachability-check = Tor now checks that the ORPort is reachable from the internet; forward it on your router if needed. `hulios relay status` shows the result.
+relay-not-running = The relay is not running
+relay-stopped = Relay stopped
+relay-should-running-but-not = The relay should be running but is not; run `sudo hulios relay start`
+relay-cannot-reach-relay-control = Cannot reach the relay's control port
+relay-bridge-running-pid = Bridge running (PID { $pid })
+relay-running-pid = Relay running (PID { $pid })
+relay-fingerprint = Fingerprint: { $fingerprint }
+relay-bootstrapping = Bootstrapping: { $progress }%
+relay-orport-reachable-internet = ORPort { $or_port } is reachable from the internet
+relay-orport-not-confirmed-reachable = ORPort { $or_port } not confirmed reachable yet (forward it on your router?)
+relay-uptime = Up { $hours }h { $minutes }m
+relay-relayed = Relayed: { $read } MB in, { $written } MB out
+relay-bridge-line-share = Bridge line to share: { $line }
+relay-failed-run-systemd-run = Failed to run systemd-run
+relay-systemd-run-failed-start = systemd-run failed to start { $unit }
+relay-failed-query-relay-unit = Failed to query the relay unit's PID
+relay-exited-immediately-check = The relay exited immediately, check { $log }
+relay-failed-start-relay-tor = Failed to start the relay's tor
+relay-failed-move-relay-into = Failed to move the relay into its cgroup
+relay-failed-chown-relay-data = Failed to chown the relay's data dir
+relay-bootstrapped = Relay bootstrapped in { $seconds }s
+relay-tor-died-during-bootstrap = The relay's tor died during bootstrap at { $percent }% ({ $phase }), check { $log }
+relay-bootstrap-timed-out-after = Relay bootstrap timed out after { $seconds }s at { $percent }% ({ $phase }), check { $log }
+
+## Onion services
+
+onion-invalid-client-name = Client name '{ $client }' must be 1 to 32 letters, digits, '-' or '_'
+onion-not-running-use-start = HULIOS is not running; use `hulios start` first
+onion-tor-refused-onion-service = Tor refused the onion service
+onion-sshd-published-port = sshd is published at { $address } (port 22)
+onion-client-point-tor-directory = On the client, point Tor at a directory for onion keys (torrc):
+onion-save-line-var-lib = and save this line as /var/lib/tor/onion_auth/{ $client }.auth_private:
+onion-shown-only-once-then = It is shown only once. Then connect through Tor, e.g. `hulios remote add { $address }`
+onion-followed-ssh-user = followed by `ssh user@{ $address }`.
+onion-sshd-listens-configured-addresses = sshd listens on its configured addresses again
+onion-sshd-no-longer-published = sshd is no longer published as an onion service
+onion-sshd-not-published-onion = sshd is not published as an onion service
+onion-sshd-published = sshd is published at { $address }
+onion-sshd-published-while-runs = sshd is published at { $address } while HULIOS runs
+onion-authorized-clients = Authorized clients: { $clients }
+onion-sshd-listens-loopback = sshd listens on 127.0.0.1 only ({ $path })
+onion-failed-chown = Failed to chown { $path }
+onion-tor-did-not-create = Tor did not create { $path }, check { $log }
+onion-failed-run-openssl-installed = Failed to run openssl (is it installed?)
+onion-openssl-could-not-generate = openssl could not generate an x25519 key
+onion-unexpected-key-output-openssl = Unexpected key output from openssl
+onion-does-not-include-sshd = { $sshd_config } does not include sshd_config.d; set `ListenAddress 127.0.0.1` there yourself
+onion-sshd-rejected-configuration-sshd = sshd -t rejected the configuration; sshd was left as it was
+onion-sshd-now-listens-loopback = sshd now listens on 127.0.0.1:{ $port } only
+onion-could-not-reload-sshd = Could not reload sshd; run `systemctl reload sshd` yourself
+
+## Remote hosts
+
+remote-already-goes-through-tor = { $host } already goes through Tor
+remote-ssh-git-now-go = ssh and git to { $host } now go through Tor
+remote-ssh-proxycommand-connect = ssh: { $ssh_include } (ProxyCommand hulios connect)
+remote-git = git: { $proxy } in { $gitconfig }
+remote-not-configured-see-remote = { $host } is not configured, see `hulios remote list`
+remote-removed = Removed { $host }
+remote-no-remotes-configured-add = No remotes configured. Add one with `hulios remote add <host>`.
+remote-tor-socks-port-not = Tor SOCKS port { $socks_addr } is not answering, is HULIOS running?
+remote-socks-server-refused-no = SOCKS server refused the no-auth method
+remote-hostname-too-long-socks = Hostname too long for SOCKS: { $host }
+remote-tor-could-not-connect = Tor could not connect to { $host }:{ $port }: { $error }
+remote-malformed-socks-reply-address = Malformed SOCKS reply (address type { $kind })
+remote-general-failure = general failure
+remote-not-allowed-ruleset = not allowed by ruleset
+remote-network-unreachable = network unreachable
+remote-host-unreachable = host unreachable
+remote-connection-refused = connection refused
+remote-ttl-expired-onion-service = TTL expired (onion service unreachable?)
+remote-command-not-supported = command not supported
+remote-address-type-not-supported = address type not supported
+remote-unknown-error = unknown error
+remote-cannot-look-up-current = Cannot look up the current user
+remote-failed-switch-invoking-user = Failed to switch to the invoking user
+remote-failed-run-git = Failed to run git
+remote-git-config-failed = git config { $args } failed
+remote-not-valid-hostname = '{ $host }' is not a valid hostname
+
+## Onion aliases
+
+alias-must-not-end-onion = alias '{ $name }' must not end in .onion or .exit
+alias-not-valid-hostname = alias '{ $name }' is not a valid hostname
+alias-not-onion-address = '{ $target }' is not a .onion address
+alias-not-v3-onion-address = '{ $target }' is not a v3 onion address (56 base32 characters)
+alias-no-aliases-configured-add = No aliases configured. Add one with `hulios alias add <name> <onion>`.
+alias-added = Alias added: { $name } -> { $target }
+alias-no-alias-named = No alias named '{ $name }'
+alias-removed = Alias removed: { $name }
+alias-aliases-not-table = 'aliases' is not a table
+alias-saved-but-could-not = Saved, but could not reach Tor to apply the alias live
+alias-aliases-applied-running-tor = Aliases applied to the running Tor
+
+## Isolated apps
+
+isolate-no-command-run = No command to run
+isolate-isolated-apps-need-nat = Isolated apps need NAT redirection; set firewall.redirect = "nat" and run `sudo hulios restart`
+isolate-isolated-apps-already-running = { $slots } isolated apps are already running
+isolate-running-own-circuits = Running { $program } with its own circuits
+isolate-ports = SOCKS 127.0.0.1:{ $socks_port }, TransPort { $trans_port }, DNSPort { $dns_port }
+isolate-exited-waiting-processes-left = { $program } exited; waiting for the processes it left running
+isolate-isolated-listeners-removed = Isolated listeners of { $program } removed
+isolate-failed-run = Failed to run { $program }
+isolate-more-than-groups = { $name } is in more than { $count } groups
+isolate-cannot-look-up-root = Cannot look up root
+isolate-tor-refused-isolated-listeners = Tor refused the isolated listeners
+
+## Browser hardening
+
+browser-failed-back-up = Failed to back up { $path }
+browser-failed-restore = Failed to restore { $path }
+browser-not-running-browser-policies = HULIOS is not running; browser policies are only installed for a session
+browser-no-browser-left-harden = No browser left to harden
+browser-failed-harden = Failed to harden { $name }
+browser-hardened = { $name } hardened
+browser-restart-browsers = Restart the browsers for the policies to take effect
+browser-policies-removed = { $name } policies removed
+
+## DNS-over-HTTPS
+
+doh-downloading-doh-provider-list = Downloading DoH provider list from { $url }...
+doh-failed-download-doh-provider = Failed to download the DoH provider list (is Tor running?)
+doh-downloaded-doh-provider-list = Downloaded DoH provider list has no usable entries
+doh-failed-update = Failed to update { $list }
+doh-provider-list-updated-entries = DoH provider list updated: { $count } entries in { $list }
+doh-set-firewall-block-doh = Set firewall.block_doh = true to reject them
+doh-run-reload-apply-new = Run `hulios reload` to apply the new list
+doh-no-answer = no answer from { $probe }
+doh-reachable = { $probe } is reachable
+doh-firefox-profile-uses-dns = Firefox profile { $path } uses DNS-over-HTTPS (network.trr.mode = { $mode })
+doh-uses-secure-dns-over = { $name } ({ $state }) uses secure DNS-over-HTTPS
+doh-already-exists-add-dnsoverhttps = { $path } already exists; add "DNSOverHTTPS": { "Enabled": false } to it by hand
+doh-installed = Installed { $path }
+doh-no-firefox-chromium-based = No Firefox or Chromium-based browser found
+doh-removed = Removed { $path }
+
+## Local resolvers
+
+resolvers-could-not-bypass-nss-resolve = Could not bypass nss-resolve: { $error }
+resolvers-could-not-forward-unbound = Could not forward unbound to Tor: { $error }
+resolvers-could-not-flush-nscd = Could not flush the nscd hosts cache: { $error }
+resolvers-could-not-restore-nsswitch = Could not restore nsswitch.conf: { $error }
+resolvers-failed-restore = Failed to restore { $nsswitch }
+resolvers-restored = { $nsswitch } restored
+resolvers-could-not-restart-unbound = Could not restart unbound: { $error }
+resolvers-restore-nss-resolve-bypassed = restore { $nsswitch } (nss-resolve bypassed)
+resolvers-remove-restart-unbound = remove { $path } and restart unbound
+resolvers-failed-back-up-nsswitch = Failed to back up nsswitch.conf
+resolvers-nss-resolve-bypassed-session = nss-resolve bypassed for the session ({ $line })
+resolvers-does-not-exist-add = { $path } does not exist, add a forward-zone to 127.0.0.1@9061 by hand
+resolvers-unbound-rejected-tor-forward = unbound rejected the Tor forward-zone (is "." already forwarded?)
+resolvers-unbound-now-forwards-tor = unbound now forwards to the Tor DNSPort
+resolvers-nscd-hosts-cache-flushed = nscd hosts cache flushed
+resolvers-systemctl-restart-failed = systemctl restart { $unit } failed
+
+## Package manager proxy
+
+pkgproxy-not-running-start-first = HULIOS is not running; start it first, the proxy only answers while Tor runs
+pkgproxy-no-supported-package-manager = No supported package manager found (apt, dnf, pacman)
+pkgproxy-already-uses-tor = { $name } already uses Tor
+pkgproxy-now-downloads-through-tor = { $name } now downloads through Tor
+pkgproxy-no-package-manager-proxy = No package manager proxy configured
+pkgproxy-proxy-removed = { $name } proxy removed
+pkgproxy-no-section = { $path } has no { $header } section
+
+## Proxy environment
+
+proxyenv-not-running-these-proxies = HULIOS is not running; these proxies won't answer until `hulios start`
+
+## Network profiles
+
+netprofile-wi-fi = Wi-Fi { $ssid }
+netprofile-no-network = no network
+netprofile-network-profile = Network profile: { $name } ({ $network })
+netprofile-no-network-profile-using = No network profile for { $network }, using the base config
+netprofile-network = Network: { $network }
+netprofile-bound-profile = Bound profile: { $name }
+netprofile-bound-profile-none-base = Bound profile: none (base config)
+netprofile-profile-effect = Profile in effect: { $name }
+netprofile-profile-effect-none-base = Profile in effect: none (base config)
+netprofile-network-changed-since-profile = The network changed since the profile was applied; run `sudo hulios reload`
+netprofile-no-networks-bindings-configured = No [[networks]] bindings configured
+netprofile-ssid = ssid { $ssid }
+netprofile-subnet = subnet { $subnet }
+netprofile-any-network = any network
+netprofile-not-ipv4-network = '{ $subnet }' is not an IPv4 network
+netprofile-invalid-prefix-length = '{ $subnet }' has an invalid prefix length
+
+## Autostart
+
+autostart-does-not-exist-networkmanager = { $dispatcher } does not exist (is NetworkManager installed?)
+autostart-enabled-starts-every-untrusted = Autostart enabled: HULIOS starts on every untrusted Wi-Fi network
+autostart-no-network-trusted-yet = No network is trusted yet; trust the current one with `sudo hulios autostart trust`.
+autostart-disabled-running-session-keeps = Autostart disabled; a running session keeps running
+autostart-not-connected-wi-fi = Not connected to a Wi-Fi network; name the SSID to trust
+autostart-trusted = Trusted: { $ssid }
+autostart-not-trusted-network = '{ $ssid }' is not a trusted network
+autostart-no-longer-trusted = No longer trusted: { $ssid }
+autostart-enabled = Autostart enabled
+autostart-enabled-but-networkmanager-hook = Autostart is enabled but the NetworkManager hook is missing; run `sudo hulios autostart enable`
+autostart-disabled = Autostart disabled
+autostart-trusted-networks-none = Trusted networks: none
+autostart-trusted-networks = Trusted networks: { $trusted_ssids }
+autostart-stop-on-trusted-ask = Stop on trusted networks: ask
+autostart-stop-on-trusted-no = Stop on trusted networks: no
+autostart-connected-trusted = Connected to: { $ssid } (trusted)
+autostart-connected-untrusted = Connected to: { $ssid } (untrusted)
+autostart-connected-no-wi-fi = Connected to: no Wi-Fi
+autostart-not-table = 'autostart' is not a table
+autostart-trusted-ssids-not-array = 'autostart.trusted_ssids' is not an array
+
+## Confirmation deadline
+
+confirmation-not-running-may-already = HULIOS is not running; it may already have been rolled back
+confirmation-nothing-confirm-session-was = Nothing to confirm: the session was not started with --confirm-within, or is already confirmed
+confirmation-session-confirmed-stays-up = Session confirmed; it stays up
+confirmation-session-confirmed-time-nothing = Session confirmed in time, nothing to roll back
+confirmation-deadline-not-passed-yet = The confirmation deadline has not passed yet
+confirmation-session-was-not-confirmed = The session was not confirmed in time; rolling back
+confirmation-failed-start-confirmation-deadline = Failed to start the confirmation deadline
+confirmation-run-sudo-confirm-within = Run `sudo hulios confirm` within { $seconds }s, or everything is rolled back
+
+## SSH sessions
+
+sshguard-started-over-ssh-connection = Started over SSH from { $peer }; that connection stays open, nothing else from there gets through
+sshguard-started-over-ssh-firewall = Started over SSH from { $peer }; the firewall will cut this session off
+sshguard-ssh-session-which-firewall = This is an SSH session from { $peer }, which the firewall would cut off. Set firewall.keep_ssh_session = true to keep this one connection open, or pass --force-ssh-cutoff if console access is at hand
+
+## Connections from before the start
+
+conntrack-connections-before-start-not = Connections from before the start not checked: the conntrack table can't be read
+conntrack-no-connection-before-start = No connection from before the start bypasses Tor
+conntrack-connection-opened-before-start = { $count } connection(s) opened before the start still bypass Tor:
+conntrack-pid = { $protocol } { $destination } ({ $process }, pid { $pid })
+conntrack-they-end-when-their = They end when their apps close them; set firewall.existing_connections = "kill" to cut them at start
+conntrack-cut-connection-before-start = Cut { $count } connection(s) from before the start
+conntrack-connection-before-start-could = { $left } connection(s) from before the start could not be cut (are conntrack and ss installed?)
+
+## Firewall
+
+firewall-lan-on = LAN on { $interface }
+firewall-ssh-session-kept = SSH session from { $peer } kept
+firewall-lan-services = LAN services: { $names }
+firewall-mesh-vpns = mesh VPNs: { $names }
+firewall-libvirt-guests = libvirt guests ({ $bridges })
+firewall-mode-onion-only = onion-only, all exit-bound traffic blocked
+firewall-mode-partial-direct = partial: listed ports via Tor, other traffic direct
+firewall-mode-partial-blocked = partial: listed ports via Tor, other traffic blocked
+firewall-mode-default-deny = default-deny, Tor-only
+firewall-ip-failed = ip { $args } failed: { $stderr }
+
+## iptables
+
+iptables-rules-applied = Firewall rules applied ({ $policy }, { $redirect })
+iptables-firewall-rules-flushed-policies = Firewall rules flushed, policies reset to ACCEPT
+iptables-rules-applied-gateway = Firewall rules applied via iptables (only { $gateway } is reachable)
+iptables-rules-applied-dns-only = Firewall rules applied via iptables (DNS only)
+iptables-failed-run-iptables-installed = Failed to run iptables (is it installed?)
+iptables-failed-run-doctor = iptables { $args } failed: { $stderr } (run `hulios doctor`)
+
+## nftables
+
+nftables-rules-applied = Firewall rules applied via nftables netlink ({ $policy }, { $redirect })
+nftables-failed-delete-nftables-table = Failed to delete nftables table
+nftables-firewall-rules-flushed-nftables = Firewall rules flushed (nftables tables removed)
+nftables-failed-update-isolated-apps = Failed to update the isolated apps' rules
+nftables-rules-applied-gateway = Firewall rules applied via nftables netlink (only { $gateway } is reachable)
+nftables-rules-applied-dns-only = Firewall rules applied via nftables netlink (DNS only)
+nftables-user-does-not-exist = User '{ $tor_user }' does not exist; the nftables rules match Tor by uid, so render where Tor is installed
+nftables-failed-open-netlink-socket = Failed to open netlink socket
+nftables-rejected = nftables rejected '{ $rule }'
+
+## Mesh VPNs
+
+meshvpn-peers-reached-directly-outside = { $name } peers are reached directly, outside Tor
+meshvpn-up-but-blocked-while = { $name } ({ $interface }) is up but blocked while HULIOS runs; add "{ $name }" to firewall.mesh_vpns to keep it
+
+## Blocklist
+
+blocklist-neither-ip-address-cidr = '{ $entry }' is neither an IP address, a CIDR nor a domain name
+blocklist-invalid-prefix-length = Invalid prefix length in '{ $entry }'
+blocklist-prefix-length-larger-than = Prefix length in '{ $entry }' is larger than { $max }
+blocklist-not-blocking = Not blocking { $domain }: { $error }
+
+## hulios render
+
+render-wrote = Wrote { $path }
+render-blocked-domain-left-out = { $domains } blocked domain(s) left out: HULIOS resolves them through Tor at start
+render-ruleset-without-session-kept = The { $name } ruleset, without the session's kept SSH connection, libvirt bridges or isolated apps
+
+## Tor resource limits
+
+cgroup-failed-move-tor-into = Failed to move tor into { $dir }
+cgroup-failed-set = Failed to set { $file } of { $dir } to { $value }
+cgroup-failed-run-systemctl = Failed to run systemctl
+cgroup-systemctl-set-property-failed = systemctl set-property { $unit } failed
+cgroup-invalid-memory-size = Invalid memory size '{ $size }'
+cgroup-invalid-memory-size-use = Invalid memory size '{ $size }', use e.g. 512M or 1G
+
+## Kept Tor data
+
+tordata-no-tor-data-kept = No Tor data kept yet; this bootstrap downloads the directory, later ones reuse it
+tordata-failed-restore = Failed to restore { $source }
+tordata-failed-chown = Failed to chown { $target }
+tordata-expired-m-ago-still = expired { $minutes }m ago, still usable
+tordata-reusing-kept-guards-directory = Reusing the kept guards and directory cache (consensus { $age })
+tordata-reusing-kept-guards-kept = Reusing the kept guards; the kept consensus is too old, Tor downloads a new one
+tordata-failed-keep = Failed to keep { $source }
+
+## Amnesic mode
+
+amnesic-failed-mount-amnesic-tmpfs = Failed to mount the amnesic tmpfs
+amnesic-failed-bind-amnesic-tmpfs = Failed to bind the amnesic tmpfs over { $path }
+amnesic-mode-runtime-state-kept = Amnesic mode: runtime state kept on a private tmpfs ({ $root })
+amnesic-tmpfs-destroyed = Amnesic tmpfs destroyed
+amnesic-could-not-unmount = Could not unmount { $root }: { $error }
+
+## Privacy
+
+privacy-pipe2-failed = pipe2 failed
+privacy-dup2-failed = dup2 failed
+privacy-failed-sync = Failed to sync { $path }
+
+## Overlay networks
+
+overlay-starting-pid-experimental = { $name } starting (PID: { $pid }), experimental
+overlay-did-not-start-names = { $name } did not start, its names won't resolve: { $error }
+overlay-failed-start-overlay-router = Failed to start the overlay router
+overlay-router-did-not-start = The overlay router did not start, check { $log }
+overlay-router-listening = Overlay router on 127.0.0.1:{ $dns_port } (DNS) and { $trans_port } (TCP)
+
+## I2P
+
+i2p-i2pd-not-found-installed = i2pd not found in { $binary_candidates } (is it installed?)
+i2p-failed-chown-i2p-data = Failed to chown the I2P data dir
+i2p-failed-start-i2pd = Failed to start i2pd
+
+## Tor exit list
+
+exitlist-using-stale-exit-list = Using stale exit list: { $error }
+exitlist-failed-download-tor-exit = Failed to download Tor exit list (is Tor running?)
+exitlist-downloaded-exit-list-empty = Downloaded exit list is empty
+exitlist-failed-update = Failed to update { $exit_list }
+exitlist-failed-parse-onionoo-response = Failed to parse Onionoo response
+
+## GeoIP
+
+geoip-invalid-ip-address = Invalid IP address: { $ip }
+geoip-downloading = Downloading { $url }...
+geoip-failed-decompress-geoip-database = Failed to decompress GeoIP database
+geoip-downloaded-file-not-valid = Downloaded file is not a valid GeoIP database
+geoip-failed-update = Failed to update { $dest }
+
+## Updates
+
+update-checking = Checking { $url }...
+update-failed-read-release-feed = Failed to read the release feed at { $url }
+update-up-date = HULIOS { $current } is up to date
+update-security-available = HULIOS { $version } is available as a security update (running { $current })
+update-available = HULIOS { $version } is available as an update (running { $current })
+update-install-sudo-update = Install it with `sudo hulios update`
+update-release-no-build = Release { $version } has no build for { $arch }
+update-downloading = Downloading { $binary_url }...
+update-signature-not-text = The signature is not text
+update-signature-verified = Signature verified
+update-updated = HULIOS updated to { $version }
+update-binary-was-built-git = This binary was built from a git clone; `git pull` before rebuilding, or the build puts { $current } back
+update-run-sudo-restart-session = Run `sudo hulios restart` for the session and daemon to use it
+update-not-running-checking-updates = HULIOS is not running; checking for updates over the clearnet
+update-build-no-release-signing = This build has no release signing key; set update.public_key to the key published with the releases
+update-invalid-release-signing-key = Invalid release signing key: { $error }
+update-invalid-signature-file = Invalid signature file: { $error }
+update-new-binary-signature-does = The new binary's signature does not verify, not installing it: { $error }
+update-signature-not-not-installing = The signature is for '{ $trusted_comment }', not '{ $expected }'; not installing it
+update-new-binary-does-not = The new binary does not run on this machine; { $exe } was left alone
+update-failed-replace = Failed to replace { $exe }
+update-invalid-version = Invalid version '{ $version }'
+
+## Configuration
+
+config-invalid-port-range-expected = Invalid port range '{ $range }', expected e.g. 8000-8100
+config-invalid-port-range = Invalid port range { $low }-{ $high }
+config-network-profile-does-not = The network profile '{ $name }' does not fit the config
+config-failed-apply-overrides = Failed to apply the overrides
+config-empty-override-key = Empty override key
+config-override-not-table = Override { $key }: '{ $part }' is not a table
+config-invalid-config-override = Invalid config override
+
+## hulios config
+
+configtool-failed-render-configuration = Failed to render the configuration
+configtool-empty-key = Empty key
+configtool-not-table = '{ $part }' is not a table
+configtool-failed-create-draft-config = Failed to create the draft config
+configtool-failed-run-editor = Failed to run editor '{ $editor }'
+configtool-editor-exited-config-left = Editor exited with { $status }, config left unchanged
+configtool-saved = { $path } saved.
+configtool-edit-again-y-n = Edit again? [Y/n]
+configtool-config-left-unchanged = Config left unchanged
+configtool-invalid-configuration = Invalid configuration
+configtool-line = line { $line }:
+configtool-invalid-configuration-issues = Invalid configuration:
+
+## Config validation
+
+validate-does-not-exist-built = { $path } does not exist, the built-in defaults are used.
+validate-error = { $path } has { $errors } error(s)
+validate-valid = { $path } is valid.
+validate-invalid-config = Invalid { $path } (run `hulios config validate`):
+validate-least-one-verification-endpoint = at least one verification endpoint is required
+validate-must-absolute-path = must be an absolute path
+validate-must-be-positive-seconds = must be greater than 0 seconds
+validate-must-least-bandwidth-rate = must be at least bandwidth_rate, Tor refuses to start otherwise
+validate-must-be-1-to-1024 = must be between 1 and 1024
+validate-must-be-positive = must be greater than 0
+validate-no-effect-above-memory = has no effect at or above memory_max
+validate-must-be-positive-percent = must be greater than 0 percent
+validate-must-be-nice = must be between -20 and 19
+validate-must-be-io-priority = must be between 0 and 7
+validate-no-effect-io-class = has no effect with io_class = "idle"
+validate-no-effect-sched-policy = has no effect with sched_policy = "idle"
+validate-keeps-tor-guards-directory = keeps Tor's guards and directory cache in /var/lib/hulios/tor-data, which privacy.secure_delete leaves alone
+validate-no-effect-tor-confine = has no effect with tor.confine: the daemon can't see why a confined Tor exited
+validate-needs-tor-bridges = needs tor.bridges
+validate-no-effect-without-tor = has no effect without tor.bridges
+validate-stops-tor-running-tor = stops Tor from running tor.transport_plugin
+validate-must-absolute-path-path = must be an absolute path, PATH is never searched
+validate-must-be-free-port = must be a free port other than Tor's 9050, 9051, 9052 and 9061
+validate-must-64-hexadecimal-characters = must be 64 hexadecimal characters
+validate-must-be-free-port-health = must be a free port other than Tor's 9050, 9051, 9052, 9061 and tor.http_tunnel_port
+validate-not-address-port = '{ $address }' is not an address:port
+validate-must-least-1-minute = must be at least 1 minute
+validate-no-effect-without-bandwidth = has no effect without bandwidth_rate
+validate-no-effect-without-daemon = has no effect without daemon.rotate_identity
+validate-not-message-id-see = is not a message id; see locales/en.ftl
+validate-not-minisign-public-key = is not a minisign public key
+validate-must-sshd-port-e = must be sshd's port, e.g. 22
+validate-must-be-free-port-relay = must be a free port other than Tor's 9050-9053 and 9061
+validate-must-1-19-letters = must be 1 to 19 letters and digits
+validate-must-single-line = must be a single line
+validate-no-effect-unless-bridge = has no effect unless bridge = true
+validate-transport-plugin-transport-port = transport_plugin and transport_port must be set together
+validate-must-be-free-port-transport = must be a free port other than or_port and Tor's 9050-9053 and 9061
+validate-not-interface-name-prefix = '{ $interface }' is not an interface name or prefix
+validate-must-interface-facing-lan = must be the interface facing the LAN, not loopback
+validate-needed-interface-prefix-e = is needed with an interface prefix, e.g. the gateway VM's IP
+validate-gateway-mode-needs-firewall = gateway mode needs firewall.redirect = "nat"
+validate-appliance-profile-needs-interface = the appliance profile needs the interface facing the LAN, e.g. "eth0"
+validate-not-set-so-other = is not set, so the other [gateway] settings have no effect
+validate-must-address-clients-reach = must be an address the clients reach, not loopback or 0.0.0.0
+validate-not-ipv4-address = '{ $address }' is not an IPv4 address
+validate-can-checked-against-which = can't be checked against { $interface }, which has no IPv4 address now
+validate-not-address-lan-interface = { $ip } is not an address of { $interface }, the LAN interface its clients come in on
+validate-not-interface-name = is not an interface name
+validate-torifying-guests-needs-firewall = torifying guests needs firewall.redirect = "nat"
+validate-must-be-free-port-i2p = must be a free port other than Tor's 9050-9053 and 9061 and the overlay router's 9062-9063
+validate-i2p-needs-firewall-redirect = I2P needs firewall.redirect = "nat"
+validate-network-profile-can-set = a network profile can't set '{ $key }'
+validate-does-not-fit-config = does not fit the config: { $error }
+validate-no-network-profiles = no [network_profiles.{ $profile }]
+validate-must-https-url = must be an https:// URL
+validate-must-list-addresses-doh = must list the addresses of doh_url's host
+validate-partial-mode-needs-least = partial mode needs at least one port to torify
+validate-no-effect-unless-mode = has no effect unless mode = "partial"
+validate-ignored-onion-only-mode = is ignored in onion-only mode, all non-onion traffic is dropped
+validate-ranges-overlap = ranges { $low }-{ $high } and { $other_low }-{ $other_high } overlap
+validate-no-effect-onion-only = has no effect in onion-only mode, DoH providers are unreachable anyway
+validate-no-effect-event-not = has no effect, the event is not listed in notifications.events
+validate-invalid-url = invalid URL '{ $url }': { $error }
+validate-must-http-https-url = '{ $url }' must be an http or https URL
+validate-invalid-bandwidth = Invalid bandwidth
+
+## Daemon
+
+daemon-failed-start-daemon = Failed to start the HULIOS daemon
+daemon-did-not-come-up = The HULIOS daemon did not come up, check { $log }
+daemon-systemd-run-failed-start = systemd-run failed to start { $path }
+
+## Daemon socket
+
+ipc-failed-bind = Failed to bind { $socket }
+ipc-invalid-request = Invalid request: { $error }
+ipc-daemon-not-running = The HULIOS daemon is not running
+ipc-daemon-did-not-answer = The HULIOS daemon did not answer
+ipc-malformed-reply-daemon = Malformed reply from the HULIOS daemon
+
+## Privileged helper
+
+helper-cannot-reach-helper-you = Cannot reach the HULIOS helper at { $socket } (are you in the '{ $group }' group?)
+helper-closed-connection-before-finishing = The HULIOS helper closed the connection before finishing
+helper-malformed-reply-helper = Malformed reply from the HULIOS helper
+helper-failed-create-pipe = Failed to create a pipe
+helper-not-group = { $name } is not in the '{ $group }' group
+helper-cannot-identify-connecting-user = Cannot identify the connecting user
+helper-installing-helper-needs-root = Installing the helper needs root
+helper-groupadd-failed-create-group = groupadd failed to create the '{ $group }' group
+helper-systemctl-failed-enable-helper = systemctl failed to enable hulios-helper.socket and hulios-tray.socket
+helper-installed = Helper installed on { $socket }
+helper-tray-applets-can-connect = Tray applets can connect to { $socket }
+helper-let-user-start-stop = Let a user start and stop HULIOS without sudo: usermod -aG { $group } <user> (then log in again)
+
+## Privileged steps
+
+privileged-config-overrides-not-passed = Config overrides are not passed to the helper; run this with sudo
+privileged-unexpected-result = Unexpected result of a privileged step
+
+## Locking
+
+lock-failed-lock-state = Failed to lock HULIOS state
+lock-another-command-running-waiting = Another hulios command is running, waiting for it to finish...
+
+## Debug bundle
+
+debugbundle-failed-add = Failed to add { $name }
+debugbundle-debug-bundle-written = Debug bundle written to { $output }
+debugbundle-look-through-before-attaching = Look through it before attaching it (`tar -xzf { $output }`): scrubbing is by pattern and may miss something
+debugbundle-failed-run = Failed to run hulios
+
+## Uninstall
+
+uninstall-kept-use-purge-remove = Kept { $path } (use --purge to remove it)
+uninstall-kept-audit-log = Kept the audit log { $path }
+uninstall-removed-system-binary-itself = HULIOS removed from the system. The binary itself is left in place.
+
+## Languages
+
+i18n-no-english-catalog = The built-in English catalog is missing
+i18n-language = Language: { $language }
+i18n-built-in = built in
+i18n-catalog = { $translated }/{ $count } messages ({ $origin })
+i18n-missing = { $count } message(s) missing from { $language }
+
+## JSON output
+
+report-failed-redirect-stdout = Failed to redirect stdout: { $error }
+
+## Journal
+
+journal-journald-not-listening = journald is not listening on { $socket }
+
+## File watches
+
+inotify-init1-failed = inotify_init1 failed
+inotify-cannot-watch = Cannot watch { $path }
+inotify-reading-inotify-events-failed = Reading inotify events failed
+
+## External commands
+
+exec-timed-out-after = timed out after { $seconds }s
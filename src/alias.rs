@@ -0,0 +1,144 @@
+use anyhow::{Result, Context};
+use colored::*;
+use toml_edit::DocumentMut;
+use crate::config::{Config, FirewallMode, CONFIG_PATH};
+use crate::control::ControlClient;
+use crate::{configtool, engine, i18n, report};
+
+/// Length of a v3 onion address without the ".onion" suffix
+const ONION_V3_LEN: usize = 56;
+
+// =============================================================================
+// Validation
+// =============================================================================
+
+/// An alias must be a plain hostname that Tor won't treat as an onion itself
+pub fn check_name(name: &str) -> Result<()> {
+    let name = name.to_ascii_lowercase();
+    if name.ends_with(".onion") || name.ends_with(".exit") {
+        anyhow::bail!(i18n::tr("alias-must-not-end-onion", &[("name", &name)]));
+    }
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if name.len() > 253 || !name.split('.').all(valid_label) {
+        anyhow::bail!(i18n::tr("alias-not-valid-hostname", &[("name", &name)]));
+    }
+    Ok(())
+}
+
+/// Targets must be v3 onion addresses (optionally with a subdomain)
+pub fn check_target(target: &str) -> Result<()> {
+    let service = target
+        .to_ascii_lowercase()
+        .strip_suffix(".onion")
+        .and_then(|host| host.rsplit('.').next().map(str::to_string))
+        .with_context(|| i18n::tr("alias-not-onion-address", &[("target", target)]))?;
+    if service.len() != ONION_V3_LEN || !service.chars().all(|c| matches!(c, 'a'..='z' | '2'..='7')) {
+        anyhow::bail!(i18n::tr("alias-not-v3-onion-address", &[("target", target)]));
+    }
+    Ok(())
+}
+
+/// AutomapHostsSuffixes for the torrc: onions, plus every alias so that
+/// resolving it through the DNSPort hands out a virtual address
+pub fn automap_suffixes(config: &Config) -> String {
+    let mut suffixes = vec![".onion".to_string()];
+    // .exit names pick a specific exit relay, which onion-only never uses
+    if config.firewall.mode != FirewallMode::OnionOnly {
+        suffixes.push(".exit".to_string());
+    }
+    suffixes.extend(config.aliases.keys().cloned());
+    suffixes.join(",")
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+pub fn list() -> Result<()> {
+    let config = Config::load()?;
+    if config.aliases.is_empty() {
+        println!("[*] {}", i18n::tr("alias-no-aliases-configured-add", &[]));
+    }
+    for (name, target) in &config.aliases {
+        println!("{} -> {}", name.bold(), target);
+    }
+    report::data("aliases", &config.aliases);
+    Ok(())
+}
+
+pub fn add(name: &str, target: &str) -> Result<()> {
+    let name = name.to_ascii_lowercase();
+    let target = target.to_ascii_lowercase();
+    check_name(&name)?;
+    check_target(&target)?;
+
+    update(|aliases| {
+        aliases.insert(&name, toml_edit::value(target.as_str()));
+        Ok(())
+    })?;
+
+    println!("{}", format!("[+] {}", i18n::tr("alias-added", &[("name", &name), ("target", &target)])).green());
+    report::action(format!("Alias added: {} -> {}", name, target));
+    apply_live()
+}
+
+pub fn remove(name: &str) -> Result<()> {
+    let name = name.to_ascii_lowercase();
+    update(|aliases| {
+        aliases
+            .remove(&name)
+            .with_context(|| i18n::tr("alias-no-alias-named", &[("name", &name)]))?;
+        Ok(())
+    })?;
+
+    println!("{}", format!("[+] {}", i18n::tr("alias-removed", &[("name", &name)])).green());
+    report::action(format!("Alias removed: {}", name));
+    apply_live()
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Edit the `[aliases]` table of the config file, validating before writing
+fn update(change: impl FnOnce(&mut toml_edit::Table) -> Result<()>) -> Result<()> {
+    let source = configtool::read_existing()?;
+    let mut doc: DocumentMut = source.parse().with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+    let aliases = doc
+        .entry("aliases")
+        .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()))
+        .as_table_mut()
+        .with_context(|| i18n::tr("alias-aliases-not-table", &[]))?;
+    change(aliases)?;
+
+    let updated = doc.to_string();
+    configtool::check(&updated)?;
+    configtool::write(&updated)
+}
+
+/// Push the aliases to a running Tor so they work without a restart
+fn apply_live() -> Result<()> {
+    if !engine::is_tor_running() {
+        return Ok(());
+    }
+    let config = Config::load()?;
+    let mut client = ControlClient::connect().with_context(|| i18n::tr("alias-saved-but-could-not", &[]))?;
+    if config.aliases.is_empty() {
+        client.command("RESETCONF MapAddress")?;
+    } else {
+        let settings: Vec<(&str, String)> = config.aliases
+            .iter()
+            .map(|(name, target)| ("MapAddress", format!("{} {}", name, target)))
+            .collect();
+        client.setconf(&settings)?;
+    }
+    client.setconf(&[("AutomapHostsSuffixes", automap_suffixes(&config))])?;
+    println!("[+] {}", i18n::tr("alias-aliases-applied-running-tor", &[]));
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use anyhow::{Result, Context};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::{audit, daemon, engine, i18n, report, state, stats, status, tuning};
+
+/// Private tmpfs holding every runtime file of an `--amnesic` session
+const ROOT: &str = "/run/hulios/amnesic";
+
+/// Tmpfs size; Tor's data directory is the only thing of any size
+const SIZE: &str = "size=256m";
+
+/// Runtime paths redirected onto the tmpfs, each by a bind mount over the
+/// regular location so Tor and the rest of HULIOS need no changes: (path, is_dir)
+fn targets() -> [(&'static str, bool); 5] {
+    [
+        (engine::TOR_DATA_DIR, true),
+        (engine::TOR_LOG, false),
+        (engine::TORRC_PATH, false),
+        (status::STATUS_CACHE, false),
+        (daemon::LOG, false),
+    ]
+}
+
+/// Files replaced atomically by rename, which a bind-mounted file can't
+/// take; they are read and written through `redirect` instead
+const REDIRECTED: [&str; 3] = [state::STATE_PATH, stats::STATS_PATH, tuning::CIRCUITS_PATH];
+
+/// Mount the tmpfs and bind it over every runtime path
+pub fn mount() -> Result<()> {
+    fs::create_dir_all(ROOT)?;
+    sys_mount("tmpfs", ROOT, "tmpfs", libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC, &format!("mode=0700,{}", SIZE))
+        .with_context(|| i18n::tr("amnesic-failed-mount-amnesic-tmpfs", &[]))?;
+
+    for (index, (path, is_dir)) in targets().into_iter().enumerate() {
+        let backing = format!("{}/{}", ROOT, index);
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if is_dir {
+            fs::create_dir_all(&backing)?;
+            fs::create_dir_all(path)?;
+        } else {
+            fs::write(&backing, "")?;
+            if !Path::new(path).exists() {
+                fs::write(path, "")?;
+            }
+        }
+        if let Err(e) = sys_mount(&backing, path, "", libc::MS_BIND, "") {
+            unmount();
+            return Err(e).with_context(|| i18n::tr("amnesic-failed-bind-amnesic-tmpfs", &[("path", path)]));
+        }
+    }
+
+    // The totals so far stay visible; this session's traffic is discarded
+    if let Ok(content) = fs::read(stats::STATS_PATH) {
+        fs::write(redirect(stats::STATS_PATH), content)?;
+    }
+
+    println!("[+] {}", i18n::tr("amnesic-mode-runtime-state-kept", &[("root", ROOT)]));
+    report::action("Amnesic tmpfs mounted");
+    Ok(())
+}
+
+/// Whether an amnesic session's tmpfs is mounted
+pub fn is_active() -> bool {
+    fs::read_to_string("/proc/self/mountinfo")
+        .is_ok_and(|mounts| mounts.lines().any(|line| line.split(' ').nth(4) == Some(ROOT)))
+}
+
+/// Where a REDIRECTED file lives right now: on the tmpfs while an amnesic
+/// session is active, else at its regular path
+pub fn redirect(path: &str) -> String {
+    match Path::new(path).file_name() {
+        Some(name) if REDIRECTED.contains(&path) && is_active() => format!("{}/{}", ROOT, name.to_string_lossy()),
+        _ => path.to_string(),
+    }
+}
+
+/// Detach the bind mounts and the tmpfs, destroying everything on it
+pub fn unmount() {
+    for (path, _) in targets().into_iter().rev() {
+        let _ = sys_umount(path);
+    }
+    match sys_umount(ROOT) {
+        Ok(()) => {
+            let _ = fs::remove_dir(ROOT);
+            println!("[+] {}", i18n::tr("amnesic-tmpfs-destroyed", &[]));
+            report::action("Amnesic tmpfs destroyed");
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+        Err(e) => {
+            eprintln!("[!] {}", i18n::tr("amnesic-could-not-unmount", &[("root", ROOT), ("error", &e.to_string())]));
+            report::warning(format!("Could not unmount {}: {}", ROOT, e));
+        }
+    }
+    // The empty placeholders underneath carry no session data
+    for (path, is_dir) in targets() {
+        if is_dir {
+            let _ = fs::remove_dir(path);
+        } else if fs::metadata(path).is_ok_and(|m| m.len() == 0) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn sys_mount(source: &str, target: &str, fstype: &str, flags: libc::c_ulong, data: &str) -> io::Result<()> {
+    audit::record("mount", &format!("{} on {}", source, target));
+    let c = |s: &str| CString::new(s).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput));
+    let (source, target, fstype, data) = (c(source)?, c(target)?, c(fstype)?, c(data)?);
+    // SAFETY: all pointers are valid NUL-terminated strings for the call's duration
+    let rc = unsafe { libc::mount(source.as_ptr(), target.as_ptr(), fstype.as_ptr(), flags, data.as_ptr().cast()) };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn sys_umount(target: &str) -> io::Result<()> {
+    let path = CString::new(target).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: valid NUL-terminated path; MNT_DETACH never blocks on busy mounts
+    if unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Only once it worked: stop tries every path whether or not it is mounted
+    audit::record("unmount", target);
+    Ok(())
+}
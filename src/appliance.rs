@@ -0,0 +1,176 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use users::get_current_uid;
+use crate::config::{Config, GatewayConfig, Profile};
+use crate::exec::Run;
+use crate::{audit, configtool, confirm, i18n, report, sdnotify};
+
+/// Starts the session at boot; listed in uninstall's INSTALLED_FILES
+const UNIT_PATH: &str = "/etc/systemd/system/hulios.service";
+const UNIT: &str = "hulios.service";
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios appliance enable`: turn this machine into a headless Tor
+/// middlebox for the LAN on `lan`, started at every boot
+pub fn enable(lan: &str, webhook: Option<&str>, assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if !Path::new("/sys/class/net").join(lan).exists() {
+        anyhow::bail!(i18n::tr("appliance-there-no-network-interface", &[("lan", lan)]));
+    }
+    let address = lan_address(lan)?;
+    confirm::confirm(&i18n::tr("confirm-setting-up-appliance", &[]), &confirm::changes(confirm::APPLIANCE_CHANGES), assume_yes)?;
+
+    // The profile needs the interface, and each step is validated on its own
+    configtool::set("gateway.lan_interface", lan)?;
+    if let Some(url) = webhook {
+        configtool::set("notifications.webhook", url)?;
+    }
+    configtool::set("profile", "appliance")?;
+
+    if sdnotify::booted() {
+        install_unit()?;
+        println!("[+] {}", i18n::tr("appliance-enabled-starts-every-boot", &[("unit", UNIT)]));
+    } else {
+        println!("{}", format!("[!] {}", i18n::tr("appliance-no-systemd-here-start", &[])).yellow());
+    }
+
+    println!("{}", format!("[+] {}", i18n::tr("appliance-profile-enabled", &[])).green());
+    println!("[*] {}", i18n::tr("appliance-point-lan-clients-their", &[("address", &address.to_string())]));
+    println!("[*] {}", i18n::tr("appliance-run-sudo-restart-reboot", &[]));
+    report::action(format!("Appliance profile enabled on {}", lan));
+    Ok(())
+}
+
+/// `hulios appliance disable`: back to the desktop profile, no longer
+/// started at boot. Gateway mode stays configured until
+/// gateway.lan_interface is removed from the config.
+pub fn disable() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if Path::new(UNIT_PATH).exists() {
+        let _ = Command::new("systemctl").args(["disable", UNIT]).stderr(Stdio::null()).run();
+        audit::record("remove", UNIT_PATH);
+        fs::remove_file(UNIT_PATH).with_context(|| i18n::tr("error-remove", &[("path", UNIT_PATH)]))?;
+        let _ = Command::new("systemctl").arg("daemon-reload").run();
+        println!("[+] {}", i18n::tr("appliance-removed-no-longer-starts", &[("unit", UNIT)]));
+    }
+    if Config::load()?.profile == Profile::Appliance {
+        configtool::set("profile", "desktop")?;
+    }
+    println!("{}", format!("[+] {}", i18n::tr("appliance-profile-disabled", &[])).green());
+    println!("[*] {}", i18n::tr("appliance-gateway-lan-interface-still", &[]));
+    report::action("Appliance profile disabled");
+    Ok(())
+}
+
+/// `hulios appliance status`
+pub fn status() -> Result<()> {
+    let config = Config::load()?;
+    let appliance = config.profile == Profile::Appliance;
+    let lan = config.gateway.lan_interface.as_deref();
+    let address = listen_address(&config.gateway).ok().flatten();
+    let at_boot = Command::new("systemctl")
+        .args(["is-enabled", "--quiet", UNIT])
+        .stderr(Stdio::null())
+        .run()
+        .is_ok_and(|status| status.success());
+
+    if appliance {
+        println!("{}", format!("[+] {}", i18n::tr("appliance-profile", &[])).green());
+    } else {
+        println!("[*] {}", i18n::tr("appliance-desktop-profile", &[]));
+    }
+    match (lan, address) {
+        (Some(lan), Some(address)) => println!("    {}", i18n::tr("appliance-gateway-mode", &[("lan", lan), ("address", &address.to_string())])),
+        (Some(lan), None) => println!("{}", format!("    {}", i18n::tr("appliance-gateway-mode-which-no", &[("lan", lan)])).yellow()),
+        (None, _) => println!("    {}", i18n::tr("appliance-gateway-mode-off", &[])),
+    }
+    println!("    {}", i18n::tr(if at_boot { "appliance-started-at-boot" } else { "appliance-not-started-at-boot" }, &[]));
+    if let Some(url) = &config.notifications.webhook {
+        println!("    {}", i18n::tr("appliance-alerts-also-go", &[("url", url)]));
+    }
+    if appliance {
+        println!("    {}", i18n::tr("appliance-daemon-restarts-broken-session", &[]));
+    }
+    report::data("appliance", serde_json::json!({
+        "profile": config.profile,
+        "lan_interface": lan,
+        "lan_address": address,
+        "at_boot": at_boot,
+        "webhook": config.notifications.webhook,
+    }));
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Where Tor listens for the LAN: gateway.listen_address, or else the
+/// IPv4 address of gateway.lan_interface. None outside gateway mode.
+pub fn listen_address(gateway: &GatewayConfig) -> Result<Option<Ipv4Addr>> {
+    let Some(interface) = &gateway.lan_interface else {
+        return Ok(None);
+    };
+    if let Some(address) = &gateway.listen_address {
+        return Ok(Some(address.parse().with_context(|| i18n::tr("appliance-gateway-listen-address-not", &[("address", address)]))?));
+    }
+    lan_address(interface).map(Some)
+}
+
+/// The IPv4 address of `interface`
+pub fn lan_address(interface: &str) -> Result<Ipv4Addr> {
+    lan_addresses(interface)?
+        .first()
+        .copied()
+        .with_context(|| i18n::tr("appliance-no-ipv4-address-gateway", &[("interface", interface)]))
+}
+
+/// Every IPv4 address of `interface`
+pub fn lan_addresses(interface: &str) -> Result<Vec<Ipv4Addr>> {
+    let output = Command::new("ip")
+        .args(["-4", "-o", "addr", "show", "dev", interface])
+        .run_output()
+        .with_context(|| i18n::tr("error-no-iproute2", &[]))?;
+    // "2: eth0    inet 192.168.1.1/24 brd ... scope global eth0", one line each
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            line.split_whitespace()
+                .skip_while(|word| *word != "inet")
+                .nth(1)
+                .and_then(|cidr| cidr.split('/').next()?.parse().ok())
+        })
+        .collect())
+}
+
+fn install_unit() -> Result<()> {
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+    let unit = format!(
+        "# Installed by `hulios appliance enable`, removed by `hulios uninstall`\n\
+         [Unit]\nDescription=HULIOS: route this machine and its LAN through Tor\n\
+         Wants=network-online.target\nAfter=network-online.target\n\n\
+         [Service]\nType=oneshot\nRemainAfterExit=yes\n\
+         ExecStart={exe} --yes start\nExecStop={exe} --yes stop\n\n\
+         [Install]\nWantedBy=multi-user.target\n",
+        exe = exe.display());
+    audit::record("write", UNIT_PATH);
+    fs::write(UNIT_PATH, unit).with_context(|| i18n::tr("error-write", &[("path", UNIT_PATH)]))?;
+
+    let _ = Command::new("systemctl").arg("daemon-reload").run();
+    let status = Command::new("systemctl").args(["enable", UNIT]).run()?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("appliance-systemctl-failed-enable", &[("unit", UNIT)]));
+    }
+    Ok(())
+}
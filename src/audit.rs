@@ -0,0 +1,86 @@
+use serde_json::json;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+/// Outside /var/lib/hulios, so neither `uninstall` nor an amnesic session
+/// takes the trail with it
+pub const AUDIT_LOG: &str = "/var/log/hulios/audit.log";
+
+/// The hulios subcommand this process runs, e.g. "start" or "daemon"
+static COMMAND: OnceLock<&'static str> = OnceLock::new();
+
+pub fn set_command(command: &'static str) {
+    let _ = COMMAND.set(command);
+}
+
+/// Append one change HULIOS makes to the system, such as `("file", "replaced
+/// /etc/resolv.conf")`, to the audit log as a JSON line with the time and
+/// the user who ran hulios. Best effort: a log that can't be written never
+/// stops the change itself.
+pub fn record(action: &str, target: &str) {
+    let entry = json!({
+        "time": chrono::Local::now().to_rfc3339(),
+        "user": user(),
+        "pid": std::process::id(),
+        "command": COMMAND.get().copied().unwrap_or("unknown"),
+        "action": action,
+        "target": target,
+    });
+    let _ = append(&format!("{}\n", entry));
+}
+
+/// Record an external command if it changes the system. Called by exec::Run
+/// for every command, so firewall rules, units and attributes changed
+/// through the system tools are covered wherever they are run from.
+pub fn command(cmd: &Command) {
+    let program = cmd.get_program().to_string_lossy();
+    let args: Vec<_> = cmd.get_args().map(OsStr::to_string_lossy).collect();
+    let first = args.iter().map(|arg| arg.as_ref()).find(|arg| !arg.starts_with('-')).unwrap_or_default();
+    let mutates = match program.as_ref() {
+        "iptables" | "ip6tables" | "iptables-legacy" | "ip6tables-legacy" => args.iter().any(|arg| {
+            ["-A", "-I", "-D", "-R", "-F", "-X", "-N", "-P"].contains(&arg.as_ref())
+        }),
+        "systemctl" => ["mask", "unmask", "start", "stop", "restart", "enable", "disable", "kill"].contains(&first),
+        "ip" => args.iter().any(|arg| ["add", "del", "flush", "replace"].contains(&arg.as_ref())),
+        "sysctl" => args.iter().any(|arg| arg == "-w"),
+        "chattr" | "killall" | "systemd-run" | "groupadd" => true,
+        _ => false,
+    };
+    if mutates {
+        record(&program, &args.join(" "));
+    }
+}
+
+/// The user behind this run: the one who invoked sudo (or doas), else root
+fn user() -> String {
+    ["SUDO_USER", "DOAS_USER"]
+        .iter()
+        .find_map(|var| env::var(var).ok())
+        .unwrap_or_else(|| "root".to_string())
+}
+
+fn append(line: &str) -> std::io::Result<()> {
+    let path = Path::new(AUDIT_LOG);
+    if let Some(parent) = path.parent() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+    }
+    let created = !path.exists();
+    let mut file = OpenOptions::new().create(true).append(true).mode(0o600).open(path)?;
+    if created {
+        // Append-only even for root, until someone deliberately runs chattr -a.
+        // Spawned directly: going through exec::Run would audit this too.
+        let _ = Command::new("chattr")
+            .args(["+a", AUDIT_LOG])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+    // One write per line, which O_APPEND keeps whole between concurrent writers
+    file.write_all(line.as_bytes())
+}
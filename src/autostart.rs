@@ -0,0 +1,235 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use toml_edit::DocumentMut;
+use users::get_current_uid;
+use crate::config::{Config, Profile, CONFIG_PATH};
+use crate::exec::Run;
+use crate::mode::{self, Mode};
+use crate::{audit, configtool, confirm, engine, i18n, notify, report, workstation};
+
+/// NetworkManager runs this on every connection change; listed in
+/// uninstall's INSTALLED_FILES
+const HOOK_PATH: &str = "/etc/NetworkManager/dispatcher.d/90-hulios";
+
+/// Where the hook's runs go without systemd-run
+const LOG: &str = "/var/lib/hulios/autostart.log";
+
+/// How long the "stop on a trusted network" question stays up
+const ASK_TIMEOUT: Duration = Duration::from_secs(120);
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios autostart enable`: start HULIOS whenever NetworkManager connects
+/// to a Wi-Fi network that isn't trusted
+pub fn enable(assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let dispatcher = Path::new(HOOK_PATH).parent().unwrap_or(Path::new("/"));
+    if !dispatcher.is_dir() {
+        anyhow::bail!(i18n::tr("autostart-does-not-exist-networkmanager", &[("dispatcher", &dispatcher.display().to_string())]));
+    }
+    confirm::confirm(&i18n::tr("confirm-enabling-autostart", &[]), &confirm::changes(confirm::AUTOSTART_CHANGES), assume_yes)?;
+
+    configtool::set("autostart.enabled", "true")?;
+    install_hook()?;
+    println!("{}", format!("[+] {}", i18n::tr("autostart-enabled-starts-every-untrusted", &[])).green());
+    if Config::load()?.autostart.trusted_ssids.is_empty() {
+        println!("[*] {}", i18n::tr("autostart-no-network-trusted-yet", &[]));
+    }
+    report::action("Autostart enabled");
+    Ok(())
+}
+
+/// `hulios autostart disable`
+pub fn disable() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if Path::new(HOOK_PATH).exists() {
+        audit::record("remove", HOOK_PATH);
+        fs::remove_file(HOOK_PATH).with_context(|| i18n::tr("error-remove", &[("path", HOOK_PATH)]))?;
+    }
+    configtool::set("autostart.enabled", "false")?;
+    println!("{}", format!("[+] {}", i18n::tr("autostart-disabled-running-session-keeps", &[])).green());
+    report::action("Autostart disabled");
+    Ok(())
+}
+
+/// `hulios autostart trust [ssid]`: the current Wi-Fi network by default
+pub fn trust(ssid: Option<&str>) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let ssid = match ssid {
+        Some(ssid) => ssid.to_string(),
+        None => current_ssid(None).with_context(|| i18n::tr("autostart-not-connected-wi-fi", &[]))?,
+    };
+    update_trusted(|trusted| {
+        if !trusted.iter().any(|value| value.as_str() == Some(ssid.as_str())) {
+            trusted.push(ssid.as_str());
+        }
+        Ok(())
+    })?;
+    println!("{}", format!("[+] {}", i18n::tr("autostart-trusted", &[("ssid", &ssid)])).green());
+    report::action(format!("Trusted the Wi-Fi network {}", ssid));
+    Ok(())
+}
+
+/// `hulios autostart untrust <ssid>`
+pub fn untrust(ssid: &str) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    update_trusted(|trusted| {
+        let before = trusted.len();
+        trusted.retain(|value| value.as_str() != Some(ssid));
+        if trusted.len() == before {
+            anyhow::bail!(i18n::tr("autostart-not-trusted-network", &[("ssid", ssid)]));
+        }
+        Ok(())
+    })?;
+    println!("{}", format!("[+] {}", i18n::tr("autostart-no-longer-trusted", &[("ssid", ssid)])).green());
+    report::action(format!("No longer trusting the Wi-Fi network {}", ssid));
+    Ok(())
+}
+
+/// `hulios autostart status`
+pub fn status() -> Result<()> {
+    let config = Config::load()?;
+    let autostart = &config.autostart;
+    let hook = Path::new(HOOK_PATH).exists();
+    let current = current_ssid(None);
+
+    match (autostart.enabled, hook) {
+        (true, true) => println!("{}", format!("[+] {}", i18n::tr("autostart-enabled", &[])).green()),
+        (true, false) => println!("{}", format!("[!] {}", i18n::tr("autostart-enabled-but-networkmanager-hook", &[])).yellow()),
+        (false, _) => println!("[*] {}", i18n::tr("autostart-disabled", &[])),
+    }
+    if autostart.trusted_ssids.is_empty() {
+        println!("    {}", i18n::tr("autostart-trusted-networks-none", &[]));
+    } else {
+        println!("    {}", i18n::tr("autostart-trusted-networks", &[("trusted_ssids", &autostart.trusted_ssids.join(", "))]));
+    }
+    println!("    {}", i18n::tr(if autostart.stop_on_trusted { "autostart-stop-on-trusted-ask" } else { "autostart-stop-on-trusted-no" }, &[]));
+    match &current {
+        Some(ssid) if autostart.trusted_ssids.contains(ssid) => println!("    {}", i18n::tr("autostart-connected-trusted", &[("ssid", ssid)])),
+        Some(ssid) => println!("    {}", i18n::tr("autostart-connected-untrusted", &[("ssid", ssid)])),
+        None => println!("    {}", i18n::tr("autostart-connected-no-wi-fi", &[])),
+    }
+    report::data("autostart", serde_json::json!({
+        "enabled": autostart.enabled,
+        "hook_installed": hook,
+        "trusted_ssids": autostart.trusted_ssids,
+        "stop_on_trusted": autostart.stop_on_trusted,
+        "current_ssid": current,
+    }));
+    Ok(())
+}
+
+/// `hulios network-event <interface>`, run by the hook when `interface`
+/// comes up: start on an untrusted Wi-Fi network, offer to stop on a
+/// trusted one. Anything but Wi-Fi is left alone.
+pub fn handle_event(interface: &str) -> Result<()> {
+    let config = Config::load()?;
+    // An appliance runs all the time, a workstation goes through its gateway
+    if !config.autostart.enabled || config.profile == Profile::Appliance || workstation::is_attached() {
+        return Ok(());
+    }
+    let Some(ssid) = current_ssid(Some(interface)) else {
+        return Ok(());
+    };
+    let trusted = config.autostart.trusted_ssids.contains(&ssid);
+    let mode = mode::current();
+    println!("[*] {} connected to {} ({}), mode {}", interface, ssid, if trusted { "trusted" } else { "untrusted" }, mode);
+
+    if !trusted {
+        if mode == Mode::Off {
+            report::action(format!("Starting on the untrusted Wi-Fi network {}", ssid));
+            engine::start(&engine::StartOptions::default())?;
+        }
+        return Ok(());
+    }
+    if mode == Mode::Off || !config.autostart.stop_on_trusted {
+        return Ok(());
+    }
+    let body = i18n::tr("notify-trusted-network-body", &[("ssid", &ssid), ("mode", &mode.to_string())]);
+    if notify::ask(&i18n::tr("notify-trusted-network-title", &[]), &body, &i18n::tr("notify-trusted-network-action", &[]), ASK_TIMEOUT) {
+        mode::set(Mode::Off, true)?;
+    } else {
+        println!("[*] Not confirmed, the session keeps running");
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn install_hook() -> Result<()> {
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+    let hook = format!(
+        "#!/bin/sh\n\
+         # Installed by `hulios autostart enable`, removed by `hulios autostart disable`\n\
+         [ \"$2\" = \"up\" ] || exit 0\n\
+         # Out of NetworkManager-dispatcher's cgroup: `hulios start` stops that service\n\
+         if command -v systemd-run >/dev/null 2>&1; then\n    \
+             exec systemd-run --quiet --collect --no-block {exe} --yes network-event \"$1\"\n\
+         fi\n\
+         {exe} --yes network-event \"$1\" </dev/null >>{log} 2>&1 &\n",
+        exe = exe.display(), log = LOG);
+    audit::record("write", HOOK_PATH);
+    fs::write(HOOK_PATH, hook).with_context(|| i18n::tr("error-write", &[("path", HOOK_PATH)]))?;
+    // NetworkManager skips hooks that aren't root-owned, executable and
+    // unwritable by others
+    fs::set_permissions(HOOK_PATH, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+/// SSID of the Wi-Fi network connected on `interface`, or on any interface.
+/// None if it isn't Wi-Fi or NetworkManager doesn't know.
+pub fn current_ssid(interface: Option<&str>) -> Option<String> {
+    let mut command = Command::new("nmcli");
+    command.args(["-t", "-f", "ACTIVE,SSID", "device", "wifi", "list"]);
+    if let Some(interface) = interface {
+        command.args(["ifname", interface]);
+    }
+    let output = command.args(["--rescan", "no"]).stderr(Stdio::null()).run_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // "yes:Cafe\:Guest"; terse output escapes ':' and '\'
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("yes:"))
+        .map(|ssid| ssid.replace("\\:", ":").replace("\\\\", "\\"))
+        .filter(|ssid| !ssid.is_empty())
+}
+
+/// Edit `autostart.trusted_ssids` in the config file, validating before writing
+fn update_trusted(change: impl FnOnce(&mut toml_edit::Array) -> Result<()>) -> Result<()> {
+    let source = configtool::read_existing()?;
+    let mut doc: DocumentMut = source.parse().with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+    let autostart = doc
+        .entry("autostart")
+        .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()))
+        .as_table_mut()
+        .with_context(|| i18n::tr("autostart-not-table", &[]))?;
+    let trusted = autostart
+        .entry("trusted_ssids")
+        .or_insert_with(|| toml_edit::value(toml_edit::Array::new()))
+        .as_array_mut()
+        .with_context(|| i18n::tr("autostart-trusted-ssids-not-array", &[]))?;
+    change(trusted)?;
+
+    let updated = doc.to_string();
+    configtool::check(&updated)?;
+    configtool::write(&updated)
+}
@@ -0,0 +1,104 @@
+use anyhow::{Result, Context};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::{i18n, report, verify};
+
+/// Tor DNSPort, used to resolve blocked domains without leaking the lookup
+const TOR_DNS: &str = "127.0.0.1:9061";
+
+/// One `firewall.block` entry
+pub enum Entry {
+    V4(Ipv4Addr, u8),
+    V6(Ipv6Addr, u8),
+    Domain(String),
+}
+
+/// Parse "10.0.0.0/8", "192.0.2.7", "2001:db8::/32" or "tracker.example.com"
+pub fn parse(entry: &str) -> Result<Entry> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+
+    match addr.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => Ok(Entry::V4(ip, parse_prefix(prefix, 32, entry)?)),
+        Ok(IpAddr::V6(ip)) => Ok(Entry::V6(ip, parse_prefix(prefix, 128, entry)?)),
+        Err(_) if prefix.is_none() && is_domain(entry) => Ok(Entry::Domain(entry.to_ascii_lowercase())),
+        Err(_) => anyhow::bail!(i18n::tr("blocklist-neither-ip-address-cidr", &[("entry", entry)])),
+    }
+}
+
+fn parse_prefix(prefix: Option<&str>, max: u8, entry: &str) -> Result<u8> {
+    let Some(prefix) = prefix else {
+        return Ok(max);
+    };
+    let prefix: u8 = prefix.parse().with_context(|| i18n::tr("blocklist-invalid-prefix-length", &[("entry", entry)]))?;
+    if prefix > max {
+        anyhow::bail!(i18n::tr("blocklist-prefix-length-larger-than", &[("entry", entry), ("max", &max.to_string())]));
+    }
+    Ok(prefix)
+}
+
+fn is_domain(name: &str) -> bool {
+    name.contains('.')
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Destinations to reject, with domains resolved to their current addresses
+#[derive(Default)]
+pub struct Resolved {
+    pub v4: Vec<(Ipv4Addr, u8)>,
+    pub v6: Vec<(Ipv6Addr, u8)>,
+}
+
+/// Resolve the blocklist. Domains are looked up through Tor's DNSPort at
+/// apply time, so they only cover the addresses returned right now.
+/// Entries that can't be resolved are skipped with a warning.
+pub fn resolve(entries: &[String]) -> Resolved {
+    let mut resolved = Resolved::default();
+    for entry in entries {
+        match parse(entry) {
+            Ok(Entry::V4(ip, prefix)) => resolved.v4.push((ip, prefix)),
+            Ok(Entry::V6(ip, prefix)) => resolved.v6.push((ip, prefix)),
+            Ok(Entry::Domain(domain)) => match verify::lookup(TOR_DNS, &domain) {
+                Ok(addresses) => resolved.v4.extend(addresses.into_iter().map(|ip| (ip, 32))),
+                Err(e) => warn(&i18n::tr("blocklist-not-blocking", &[("domain", &domain), ("error", &format!("{:#}", e))])),
+            },
+            Err(e) => warn(&format!("{:#}", e)),
+        }
+    }
+    resolved
+}
+
+/// The blocklist's addresses alone, without looking up its domains; for
+/// rules written out ahead of time
+pub fn literal(entries: &[String]) -> Resolved {
+    let mut resolved = Resolved::default();
+    for entry in entries {
+        match parse(entry) {
+            Ok(Entry::V4(ip, prefix)) => resolved.v4.push((ip, prefix)),
+            Ok(Entry::V6(ip, prefix)) => resolved.v6.push((ip, prefix)),
+            Ok(Entry::Domain(_)) | Err(_) => {}
+        }
+    }
+    resolved
+}
+
+fn warn(message: &str) {
+    eprintln!("[!] {}", message);
+    report::warning(message);
+}
+
+/// The network address of a CIDR, as iptables/nftables expect it
+pub fn network_v4(ip: Ipv4Addr, prefix: u8) -> Ipv4Addr {
+    let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+    Ipv4Addr::from(u32::from(ip) & mask)
+}
+
+pub fn network_v6(ip: Ipv6Addr, prefix: u8) -> Ipv6Addr {
+    let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+    Ipv6Addr::from(u128::from(ip) & mask)
+}
@@ -0,0 +1,204 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::IpAddr;
+use crate::firewall::{self, FirewallBackend, Policy};
+use crate::i18n;
+use crate::notify::{self, Event};
+use crate::procfs;
+
+/// Scans in a row an app must be seen before it is reported, so one-off
+/// probes (e.g. a browser trying QUIC once) don't produce a notification
+const REPORT_AFTER: u32 = 3;
+
+/// Apps known to carry calls over UDP
+const VOICE_APPS: &[(&str, &str)] = &[
+    ("discord", "Discord"),
+    ("zoom", "Zoom"),
+    ("teams", "Teams"),
+    ("skypeforlinux", "Skype"),
+    ("signal-desktop", "Signal"),
+    ("telegram-desktop", "Telegram"),
+    ("mumble", "Mumble"),
+    ("steam", "Steam"),
+];
+
+/// Traffic that transparent Tor routing cannot carry
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    /// HTTP/3 over UDP 443
+    Quic,
+    /// WebRTC connectivity checks (STUN/TURN)
+    Webrtc,
+    /// Any other UDP, e.g. voice or games
+    Udp(u16),
+    /// TCP connects that hang because the firewall drops them instead of
+    /// sending them to Tor
+    Connect(u16),
+}
+
+/// Firewall drop counters; packets can't be traced to a process, so a
+/// counter that keeps growing is reported on its own
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Counter {
+    /// Packets rejected by `firewall.block`
+    Blocklist,
+    /// ICMP dropped, e.g. ping or traceroute
+    Icmp,
+}
+
+/// Correlates non-Tor sockets with their processes across monitor ticks
+#[derive(Default)]
+pub struct Tracker {
+    /// Consecutive scans each (app, kind) was seen in
+    seen: HashMap<(String, Kind), u32>,
+    /// Already notified this session
+    reported: HashSet<(String, Kind)>,
+    /// Each counter's last value and the scans in a row it grew in
+    counters: HashMap<Counter, (u64, u32)>,
+    /// Counters already notified this session
+    reported_counters: HashSet<Counter>,
+}
+
+impl Tracker {
+    /// Look for connected UDP sockets to the outside world (Tor only carries
+    /// TCP, so the firewall drops them) and TCP connects stuck in SYN_SENT
+    /// to destinations that aren't torified, and report apps that keep
+    /// trying. Firewall drop counters that keep growing are reported too.
+    pub fn scan(&mut self, policy: &Policy, backend: &dyn FirewallBackend) {
+        // With other_traffic = "allow", UDP leaves directly and works
+        if policy.is_partial_allow() {
+            return;
+        }
+
+        let sockets: Vec<(u64, Kind)> = blocked_udp_sockets()
+            .into_iter()
+            .map(|(inode, port)| (inode, classify(port)))
+            .chain(failed_connects(policy).into_iter().map(|(inode, port)| (inode, Kind::Connect(port))))
+            .collect();
+        let owners = if sockets.is_empty() { HashMap::new() } else { procfs::socket_owners() };
+        let mut current = HashSet::new();
+        for (inode, kind) in sockets {
+            let Some(info) = owners.get(&inode).and_then(|&pid| procfs::inspect(pid)) else {
+                continue;
+            };
+            current.insert((app_name(&info.comm), kind));
+        }
+
+        self.seen.retain(|key, _| current.contains(key));
+        let mut digest = Vec::new();
+        for key in current {
+            let count = self.seen.entry(key.clone()).or_insert(0);
+            *count += 1;
+            if *count >= REPORT_AFTER && self.reported.insert(key.clone()) {
+                digest.push(describe(&key.0, key.1));
+            }
+        }
+
+        for (counter, value) in [(Counter::Blocklist, backend.blocked_packets()), (Counter::Icmp, backend.icmp_dropped())] {
+            let Some(value) = value else {
+                continue;
+            };
+            let (last, streak) = self.counters.entry(counter).or_insert((value, 0));
+            *streak = if value > *last { *streak + 1 } else { 0 };
+            *last = value;
+            if *streak >= REPORT_AFTER && self.reported_counters.insert(counter) {
+                digest.push(describe_counter(counter, value));
+            }
+        }
+
+        if !digest.is_empty() {
+            for line in &digest {
+                eprintln!("[!] {}", line);
+            }
+            let title = if digest.len() == 1 { "notify-broken-app-title" } else { "notify-broken-apps-title" };
+            notify::send(Event::BrokenApp, &i18n::tr(title, &[]), &digest.join("\n"));
+        }
+    }
+}
+
+/// Inode and remote port of every connected UDP socket whose peer is
+/// outside this machine (DNS excluded, it is redirected to Tor)
+fn blocked_udp_sockets() -> Vec<(u64, u16)> {
+    ["/proc/net/udp", "/proc/net/udp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| table.lines().skip(1).filter_map(parse_socket).collect::<Vec<_>>())
+        .filter(|&(_, addr, port)| port != 53 && !addr.is_loopback() && !addr.is_unspecified())
+        .map(|(inode, _, port)| (inode, port))
+        .collect()
+}
+
+/// Inode and remote port of every TCP socket still in SYN_SENT to a
+/// destination the firewall drops rather than sends to Tor: all of IPv6,
+/// and IPv4 ports outside `torify_ports` (except the onion and overlay
+/// ranges, which are always redirected). Torified connects complete at once
+/// against Tor's TransPort, so only dropped ones stay in this state.
+fn failed_connects(policy: &Policy) -> Vec<(u64, u16)> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| table.lines().skip(1).filter(|line| is_syn_sent(line)).filter_map(parse_socket).collect::<Vec<_>>())
+        .filter(|&(_, addr, port)| !addr.is_loopback() && !addr.is_unspecified() && !torified(policy, addr, port))
+        .map(|(inode, _, port)| (inode, port))
+        .collect()
+}
+
+fn is_syn_sent(line: &str) -> bool {
+    // TCP_SYN_SENT in the `st` column
+    line.split_whitespace().nth(3) == Some("02")
+}
+
+fn torified(policy: &Policy, addr: IpAddr, port: u16) -> bool {
+    let IpAddr::V4(addr) = addr else {
+        return false;
+    };
+    let within = |(network, prefix): ([u8; 4], u8)| {
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        u32::from(addr) & mask == u32::from_be_bytes(network) & mask
+    };
+    within(firewall::ONION_NETWORK) || within(firewall::OVERLAY_NETWORK) || policy.torifies(port)
+}
+
+/// `sl local rem st ... inode` line of /proc/net/{udp,tcp}{,6}: inode, peer address and port
+fn parse_socket(line: &str) -> Option<(u64, IpAddr, u16)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let peer = procfs::parse_address(fields.get(2)?)?;
+    let inode = fields.get(9)?.parse().ok()?;
+    Some((inode, peer.ip(), peer.port()))
+}
+
+fn classify(port: u16) -> Kind {
+    match port {
+        443 => Kind::Quic,
+        3478 | 3479 | 5349 | 19302..=19309 => Kind::Webrtc,
+        _ => Kind::Udp(port),
+    }
+}
+
+/// Friendly name for a process, e.g. "Discord" for comm "Discord" or "discord"
+fn app_name(comm: &str) -> String {
+    let lower = comm.to_ascii_lowercase();
+    VOICE_APPS
+        .iter()
+        .find(|(prefix, _)| lower.starts_with(prefix))
+        .map_or_else(|| comm.to_string(), |(_, name)| name.to_string())
+}
+
+fn describe(app: &str, kind: Kind) -> String {
+    match kind {
+        Kind::Quic => i18n::tr("breakage-quic", &[("app", app)]),
+        Kind::Webrtc => i18n::tr("breakage-webrtc", &[("app", app)]),
+        Kind::Udp(_) if VOICE_APPS.iter().any(|(_, name)| *name == app) => {
+            i18n::tr("breakage-udp-voice", &[("app", app)])
+        }
+        Kind::Udp(port) => i18n::tr("breakage-udp", &[("app", app), ("port", &port.to_string())]),
+        Kind::Connect(port) => i18n::tr("breakage-connect", &[("app", app), ("port", &port.to_string())]),
+    }
+}
+
+fn describe_counter(counter: Counter, packets: u64) -> String {
+    match counter {
+        Counter::Blocklist => i18n::tr("breakage-blocklist", &[("packets", &packets.to_string())]),
+        Counter::Icmp => i18n::tr("breakage-icmp", &[("packets", &packets.to_string())]),
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::path::Path;
+use crate::{audit, confirm, engine, i18n, report};
+
+/// Firefox reads a single policies.json, shared by every HULIOS policy
+pub const FIREFOX_POLICIES: &str = "/etc/firefox/policies/policies.json";
+
+/// A policies.json that existed before `harden-browser`, put back on stop
+const FIREFOX_BACKUP: &str = "/var/lib/hulios/firefox-policies.json.backup";
+
+/// Session hardening for Firefox: no DoH, WebRTC only through the proxy
+/// path, no prefetching or speculative connections
+const FIREFOX_HARDENED: &str = r#"{
+  "policies": {
+    "DNSOverHTTPS": { "Enabled": false, "Locked": true },
+    "NetworkPrediction": false,
+    "Preferences": {
+      "media.peerconnection.ice.default_address_only": { "Value": true, "Status": "locked" },
+      "media.peerconnection.ice.no_host": { "Value": true, "Status": "locked" },
+      "media.peerconnection.ice.proxy_only_if_behind_proxy": { "Value": true, "Status": "locked" },
+      "network.prefetch-next": { "Value": false, "Status": "locked" },
+      "network.dns.disablePrefetch": { "Value": true, "Status": "locked" }
+    }
+  }
+}
+"#;
+
+/// The same for Chromium-based browsers, which merge every managed file
+const CHROMIUM_HARDENED: &str = r#"{
+  "DnsOverHttpsMode": "off",
+  "WebRtcIPHandling": "disable_non_proxied_udp",
+  "NetworkPredictionOptions": 2
+}
+"#;
+
+/// Name of the session policy file in a Chromium policy directory
+const CHROMIUM_FILE: &str = "hulios-harden.json";
+
+/// A browser HULIOS can write enterprise policies for
+#[derive(Clone, Copy)]
+pub enum Browser {
+    Firefox,
+    Chromium,
+    ChromiumDeb,
+    Chrome,
+    Brave,
+}
+
+impl Browser {
+    pub const ALL: [Browser; 5] = [
+        Browser::Firefox, Browser::Chromium, Browser::ChromiumDeb, Browser::Chrome, Browser::Brave,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Browser::Firefox => "Firefox",
+            Browser::Chromium => "Chromium",
+            Browser::ChromiumDeb => "Chromium (chromium-browser)",
+            Browser::Chrome => "Google Chrome",
+            Browser::Brave => "Brave",
+        }
+    }
+
+    pub fn installed(self) -> bool {
+        let binaries: &[&str] = match self {
+            Browser::Firefox => &["/usr/bin/firefox", "/usr/lib/firefox/firefox", "/usr/lib64/firefox/firefox"],
+            Browser::Chromium => &["/usr/bin/chromium"],
+            Browser::ChromiumDeb => &["/usr/bin/chromium-browser"],
+            Browser::Chrome => &["/usr/bin/google-chrome", "/usr/bin/google-chrome-stable"],
+            Browser::Brave => &["/usr/bin/brave-browser", "/usr/bin/brave"],
+        };
+        binaries.iter().any(|binary| Path::new(binary).exists())
+    }
+
+    /// Managed policy directory; None for Firefox, which uses FIREFOX_POLICIES
+    pub fn policy_dir(self) -> Option<&'static str> {
+        match self {
+            Browser::Firefox => None,
+            Browser::Chromium => Some("/etc/chromium/policies/managed"),
+            Browser::ChromiumDeb => Some("/etc/chromium-browser/policies/managed"),
+            Browser::Chrome => Some("/etc/opt/chrome/policies/managed"),
+            Browser::Brave => Some("/etc/brave/policies/managed"),
+        }
+    }
+
+    /// Per-user `Local State` file of a Chromium-based browser, relative to home
+    pub fn local_state(self) -> Option<&'static str> {
+        match self {
+            Browser::Firefox => None,
+            Browser::Chromium | Browser::ChromiumDeb => Some(".config/chromium/Local State"),
+            Browser::Chrome => Some(".config/google-chrome/Local State"),
+            Browser::Brave => Some(".config/BraveSoftware/Brave-Browser/Local State"),
+        }
+    }
+
+    fn hardened(self) -> bool {
+        match self.policy_dir() {
+            Some(dir) => Path::new(dir).join(CHROMIUM_FILE).exists(),
+            None => fs::read_to_string(FIREFOX_POLICIES).is_ok_and(|policy| policy == FIREFOX_HARDENED),
+        }
+    }
+
+    fn harden(self) -> Result<()> {
+        match self.policy_dir() {
+            Some(dir) => write_policy(&Path::new(dir).join(CHROMIUM_FILE), CHROMIUM_HARDENED),
+            None => {
+                if Path::new(FIREFOX_POLICIES).exists() && !Path::new(FIREFOX_BACKUP).exists() {
+                    fs::copy(FIREFOX_POLICIES, FIREFOX_BACKUP)
+                        .with_context(|| i18n::tr("browser-failed-back-up", &[("path", FIREFOX_POLICIES)]))?;
+                }
+                write_policy(Path::new(FIREFOX_POLICIES), FIREFOX_HARDENED)
+            }
+        }
+    }
+
+    fn unharden(self) -> Result<()> {
+        match self.policy_dir() {
+            Some(dir) => {
+                let path = Path::new(dir).join(CHROMIUM_FILE);
+                audit::record("remove", &path.to_string_lossy());
+                let _ = fs::remove_file(path);
+            }
+            None if Path::new(FIREFOX_BACKUP).exists() => {
+                audit::record("restore", FIREFOX_POLICIES);
+                fs::copy(FIREFOX_BACKUP, FIREFOX_POLICIES)
+                    .with_context(|| i18n::tr("browser-failed-restore", &[("path", FIREFOX_POLICIES)]))?;
+                fs::remove_file(FIREFOX_BACKUP)?;
+            }
+            None => {
+                audit::record("remove", FIREFOX_POLICIES);
+                let _ = fs::remove_file(FIREFOX_POLICIES);
+            }
+        }
+        Ok(())
+    }
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios harden-browser`: install hardening policies for every installed
+/// browser for the rest of the session; `hulios stop` removes them
+pub fn harden(assume_yes: bool) -> Result<()> {
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("browser-not-running-browser-policies", &[]));
+    }
+    let browsers: Vec<Browser> = Browser::ALL.into_iter().filter(|b| b.installed() && !b.hardened()).collect();
+    if browsers.is_empty() {
+        println!("[*] {}", i18n::tr("browser-no-browser-left-harden", &[]));
+        return Ok(());
+    }
+
+    let mut changes: Vec<String> = browsers
+        .iter()
+        .map(|b| i18n::tr("confirm-harden-browser-policy", &[("name", b.name()), ("policy_dir", b.policy_dir().unwrap_or(FIREFOX_POLICIES))]))
+        .collect();
+    changes.push(i18n::tr("confirm-harden-browser-disable", &[]));
+    confirm::confirm(&i18n::tr("confirm-hardening-browsers", &[]), &changes, assume_yes)?;
+
+    for browser in browsers {
+        browser.harden().with_context(|| i18n::tr("browser-failed-harden", &[("name", browser.name())]))?;
+        println!("[+] {}", i18n::tr("browser-hardened", &[("name", browser.name())]));
+        report::action(format!("{} hardened", browser.name()));
+    }
+    println!("{}", format!("[+] {}", i18n::tr("browser-restart-browsers", &[])).green());
+    Ok(())
+}
+
+/// Whether any session hardening policy is still installed
+pub fn is_hardened() -> bool {
+    Browser::ALL.into_iter().any(Browser::hardened) || Path::new(FIREFOX_BACKUP).exists()
+}
+
+/// Remove the session policies and put back a pre-existing Firefox policies.json
+pub fn unharden() -> Result<()> {
+    for browser in Browser::ALL {
+        let firefox_pending = matches!(browser, Browser::Firefox) && Path::new(FIREFOX_BACKUP).exists();
+        if browser.hardened() || firefox_pending {
+            browser.unharden()?;
+            println!("[+] {}", i18n::tr("browser-policies-removed", &[("name", browser.name())]));
+            report::action(format!("{} policies removed", browser.name()));
+        }
+    }
+    Ok(())
+}
+
+pub fn write_policy(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    audit::record("write", &path.to_string_lossy());
+    fs::write(path, content).with_context(|| i18n::tr("error-write", &[("path", &path.display().to_string())]))
+}
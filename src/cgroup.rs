@@ -0,0 +1,322 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::config::TorConfig;
+use crate::exec::Run;
+use crate::{audit, i18n, procfs, sdnotify};
+
+/// Transient scope the daemon-spawned Tor runs in on systemd machines
+pub const SCOPE: &str = "hulios-tor.scope";
+
+/// Without systemd, a cgroup of HULIOS's own at the top of the hierarchy
+const GROUP: &str = "hulios-tor";
+
+/// Groups that only ever hold HULIOS's Tor: the scope, the raw group, and
+/// the confined engine::TOR_UNIT service
+const DEDICATED: &[&str] = &[SCOPE, GROUP, "hulios-tor.service"];
+
+/// cpu.max's period, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+/// Resource usage of a cgroup, for `hulios status`
+pub struct Usage {
+    pub processes: usize,
+    pub memory: Option<u64>,
+    pub cpu: Option<Duration>,
+    /// memory.max, if capped
+    pub memory_max: Option<u64>,
+    /// Processes the kernel killed for going over memory.max
+    pub oom_kills: Option<u64>,
+}
+
+/// Memory and CPU caps on Tor's group, from `[tor]`
+#[derive(Default, PartialEq)]
+pub struct Limits {
+    pub memory_max: Option<u64>,
+    pub memory_high: Option<u64>,
+    /// Percent of one CPU
+    pub cpu_quota: Option<u32>,
+}
+
+/// The cgroup v2 mount: /sys/fs/cgroup, or its `unified` subdirectory on
+/// hybrid setups
+fn hierarchy() -> Option<&'static Path> {
+    ["/sys/fs/cgroup", "/sys/fs/cgroup/unified"]
+        .into_iter()
+        .map(Path::new)
+        .find(|dir| dir.join("cgroup.procs").exists())
+}
+
+/// Directory of a group given by its path in the hierarchy, e.g.
+/// "/system.slice/hulios-tor.scope"
+fn dir(group: &str) -> Option<PathBuf> {
+    Some(hierarchy()?.join(group.trim_start_matches('/')))
+}
+
+/// Wrap `tor` so it starts in its own transient scope, which systemd-run
+/// creates and then execs tor in, keeping the PID. Without systemd the
+/// command is returned as is, to be placed with `attach` once spawned.
+/// The wrapper's output is discarded like Tor's.
+pub fn scoped(tor: Command, limits: &Limits) -> Command {
+    if !sdnotify::booted() {
+        return tor;
+    }
+    let mut command = Command::new("systemd-run");
+    command.args(["--scope", "--unit", SCOPE, "--collect", "--quiet"]);
+    if !limits.is_empty() {
+        command.args(limits.properties().iter().map(|property| format!("--property={}", property)));
+    }
+    command
+        .arg(tor.get_program())
+        .args(tor.get_args())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    audit::command(&command);
+    command
+}
+
+/// Wait for a process started through `scoped` to become tor, so it is
+/// never seen half-started (still systemd-run, not yet in its scope)
+pub fn wait_scoped(pid: u32) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline
+        && procfs::inspect(pid as i32).is_some_and(|info| info.comm == "systemd-run")
+    {
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Move `pid` into GROUP unless it already runs in a dedicated group,
+/// capped by `limits`; the fallback for machines without systemd
+pub fn attach(pid: u32, limits: &Limits) -> Result<()> {
+    if dedicated(pid).is_some() {
+        return Ok(());
+    }
+    let dir = dir(GROUP).with_context(|| i18n::tr("error-no-cgroup2", &[]))?;
+    fs::create_dir_all(&dir).with_context(|| i18n::tr("error-create", &[("path", &dir.display().to_string())]))?;
+    audit::record("cgroup", &format!("created {}", dir.display()));
+    fs::write(dir.join("cgroup.procs"), pid.to_string())
+        .with_context(|| i18n::tr("cgroup-failed-move-tor-into", &[("dir", &dir.display().to_string())]))?;
+    if limits.is_empty() {
+        return Ok(());
+    }
+    limits.write(GROUP)
+}
+
+/// The group `pid` runs in, if it is one that holds nothing but HULIOS's
+/// Tor; never the daemon's or a user's group, which are not ours to kill
+pub fn dedicated(pid: u32) -> Option<String> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    let group = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let name = group.rsplit('/').next()?;
+    DEDICATED.contains(&name).then(|| group.to_string())
+}
+
+/// PIDs in `group`, Tor and anything it started
+pub fn members(group: &str) -> Vec<i32> {
+    dir(group)
+        .and_then(|dir| fs::read_to_string(dir.join("cgroup.procs")).ok())
+        .map(|procs| procs.lines().filter_map(|pid| pid.trim().parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// SIGTERM everything in `group`, kill whatever is left after `grace`,
+/// then remove the group if HULIOS created it. The whole tree goes and
+/// nothing outside it is touched.
+pub fn stop(group: &str, grace: Duration) {
+    let Some(dir) = dir(group) else {
+        return;
+    };
+    for pid in members(group) {
+        // SAFETY: plain signal delivery to a specific, positive PID
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+    }
+    let deadline = Instant::now() + grace;
+    while !members(group).is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+    if !members(group).is_empty() {
+        // cgroup.kill needs Linux 5.14; older kernels get one signal per PID
+        if fs::write(dir.join("cgroup.kill"), "1").is_err() {
+            for pid in members(group) {
+                // SAFETY: as above
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+            }
+        }
+    }
+    // systemd collects the scope and service itself
+    if group.rsplit('/').next() == Some(GROUP) {
+        remove(group);
+    }
+}
+
+/// Create `group` for a process HULIOS starts itself, below the top of
+/// the hierarchy
+pub fn create(group: &str) -> Result<()> {
+    let dir = dir(group).with_context(|| i18n::tr("error-no-cgroup2", &[]))?;
+    fs::create_dir_all(&dir).with_context(|| i18n::tr("error-create", &[("path", &dir.display().to_string())]))?;
+    audit::record("cgroup", &format!("created {}", dir.display()));
+    Ok(())
+}
+
+/// Remove a group HULIOS created, once its last process has gone
+pub fn remove(group: &str) {
+    let Some(dir) = dir(group) else {
+        return;
+    };
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while fs::remove_dir(&dir).is_err() && dir.exists() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// The `cgroup.procs` file a process writes to (0 for itself) to join `group`
+pub fn procs_file(group: &str) -> Option<PathBuf> {
+    Some(dir(group)?.join("cgroup.procs"))
+}
+
+/// The group's cgroup ID, the inode of its directory, which nftables
+/// compares sockets against
+pub fn id(group: &str) -> Option<u64> {
+    Some(fs::metadata(dir(group)?).ok()?.ino())
+}
+
+/// Whether `group` exists
+pub fn exists(group: &str) -> bool {
+    dir(group).is_some_and(|dir| dir.is_dir())
+}
+
+/// What `group` uses right now; memory needs the memory controller,
+/// which hybrid setups don't give cgroup v2
+pub fn usage(group: &str) -> Option<Usage> {
+    let dir = dir(group)?;
+    if !dir.exists() {
+        return None;
+    }
+    let memory = fs::read_to_string(dir.join("memory.current")).ok().and_then(|value| value.trim().parse().ok());
+    let cpu = fs::read_to_string(dir.join("cpu.stat")).ok().and_then(|stat| {
+        stat.lines()
+            .find_map(|line| line.strip_prefix("usage_usec "))
+            .and_then(|usec| usec.trim().parse().ok())
+            .map(Duration::from_micros)
+    });
+    let memory_max = fs::read_to_string(dir.join("memory.max")).ok().and_then(|value| value.trim().parse().ok());
+    let oom_kills = fs::read_to_string(dir.join("memory.events")).ok().and_then(|events| {
+        events.lines().find_map(|line| line.strip_prefix("oom_kill ")).and_then(|count| count.trim().parse().ok())
+    });
+    Some(Usage { processes: members(group).len(), memory, cpu, memory_max, oom_kills })
+}
+
+// =============================================================================
+// Limits
+// =============================================================================
+
+impl Limits {
+    /// Values `hulios validate` rejects are left out; memory is rounded
+    /// down to whole pages, as the kernel keeps it
+    pub fn from_config(tor: &TorConfig) -> Limits {
+        let memory = |value: &Option<String>| value.as_deref().and_then(|value| memory_bytes(value).ok()).map(|bytes| bytes / 4096 * 4096);
+        Limits {
+            memory_max: memory(&tor.memory_max),
+            memory_high: memory(&tor.memory_high),
+            cpu_quota: tor.cpu_quota.filter(|quota| *quota > 0),
+        }
+    }
+
+    /// The caps `group` has now
+    pub fn current(group: &str) -> Limits {
+        let read = |file: &str| dir(group).and_then(|dir| fs::read_to_string(dir.join(file)).ok()).unwrap_or_default();
+        let cpu = read("cpu.max");
+        let mut cpu = cpu.split_whitespace().map(|value| value.parse::<u64>().ok());
+        let cpu_quota = match (cpu.next().flatten(), cpu.next().flatten()) {
+            (Some(max), Some(period)) if period > 0 => Some((max * 100 / period) as u32),
+            _ => None,
+        };
+        Limits {
+            memory_max: read("memory.max").trim().parse().ok(),
+            memory_high: read("memory.high").trim().parse().ok(),
+            cpu_quota,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// As systemd unit properties; those unset lift the cap
+    pub fn properties(&self) -> Vec<String> {
+        let memory = |bytes: Option<u64>| bytes.map_or("infinity".to_string(), |bytes| bytes.to_string());
+        vec![
+            format!("MemoryMax={}", memory(self.memory_max)),
+            format!("MemoryHigh={}", memory(self.memory_high)),
+            format!("CPUQuota={}", self.cpu_quota.map_or(String::new(), |quota| format!("{}%", quota))),
+        ]
+    }
+
+    /// Write the caps into `group`'s own files, once its parent hands it
+    /// the memory and cpu controllers
+    fn write(&self, group: &str) -> Result<()> {
+        let dir = dir(group).with_context(|| i18n::tr("error-no-cgroup2", &[]))?;
+        if let Some(parent) = dir.parent() {
+            // One at a time: a single write fails whole if one is missing
+            for controller in ["+memory", "+cpu"] {
+                let _ = fs::write(parent.join("cgroup.subtree_control"), controller);
+            }
+        }
+        let memory = |bytes: Option<u64>| bytes.map_or("max".to_string(), |bytes| bytes.to_string());
+        let files = [
+            ("memory.max", memory(self.memory_max)),
+            ("memory.high", memory(self.memory_high)),
+            ("cpu.max", format!("{} {}",
+                self.cpu_quota.map_or("max".to_string(), |quota| (quota as u64 * CPU_PERIOD / 100).to_string()), CPU_PERIOD)),
+        ];
+        for (file, value) in files {
+            fs::write(dir.join(file), &value)
+                .with_context(|| i18n::tr("cgroup-failed-set", &[("file", file), ("dir", &dir.display().to_string()), ("value", &value)]))?;
+        }
+        audit::record("cgroup", &format!("limited {}", dir.display()));
+        Ok(())
+    }
+}
+
+/// Cap a running group: through systemd for its scope or service, which
+/// would otherwise put the old values back, directly for GROUP
+pub fn limit(group: &str, limits: &Limits) -> Result<()> {
+    let unit = group.rsplit('/').next().unwrap_or(group);
+    if unit == GROUP {
+        return limits.write(group);
+    }
+    let status = Command::new("systemctl")
+        .args(["set-property", "--runtime", unit])
+        .args(limits.properties())
+        .run()
+        .with_context(|| i18n::tr("cgroup-failed-run-systemctl", &[]))?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("cgroup-systemctl-set-property-failed", &[("unit", unit)]));
+    }
+    Ok(())
+}
+
+/// "512M", "1.5G", "512 MiB" or plain bytes; units are powers of 1024,
+/// as systemd reads them
+pub fn memory_bytes(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().with_context(|| i18n::tr("cgroup-invalid-memory-size", &[("size", value)]))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let shift = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => anyhow::bail!(i18n::tr("cgroup-invalid-memory-size-use", &[("size", value)])),
+    };
+    Ok((amount * (1u64 << shift) as f64) as u64)
+}
@@ -0,0 +1,286 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, ToSocketAddrs};
+use users::get_current_uid;
+use crate::config::Config;
+use crate::control::ControlClient;
+use crate::{engine, i18n, lock, report, state};
+
+/// An exit reported with `hulios badexit`, excluded for the rest of the session
+#[derive(Deserialize, Serialize, Clone)]
+pub struct BadExit {
+    /// "$FINGERPRINT~nickname", or the address if no current exit has it
+    pub node: String,
+    pub address: String,
+    pub reported_at: i64,
+}
+
+/// A built circuit, as GETINFO circuit-status lists it
+struct Circuit {
+    id: String,
+    /// Fingerprint of the last hop
+    exit: Option<String>,
+    purpose: String,
+}
+
+/// An open stream, as GETINFO stream-status lists it
+struct Stream {
+    circuit: String,
+    /// Destination host (name or address) without the port
+    host: String,
+}
+
+/// Built circuits; "ID BUILT $FP~nick,$FP~nick,... PURPOSE=GENERAL ..."
+fn circuits(client: &mut ControlClient) -> Result<Vec<Circuit>> {
+    let status = client.getinfo("circuit-status")?;
+    Ok(status
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split(' ');
+            let id = words.next()?.to_string();
+            if words.next()? != "BUILT" {
+                return None;
+            }
+            let words: Vec<&str> = words.collect();
+            let exit = words
+                .first()
+                .filter(|path| path.starts_with('$'))
+                .and_then(|path| path.rsplit(',').next())
+                .and_then(|hop| hop.trim_start_matches('$').split(['~', '=']).next())
+                .map(str::to_string);
+            let purpose = words.iter().find_map(|word| word.strip_prefix("PURPOSE=")).unwrap_or_default().to_string();
+            Some(Circuit { id, exit, purpose })
+        })
+        .collect())
+}
+
+/// Streams attached to a circuit; "ID STATUS CIRCUIT host:port"
+fn streams(client: &mut ControlClient) -> Result<Vec<Stream>> {
+    let status = client.getinfo("stream-status")?;
+    Ok(status
+        .lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split(' ').collect();
+            let (circuit, target) = (*words.get(2)?, *words.get(3)?);
+            if circuit == "0" {
+                return None;
+            }
+            let host = target.rsplit_once(':').map_or(target, |(host, _)| host);
+            Some(Stream { circuit: circuit.to_string(), host: host.trim_matches(['[', ']']).to_ascii_lowercase() })
+        })
+        .collect())
+}
+
+/// Close each circuit; one that closed meanwhile is no error
+fn close(client: &mut ControlClient, ids: &BTreeSet<String>) -> usize {
+    ids.iter().filter(|id| client.command(&format!("CLOSECIRCUIT {}", id)).is_ok()).count()
+}
+
+/// Nickname and addresses of relay `fingerprint`, from its consensus entry
+/// ("r nick identity digest date time IP ORPort DirPort", then "a [IPv6]:port")
+fn relay(client: &mut ControlClient, fingerprint: &str) -> Option<(String, Vec<IpAddr>)> {
+    let entry = client.getinfo(&format!("ns/id/{}", fingerprint)).ok()?;
+    let mut nickname = None;
+    let mut addresses = Vec::new();
+    for line in entry.lines() {
+        if let Some(fields) = line.strip_prefix("r ") {
+            nickname = fields.split(' ').next().map(str::to_string);
+            addresses.extend(fields.split(' ').filter_map(|field| field.parse::<IpAddr>().ok()));
+        } else if let Some(address) = line.strip_prefix("a ") {
+            let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+            addresses.extend(host.trim_matches(['[', ']']).parse::<IpAddr>().ok());
+        }
+    }
+    Some((nickname?, addresses))
+}
+
+/// Country codes of the exits the built circuits for connections use,
+/// from Tor's own GeoIP data
+pub fn exit_countries(client: &mut ControlClient) -> Result<BTreeSet<String>> {
+    let exits: BTreeSet<String> = circuits(client)?
+        .into_iter()
+        .filter(|circuit| circuit.purpose == "GENERAL")
+        .filter_map(|circuit| circuit.exit)
+        .collect();
+    let mut countries = BTreeSet::new();
+    for exit in exits {
+        let Some(address) = relay(client, &exit).and_then(|(_, addresses)| addresses.into_iter().find(IpAddr::is_ipv4)) else {
+            continue;
+        };
+        let country = client.getinfo(&format!("ip-to-country/{}", address)).unwrap_or_default();
+        if country.len() == 2 && country != "??" {
+            countries.insert(country.to_ascii_uppercase());
+        }
+    }
+    Ok(countries)
+}
+
+/// The configured ExcludeExitNodes with the session's bad exits added, if
+/// either has any
+pub fn excluded_exits(configured: Option<&str>, bad_exits: &[BadExit]) -> Option<String> {
+    let mut nodes: Vec<&str> = configured.into_iter().flat_map(|value| value.split(',')).collect();
+    for exit in bad_exits {
+        if !nodes.contains(&exit.node.as_str()) {
+            nodes.push(&exit.node);
+        }
+    }
+    (!nodes.is_empty()).then(|| nodes.join(","))
+}
+
+/// The bare hostname of "host", "host:port" or a URL
+fn host_of(target: &str) -> String {
+    let rest = target.trim().split_once("://").map_or(target.trim(), |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None if authority.parse::<std::net::Ipv6Addr>().is_ok() => authority,
+        None => authority.rsplit_once(':').map_or(authority, |(host, _)| host),
+    };
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios newcircuit <host>`: close the circuits carrying streams to
+/// `host`, and the other circuits through their exits, so the next
+/// connection to it gets a fresh exit while everything else keeps its
+/// circuits. Closing is the only way the control protocol offers to take
+/// a single circuit out of use.
+pub fn new_circuit(target: &str) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("error-not-running", &[]));
+    }
+    let host = host_of(target);
+    if host.is_empty() {
+        anyhow::bail!(i18n::tr("circuits-no-host", &[("target", target)]));
+    }
+    // Transparently routed apps connect to addresses, which name lookups
+    // through the DNSPort return
+    let mut names: BTreeSet<String> = (host.as_str(), 0)
+        .to_socket_addrs()
+        .map(|addrs| addrs.map(|addr| addr.ip().to_string()).collect())
+        .unwrap_or_default();
+    names.insert(host.clone());
+
+    let mut client = ControlClient::connect().with_context(|| i18n::tr("error-control-unreachable", &[]))?;
+    let carrying: BTreeSet<String> = streams(&mut client)?
+        .into_iter()
+        .filter(|stream| names.contains(&stream.host))
+        .map(|stream| stream.circuit)
+        .collect();
+    if carrying.is_empty() {
+        println!("[*] {}", i18n::tr("circuits-no-open-connections-go", &[("host", &host)]));
+        report::data("closed", 0);
+        return Ok(());
+    }
+
+    let circuits = circuits(&mut client)?;
+    let exits: BTreeSet<&str> = circuits
+        .iter()
+        .filter(|circuit| carrying.contains(&circuit.id))
+        .filter_map(|circuit| circuit.exit.as_deref())
+        .collect();
+    // Otherwise the next connection could be attached to one of them
+    let through_exits = circuits
+        .iter()
+        .filter(|circuit| circuit.purpose == "GENERAL" && circuit.exit.as_deref().is_some_and(|exit| exits.contains(exit)))
+        .map(|circuit| circuit.id.clone());
+    let ids: BTreeSet<String> = carrying.iter().cloned().chain(through_exits).collect();
+
+    let closed = close(&mut client, &ids);
+    let exit_count = i18n::tr(if exits.len() == 1 { "circuits-exit" } else { "circuits-exits" }, &[("count", &exits.len().to_string())]);
+    let id = if closed == 1 { "circuits-closed-circuit-through" } else { "circuits-closed-circuits-through" };
+    println!("{}", format!("[+] {}", i18n::tr(id, &[("closed", &closed.to_string()), ("exits", &exit_count), ("host", &host)])).green());
+    report::action(format!("Closed {} circuits to {}", closed, host));
+    report::data("closed", closed);
+    Ok(())
+}
+
+/// `hulios badexit <ip>`: a site rejects the exit it saw connecting from
+/// `ip`. The relay is looked up among the exits of the current circuits,
+/// excluded as an exit until the session ends, and its circuits closed so
+/// the next connections get another one.
+pub fn bad_exit(address: &str) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let address: IpAddr = address.trim().parse().with_context(|| i18n::tr("error-not-ip", &[("address", address.trim())]))?;
+    let _lock = lock::acquire()?;
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("error-not-running", &[]));
+    }
+    let mut session = state::load().with_context(|| i18n::tr("circuits-no-session", &[]))?;
+    if session.bad_exits.iter().any(|exit| exit.address == address.to_string()) {
+        println!("[*] {}", i18n::tr("circuits-already-excluded-session", &[("address", &address.to_string())]));
+        report::data("excluded", Vec::<String>::new());
+        return Ok(());
+    }
+
+    let mut client = ControlClient::connect().with_context(|| i18n::tr("error-control-unreachable", &[]))?;
+    let circuits = circuits(&mut client)?;
+    let exits: BTreeSet<&str> = circuits.iter().filter_map(|circuit| circuit.exit.as_deref()).collect();
+    let mut matching = BTreeSet::new();
+    let mut nodes = Vec::new();
+    for fingerprint in exits {
+        if let Some((nickname, addresses)) = relay(&mut client, fingerprint) {
+            if addresses.contains(&address) {
+                matching.insert(fingerprint.to_string());
+                nodes.push(format!("${}~{}", fingerprint, nickname));
+            }
+        }
+    }
+    if nodes.is_empty() {
+        // Tor matches addresses against where relays listen, which is
+        // usually but not always where their exit traffic comes from
+        if !address.is_ipv4() {
+            anyhow::bail!(i18n::tr("circuits-no-current-exit-address", &[("address", &address.to_string())]));
+        }
+        println!("{}", format!("[!] {}", i18n::tr("circuits-no-current-exit-excluding-address", &[("address", &address.to_string())])).yellow());
+        report::warning(format!("No current exit has address {}", address));
+        nodes.push(address.to_string());
+    }
+
+    let now = chrono::Local::now().timestamp();
+    session.bad_exits.extend(nodes.iter().map(|node| BadExit { node: node.clone(), address: address.to_string(), reported_at: now }));
+    let config = Config::load()?;
+    let configured = config.tor.options.iter().find(|(name, _)| name.eq_ignore_ascii_case("ExcludeExitNodes")).map(|(_, value)| value.as_str());
+    let excluded = excluded_exits(configured, &session.bad_exits).unwrap_or_default();
+    // Tor stops using circuits that no longer conform for new streams
+    client.setconf(&[("ExcludeExitNodes", excluded)]).with_context(|| i18n::tr("circuits-tor-refused-exclusion", &[]))?;
+    state::save(&session)?;
+
+    let ids: BTreeSet<String> = circuits
+        .iter()
+        .filter(|circuit| circuit.purpose == "GENERAL" && circuit.exit.as_ref().is_some_and(|exit| matching.contains(exit)))
+        .map(|circuit| circuit.id.clone())
+        .collect();
+    let closed = close(&mut client, &ids);
+    let id = if closed == 1 { "circuits-excluded-closed-circuit" } else { "circuits-excluded-closed-circuits" };
+    println!("{}", format!("[+] {}", i18n::tr(id, &[("nodes", &nodes.join(", ")), ("closed", &closed.to_string())])).green());
+    report::action(format!("Excluded bad exit {} ({})", nodes.join(", "), address));
+    report::data("excluded", &nodes);
+    Ok(())
+}
+
+/// `hulios badexit` with no address: the exits excluded this session
+pub fn list_bad_exits() -> Result<()> {
+    let bad_exits = state::load().map(|session| session.bad_exits).unwrap_or_default();
+    if bad_exits.is_empty() {
+        println!("[*] {}", i18n::tr("circuits-no-exits-reported-session", &[]));
+    }
+    for exit in &bad_exits {
+        let reported = chrono::DateTime::from_timestamp(exit.reported_at, 0)
+            .map(|time| time.with_timezone(&chrono::Local).format("%H:%M").to_string())
+            .unwrap_or_default();
+        println!("    {}", i18n::tr("circuits-reported", &[("node", &exit.node.bold()), ("address", &exit.address), ("reported", &reported)]));
+    }
+    report::data("bad_exits", &bad_exits);
+    Ok(())
+}
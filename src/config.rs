@@ -0,0 +1,951 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::OnceLock;
+use crate::i18n;
+use crate::netprofile;
+
+pub const CONFIG_PATH: &str = "/etc/hulios/config.toml";
+
+/// Environment variables overriding one key each, e.g.
+/// `HULIOS__TOR__BOOTSTRAP_TIMEOUT=120` for `tor.bootstrap_timeout`
+pub const ENV_PREFIX: &str = "HULIOS__";
+
+/// `--set KEY=VALUE` overrides from the command line, applied after the
+/// environment's
+static FLAG_OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+pub fn set_flag_overrides(overrides: Vec<(String, String)>) {
+    let _ = FLAG_OVERRIDES.set(overrides);
+}
+
+/// HULIOS configuration, loaded from /etc/hulios/config.toml.
+/// Every section is optional; missing values fall back to the defaults.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `appliance` for a headless box routing a LAN through Tor
+    /// (`hulios appliance enable`)
+    pub profile: Profile,
+    /// Language of messages and notifications, e.g. "fa" or "pt_BR";
+    /// `auto` or unset follows the locale (LANG)
+    pub language: Option<String>,
+    /// Messages replaced by templates, by catalog id, e.g.
+    /// `notify-started-body = "Exiting in { $country }"`
+    pub messages: BTreeMap<String, String>,
+    pub status: StatusConfig,
+    pub geoip: GeoipConfig,
+    pub tor: TorConfig,
+    pub firewall: FirewallConfig,
+    pub notifications: NotificationConfig,
+    pub privacy: PrivacyConfig,
+    pub daemon: DaemonConfig,
+    pub relay: RelayConfig,
+    pub onion: OnionConfig,
+    pub gateway: GatewayConfig,
+    pub libvirt: LibvirtConfig,
+    pub i2p: I2pConfig,
+    pub dns_only: DnsOnlyConfig,
+    pub autostart: AutostartConfig,
+    pub power: PowerConfig,
+    pub update: UpdateConfig,
+    /// Partial configs applied over this one on the networks `networks`
+    /// binds them to, e.g. `[network_profiles.work]` with
+    /// `tor = { use_bridges = true }`
+    pub network_profiles: BTreeMap<String, toml::Table>,
+    /// Which network profile applies where; the first match wins
+    pub networks: Vec<NetworkBinding>,
+    /// Friendly names for onion services, e.g. `"mail.local" = "xyz...onion"`,
+    /// emitted as Tor MapAddress lines and resolvable through the DNSPort
+    pub aliases: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Desktop,
+    /// Headless: gateway mode, started at boot, alerts to the journal and
+    /// `notifications.webhook` instead of the desktop, and the daemon
+    /// restarts a broken session by itself
+    Appliance,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FirewallConfig {
+    pub backend: Backend,
+    /// How TCP reaches the TransPort: `nat` (REDIRECT) or `tproxy` (policy
+    /// routing, falling back to `nat` where the kernel lacks support)
+    pub redirect: Redirect,
+    /// `full` torifies all TCP; `partial` only the `torify_ports`;
+    /// `onion_only` permits nothing but .onion destinations
+    pub mode: FirewallMode,
+    /// TCP destination ports forced through Tor in partial mode,
+    /// e.g. `[80, 443, "8000-8100"]`. DNS is always torified.
+    pub torify_ports: Vec<PortSpec>,
+    /// What happens to traffic outside `torify_ports` in partial mode
+    pub other_traffic: OtherTraffic,
+    /// Destinations rejected outright instead of torified: IPs, CIDRs or
+    /// domains (resolved through Tor when the rules are applied)
+    pub block: Vec<String>,
+    /// Also reject well-known DNS-over-HTTPS providers, whose lookups
+    /// would otherwise bypass the DNS redirect in partial modes
+    pub block_doh: bool,
+    /// Where `hulios doh update` downloads the provider list from
+    pub doh_list_url: Option<String>,
+    /// When started over SSH, keep that one connection open instead of
+    /// refusing to start (see `hulios start --force-ssh-cutoff`)
+    pub keep_ssh_session: bool,
+    /// What start does about connections opened before it that still
+    /// leave outside Tor: `report` them or `kill` them
+    pub existing_connections: ExistingConnections,
+    /// ICMP types this machine may send and receive outside a tracked
+    /// connection; all other ICMP is dropped and counted
+    pub icmp_allow: Vec<IcmpType>,
+    /// Let the `lan_services` reach the local network directly, as
+    /// `hulios start --allow-lan` does for one session
+    pub allow_lan: bool,
+    /// Which services `allow_lan` lets through; all of them if unset
+    pub lan_services: Option<Vec<LanService>>,
+    /// Mesh VPNs whose peers are reached directly, outside Tor
+    pub mesh_vpns: Vec<MeshVpn>,
+}
+
+/// A mesh VPN, whose traffic to its peers and own UDP may bypass Tor
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MeshVpn {
+    Tailscale,
+    Zerotier,
+}
+
+impl MeshVpn {
+    pub const ALL: [MeshVpn; 2] = [MeshVpn::Tailscale, MeshVpn::Zerotier];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MeshVpn::Tailscale => "tailscale",
+            MeshVpn::Zerotier => "zerotier",
+        }
+    }
+
+    /// Its interface, or a prefix ending in "+"
+    pub fn interface(self) -> &'static str {
+        match self {
+            MeshVpn::Tailscale => "tailscale0",
+            MeshVpn::Zerotier => "zt+",
+        }
+    }
+
+    /// The addresses its peers have; None where the network decides them,
+    /// leaving only the interface to go by
+    pub fn network(self) -> Option<([u8; 4], u8)> {
+        match self {
+            // CGNAT range, which includes MagicDNS's 100.100.100.100
+            MeshVpn::Tailscale => Some(([100, 64, 0, 0], 10)),
+            MeshVpn::Zerotier => None,
+        }
+    }
+
+    /// Process name of its daemon
+    pub fn daemon(self) -> &'static str {
+        match self {
+            MeshVpn::Tailscale => "tailscaled",
+            MeshVpn::Zerotier => "zerotier-one",
+        }
+    }
+
+    /// UDP the daemon sends, by default: (source ports, destination ports)
+    pub fn udp_ports(self) -> (&'static [u16], &'static [u16]) {
+        match self {
+            // WireGuard, and STUN to find its public address
+            MeshVpn::Tailscale => (&[41641], &[3478]),
+            MeshVpn::Zerotier => (&[9993], &[9993]),
+        }
+    }
+}
+
+/// A desktop service that talks to the local network, never through Tor
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LanService {
+    /// Printing: IPP (CUPS) and raw printing to network printers
+    Cups,
+    /// Windows file shares and NetBIOS names
+    Smb,
+    Kdeconnect,
+    /// Multicast DNS: printer and share discovery, .local names
+    Mdns,
+}
+
+impl LanService {
+    pub const ALL: [LanService; 4] = [LanService::Cups, LanService::Smb, LanService::Kdeconnect, LanService::Mdns];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LanService::Cups => "cups",
+            LanService::Smb => "smb",
+            LanService::Kdeconnect => "kdeconnect",
+            LanService::Mdns => "mdns",
+        }
+    }
+
+    /// What it sends: protocol and destination port range
+    pub fn ports(self) -> &'static [(&'static str, u16, u16)] {
+        match self {
+            LanService::Cups => &[("tcp", 631, 631), ("tcp", 9100, 9100)],
+            LanService::Smb => &[("tcp", 445, 445), ("tcp", 139, 139), ("udp", 137, 138)],
+            LanService::Kdeconnect => &[("tcp", 1714, 1764), ("udp", 1714, 1764)],
+            LanService::Mdns => &[("udp", 5353, 5353)],
+        }
+    }
+}
+
+/// The ICMP messages path MTU discovery and error reporting rely on. Echo
+/// is not among them: a ping would leave outside Tor.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IcmpType {
+    /// Includes IPv4's "fragmentation needed", which PMTU discovery uses
+    DestinationUnreachable,
+    /// IPv6 only: its PMTU discovery message
+    PacketTooBig,
+    TimeExceeded,
+    ParameterProblem,
+}
+
+impl IcmpType {
+    /// The name iptables and ip6tables know it by
+    pub fn name(self) -> &'static str {
+        match self {
+            IcmpType::DestinationUnreachable => "destination-unreachable",
+            IcmpType::PacketTooBig => "packet-too-big",
+            IcmpType::TimeExceeded => "time-exceeded",
+            IcmpType::ParameterProblem => "parameter-problem",
+        }
+    }
+
+    /// Type number in ICMP; None if it only exists in ICMPv6
+    pub fn v4(self) -> Option<u8> {
+        match self {
+            IcmpType::DestinationUnreachable => Some(3),
+            IcmpType::PacketTooBig => None,
+            IcmpType::TimeExceeded => Some(11),
+            IcmpType::ParameterProblem => Some(12),
+        }
+    }
+
+    /// Type number in ICMPv6
+    pub fn v6(self) -> u8 {
+        match self {
+            IcmpType::DestinationUnreachable => 1,
+            IcmpType::PacketTooBig => 2,
+            IcmpType::TimeExceeded => 3,
+            IcmpType::ParameterProblem => 4,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingConnections {
+    #[default]
+    Report,
+    Kill,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FirewallMode {
+    #[default]
+    Full,
+    Partial,
+    /// Only the automapped .onion range is routed; exits are never used
+    OnionOnly,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OtherTraffic {
+    /// Leaves directly, outside Tor
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// A port or an inclusive "low-high" range
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum PortSpec {
+    Port(u16),
+    Range(String),
+}
+
+impl PortSpec {
+    pub fn range(&self) -> Result<(u16, u16)> {
+        let (low, high) = match self {
+            PortSpec::Port(port) => (*port, *port),
+            PortSpec::Range(range) => {
+                let (low, high) = range.split_once('-').unwrap_or((range, range));
+                let parse = |p: &str| p.trim().parse::<u16>()
+                    .with_context(|| i18n::tr("config-invalid-port-range-expected", &[("range", range)]));
+                (parse(low)?, parse(high)?)
+            }
+        };
+        if low == 0 || low > high {
+            anyhow::bail!(i18n::tr("config-invalid-port-range", &[("low", &low.to_string()), ("high", &high.to_string())]));
+        }
+        Ok((low, high))
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Spawn the iptables/ip6tables binaries
+    #[default]
+    Iptables,
+    /// Program nftables directly over netlink, no userspace tools needed
+    Nftables,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Redirect {
+    /// NAT REDIRECT to 127.0.0.1, needs route_localnet
+    #[default]
+    Nat,
+    /// TPROXY with a fwmark and a local route; Tor sees the original
+    /// destination directly and no address is rewritten
+    Tproxy,
+}
+
+impl Redirect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Redirect::Nat => "NAT REDIRECT",
+            Redirect::Tproxy => "TPROXY",
+        }
+    }
+}
+
+/// What HULIOS and Tor keep on disk about a session
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Scrub addresses, URLs and onion names from the daemon log and run Tor with
+    /// `SafeLogging 1`; false logs them in full for debugging
+    pub log_scrub: bool,
+    /// Zero and unlink the Tor log, torrc, data directory, daemon log,
+    /// status cache and state file on stop
+    pub secure_delete: bool,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig { log_scrub: true, secure_delete: false }
+    }
+}
+
+/// Desktop notification preferences
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Master switch; false never invokes notify-send
+    pub enabled: bool,
+    /// Events that produce a notification
+    pub events: Vec<NotifyEvent>,
+    /// Per-event urgency overrides, e.g. `start = "low"`
+    pub urgency: HashMap<NotifyEvent, Urgency>,
+    /// Also POST each notification as JSON to this URL (ntfy, Gotify, a
+    /// chat bridge), for machines nobody sits in front of
+    pub webhook: Option<String>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            enabled: true,
+            events: vec![
+                NotifyEvent::Start,
+                NotifyEvent::Stop,
+                NotifyEvent::Crash,
+                NotifyEvent::Leak,
+                NotifyEvent::IdentityChange,
+                NotifyEvent::BrokenApp,
+            ],
+            urgency: HashMap::new(),
+            webhook: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    /// HULIOS started
+    Start,
+    /// HULIOS stopped or flushed
+    Stop,
+    /// Tor failed or died, or the session became degraded
+    Crash,
+    /// The post-start leak check failed
+    Leak,
+    /// Tor was restarted with fresh circuits
+    IdentityChange,
+    /// An app keeps sending traffic Tor can't carry (UDP, QUIC)
+    BrokenApp,
+}
+
+impl NotifyEvent {
+    /// Name as written in the config file
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotifyEvent::Start => "start",
+            NotifyEvent::Stop => "stop",
+            NotifyEvent::Crash => "crash",
+            NotifyEvent::Leak => "leak",
+            NotifyEvent::IdentityChange => "identity_change",
+            NotifyEvent::BrokenApp => "broken_app",
+        }
+    }
+
+    pub fn default_urgency(self) -> Urgency {
+        match self {
+            NotifyEvent::Crash | NotifyEvent::Leak => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// Value for notify-send's `-u`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+/// The background `hulios daemon`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Serve `/healthz` and `/readyz` over HTTP on 127.0.0.1 at this port
+    pub health_port: Option<u16>,
+    /// Where the daemon's log goes
+    pub log: LogSink,
+    /// Accept `hulios gateway` requests from other machines on this
+    /// address, e.g. "192.168.1.1:9300"; needs `hulios gateway init`
+    pub remote_control: Option<String>,
+    /// Ask Tor for new circuits (NEWNYM) every this many minutes
+    pub rotate_identity: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSink {
+    /// /var/lib/hulios/daemon.log
+    #[default]
+    File,
+    /// journald, with structured fields and priorities
+    Journal,
+}
+
+/// Extra options for the managed Tor instance
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TorConfig {
+    /// Average bandwidth Tor may use, e.g. "2 MB"
+    pub bandwidth_rate: Option<String>,
+    /// Maximum burst above the rate, e.g. "4 MB"
+    pub bandwidth_burst: Option<String>,
+    /// MaxClientCircuitsPending: circuits Tor may build at once
+    pub max_client_circuits_pending: Option<u32>,
+    /// Fixed CircuitBuildTimeout in seconds (disables the learned timeout)
+    pub circuit_build_timeout: Option<u32>,
+    /// Enable Tor's seccomp syscall sandbox (`Sandbox 1`)
+    pub sandbox: bool,
+    /// Forbid Tor from spawning any process (`NoExec 1`), breaks pluggable transports
+    pub no_exec: bool,
+    /// Launch Tor as a transient systemd unit with PrivateTmp, ProtectSystem,
+    /// NoNewPrivileges and a reduced capability set
+    pub confine: bool,
+    /// Hard memory cap on Tor's cgroup, e.g. "512M"; the kernel kills Tor
+    /// beyond it
+    pub memory_max: Option<String>,
+    /// Memory above which Tor's cgroup is throttled and reclaimed, e.g. "384M"
+    pub memory_high: Option<String>,
+    /// CPU Tor's cgroup may use, in percent of one CPU
+    pub cpu_quota: Option<u32>,
+    /// Have the daemon restart the session when Tor is killed, by the
+    /// kernel's OOM killer or systemd-oomd
+    pub restart_on_oom: bool,
+    /// Niceness of Tor's process, from -20 (first) to 19 (last)
+    pub nice: Option<i32>,
+    /// I/O scheduling class of Tor's process
+    pub io_class: Option<IoClass>,
+    /// Priority within the best-effort I/O class, from 0 (first) to 7 (last)
+    pub io_priority: Option<u8>,
+    /// CPU scheduling policy of Tor's process
+    pub sched_policy: Option<SchedPolicy>,
+    /// Absolute path of the tor binary; by default the first of
+    /// /usr/bin/tor, /usr/sbin/tor, /usr/local/bin/tor that exists
+    pub binary: Option<String>,
+    /// Expected SHA-256 of the tor binary (hex), checked before every start
+    pub binary_sha256: Option<String>,
+    /// Keep Tor's guards and directory cache across sessions, so a start
+    /// reuses the consensus instead of downloading it
+    pub persistent: bool,
+    /// Seconds to wait for Tor to reach 100% bootstrap (default 120)
+    pub bootstrap_timeout: Option<u64>,
+    /// Further bootstrap attempts, with exponential backoff, after Tor dies
+    /// or times out during bootstrap (default 2)
+    pub bootstrap_retries: Option<u32>,
+    /// Bridge lines (`Bridge ...` without the keyword) used for the retries
+    /// if direct bootstrap failed
+    pub bridges: Vec<String>,
+    /// Bootstrap over the bridges from the first attempt instead of only
+    /// after a direct bootstrap failed
+    pub use_bridges: bool,
+    /// `ClientTransportPlugin` for the bridges' transport, e.g.
+    /// "obfs4 exec /usr/bin/lyrebird"
+    pub transport_plugin: Option<String>,
+    /// Open Tor's HTTPTunnelPort (HTTP CONNECT proxy) on 127.0.0.1 at this
+    /// port, for apps that only speak HTTP proxies
+    pub http_tunnel_port: Option<u16>,
+    /// What `hulios start` does about a running Tor Browser
+    pub browser: TorBrowserMode,
+    /// Further torrc options set with `hulios setconf`, from its whitelist
+    pub options: BTreeMap<String, String>,
+    /// Let the daemon make the circuit tuning `hulios stats` suggests
+    /// (learned build timeout, two guards, bridges) instead of only suggesting it
+    pub auto_tune: bool,
+}
+
+/// Onion services published by the session's Tor
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OnionConfig {
+    /// Publish the local sshd with client authorization (`hulios onion ssh enable`)
+    pub ssh: bool,
+    /// Port the local sshd listens on
+    pub ssh_port: u16,
+}
+
+impl Default for OnionConfig {
+    fn default() -> Self {
+        OnionConfig { ssh: false, ssh_port: 22 }
+    }
+}
+
+/// Gateway mode: this machine is the router of a LAN and torifies it
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GatewayConfig {
+    /// Interface facing the LAN, e.g. "eth0", or a prefix ending in "+"
+    /// such as Qubes' per-VM "vif+". Its clients' DNS and TCP go through
+    /// Tor and nothing of theirs is forwarded.
+    pub lan_interface: Option<String>,
+    /// Address Tor listens on for the clients; the interface's own IPv4
+    /// address by default, and required with a "+" prefix
+    pub listen_address: Option<String>,
+    /// Also offer Tor's SOCKSPort to the clients, for apps that isolate
+    /// their own streams (e.g. Tor Browser in a workstation VM)
+    pub socks: bool,
+    /// Clients may reach nothing on this machine but Tor's ports, as with
+    /// a Whonix gateway; off on a router that also serves DHCP or ssh
+    pub tor_ports_only: bool,
+}
+
+/// What guests on libvirt's NAT bridges (virbr0...) may reach
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibvirtConfig {
+    /// For every bridge not listed in `bridges`
+    pub guests: GuestPolicy,
+    /// By bridge name, e.g. `virbr1 = "clearnet"`
+    pub bridges: BTreeMap<String, GuestPolicy>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuestPolicy {
+    /// DNS and TCP go through Tor, nothing is forwarded, as in gateway mode
+    Torify,
+    /// Forwarded by libvirt's NAT, outside Tor
+    Clearnet,
+    #[default]
+    Block,
+}
+
+impl GuestPolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            GuestPolicy::Torify => "torified",
+            GuestPolicy::Clearnet => "clearnet",
+            GuestPolicy::Block => "blocked",
+        }
+    }
+}
+
+/// Experimental: .i2p destinations through i2pd, everything else through Tor
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct I2pConfig {
+    pub enabled: bool,
+    /// Absolute path of i2pd; found in the usual places by default
+    pub binary: Option<String>,
+    /// Port of i2pd's SOCKS proxy on 127.0.0.1, which .i2p connections
+    /// are handed to
+    pub socks_port: u16,
+}
+
+impl Default for I2pConfig {
+    fn default() -> Self {
+        I2pConfig { enabled: false, binary: None, socks_port: 4447 }
+    }
+}
+
+/// `hulios dns-only`: encrypted DNS without Tor
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsOnlyConfig {
+    pub resolver: DnsOnlyResolver,
+    /// DNS-over-HTTPS endpoint of the built-in client (RFC 8484)
+    pub doh_url: String,
+    /// Addresses of doh_url's host, so reaching it needs no plaintext lookup
+    pub doh_addresses: Vec<String>,
+    /// Absolute path of dnscrypt-proxy; found in the usual places by default
+    pub dnscrypt_binary: Option<String>,
+    /// dnscrypt-proxy `server_names`; its own choice when empty
+    pub dnscrypt_servers: Vec<String>,
+}
+
+impl Default for DnsOnlyConfig {
+    fn default() -> Self {
+        DnsOnlyConfig {
+            resolver: DnsOnlyResolver::Doh,
+            doh_url: "https://dns.quad9.net/dns-query".to_string(),
+            doh_addresses: vec!["9.9.9.9".to_string(), "149.112.112.112".to_string()],
+            dnscrypt_binary: None,
+            dnscrypt_servers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsOnlyResolver {
+    /// HULIOS's own DNS-over-HTTPS client
+    #[default]
+    Doh,
+    /// dnscrypt-proxy, with the DNSCrypt and DoH servers it picks
+    Dnscrypt,
+}
+
+/// `hulios autostart`: start on untrusted Wi-Fi, offer to stop on trusted
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutostartConfig {
+    /// Act on NetworkManager connection events (`hulios autostart enable`)
+    pub enabled: bool,
+    /// Wi-Fi networks HULIOS doesn't start on, by SSID
+    pub trusted_ssids: Vec<String>,
+    /// On a trusted network, ask on the desktop whether to stop a session
+    pub stop_on_trusted: bool,
+}
+
+impl Default for AutostartConfig {
+    fn default() -> Self {
+        AutostartConfig { enabled: false, trusted_ssids: Vec::new(), stop_on_trusted: true }
+    }
+}
+
+/// `hulios update`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// Where the release feed is downloaded from
+    pub feed_url: Option<String>,
+    /// minisign public key the releases are signed with, for builds that
+    /// don't have it built in (e.g. from a git clone)
+    pub public_key: Option<String>,
+}
+
+/// Adjustments while on battery or a metered connection; both can apply
+/// at once
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    /// On battery, as reported by UPower
+    pub battery: PowerProfile,
+    /// On a connection NetworkManager considers metered
+    pub metered: PowerProfile,
+}
+
+#[derive(Deserialize, Serialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerProfile {
+    /// Replace tor.bandwidth_rate/bandwidth_burst, e.g. "200 KB"
+    pub bandwidth_rate: Option<String>,
+    pub bandwidth_burst: Option<String>,
+    /// false pauses daemon.rotate_identity
+    pub rotate_identity: bool,
+    /// Seconds between the daemon's health checks, instead of 10
+    pub monitor_interval: Option<u64>,
+}
+
+impl Default for PowerProfile {
+    fn default() -> Self {
+        PowerProfile { bandwidth_rate: None, bandwidth_burst: None, rotate_identity: true, monitor_interval: None }
+    }
+}
+
+impl PowerProfile {
+    /// Whether it changes anything
+    pub fn is_set(&self) -> bool {
+        *self != PowerProfile::default()
+    }
+}
+
+/// Binds a network profile to the networks matching every given criterion;
+/// with neither `ssid` nor `subnet` it matches any network
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkBinding {
+    /// Wi-Fi network name
+    pub ssid: Option<String>,
+    /// IPv4 network one of the interfaces is on, e.g. "10.20.0.0/16"
+    pub subnet: Option<String>,
+    /// Name of a `[network_profiles]` entry
+    pub profile: String,
+}
+
+/// `hulios relay`: a second Tor relaying for others, never for this machine
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    /// Run as a bridge, which is not listed in the public consensus
+    pub bridge: bool,
+    /// ORPort other relays and Tor users connect to
+    pub or_port: u16,
+    /// Nickname published with the relay, 1-19 letters and digits
+    pub nickname: Option<String>,
+    /// ContactInfo published with the relay, e.g. an email address
+    pub contact_info: Option<String>,
+    /// Average bandwidth the relay may use, e.g. "1 MB"
+    pub bandwidth_rate: Option<String>,
+    /// Maximum burst above the rate, e.g. "2 MB"
+    pub bandwidth_burst: Option<String>,
+    /// `ServerTransportPlugin` for a bridge, e.g. "obfs4 exec /usr/bin/lyrebird"
+    pub transport_plugin: Option<String>,
+    /// Port the bridge's pluggable transport listens on
+    pub transport_port: Option<u16>,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        RelayConfig {
+            bridge: false,
+            or_port: 9001,
+            nickname: None,
+            contact_info: None,
+            bandwidth_rate: None,
+            bandwidth_burst: None,
+            transport_plugin: None,
+            transport_port: None,
+        }
+    }
+}
+
+/// I/O scheduling class (`ionice -c`) of the managed Tor
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IoClass {
+    /// Served by priority, like every process by default
+    BestEffort,
+    /// Served only when no other process wants the disk
+    Idle,
+}
+
+/// CPU scheduling policy of the managed Tor
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedPolicy {
+    /// The default time-sharing policy
+    Other,
+    /// For CPU-bound work: scheduled like other, but never preferred for
+    /// waking up over interactive processes
+    Batch,
+    /// Runs only when the CPU would otherwise be idle
+    Idle,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TorBrowserMode {
+    /// Leave it running and warn that its circuits go Tor over Tor
+    #[default]
+    Warn,
+    /// Warn, and while HULIOS is off let `hulios status` check through its SOCKS port
+    Integrate,
+    /// Refuse to start until it is closed
+    Refuse,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusConfig {
+    /// Verification endpoints, tried in order until one answers
+    pub endpoints: Vec<CheckEndpoint>,
+    /// Local copy of the Tor exit address list used by `exit_list` endpoints
+    pub exit_list: String,
+    /// Where to fetch the exit address list (Onionoo JSON or one IP per line)
+    pub exit_list_url: String,
+    /// Seconds before the cached exit list is downloaded again
+    pub exit_list_max_age: u64,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: vec![
+                CheckEndpoint {
+                    kind: EndpointKind::TorCheck,
+                    url: "https://check.torproject.org/api/ip".to_string(),
+                },
+                CheckEndpoint {
+                    kind: EndpointKind::ExitList,
+                    url: "https://api.ipify.org".to_string(),
+                },
+            ],
+            exit_list: "/var/lib/hulios/exit-list.txt".to_string(),
+            exit_list_url: "https://onionoo.torproject.org/details?flag=Exit&fields=or_addresses,exit_addresses".to_string(),
+            exit_list_max_age: 6 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CheckEndpoint {
+    pub kind: EndpointKind,
+    pub url: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    /// JSON API in the check.torproject.org format ({"IsTor": bool, "IP": "..."})
+    TorCheck,
+    /// Plain-text "what is my IP" service, compared against the exit list
+    ExitList,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoipConfig {
+    /// City/country database (MaxMind DB format)
+    pub database: String,
+    /// ASN database (MaxMind DB format)
+    pub asn_database: String,
+    /// Download URLs for `--refresh-geoip`; `{month}` expands to YYYY-MM.
+    /// Gzipped (.gz) downloads are decompressed automatically.
+    pub database_url: String,
+    pub asn_database_url: String,
+}
+
+impl Default for GeoipConfig {
+    fn default() -> Self {
+        Self {
+            database: "/var/lib/hulios/geoip-city.mmdb".to_string(),
+            asn_database: "/var/lib/hulios/geoip-asn.mmdb".to_string(),
+            database_url: "https://download.db-ip.com/free/dbip-city-lite-{month}.mmdb.gz".to_string(),
+            asn_database_url: "https://download.db-ip.com/free/dbip-asn-lite-{month}.mmdb.gz".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file, or the defaults if it doesn't exist
+    pub fn load() -> Result<Config> {
+        let content = match fs::read_to_string(CONFIG_PATH) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| i18n::tr("error-read", &[("path", CONFIG_PATH)])),
+        };
+        let config = Config::parse(&content).with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+        with_overrides(config)
+    }
+
+    /// Parse config file contents, with the active network profile (if
+    /// any) applied over them
+    pub fn parse(source: &str) -> Result<Config> {
+        let config: Config = toml::from_str(source)?;
+        let config = match netprofile::active().and_then(|name| config.network_profiles.get(&name).map(|p| (name, p))) {
+            Some((name, profile)) => netprofile::apply(source, profile)
+                .with_context(|| i18n::tr("config-network-profile-does-not", &[("name", &name)]))?,
+            None => config,
+        };
+        Ok(config)
+    }
+}
+
+// =============================================================================
+// Overrides
+// =============================================================================
+
+/// Every override in effect, environment first, as dotted keys and raw values
+pub fn overrides() -> Vec<(String, String)> {
+    let mut from_env: Vec<(String, String)> = std::env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?.split("__").map(str::to_lowercase).collect::<Vec<_>>().join(".");
+            Some((key, value))
+        })
+        .collect();
+    from_env.sort();
+    from_env.extend(FLAG_OVERRIDES.get().cloned().unwrap_or_default());
+    from_env
+}
+
+/// `config` with each override's dotted key set to its value, read as a
+/// TOML literal (number, bool, array) or else as a string, as `hulios
+/// config set` does
+pub fn with_overrides(config: Config) -> Result<Config> {
+    let overrides = overrides();
+    if overrides.is_empty() {
+        return Ok(config);
+    }
+    let mut root = toml::Table::try_from(&config).with_context(|| i18n::tr("config-failed-apply-overrides", &[]))?;
+    for (key, value) in &overrides {
+        let parts: Vec<&str> = key.split('.').collect();
+        let (name, tables) = parts.split_last().with_context(|| i18n::tr("config-empty-override-key", &[]))?;
+        let mut table = &mut root;
+        for part in tables {
+            table = table
+                .entry(part.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .with_context(|| i18n::tr("config-override-not-table", &[("key", key), ("part", part)]))?;
+        }
+        let value = toml::from_str::<toml::Table>(&format!("v = {}", value))
+            .ok()
+            .and_then(|mut literal| literal.remove("v"))
+            .unwrap_or_else(|| toml::Value::String(value.clone()));
+        table.insert(name.to_string(), value);
+    }
+    toml::Value::Table(root).try_into().with_context(|| i18n::tr("config-invalid-config-override", &[]))
+}
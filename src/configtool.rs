@@ -0,0 +1,168 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::Path;
+use std::process::Command;
+use toml_edit::DocumentMut;
+use crate::config::{self, Config, CONFIG_PATH};
+use crate::{i18n, lock, netprofile, report, validate};
+
+// =============================================================================
+// Show
+// =============================================================================
+
+/// Print the effective configuration, built as every command builds it:
+/// the defaults, the file, the network profile, then the overrides
+pub fn show() -> Result<()> {
+    let config = Config::load()?;
+    let source = if Path::new(CONFIG_PATH).exists() { CONFIG_PATH } else { "built-in defaults" };
+    println!("# Effective configuration ({})", source);
+    if let Some(profile) = netprofile::active() {
+        println!("# Network profile in effect: {}", profile);
+    }
+    for (key, value) in config::overrides() {
+        println!("# Overridden: {} = {}", key, value);
+    }
+    print!("{}", toml::to_string_pretty(&config).with_context(|| i18n::tr("configtool-failed-render-configuration", &[]))?);
+    report::data("config", &config);
+    Ok(())
+}
+
+// =============================================================================
+// Set
+// =============================================================================
+
+/// Set one dotted key, e.g. `tor.bandwidth_rate "2 MB"`, keeping the rest of
+/// the file (comments included) untouched. The result must validate before
+/// it is written.
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let source = read_existing()?;
+    let mut doc: DocumentMut = source.parse().with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+
+    let parts: Vec<&str> = key.split('.').collect();
+    let (name, tables) = parts.split_last().with_context(|| i18n::tr("configtool-empty-key", &[]))?;
+    let mut table = doc.as_table_mut();
+    for part in tables {
+        let entry = table.entry(part).or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+        table = entry
+            .as_table_mut()
+            .with_context(|| i18n::tr("configtool-not-table", &[("part", part)]))?;
+    }
+    table.insert(name, toml_edit::value(parse_value(value)));
+
+    let updated = doc.to_string();
+    check(&updated)?;
+    write(&updated)?;
+
+    println!("{}", format!("[+] {} = {}", key, value).green());
+    report::action(format!("Set {} = {}", key, value));
+    Ok(())
+}
+
+/// Interpret the value as a TOML literal (number, bool, array), else a string
+fn parse_value(value: &str) -> toml_edit::Value {
+    format!("v = {}", value)
+        .parse::<DocumentMut>()
+        .ok()
+        .and_then(|doc| doc.get("v").and_then(|item| item.as_value().cloned()))
+        .unwrap_or_else(|| value.into())
+}
+
+// =============================================================================
+// Edit
+// =============================================================================
+
+/// Open a copy of the config in $EDITOR and install it only once it validates
+pub fn edit() -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Created exclusively with mode 0600 where only root can look, and
+    // removed when dropped
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(lock::RUN_DIR)
+        .with_context(|| i18n::tr("error-create", &[("path", lock::RUN_DIR)]))?;
+    let draft = tempfile::Builder::new()
+        .prefix("config-")
+        .suffix(".toml")
+        .tempfile_in(lock::RUN_DIR)
+        .with_context(|| i18n::tr("configtool-failed-create-draft-config", &[]))?;
+    fs::write(draft.path(), read_existing()?)?;
+    let path = draft.path().to_string_lossy().into_owned();
+
+    loop {
+        let status = Command::new("sh")
+            .args(["-c", &format!("{} \"$1\"", editor), "sh", &path])
+            .status()
+            .with_context(|| i18n::tr("configtool-failed-run-editor", &[("editor", &editor)]))?;
+        if !status.success() {
+            anyhow::bail!(i18n::tr("configtool-editor-exited-config-left", &[("status", &status.to_string())]));
+        }
+
+        let edited = fs::read_to_string(draft.path())?;
+        match check(&edited) {
+            Ok(()) => {
+                write(&edited)?;
+                println!("{}", format!("[+] {}", i18n::tr("configtool-saved", &[("path", CONFIG_PATH)])).green());
+                report::action(format!("Updated {}", CONFIG_PATH));
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", "[!]".red(), e);
+                print!("[?] {} ", i18n::tr("configtool-edit-again-y-n", &[]));
+                io::stdout().flush()?;
+                let mut answer = String::new();
+                io::stdin().lock().read_line(&mut answer)?;
+                if answer.trim().eq_ignore_ascii_case("n") {
+                    anyhow::bail!(i18n::tr("configtool-config-left-unchanged", &[]));
+                }
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+pub fn read_existing() -> Result<String> {
+    match fs::read_to_string(CONFIG_PATH) {
+        Ok(source) => Ok(source),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| i18n::tr("error-read", &[("path", CONFIG_PATH)])),
+    }
+}
+
+/// Parse and run every validation check; warnings are shown but allowed
+pub fn check(source: &str) -> Result<()> {
+    let config: Config = toml::from_str(source).with_context(|| i18n::tr("configtool-invalid-configuration", &[]))?;
+    let mut errors = Vec::new();
+    for issue in validate::check(&config, source) {
+        let line = issue.line.map(|l| format!("{} ", i18n::tr("configtool-line", &[("line", &l.to_string())]))).unwrap_or_default();
+        if issue.warning {
+            println!("{} {}{}: {}", "[*]".yellow(), line, issue.field, issue.message);
+        } else {
+            errors.push(format!("{}{}: {}", line, issue.field, issue.message));
+        }
+    }
+    if !errors.is_empty() {
+        anyhow::bail!("{}\n  {}", i18n::tr("configtool-invalid-configuration-issues", &[]), errors.join("\n  "));
+    }
+    Ok(())
+}
+
+/// Atomically replace the config file
+pub fn write(content: &str) -> Result<()> {
+    if let Some(parent) = Path::new(CONFIG_PATH).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = format!("{}.tmp", CONFIG_PATH);
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, CONFIG_PATH).with_context(|| i18n::tr("error-write", &[("path", CONFIG_PATH)]))?;
+    Ok(())
+}
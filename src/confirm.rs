@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::io::{self, BufRead, IsTerminal, Write};
+use crate::i18n;
+
+/// Describe what is about to change and ask before doing it.
+/// `assume_yes` (-y/--yes) skips the prompt; without a terminal to ask on,
+/// the operation is refused rather than performed silently.
+pub fn confirm(action: &str, changes: &[String], assume_yes: bool) -> Result<()> {
+    if assume_yes {
+        return Ok(());
+    }
+
+    println!("[*] {}", i18n::tr("confirm-will", &[("action", action)]));
+    for change in changes {
+        println!("    - {}", change);
+    }
+
+    if !io::stdin().is_terminal() {
+        anyhow::bail!(i18n::tr("confirm-no-terminal", &[]));
+    }
+
+    print!("[?] {} ", i18n::tr("confirm-continue", &[]));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => anyhow::bail!(i18n::tr("confirm-aborted", &[])),
+    }
+}
+
+/// The changes listed under catalog ids `ids`, e.g. START_CHANGES
+pub fn changes(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| i18n::tr(id, &[])).collect()
+}
+
+pub const START_CHANGES: &[&str] = &[
+    "confirm-start-stop-any-tor",
+    "confirm-start-mask-stop-systemd",
+    "confirm-start-replace-etc-resolv",
+    "confirm-start-set-firewall-output",
+    "confirm-start-enable-net-ipv4",
+];
+
+pub const FLUSH_CHANGES: &[&str] = &[
+    "confirm-flush-reset-all-iptables",
+    "confirm-flush-flush-nat-output",
+    "confirm-flush-restore-etc-resolv",
+];
+
+pub const UNINSTALL_CHANGES: &[&str] = &[
+    "confirm-uninstall-stop-restore-networking",
+    "confirm-uninstall-delete-installed-systemd",
+    "confirm-uninstall-remove-selinux-module",
+    "confirm-uninstall-delete-var-lib",
+];
+
+pub const RELAY_CHANGES: &[&str] = &[
+    "confirm-relay-run-second-tor",
+    "confirm-relay-accept-connections-anywhere",
+    "confirm-relay-publish-machine-address",
+    "confirm-relay-keep-relay-keys",
+];
+
+pub const DNS_ONLY_CHANGES: &[&str] = &[
+    "confirm-dns-only-run-encrypted-dns",
+    "confirm-dns-only-mask-stop-systemd",
+    "confirm-dns-only-replace-etc-resolv",
+    "confirm-dns-only-redirect-all-dns",
+    "confirm-dns-only-enable-net-ipv4",
+];
+
+pub const SWITCH_TO_DNS_ONLY_CHANGES: &[&str] = &[
+    "confirm-switch-to-dns-only-stop-tor-daemon",
+    "confirm-switch-to-dns-only-run-encrypted-dns",
+    "confirm-switch-to-dns-only-replace-tor-firewall",
+    "confirm-switch-to-dns-only-point-etc-resolv",
+];
+
+pub const SWITCH_TO_FULL_CHANGES: &[&str] = &[
+    "confirm-switch-to-full-start-tor-system",
+    "confirm-switch-to-full-replace-dns-only",
+    "confirm-switch-to-full-point-etc-resolv",
+];
+
+pub const AUTOSTART_CHANGES: &[&str] = &[
+    "confirm-autostart-set-autostart-enabled",
+    "confirm-autostart-install-etc-networkmanager",
+    "confirm-autostart-start-full-torification",
+    "confirm-autostart-trusted-network-ask",
+];
+
+pub const APPLIANCE_CHANGES: &[&str] = &[
+    "confirm-appliance-set-profile-appliance",
+    "confirm-appliance-torify-dns-tcp",
+    "confirm-appliance-install-enable-service",
+    "confirm-appliance-never-show-desktop",
+    "confirm-appliance-let-daemon-run",
+];
+
+pub const UPDATE_CHANGES: &[&str] = &[
+    "confirm-update-download-new-binary",
+    "confirm-update-replace-binary-place",
+];
+
+pub const DEBUG_BUNDLE_CONTENTS: &[&str] = &[
+    "confirm-debug-bundle-include-version-details",
+    "confirm-debug-bundle-include-last-lines",
+    "confirm-debug-bundle-include-firewall-rules",
+    "confirm-debug-bundle-replace-public-ip",
+];
+
+pub const ATTACH_CHANGES: &[&str] = &[
+    "confirm-attach-route-all-ipv4",
+    "confirm-attach-set-firewall-output",
+    "confirm-attach-block-all-ipv6",
+];
@@ -0,0 +1,130 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs::OpenOptions;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use users::get_current_uid;
+use crate::exec::Run;
+use crate::notify::{self, Event};
+use crate::privileged::{self, Step};
+use crate::{daemon, engine, i18n, lock, report, sdnotify, state};
+
+/// The transient unit waiting out the deadline
+const WAITER_UNIT: &str = "hulios-confirm";
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios confirm`: keep a session started with `--confirm-within`, which
+/// running this at all proves is still reachable
+pub fn confirm() -> Result<()> {
+    let _lock = privileged::lock()?;
+    let Some(session) = privileged::query::<Option<state::State>>(Step::Session)? else {
+        anyhow::bail!(i18n::tr("confirmation-not-running-may-already", &[]));
+    };
+    if session.confirm_by.is_none() {
+        println!("[*] {}", i18n::tr("confirmation-nothing-confirm-session-was", &[]));
+        return Ok(());
+    }
+    privileged::run(Step::Confirm)?;
+    println!("{}", format!("[+] {}", i18n::tr("confirmation-session-confirmed-stays-up", &[])).green());
+    report::action("Session confirmed, rollback cancelled");
+    Ok(())
+}
+
+/// Run once the deadline has passed: undo the session unless `hulios
+/// confirm` got in first
+pub fn expire() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let _lock = lock::acquire()?;
+    let Some(deadline) = state::load().and_then(|session| session.confirm_by) else {
+        println!("[*] {}", i18n::tr("confirmation-session-confirmed-time-nothing", &[]));
+        return Ok(());
+    };
+    if chrono::Local::now().timestamp() < deadline {
+        println!("[*] {}", i18n::tr("confirmation-deadline-not-passed-yet", &[]));
+        return Ok(());
+    }
+    println!("{}", format!("[!] {}", i18n::tr("confirmation-session-was-not-confirmed", &[])).yellow());
+    engine::teardown()?;
+    notify::send(Event::Stop, &i18n::tr("notify-rolled-back-title", &[]), &i18n::tr("notify-rolled-back-body", &[]));
+    report::action("Rolled back the unconfirmed session");
+    println!("[+] {}", i18n::tr("cli-stopped", &[]));
+    Ok(())
+}
+
+// =============================================================================
+// Deadline
+// =============================================================================
+
+/// Before the first change of a `--confirm-within` start, with the
+/// deadline already in the state: a process outside the starting
+/// terminal's session waits it out. Armed this early, it also rolls back
+/// a start that dies halfway, e.g. of SIGHUP when the SSH session drops.
+pub fn arm() -> Result<()> {
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+    if sdnotify::booted() {
+        // A waiter from an earlier session has nothing left to do
+        let _ = Command::new("systemctl").args(["stop", WAITER_UNIT]).stderr(Stdio::null()).run();
+        let status = Command::new("systemd-run")
+            .args(["--unit", WAITER_UNIT, "--collect", "--quiet"])
+            .arg(format!("--property=StandardOutput=append:{}", daemon::LOG))
+            .arg(format!("--property=StandardError=append:{}", daemon::LOG))
+            .arg(&exe)
+            .args(["--yes", "confirm-expired", "--wait"])
+            .stderr(Stdio::null())
+            .run();
+        if status.is_ok_and(|status| status.success()) {
+            return Ok(());
+        }
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(daemon::LOG)
+        .with_context(|| i18n::tr("error-open", &[("path", daemon::LOG)]))?;
+    Command::new(exe)
+        .args(["--yes", "confirm-expired", "--wait"])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+        .with_context(|| i18n::tr("confirmation-failed-start-confirmation-deadline", &[]))?;
+    Ok(())
+}
+
+/// `hulios confirm-expired --wait`: sleep until the deadline, then roll
+/// back. A start that completes moves the deadline, so it is read again
+/// after each wait; a confirmed or stopped session ends the wait.
+pub fn wait() -> Result<()> {
+    while let Some(remaining) = remaining() {
+        if remaining > 0 {
+            thread::sleep(Duration::from_secs(remaining as u64));
+            continue;
+        }
+        expire()?;
+    }
+    Ok(())
+}
+
+/// Once the session is up: give it `seconds` from now to be confirmed
+pub fn schedule(seconds: u64) -> Result<()> {
+    let mut session = state::load().unwrap_or_default();
+    session.confirm_by = Some(chrono::Local::now().timestamp() + seconds as i64);
+    state::save(&session)?;
+    println!("{}", format!("[!] {}", i18n::tr("confirmation-run-sudo-confirm-within", &[("seconds", &seconds.to_string())])).yellow());
+    report::warning(format!("Rolled back unless confirmed within {}s", seconds));
+    Ok(())
+}
+
+/// Seconds left to confirm the session in, if it still needs it
+pub fn remaining() -> Option<i64> {
+    let deadline = state::load()?.confirm_by?;
+    Some((deadline - chrono::Local::now().timestamp()).max(0))
+}
@@ -0,0 +1,224 @@
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::config::{Config, ExistingConnections};
+use crate::exec::Run;
+use crate::firewall::Policy;
+use crate::{i18n, privacy, procfs, report};
+
+const TABLE: &str = "/proc/net/nf_conntrack";
+
+/// One connection the kernel tracks
+pub struct Entry {
+    /// "tcp", "udp"...
+    pub protocol: String,
+    /// TCP state, e.g. "ESTABLISHED"
+    pub state: Option<String>,
+    /// Both ends in the original direction
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    /// Who answers: the destination, unless the connection was redirected
+    pub reply_source: SocketAddr,
+}
+
+/// A connection from before the start that still leaves outside Tor
+#[derive(Serialize)]
+struct Bypass {
+    protocol: String,
+    /// Only the port: the output may be shared
+    destination: String,
+    pid: Option<i32>,
+    process: Option<String>,
+}
+
+// =============================================================================
+// Table
+// =============================================================================
+
+/// Every tracked TCP and UDP connection, from /proc/net/nf_conntrack or, on
+/// kernels without it, `conntrack -L`; None if neither can be read
+pub fn entries() -> Option<Vec<Entry>> {
+    let table = if Path::new(TABLE).exists() {
+        fs::read_to_string(TABLE).ok()?
+    } else {
+        let output = Command::new("conntrack").arg("-L").stderr(Stdio::null()).run_output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    Some(table.lines().filter_map(parse).collect())
+}
+
+/// "[ipv4 2] tcp 6 431999 ESTABLISHED src=A dst=B sport=P dport=Q src=B
+/// dst=A sport=Q dport=P [ASSURED] ...": the original direction first,
+/// then the reply. Entries without ports (ICMP) are skipped.
+fn parse(line: &str) -> Option<Entry> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let protocol = words.iter().find(|word| matches!(**word, "tcp" | "udp" | "udplite" | "sctp" | "dccp"))?;
+    let state = words.iter()
+        .find(|word| word.len() > 1 && word.chars().all(|c| c.is_ascii_uppercase() || c == '_'))
+        .map(|state| state.to_string());
+    let values = |key: &str| -> Vec<&str> {
+        words.iter().filter_map(|word| word.strip_prefix(key)?.strip_prefix('=')).collect()
+    };
+    let (src, dst, sport, dport) = (values("src"), values("dst"), values("sport"), values("dport"));
+    let address = |ips: &Vec<&str>, ports: &Vec<&str>, i: usize| -> Option<SocketAddr> {
+        Some(SocketAddr::new(ips.get(i)?.parse().ok()?, ports.get(i)?.parse().ok()?))
+    };
+    Some(Entry {
+        protocol: protocol.to_string(),
+        state,
+        source: address(&src, &sport, 0)?,
+        destination: address(&dst, &dport, 0)?,
+        reply_source: address(&src, &sport, 1)?,
+    })
+}
+
+// =============================================================================
+// Check
+// =============================================================================
+
+/// After start: connections opened before the firewall went up are let
+/// through by its ESTABLISHED accept rule, outside Tor. Find those of this
+/// machine's processes other than Tor, and report or (with
+/// `firewall.existing_connections = "kill"`) cut them.
+pub fn check_existing(config: &Config, tor_user: &str) {
+    let Some(found) = bypassing(config, tor_user) else {
+        println!("[*] {}", i18n::tr("conntrack-connections-before-start-not", &[]));
+        report::warning("Connections from before the start not checked (no conntrack table)");
+        return;
+    };
+    if found.is_empty() {
+        println!("{}", format!("[+] {}", i18n::tr("conntrack-no-connection-before-start", &[])).green());
+        report::action("Verified: no connection from before the start bypasses Tor");
+        return;
+    }
+
+    let kill = config.firewall.existing_connections == ExistingConnections::Kill;
+    let listed: Vec<Bypass> = found.iter().map(|(entry, pid)| {
+        let process = pid.and_then(procfs::inspect).map(|info| info.comm);
+        Bypass {
+            protocol: entry.protocol.clone(),
+            destination: format!("{}:{}", privacy::SCRUBBED, entry.destination.port()),
+            pid: *pid,
+            process,
+        }
+    }).collect();
+    println!("{}", format!("[!] {}", i18n::tr("conntrack-connection-opened-before-start", &[("count", &found.len().to_string())])).yellow());
+    for flow in &listed {
+        println!("    {}", i18n::tr("conntrack-pid", &[("protocol", &flow.protocol), ("destination", &flow.destination), ("process", flow.process.as_deref().unwrap_or("?")), ("pid", &flow.pid.map_or("?".to_string(), |pid| pid.to_string()))]));
+    }
+    report::data("existing_connections", &listed);
+
+    if !kill {
+        println!("[*] {}", i18n::tr("conntrack-they-end-when-their", &[]));
+        report::warning(format!("{} connection(s) from before the start bypass Tor", found.len()));
+        return;
+    }
+    for (entry, _) in &found {
+        cut(entry);
+    }
+    let left = bypassing(config, tor_user).map_or(0, |left| left.len());
+    if left == 0 {
+        println!("{}", format!("[+] {}", i18n::tr("conntrack-cut-connection-before-start", &[("count", &found.len().to_string())])).green());
+        report::action(format!("Cut {} connection(s) from before the start", found.len()));
+    } else {
+        eprintln!("[!] {}", i18n::tr("conntrack-connection-before-start-could", &[("left", &left.to_string())]));
+        report::warning(format!("{} connection(s) from before the start could not be cut", left));
+    }
+}
+
+/// Tracked connections from a socket on this machine, not Tor's, to
+/// somewhere outside it that the ruleset would not let a new one reach
+/// directly; each with the PID holding the socket
+fn bypassing(config: &Config, tor_user: &str) -> Option<Vec<(Entry, Option<i32>)>> {
+    let entries = entries()?;
+    let policy = Policy::from_config(config);
+    let tor_uid = users::get_user_by_name(tor_user).map(|user| user.uid());
+    let tcp = procfs::established_tcp();
+    let udp = procfs::udp_sockets();
+    let owners = procfs::socket_owners();
+
+    let found = entries.into_iter()
+        // Loopback, or redirected to Tor's ports
+        .filter(|entry| !entry.destination.ip().is_loopback() && !entry.reply_source.ip().is_loopback())
+        .filter(|entry| !leaves_directly(&policy, entry))
+        .filter_map(|entry| {
+            // Only connections this machine opened have a socket with the
+            // original source as its local end
+            let inode = match entry.protocol.as_str() {
+                "tcp" if entry.state.as_deref() == Some("ESTABLISHED") => tcp.iter()
+                    .find(|(local, remote, _)| *local == entry.source && *remote == entry.destination)
+                    .map(|(_, _, inode)| *inode),
+                "udp" => udp.iter()
+                    .find(|(local, _)| local.port() == entry.source.port()
+                        && (local.ip() == entry.source.ip() || local.ip().is_unspecified()))
+                    .map(|(_, inode)| *inode),
+                _ => None,
+            }?;
+            let pid = owners.get(&inode).copied();
+            let uid = pid.and_then(procfs::inspect).map(|info| info.uid);
+            if uid.is_some() && uid == tor_uid {
+                return None;
+            }
+            Some((entry, pid))
+        })
+        .collect();
+    Some(found)
+}
+
+/// A LAN service let out with `allow_lan`, a mesh VPN's, or in partial
+/// mode with other traffic allowed: what a new connection to the same port
+/// could do anyway
+fn leaves_directly(policy: &Policy, entry: &Entry) -> bool {
+    if policy.lan_flows().iter().any(|flow| flow.matches(&entry.protocol, entry.destination)) {
+        return true;
+    }
+    for mesh in &policy.mesh {
+        let (sports, dports) = mesh.vpn.udp_ports();
+        let daemon = entry.protocol == "udp"
+            && (sports.contains(&entry.source.port()) || dports.contains(&entry.destination.port()));
+        let peer = match (mesh.vpn.network(), entry.destination.ip()) {
+            (Some((network, prefix)), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(ip) & mask == u32::from_be_bytes(network) & mask
+            }
+            _ => false,
+        };
+        if daemon || peer {
+            return true;
+        }
+    }
+    if !policy.is_partial_allow() {
+        return false;
+    }
+    let port = entry.destination.port();
+    // Dropped in every mode: DNS, DNS-over-TLS and QUIC
+    let dropped = policy.torifies(port) || matches!(port, 53 | 853) || (entry.protocol == "udp" && port == 443);
+    !dropped
+}
+
+/// Forget the connection, so its next packet meets the ruleset as a new
+/// one, and destroy a TCP socket so its app notices at once
+fn cut(entry: &Entry) {
+    let _ = Command::new("conntrack")
+        .args(["-D", "-p", &entry.protocol,
+            "--orig-src", &entry.source.ip().to_string(), "--orig-dst", &entry.destination.ip().to_string(),
+            "--sport", &entry.source.port().to_string(), "--dport", &entry.destination.port().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .run();
+    if entry.protocol == "tcp" {
+        let _ = Command::new("ss")
+            .args(["-K", "-t",
+                "src", &entry.source.ip().to_string(), "sport", "=", &format!(":{}", entry.source.port()),
+                "dst", &entry.destination.ip().to_string(), "dport", "=", &format!(":{}", entry.destination.port())])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .run();
+    }
+}
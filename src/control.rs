@@ -0,0 +1,359 @@
+use anyhow::{Result, Context};
+use sha1::{Digest, Sha1};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use crate::engine::TOR_DATA_DIR;
+use crate::{engine, i18n, report};
+
+const CONTROL_HOST: &str = "127.0.0.1";
+const CONTROL_PORT: u16 = 9052;
+const CONTROL_ADDR: &str = "127.0.0.1:9052";
+
+/// The session's control port password, root only. A new one is made for
+/// every session and removed when it ends.
+const PASSWORD_PATH: &str = "/run/hulios/control_password";
+
+/// Its HashedControlPassword, kept so every torrc rendered this session
+/// has the same salt: `hulios reload` compares them line by line
+const HASH_PATH: &str = "/run/hulios/control_password.hash";
+
+/// S2K count byte Tor's own --hash-password uses: 65536 bytes hashed
+const S2K_SPECIFIER: u8 = 0x60;
+
+/// Minimal client for Tor's control protocol (control-spec.txt),
+/// authenticated with the session's password.
+pub struct ControlClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl ControlClient {
+    /// Connect to the HULIOS-managed Tor and authenticate
+    pub fn connect() -> Result<ControlClient> {
+        Self::open(CONTROL_ADDR, &authenticate()?)
+    }
+
+    /// Connect to another Tor HULIOS runs (the relay), which authenticates
+    /// with the cookie file in its data directory
+    pub fn connect_with_cookie(addr: &str, cookie_path: &str) -> Result<ControlClient> {
+        Self::open(addr, &cookie_authentication(cookie_path)?)
+    }
+
+    fn open(addr: &str, authentication: &str) -> Result<ControlClient> {
+        let stream = TcpStream::connect(addr)
+            .with_context(|| i18n::tr("control-failed-connect-tor-control", &[("addr", addr)]))?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        let mut client = ControlClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+
+        client.command(authentication)
+            .with_context(|| i18n::tr("control-tor-control-port-authentication", &[]))?;
+
+        Ok(client)
+    }
+
+    /// Send a raw command and return the reply lines (without status codes).
+    /// Fails if Tor answers with anything other than 250.
+    pub fn command(&mut self, line: &str) -> Result<Vec<String>> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.writer.flush()?;
+
+        let mut lines = Vec::new();
+        loop {
+            let mut raw = String::new();
+            if self.reader.read_line(&mut raw)? == 0 {
+                anyhow::bail!(i18n::tr("error-control-closed", &[]));
+            }
+            let (sep, text) = status_line(&raw)?;
+            match sep {
+                // Data reply: body follows until a lone "."
+                "+" => {
+                    lines.push(text.to_string());
+                    loop {
+                        let mut data = String::new();
+                        if self.reader.read_line(&mut data)? == 0 {
+                            anyhow::bail!(i18n::tr("error-control-closed", &[]));
+                        }
+                        let data = data.trim_end_matches(['\r', '\n']);
+                        if data == "." {
+                            break;
+                        }
+                        lines.push(data.to_string());
+                    }
+                }
+                "-" => lines.push(text.to_string()),
+                _ => {
+                    if text != "OK" {
+                        lines.push(text.to_string());
+                    }
+                    return Ok(lines);
+                }
+            }
+        }
+    }
+
+    /// SETCONF one or more options on the running Tor
+    pub fn setconf(&mut self, settings: &[(&str, String)]) -> Result<()> {
+        let args: Vec<String> = settings
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        self.command(&format!("SETCONF {}", args.join(" ")))?;
+        Ok(())
+    }
+
+    /// Replace Tor's whole configuration with `torrc` (LOADCONF, a SIGHUP
+    /// with a new file). Tor rejects it as a whole if any option is invalid
+    /// or can't change while running.
+    pub fn loadconf(&mut self, torrc: &str) -> Result<()> {
+        let mut body = String::from("+LOADCONF\r\n");
+        for line in torrc.lines() {
+            // Data lines starting with "." are dot-escaped
+            if line.starts_with('.') {
+                body.push('.');
+            }
+            body.push_str(line);
+            body.push_str("\r\n");
+        }
+        body.push('.');
+        self.command(&body)?;
+        Ok(())
+    }
+
+    /// GETINFO a single key, returning its value
+    pub fn getinfo(&mut self, key: &str) -> Result<String> {
+        let lines = self.command(&format!("GETINFO {}", key))?;
+        let prefix = format!("{}=", key);
+        let first = lines.first().with_context(|| i18n::tr("control-empty-getinfo-reply", &[]))?;
+        let value = first.strip_prefix(&prefix).unwrap_or(first);
+        // Multi-line values come back as a data reply after "key="
+        if value.is_empty() && lines.len() > 1 {
+            return Ok(lines[1..].join("\n"));
+        }
+        Ok(value.to_string())
+    }
+}
+
+/// The same client on tokio, for the daemon's tasks
+pub struct AsyncControlClient {
+    reader: tokio::io::BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl AsyncControlClient {
+    /// Connect to the HULIOS-managed Tor and authenticate
+    pub async fn connect() -> Result<AsyncControlClient> {
+        let stream = tokio::time::timeout(Duration::from_secs(10), tokio::net::TcpStream::connect(CONTROL_ADDR))
+            .await
+            .with_context(|| i18n::tr("control-timed-out-connecting-tor", &[]))?
+            .with_context(|| i18n::tr("control-failed-connect-tor-control-port", &[("addr", CONTROL_ADDR)]))?;
+        let (reader, writer) = stream.into_split();
+        let mut client = AsyncControlClient { reader: tokio::io::BufReader::new(reader), writer };
+        client.command(&authenticate()?).await
+            .with_context(|| i18n::tr("control-tor-control-port-authentication", &[]))?;
+        Ok(client)
+    }
+
+    /// Send a raw command and return the reply lines, like ControlClient::command
+    pub async fn command(&mut self, line: &str) -> Result<Vec<String>> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let raw = self.read_line().await?;
+            let (sep, text) = status_line(&raw)?;
+            match sep {
+                "+" => {
+                    lines.push(text.to_string());
+                    loop {
+                        let data = self.read_line().await?;
+                        let data = data.trim_end_matches(['\r', '\n']);
+                        if data == "." {
+                            break;
+                        }
+                        lines.push(data.to_string());
+                    }
+                }
+                "-" => lines.push(text.to_string()),
+                _ => {
+                    if text != "OK" {
+                        lines.push(text.to_string());
+                    }
+                    return Ok(lines);
+                }
+            }
+        }
+    }
+
+    /// GETINFO a single-line key, returning its value
+    pub async fn getinfo(&mut self, key: &str) -> Result<String> {
+        let lines = self.command(&format!("GETINFO {}", key)).await?;
+        let first = lines.first().with_context(|| i18n::tr("control-empty-getinfo-reply", &[]))?;
+        Ok(first.strip_prefix(&format!("{}=", key)).unwrap_or(first).to_string())
+    }
+
+    /// Wait for the next asynchronous event after SETEVENTS, without its
+    /// "650 " prefix. Events can be hours apart, so there is no timeout.
+    pub async fn next_event(&mut self) -> Result<String> {
+        loop {
+            let mut raw = String::new();
+            if self.reader.read_line(&mut raw).await? == 0 {
+                anyhow::bail!(i18n::tr("error-control-closed", &[]));
+            }
+            // Continuation lines of multi-line events are skipped
+            if let Some(event) = raw.trim_end_matches(['\r', '\n']).strip_prefix("650 ") {
+                return Ok(event.to_string());
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut raw = String::new();
+        let read = tokio::time::timeout(Duration::from_secs(10), self.reader.read_line(&mut raw))
+            .await
+            .with_context(|| i18n::tr("control-timed-out-waiting-tor", &[]))??;
+        if read == 0 {
+            anyhow::bail!(i18n::tr("error-control-closed", &[]));
+        }
+        Ok(raw)
+    }
+}
+
+/// The AUTHENTICATE command for the session's password, or for the cookie
+/// file of a Tor started before sessions had one
+fn authenticate() -> Result<String> {
+    if let Some(password) = password() {
+        return Ok(format!("AUTHENTICATE \"{}\"", password));
+    }
+    cookie_authentication(&format!("{}/control_auth_cookie", TOR_DATA_DIR))
+}
+
+fn cookie_authentication(cookie_path: &str) -> Result<String> {
+    let cookie = fs::read(cookie_path)
+        .with_context(|| i18n::tr("control-failed-read-you-root", &[("cookie_path", cookie_path)]))?;
+    Ok(format!("AUTHENTICATE {}", hex(&cookie)))
+}
+
+/// Split a reply line into its separator and text; fails unless the
+/// status code is 250
+fn status_line(raw: &str) -> Result<(&str, &str)> {
+    let raw = raw.trim_end_matches(['\r', '\n']);
+    if raw.len() < 4 {
+        anyhow::bail!(i18n::tr("control-malformed-control-reply", &[("raw", raw)]));
+    }
+    let (code, sep, text) = (&raw[..3], &raw[3..4], &raw[4..]);
+    if code != "250" {
+        anyhow::bail!(i18n::tr("control-tor-replied", &[("code", code), ("text", text)]));
+    }
+    Ok((sep, text))
+}
+
+// =============================================================================
+// Session Password
+// =============================================================================
+
+/// Make a new random password for the session's Tor, replacing the last one
+pub fn new_password() -> Result<()> {
+    let mut bytes = [0u8; 32];
+    random(&mut bytes)?;
+    let _ = fs::remove_file(HASH_PATH);
+    let _ = fs::remove_file(PASSWORD_PATH);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(PASSWORD_PATH)
+        .with_context(|| i18n::tr("error-create", &[("path", PASSWORD_PATH)]))?;
+    file.write_all(hex(&bytes).as_bytes())?;
+    Ok(())
+}
+
+/// Forget the password once the session is over
+pub fn forget_password() {
+    let _ = fs::remove_file(PASSWORD_PATH);
+    let _ = fs::remove_file(HASH_PATH);
+}
+
+fn password() -> Option<String> {
+    fs::read_to_string(PASSWORD_PATH).ok().map(|password| password.trim().to_string())
+}
+
+/// The torrc's HashedControlPassword for the session's password, salted
+/// and iterated like `tor --hash-password` (RFC 2440 S2K), without the
+/// password ever appearing on a command line. Hashed once per password.
+pub fn hashed_password() -> Result<String> {
+    if password().is_none() {
+        new_password()?;
+    }
+    if let Ok(hash) = fs::read_to_string(HASH_PATH) {
+        return Ok(hash.trim().to_string());
+    }
+    let password = password().with_context(|| i18n::tr("control-no-control-password-session", &[]))?;
+    let mut salt = [0u8; 8];
+    random(&mut salt)?;
+
+    let mut input = salt.to_vec();
+    input.extend_from_slice(password.as_bytes());
+    let mut count = (16usize + (S2K_SPECIFIER & 15) as usize) << ((S2K_SPECIFIER >> 4) + 6);
+    let mut hasher = Sha1::new();
+    while count > 0 {
+        let take = count.min(input.len());
+        hasher.update(&input[..take]);
+        count -= take;
+    }
+
+    let mut key = salt.to_vec();
+    key.push(S2K_SPECIFIER);
+    key.extend_from_slice(&hasher.finalize());
+    let hash = format!("16:{}", hex(&key));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(HASH_PATH)
+        .with_context(|| i18n::tr("error-create", &[("path", HASH_PATH)]))?;
+    file.write_all(hash.as_bytes())?;
+    Ok(hash)
+}
+
+pub fn random(buf: &mut [u8]) -> Result<()> {
+    // SAFETY: buf is valid for writes of its length
+    let read = unsafe { libc::getrandom(buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if read != buf.len() as isize {
+        return Err(std::io::Error::last_os_error()).with_context(|| i18n::tr("control-failed-get-random-bytes", &[]));
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Print shell exports for tools that speak Tor's control protocol, in the
+/// variables Tor Browser's launcher reads: `eval "$(sudo hulios controlsocket)"`
+pub fn print_connection() -> Result<()> {
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("control-not-running-tor-no", &[]));
+    }
+    let password = password()
+        .with_context(|| i18n::tr("control-cannot-read-you-root", &[("path", PASSWORD_PATH)]))?;
+    let vars = [
+        ("TOR_CONTROL_HOST", CONTROL_HOST.to_string()),
+        ("TOR_CONTROL_PORT", CONTROL_PORT.to_string()),
+        ("TOR_CONTROL_PASSWD", password),
+    ];
+    println!("# Control port of the HULIOS session; the password changes every session");
+    for (name, value) in &vars {
+        println!("export {}={}", name, value);
+    }
+    report::data("control", vars.into_iter().collect::<std::collections::BTreeMap<_, _>>());
+    Ok(())
+}
@@ -0,0 +1,854 @@
+use anyhow::{Result, Context};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::os::fd::OwnedFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::time::{self, MissedTickBehavior};
+use crate::config::{Config, LogSink, Profile};
+use crate::control::AsyncControlClient;
+use crate::exec::Run;
+use crate::health::{self, Assessment, Health};
+use crate::journal::{self, Priority};
+use crate::notify::{self, Event};
+use crate::power::{self, PowerState};
+use crate::scheduling::Scheduling;
+use crate::{amnesic, breakage, cgroup, engine, exitlist, firewall, gateway, healthz, i18n, inotify, ipc, netprofile, privacy, procfs, relay, sdnotify, state, stats, tordata, tuning, verify};
+
+/// PID of the background `hulios daemon` watching the session
+const PID_FILE: &str = "/run/hulios/daemon.pid";
+
+/// The daemon has no terminal; its messages are appended here
+pub const LOG: &str = "/var/lib/hulios/daemon.log";
+
+/// Processes known to rewrite /etc/resolv.conf, checked when the writer
+/// can't be caught with the file still open
+const RESOLV_WRITERS: &[&str] = &[
+    "NetworkManager", "resolvconf", "dhclient", "dhcpcd", "openvpn",
+    "wg-quick", "netconfig", "systemd-resolve",
+];
+
+/// Sysctls the redirection depends on, held for the whole session: a fixed
+/// value, or None to keep whatever the session started with
+const PINNED_SYSCTLS: &[(&str, Option<&str>)] = &[
+    // The DNS redirect to 127.0.0.1 is dropped as martian without it
+    (engine::ROUTE_LOCALNET, Some("1")),
+    ("net.ipv4.conf.all.rp_filter", None),
+    ("net.ipv4.conf.default.rp_filter", None),
+];
+
+/// Wait after start before the first health check, while Tor settles
+const MONITOR_GRACE: Duration = Duration::from_secs(30);
+
+const MONITOR_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Look a name up through the DNSPort every this many monitor ticks
+const DNS_CHECK_TICKS: u32 = 6;
+const DNS_PORT: &str = "127.0.0.1:9061";
+
+/// Transient unit the daemon runs in on systemd machines
+const DAEMON_UNIT: &str = "hulios-daemon";
+
+/// systemd restarts the daemon if no heartbeat arrives for this long
+const WATCHDOG_SEC: u64 = 90;
+
+/// Without a completed health check for this long the monitor is considered
+/// hung and the watchdog heartbeat stops
+const MONITOR_STALL: Duration = Duration::from_secs(60);
+
+/// How long `spawn` waits for the new daemon's control socket
+const SOCKET_WAIT: Duration = Duration::from_secs(5);
+
+/// /proc/sys produces no inotify events, so the sysctls are polled
+const SYSCTL_POLL: Duration = Duration::from_secs(2);
+
+/// Reversions of one sysctl before the user is alerted
+const REVERSION_ALERT: u32 = 3;
+
+/// How often the network is looked at for `[[networks]]` profile bindings
+const NETWORK_POLL: Duration = Duration::from_secs(15);
+
+/// How often UPower and NetworkManager are asked for the `[power]` state
+const POWER_POLL: Duration = Duration::from_secs(30);
+
+/// Appliance profile: checks in a row needing a restart before the daemon
+/// runs it, and how long before it tries again
+const HEAL_CHECKS: u32 = 3;
+const HEAL_COOLDOWN: Duration = Duration::from_secs(600);
+/// When the daemon last restarted the session; /run, so it resets at boot
+const HEAL_STAMP: &str = "/run/hulios/healed";
+
+// =============================================================================
+// Lifecycle
+// =============================================================================
+
+/// Launch `hulios daemon` in the background, detached from the terminal,
+/// unless it is already running; returns once its control socket is up
+pub fn spawn() -> Result<()> {
+    if running().is_some() {
+        return Ok(());
+    }
+    ipc::remove();
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+    if !sdnotify::booted() || spawn_unit(&exe).is_err() {
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(LOG)
+            .with_context(|| i18n::tr("error-open", &[("path", LOG)]))?;
+        Command::new(exe)
+            .arg("daemon")
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            // Own process group, so Ctrl-C in the starting terminal doesn't reach it
+            .process_group(0)
+            .spawn()
+            .with_context(|| i18n::tr("daemon-failed-start-daemon", &[]))?;
+    }
+
+    let deadline = Instant::now() + SOCKET_WAIT;
+    while !Path::new(ipc::SOCKET).exists() {
+        if Instant::now() > deadline {
+            anyhow::bail!(i18n::tr("daemon-did-not-come-up", &[("log", LOG)]));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    Ok(())
+}
+
+/// Run the daemon as a transient Type=notify unit, so systemd restarts it
+/// if its heartbeat stops and `systemctl status hulios-daemon` shows the
+/// session's state. Only the daemon itself is killed on a restart; a Tor
+/// it spawned keeps running and is adopted by the next one.
+fn spawn_unit(exe: &Path) -> Result<()> {
+    let status = Command::new("systemd-run")
+        .args(["--unit", DAEMON_UNIT, "--collect", "--quiet"])
+        .arg("--property=Type=notify")
+        .arg(format!("--property=WatchdogSec={}", WATCHDOG_SEC))
+        .arg("--property=Restart=on-watchdog")
+        .arg("--property=KillMode=process")
+        .arg(format!("--property=StandardOutput=append:{}", LOG))
+        .arg(format!("--property=StandardError=append:{}", LOG))
+        .arg(exe)
+        .arg("daemon")
+        .stderr(Stdio::null())
+        .run()?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("daemon-systemd-run-failed-start", &[("path", DAEMON_UNIT)]));
+    }
+    Ok(())
+}
+
+/// Stop the daemon before undoing the session, so it doesn't fight the
+/// restore. A Tor it spawned keeps running until it is stopped itself.
+pub fn stop() {
+    if let Some(pid) = running() {
+        procfs::terminate(pid, Duration::from_secs(2));
+    }
+    let _ = fs::remove_file(PID_FILE);
+}
+
+/// PID of the running daemon, guarding against PID reuse
+fn running() -> Option<i32> {
+    let pid = fs::read_to_string(PID_FILE).ok()?.trim().parse::<i32>().ok()?;
+    procfs::inspect(pid)
+        .is_some_and(|info| info.cmdline.get(1).is_some_and(|arg| arg == "daemon"))
+        .then_some(pid)
+}
+
+/// Body of `hulios daemon`: watch the session until it ends or the daemon
+/// is told to stop
+pub fn run() -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let scrub = config.privacy.log_scrub;
+    // The journal is on disk, which an amnesic session must not touch
+    let journal = config.daemon.log == LogSink::Journal && !amnesic::is_active() && match journal::open(scrub) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("[!] Logging to {} instead of the journal: {:#}", LOG, e);
+            false
+        }
+    };
+    if !journal && scrub {
+        privacy::scrub_output(LOG)?;
+    }
+    println!("[*] {} daemon started (pid {})", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), std::process::id());
+    fs::write(PID_FILE, std::process::id().to_string())?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the daemon's runtime")?;
+    let result = runtime.block_on(supervise());
+    sdnotify::notify("STOPPING=1");
+    // The resolv.conf watch blocks in read(); don't wait for it
+    runtime.shutdown_background();
+
+    ipc::remove();
+    let _ = fs::remove_file(PID_FILE);
+    result
+}
+
+/// Run every watch concurrently; returns once the session has failed or
+/// stopped, or on SIGTERM/SIGINT
+async fn supervise() -> Result<()> {
+    let started_at = chrono::Utc::now().timestamp();
+    let (health_tx, health_rx) = watch::channel(None);
+    let (launched_tx, launched_rx) = mpsc::channel(1);
+    let kick = Arc::new(Notify::new());
+    // When the monitor last finished a check; the first one is due after the grace
+    let checked = Arc::new(Mutex::new(Instant::now() + MONITOR_GRACE));
+    let listener = ipc::bind()?;
+
+    tokio::task::spawn_blocking(|| {
+        if let Err(e) = watch_resolv() {
+            eprintln!("[!] resolv.conf watch stopped: {:#}", e);
+        }
+    });
+    tokio::spawn(watch_sysctls());
+    tokio::spawn(supervise_tor(launched_rx, kick.clone()));
+    tokio::spawn(tuning::watch());
+    let config = Config::load().unwrap_or_default();
+    if !config.networks.is_empty() {
+        tokio::spawn(watch_network());
+    }
+    let (power_tx, power_rx) = watch::channel(power::current(&config));
+    if power::is_configured(&config) {
+        tokio::spawn(watch_power(power_tx));
+    }
+    if let Some(minutes) = config.daemon.rotate_identity {
+        tokio::spawn(rotate_identity(Duration::from_secs(minutes * 60), power_rx.clone()));
+    }
+    if let Some(port) = config.daemon.health_port {
+        let health = health_rx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = healthz::serve(port, health).await {
+                eprintln!("[!] Health endpoint stopped: {:#}", e);
+            }
+        });
+    }
+    if let Some(address) = config.daemon.remote_control {
+        let health = health_rx.clone();
+        tokio::spawn(async move {
+            let handler = move |request| {
+                let health = health_name(&health);
+                async move {
+                    match request {
+                        gateway::Request::Status => serde_json::json!(ipc::DaemonStatus {
+                            pid: std::process::id(),
+                            started_at,
+                            health,
+                            circuit_established: circuit_established().await,
+                        }),
+                        gateway::Request::Newnym => match newnym().await {
+                            Ok(()) => serde_json::json!({ "newnym": true }),
+                            Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+                        },
+                        gateway::Request::Restart => match spawn_restart() {
+                            Ok(()) => {
+                                journal::log(Priority::Notice, "restart requested remotely", &[("HULIOS_EVENT", "remote_restart")]);
+                                serde_json::json!({ "restarting": true })
+                            }
+                            Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+                        },
+                    }
+                }
+            };
+            if let Err(e) = gateway::serve(&address, handler).await {
+                eprintln!("[!] Remote control stopped: {:#}", e);
+            }
+        });
+    }
+    tokio::spawn(async move {
+        let handler = move |request| {
+            let health = health_name(&health_rx);
+            let launched = launched_tx.clone();
+            async move {
+                match request {
+                    ipc::Request::Status => serde_json::json!(ipc::DaemonStatus {
+                        pid: std::process::id(),
+                        started_at,
+                        health,
+                        circuit_established: circuit_established().await,
+                    }),
+                    ipc::Request::LaunchTor => match launch_tor(&launched).await {
+                        Ok(pid) => serde_json::json!(ipc::Launched { pid }),
+                        Err(e) => serde_json::json!({ "error": format!("{:#}", e) }),
+                    },
+                }
+            }
+        };
+        if let Err(e) = ipc::serve(listener, handler).await {
+            eprintln!("[!] Control socket stopped: {:#}", e);
+        }
+    });
+    if let Some(interval) = sdnotify::watchdog_interval() {
+        tokio::spawn(watchdog(interval, checked.clone()));
+    }
+    sdnotify::notify("READY=1\nSTATUS=Waiting for the first health check");
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        result = monitor(health_tx, kick, checked, power_rx) => result?,
+        _ = terminate.recv() => println!("[*] Stopping on SIGTERM"),
+        _ = interrupt.recv() => println!("[*] Stopping on SIGINT"),
+    }
+    Ok(())
+}
+
+/// The last check's verdict, for status replies
+fn health_name(health: &watch::Receiver<Option<Assessment>>) -> String {
+    health.borrow().as_ref().map_or("not run yet".to_string(), |assessment| assessment.health.to_string())
+}
+
+/// daemon.rotate_identity: new circuits for new connections every
+/// `every`, unless the power state pauses it
+async fn rotate_identity(every: Duration, power: watch::Receiver<PowerState>) {
+    let config = Config::load().unwrap_or_default();
+    let mut rotations = time::interval_at(time::Instant::now() + every, every);
+    rotations.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        rotations.tick().await;
+        if !power::adjustments(&config, *power.borrow()).rotate_identity {
+            continue;
+        }
+        let rotated = async {
+            AsyncControlClient::connect().await?.command("SIGNAL NEWNYM").await
+        };
+        match rotated.await {
+            Ok(_) => journal::log(Priority::Info, "new circuits for new connections (rotate_identity)",
+                &[("HULIOS_EVENT", "identity_rotated")]),
+            Err(e) => eprintln!("[!] Identity not rotated: {:#}", e),
+        }
+    }
+}
+
+/// New circuits for new connections, on a remote client's request
+async fn newnym() -> Result<()> {
+    let mut client = AsyncControlClient::connect().await?;
+    client.command("SIGNAL NEWNYM").await?;
+    journal::log(Priority::Notice, "new circuits requested remotely", &[("HULIOS_EVENT", "remote_newnym")]);
+    Ok(())
+}
+
+/// Run `hulios restart`. It stops this daemon and starts another, so it
+/// runs in its own process group, and a remote client's reply goes out
+/// before it gets that far.
+fn spawn_restart() -> Result<()> {
+    spawn_hulios("restart")
+}
+
+/// Run `hulios <command>` detached, like spawn_restart
+fn spawn_hulios(command: &str) -> Result<()> {
+    let exe = std::env::current_exe().context("Cannot locate the hulios binary")?;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG)
+        .with_context(|| format!("Failed to open {}", LOG))?;
+    Command::new(exe)
+        .args(["--yes", command])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to run hulios {}", command))?;
+    Ok(())
+}
+
+/// Tor's own view of whether it can build circuits
+async fn circuit_established() -> Option<bool> {
+    let mut client = AsyncControlClient::connect().await.ok()?;
+    client.getinfo("status/circuit-established").await.ok().map(|value| value == "1")
+}
+
+// =============================================================================
+// Tor Supervision
+// =============================================================================
+
+/// The Tor process the daemon waits on
+struct Supervised {
+    pid: u32,
+    process: Process,
+}
+
+enum Process {
+    /// Spawned by this daemon, reaped with waitpid
+    Child(tokio::process::Child),
+    /// Started by systemd (tor.confine) or an earlier daemon, watched
+    /// through a pidfd, which becomes readable when it exits
+    Adopted(AsyncFd<OwnedFd>),
+}
+
+impl Supervised {
+    /// Resolves once Tor has exited, with its status if it was our child
+    async fn exited(&mut self) -> Option<ExitStatus> {
+        match &mut self.process {
+            Process::Child(child) => child.wait().await.ok(),
+            Process::Adopted(pidfd) => {
+                let _ = pidfd.readable().await;
+                None
+            }
+        }
+    }
+
+    /// The session's Tor if this daemon didn't spawn it. The pidfd is taken
+    /// before the process is checked, so a reused PID can't slip in between.
+    fn adopt() -> Option<Supervised> {
+        let pid = engine::tor_pid()?;
+        let pidfd = procfs::pidfd(pid as i32).ok()?;
+        if !engine::is_tor_running() {
+            return None;
+        }
+        Some(Supervised { pid, process: Process::Adopted(AsyncFd::new(pidfd).ok()?) })
+    }
+}
+
+/// Spawn Tor as this daemon's child and hand it to the supervisor
+async fn launch_tor(launched: &mpsc::Sender<tokio::process::Child>) -> Result<u32> {
+    let config = Config::load()?;
+    let limits = cgroup::Limits::from_config(&config.tor);
+    let mut command = cgroup::scoped(engine::tor_command(&config)?, &limits);
+    Scheduling::from_config(&config.tor).apply(&mut command);
+    let mut command = tokio::process::Command::from(command);
+    // Tor's own group, so signals aimed at the daemon's never reach it
+    command.process_group(0);
+    let child = command.spawn().context("Failed to start tor process")?;
+    let pid = child.id().context("tor exited immediately")?;
+    // Its own cgroup too, so the session's Tor is found and stopped as a
+    // whole tree, apart from the daemon's
+    if sdnotify::booted() {
+        cgroup::wait_scoped(pid);
+    } else if let Err(e) = cgroup::attach(pid, &limits) {
+        journal::log(Priority::Warning, &format!("tor's cgroup could not be set up: {:#}", e), &[]);
+    }
+    launched.send(child).await?;
+    journal::log(Priority::Info, &format!("Spawned tor (pid {})", pid),
+        &[("HULIOS_EVENT", "tor_spawned"), ("TOR_PID", &pid.to_string())]);
+    Ok(pid)
+}
+
+/// Wait on Tor and run a health check the moment it exits, rather than at
+/// the next monitor tick
+async fn supervise_tor(mut launched: mpsc::Receiver<tokio::process::Child>, kick: Arc<Notify>) {
+    let mut tor = Supervised::adopt();
+    loop {
+        let exited = async {
+            match tor.as_mut() {
+                Some(tor) => tor.exited().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            child = launched.recv() => {
+                let Some(child) = child else {
+                    return;
+                };
+                let pid = child.id().unwrap_or_default();
+                tor = Some(Supervised { pid, process: Process::Child(child) });
+            }
+            status = exited => {
+                let pid = tor.take().map(|tor| tor.pid).unwrap_or_default();
+                // What the kernel's OOM killer and systemd-oomd both send
+                let killed = status.and_then(|status| status.signal()) == Some(libc::SIGKILL);
+                let status = status.map(|status| status.to_string()).unwrap_or_default();
+                let message = match status.as_str() {
+                    "" => format!("tor (pid {}) exited", pid),
+                    status => format!("tor (pid {}) exited ({})", pid, status),
+                };
+                journal::log(Priority::Warning, &message,
+                    &[("HULIOS_EVENT", "tor_exited"), ("TOR_PID", &pid.to_string()), ("TOR_EXIT_STATUS", &status)]);
+                // `stop` and `restart` stop the daemon first, so this Tor wasn't stopped
+                stats::record_crash(pid);
+                if killed && Config::load().is_ok_and(|config| config.tor.restart_on_oom) {
+                    restart_killed(pid);
+                }
+                kick.notify_one();
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Monitor
+// =============================================================================
+
+/// Monitor state carried from one tick to the next
+#[derive(Default)]
+struct Monitor {
+    config: Config,
+    policy: firewall::Policy,
+    last: Option<Health>,
+    /// Problems already alerted on, so each is reported once until it clears
+    alerted: HashSet<String>,
+    ticks: u32,
+    /// Result of the last lookup through the DNSPort
+    dns_failing: bool,
+    broken_apps: breakage::Tracker,
+}
+
+impl Monitor {
+    /// One round of health, stats and exit list upkeep. These shell out
+    /// and read /proc, so they run on the blocking pool.
+    fn tick(&mut self) -> Assessment {
+        let mut assessment = health::assess(&self.config);
+        if assessment.health == Health::Active {
+            // An actual lookup costs a Tor round trip, so only every few ticks
+            if self.ticks.is_multiple_of(DNS_CHECK_TICKS) {
+                self.dns_failing = verify::resolve(DNS_PORT).is_err();
+            }
+            if self.dns_failing {
+                assessment.health = Health::Degraded;
+                assessment.problems.push(health::Problem {
+                    description: i18n::tr("health-dns-not-through-tor", &[]),
+                    remedy: "sudo hulios restart",
+                });
+            }
+        }
+        self.ticks += 1;
+
+        if Some(assessment.health) != self.last {
+            if self.last.is_some() {
+                let health = assessment.health.to_string();
+                journal::log(Priority::Notice, &format!("session is now {}", health),
+                    &[("HULIOS_EVENT", "health_changed"), ("HULIOS_HEALTH", &health)]);
+            }
+            self.last = Some(assessment.health);
+        }
+        // Alert on each failure as it appears, not just on the first one
+        for problem in &assessment.problems {
+            if self.alerted.contains(&problem.description) {
+                continue;
+            }
+            journal::log(Priority::Warning,
+                &format!("{}: {} (fix: {})", assessment.health, problem.description, problem.remedy),
+                &[
+                    ("HULIOS_EVENT", "problem"),
+                    ("HULIOS_HEALTH", &assessment.health.to_string()),
+                    ("HULIOS_PROBLEM", &problem.description),
+                    ("HULIOS_REMEDY", problem.remedy),
+                ]);
+            let body = i18n::tr("notify-problem-body", &[("problem", &problem.description), ("remedy", problem.remedy)]);
+            notify::send(Event::Crash, &i18n::tr("notify-problem-title", &[("health", &assessment.health.to_string())]), &body);
+        }
+        self.alerted = assessment.problems.iter().map(|problem| problem.description.clone()).collect();
+        if matches!(assessment.health, Health::Failed | Health::Stopped) {
+            return assessment;
+        }
+
+        let _ = stats::update_session();
+        self.broken_apps.scan(&self.policy, firewall::backend(&self.config).as_ref());
+
+        // Without systemd the daemon is what restarts a relay that died
+        match relay::revive() {
+            Some(Ok(pid)) => journal::log(Priority::Warning, &format!("relay tor had exited, restarted it (pid {})", pid),
+                &[("HULIOS_EVENT", "relay_restarted"), ("TOR_PID", &pid.to_string())]),
+            Some(Err(e)) => journal::log(Priority::Warning, &format!("relay tor exited and could not be restarted: {:#}", e),
+                &[("HULIOS_EVENT", "relay_failed")]),
+            None => {}
+        }
+
+        // Keep the offline exit list fresh while Tor is up
+        if exitlist::is_stale(&self.config.status) {
+            let _ = exitlist::refresh(&self.config.status);
+        }
+        // And the kept consensus as fresh as Tor's, which it renews hourly
+        if let Err(e) = tordata::save(&self.config) {
+            journal::log(Priority::Warning, &format!("could not keep Tor's data: {:#}", e), &[]);
+        }
+        assessment
+    }
+}
+
+/// Periodic health, stats and exit list upkeep, published to `health` for
+/// the control socket and health endpoint, and out of turn whenever `kick`
+/// fires; spaced out as the power state asks. Returns once the session
+/// has failed or stopped.
+async fn monitor(health: watch::Sender<Option<Assessment>>, kick: Arc<Notify>, checked: Arc<Mutex<Instant>>,
+                 power: watch::Receiver<PowerState>) -> Result<()> {
+    let config = Config::load().unwrap_or_default();
+    let policy = firewall::Policy::from_config(&config);
+    let appliance = config.profile == Profile::Appliance;
+    let mut monitor = Monitor { config, policy, ..Default::default() };
+    // Checks in a row that found the session needing a restart
+    let mut broken = 0;
+    let mut period = MONITOR_INTERVAL;
+    let mut ticks = time::interval_at(time::Instant::now() + MONITOR_GRACE, period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = kick.notified() => {}
+        }
+        let (state, assessment) = tokio::task::spawn_blocking(move || {
+            let assessment = monitor.tick();
+            (monitor, assessment)
+        }).await?;
+        monitor = state;
+        *checked.lock().unwrap() = Instant::now();
+        let wanted = power::adjustments(&monitor.config, *power.borrow()).monitor_interval.unwrap_or(MONITOR_INTERVAL);
+        if wanted != period {
+            period = wanted;
+            ticks = time::interval_at(time::Instant::now() + period, period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        }
+        sdnotify::notify(&format!("STATUS=Session {}, {} problem(s)", assessment.health, assessment.problems.len()));
+        if appliance {
+            broken = if needs_restart(&assessment) { broken + 1 } else { 0 };
+            // A failed session leaks, so it doesn't get to wait
+            if broken >= HEAL_CHECKS || assessment.health == Health::Failed {
+                heal(&assessment);
+                broken = 0;
+            }
+        }
+        let done = matches!(assessment.health, Health::Failed | Health::Stopped);
+        health.send_replace(Some(assessment));
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether `hulios restart` is what fixes the session
+fn needs_restart(assessment: &Assessment) -> bool {
+    assessment.health == Health::Failed
+        || assessment.problems.iter().any(|problem| problem.remedy == "sudo hulios restart")
+}
+
+/// Appliance profile: nobody is there to run the remedy, so the daemon
+/// runs `hulios restart` itself. Only once per HEAL_COOLDOWN, across the
+/// daemons the restarts start, so a session that can't be fixed that way
+/// doesn't restart forever.
+fn heal(assessment: &Assessment) {
+    if !restart_due() {
+        return;
+    }
+    let health = assessment.health.to_string();
+    match spawn_restart() {
+        Ok(()) => {
+            journal::log(Priority::Warning, &format!("session is {}, restarting it", health),
+                &[("HULIOS_EVENT", "auto_heal"), ("HULIOS_HEALTH", &health)]);
+            notify::send(Event::Crash, &i18n::tr("notify-self-restart-title", &[]),
+                &i18n::tr("notify-self-restart-body", &[("health", &health)]));
+        }
+        Err(e) => journal::log(Priority::Warning, &format!("session is {} and could not be restarted: {:#}", health, e),
+            &[("HULIOS_EVENT", "auto_heal_failed"), ("HULIOS_HEALTH", &health)]),
+    }
+}
+
+/// Whether HEAL_COOLDOWN has passed since the daemon last restarted the
+/// session, in which case this restart is stamped
+fn restart_due() -> bool {
+    let now = chrono::Utc::now().timestamp();
+    let last: i64 = fs::read_to_string(HEAL_STAMP).ok().and_then(|stamp| stamp.trim().parse().ok()).unwrap_or(0);
+    if now - last < HEAL_COOLDOWN.as_secs() as i64 {
+        return false;
+    }
+    let _ = fs::write(HEAL_STAMP, now.to_string());
+    true
+}
+
+/// `tor.restart_on_oom`: Tor was killed, most likely for outgrowing its
+/// memory, and the session is restarted instead of staying closed. The
+/// cooldown is heal's, so a limit too low for Tor doesn't restart it
+/// forever and the monitor doesn't restart it a second time.
+fn restart_killed(pid: u32) {
+    if !restart_due() {
+        journal::log(Priority::Warning, &format!("tor (pid {}) was killed again soon after a restart, leaving the session down", pid),
+            &[("HULIOS_EVENT", "oom_restart_skipped"), ("TOR_PID", &pid.to_string())]);
+        return;
+    }
+    match spawn_restart() {
+        Ok(()) => {
+            journal::log(Priority::Warning, &format!("tor (pid {}) was killed, likely out of memory; restarting the session", pid),
+                &[("HULIOS_EVENT", "oom_restart"), ("TOR_PID", &pid.to_string())]);
+            notify::send(Event::Crash, &i18n::tr("notify-self-restart-title", &[]), &i18n::tr("notify-oom-restart-body", &[]));
+        }
+        Err(e) => journal::log(Priority::Warning, &format!("tor (pid {}) was killed and the session could not be restarted: {:#}", pid, e),
+            &[("HULIOS_EVENT", "oom_restart_failed"), ("TOR_PID", &pid.to_string())]),
+    }
+}
+
+/// Heartbeat for systemd's watchdog, sent only while health checks keep
+/// completing: a monitor hung in a check stops it and systemd restarts
+/// the daemon
+async fn watchdog(interval: Duration, checked: Arc<Mutex<Instant>>) {
+    let mut beats = time::interval(interval);
+    loop {
+        beats.tick().await;
+        if checked.lock().unwrap().elapsed() < MONITOR_STALL {
+            sdnotify::notify("WATCHDOG=1");
+        }
+    }
+}
+
+// =============================================================================
+// resolv.conf Watch
+// =============================================================================
+
+/// Put the Tor nameserver back whenever something replaces resolv.conf
+/// despite chattr +i (NixOS activation, VPN clients, resolvconf)
+fn watch_resolv() -> Result<()> {
+    let watch = inotify::Watch::new()?;
+    // Watch /etc itself: most writers replace the file by renaming over it
+    watch.add("/etc", inotify::IN_CLOSE_WRITE | inotify::IN_MOVED_TO | inotify::IN_CREATE
+        | inotify::IN_DELETE | inotify::IN_ATTRIB)?;
+
+    loop {
+        let events = watch.wait()?;
+        let changed = events.iter().any(|event| event.name == "resolv.conf");
+        if !changed || engine::dns_owned() || state::load().is_none() {
+            continue;
+        }
+        // The writer may still hold the file open; look before it is gone
+        let writer = resolv_writer();
+        // Let a multi-step replacement (write, rename, chmod) finish
+        thread::sleep(Duration::from_millis(200));
+        if engine::dns_owned() {
+            continue;
+        }
+
+        let writer = writer.as_deref().unwrap_or("an unknown process");
+        journal::log(Priority::Warning, &format!("/etc/resolv.conf was replaced by {}; restoring Tor DNS", writer),
+            &[("HULIOS_EVENT", "resolv_replaced"), ("HULIOS_WRITER", writer)]);
+        if let Err(e) = engine::reassert_dns() {
+            eprintln!("[!] Could not restore resolv.conf: {:#}", e);
+        }
+    }
+}
+
+/// Best guess at who changed resolv.conf: a process holding it (or a temp
+/// file next to it) open, else a known resolv.conf writer that is running
+fn resolv_writer() -> Option<String> {
+    let holder = fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+        let holds = fs::read_dir(format!("/proc/{}/fd", pid)).ok()?.flatten().any(|fd| {
+            fs::read_link(fd.path()).is_ok_and(|target| {
+                target.to_str().is_some_and(|t| t.starts_with("/etc/resolv.conf") || t.starts_with("/etc/.resolv.conf"))
+            })
+        });
+        holds.then_some(pid)
+    });
+    if let Some(info) = holder.and_then(procfs::inspect) {
+        return Some(format!("{}[{}]", info.comm, info.pid));
+    }
+
+    RESOLV_WRITERS.iter().find_map(|name| {
+        let info = procfs::find_by_name(name).into_iter().next()?;
+        Some(format!("{}[{}] (likely)", info.comm, info.pid))
+    })
+}
+
+// =============================================================================
+// Network Watch
+// =============================================================================
+
+/// Apply the `[[networks]]` profile bound to the network whenever it
+/// changes, with `hulios reload` (which also replaces this daemon)
+async fn watch_network() {
+    let mut polls = time::interval(NETWORK_POLL);
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        polls.tick().await;
+        let selected = tokio::task::spawn_blocking(|| {
+            let config = Config::load()?;
+            netprofile::select(&config)
+        }).await;
+        match selected {
+            Ok(Ok(true)) => {
+                let profile = netprofile::active().unwrap_or_else(|| "none".to_string());
+                journal::log(Priority::Notice, &format!("network changed, applying network profile {}", profile),
+                    &[("HULIOS_EVENT", "network_profile"), ("HULIOS_PROFILE", &profile)]);
+                if let Err(e) = spawn_hulios("reload") {
+                    eprintln!("[!] {:#}", e);
+                }
+            }
+            Ok(Err(e)) => eprintln!("[!] Network profile not updated: {:#}", e),
+            _ => {}
+        }
+    }
+}
+
+// =============================================================================
+// Power Watch
+// =============================================================================
+
+/// Follow battery and metered changes: Tor's bandwidth limits are set
+/// here, identity rotation and the monitor read them from `state`
+async fn watch_power(state: watch::Sender<PowerState>) {
+    let config = Config::load().unwrap_or_default();
+    // What Tor's limits were last set for; None until Tor took them
+    let mut applied = None;
+    let mut polls = time::interval(POWER_POLL);
+    polls.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        polls.tick().await;
+        let Ok(current) = tokio::task::spawn_blocking(PowerState::detect).await else {
+            continue;
+        };
+        if current != *state.borrow() {
+            let description = current.describe();
+            journal::log(Priority::Notice, &format!("power state changed: {}", description),
+                &[("HULIOS_EVENT", "power_changed"), ("HULIOS_POWER", &description)]);
+            state.send_replace(current);
+        }
+        if applied == Some(current) {
+            continue;
+        }
+        // Fails while Tor isn't up yet; tried again at the next poll
+        if power::apply_bandwidth(&config, current).await.is_ok() {
+            applied = Some(current);
+        }
+    }
+}
+
+// =============================================================================
+// Sysctl Watch
+// =============================================================================
+
+/// Re-assert the pinned sysctls whenever something (NetworkManager, a
+/// `sysctl --system` reload) resets them, alerting if it keeps happening
+async fn watch_sysctls() {
+    let pinned: Vec<(&str, String)> = PINNED_SYSCTLS
+        .iter()
+        .filter_map(|&(key, value)| Some((key, value.map(str::to_string).or_else(|| engine::read_sysctl(key))?)))
+        .collect();
+    let mut reversions: HashMap<&str, u32> = HashMap::new();
+    let mut polls = time::interval(SYSCTL_POLL);
+
+    loop {
+        polls.tick().await;
+        if state::load().is_none() {
+            continue;
+        }
+        for (key, expected) in &pinned {
+            let Some(actual) = engine::read_sysctl(key) else {
+                continue;
+            };
+            if actual == *expected {
+                continue;
+            }
+            engine::write_sysctl(key, expected);
+            let count = reversions.entry(key).or_insert(0);
+            *count += 1;
+            journal::log(Priority::Warning,
+                &format!("{} was reset to {}; set back to {} ({} time(s) this session)", key, actual, expected, count),
+                &[("HULIOS_EVENT", "sysctl_reverted"), ("HULIOS_SYSCTL", key), ("HULIOS_SYSCTL_VALUE", &actual)]);
+            if *count == REVERSION_ALERT {
+                notify::send(Event::Crash, &i18n::tr("notify-sysctl-reverted-title", &[]),
+                    &i18n::tr("notify-sysctl-reverted-body", &[("key", key), ("count", &count.to_string())]));
+            }
+        }
+    }
+}
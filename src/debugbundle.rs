@@ -0,0 +1,154 @@
+use anyhow::{Result, Context};
+use colored::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use users::get_current_uid;
+use crate::exec::Run;
+use crate::version::BuildInfo;
+use crate::{confirm, daemon, engine, i18n, privacy, report};
+
+/// Lines kept from the end of each log
+const LOG_TAIL: usize = 500;
+const TOR_LOG_TAIL: usize = 200;
+
+/// Stands in for the machine's hostname
+const HOSTNAME: &str = "[hostname]";
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios debug-bundle [--output file]`: gather what a bug report needs
+/// into a tarball, with public addresses, URLs, onion names and the
+/// hostname scrubbed out of every file
+pub fn create(output: Option<&str>, assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let output = match output {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(format!("hulios-debug-{}.tar.gz", chrono::Local::now().format("%Y%m%d-%H%M%S"))),
+    };
+    confirm::confirm(&i18n::tr("confirm-writing", &[("path", &output.display().to_string())]), &confirm::changes(confirm::DEBUG_BUNDLE_CONTENTS), assume_yes)?;
+
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default().trim().to_string();
+    let scrub = |text: &str| -> String {
+        text.lines()
+            .map(|line| {
+                let line = privacy::scrub_public(line);
+                // Too short to replace without mangling unrelated words
+                if hostname.len() >= 3 { line.replace(&hostname, HOSTNAME) } else { line }
+            })
+            .collect::<Vec<_>>()
+            .join("\n") + "\n"
+    };
+
+    let files: Vec<(&str, String)> = vec![
+        ("version.txt", BuildInfo::gather().lines().join("\n") + "\n"),
+        ("doctor.txt", hulios_output(&["doctor"])),
+        ("hulios.log", tail(daemon::LOG, LOG_TAIL)),
+        ("tor.log", tail(engine::TOR_LOG, TOR_LOG_TAIL)),
+        ("ruleset.txt", ruleset()),
+        ("resolv.conf", resolv_conf()),
+    ];
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&output)
+        .with_context(|| i18n::tr("error-create", &[("path", &output.display().to_string())]))?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    let now = chrono::Local::now().timestamp() as u64;
+    for (name, content) in &files {
+        let content = scrub(content);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now);
+        header.set_cksum();
+        tar.append_data(&mut header, format!("hulios-debug/{}", name), content.as_bytes())
+            .with_context(|| i18n::tr("debugbundle-failed-add", &[("name", name)]))?;
+    }
+    tar.into_inner()?.finish()?;
+
+    // Readable by whoever ran sudo, who attaches it
+    if let (Some(uid), Some(gid)) = (sudo_id("SUDO_UID"), sudo_id("SUDO_GID")) {
+        let _ = std::os::unix::fs::chown(&output, Some(uid), Some(gid));
+    }
+    println!("{}", format!("[+] {}", i18n::tr("debugbundle-debug-bundle-written", &[("output", &output.display().to_string())])).green());
+    println!("[*] {}", i18n::tr("debugbundle-look-through-before-attaching", &[("output", &output.display().to_string())]));
+    report::action(format!("Debug bundle written to {}", output.display()));
+    report::data("bundle", output.display().to_string());
+    report::data("files", files.iter().map(|(name, _)| *name).collect::<Vec<_>>());
+    Ok(())
+}
+
+// =============================================================================
+// Contents
+// =============================================================================
+
+/// The last `lines` lines of a log, or why there are none
+fn tail(path: &str, lines: usize) -> String {
+    match fs::read_to_string(path) {
+        Ok(log) => {
+            let all: Vec<&str> = log.lines().collect();
+            all[all.len().saturating_sub(lines)..].join("\n")
+        }
+        Err(e) => format!("# {}: {}", path, e),
+    }
+}
+
+/// Everything in the firewall, whichever backend put it there
+fn ruleset() -> String {
+    [("iptables-save", &[][..]), ("ip6tables-save", &[][..]), ("nft", &["list", "ruleset"][..])]
+        .into_iter()
+        .map(|(program, args)| {
+            let command = std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+            let output = match Command::new(program).args(args).stdin(Stdio::null()).run_output() {
+                Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout).into_owned(),
+                Ok(output) => format!("failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+                Err(e) => format!("not available: {}", e),
+            };
+            format!("# {}\n{}\n", command, output.trim_end())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// resolv.conf, with where it points if it is a link
+fn resolv_conf() -> String {
+    let path = "/etc/resolv.conf";
+    let target = fs::read_link(path).map(|target| format!("# -> {}\n", target.display())).unwrap_or_default();
+    match fs::read_to_string(path) {
+        Ok(content) => target + &content,
+        Err(e) => format!("{}# {}: {}", target, path, e),
+    }
+}
+
+/// Output of another hulios command, as plain text
+fn hulios_output(args: &[&str]) -> String {
+    let output = std::env::current_exe()
+        .with_context(|| i18n::tr("error-no-binary", &[]))
+        .and_then(|exe| {
+            Command::new(exe)
+                .arg("--plain")
+                .args(args)
+                .stdin(Stdio::null())
+                .run_output()
+                .with_context(|| i18n::tr("debugbundle-failed-run", &[]))
+        });
+    match output {
+        Ok(output) => format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr)),
+        Err(e) => format!("# hulios {}: {:#}", args.join(" "), e),
+    }
+}
+
+fn sudo_id(var: &str) -> Option<u32> {
+    std::env::var(var).ok()?.parse().ok()
+}
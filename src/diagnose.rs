@@ -0,0 +1,65 @@
+use std::fs;
+
+/// A known reason for Tor failing to bootstrap, read from its log
+pub struct Diagnosis {
+    /// Catalog ids of the explanation and what to do about it
+    pub cause: &'static str,
+    pub fix: &'static str,
+    /// Whether starting Tor again can help; a wrong clock or an unwritable
+    /// data directory fails the same way every time
+    pub retry: bool,
+}
+
+const CLOCK_SKEW: Diagnosis = Diagnosis {
+    cause: "diagnose-clock-skew",
+    fix: "diagnose-clock-skew-fix",
+    retry: false,
+};
+
+/// Failure signatures in the order they are checked; the first one with a
+/// matching log line wins. Every needle of an entry must be on the same line.
+const SIGNATURES: &[(&[&str], Diagnosis)] = &[
+    (&["Permission denied"], Diagnosis {
+        cause: "diagnose-permission-denied",
+        fix: "diagnose-permission-denied-fix",
+        retry: false,
+    }),
+    (&["Address already in use"], Diagnosis {
+        cause: "diagnose-port-in-use",
+        fix: "diagnose-port-in-use-fix",
+        retry: false,
+    }),
+    (&["clock is"], CLOCK_SKEW),
+    (&["skewed time"], CLOCK_SKEW),
+    (&["Network is unreachable"], Diagnosis {
+        cause: "diagnose-network-unreachable",
+        fix: "diagnose-network-unreachable-fix",
+        retry: true,
+    }),
+    (&["Problem bootstrapping", "CONNECTREFUSED"], Diagnosis {
+        cause: "diagnose-refused",
+        fix: "diagnose-bridges-fix",
+        retry: true,
+    }),
+    (&["Problem bootstrapping", "TIMEOUT"], Diagnosis {
+        cause: "diagnose-timeout",
+        fix: "diagnose-bridges-fix",
+        retry: true,
+    }),
+    (&["Problem bootstrapping", "NOROUTE"], Diagnosis {
+        cause: "diagnose-no-route",
+        fix: "diagnose-no-route-fix",
+        retry: true,
+    }),
+];
+
+/// Look through Tor's log for a known failure; None if nothing matches
+pub fn bootstrap_failure(log: &str) -> Option<&'static Diagnosis> {
+    let content = fs::read_to_string(log).ok()?;
+    SIGNATURES.iter().find_map(|(needles, diagnosis)| {
+        content
+            .lines()
+            .any(|line| needles.iter().all(|needle| line.contains(needle)))
+            .then_some(diagnosis)
+    })
+}
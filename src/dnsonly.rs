@@ -0,0 +1,409 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs::{self, OpenOptions};
+use std::net::{IpAddr, SocketAddr, UdpSocket as StdUdpSocket};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use users::get_current_uid;
+use crate::config::{Config, DnsOnlyConfig, DnsOnlyResolver};
+use crate::engine::{self, ROUTE_LOCALNET};
+use crate::{confirm, features, firewall, i18n, lock, overlay, procfs, report, state, validate, verify, workstation};
+
+/// The local encrypted resolver all DNS is redirected to
+pub const PORT: u16 = 9064;
+
+const LOG: &str = "/tmp/hulios_dns_only.log";
+
+/// The resolver runs as this user; for dnscrypt-proxy it is also the one
+/// whose DNS is not redirected, for its bootstrap lookups
+const RESOLVER_USER: &str = "nobody";
+
+const DNSCRYPT_CONFIG: &str = "/var/lib/hulios/dnscrypt-proxy.toml";
+
+/// dnscrypt-proxy's resolver list cache, kept across sessions
+const DNSCRYPT_CACHE: &str = "/var/lib/hulios/dnscrypt";
+
+const DNSCRYPT_CANDIDATES: &[&str] = &["/usr/bin/dnscrypt-proxy", "/usr/sbin/dnscrypt-proxy", "/usr/local/bin/dnscrypt-proxy"];
+
+/// Signing key of the public resolver list published by the DNSCrypt project
+const DNSCRYPT_LIST_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+
+pub const RESOLV: &str = "# HULIOS - encrypted DNS (dns-only)\n# DO NOT MODIFY - This file is managed by HULIOS\nnameserver 127.0.0.1\n";
+
+const DOH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios dns-only start`: encrypted DNS without Tor. Every DNS query goes
+/// to a local DoH client or dnscrypt-proxy, while TCP leaves directly.
+pub fn start(assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let _lock = lock::acquire()?;
+    if is_active() {
+        println!("[*] {}", i18n::tr("dnsonly-dns-only-mode-already", &[]));
+        return Ok(());
+    }
+    if workstation::is_attached() {
+        anyhow::bail!(i18n::tr("engine-attached-to-gateway", &[]));
+    }
+    if state::load().is_some() {
+        anyhow::bail!(i18n::tr("dnsonly-session-active-switch-sudo", &[]));
+    }
+    let config = validate::load()?;
+    confirm::confirm(&i18n::tr("confirm-starting-dns-only", &[]), &confirm::changes(confirm::DNS_ONLY_CHANGES), assume_yes)?;
+
+    // Record the session before touching anything, for `hulios recover`
+    let session = state::State {
+        started_at: chrono::Local::now().timestamp(),
+        firewall_backend: firewall::backend(&config).name().to_string(),
+        route_localnet: engine::read_sysctl(ROUTE_LOCALNET),
+        dns_only: true,
+        ..Default::default()
+    };
+    state::save(&session)?;
+
+    if let Err(e) = apply(&config) {
+        eprintln!("[!] {}", i18n::tr("error-rolling-back", &[]));
+        report::action("Rolled back after the failed dns-only start");
+        engine::rollback();
+        return Err(e);
+    }
+
+    println!("{}", format!("[+] {}", i18n::tr("dnsonly-dns-only-mode-started", &[])).green());
+    println!("[*] {}", i18n::tr("dnsonly-undo-sudo-dns-only", &[]));
+    Ok(())
+}
+
+/// Swap a running Tor session for dns-only mode, for `hulios mode set`:
+/// the resolver starts while Tor still answers, then the firewall is
+/// swapped over the live rules and Tor stopped. The system resolver stays
+/// neutralized throughout. Caller holds the HULIOS lock.
+pub fn switch_from_tor() -> Result<()> {
+    let config = validate::load()?;
+    let features = features::probe(&config);
+    let resolver_uid = launch(&config.dns_only).inspect_err(|_| halt())?;
+
+    let result = (|| {
+        firewall::adapted_backend(&config, &features).dns_only(resolver_uid)?;
+        firewall::flush_others(&config)?;
+        firewall::remove_policy_routing();
+        engine::write_resolv(RESOLV)?;
+        println!("[+] {}", i18n::tr("dnsonly-dns-now-points-localhost", &[]));
+        engine::leave_tor()?;
+
+        let mut session = state::load().unwrap_or_default();
+        session.dns_only = true;
+        session.tor_pid = None;
+        session.tor_cgroup = None;
+        session.bridges = false;
+        session.firewall_policy = None;
+        session.started_at = chrono::Local::now().timestamp();
+        session.firewall_backend = firewall::backend(&config).name().to_string();
+        state::save(&session)?;
+
+        verify::resolve("1.1.1.1:53").with_context(|| i18n::tr("dnsonly-dns-does-not-work", &[]))?;
+        println!("[+] {}", i18n::tr("dnsonly-verified-dns-answered-encrypted", &[]));
+        Ok(())
+    })();
+    if let Err(e) = result {
+        eprintln!("[!] {}", i18n::tr("error-rolling-back", &[]));
+        report::action("Rolled back after the failed switch to dns-only");
+        engine::rollback();
+        return Err(e);
+    }
+    report::action("Switched from full torification to dns-only");
+    Ok(())
+}
+
+/// `hulios dns-only stop`
+pub fn stop() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let _lock = lock::acquire()?;
+    if !is_active() {
+        println!("[*] {}", i18n::tr("dnsonly-dns-only-mode-not", &[]));
+        return Ok(());
+    }
+    engine::teardown()?;
+    println!("{}", format!("[+] {}", i18n::tr("dnsonly-dns-only-mode-stopped", &[])).green());
+    report::action("dns-only mode stopped");
+    Ok(())
+}
+
+/// `hulios dns-only status`
+pub fn status() -> Result<()> {
+    let active = is_active();
+    report::data("active", active);
+    if !active {
+        println!("[*] {}", i18n::tr("dnsonly-dns-only-mode-not", &[]));
+        return Ok(());
+    }
+    let config = Config::load().unwrap_or_default();
+    let resolver = match config.dns_only.resolver {
+        DnsOnlyResolver::Doh => format!("DoH ({})", config.dns_only.doh_url),
+        DnsOnlyResolver::Dnscrypt => "dnscrypt-proxy".to_string(),
+    };
+    println!("{}", format!("[+] {}", i18n::tr("dnsonly-dns-only-mode-active", &[])).green());
+    println!("[*] {}", i18n::tr("dnsonly-resolver", &[("resolver", &resolver)]));
+    report::data("resolver", &resolver);
+
+    match verify::resolve(&format!("127.0.0.1:{}", PORT)) {
+        Ok(()) => println!("[+] {}", i18n::tr("dnsonly-resolver-answering", &[("port", &PORT.to_string())])),
+        Err(e) => {
+            println!("{}", format!("[!] {}", i18n::tr("dnsonly-resolver-not-answering", &[("error", &format!("{:#}", e))])).red());
+            report::warning(format!("Resolver not answering: {:#}", e));
+        }
+    }
+    if !engine::dns_owned() {
+        println!("{}", format!("[!] {}", i18n::tr("dnsonly-etc-resolv-conf-no", &[])).red());
+        report::warning("/etc/resolv.conf no longer points at the resolver");
+    }
+    Ok(())
+}
+
+/// Whether a dns-only session is active
+pub fn is_active() -> bool {
+    state::load().is_some_and(|session| session.dns_only)
+}
+
+/// Stop the resolver, if it runs. Part of every teardown and `hulios recover`.
+pub fn halt() {
+    let pids = procfs::find_by_name("hulios")
+        .into_iter()
+        .filter(|info| info.cmdline.iter().any(|arg| arg == "dns-only-server"))
+        .chain(
+            procfs::find_by_name("dnscrypt-proxy")
+                .into_iter()
+                .filter(|info| info.cmdline.iter().any(|arg| arg == DNSCRYPT_CONFIG)),
+        );
+    for info in pids {
+        procfs::terminate(info.pid, Duration::from_secs(5));
+    }
+}
+
+// =============================================================================
+// Session
+// =============================================================================
+
+/// Resolver first, so DNS is never redirected to a port nobody listens on
+fn apply(config: &Config) -> Result<()> {
+    let features = features::probe(config);
+    let resolver_uid = launch(&config.dns_only)?;
+
+    engine::neutralize_system_resolver()?;
+    engine::enable_route_localnet()?;
+    firewall::adapted_backend(config, &features).dns_only(resolver_uid)?;
+
+    engine::take_dns_ownership(RESOLV)?;
+    println!("[+] {}", i18n::tr("dnsonly-dns-now-points-localhost", &[]));
+    report::action("DNS now points to the encrypted resolver");
+
+    // A public server proves the redirect catches DNS not sent to localhost
+    verify::resolve("1.1.1.1:53").with_context(|| i18n::tr("dnsonly-dns-does-not-work", &[]))?;
+    println!("[+] {}", i18n::tr("dnsonly-verified-dns-answered-encrypted", &[]));
+    Ok(())
+}
+
+/// Start the configured resolver; returns the user whose DNS must not be
+/// redirected, if the resolver needs plaintext bootstrap lookups
+fn launch(config: &DnsOnlyConfig) -> Result<Option<u32>> {
+    let user = users::get_user_by_name(RESOLVER_USER)
+        .with_context(|| i18n::tr("error-no-user", &[("user", RESOLVER_USER)]))?;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG)
+        .with_context(|| i18n::tr("error-open", &[("path", LOG)]))?;
+
+    let (mut command, name, bypass, timeout) = match config.resolver {
+        DnsOnlyResolver::Doh => {
+            // Started as root so it can load itself from anywhere; it
+            // switches to RESOLVER_USER once its ports are bound
+            let mut command = Command::new(std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?);
+            command.args(["dns-only-server", "--url", &config.doh_url]);
+            for address in &config.doh_addresses {
+                command.args(["--address", address]);
+            }
+            (command, "DoH client", None, Duration::from_secs(5))
+        }
+        DnsOnlyResolver::Dnscrypt => {
+            write_dnscrypt_config(config, &user)?;
+            let mut command = Command::new(dnscrypt_binary(config)?);
+            command.args(["-config", DNSCRYPT_CONFIG]).uid(user.uid()).gid(user.primary_group_id());
+            // It fetches its resolver list before listening
+            (command, "dnscrypt-proxy", Some(user.uid()), Duration::from_secs(30))
+        }
+    };
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Own process group, so Ctrl-C in the starting terminal doesn't reach it
+        .process_group(0)
+        .spawn()
+        .with_context(|| i18n::tr("dnsonly-failed-start", &[("name", name)]))?;
+
+    // Ready once its port is taken
+    let deadline = Instant::now() + timeout;
+    while StdUdpSocket::bind(("127.0.0.1", PORT)).is_ok() {
+        if Instant::now() > deadline || !procfs::is_alive(child.id() as i32) {
+            anyhow::bail!(i18n::tr("dnsonly-did-not-start-check", &[("name", name), ("log", LOG)]));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    println!("[+] {}", i18n::tr("dnsonly-listening", &[("name", name), ("port", &PORT.to_string())]));
+    report::action(format!("{} started (PID {})", name, child.id()));
+    Ok(bypass)
+}
+
+fn dnscrypt_binary(config: &DnsOnlyConfig) -> Result<String> {
+    match &config.dnscrypt_binary {
+        Some(path) => Ok(path.clone()),
+        None => DNSCRYPT_CANDIDATES
+            .iter()
+            .find(|candidate| Path::new(candidate).is_file())
+            .map(|candidate| candidate.to_string())
+            .with_context(|| i18n::tr("dnsonly-dnscrypt-proxy-not-found", &[("dnscrypt_candidates", &DNSCRYPT_CANDIDATES.join(", "))])),
+    }
+}
+
+fn write_dnscrypt_config(config: &DnsOnlyConfig, user: &users::User) -> Result<()> {
+    fs::create_dir_all(DNSCRYPT_CACHE).with_context(|| i18n::tr("error-create", &[("path", DNSCRYPT_CACHE)]))?;
+    std::os::unix::fs::chown(DNSCRYPT_CACHE, Some(user.uid()), Some(user.primary_group_id()))
+        .with_context(|| i18n::tr("dnsonly-failed-chown-dnscrypt-proxy", &[]))?;
+    fs::set_permissions(DNSCRYPT_CACHE, fs::Permissions::from_mode(0o700))?;
+
+    let mut toml = format!("listen_addresses = ['127.0.0.1:{}']\n", PORT);
+    if !config.dnscrypt_servers.is_empty() {
+        let names: Vec<String> = config.dnscrypt_servers.iter().map(|name| format!("'{}'", name)).collect();
+        toml.push_str(&format!("server_names = [{}]\n", names.join(", ")));
+    }
+    toml.push_str("ipv4_servers = true\nipv6_servers = false\n");
+    toml.push_str("require_nolog = true\nrequire_nofilter = true\n");
+    // Only to find the servers; the firewall lets RESOLVER_USER's plaintext DNS out
+    toml.push_str("bootstrap_resolvers = ['9.9.9.9:53', '1.1.1.1:53']\nignore_system_dns = true\n");
+    toml.push_str("netprobe_address = '9.9.9.9:53'\nnetprobe_timeout = 30\n");
+    toml.push_str("\n[sources.public-resolvers]\n");
+    toml.push_str("urls = ['https://raw.githubusercontent.com/DNSCrypt/dnscrypt-resolvers/master/v3/public-resolvers.md', 'https://download.dnscrypt.info/resolvers-list/v3/public-resolvers.md']\n");
+    toml.push_str(&format!("cache_file = '{}/public-resolvers.md'\n", DNSCRYPT_CACHE));
+    toml.push_str(&format!("minisign_key = '{}'\nrefresh_delay = 72\n", DNSCRYPT_LIST_KEY));
+    fs::write(DNSCRYPT_CONFIG, toml).with_context(|| i18n::tr("error-write", &[("path", DNSCRYPT_CONFIG)]))
+}
+
+// =============================================================================
+// DoH Client (`hulios dns-only-server`)
+// =============================================================================
+
+/// Answer DNS on PORT (UDP and TCP) by forwarding every query to `url`
+/// (RFC 8484). `addresses` are the DoH host's, so no lookup is needed to
+/// reach it.
+pub fn serve(url: &str, addresses: &[String]) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).with_context(|| i18n::tr("error-not-url", &[("url", url)]))?;
+    let host = parsed.host_str().with_context(|| i18n::tr("error-no-host", &[("url", url)]))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let addresses = addresses
+        .iter()
+        .map(|address| address.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, port)))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid DoH server address")?;
+    if addresses.is_empty() {
+        anyhow::bail!("No addresses for {}; looking it up would go through this very resolver", host);
+    }
+    let client = reqwest::Client::builder()
+        .resolve_to_addrs(host, &addresses)
+        .timeout(DOH_TIMEOUT)
+        .build()
+        .context("Failed to create the DoH client")?;
+    let doh = Arc::new(Doh { client, url: url.to_string() });
+    println!("[*] {} DoH client started (pid {})", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), std::process::id());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the DoH client's runtime")?;
+    runtime.block_on(async {
+        let udp = Arc::new(UdpSocket::bind(("127.0.0.1", PORT)).await.context("Failed to bind the UDP DNS port")?);
+        let listener = TcpListener::bind(("127.0.0.1", PORT)).await.context("Failed to bind the TCP DNS port")?;
+        overlay::drop_privileges(RESOLVER_USER)?;
+        tokio::select! {
+            result = serve_udp(udp, doh.clone()) => result,
+            result = serve_tcp(listener, doh) => result,
+        }
+    })
+}
+
+struct Doh {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Doh {
+    async fn query(&self, query: &[u8]) -> Result<Vec<u8>> {
+        let response = self.client
+            .post(&self.url)
+            .header("content-type", "application/dns-message")
+            .header("accept", "application/dns-message")
+            .body(query.to_vec())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+async fn serve_udp(socket: Arc<UdpSocket>, doh: Arc<Doh>) -> Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let query = buf[..len].to_vec();
+        let socket = socket.clone();
+        let doh = doh.clone();
+        tokio::spawn(async move {
+            match doh.query(&query).await {
+                Ok(reply) => {
+                    let _ = socket.send_to(&reply, peer).await;
+                }
+                Err(e) => eprintln!("[!] DoH query failed: {:#}", e),
+            }
+        });
+    }
+}
+
+async fn serve_tcp(listener: TcpListener, doh: Arc<Doh>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let doh = doh.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer_tcp(stream, &doh).await {
+                eprintln!("[!] DoH query failed: {:#}", e);
+            }
+        });
+    }
+}
+
+/// DNS over TCP: each message is prefixed with its 2-byte length
+async fn answer_tcp(mut stream: TcpStream, doh: &Doh) -> Result<()> {
+    loop {
+        let mut len = [0u8; 2];
+        if stream.read_exact(&mut len).await.is_err() {
+            return Ok(());
+        }
+        let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut query).await?;
+        let reply = doh.query(&query).await?;
+        stream.write_all(&(reply.len() as u16).to_be_bytes()).await?;
+        stream.write_all(&reply).await?;
+    }
+}
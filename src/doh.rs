@@ -0,0 +1,253 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::config::Config;
+use crate::browser::{self, Browser};
+use crate::{audit, blocklist, engine, i18n, report};
+
+/// Provider list downloaded by `hulios doh update`; the built-in list is
+/// used until it exists
+pub const LIST: &str = "/var/lib/hulios/doh.list";
+
+/// Default source for `hulios doh update`: one address per line, `#` comments
+const DEFAULT_LIST_URL: &str = "https://raw.githubusercontent.com/dibdot/DoH-IP-blocklists/master/doh-ipv4.txt";
+
+/// Anycast addresses of the DoH providers browsers offer out of the box
+const BUILTIN: &[&str] = &[
+    // Cloudflare
+    "1.1.1.1", "1.0.0.1", "2606:4700:4700::1111", "2606:4700:4700::1001",
+    // Google
+    "8.8.8.8", "8.8.4.4", "2001:4860:4860::8888", "2001:4860:4860::8844",
+    // Quad9
+    "9.9.9.9", "149.112.112.112", "2620:fe::fe", "2620:fe::9",
+    // OpenDNS
+    "208.67.222.222", "208.67.220.220",
+    // AdGuard
+    "94.140.14.14", "94.140.15.15",
+    // NextDNS
+    "45.90.28.0/24", "45.90.30.0/24",
+    // CleanBrowsing
+    "185.228.168.9", "185.228.169.9",
+    // Control D
+    "76.76.2.0/24", "76.76.10.0/24",
+];
+
+/// Probed by `hulios test` to prove the DoH blocklist is in place
+const PROBE: &str = "1.1.1.1:443";
+
+/// Persistent DoH-off policies; Firefox's is only written where no
+/// policies.json exists yet (Firefox reads a single file)
+const FIREFOX_POLICY: &str = "{\n  \"policies\": {\n    \"DNSOverHTTPS\": { \"Enabled\": false, \"Locked\": true }\n  }\n}\n";
+const CHROMIUM_POLICY: &str = "{ \"DnsOverHttpsMode\": \"off\" }\n";
+
+/// Name of the DoH policy file in a Chromium policy directory
+const CHROMIUM_FILE: &str = "hulios-doh.json";
+
+// =============================================================================
+// Provider List
+// =============================================================================
+
+/// Blocklist entries for `firewall.block_doh`
+pub fn providers() -> Vec<String> {
+    match fs::read_to_string(LIST) {
+        Ok(content) => parse(&content),
+        Err(_) => BUILTIN.iter().map(|entry| entry.to_string()).collect(),
+    }
+}
+
+/// Download a fresh provider list through Tor. The rules pick it up on the
+/// next start, restart or reload.
+pub fn update(config: &Config) -> Result<()> {
+    let url = config.firewall.doh_list_url.as_deref().unwrap_or(DEFAULT_LIST_URL);
+    println!("[*] {}", i18n::tr("doh-downloading-doh-provider-list", &[("url", url)]));
+
+    let client = engine::tor_http_client(Duration::from_secs(60))?;
+    let body = client.get(url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.text())
+        .with_context(|| i18n::tr("doh-failed-download-doh-provider", &[]))?;
+
+    let mut entries = parse(&body);
+    if entries.is_empty() {
+        anyhow::bail!(i18n::tr("doh-downloaded-doh-provider-list", &[]));
+    }
+    // Downloaded lists are IPv4-only more often than not
+    for builtin in BUILTIN {
+        if !entries.iter().any(|entry| entry == builtin) {
+            entries.push(builtin.to_string());
+        }
+    }
+
+    if let Some(parent) = Path::new(LIST).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write then rename so the rules never see a half-written list
+    let tmp = format!("{}.tmp", LIST);
+    fs::write(&tmp, entries.join("\n") + "\n")?;
+    fs::rename(&tmp, LIST).with_context(|| i18n::tr("doh-failed-update", &[("list", LIST)]))?;
+
+    println!("[+] {}", i18n::tr("doh-provider-list-updated-entries", &[("count", &entries.len().to_string()), ("list", LIST)]));
+    if !config.firewall.block_doh {
+        println!("[*] {}", i18n::tr("doh-set-firewall-block-doh", &[]));
+    } else if engine::is_tor_running() {
+        println!("[*] {}", i18n::tr("doh-run-reload-apply-new", &[]));
+    }
+    report::action(format!("DoH provider list updated ({} entries)", entries.len()));
+    report::data("entries", entries.len());
+    Ok(())
+}
+
+/// IPs, CIDRs and domains from a list, one per line; anything after the
+/// first field and `#` comments are ignored, invalid entries dropped
+fn parse(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.split('#').next()?.split_whitespace().next())
+        .filter(|entry| blocklist::parse(entry).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+// =============================================================================
+// Detection
+// =============================================================================
+
+/// The blocklist must make a DoH provider fail fast instead of being torified
+pub fn blocked() -> Result<()> {
+    let target: SocketAddr = PROBE.parse()?;
+    match TcpStream::connect_timeout(&target, Duration::from_secs(10)) {
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => anyhow::bail!(i18n::tr("doh-no-answer", &[("probe", PROBE)])),
+        Err(_) => Ok(()),
+        Ok(_) => anyhow::bail!(i18n::tr("doh-reachable", &[("probe", PROBE)])),
+    }
+}
+
+/// Browser profiles with DNS-over-HTTPS switched on, which would resolve
+/// names without going through the DNS redirect
+pub fn detect() -> Vec<String> {
+    let firefox_locked = fs::read_to_string(browser::FIREFOX_POLICIES).is_ok_and(|policy| policy.contains("DNSOverHTTPS"));
+    let mut findings = Vec::new();
+
+    for home in homes() {
+        if !firefox_locked {
+            let profiles = fs::read_dir(home.join(".mozilla/firefox")).into_iter().flatten().flatten();
+            for profile in profiles {
+                if let Some(mode) = firefox_trr_mode(&profile.path()) {
+                    findings.push(i18n::tr("doh-firefox-profile-uses-dns", &[("path", &profile.path().display().to_string()), ("mode", &mode.to_string())]));
+                }
+            }
+        }
+        for browser in Browser::ALL {
+            let (Some(dir), Some(state)) = (browser.policy_dir(), browser.local_state()) else {
+                continue;
+            };
+            if chromium_doh_off(dir) {
+                continue;
+            }
+            let state = home.join(state);
+            // chromium and chromium-browser share a profile
+            let reported = findings.iter().any(|f: &String| f.contains(&*state.to_string_lossy()));
+            if !reported && chromium_secure_dns(&state) {
+                findings.push(i18n::tr("doh-uses-secure-dns-over", &[("name", browser.name()), ("state", &state.display().to_string())]));
+            }
+        }
+    }
+    findings
+}
+
+/// network.trr.mode 2 (DoH first) or 3 (DoH only) from prefs.js or user.js
+fn firefox_trr_mode(profile: &Path) -> Option<u32> {
+    ["prefs.js", "user.js"]
+        .iter()
+        .filter_map(|file| fs::read_to_string(profile.join(file)).ok())
+        .flat_map(|prefs| {
+            prefs.lines()
+                .filter_map(|line| line.trim().strip_prefix("user_pref(\"network.trr.mode\","))
+                .filter_map(|rest| rest.trim().trim_end_matches(");").trim().parse::<u32>().ok())
+                .collect::<Vec<_>>()
+        })
+        .last()
+        .filter(|mode| matches!(mode, 2 | 3))
+}
+
+/// A managed policy in `dir` already sets DnsOverHttpsMode
+fn chromium_doh_off(dir: &str) -> bool {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .any(|file| fs::read_to_string(file.path()).is_ok_and(|policy| policy.contains("DnsOverHttpsMode")))
+}
+
+/// "automatic" only upgrades known resolvers, which 127.0.0.1 is not;
+/// "secure" always uses the configured provider
+fn chromium_secure_dns(state: &Path) -> bool {
+    fs::read_to_string(state)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .is_some_and(|state| state["dns_over_https"]["mode"] == "secure")
+}
+
+fn homes() -> Vec<PathBuf> {
+    let mut homes: Vec<PathBuf> = fs::read_dir("/home").into_iter().flatten().flatten().map(|entry| entry.path()).collect();
+    homes.push(PathBuf::from("/root"));
+    homes
+}
+
+// =============================================================================
+// Browser Policies
+// =============================================================================
+
+/// Install enterprise policies disabling DoH for every installed browser.
+/// They stay in place across sessions; `hulios doh remove-policies` deletes them.
+pub fn install_policies() -> Result<()> {
+    let mut installed = 0;
+    for browser in Browser::ALL.into_iter().filter(|b| b.installed()) {
+        let (path, content) = match browser.policy_dir() {
+            Some(dir) => (Path::new(dir).join(CHROMIUM_FILE), CHROMIUM_POLICY),
+            None => (PathBuf::from(browser::FIREFOX_POLICIES), FIREFOX_POLICY),
+        };
+        match fs::read_to_string(&path) {
+            Ok(existing) if existing == content => {}
+            Ok(_) if browser.policy_dir().is_none() => {
+                let message = i18n::tr("doh-already-exists-add-dnsoverhttps", &[("path", &path.display().to_string())]);
+                eprintln!("[!] {}", message);
+                report::warning(message);
+                continue;
+            }
+            _ => {
+                browser::write_policy(&path, content)?;
+                println!("[+] {}", i18n::tr("doh-installed", &[("path", &path.display().to_string())]));
+                report::action(format!("Installed {}", path.display()));
+            }
+        }
+        installed += 1;
+    }
+
+    if installed == 0 {
+        println!("[*] {}", i18n::tr("doh-no-firefox-chromium-based", &[]));
+    } else {
+        println!("[*] {}", i18n::tr("browser-restart-browsers", &[]));
+    }
+    Ok(())
+}
+
+/// Delete the policy files written by `install_policies`
+pub fn remove_policies() -> Result<()> {
+    let mut paths = vec![PathBuf::from(browser::FIREFOX_POLICIES)];
+    paths.extend(Browser::ALL.into_iter().filter_map(|b| Some(Path::new(b.policy_dir()?).join(CHROMIUM_FILE))));
+    for path in paths {
+        // Never delete a Firefox policies.json someone else wrote
+        let ours = fs::read_to_string(&path).is_ok_and(|content| content == FIREFOX_POLICY || content == CHROMIUM_POLICY);
+        if ours {
+            audit::record("remove", &path.to_string_lossy());
+            fs::remove_file(&path).with_context(|| i18n::tr("error-remove", &[("path", &path.display().to_string())]))?;
+            println!("[+] {}", i18n::tr("doh-removed", &[("path", &path.display().to_string())]));
+            report::action(format!("Removed {}", path.display()));
+        }
+    }
+    Ok(())
+}
@@ -1,284 +1,1308 @@
 use anyhow::{Result, Context};
 use std::process::Command;
 use std::fs;
+use std::os::unix::fs::MetadataExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::thread;
-use std::time::Duration;
-use crate::iptables; 
+use tokio::io::unix::AsyncFd;
+use std::time::{Duration, Instant, SystemTime};
+use crate::config::{Config, FirewallMode, Redirect, TorBrowserMode, TorConfig};
+use crate::control::{self, ControlClient};
+use crate::exec::Run;
+use crate::notify::{self, Event};
+use crate::privileged::{self, Step};
+use crate::scheduling::Scheduling;
+use crate::{alias, amnesic, appliance, audit, browser, cgroup, circuits, daemon, diagnose, dnsonly, features, firewall, i18n, ipc, isolate, libvirt, lsm, meshvpn, onion, output, overlay, pkgproxy, power, preflight, privacy, procfs, report, resolvers, setconf, state, stats, status, torbrowser, tordata, tuning, validate, verify, workstation};
 use users::get_current_uid;
 
-const TOR_USER: &str = "tor";
-const RESOLV_BACKUP: &str = "/tmp/hulios_resolv.conf.backup";
+pub const TOR_USER: &str = "tor";
+const RESOLV_BACKUP: &str = "/var/lib/hulios/resolv.conf.backup";
 const RESOLV_PATH: &str = "/etc/resolv.conf";
-const TOR_PID_FILE: &str = "/tmp/hulios_tor.pid";
+/// resolv.conf of a session: localhost, redirected to the DNSPort
+pub const TOR_RESOLV: &str = r#"# HULIOS - Tor DNS
+# DO NOT MODIFY - This file is managed by HULIOS
+# All DNS queries are routed through Tor
+nameserver 127.0.0.1
+options edns0 trust-ad ndots:0
+"#;
+pub const TORRC_PATH: &str = "/tmp/hulios_torrc";
+const TOR_UNIT: &str = "hulios-tor";
+/// On systemd-networkd machines resolved stays up, constrained by this drop-in
+pub const RESOLVED_DROPIN: &str = "/etc/systemd/resolved.conf.d/90-hulios.conf";
+pub const RESOLVED_CONSTRAINTS: &str = r#"# HULIOS - removed on hulios stop
+[Resolve]
+DNS=127.0.0.1:9061
+Domains=~.
+FallbackDNS=
+DNSStubListener=no
+LLMNR=no
+MulticastDNS=no
+DNSSEC=no
+DNSOverTLS=no
+"#;
+pub const ROUTE_LOCALNET: &str = "net.ipv4.conf.all.route_localnet";
+const TOR_BINARY_CANDIDATES: [&str; 3] = ["/usr/bin/tor", "/usr/sbin/tor", "/usr/local/bin/tor"];
+pub const TOR_LOG: &str = "/tmp/tor_debug.log";
+const TOR_SOCKS: &str = "socks5h://127.0.0.1:9050";
+pub const TOR_DATA_DIR: &str = "/tmp/hulios_tor_data";
+/// Bootstrap retries unless `tor.bootstrap_retries` says otherwise
+const DEFAULT_BOOTSTRAP_RETRIES: u32 = 2;
+/// Wait before the first retry, doubled for each one after it
+const BOOTSTRAP_BACKOFF: Duration = Duration::from_secs(5);
+/// Upper bound for each systemctl/killall call while preparing a start
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
 
 // =============================================================================
 // Main Commands
 // =============================================================================
 
-pub fn start() -> Result<()> {
-    if get_current_uid() != 0 {
-        anyhow::bail!("HULIOS must be run as root.");
+#[derive(Default)]
+pub struct StartOptions {
+    /// Don't run the post-start leak check
+    pub skip_verify: bool,
+    /// Keep all runtime state on a private tmpfs destroyed on stop
+    pub amnesic: bool,
+    /// Stop the distribution's tor.service too, instead of refusing to start
+    /// when it holds HULIOS's ports
+    pub stop_system_tor: bool,
+    /// Go ahead even though the firewall cuts off the SSH session this
+    /// runs in
+    pub force_ssh_cutoff: bool,
+    /// Roll everything back unless `hulios confirm` runs within this many
+    /// seconds
+    pub confirm_within: Option<u64>,
+    /// Let the LAN services leave directly this session
+    pub allow_lan: bool,
+}
+
+/// Without root, each step that needs it is run by the helper
+/// (privileged::Step); this process keeps the rest, the notifications
+/// included, and runs as the user they are for.
+pub fn start(opts: &StartOptions) -> Result<()> {
+    let _lock = privileged::lock()?;
+    let began = chrono::Local::now().timestamp();
+    let result = start_locked(opts);
+    // A --confirm-within start never leaves half a session behind; only
+    // one this start recorded, never a session already running
+    let unconfirmed = session().is_some_and(|s| s.confirm_by.is_some() && s.started_at >= began);
+    if result.is_err() && unconfirmed {
+        eprintln!("[!] {}", i18n::tr("error-rolling-back", &[]));
+        rollback();
     }
+    result
+}
 
-    // Stop any existing tor and system resolver
-    stop_tor_service()?;
-    neutralize_system_resolver()?;
-    
-    // Enable route_localnet for DNS redirection
-    enable_route_localnet()?;
-    
-    // Prepare Tor data directory
-    let data_dir = "/tmp/hulios_tor_data";
-    let _ = fs::remove_dir_all(data_dir);
-    fs::create_dir_all(data_dir).context("Failed to create data dir")?;
-    
-    Command::new("chown")
-        .args(["-R", "tor:tor", data_dir])
-        .status()
-        .context("Failed to chown data dir")?;
+/// Start sequence; the caller holds the HULIOS lock
+fn start_locked(opts: &StartOptions) -> Result<()> {
+    if workstation::is_attached() {
+        anyhow::bail!(i18n::tr("engine-attached-to-gateway", &[]));
+    }
+    if session().is_some_and(|s| s.dns_only) {
+        anyhow::bail!(i18n::tr("engine-dns-only-mode-active", &[]));
+    }
+    // Before loading the config, which the profile applies to
+    privileged::run(Step::SelectProfile)?;
+    let config = validate::load()?;
+    // WSL, containers without NET_ADMIN or missing modules would half-apply
+    // the rules; the probed kernel features decide how the ruleset adapts
+    privileged::run(Step::Preflight)?;
+    check_tor_browser(&config)?;
+    meshvpn::check(&config);
+    if opts.stop_system_tor {
+        privileged::run(Step::StopSystemTor)?;
+    } else {
+        check_system_tor()?;
+    }
 
-    // Write torrc
-    let torrc_content = format!(r#"RunAsDaemon 1
-User tor
-DataDirectory {}
-Log notice file /tmp/tor_debug.log
-SOCKSPort 9050
-TransPort 9051
-DNSPort 9061
-VirtualAddrNetwork 10.66.0.0/255.255.0.0
-AutomapHostsOnResolve 1
-"#, data_dir);
-    
-    fs::write("/tmp/hulios_torrc", &torrc_content)?;
+    // Record the session before touching anything, for `hulios recover`
+    privileged::run(Step::BeginSession {
+        amnesic: opts.amnesic,
+        force_ssh_cutoff: opts.force_ssh_cutoff,
+        confirm_within: opts.confirm_within,
+        allow_lan: opts.allow_lan,
+    })?;
 
-    // Start Tor
-    let tor_child = Command::new("tor")
-        .args(["-f", "/tmp/hulios_torrc"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .context("Failed to start tor process")?;
-    
-    let tor_pid = tor_child.id();
-    fs::write(TOR_PID_FILE, tor_pid.to_string())?;
-    println!("[*] Tor starting (PID: {})...", tor_pid);
+    // Stop any existing tor, neutralize the system resolver and enable
+    // route_localnet (for DNS redirection)
+    let preparing = Instant::now();
+    privileged::run(Step::PrepareSystem)?;
+    println!("[+] {}", i18n::tr("engine-system-prepared", &[("seconds", &format!("{:.1}", preparing.elapsed().as_secs_f64()))]));
 
-    // Wait for Tor to bootstrap
-    thread::sleep(Duration::from_secs(10));
-    
-    // Verify Tor is still running
-    if !is_tor_running() {
-        send_notification("HULIOS Error", "Tor failed to start! Check /tmp/tor_debug.log", "critical");
-        anyhow::bail!("Tor process died during startup");
-    }
+    launch_tor(&config)?;
 
     // Apply iptables rules
-    iptables::apply_rules(TOR_USER)?;
-    
+    privileged::run(Step::ApplyFirewall { only_if_changed: false })?;
+
     // Force DNS to point to localhost
-    take_dns_ownership()?;
+    privileged::run(Step::TakeDns)?;
+    println!("[+] {}", i18n::tr("cli-dns-localhost", &[]));
+    report::action("DNS now points to the Tor DNSPort");
+
+    // Prove it works before declaring success; never leave a half-working setup
+    if !opts.skip_verify {
+        verify_or_rollback(&config)?;
+    }
+    // The ESTABLISHED accept rule lets through what was open before
+    privileged::run(Step::CheckConntrack)?;
+
+    if let Some(seconds) = opts.confirm_within {
+        privileged::run(Step::ScheduleConfirm { seconds })?;
+    }
 
     // Send success notification
-    send_notification("HULIOS Started", "All traffic now routed through Tor 🧅", "normal");
-    println!("[+] HULIOS started successfully.");
-    
-    // Spawn background Tor monitor
-    spawn_tor_monitor();
-    
+    notify::send(Event::Start, &i18n::tr("notify-started-title", &[]), &i18n::tr("notify-started-body", &[]));
+    println!("[+] {}", i18n::tr("cli-started", &[]));
+
+    // Background daemon: health monitor and resolv.conf watch
+    if let Err(e) = privileged::run(Step::StartDaemon) {
+        eprintln!("[!] {}", i18n::tr("error-unmonitored", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("{:#}; the session runs unmonitored", e));
+    }
+
     Ok(())
 }
 
-pub fn stop() -> Result<()> {
-    if get_current_uid() != 0 {
-        anyhow::bail!("HULIOS must be run as root.");
+/// Undo the session. Only HULIOS's own Tor is stopped; `stop_system_tor`
+/// stops the distribution's tor.service as well.
+pub fn stop(stop_system_tor: bool) -> Result<()> {
+    let _lock = privileged::lock()?;
+
+    teardown()?;
+    if stop_system_tor {
+        privileged::run(Step::StopSystemTor)?;
     }
 
+    // Send notification
+    notify::send(Event::Stop, &i18n::tr("notify-stopped-title", &[]), &i18n::tr("notify-stopped-body", &[]));
+    println!("[+] {}", i18n::tr("cli-stopped", &[]));
+    Ok(())
+}
+
+/// The recorded session, read by whoever may (the helper, for a frontend)
+fn session() -> Option<state::State> {
+    privileged::query(Step::Session).ok().flatten()
+}
+
+/// Spawn Tor and wait for it to bootstrap, the PID recorded in the
+/// session. If Tor dies or times out during bootstrap it is retried with
+/// backoff, over `tor.bridges` if any (or over them from the start with
+/// `tor.use_bridges`).
+fn launch_tor(config: &Config) -> Result<()> {
+    let cached: bool = privileged::query(Step::PrepareTor)?;
+
+    let timeout = Duration::from_secs(config.tor.bootstrap_timeout.unwrap_or(120));
+    let retries = config.tor.bootstrap_retries.unwrap_or(DEFAULT_BOOTSTRAP_RETRIES);
+    // Waits run on the runtime; spawning, diagnosing and stopping Tor stay
+    // outside it, as they block
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .with_context(|| i18n::tr("engine-failed-start-async-runtime", &[]))?;
+    let mut attempt = 0;
+    loop {
+        let bridges = (attempt > 0 || config.tor.use_bridges) && !config.tor.bridges.is_empty();
+        let spawned = SystemTime::now();
+        let pid: u32 = privileged::query(Step::SpawnTor { bridges })?;
+        let e = match runtime.block_on(wait_for_bootstrap(Some(pid), timeout)) {
+            Ok(took) => {
+                privileged::run(Step::Bootstrapped { millis: took.as_millis() as u64, cached })?;
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        let diagnosis = diagnose::bootstrap_failure(TOR_LOG);
+        if let Some(diagnosis) = diagnosis {
+            let cause = i18n::tr(diagnosis.cause, &[]);
+            let fix = i18n::tr("engine-fix", &[("fix", &i18n::tr(diagnosis.fix, &[]))]);
+            eprintln!("[!] {}", cause);
+            eprintln!("    {}", fix);
+            report::warning(format!("{}. {}", cause, fix));
+        }
+        // What the log shows as "Permission denied" is often SELinux or AppArmor
+        let since = spawned.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+        let denied: bool = privileged::query(Step::ExplainDenials { since })?;
+        if attempt == retries || denied || diagnosis.is_some_and(|diagnosis| !diagnosis.retry) {
+            let body = diagnosis.map_or_else(|| i18n::tr("notify-tor-failed-body", &[("log", TOR_LOG)]), |diagnosis| i18n::tr(diagnosis.cause, &[]));
+            notify::send(Event::Crash, &i18n::tr("notify-error-title", &[]), &body);
+            return Err(e);
+        }
+
+        attempt += 1;
+        let delay = BOOTSTRAP_BACKOFF * 2u32.pow(attempt - 1);
+        eprintln!("[!] {:#}", e);
+        report::warning(format!("{:#}", e));
+        let retrying = if config.tor.bridges.is_empty() { "engine-retrying" } else { "engine-retrying-bridges" };
+        println!("[*] {}", i18n::tr(retrying, &[("seconds", &delay.as_secs().to_string()),
+            ("attempt", &(attempt + 1).to_string()), ("attempts", &(retries + 1).to_string())]));
+        privileged::run(Step::StopTor)?;
+        runtime.block_on(tokio::time::sleep(delay));
+    }
+}
+
+/// Prepare the data directory, Tor's kept data and a control password for
+/// a new Tor; true if kept data was restored. The session counts from here.
+pub fn prepare_tor(config: &Config) -> Result<bool> {
+    // Prepare Tor data directory
+    let data_dir = TOR_DATA_DIR;
+    let _ = fs::remove_dir_all(data_dir);
+    fs::create_dir_all(data_dir).with_context(|| i18n::tr("engine-failed-create-data-dir", &[]))?;
+
+    let tor_user = users::get_user_by_name(TOR_USER)
+        .with_context(|| i18n::tr("error-no-tor-user", &[("user", TOR_USER)]))?;
+    std::os::unix::fs::chown(data_dir, Some(tor_user.uid()), Some(tor_user.primary_group_id()))
+        .with_context(|| i18n::tr("engine-failed-chown-data-dir", &[]))?;
+    let cached = tordata::restore(config, &tor_user).unwrap_or_else(|e| {
+        eprintln!("[!] {}", i18n::tr("engine-tor-starts-without-kept", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("{:#}; Tor started without the kept data", e));
+        false
+    });
+    // Never a fixed value: each session's Tor gets a control password of its own
+    control::new_password()?;
+
+    let mut session = state::load().unwrap_or_default();
+    session.started_at = chrono::Local::now().timestamp();
+    state::save(&session)?;
+    Ok(cached)
+}
+
+/// Write the torrc, spawn Tor and record it in the session; returns its PID
+pub fn spawn_attempt(config: &Config, tproxy: bool, bridges: bool) -> Result<u32> {
+    let torrc_content = render_torrc(config, tproxy, bridges, true)?;
+    fs::write(TORRC_PATH, &torrc_content)?;
+
+    // Fresh log, so bootstrap progress isn't read from a previous attempt
+    // (truncated when it is bind-mounted by amnesic mode)
+    if fs::remove_file(TOR_LOG).is_err() {
+        let _ = fs::write(TOR_LOG, "");
+    }
+    lsm::relabel();
+
+    let tor_pid = if config.tor.confine {
+        spawn_tor_confined(&resolve_tor_binary(&config.tor)?, tproxy, &config.tor)?
+    } else {
+        spawn_tor()?
+    };
+    let mut session = state::load().unwrap_or_default();
+    session.tor_pid = Some(tor_pid);
+    session.tor_cgroup = cgroup::dedicated(tor_pid);
+    session.bridges = bridges;
+    state::save(&session)?;
+    println!("[*] {}", i18n::tr("engine-tor-starting-pid", &[("tor_pid", &tor_pid.to_string())]));
+    report::action(format!("Tor started (PID {}){}", tor_pid, if bridges { " over bridges" } else { "" }));
+    stats::begin_session(tor_pid);
+    Ok(tor_pid)
+}
+
+/// Run the leak check; on failure tear the session down so the normal
+/// network comes back, instead of leaving a half-working setup
+fn verify_or_rollback(config: &Config) -> Result<()> {
+    if let Err(e) = verify::run(config) {
+        notify::send(Event::Leak, &i18n::tr("notify-error-title", &[]), &i18n::tr("notify-leak-check-failed-body", &[]));
+        eprintln!("[!] {}", i18n::tr("error-rolling-back", &[]));
+        report::action("Rolled back after the failed leak check");
+        rollback();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Undo everything start (or `hulios dns-only start`) did; the caller
+/// holds the HULIOS lock
+pub fn teardown() -> Result<()> {
+    privileged::run(Step::StopDaemon)?;
+
+    // Record final traffic totals while Tor can still answer
+    privileged::run(Step::EndStats)?;
+
     // Restore firewall
-    iptables::flush_rules()?;
-    
+    privileged::run(Step::FlushFirewall)?;
+
     // Stop tor
+    privileged::run(Step::ReleaseTor)?;
+
+    // Restore DNS and the system resolver
+    privileged::run(Step::RestoreDns)?;
+
+    // Put route_localnet back as it was before the session
+    privileged::run(Step::RestoreSysctls)?;
+
+    privileged::run(Step::EndSession { secure_delete: true })
+}
+
+/// Forget the session; `secure_delete` shreds its files first when
+/// `privacy.secure_delete` is set
+pub fn end_session(secure_delete: bool) {
+    // In amnesic mode destroying the tmpfs is the secure deletion
+    let amnesic = amnesic::is_active();
+    if secure_delete && !amnesic && Config::load().unwrap_or_default().privacy.secure_delete {
+        shred_session_artifacts();
+    }
+    state::clear();
+    if amnesic {
+        amnesic::unmount();
+    }
+}
+
+/// Stop Tor and undo what only makes sense while traffic goes through it;
+/// the firewall, resolv.conf and system resolver are left to the caller
+pub fn release_tor() -> Result<()> {
     stop_tor_service()?;
-    
-    // Restore DNS
-    restore_dns()?;
-    
-    // Restore system resolver
-    restore_system_resolver()?;
+    overlay::halt_all(&Config::load().unwrap_or_default());
+    control::forget_password();
+    report::action("Tor stopped");
 
-    // Send notification
-    send_notification("HULIOS Stopped", "Normal network restored", "normal");
-    println!("[+] HULIOS stopped.");
+    // Package managers would otherwise fail against the dead SOCKS port
+    if pkgproxy::is_enabled() {
+        if let Err(e) = pkgproxy::disable() {
+            eprintln!("[!] {}", i18n::tr("engine-could-not-remove-package", &[("error", &format!("{:#}", e))]));
+            report::warning(format!("Could not remove the package manager proxy: {:#}", e));
+        }
+    }
+
+    // The policies only make sense while traffic goes through Tor
+    if browser::is_hardened() {
+        if let Err(e) = browser::unharden() {
+            eprintln!("[!] {}", i18n::tr("engine-could-not-remove-browser", &[("error", &format!("{:#}", e))]));
+            report::warning(format!("Could not remove the browser policies: {:#}", e));
+        }
+    }
     Ok(())
 }
 
+/// Everything on disk that records this session, for `privacy.secure_delete`
+fn shred_session_artifacts() {
+    let artifacts = [
+        TOR_LOG, TORRC_PATH, TOR_DATA_DIR,
+        daemon::LOG, status::STATUS_CACHE, state::STATE_PATH, tuning::CIRCUITS_PATH,
+    ];
+    let mut failed = 0;
+    for path in artifacts {
+        if let Err(e) = privacy::shred(std::path::Path::new(path)) {
+            eprintln!("[!] {}", i18n::tr("engine-could-not-securely-delete", &[("path", path), ("error", &format!("{:#}", e))]));
+            report::warning(format!("Could not securely delete {}: {:#}", path, e));
+            failed += 1;
+        }
+    }
+    if failed == 0 {
+        println!("[+] {}", i18n::tr("engine-session-artifacts-zeroed-deleted", &[]));
+        report::action("Session artifacts securely deleted");
+    }
+}
+
+/// Best-effort teardown after a failed start
+pub fn rollback() {
+    if let Err(e) = teardown() {
+        eprintln!("[!] {}", i18n::tr("engine-rollback-incomplete-run-sudo", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("Rollback incomplete ({:#})", e));
+    }
+}
+
+/// Cycle Tor under a closed firewall: the new ruleset replaces the old one
+/// without ever opening it, and only the Tor process is restarted, so no
+/// packet can leave outside Tor at any point
 pub fn restart() -> Result<()> {
-    println!("[+] Restarting HULIOS...");
-    
-    // Quiet stop (no notification)
-    if get_current_uid() != 0 {
-        anyhow::bail!("HULIOS must be run as root.");
+    let _lock = privileged::lock()?;
+    if session().is_some_and(|s| s.dns_only) {
+        anyhow::bail!(i18n::tr("engine-dns-only-mode-active", &[]));
     }
-    iptables::flush_rules()?;
-    stop_tor_service()?;
-    restore_dns()?;
-    
-    thread::sleep(Duration::from_secs(2));
-    
-    // Start (will send its own notification)
-    start()?;
-    
-    // Override with restart-specific notification
-    send_notification("HULIOS Restarted", "Tor connection refreshed 🔄", "normal");
-    println!("[+] HULIOS restarted.");
+    privileged::run(Step::SelectProfile)?;
+    let config = validate::load()?;
+    if privileged::query::<Option<bool>>(Step::RulesPresent)? != Some(true) {
+        println!("[*] {}", i18n::tr("engine-not-running-starting", &[]));
+        return start_locked(&StartOptions::default());
+    }
+    println!("[+] {}", i18n::tr("cli-restarting", &[]));
+    privileged::run(Step::Preflight)?;
+
+    privileged::run(Step::StopDaemon)?;
+    privileged::run(Step::EndStats)?;
+
+    // Kill switch first: re-rendered from the current config, applied over
+    // the live rules, and a previously configured backend's rules removed
+    privileged::run(Step::ApplyFirewall { only_if_changed: false })?;
+
+    privileged::run(Step::StopTor)?;
+    if let Err(e) = launch_tor(&config) {
+        eprintln!("[!] {}", i18n::tr("engine-firewall-stays-closed-run", &[]));
+        report::warning("Tor failed to restart, the firewall stays closed");
+        return Err(e);
+    }
+    privileged::run(Step::ReassertDns)?;
+    verify_or_rollback(&config)?;
+
+    if let Err(e) = privileged::run(Step::StartDaemon) {
+        eprintln!("[!] {}", i18n::tr("error-unmonitored", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("{:#}; the session runs unmonitored", e));
+    }
+    notify::send(Event::IdentityChange, &i18n::tr("notify-restarted-title", &[]), &i18n::tr("notify-restarted-body", &[]));
+    println!("[+] {}", i18n::tr("engine-restarted-firewall-stayed-closed", &[]));
+    report::action("Tor restarted behind the firewall");
+    Ok(())
+}
+
+/// Apply a changed config to the running session without a stop/start.
+/// Tor gets the re-rendered torrc over the control port (LOADCONF, which
+/// keeps its circuits); the firewall is re-applied in place, never opened,
+/// and only if its policy changed.
+pub fn reload() -> Result<()> {
+    let _lock = privileged::lock()?;
+    if !is_tor_running() {
+        anyhow::bail!(i18n::tr("error-not-running", &[]));
+    }
+    let config = validate::load()?;
+    privileged::run(Step::Preflight)?;
+
+    let mut changed = privileged::query::<bool>(Step::ReloadTor)?;
+
+    // Firewall: swapped over the live rules like on restart
+    if privileged::query::<bool>(Step::ApplyFirewall { only_if_changed: true })? {
+        verify_or_rollback(&config)?;
+        changed = true;
+    } else {
+        println!("[*] {}", i18n::tr("engine-firewall-rules-unchanged", &[]));
+    }
+
+    if !changed {
+        println!("[+] {}", i18n::tr("engine-nothing-reload", &[]));
+        return Ok(());
+    }
+    // The daemon reads the config once, when it starts
+    privileged::run(Step::StopDaemon)?;
+    if let Err(e) = privileged::run(Step::StartDaemon) {
+        eprintln!("[!] {}", i18n::tr("error-unmonitored", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("{:#}; the session runs unmonitored", e));
+    }
+    println!("[+] {}", i18n::tr("engine-configuration-reloaded-without-restarting", &[]));
     Ok(())
 }
 
+/// Give the running Tor what changed in the config: the re-rendered torrc
+/// and its cgroup's caps; true if either did
+pub fn reload_tor(config: &Config) -> Result<bool> {
+    let features = features::probe(config);
+    let session = state::load().unwrap_or_default();
+    let mut changed = false;
+
+    // Tor: everything torrc carries (aliases, bandwidth, timeouts, ports, logging)
+    let tproxy = features::redirect(config, &features) == Some(Redirect::Tproxy);
+    // Keep the bridges the session fell back to, if it did
+    let torrc = render_torrc(config, tproxy, session.bridges || config.tor.use_bridges, true)?;
+    let current = fs::read_to_string(TORRC_PATH).unwrap_or_default();
+    if torrc != current {
+        let added = torrc.lines().filter(|line| !current.lines().any(|old| old == *line)).count();
+        let removed = current.lines().filter(|line| !torrc.lines().any(|new| new == *line)).count();
+        ControlClient::connect()?
+            .loadconf(&torrc)
+            .with_context(|| i18n::tr("engine-tor-refused-new-configuration", &[]))?;
+        fs::write(TORRC_PATH, &torrc)?;
+        println!("[+] {}", i18n::tr("engine-tor-configuration-reloaded-lines", &[("added", &added.to_string()), ("removed", &removed.to_string())]));
+        report::action("Tor configuration reloaded");
+        changed = true;
+    } else {
+        println!("[*] {}", i18n::tr("engine-tor-configuration-unchanged", &[]));
+    }
+
+    // Tor's cgroup: caps set on the running scope, service or group
+    if let Some(group) = &session.tor_cgroup {
+        let limits = cgroup::Limits::from_config(&config.tor);
+        if cgroup::Limits::current(group) != limits {
+            cgroup::limit(group, &limits)?;
+            println!("[+] {}", i18n::tr("engine-tor-resource-limits-updated", &[]));
+            report::action("Tor resource limits updated");
+            changed = true;
+        }
+    }
+    Ok(changed)
+}
+
+/// Apply the ruleset rendered from `config` over the live one and remove a
+/// previously configured backend's, recording its policy in the session.
+/// The overlay router comes up first, as DNS is sent to it. With
+/// `only_if_changed`, nothing happens if the policy is the applied one.
+pub fn apply_firewall(config: &Config, only_if_changed: bool) -> Result<bool> {
+    let features = features::probe(config);
+    let mut session = state::load().unwrap_or_default();
+    let fingerprint = firewall::fingerprint(config, &features);
+    if only_if_changed && session.firewall_policy.as_deref() == Some(fingerprint.as_str()) {
+        return Ok(false);
+    }
+    overlay::launch_all(config)?;
+    firewall::adapted_backend(config, &features).apply_rules(TOR_USER)?;
+    firewall::flush_others(config)?;
+    session.firewall_backend = firewall::backend(config).name().to_string();
+    session.firewall_policy = Some(fingerprint);
+    state::save(&session)?;
+    Ok(true)
+}
+
+/// Clear the rules and end the session at once, without stop's traffic
+/// totals or secure deletion. The daemon goes first: it would otherwise
+/// put the Tor-only resolv.conf and route_localnet back.
 pub fn flush() -> Result<()> {
-    if get_current_uid() != 0 {
-        anyhow::bail!("HULIOS must be run as root.");
+    let _lock = privileged::lock()?;
+    privileged::run(Step::StopDaemon)?;
+
+    // The rest is undone even if the rules could not all be flushed
+    let flushed = privileged::run(Step::FlushFirewall);
+    privileged::run(Step::ReleaseTor)?;
+    privileged::run(Step::RestoreDns)?;
+    privileged::run(Step::RestoreSysctls)?;
+    privileged::run(Step::EndSession { secure_delete: false })?;
+    flushed?;
+    notify::send(Event::Stop, &i18n::tr("notify-flushed-title", &[]), &i18n::tr("notify-flushed-body", &[]));
+    println!("[+] {}", i18n::tr("engine-firewall-rules-flushed-dns", &[]));
+    Ok(())
+}
+
+/// What an unclean shutdown can leave behind, as `recover` finds it
+#[derive(Deserialize, Serialize, Default)]
+pub struct Leftovers {
+    pub unconfirmed: bool,
+    pub tor_running: bool,
+    pub rules: bool,
+    pub policy_routing: bool,
+    pub attached: bool,
+    /// Masked resolver services
+    pub masked: Vec<String>,
+    pub resolved_dropin: bool,
+    /// Resolver changes, described (resolvers::leftovers)
+    pub resolver_changes: Vec<String>,
+    pub resolv_owned: bool,
+    pub resolv_backup: bool,
+    pub pkgproxy: bool,
+    pub browser: bool,
+    pub route_localnet: bool,
+    pub torrc: bool,
+    pub data_dir: bool,
+    pub amnesic: bool,
+}
+
+/// Inspect the persisted state and every artifact HULIOS may leave behind
+pub fn leftovers() -> Leftovers {
+    let session = state::load();
+    let config = Config::load().unwrap_or_default();
+    Leftovers {
+        unconfirmed: session.as_ref().is_some_and(|s| s.confirm_by.is_some()),
+        tor_running: is_tor_running() || !find_hulios_tor().is_empty(),
+        rules: firewall::backend(&config).rules_present() == Some(true),
+        policy_routing: firewall::policy_routing_present(),
+        attached: workstation::is_attached(),
+        masked: ["systemd-resolved", "dnsmasq"]
+            .into_iter()
+            .filter(|unit| unit_is_masked(unit))
+            .map(str::to_string)
+            .collect(),
+        resolved_dropin: fs::metadata(RESOLVED_DROPIN).is_ok(),
+        resolver_changes: resolvers::leftovers(),
+        resolv_owned: dns_owned(),
+        resolv_backup: fs::metadata(RESOLV_BACKUP).is_ok(),
+        pkgproxy: pkgproxy::is_enabled(),
+        browser: browser::is_hardened(),
+        route_localnet: session
+            .as_ref()
+            .and_then(|s| s.route_localnet.as_deref())
+            .is_some_and(|previous| read_sysctl(ROUTE_LOCALNET).as_deref() != Some(previous)),
+        torrc: fs::metadata(TORRC_PATH).is_ok(),
+        data_dir: fs::metadata(TOR_DATA_DIR).is_ok(),
+        amnesic: amnesic::is_active(),
+    }
+}
+
+/// Repair the system after an unclean shutdown (crash, reboot mid-session):
+/// inspect the persisted state and every artifact HULIOS may leave behind,
+/// and restore each one to the stock configuration
+pub fn recover() -> Result<()> {
+    let _lock = privileged::lock()?;
+
+    match session() {
+        Some(s) => println!("[*] {}", i18n::tr("engine-found-session-state-backend", &[("started", &chrono::DateTime::from_timestamp(s.started_at, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default()), ("backend", &s.firewall_backend)])),
+        None => println!("[*] {}", i18n::tr("engine-no-session-state-found", &[])),
+    }
+
+    let found: Leftovers = privileged::query(Step::Leftovers)?;
+    let mut repaired = 0;
+    let mut fix = |found: bool, what: &str| {
+        if found {
+            println!("[!] {}", what);
+            report::action(what);
+            repaired += 1;
+        }
+    };
+    // Its deadline is moot: everything is undone below
+    fix(found.unconfirmed, &i18n::tr("engine-leftover-unconfirmed", &[]));
+    fix(found.tor_running, &i18n::tr("engine-leftover-tor", &[]));
+    fix(found.rules, &i18n::tr("engine-leftover-rules", &[]));
+    fix(found.policy_routing, &i18n::tr("engine-leftover-policy-routing", &[]));
+    fix(found.attached, &i18n::tr("engine-leftover-attached", &[]));
+    fix(!found.masked.is_empty(), &i18n::tr("engine-leftover-masked", &[("masked", &found.masked.join(", "))]));
+    fix(found.resolved_dropin, &i18n::tr("engine-leftover-resolved-dropin", &[]));
+    for leftover in &found.resolver_changes {
+        fix(true, &i18n::tr("engine-leftover-resolver-change", &[("leftover", leftover)]));
+    }
+    fix(found.resolv_owned, &i18n::tr("engine-leftover-resolv-owned", &[]));
+    fix(found.resolv_backup, &i18n::tr("engine-leftover-resolv-backup", &[]));
+    fix(found.pkgproxy, &i18n::tr("engine-leftover-pkgproxy", &[]));
+    fix(found.browser, &i18n::tr("engine-leftover-browser", &[]));
+    fix(found.route_localnet, &i18n::tr("engine-leftover-route-localnet", &[]));
+    fix(found.torrc, &i18n::tr("engine-leftover-removed", &[("path", TORRC_PATH)]));
+    fix(found.data_dir, &i18n::tr("engine-leftover-removed", &[("path", TOR_DATA_DIR)]));
+    fix(found.amnesic, &i18n::tr("engine-leftover-amnesic", &[]));
+
+    privileged::run(Step::StopDaemon)?;
+    privileged::run(Step::ReleaseTor)?;
+    if found.attached {
+        privileged::run(Step::DetachGateway)?;
+    }
+    privileged::run(Step::FlushFirewall)?;
+    privileged::run(Step::RestoreDns)?;
+    privileged::run(Step::RestoreSysctls)?;
+    privileged::run(Step::ForgetTor)?;
+    privileged::run(Step::EndStats)?;
+    privileged::run(Step::EndSession { secure_delete: false })?;
+
+    if repaired == 0 {
+        println!("[+] {}", i18n::tr("engine-nothing-recover-system-clean", &[]));
+    } else {
+        println!("[+] {}", i18n::tr("engine-recovered-leftover-normal-networking", &[("repaired", &repaired.to_string())]));
     }
-    iptables::flush_rules()?;
-    restore_dns()?;
-    restore_system_resolver()?;
-    send_notification("HULIOS Flushed", "Firewall rules cleared", "normal");
-    println!("[+] Firewall rules flushed and DNS restored.");
     Ok(())
 }
 
 // =============================================================================
-// Tor Monitoring
+// Mode Switching
 // =============================================================================
 
-/// Check if Tor process is running
-fn is_tor_running() -> bool {
-    if let Ok(pid_str) = fs::read_to_string(TOR_PID_FILE) {
-        if let Ok(pid) = pid_str.trim().parse::<i32>() {
-            let status = Command::new("kill")
-                .args(["-0", &pid.to_string()])
-                .status();
-            return status.map(|s| s.success()).unwrap_or(false);
+/// dns-only to full torification without a teardown: Tor bootstraps while
+/// DNS stays with the encrypted resolver, then the Tor ruleset is swapped
+/// over the dns-only one and resolv.conf rewritten in place. The system
+/// resolver stays neutralized throughout. Caller holds the HULIOS lock.
+pub fn switch_to_tor() -> Result<()> {
+    let config = validate::load()?;
+    let features = preflight::enforce(&config)?;
+    check_tor_browser(&config)?;
+    check_system_tor()?;
+
+    if let Err(e) = launch_tor(&config) {
+        let _ = privileged::run(Step::StopTor);
+        eprintln!("[!] {}", i18n::tr("engine-tor-did-not-start", &[]));
+        report::warning("Tor did not start, dns-only mode kept");
+        return Err(e);
+    }
+
+    // Past this point the dns-only ruleset may already be gone, so a
+    // failure tears the whole session down instead of leaving half of each
+    let mut session = state::load().unwrap_or_default();
+    let result = (|| {
+        overlay::launch_all(&config)?;
+        firewall::adapted_backend(&config, &features).apply_rules(TOR_USER)?;
+        firewall::flush_others(&config)?;
+        session.dns_only = false;
+        session.started_at = chrono::Local::now().timestamp();
+        session.firewall_backend = firewall::backend(&config).name().to_string();
+        session.firewall_policy = Some(firewall::fingerprint(&config, &features));
+        state::save(&session)?;
+        write_resolv(TOR_RESOLV)?;
+        dnsonly::halt();
+        Ok(())
+    })();
+    if let Err(e) = result {
+        eprintln!("[!] {}", i18n::tr("error-rolling-back", &[]));
+        report::action("Rolled back after the failed switch to full torification");
+        rollback();
+        return Err(e);
+    }
+    println!("[+] {}", i18n::tr("cli-dns-localhost", &[]));
+    report::action("Switched from dns-only to full torification");
+
+    verify_or_rollback(&config)?;
+    if let Err(e) = daemon::spawn() {
+        eprintln!("[!] {}", i18n::tr("error-unmonitored", &[("error", &format!("{:#}", e))]));
+        report::warning(format!("{:#}; the session runs unmonitored", e));
+    }
+    notify::send(Event::Start, &i18n::tr("notify-started-title", &[]), &i18n::tr("notify-started-body", &[]));
+    Ok(())
+}
+
+/// Full torification to dns-only: the session's Tor is stopped, and the
+/// daemon with it, but the firewall swap and resolv.conf rewrite are left
+/// to `dnsonly`. Caller holds the HULIOS lock.
+pub fn leave_tor() -> Result<()> {
+    daemon::stop();
+    stats::end_session();
+    release_tor()?;
+    let _ = fs::remove_file(TORRC_PATH);
+    let _ = fs::remove_dir_all(TOR_DATA_DIR);
+    Ok(())
+}
+
+// =============================================================================
+// Check Mode
+// =============================================================================
+
+/// What `hulios start` would change right now, without changing anything.
+/// Empty when HULIOS is already fully active.
+pub fn pending_start_changes() -> Result<Vec<String>> {
+    let config = Config::load()?;
+    let backend = firewall::backend(&config);
+    let Some(rules) = backend.rules_present() else {
+        anyhow::bail!(i18n::tr("engine-cannot-read-firewall-rules", &[]));
+    };
+
+    let mut changes = Vec::new();
+    if !rules {
+        changes.push(i18n::tr("engine-check-apply-rules", &[("name", backend.name())]));
+        if config.firewall.redirect == Redirect::Tproxy {
+            changes.push(i18n::tr("engine-check-route-tproxy-marked-traffic", &[]));
         }
     }
-    
-    // Fallback: check by name
-    let status = Command::new("pgrep")
-        .args(["-x", "tor"])
-        .status();
-    status.map(|s| s.success()).unwrap_or(false)
+    if !is_tor_running() {
+        changes.push(i18n::tr("engine-check-start-tor-instance", &[]));
+    }
+    if !dns_owned() {
+        changes.push(i18n::tr("engine-check-point-etc-resolv-conf", &[]));
+    }
+    if networkd_managed() {
+        if fs::metadata(RESOLVED_DROPIN).is_err() {
+            changes.push(i18n::tr("engine-check-constrain-resolved", &[("path", RESOLVED_DROPIN)]));
+        }
+    } else if !unit_is_masked("systemd-resolved") {
+        changes.push(i18n::tr("engine-check-mask-systemd-resolved", &[]));
+    }
+    if !unit_is_masked("dnsmasq") {
+        changes.push(i18n::tr("engine-check-mask-dnsmasq", &[]));
+    }
+    if unit_is_active("unbound") && fs::metadata(resolvers::UNBOUND_DROPIN).is_err() {
+        changes.push(i18n::tr("engine-check-forward-unbound", &[("path", resolvers::UNBOUND_DROPIN)]));
+    }
+    if unit_is_active("nscd") {
+        changes.push(i18n::tr("engine-check-flush-nscd-hosts-cache", &[]));
+    }
+    if read_sysctl(ROUTE_LOCALNET).as_deref() != Some("1") {
+        changes.push(i18n::tr("engine-check-enable", &[("key", ROUTE_LOCALNET)]));
+    }
+    Ok(changes)
 }
 
-/// Spawn a background thread to monitor Tor
-fn spawn_tor_monitor() {
-    thread::spawn(|| {
-        thread::sleep(Duration::from_secs(30));
-        
-        loop {
-            thread::sleep(Duration::from_secs(10));
-            
-            if !is_tor_running() {
-                send_notification(
-                    "⚠️ HULIOS CRITICAL", 
-                    "Tor process crashed! Network may be leaking. Run: sudo hulios restart",
-                    "critical"
-                );
-                eprintln!("[!] CRITICAL: Tor process died!");
-                break;
-            }
+/// What `hulios stop` would change right now, without changing anything.
+/// Empty when HULIOS is fully stopped.
+pub fn pending_stop_changes() -> Result<Vec<String>> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("engine-cannot-read-firewall-rules", &[]));
+    }
+
+    let mut changes = Vec::new();
+    let config = Config::load().unwrap_or_default();
+    if firewall::backend(&config).rules_present() == Some(true) {
+        changes.push(i18n::tr("engine-check-flush-firewall-rules", &[]));
+    }
+    if firewall::policy_routing_present() {
+        changes.push(i18n::tr("engine-check-remove-tproxy-policy-routing", &[]));
+    }
+    if is_tor_running() || !find_hulios_tor().is_empty() {
+        changes.push(i18n::tr("engine-check-stop-tor-instance", &[]));
+    }
+    if dns_owned() {
+        changes.push(i18n::tr("engine-check-restore-etc-resolv-conf", &[]));
+    }
+    for unit in ["systemd-resolved", "dnsmasq"] {
+        if unit_is_masked(unit) {
+            changes.push(i18n::tr("engine-check-unmask", &[("unit", unit)]));
         }
-    });
+    }
+    if fs::metadata(RESOLVED_DROPIN).is_ok() {
+        changes.push(i18n::tr("engine-check-remove-dropin", &[("path", RESOLVED_DROPIN)]));
+    }
+    changes.extend(resolvers::leftovers());
+    if browser::is_hardened() {
+        changes.push(i18n::tr("engine-check-remove-browser-hardening-policies", &[]));
+    }
+    if let Some(previous) = state::load().and_then(|s| s.route_localnet) {
+        if read_sysctl(ROUTE_LOCALNET).as_deref() != Some(previous.as_str()) {
+            changes.push(i18n::tr("engine-check-restore", &[("key", ROUTE_LOCALNET), ("previous", &previous)]));
+        }
+    }
+    if amnesic::is_active() {
+        changes.push(i18n::tr("engine-check-destroy-amnesic-tmpfs", &[]));
+    }
+    if state::load().is_some() {
+        changes.push(i18n::tr("engine-check-clear-recorded-session-state", &[]));
+    }
+    Ok(changes)
+}
+
+/// Apply bandwidth limits to the running Tor without restarting it.
+/// `rate` of "off" restores Tor's defaults.
+pub fn limit(rate: &str, burst: Option<&str>) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+
+    let mut client = ControlClient::connect()?;
+    if rate.eq_ignore_ascii_case("off") {
+        client.command("RESETCONF BandwidthRate BandwidthBurst")?;
+        println!("[+] {}", i18n::tr("engine-bandwidth-limits-removed", &[]));
+        report::action("Bandwidth limits removed");
+        return Ok(());
+    }
+
+    let rate = parse_bandwidth(rate)?;
+    let mut settings = vec![("BandwidthRate", rate.clone())];
+    if let Some(burst) = burst {
+        settings.push(("BandwidthBurst", parse_bandwidth(burst)?));
+    }
+    client.setconf(&settings)?;
+
+    println!("[+] {}", i18n::tr("engine-tor-bandwidth-limited", &[("rate", &rate)]));
+    report::action(format!("Tor bandwidth limited to {}/s", rate));
+    println!("[*] {}", i18n::tr("engine-set-tor-bandwidth-rate", &[("path", crate::config::CONFIG_PATH)]));
+    Ok(())
 }
 
 // =============================================================================
-// Notifications - Works on both X11 and Wayland (Hyprland, Sway, etc.)
+// Torrc Generation
 // =============================================================================
 
-/// Send desktop notification using notify-send
-/// Works on both X11 and Wayland by detecting the environment
-fn send_notification(title: &str, body: &str, urgency: &str) {
-    // Get the original user (before sudo)
-    let sudo_user = std::env::var("SUDO_USER").unwrap_or_default();
-    if sudo_user.is_empty() {
-        // Running as root directly without sudo, try anyway
-        let _ = Command::new("notify-send")
-            .args(["-u", urgency, "-a", "HULIOS", title, body])
-            .status();
-        return;
+/// The torrc `hulios render` writes: the config's alone, as a session
+/// without bridge fallback, power adjustments or detected libvirt bridges
+/// would have it
+pub fn standalone_torrc(config: &Config) -> Result<String> {
+    render_torrc(config, config.firewall.redirect == Redirect::Tproxy, config.tor.use_bridges, false)
+}
+
+/// Render the torrc for the HULIOS-managed Tor instance; `bridges` connects
+/// through `tor.bridges` instead of directly. A `session` torrc also
+/// carries what only the running system knows: the session's control
+/// password, addresses read from interfaces, the power state, reported bad
+/// exits and isolated apps.
+fn render_torrc(config: &Config, tproxy: bool, bridges: bool, session: bool) -> Result<String> {
+    let tor = &config.tor;
+    let onion_only = config.firewall.mode == FirewallMode::OnionOnly;
+    // Onion-only: SOCKS and DNS refuse anything that would need an exit
+    // relay; the firewall only lets TransPort reach automapped onions
+    let onion_flag = if onion_only { " OnionTrafficOnly" } else { "" };
+    // Tor stays in the foreground: the HULIOS daemon (or, confined, the
+    // systemd unit) is its parent and waits on it
+    let mut torrc = format!(r#"RunAsDaemon 0
+User tor
+DataDirectory {}
+Log notice file {}
+SOCKSPort 9050{}
+TransPort 9051
+DNSPort 9061{}
+ControlPort 127.0.0.1:9052
+{}
+VirtualAddrNetwork 10.66.0.0/255.255.0.0
+AutomapHostsOnResolve 1
+"#, TOR_DATA_DIR, TOR_LOG, onion_flag, onion_flag, control_auth(session)?);
+    torrc.push_str(&format!("AutomapHostsSuffixes {}\n", alias::automap_suffixes(config)));
+    // Gateway mode: the LAN is redirected to the address Tor listens on
+    let gateway = match &config.gateway.lan_interface {
+        Some(interface) if !session && config.gateway.listen_address.is_none() => {
+            anyhow::bail!(i18n::tr("engine-set-gateway-listen-address", &[("interface", interface)]));
+        }
+        _ => appliance::listen_address(&config.gateway)?,
+    };
+    if let Some(address) = gateway {
+        torrc.push_str(&format!("TransPort {}:9051\nDNSPort {}:9061{}\n", address, address, onion_flag));
+        if config.gateway.socks {
+            torrc.push_str(&format!("SOCKSPort {}:9050{}\n", address, onion_flag));
+        }
     }
-    
-    // Get the user's UID for XDG_RUNTIME_DIR
-    let uid = get_user_uid(&sudo_user).unwrap_or(1000);
-    let xdg_runtime = format!("/run/user/{}", uid);
-    
-    // Try to detect Wayland first (common for Hyprland/Sway)
-    let wayland_display = find_wayland_display(&xdg_runtime);
-    
-    // Also get X11 display if available
-    let x11_display = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
-    
-    // Build environment for the notification command
-    let mut env_vars = vec![
-        ("XDG_RUNTIME_DIR", xdg_runtime.clone()),
-        ("HOME", format!("/home/{}", sudo_user)),
-    ];
-    
-    // Add Wayland-specific vars if detected
-    if let Some(ref wd) = wayland_display {
-        env_vars.push(("WAYLAND_DISPLAY", wd.clone()));
-        // Hyprland instance signature (if available)
-        if let Ok(his) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
-            env_vars.push(("HYPRLAND_INSTANCE_SIGNATURE", his));
+    // So are torified libvirt guests, to their bridge's address
+    for address in if session { libvirt::listen_addresses(config) } else { Vec::new() } {
+        torrc.push_str(&format!("TransPort {}:9051\nDNSPort {}:9061{}\n", address, address, onion_flag));
+    }
+    if tproxy {
+        // TransPort reads the original destination from the transparent socket
+        torrc.push_str("TransProxyType TPROXY\n");
+    }
+    for (name, target) in &config.aliases {
+        torrc.push_str(&format!("MapAddress {} {}\n", name, target));
+    }
+
+    // On battery or a metered connection, [power] may lower them
+    let state = if session { power::current(config) } else { power::PowerState::default() };
+    let (rate, burst) = power::bandwidth(config, state);
+    if let Some(rate) = &rate {
+        torrc.push_str(&format!("BandwidthRate {}\n", parse_bandwidth(rate)?));
+    }
+    if let Some(burst) = &burst {
+        torrc.push_str(&format!("BandwidthBurst {}\n", parse_bandwidth(burst)?));
+    }
+    if let Some(pending) = tor.max_client_circuits_pending {
+        torrc.push_str(&format!("MaxClientCircuitsPending {}\n", pending));
+    }
+    if let Some(timeout) = tor.circuit_build_timeout {
+        // A fixed timeout only sticks if Tor stops learning its own
+        torrc.push_str("LearnCircuitBuildTimeout 0\n");
+        torrc.push_str(&format!("CircuitBuildTimeout {}\n", timeout));
+    }
+    if let Some(port) = tor.http_tunnel_port {
+        torrc.push_str(&format!("HTTPTunnelPort 127.0.0.1:{}{}\n", port, onion_flag));
+    }
+    // Tor scrubs by default; only a deliberate opt-out logs addresses
+    torrc.push_str(&format!("SafeLogging {}\n", if config.privacy.log_scrub { 1 } else { 0 }));
+    if bridges {
+        torrc.push_str("UseBridges 1\n");
+        for bridge in &tor.bridges {
+            torrc.push_str(&format!("Bridge {}\n", bridge));
+        }
+        if let Some(plugin) = &tor.transport_plugin {
+            torrc.push_str(&format!("ClientTransportPlugin {}\n", plugin));
         }
     }
-    
-    // Always add DISPLAY for X11 fallback
-    env_vars.push(("DISPLAY", x11_display.clone()));
-    
-    // Also need DBUS for notifications on most systems
-    let dbus_addr = format!("unix:path={}/bus", xdg_runtime);
-    env_vars.push(("DBUS_SESSION_BUS_ADDRESS", dbus_addr));
-    
-    // Run notify-send as the original user with proper environment
-    let mut cmd = Command::new("sudo");
-    cmd.arg("-u").arg(&sudo_user);
-    
-    // Set environment variables
-    for (key, val) in &env_vars {
-        cmd.arg(format!("{}={}", key, val));
+    if tor.sandbox {
+        torrc.push_str("Sandbox 1\n");
     }
-    
-    cmd.arg("notify-send")
-        .args(["-u", urgency, "-a", "HULIOS", "-i", "network-vpn", title, body]);
-    
-    let _ = cmd.status();
+    if tor.no_exec {
+        torrc.push_str("NoExec 1\n");
+    }
+    let mut exclude_exits = None;
+    for (name, value) in &tor.options {
+        let value = setconf::check(name, value)?;
+        if name.eq_ignore_ascii_case("ExcludeExitNodes") {
+            exclude_exits = Some(value);
+            continue;
+        }
+        torrc.push_str(&format!("{} {}\n", name, value));
+    }
+    // Exits reported with `hulios badexit` stay excluded across reloads
+    // and restarts of the session
+    let bad_exits = state::load().filter(|_| session).map(|session| session.bad_exits).unwrap_or_default();
+    if let Some(nodes) = circuits::excluded_exits(exclude_exits.as_deref(), &bad_exits) {
+        torrc.push_str(&format!("ExcludeExitNodes {}\n", nodes));
+    }
+    for (option, value) in onion::torrc_options(config) {
+        torrc.push_str(&format!("{} {}\n", option, value));
+    }
+    // Listeners of apps started with `hulios run --isolated`, so they
+    // survive a reload or restart
+    for app in if session { isolate::active() } else { Vec::new() } {
+        for (option, value) in app.listeners(config) {
+            torrc.push_str(&format!("{} {}\n", option, value));
+        }
+    }
+
+    Ok(torrc)
 }
 
-/// Get the UID of a user by name
-fn get_user_uid(username: &str) -> Option<u32> {
-    let output = Command::new("id")
-        .args(["-u", username])
-        .output()
-        .ok()?;
-    
-    String::from_utf8_lossy(&output.stdout)
-        .trim()
-        .parse()
-        .ok()
+/// How the control port is authenticated: with the session's password,
+/// or outside a session with the cookie in the data directory, which
+/// HULIOS falls back to when it has no password
+fn control_auth(session: bool) -> Result<String> {
+    if session {
+        Ok(format!("HashedControlPassword {}", control::hashed_password()?))
+    } else {
+        Ok("CookieAuthentication 1".to_string())
+    }
 }
 
-/// Find the Wayland display socket in XDG_RUNTIME_DIR
-fn find_wayland_display(xdg_runtime: &str) -> Option<String> {
-    // Check for common Wayland socket names
-    let candidates = ["wayland-0", "wayland-1", "wayland-2"];
-    
-    for candidate in candidates {
-        let path = format!("{}/{}", xdg_runtime, candidate);
-        if std::path::Path::new(&path).exists() {
-            return Some(candidate.to_string());
+/// Normalize a bandwidth like "2MB" or "512 KBytes" into Tor's "2 MBytes"
+pub fn parse_bandwidth(value: &str) -> Result<String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse()
+        .with_context(|| i18n::tr("engine-invalid-bandwidth-expected-e", &[("rate", value)]))?;
+
+    let unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" | "bytes" => "Bytes",
+        "k" | "kb" | "kbytes" => "KBytes",
+        "m" | "mb" | "mbytes" => "MBytes",
+        "g" | "gb" | "gbytes" => "GBytes",
+        other => anyhow::bail!(i18n::tr("engine-unknown-bandwidth-unit-use", &[("unit", other)])),
+    };
+    Ok(format!("{} {}", amount, unit))
+}
+
+// =============================================================================
+// Tor Process
+// =============================================================================
+
+/// Find the tor binary without trusting PATH, and refuse to run it as root
+/// unless it (and its directory) can only be modified by root
+pub fn resolve_tor_binary(tor: &TorConfig) -> Result<String> {
+    let path = match &tor.binary {
+        Some(path) => {
+            if !path.starts_with('/') {
+                anyhow::bail!(i18n::tr("engine-tor-binary-must-absolute", &[("path", path)]));
+            }
+            path.clone()
+        }
+        None => TOR_BINARY_CANDIDATES
+            .iter()
+            .find(|candidate| std::path::Path::new(candidate).is_file())
+            .map(|candidate| candidate.to_string())
+            .with_context(|| i18n::tr("engine-tor-not-found", &[("candidates", &TOR_BINARY_CANDIDATES.join(", "))]))?,
+    };
+
+    let binary = std::path::Path::new(&path);
+    let meta = fs::metadata(binary).with_context(|| i18n::tr("error-stat", &[("path", &path)]))?;
+    if !meta.is_file() || meta.mode() & 0o111 == 0 {
+        anyhow::bail!(i18n::tr("engine-not-executable-file", &[("path", &path)]));
+    }
+    check_root_owned(binary)?;
+    if let Some(parent) = binary.parent() {
+        check_root_owned(parent)?;
+    }
+
+    if let Some(expected) = &tor.binary_sha256 {
+        let actual: String = Sha256::digest(fs::read(binary)?)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            anyhow::bail!(i18n::tr("engine-sha-256-mismatch-expected", &[("path", &path), ("expected", expected.trim()), ("actual", &actual)]));
+        }
+    }
+
+    Ok(path)
+}
+
+/// Fail unless the path is owned by root and not group/world writable
+fn check_root_owned(path: &std::path::Path) -> Result<()> {
+    let meta = fs::metadata(path).with_context(|| i18n::tr("error-stat", &[("path", &path.display().to_string())]))?;
+    if meta.uid() != 0 {
+        anyhow::bail!(i18n::tr("engine-not-owned-root-refusing", &[("path", &path.display().to_string())]));
+    }
+    if meta.mode() & 0o022 != 0 {
+        anyhow::bail!(i18n::tr("engine-group-world-writable-refusing", &[("path", &path.display().to_string())]));
+    }
+    Ok(())
+}
+
+/// Have the HULIOS daemon spawn Tor as its child, so it can wait on it
+/// rather than poll a PID
+fn spawn_tor() -> Result<u32> {
+    daemon::spawn()?;
+    let launched: ipc::Launched = ipc::request(&ipc::Request::LaunchTor)
+        .with_context(|| i18n::tr("engine-daemon-could-not-start", &[]))?;
+    Ok(launched.pid)
+}
+
+/// The command running the HULIOS-managed Tor in the foreground; the
+/// binary is resolved and checked like for every other launch
+pub fn tor_command(config: &Config) -> Result<Command> {
+    let mut command = Command::new(resolve_tor_binary(&config.tor)?);
+    command
+        .args(["-f", TORRC_PATH])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    Ok(command)
+}
+
+/// Spawn Tor as a transient systemd service, confined at least as tightly
+/// as the distribution's tor.service
+fn spawn_tor_confined(tor_binary: &str, tproxy: bool, tor: &TorConfig) -> Result<u32> {
+    // Bind targets must exist before the unit's mount namespace is built
+    fs::OpenOptions::new().create(true).append(true).open(TOR_LOG)?;
+
+    let mut properties = vec![
+        "NoNewPrivileges=yes".to_string(),
+        "PrivateTmp=yes".to_string(),
+        "PrivateDevices=yes".to_string(),
+        "ProtectSystem=strict".to_string(),
+        "ProtectHome=yes".to_string(),
+        "ProtectKernelTunables=yes".to_string(),
+        "ProtectKernelModules=yes".to_string(),
+        "ProtectControlGroups=yes".to_string(),
+        format!("CapabilityBoundingSet=CAP_SETUID CAP_SETGID CAP_NET_BIND_SERVICE CAP_DAC_READ_SEARCH CAP_CHOWN CAP_FOWNER{}",
+            // IP_TRANSPARENT on the TransPort socket
+            if tproxy { " CAP_NET_ADMIN" } else { "" }),
+        format!("BindPaths={} {}", TOR_DATA_DIR, TOR_LOG),
+        format!("BindReadOnlyPaths={}", TORRC_PATH),
+    ];
+    let limits = cgroup::Limits::from_config(tor);
+    if !limits.is_empty() {
+        properties.extend(limits.properties());
+    }
+    properties.extend(Scheduling::from_config(tor).properties());
+
+    let mut cmd = Command::new("systemd-run");
+    cmd.args(["--unit", TOR_UNIT, "--collect", "--quiet"]);
+    for property in &properties {
+        cmd.arg(format!("--property={}", property));
+    }
+    let status = cmd
+        .args([tor_binary, "-f", TORRC_PATH])
+        .run()
+        .with_context(|| i18n::tr("engine-failed-run-systemd-run", &[]))?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("engine-systemd-run-failed-start", &[]));
+    }
+
+    let output = Command::new("systemctl")
+        .args(["show", "--property", "MainPID", "--value", TOR_UNIT])
+        .run_output()
+        .with_context(|| i18n::tr("engine-failed-query-tor-unit", &[]))?;
+    let pid: u32 = String::from_utf8_lossy(&output.stdout).trim().parse().unwrap_or(0);
+    if pid == 0 {
+        anyhow::bail!(i18n::tr("engine-confined-tor-unit-exited", &[("path", TOR_LOG)]));
+    }
+    Ok(pid)
+}
+
+/// Show a live progress bar driven by Tor's bootstrap phases until 100%,
+/// failing with the exact phase Tor was stuck in; returns how long it took.
+/// Tor's exit, its progress and the deadline are awaited together, so a
+/// Tor that dies is noticed at once rather than at the next progress check.
+async fn wait_for_bootstrap(pid: Option<u32>, timeout: Duration) -> Result<Duration> {
+    let template = format!("[*] {} [{{bar:30}}] {{pos:>3}}% {{msg}}", i18n::tr("engine-bootstrapping-bar", &[]));
+    let bar = output::progress_bar(100, &template);
+
+    let started = std::time::Instant::now();
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    let mut ticks = tokio::time::interval(Duration::from_millis(250));
+    // Readable once Tor exits; without one, liveness is polled with progress
+    let pidfd = pid
+        .and_then(|pid| procfs::pidfd(pid as i32).ok())
+        .and_then(|pidfd| AsyncFd::new(pidfd).ok());
+    let mut last = (0u8, "Starting".to_string());
+    loop {
+        let exited = async {
+            match &pidfd {
+                Some(pidfd) => drop(pidfd.readable().await),
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = ticks.tick() => {
+                if let Some(progress) = bootstrap_progress() {
+                    if output::plain() && progress != last {
+                        println!("[*] {}", i18n::tr("engine-bootstrapping", &[("progress", &format!("{:>3}", progress.0)), ("summary", &progress.1)]));
+                    }
+                    last = progress;
+                }
+                bar.set_position(last.0 as u64);
+                bar.set_message(last.1.clone());
+
+                if last.0 >= 100 {
+                    bar.finish_and_clear();
+                    println!("[+] {}", i18n::tr("engine-tor-bootstrapped", &[("seconds", &started.elapsed().as_secs().to_string())]));
+                    report::action(format!("Tor bootstrapped in {}s", started.elapsed().as_secs()));
+                    return Ok(started.elapsed());
+                }
+                if pidfd.is_none() && !is_tor_running() {
+                    bar.abandon();
+                    anyhow::bail!(i18n::tr("engine-tor-process-died-during", &[("progress", &last.0.to_string()), ("summary", &last.1), ("path", TOR_LOG)]));
+                }
+            }
+            _ = exited => {
+                bar.abandon();
+                anyhow::bail!(i18n::tr("engine-tor-process-died-during", &[("progress", &last.0.to_string()), ("summary", &last.1), ("path", TOR_LOG)]));
+            }
+            _ = &mut deadline => {
+                bar.abandon();
+                anyhow::bail!(i18n::tr("engine-tor-bootstrap-timed-out", &[("seconds", &timeout.as_secs().to_string()), ("progress", &last.0.to_string()), ("summary", &last.1), ("path", TOR_LOG)]));
+            }
         }
     }
-    
-    // Also check WAYLAND_DISPLAY from current environment
-    std::env::var("WAYLAND_DISPLAY").ok()
+}
+
+// =============================================================================
+// Tor Monitoring
+// =============================================================================
+
+/// PID of the Tor process spawned by HULIOS, as recorded in the session
+pub fn tor_pid() -> Option<u32> {
+    state::load()?.tor_pid
+}
+
+/// The cgroup holding HULIOS's Tor and nothing else, as recorded in the session
+pub fn tor_cgroup() -> Option<String> {
+    state::load()?.tor_cgroup
+}
+
+/// Whether the session's Tor takes TCP diverted by TPROXY rather than
+/// NAT-redirected
+pub fn tproxy_active() -> bool {
+    fs::read_to_string(TORRC_PATH).is_ok_and(|torrc| torrc.lines().any(|line| line == "TransProxyType TPROXY"))
+}
+
+/// Last bootstrap percentage and phase summary reported in the Tor notice
+/// log, e.g. `Bootstrapped 14% (handshake): Handshaking with a relay`
+pub fn bootstrap_progress() -> Option<(u8, String)> {
+    bootstrap_progress_in(TOR_LOG)
+}
+
+/// The same, from the notice log of another Tor (the relay)
+pub fn bootstrap_progress_in(log: &str) -> Option<(u8, String)> {
+    let log = fs::read_to_string(log).ok()?;
+    log.lines()
+        .rev()
+        .find_map(|line| {
+            let rest = line.split("Bootstrapped ").nth(1)?;
+            let (pct, phase) = rest.split_once('%')?;
+            let summary = phase.split_once(": ").map(|(_, s)| s).unwrap_or("").trim();
+            Some((pct.trim().parse().ok()?, summary.to_string()))
+        })
+}
+
+/// Check whether /etc/resolv.conf is the HULIOS-managed version
+pub fn dns_owned() -> bool {
+    fs::read_to_string(RESOLV_PATH)
+        .map(|content| content.starts_with("# HULIOS"))
+        .unwrap_or(false)
+}
+
+/// Whether every nameserver in /etc/resolv.conf is 127.0.0.1 (the DNS
+/// redirect target), whatever wrote the file
+pub fn dns_points_to_tor() -> bool {
+    let content = fs::read_to_string(RESOLV_PATH).unwrap_or_default();
+    let servers: Vec<&str> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .map(str::trim)
+        .collect();
+    !servers.is_empty() && servers.iter().all(|server| *server == "127.0.0.1")
+}
+
+/// Check if Tor process is running
+pub fn is_tor_running() -> bool {
+    if let Some(pid) = tor_pid() {
+        // Guard against PID reuse: the recorded PID must still be our tor
+        return procfs::inspect(pid as i32).is_some_and(|info| is_hulios_tor(&info))
+            && procfs::is_alive(pid as i32);
+    }
+
+    // Fallback: any tor started from the HULIOS torrc
+    !find_hulios_tor().is_empty()
+}
+
+/// Tor Browser's own tor is never stopped, but once the firewall is up its
+/// guard connections are redirected into HULIOS's Tor: Tor over Tor
+fn check_tor_browser(config: &Config) -> Result<()> {
+    let Some(browser) = torbrowser::detect() else {
+        return Ok(());
+    };
+    if config.tor.browser == TorBrowserMode::Refuse {
+        anyhow::bail!(i18n::tr("engine-tor-browser-refused", &[("pid", &browser.pid.to_string()), ("user", &browser.user)]));
+    }
+    println!("[!] {}", i18n::tr("engine-tor-browser-warning", &[("pid", &browser.pid.to_string()), ("user", &browser.user)]));
+    println!("    {}", i18n::tr("engine-tor-browser-instead", &[]));
+    report::warning(format!("Tor Browser is running (PID {}); its traffic goes Tor over Tor", browser.pid));
+    Ok(())
+}
+
+/// Tor processes started from the HULIOS torrc, never anyone else's tor
+fn find_hulios_tor() -> Vec<procfs::ProcessInfo> {
+    procfs::find_by_name("tor")
+        .into_iter()
+        .filter(is_hulios_tor)
+        .collect()
+}
+
+/// Our tor runs our torrc, as root (before dropping privileges) or the tor user
+pub fn is_hulios_tor(info: &procfs::ProcessInfo) -> bool {
+    let tor_uid = users::get_user_by_name(TOR_USER).map(|user| user.uid());
+    info.comm == "tor"
+        && info.cmdline.iter().any(|arg| arg == TORRC_PATH)
+        && (info.uid == 0 || Some(info.uid) == tor_uid)
 }
 
 // =============================================================================
@@ -286,69 +1310,134 @@ fn find_wayland_display(xdg_runtime: &str) -> Option<String> {
 // =============================================================================
 
 /// Aggressively neutralize system resolver - treat as hostile
-fn neutralize_system_resolver() -> Result<()> {
-    println!("[*] Neutralizing system resolver (treating as hostile)...");
-    
-    // MASK the service (stronger than disable)
-    let _ = Command::new("systemctl")
-        .args(["mask", "systemd-resolved"])
-        .status();
-    
-    let _ = Command::new("systemctl")
-        .args(["stop", "systemd-resolved"])
-        .status();
-    
-    let _ = Command::new("killall")
-        .args(["systemd-resolved"])
-        .status();
-    
-    let _ = Command::new("systemctl")
-        .args(["stop", "NetworkManager-dispatcher"])
-        .status();
-    
-    let _ = Command::new("systemctl")
-        .args(["stop", "dnsmasq"])
-        .status();
-    
-    let _ = Command::new("systemctl")
-        .args(["mask", "dnsmasq"])
-        .status();
+pub fn neutralize_system_resolver() -> Result<()> {
+    println!("[*] {}", i18n::tr("engine-neutralizing-system-resolver-treating", &[]));
     
+    let networkd = networkd_managed();
+    run_parallel(&[
+        ("engine-step-neutralizing-systemd-resolved", &|| {
+            if networkd {
+                // networkd re-asserts the stub listener and per-link DNS, so masking
+                // resolved fights the network stack; constrain it to Tor instead
+                return constrain_resolved();
+            }
+            // MASK the service (stronger than disable)
+            let _ = Command::new("systemctl").args(["mask", "systemd-resolved"]).run_timeout(STEP_TIMEOUT);
+            let _ = Command::new("systemctl").args(["stop", "systemd-resolved"]).run_timeout(STEP_TIMEOUT);
+            let _ = Command::new("killall").args(["systemd-resolved"]).run_timeout(STEP_TIMEOUT);
+            Ok(())
+        }),
+        ("engine-step-stopping-networkmanager-dispatcher", &|| {
+            let _ = Command::new("systemctl").args(["stop", "NetworkManager-dispatcher"]).run_timeout(STEP_TIMEOUT);
+            Ok(())
+        }),
+        ("engine-step-masking-dnsmasq", &|| {
+            let _ = Command::new("systemctl").args(["stop", "dnsmasq"]).run_timeout(STEP_TIMEOUT);
+            let _ = Command::new("systemctl").args(["mask", "dnsmasq"]).run_timeout(STEP_TIMEOUT);
+            Ok(())
+        }),
+        // nss-resolve, nscd and a local unbound sit between apps and resolv.conf
+        ("engine-step-adapting-local-resolvers", &|| {
+            resolvers::adapt(!networkd);
+            Ok(())
+        }),
+    ])
+}
+
+/// Run independent steps, named by catalog id, on their own threads and
+/// wait for all of them. A failing step doesn't stop the others; the first
+/// failure is returned.
+fn run_parallel(steps: &[(&str, &(dyn Fn() -> Result<()> + Sync))]) -> Result<()> {
+    let results: Vec<Result<()>> = thread::scope(|scope| {
+        let handles: Vec<_> = steps.iter().map(|(_, step)| scope.spawn(step)).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow::anyhow!("panicked"))))
+            .collect()
+    });
+    let mut failure = None;
+    for ((id, _), result) in steps.iter().zip(results) {
+        if let Err(e) = result {
+            let name = i18n::tr(id, &[]);
+            eprintln!("[!] {}", i18n::tr("engine-step-failed", &[("name", &name), ("error", &format!("{:#}", e))]));
+            failure.get_or_insert(e.context(i18n::tr("engine-step-failed-short", &[("name", &name)])));
+        }
+    }
+    failure.map_or(Ok(()), Err)
+}
+
+/// systemd-resolved kept running on a systemd-networkd machine
+fn networkd_managed() -> bool {
+    unit_is_active("systemd-networkd") && unit_is_active("systemd-resolved")
+}
+
+/// Point resolved's global DNS at the Tor DNSPort for every domain and turn
+/// off everything that could bypass it. Per-link servers from networkd are
+/// still queried on port 53, which the firewall redirects to Tor.
+fn constrain_resolved() -> Result<()> {
+    let dropin = std::path::Path::new(RESOLVED_DROPIN);
+    if let Some(parent) = dropin.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    audit::record("write", RESOLVED_DROPIN);
+    fs::write(dropin, RESOLVED_CONSTRAINTS).with_context(|| i18n::tr("error-write", &[("path", RESOLVED_DROPIN)]))?;
+
+    let status = Command::new("systemctl")
+        .args(["restart", "systemd-resolved"])
+        .run()?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("engine-systemd-resolved-failed-restart", &[("path", RESOLVED_DROPIN)]));
+    }
+    println!("[+] {}", i18n::tr("engine-systemd-resolved-constrained-tor", &[]));
+    report::action("systemd-resolved constrained to the Tor DNSPort");
     Ok(())
 }
 
-/// Restore systemd-resolved
-fn restore_system_resolver() -> Result<()> {
-    println!("[*] Restoring system resolver...");
+pub fn restore_system_resolver() -> Result<()> {
+    println!("[*] {}", i18n::tr("engine-restoring-system-resolver", &[]));
+    report::action("System resolver restored");
     
-    let _ = Command::new("systemctl")
-        .args(["unmask", "systemd-resolved"])
-        .status();
+    if fs::remove_file(RESOLVED_DROPIN).is_ok() {
+        audit::record("remove", RESOLVED_DROPIN);
+        // Only the drop-in changed; resolved picks its own config back up
+        let _ = Command::new("systemctl")
+            .args(["restart", "systemd-resolved"])
+            .run();
+    } else {
+        let _ = Command::new("systemctl")
+            .args(["unmask", "systemd-resolved"])
+            .run();
+        
+        let _ = Command::new("systemctl")
+            .args(["start", "systemd-resolved"])
+            .run();
+    }
     
     let _ = Command::new("systemctl")
         .args(["unmask", "dnsmasq"])
-        .status();
-    
-    let _ = Command::new("systemctl")
-        .args(["start", "systemd-resolved"])
-        .status();
+        .run();
     
     let _ = Command::new("systemctl")
         .args(["start", "NetworkManager-dispatcher"])
-        .status();
+        .run();
+    
+    resolvers::restore();
     
     Ok(())
 }
 
-/// Take ownership of DNS by replacing /etc/resolv.conf
-fn take_dns_ownership() -> Result<()> {
-    println!("[*] Taking DNS ownership...");
+/// Take ownership of DNS by replacing /etc/resolv.conf with `content`
+pub fn take_dns_ownership(content: &str) -> Result<()> {
+    println!("[*] {}", i18n::tr("engine-taking-dns-ownership", &[]));
     
     let _ = Command::new("chattr")
         .args(["-i", RESOLV_PATH])
-        .status();
+        .run();
     
     if fs::metadata(RESOLV_BACKUP).is_err() {
+        if let Some(parent) = std::path::Path::new(RESOLV_BACKUP).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
         let sources = [
             "/run/systemd/resolve/resolv.conf",
             "/run/NetworkManager/resolv.conf",
@@ -357,55 +1446,61 @@ fn take_dns_ownership() -> Result<()> {
         
         for src in sources {
             if fs::metadata(src).is_ok() {
-                let _ = Command::new("cp")
-                    .args(["-L", src, RESOLV_BACKUP])
-                    .status();
+                // fs::copy follows symlinks, like cp -L
+                let _ = fs::copy(src, RESOLV_BACKUP);
                 break;
             }
         }
     }
     
+    write_resolv(content)
+}
+
+/// (Re)write the resolv.conf of the current mode (Tor or dns-only) and
+/// make it immutable
+pub fn reassert_dns() -> Result<()> {
+    if dnsonly::is_active() {
+        return write_resolv(dnsonly::RESOLV);
+    }
+    write_resolv(TOR_RESOLV)
+}
+
+/// Replace resolv.conf with `content` and make it immutable
+pub fn write_resolv(content: &str) -> Result<()> {
+    let _ = Command::new("chattr")
+        .args(["-i", RESOLV_PATH])
+        .run();
     let _ = fs::remove_file(RESOLV_PATH);
-    
-    let resolv_content = r#"# HULIOS - Tor DNS
-# DO NOT MODIFY - This file is managed by HULIOS
-# All DNS queries are routed through Tor
-nameserver 127.0.0.1
-options edns0 trust-ad ndots:0
-"#;
-    
-    fs::write(RESOLV_PATH, resolv_content)
-        .context("Failed to write resolv.conf")?;
-    
+
+    audit::record("write", RESOLV_PATH);
+    fs::write(RESOLV_PATH, content)
+        .with_context(|| i18n::tr("engine-failed-write-resolv-conf", &[]))?;
+
     let _ = Command::new("chattr")
         .args(["+i", RESOLV_PATH])
-        .status();
-    
-    println!("[+] DNS now points to localhost (Tor DNSPort)");
+        .run();
     Ok(())
 }
 
 /// Restore original DNS configuration
-fn restore_dns() -> Result<()> {
-    println!("[*] Restoring DNS configuration...");
+pub fn restore_dns() -> Result<()> {
+    println!("[*] {}", i18n::tr("engine-restoring-dns-configuration", &[]));
+    report::action("DNS configuration restored");
     
     let _ = Command::new("chattr")
         .args(["-i", RESOLV_PATH])
-        .status();
+        .run();
     
+    audit::record("restore", RESOLV_PATH);
     if fs::metadata(RESOLV_BACKUP).is_ok() {
         let _ = fs::remove_file(RESOLV_PATH);
         let _ = fs::copy(RESOLV_BACKUP, RESOLV_PATH);
         let _ = fs::remove_file(RESOLV_BACKUP);
     } else {
         let _ = fs::remove_file(RESOLV_PATH);
-        let _ = Command::new("ln")
-            .args(["-sf", "/run/systemd/resolve/stub-resolv.conf", RESOLV_PATH])
-            .status();
+        let _ = std::os::unix::fs::symlink("/run/systemd/resolve/stub-resolv.conf", RESOLV_PATH);
     }
     
-    let _ = fs::remove_file(TOR_PID_FILE);
-    
     Ok(())
 }
 
@@ -413,17 +1508,111 @@ fn restore_dns() -> Result<()> {
 // Helper Functions
 // =============================================================================
 
-fn stop_tor_service() -> Result<()> {
-    let _ = Command::new("systemctl").args(["stop", "tor"]).status();
-    let _ = Command::new("killall").args(["tor"]).status();
-    let _ = fs::remove_file(TOR_PID_FILE);
+/// HTTP client that always goes through Tor's SOCKSPort, so downloads
+/// never leave over the clearnet even when the firewall rules are absent
+pub fn tor_http_client(timeout: Duration) -> Result<reqwest::blocking::Client> {
+    let client = reqwest::blocking::Client::builder()
+        .proxy(reqwest::Proxy::all(TOR_SOCKS)?)
+        .timeout(timeout)
+        .build()?;
+    Ok(client)
+}
+
+/// Stop the Tor HULIOS started: the confined unit, the session's cgroup,
+/// the PID recorded in the session and any tor running HULIOS's torrc.
+/// Other tor processes (the system tor.service, Tor Browser, another
+/// user's tor) are left alone.
+pub fn stop_tor_service() -> Result<()> {
+    // Waits until the units are gone, so the next spawn can reuse their names
+    for unit in [TOR_UNIT, cgroup::SCOPE] {
+        let _ = Command::new("systemctl")
+            .args(["stop", unit])
+            .stderr(std::process::Stdio::null())
+            .run_timeout(STEP_TIMEOUT);
+    }
+    if let Some(group) = tor_cgroup() {
+        cgroup::stop(&group, Duration::from_secs(5));
+    }
+
+    let mut pids: Vec<i32> = find_hulios_tor().iter().map(|info| info.pid).collect();
+    if let Some(pid) = tor_pid() {
+        if procfs::inspect(pid as i32).is_some_and(|info| is_hulios_tor(&info)) && !pids.contains(&(pid as i32)) {
+            pids.push(pid as i32);
+        }
+    }
+    for pid in pids {
+        procfs::terminate(pid, Duration::from_secs(5));
+    }
+    // Tor writes its state on the way out
+    if let Err(e) = tordata::save(&Config::load().unwrap_or_default()) {
+        eprintln!("[!] {}", i18n::tr("engine-could-not-keep-tor", &[("error", &format!("{:#}", e))]));
+    }
     Ok(())
 }
 
-fn enable_route_localnet() -> Result<()> {
+pub fn stop_system_tor() {
+    println!("[*] {}", i18n::tr("engine-stopping-system-tor-service", &[]));
+    let _ = Command::new("systemctl").args(["stop", "tor"]).run_timeout(STEP_TIMEOUT);
+    report::action("System tor.service stopped");
+}
+
+/// A running tor.service is left alone unless it holds one of the ports
+/// HULIOS's Tor needs, which would only fail the bootstrap later
+fn check_system_tor() -> Result<()> {
+    if !unit_is_active("tor") {
+        return Ok(());
+    }
+    let busy = [9050, 9051, 9052]
+        .into_iter()
+        .find(|port| std::net::TcpListener::bind(("127.0.0.1", *port)).is_err())
+        .or_else(|| std::net::UdpSocket::bind(("127.0.0.1", 9061)).is_err().then_some(9061));
+    if let Some(port) = busy {
+        anyhow::bail!(i18n::tr("engine-system-tor-service-running", &[("port", &port.to_string())]));
+    }
+    println!("[*] {}", i18n::tr("engine-system-tor-service-keeps", &[]));
+    Ok(())
+}
+
+pub fn unit_is_active(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-active", "--quiet", unit])
+        .run()
+        .is_ok_and(|status| status.success())
+}
+
+fn unit_is_masked(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-enabled", unit])
+        .run_output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim() == "masked")
+        .unwrap_or(false)
+}
+
+pub fn read_sysctl(key: &str) -> Option<String> {
+    let path = format!("/proc/sys/{}", key.replace('.', "/"));
+    fs::read_to_string(path).ok().map(|value| value.trim().to_string())
+}
+
+pub fn write_sysctl(key: &str, value: &str) {
+    let path = format!("/proc/sys/{}", key.replace('.', "/"));
+    audit::record("sysctl", &format!("{}={}", key, value));
+    let _ = fs::write(path, value);
+}
+
+pub fn enable_route_localnet() -> Result<()> {
     let _ = Command::new("sysctl")
         .args(["-w", "net.ipv4.conf.all.route_localnet=1"])
         .stdout(std::process::Stdio::null())
-        .status();
+        .run_timeout(STEP_TIMEOUT);
     Ok(())
 }
+
+/// Stop any existing tor, neutralize the system resolver and enable
+/// route_localnet (for DNS redirection) at once: none depends on another
+pub fn prepare_system() -> Result<()> {
+    run_parallel(&[
+        ("engine-step-stopping-tor", &stop_tor_service),
+        ("engine-step-neutralizing-system-resolver", &neutralize_system_resolver),
+        ("engine-step-enabling-route-localnet", &enable_route_localnet),
+    ])
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::audit;
+use crate::i18n;
+
+/// 0: child stdout discarded, 1 (-v): child output shown,
+/// 2 (-vv): also trace every command with its exit status
+static VERBOSITY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Traced (and, when they change the system, audited) replacements for
+/// `status()` and `output()`. Every external command HULIOS runs goes through these.
+pub trait Run {
+    /// Like `status()`; the child's stdout is discarded unless -v is given
+    fn run(&mut self) -> io::Result<ExitStatus>;
+    /// Like `output()`
+    fn run_output(&mut self) -> io::Result<Output>;
+    /// Like `run()`, but the child is killed and TimedOut returned once
+    /// `timeout` has passed
+    fn run_timeout(&mut self, timeout: Duration) -> io::Result<ExitStatus>;
+}
+
+impl Run for Command {
+    fn run(&mut self) -> io::Result<ExitStatus> {
+        audit::command(self);
+        if verbosity() == 0 {
+            self.stdout(Stdio::null());
+        }
+        let result = self.status();
+        trace(self, result.as_ref().map(describe_status));
+        result
+    }
+
+    fn run_output(&mut self) -> io::Result<Output> {
+        audit::command(self);
+        let result = self.output();
+        trace(self, result.as_ref().map(|out| describe_status(&out.status)));
+        result
+    }
+
+    fn run_timeout(&mut self, timeout: Duration) -> io::Result<ExitStatus> {
+        audit::command(self);
+        if verbosity() == 0 {
+            self.stdout(Stdio::null());
+        }
+        let result = self.spawn().and_then(|mut child| wait_timeout(&mut child, timeout));
+        trace(self, result.as_ref().map(describe_status));
+        result
+    }
+}
+
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(io::ErrorKind::TimedOut, i18n::tr("exec-timed-out-after", &[("seconds", &timeout.as_secs().to_string())])));
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn trace(cmd: &Command, outcome: Result<String, &io::Error>) {
+    if verbosity() < 2 {
+        return;
+    }
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    match outcome {
+        Ok(outcome) => eprintln!("[cmd] {} -> {}", line, outcome),
+        Err(e) => eprintln!("[cmd] {} -> failed to run: {}", line, e),
+    }
+}
+
+fn describe_status(status: &ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exit {}", code),
+        None => "killed by signal".to_string(),
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{Result, Context};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use crate::config::StatusConfig;
+use crate::{engine, i18n, report};
+
+/// Onionoo relay details, as returned for `flag=Exit` queries
+#[derive(Deserialize)]
+struct OnionooDetails {
+    relays: Vec<OnionooRelay>,
+}
+
+#[derive(Deserialize)]
+struct OnionooRelay {
+    #[serde(default)]
+    or_addresses: Vec<String>,
+    #[serde(default)]
+    exit_addresses: Vec<String>,
+}
+
+/// Load the cached exit list, refreshing it through Tor first when it is
+/// missing or older than `exit_list_max_age`. A stale copy is still used if
+/// the refresh fails.
+pub fn load(config: &StatusConfig) -> Result<Vec<String>> {
+    if is_stale(config) {
+        if let Err(e) = refresh(config) {
+            if !Path::new(&config.exit_list).exists() {
+                return Err(e);
+            }
+            eprintln!("[!] {}", i18n::tr("exitlist-using-stale-exit-list", &[("error", &format!("{:#}", e))]));
+            report::warning(format!("Using stale exit list: {:#}", e));
+        }
+    }
+
+    let content = fs::read_to_string(&config.exit_list)
+        .with_context(|| i18n::tr("error-read", &[("path", &config.exit_list)]))?;
+    Ok(content.lines().map(str::to_string).collect())
+}
+
+/// Whether the cached list is missing or older than the configured max age
+pub fn is_stale(config: &StatusConfig) -> bool {
+    let modified = fs::metadata(&config.exit_list).and_then(|m| m.modified());
+    match modified {
+        Ok(modified) => {
+            let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+            age > Duration::from_secs(config.exit_list_max_age)
+        }
+        Err(_) => true,
+    }
+}
+
+/// Download the exit address list via Tor and replace the cached copy.
+/// Returns the number of addresses stored.
+pub fn refresh(config: &StatusConfig) -> Result<usize> {
+    let client = engine::tor_http_client(Duration::from_secs(60))?;
+
+    let body = client.get(&config.exit_list_url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.text())
+        .with_context(|| i18n::tr("exitlist-failed-download-tor-exit", &[]))?;
+
+    let addresses = parse(&body)?;
+    if addresses.is_empty() {
+        anyhow::bail!(i18n::tr("exitlist-downloaded-exit-list-empty", &[]));
+    }
+
+    if let Some(parent) = Path::new(&config.exit_list).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write then rename so readers never see a half-written list
+    let tmp = format!("{}.tmp", config.exit_list);
+    fs::write(&tmp, addresses.join("\n") + "\n")?;
+    fs::rename(&tmp, &config.exit_list)
+        .with_context(|| i18n::tr("exitlist-failed-update", &[("exit_list", &config.exit_list)]))?;
+
+    Ok(addresses.len())
+}
+
+/// Accept either Onionoo JSON or a plain one-address-per-line list
+fn parse(body: &str) -> Result<Vec<String>> {
+    let mut addresses: Vec<String> = if body.trim_start().starts_with('{') {
+        let details: OnionooDetails = serde_json::from_str(body)
+            .with_context(|| i18n::tr("exitlist-failed-parse-onionoo-response", &[]))?;
+        details.relays
+            .into_iter()
+            .flat_map(|relay| relay.exit_addresses.into_iter().chain(relay.or_addresses))
+            .map(|addr| strip_port(&addr).to_string())
+            .collect()
+    } else {
+        body.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    };
+
+    addresses.sort();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// "1.2.3.4:9001" -> "1.2.3.4", "[2001:db8::1]:9001" -> "2001:db8::1"
+fn strip_port(addr: &str) -> &str {
+    if let Some(rest) = addr.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match addr.rsplit_once(':') {
+        Some((host, _)) if !host.contains(':') => host,
+        _ => addr,
+    }
+}
@@ -0,0 +1,110 @@
+use serde::Serialize;
+use crate::config::{Config, Redirect};
+use crate::engine::{self, ROUTE_LOCALNET};
+use crate::firewall;
+use crate::i18n;
+use crate::preflight::{Finding, Severity};
+
+/// How the ruleset matches established connections
+#[derive(Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateMatch {
+    /// The backend's usual match (iptables `-m state`, nftables `ct state`)
+    #[default]
+    Native,
+    /// iptables `-m conntrack --ctstate`, when xt_state is missing
+    Conntrack,
+    /// No state matching: the established-connection rules are left out
+    Unavailable,
+}
+
+/// What the kernel proved it supports, by accepting a probe rule of each kind
+#[derive(Serialize, Clone, Copy)]
+pub struct Features {
+    /// Matching the Tor user's packets (xt_owner / meta skuid)
+    pub owner: bool,
+    /// NAT REDIRECT to a local port (xt_REDIRECT / nft redir)
+    pub redirect: bool,
+    /// TPROXY (xt_TPROXY / nft_tproxy), fwmark routing and a policy routing table
+    pub tproxy: bool,
+    pub state_match: StateMatch,
+    /// net.ipv4.conf.all.route_localnet can be enabled
+    pub route_localnet: bool,
+}
+
+/// Insert (and remove) one probe rule per feature with the configured backend.
+/// The probe rules live in a chain or table nothing jumps to, so they never
+/// see a packet.
+pub fn probe(config: &Config) -> Features {
+    let features = firewall::backend(config).probe();
+    Features {
+        tproxy: features.tproxy && firewall::policy_routing_available(),
+        route_localnet: probe_route_localnet(),
+        ..features
+    }
+}
+
+/// The redirection actually used for TCP: `firewall.redirect`, falling back
+/// to NAT where TPROXY is unsupported. None without REDIRECT, which DNS needs
+/// in either mode.
+pub fn redirect(config: &Config, features: &Features) -> Option<Redirect> {
+    if !features.redirect {
+        return None;
+    }
+    match config.firewall.redirect {
+        Redirect::Tproxy if !features.tproxy => Some(Redirect::Nat),
+        mode => Some(mode),
+    }
+}
+
+/// Set route_localnet, read it back and put the previous value back
+fn probe_route_localnet() -> bool {
+    let Some(previous) = engine::read_sysctl(ROUTE_LOCALNET) else {
+        return false;
+    };
+    engine::write_sysctl(ROUTE_LOCALNET, "1");
+    let works = engine::read_sysctl(ROUTE_LOCALNET).as_deref() == Some("1");
+    engine::write_sysctl(ROUTE_LOCALNET, &previous);
+    works
+}
+
+/// The degradation matrix: what HULIOS does without each feature
+pub fn findings(config: &Config, features: &Features) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut add = |severity, problem: &str, guidance: &str| {
+        findings.push(Finding { severity, problem: problem.to_string(), guidance: guidance.to_string() });
+    };
+
+    if !features.owner {
+        add(Severity::Fatal, &i18n::tr("features-owner-rejected", &[]), &i18n::tr("features-owner-guidance", &[]));
+    }
+    match redirect(config, features) {
+        None => add(Severity::Fatal, &i18n::tr("features-redirect-rejected", &[]), &i18n::tr("features-redirect-guidance", &[])),
+        Some(mode) if mode != config.firewall.redirect => add(Severity::Warning,
+            &i18n::tr("features-tproxy-rejected", &[]),
+            &i18n::tr("features-tproxy-guidance", &[("redirect", mode.name())])),
+        Some(_) => {}
+    }
+    match features.state_match {
+        StateMatch::Native => {}
+        StateMatch::Conntrack => add(Severity::Warning,
+            &i18n::tr("features-state-unavailable", &[]), &i18n::tr("features-state-guidance", &[])),
+        StateMatch::Unavailable => add(Severity::Warning,
+            &i18n::tr("features-connection-state-unavailable", &[]), &i18n::tr("features-connection-state-guidance", &[])),
+    }
+    if !features.route_localnet {
+        add(Severity::Warning, &i18n::tr("features-route-localnet-unavailable", &[]), &i18n::tr("features-route-localnet-guidance", &[]));
+    }
+    findings
+}
+
+/// One line per feature for `hulios doctor`
+pub fn summary(features: &Features) -> Vec<(String, bool)> {
+    vec![
+        (i18n::tr("features-owner-match", &[]), features.owner),
+        ("REDIRECT".to_string(), features.redirect),
+        ("TPROXY".to_string(), features.tproxy),
+        (i18n::tr("features-connection-state-match", &[]), features.state_match != StateMatch::Unavailable),
+        ("route_localnet".to_string(), features.route_localnet),
+    ]
+}
@@ -0,0 +1,507 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::process::{Command, Stdio};
+use crate::config::{Backend, Config, FirewallMode, IcmpType, LanService, OtherTraffic, Redirect};
+use crate::exec::Run;
+use crate::features::{self, Features, StateMatch};
+use crate::isolate::App;
+use crate::libvirt::{self, Guests};
+use crate::meshvpn::{self, Mesh};
+use crate::sshguard::SshSession;
+use crate::{doh, i18n, iptables, nftables, overlay, state};
+
+/// A way of programming the kernel firewall for transparent Tor routing.
+/// Every backend implements the same security model (see iptables.rs).
+pub trait FirewallBackend {
+    fn name(&self) -> &'static str;
+
+    /// Install the default-deny, Tor-only ruleset
+    fn apply_rules(&self, tor_user: &str) -> Result<()>;
+
+    /// Remove everything HULIOS installed and restore open policies
+    fn flush_rules(&self) -> Result<()>;
+
+    /// Whether the Tor redirect is installed; None if it can't be determined
+    fn rules_present(&self) -> Option<bool>;
+
+    /// Which essential parts of the installed ruleset are still in place;
+    /// None if they can't be read
+    fn rule_checks(&self) -> Option<RuleChecks>;
+
+    /// Redirect each isolated app's DNS and TCP to its own Tor listeners,
+    /// replacing the previous set; the ruleset must already be applied
+    fn isolate(&self, apps: &[App]) -> Result<()>;
+
+    /// Packets rejected by the blocklist so far; None if unknown
+    fn blocked_packets(&self) -> Option<u64>;
+
+    /// ICMP packets dropped so far (IPv4 and IPv6); None if unknown
+    fn icmp_dropped(&self) -> Option<u64>;
+
+    /// Try one probe rule per feature the ruleset needs (route_localnet is
+    /// probed separately by features::probe)
+    fn probe(&self) -> Features;
+
+    /// Workstation ruleset of `hulios attach-gateway`: nothing leaves but
+    /// through the gateway, and no IPv6 at all
+    fn attach(&self, attachment: &Attachment) -> Result<()>;
+
+    /// Ruleset of `hulios dns-only`: all DNS to the local encrypted
+    /// resolver except its own (user `resolver_uid`, if it needs plaintext
+    /// bootstrap lookups), DNS-over-TLS and IPv6 DNS dropped, and
+    /// everything else left alone
+    fn dns_only(&self, resolver_uid: Option<u32>) -> Result<()>;
+
+    /// The ruleset `apply_rules` would install, as files for the backend's
+    /// own loader (`nft -f`, `iptables-restore`), each with its name; the
+    /// kernel is never touched. Blocked domains are left out, being
+    /// resolved only at start, and so are isolated apps.
+    fn render(&self, tor_user: &str) -> Result<Vec<(&'static str, String)>>;
+}
+
+/// The parts of a live ruleset that protect the session, checked separately
+/// because something can remove one without touching the rest
+pub struct RuleChecks {
+    /// DNS (and in NAT mode TCP) is redirected to Tor
+    pub redirect: bool,
+    /// Outgoing traffic is dropped by default
+    pub kill_switch: bool,
+}
+
+/// Tor's VirtualAddrNetwork: .onion names resolve into this range
+/// (AutomapHostsOnResolve), so it is the only destination in onion-only mode
+pub const ONION_NETWORK: ([u8; 4], u8) = ([10, 66, 0, 0], 16);
+
+/// Names of the overlay networks (overlay.rs, e.g. .i2p) resolve into this
+/// range, whose TCP goes to the overlay router instead of Tor
+pub const OVERLAY_NETWORK: ([u8; 4], u8) = ([10, 67, 0, 0], 16);
+
+/// Where `allow_lan` lets services go: the private IPv4 ranges, link-local
+/// addresses and the broadcast address
+pub const LAN_NETWORKS: [([u8; 4], u8); 5] = [
+    ([10, 0, 0, 0], 8),
+    ([172, 16, 0, 0], 12),
+    ([192, 168, 0, 0], 16),
+    ([169, 254, 0, 0], 16),
+    ([255, 255, 255, 255], 32),
+];
+
+/// mDNS's multicast group, the only place it goes
+const MDNS_GROUP: ([u8; 4], u8) = ([224, 0, 0, 251], 32);
+
+/// Firewall mark on TCP diverted to Tor in TPROXY mode ("HU")
+pub const TPROXY_MARK: u32 = 0x4855;
+
+/// Routing table that sends marked packets back into the local stack
+const TPROXY_TABLE: &str = "18517";
+
+/// Which traffic the ruleset sends through Tor
+#[derive(Default, Clone, Serialize)]
+pub struct Policy {
+    /// TCP destination port ranges to torify; None torifies all TCP
+    pub torify_ports: Option<Vec<(u16, u16)>>,
+    /// Let traffic outside `torify_ports` leave directly instead of dropping it
+    pub allow_other: bool,
+    /// `firewall.block` entries (plus the DoH providers with `block_doh`),
+    /// resolved when the rules are applied
+    pub block: Vec<String>,
+    /// Only redirect TCP to ONION_NETWORK; everything else is dropped
+    pub onion_only: bool,
+    /// Tor's local proxy ports (HTTPTunnelPort) that are never redirected
+    pub proxy_ports: Vec<u16>,
+    /// How established connections are matched, as the kernel probe found
+    pub state_match: StateMatch,
+    /// Divert TCP with TPROXY and policy routing instead of NAT REDIRECT
+    pub tproxy: bool,
+    /// Gateway mode: DNS and TCP arriving from the LAN go to Tor's
+    /// listeners on its address (always by NAT), and nothing is forwarded
+    pub lan: Option<Lan>,
+    /// Overlay networks are enabled: UDP DNS goes to the overlay router,
+    /// which answers their names and passes the rest on to Tor
+    pub overlays: bool,
+    /// The SSH connection HULIOS was started from, which is neither
+    /// redirected nor dropped (`firewall.keep_ssh_session`)
+    pub ssh_session: Option<SshSession>,
+    /// ICMP types let through outside tracked connections; the rest of
+    /// ICMP is dropped and counted (unless other traffic leaves directly)
+    pub icmp_allow: Vec<IcmpType>,
+    /// Services let out directly to LAN_NETWORKS (`allow_lan`)
+    pub lan_services: Vec<LanService>,
+    /// libvirt's bridges, each with what its guests may reach
+    pub guests: Vec<Guests>,
+    /// Guests on bridges that appear after the rules were applied are
+    /// dropped rather than left to libvirt's NAT
+    pub new_guests_dropped: bool,
+    /// Mesh VPNs whose peers, and whose daemon's UDP, leave directly
+    pub mesh: Vec<Mesh>,
+}
+
+/// Packets of a LAN service, which leave directly
+pub struct LanFlow {
+    /// "tcp" or "udp"
+    pub protocol: &'static str,
+    pub ports: (u16, u16),
+    pub network: ([u8; 4], u8),
+}
+
+impl LanFlow {
+    pub fn matches(&self, protocol: &str, destination: SocketAddr) -> bool {
+        let IpAddr::V4(ip) = destination.ip() else {
+            return false;
+        };
+        let (network, prefix) = self.network;
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        protocol == self.protocol
+            && (self.ports.0..=self.ports.1).contains(&destination.port())
+            && u32::from(ip) & mask == u32::from_be_bytes(network) & mask
+    }
+}
+
+/// The LAN of gateway mode
+#[derive(Clone, Serialize)]
+pub struct Lan {
+    /// Interface name, or a prefix ending in "+" (e.g. "vif+")
+    pub interface: String,
+    /// Tor's SOCKSPort is offered to the clients too
+    pub socks: bool,
+    /// Clients may reach nothing on this machine but Tor's ports (and
+    /// the daemon's remote control)
+    pub tor_ports_only: bool,
+    /// Port of `daemon.remote_control`, which the clients reach too
+    pub remote_control: Option<u16>,
+}
+
+impl Lan {
+    /// Ports the clients' packets arrive at once redirected
+    pub fn tor_ports(&self) -> Vec<u16> {
+        if self.socks { vec![9050, 9051, 9061] } else { vec![9051, 9061] }
+    }
+}
+
+/// A workstation attached to a HULIOS gateway (`hulios attach-gateway`)
+#[derive(Deserialize, Serialize)]
+pub struct Attachment {
+    pub gateway: Ipv4Addr,
+    /// Interface the gateway is reached on, directly
+    pub interface: String,
+    /// The interface's network, where nothing but the gateway is reachable
+    pub subnet: (Ipv4Addr, u8),
+}
+
+impl Policy {
+    pub fn from_config(config: &Config) -> Policy {
+        let policy = Policy {
+            proxy_ports: config.tor.http_tunnel_port.into_iter().collect(),
+            lan: config.gateway.lan_interface.clone().map(|interface| Lan {
+                interface,
+                socks: config.gateway.socks,
+                tor_ports_only: config.gateway.tor_ports_only,
+                remote_control: config.daemon.remote_control.as_deref()
+                    .and_then(|address| address.parse::<SocketAddr>().ok())
+                    .map(|address| address.port()),
+            }),
+            overlays: !overlay::enabled(config).is_empty(),
+            ssh_session: config.firewall.keep_ssh_session
+                .then(|| state::load().and_then(|session| session.ssh_session))
+                .flatten(),
+            icmp_allow: config.firewall.icmp_allow.clone(),
+            lan_services: if config.firewall.allow_lan || state::load().is_some_and(|session| session.allow_lan) {
+                config.firewall.lan_services.clone().unwrap_or_else(|| LanService::ALL.to_vec())
+            } else {
+                Vec::new()
+            },
+            guests: libvirt::guests(config),
+            new_guests_dropped: libvirt::drops_new_bridges(config),
+            mesh: meshvpn::allowed(config),
+            ..Policy::default()
+        };
+        let config = &config.firewall;
+        let mut block = config.block.clone();
+        if config.block_doh {
+            block.extend(doh::providers());
+        }
+        match config.mode {
+            FirewallMode::Full => Policy { block, ..policy },
+            FirewallMode::Partial => Policy {
+                // Invalid ranges are rejected by config validation before start
+                torify_ports: Some(config.torify_ports.iter().filter_map(|p| p.range().ok()).collect()),
+                allow_other: config.other_traffic == OtherTraffic::Allow,
+                block,
+                ..policy
+            },
+            FirewallMode::OnionOnly => Policy { block, onion_only: true, ..policy },
+        }
+    }
+
+    /// Whether TCP to `port` is sent through Tor (to clearnet destinations)
+    pub fn torifies(&self, port: u16) -> bool {
+        if self.onion_only {
+            return false;
+        }
+        match &self.torify_ports {
+            None => true,
+            Some(ranges) => ranges.iter().any(|&(low, high)| (low..=high).contains(&port)),
+        }
+    }
+
+    /// Whether direct traffic may leave at all (partial mode with allow)
+    pub fn is_partial_allow(&self) -> bool {
+        self.torify_ports.is_some() && self.allow_other
+    }
+
+    /// Every kind of packet the `lan_services` send, each to where it goes
+    pub fn lan_flows(&self) -> Vec<LanFlow> {
+        let mut flows = Vec::new();
+        for &service in &self.lan_services {
+            let networks: &[([u8; 4], u8)] = if service == LanService::Mdns { &[MDNS_GROUP] } else { &LAN_NETWORKS };
+            for &(protocol, low, high) in service.ports() {
+                for &network in networks {
+                    flows.push(LanFlow { protocol, ports: (low, high), network });
+                }
+            }
+        }
+        flows
+    }
+
+    /// Human-readable summary used when the rules are applied
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.describe_mode()];
+        if let Some(lan) = &self.lan {
+            parts.push(i18n::tr("firewall-lan-on", &[("interface", &lan.interface)]));
+        }
+        if let Some(ssh) = &self.ssh_session {
+            parts.push(i18n::tr("firewall-ssh-session-kept", &[("peer", &ssh.peer.to_string())]));
+        }
+        if !self.lan_services.is_empty() {
+            let names: Vec<&str> = self.lan_services.iter().map(|service| service.name()).collect();
+            parts.push(i18n::tr("firewall-lan-services", &[("names", &names.join(" "))]));
+        }
+        if !self.mesh.is_empty() {
+            let names: Vec<&str> = self.mesh.iter().map(|mesh| mesh.vpn.name()).collect();
+            parts.push(i18n::tr("firewall-mesh-vpns", &[("names", &names.join(" "))]));
+        }
+        if !self.guests.is_empty() {
+            let bridges: Vec<String> = self.guests.iter()
+                .map(|guests| format!("{} {}", guests.bridge, guests.policy.name()))
+                .collect();
+            parts.push(i18n::tr("firewall-libvirt-guests", &[("bridges", &bridges.join("; "))]));
+        }
+        parts.join(", ")
+    }
+
+    fn describe_mode(&self) -> String {
+        let id = if self.onion_only {
+            "firewall-mode-onion-only"
+        } else if self.torify_ports.is_some() {
+            if self.allow_other { "firewall-mode-partial-direct" } else { "firewall-mode-partial-blocked" }
+        } else {
+            "firewall-mode-default-deny"
+        };
+        i18n::tr(id, &[])
+    }
+
+    /// How TCP reaches the TransPort
+    pub fn redirect(&self) -> Redirect {
+        if self.tproxy { Redirect::Tproxy } else { Redirect::Nat }
+    }
+}
+
+/// The backend selected by `firewall.backend`
+pub fn backend(config: &Config) -> Box<dyn FirewallBackend> {
+    with_policy(config, Policy::from_config(config))
+}
+
+/// The selected backend, with its ruleset adapted to the probed kernel features
+pub fn adapted_backend(config: &Config, features: &Features) -> Box<dyn FirewallBackend> {
+    with_policy(config, adapted_policy(config, features))
+}
+
+/// The selected backend with the policy of the config alone, for `hulios
+/// render`: the kernel's features are assumed rather than probed, and
+/// nothing of the running system (kept SSH session, `--allow-lan`, libvirt
+/// bridges, mesh VPN daemons' users) is taken in
+pub fn rendered_backend(config: &Config) -> Box<dyn FirewallBackend> {
+    let policy = Policy {
+        state_match: StateMatch::Native,
+        tproxy: config.firewall.redirect == Redirect::Tproxy,
+        ssh_session: None,
+        lan_services: if config.firewall.allow_lan {
+            config.firewall.lan_services.clone().unwrap_or_else(|| LanService::ALL.to_vec())
+        } else {
+            Vec::new()
+        },
+        guests: Vec::new(),
+        // Both daemons run as root by default
+        mesh: config.firewall.mesh_vpns.iter().map(|&vpn| Mesh { vpn, daemon_uid: 0 }).collect(),
+        ..Policy::from_config(config)
+    };
+    with_policy(config, policy)
+}
+
+fn adapted_policy(config: &Config, features: &Features) -> Policy {
+    Policy {
+        state_match: features.state_match,
+        tproxy: features::redirect(config, features) == Some(Redirect::Tproxy),
+        ..Policy::from_config(config)
+    }
+}
+
+/// Identifies the ruleset `adapted_backend` would apply; equal fingerprints
+/// mean re-applying would change nothing
+pub fn fingerprint(config: &Config, features: &Features) -> String {
+    let policy = serde_json::to_string(&adapted_policy(config, features)).unwrap_or_default();
+    format!("{}:{}", with_policy(config, Policy::default()).name(), policy)
+}
+
+fn with_policy(config: &Config, policy: Policy) -> Box<dyn FirewallBackend> {
+    match config.firewall.backend {
+        Backend::Iptables => Box::new(IptablesBackend { policy }),
+        Backend::Nftables => Box::new(nftables::NftablesBackend { policy }),
+    }
+}
+
+/// Flush every backend. Used on stop/flush so rules installed by a
+/// previously configured backend never survive a config change. Both are
+/// always flushed, whatever the other's outcome: this is the way out of a
+/// DROP policy.
+pub fn flush_all() -> Result<()> {
+    remove_policy_routing();
+    let results = [
+        nftables::NftablesBackend::default().flush_rules(),
+        IptablesBackend::default().flush_rules(),
+    ];
+    let errors: Vec<String> = results.into_iter().filter_map(Result::err).map(|e| format!("{:#}", e)).collect();
+    if !errors.is_empty() {
+        anyhow::bail!("{}", errors.join("; "));
+    }
+    Ok(())
+}
+
+/// Flush every backend except the configured one, whose rules stay live.
+/// Used on restart, where opening the firewall even briefly is not an option.
+pub fn flush_others(config: &Config) -> Result<()> {
+    match config.firewall.backend {
+        Backend::Iptables => nftables::NftablesBackend::default().flush_rules(),
+        Backend::Nftables => IptablesBackend::default().flush_rules(),
+    }
+}
+
+// =============================================================================
+// Policy Routing (TPROXY)
+// =============================================================================
+
+/// Whether `ip rule` works: iproute2 is installed and the kernel has
+/// multiple routing tables
+pub fn policy_routing_available() -> bool {
+    Command::new("ip")
+        .args(["-4", "rule", "show"])
+        .stderr(Stdio::null())
+        .run()
+        .is_ok_and(|status| status.success())
+}
+
+/// Route packets carrying TPROXY_MARK to the local stack, where the TPROXY
+/// rule in PREROUTING hands them to Tor's TransPort
+pub fn add_policy_routing() -> Result<()> {
+    remove_policy_routing();
+    for args in policy_routing() {
+        let output = Command::new("ip").args(&args).run_output()
+            .with_context(|| i18n::tr("error-no-iproute2", &[]))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!(i18n::tr("firewall-ip-failed", &[("args", &args.join(" ")), ("stderr", stderr.trim())]));
+        }
+    }
+    Ok(())
+}
+
+/// The `ip` arguments of the TPROXY rule and route
+pub fn policy_routing() -> [Vec<String>; 2] {
+    let mark = TPROXY_MARK.to_string();
+    [
+        vec!["-4", "rule", "add", "fwmark", &mark, "lookup", TPROXY_TABLE],
+        vec!["-4", "route", "add", "local", "0.0.0.0/0", "dev", "lo", "table", TPROXY_TABLE],
+    ].map(|args| args.into_iter().map(str::to_string).collect())
+}
+
+/// Whether the TPROXY routing rule is installed
+pub fn policy_routing_present() -> bool {
+    Command::new("ip")
+        .args(["-4", "rule", "show"])
+        .run_output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("lookup {}", TPROXY_TABLE)))
+}
+
+/// Remove the TPROXY rule and table; a no-op when they aren't installed
+pub fn remove_policy_routing() {
+    let mark = TPROXY_MARK.to_string();
+    // `ip rule del` removes one matching rule per call
+    while Command::new("ip")
+        .args(["-4", "rule", "del", "fwmark", &mark, "lookup", TPROXY_TABLE])
+        .stderr(Stdio::null())
+        .run()
+        .is_ok_and(|status| status.success())
+    {}
+    let _ = Command::new("ip")
+        .args(["-4", "route", "flush", "table", TPROXY_TABLE])
+        .stderr(Stdio::null())
+        .run();
+}
+
+/// Spawns the iptables/ip6tables binaries, one process per rule
+#[derive(Default)]
+pub struct IptablesBackend {
+    policy: Policy,
+}
+
+impl FirewallBackend for IptablesBackend {
+    fn name(&self) -> &'static str {
+        "iptables"
+    }
+
+    fn apply_rules(&self, tor_user: &str) -> Result<()> {
+        iptables::apply_rules(tor_user, &self.policy)
+    }
+
+    fn flush_rules(&self) -> Result<()> {
+        iptables::flush_rules()
+    }
+
+    fn rules_present(&self) -> Option<bool> {
+        iptables::rules_present()
+    }
+
+    fn rule_checks(&self) -> Option<RuleChecks> {
+        iptables::rule_checks()
+    }
+
+    fn isolate(&self, apps: &[App]) -> Result<()> {
+        iptables::isolate(&self.policy, apps)
+    }
+
+    fn blocked_packets(&self) -> Option<u64> {
+        iptables::blocked_packets()
+    }
+
+    fn icmp_dropped(&self) -> Option<u64> {
+        iptables::icmp_dropped()
+    }
+
+    fn probe(&self) -> Features {
+        iptables::probe()
+    }
+
+    fn attach(&self, attachment: &Attachment) -> Result<()> {
+        iptables::attach(attachment, &self.policy)
+    }
+
+    fn dns_only(&self, resolver_uid: Option<u32>) -> Result<()> {
+        iptables::dns_only(resolver_uid)
+    }
+
+    fn render(&self, tor_user: &str) -> Result<Vec<(&'static str, String)>> {
+        let (v4, v6) = iptables::render(tor_user, &self.policy)?;
+        Ok(vec![("iptables.rules", v4), ("ip6tables.rules", v6)])
+    }
+}
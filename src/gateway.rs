@@ -0,0 +1,287 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use users::get_current_uid;
+use crate::config::Config;
+use crate::{control, i18n, ipc, report};
+
+/// Key shared by a gateway and the machines that control it, root only.
+/// Clients keep their copy at the same path, or pass --key-file.
+pub const KEY_PATH: &str = "/etc/hulios/gateway.key";
+
+/// SHA-256 block size, for HMAC
+const BLOCK: usize = 64;
+
+/// Longest line read from the other side
+const MAX_LINE: u64 = 16 * 1024;
+
+/// A client that hasn't finished by then is dropped
+const EXCHANGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a remote client can ask a gateway's daemon
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// New circuits for new connections (SIGNAL NEWNYM)
+    Newnym,
+    /// `hulios restart`, run apart from the daemon it replaces
+    Restart,
+}
+
+/// Each side opens with a nonce; the server proves it holds the key over
+/// both before the client sends anything else
+#[derive(Deserialize, Serialize)]
+struct Hello {
+    nonce: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ServerHello {
+    nonce: String,
+    proof: String,
+}
+
+/// The request, its MAC proving the client holds the key
+#[derive(Deserialize, Serialize)]
+struct Signed {
+    body: String,
+    mac: String,
+}
+
+/// One exchange, after the nonces: MACs cover both nonces, a label for
+/// the direction and the body, so nothing can be replayed or reflected
+struct Exchange {
+    key: Vec<u8>,
+    client_nonce: String,
+    server_nonce: String,
+}
+
+impl Exchange {
+    fn mac(&self, label: &str, body: &str) -> String {
+        let message = format!("{}\n{}\n{}\n{}", label, self.client_nonce, self.server_nonce, body);
+        hex(&hmac(&self.key, message.as_bytes()))
+    }
+
+    fn verify(&self, label: &str, body: &str, mac: &str) -> bool {
+        constant_time_eq(self.mac(label, body).as_bytes(), mac.as_bytes())
+    }
+}
+
+// =============================================================================
+// Server (in the daemon)
+// =============================================================================
+
+/// Accept authenticated requests on `address` until the task is dropped,
+/// answering each with `handler`'s reply. A client that can't prove it
+/// holds the key gets nothing but the server's nonce.
+pub async fn serve<H, F>(address: &str, handler: H) -> Result<()>
+where
+    H: Fn(Request) -> F + Clone + Send + 'static,
+    F: Future<Output = serde_json::Value> + Send,
+{
+    let key = read_key(KEY_PATH)?;
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to listen on {}", address))?;
+    println!("[*] Remote control on {}", address);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let handler = handler.clone();
+        let key = key.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = tokio::io::BufReader::new(reader.take(MAX_LINE * 2));
+            let exchange = async move {
+                let hello: Hello = serde_json::from_str(&read_async(&mut reader).await?)?;
+                let exchange = Exchange { key, client_nonce: hello.nonce, server_nonce: nonce()? };
+                let reply = ServerHello { nonce: exchange.server_nonce.clone(), proof: exchange.mac("server", "") };
+                writer.write_all(format!("{}\n", serde_json::to_string(&reply)?).as_bytes()).await?;
+
+                let signed: Signed = serde_json::from_str(&read_async(&mut reader).await?)?;
+                if !exchange.verify("request", &signed.body, &signed.mac) {
+                    anyhow::bail!("bad request MAC");
+                }
+                let request: Request = serde_json::from_str(&signed.body)?;
+                let body = handler(request).await.to_string();
+                let reply = Signed { mac: exchange.mac("reply", &body), body };
+                writer.write_all(format!("{}\n", serde_json::to_string(&reply)?).as_bytes()).await?;
+                Ok(())
+            };
+            let result = match tokio::time::timeout(EXCHANGE_TIMEOUT, exchange).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("timed out")),
+            };
+            if let Err(e) = result {
+                eprintln!("[!] Remote control request from {} refused: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn read_async<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        anyhow::bail!("connection closed");
+    }
+    Ok(line)
+}
+
+// =============================================================================
+// Client
+// =============================================================================
+
+/// Send `request` to the gateway at `host`, after checking it holds the
+/// same key, and return its verified reply
+pub fn request(host: &str, key_file: &str, request: Request) -> Result<serde_json::Value> {
+    let key = read_key(key_file)?;
+    let address = host
+        .to_socket_addrs()
+        .with_context(|| i18n::tr("gateway-cannot-resolve", &[("host", host)]))?
+        .next()
+        .with_context(|| i18n::tr("gateway-no-address", &[("host", host)]))?;
+    let stream = TcpStream::connect_timeout(&address, Duration::from_secs(10))
+        .with_context(|| i18n::tr("gateway-cannot-connect-gateway", &[("host", host)]))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(std::io::Read::take(stream, MAX_LINE * 2));
+
+    let client_nonce = nonce()?;
+    writer.write_all(format!("{}\n", serde_json::to_string(&Hello { nonce: client_nonce.clone() })?).as_bytes())?;
+    let hello: ServerHello = serde_json::from_str(&read_line(&mut reader)?).with_context(|| i18n::tr("gateway-malformed-reply-gateway", &[]))?;
+    let exchange = Exchange { key, client_nonce, server_nonce: hello.nonce };
+    if !exchange.verify("server", "", &hello.proof) {
+        anyhow::bail!(i18n::tr("gateway-does-not-hold-same", &[("host", host)]));
+    }
+
+    let body = serde_json::to_string(&request)?;
+    let signed = Signed { mac: exchange.mac("request", &body), body };
+    writer.write_all(format!("{}\n", serde_json::to_string(&signed)?).as_bytes())?;
+    let reply: Signed = serde_json::from_str(&read_line(&mut reader).with_context(|| i18n::tr("gateway-refused-request", &[]))?)
+        .with_context(|| i18n::tr("gateway-malformed-reply-gateway", &[]))?;
+    if !exchange.verify("reply", &reply.body, &reply.mac) {
+        anyhow::bail!(i18n::tr("gateway-reply-failed-verification", &[]));
+    }
+    let reply: serde_json::Value = serde_json::from_str(&reply.body)?;
+    if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+        anyhow::bail!("{}", error);
+    }
+    Ok(reply)
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        anyhow::bail!(i18n::tr("gateway-closed-connection", &[]));
+    }
+    Ok(line)
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios gateway init`: create the key remote clients authenticate with
+pub fn init() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if fs::metadata(KEY_PATH).is_ok() {
+        println!("[*] {}", i18n::tr("gateway-already-exists-delete-first", &[("path", KEY_PATH)]));
+        return Ok(());
+    }
+    let mut key = [0u8; 32];
+    control::random(&mut key)?;
+    if let Some(parent) = std::path::Path::new(KEY_PATH).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(KEY_PATH)
+        .with_context(|| i18n::tr("error-create", &[("path", KEY_PATH)]))?;
+    file.write_all(format!("{}\n", hex(&key)).as_bytes())?;
+
+    let config = Config::load()?;
+    println!("{}", format!("[+] {}", i18n::tr("gateway-key-written", &[("path", KEY_PATH)])).green());
+    println!("[*] {}", i18n::tr("gateway-copy-each-client-same", &[]));
+    if config.daemon.remote_control.is_none() {
+        println!("[*] {}", i18n::tr("gateway-then-set-daemon-remote", &[]));
+        println!("    {}", i18n::tr("gateway-run-sudo-restart", &[]));
+    }
+    report::action("Gateway key created");
+    Ok(())
+}
+
+/// `hulios gateway <status|newnym|restart> <host>`
+pub fn run(host: &str, key_file: Option<&str>, request: Request) -> Result<()> {
+    let reply = self::request(host, key_file.unwrap_or(KEY_PATH), request)?;
+    match request {
+        Request::Status => {
+            let status: ipc::DaemonStatus = serde_json::from_value(reply.clone()).with_context(|| i18n::tr("gateway-malformed-status-gateway", &[]))?;
+            let started = chrono::DateTime::from_timestamp(status.started_at, 0)
+                .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            println!("[+] {}", i18n::tr("gateway-session", &[("host", host), ("health", &status.health)]));
+            println!("    {}", i18n::tr("gateway-daemon-pid-up-since", &[("pid", &status.pid.to_string()), ("started", &started)]));
+            match status.circuit_established {
+                Some(true) => println!("    {}", i18n::tr("gateway-tor-circuits", &[])),
+                Some(false) => println!("{}", format!("    {}", i18n::tr("gateway-tor-no-circuits", &[])).yellow()),
+                None => println!("{}", format!("    {}", i18n::tr("gateway-tor-control-port-did", &[])).yellow()),
+            }
+        }
+        Request::Newnym => println!("{}", format!("[+] {}", i18n::tr("gateway-new-connections-use-new", &[("host", host)])).green()),
+        Request::Restart => println!("{}", format!("[+] {}", i18n::tr("gateway-restarting-check-back-gateway", &[("host", host)])).green()),
+    }
+    report::data("gateway", reply);
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn read_key(path: &str) -> Result<Vec<u8>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| i18n::tr("gateway-cannot-read-gateway-key", &[("path", path)]))?;
+    let content = content.trim();
+    let key: Option<Vec<u8>> = (0..content.len())
+        .step_by(2)
+        .map(|i| content.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+    key.filter(|key| key.len() == 32).with_context(|| i18n::tr("gateway-does-not-hold-gateway", &[("path", path)]))
+}
+
+fn nonce() -> Result<String> {
+    let mut nonce = [0u8; 16];
+    control::random(&mut nonce)?;
+    Ok(hex(&nonce))
+}
+
+/// HMAC-SHA256 (RFC 2104)
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; BLOCK];
+    block[..key.len()].copy_from_slice(key);
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,113 @@
+use anyhow::{Result, Context};
+use flate2::read::GzDecoder;
+use maxminddb::{geoip2, Reader};
+use std::fs;
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use crate::config::GeoipConfig;
+use crate::engine;
+use crate::i18n;
+
+/// Where an exit IP is located, as far as the local databases know
+#[derive(Default)]
+pub struct Location {
+    pub country: Option<String>,
+    pub city: Option<String>,
+    pub asn: Option<u32>,
+    pub organization: Option<String>,
+}
+
+impl Location {
+    pub fn is_empty(&self) -> bool {
+        self.country.is_none() && self.city.is_none() && self.asn.is_none()
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let place: Vec<&str> = [self.city.as_deref(), self.country.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        write!(f, "{}", if place.is_empty() { i18n::tr("status-unknown", &[]) } else { place.join(", ") })?;
+        match (self.asn, &self.organization) {
+            (Some(asn), Some(org)) => write!(f, " (AS{} {})", asn, org),
+            (Some(asn), None) => write!(f, " (AS{})", asn),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Look up an IP in whichever local databases exist. Missing databases are
+/// not an error, they just leave the corresponding fields empty.
+pub fn lookup(config: &GeoipConfig, ip: &str) -> Result<Location> {
+    let ip: IpAddr = ip.parse().with_context(|| i18n::tr("geoip-invalid-ip-address", &[("ip", ip)]))?;
+    let mut location = Location::default();
+
+    if let Ok(reader) = Reader::open_readfile(&config.database) {
+        if let Ok(city) = reader.lookup::<geoip2::City>(ip) {
+            location.city = city.city
+                .and_then(|c| c.names)
+                .and_then(|names| names.get("en").map(|n| n.to_string()));
+            location.country = city.country
+                .and_then(|c| c.names)
+                .and_then(|names| names.get("en").map(|n| n.to_string()));
+        }
+    }
+
+    if let Ok(reader) = Reader::open_readfile(&config.asn_database) {
+        if let Ok(asn) = reader.lookup::<geoip2::Asn>(ip) {
+            location.asn = asn.autonomous_system_number;
+            location.organization = asn.autonomous_system_organization.map(str::to_string);
+        }
+    }
+
+    Ok(location)
+}
+
+/// Download fresh databases through Tor
+pub fn refresh(config: &GeoipConfig) -> Result<()> {
+    let client = engine::tor_http_client(Duration::from_secs(300))?;
+    let month = chrono::Utc::now().format("%Y-%m").to_string();
+
+    for (url, dest) in [
+        (&config.database_url, &config.database),
+        (&config.asn_database_url, &config.asn_database),
+    ] {
+        let url = url.replace("{month}", &month);
+        println!("[*] {}", i18n::tr("geoip-downloading", &[("url", &url)]));
+        download(&client, &url, dest)?;
+    }
+    Ok(())
+}
+
+fn download(client: &reqwest::blocking::Client, url: &str, dest: &str) -> Result<()> {
+    let bytes = client.get(url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.bytes())
+        .with_context(|| i18n::tr("error-download", &[("url", url)]))?;
+
+    let data = if url.ends_with(".gz") {
+        let mut data = Vec::new();
+        GzDecoder::new(&bytes[..])
+            .read_to_end(&mut data)
+            .with_context(|| i18n::tr("geoip-failed-decompress-geoip-database", &[]))?;
+        data
+    } else {
+        bytes.to_vec()
+    };
+
+    // Refuse to install something that isn't a valid database
+    Reader::from_source(&data[..]).with_context(|| i18n::tr("geoip-downloaded-file-not-valid", &[]))?;
+
+    if let Some(parent) = Path::new(dest).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = format!("{}.tmp", dest);
+    fs::write(&tmp, &data)?;
+    fs::rename(&tmp, dest).with_context(|| i18n::tr("geoip-failed-update", &[("dest", dest)]))?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use serde::Serialize;
+use std::fmt;
+use crate::config::Config;
+use crate::{engine, firewall, i18n, state};
+
+/// Overall state of HULIOS, derived from local facts only
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Health {
+    /// Rules, Tor and DNS ownership all in place
+    Active,
+    /// Partially working; some component is missing or was undone
+    Degraded,
+    /// Nothing HULIOS-related is active
+    Stopped,
+    /// A session was started but its essential parts are gone
+    Failed,
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Health::Active => "Active",
+            Health::Degraded => "Degraded",
+            Health::Stopped => "Stopped",
+            Health::Failed => "Failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A specific detected fault and the command that fixes it
+#[derive(Serialize, Clone)]
+pub struct Problem {
+    pub description: String,
+    pub remedy: &'static str,
+}
+
+#[derive(Clone)]
+pub struct Assessment {
+    pub health: Health,
+    pub problems: Vec<Problem>,
+}
+
+/// Inspect rules, Tor and DNS and classify the overall state. An active
+/// session is also checked part by part: the redirect, the default DROP,
+/// resolv.conf's nameserver and route_localnet.
+/// Firewall rules can only be read as root; if they can't be read they
+/// are assumed to match the rest of the session.
+pub fn assess(config: &Config) -> Assessment {
+    let session = state::load().is_some();
+    let tor = engine::is_tor_running();
+    let dns = engine::dns_owned();
+    let backend = firewall::backend(config);
+    let rules = backend.rules_present();
+    let rules_known = rules.is_some();
+    let rules = rules.unwrap_or(session);
+
+    let mut problems = Vec::new();
+    let mut problem = |description: &str, remedy: &'static str| {
+        problems.push(Problem { description: description.to_string(), remedy });
+    };
+
+    let health = if rules && tor && dns {
+        let mut intact = true;
+        if let Some(checks) = backend.rule_checks() {
+            if !checks.redirect {
+                problem(&i18n::tr("health-redirect-gone", &[]), "sudo hulios restart");
+                intact = false;
+            }
+            if !checks.kill_switch {
+                problem(&i18n::tr("health-drop-gone", &[]), "sudo hulios restart");
+                intact = false;
+            }
+        }
+        if !engine::dns_points_to_tor() {
+            problem(&i18n::tr("health-resolv-conf-changed", &[]), "sudo hulios restart");
+            intact = false;
+        }
+        if engine::read_sysctl(engine::ROUTE_LOCALNET).is_some_and(|value| value != "1") {
+            problem(&i18n::tr("health-route-localnet-reset", &[]), "sudo hulios restart");
+            intact = false;
+        }
+        if intact { Health::Active } else { Health::Degraded }
+    } else if !rules && !tor && !dns && !session {
+        Health::Stopped
+    } else if session && !tor && !rules {
+        problem(&i18n::tr("health-unprotected", &[]), "sudo hulios restart");
+        Health::Failed
+    } else {
+        if rules && !tor {
+            problem(&i18n::tr("health-tor-down-blocked", &[]), "sudo hulios restart");
+        }
+        if tor && !rules && rules_known {
+            problem(&i18n::tr("health-rules-missing", &[]), "sudo hulios restart");
+        }
+        if (rules || tor) && !dns {
+            problem(&i18n::tr("health-resolv-conf-overwritten", &[]), "sudo hulios restart");
+        }
+        if !session && (rules || tor || dns) {
+            problem(&i18n::tr("health-leftovers", &[]), "sudo hulios recover");
+        }
+        Health::Degraded
+    };
+
+    Assessment { health, problems }
+}
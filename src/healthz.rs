@@ -0,0 +1,66 @@
+use anyhow::{Result, Context};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use crate::health::{Assessment, Health};
+
+/// Largest request head read; anything longer is answered from what arrived
+const MAX_REQUEST: usize = 4096;
+
+/// Serve the daemon's last assessment over plain HTTP on 127.0.0.1:`port`:
+///
+/// - `/healthz`: 200 while the session is Active or Degraded (HULIOS is
+///   running and holding the firewall), 503 once it has failed
+/// - `/readyz`: 200 only while Active, i.e. traffic is fully protected
+///
+/// Both answer 503 until the first check has run after start.
+pub async fn serve(port: u16, health: watch::Receiver<Option<Assessment>>) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Failed to listen on 127.0.0.1:{}", port))?;
+    println!("[*] Health endpoint on http://127.0.0.1:{}/healthz", port);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let health = health.clone();
+        tokio::spawn(async move {
+            let mut request = vec![0u8; MAX_REQUEST];
+            let Ok(read) = stream.read(&mut request).await else {
+                return;
+            };
+            let head = String::from_utf8_lossy(&request[..read]);
+            let path = head.split_whitespace().nth(1).unwrap_or_default();
+            let (code, body) = respond(path, health.borrow().as_ref());
+            let reason = match code {
+                200 => "OK",
+                404 => "Not Found",
+                _ => "Service Unavailable",
+            };
+            let response = format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                code, reason, body.len(), body,
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Status code and JSON body for `path`
+fn respond(path: &str, assessment: Option<&Assessment>) -> (u16, String) {
+    if !["/healthz", "/readyz"].contains(&path) {
+        return (404, json!({ "error": "not found" }).to_string());
+    }
+    let Some(assessment) = assessment else {
+        return (503, json!({ "health": "starting", "problems": [] }).to_string());
+    };
+    let healthy = match path {
+        "/healthz" => matches!(assessment.health, Health::Active | Health::Degraded),
+        _ => assessment.health == Health::Active,
+    };
+    let body = json!({
+        "health": assessment.health.to_string().to_lowercase(),
+        "problems": assessment.problems,
+    });
+    (if healthy { 200 } else { 503 }, body.to_string())
+}
@@ -0,0 +1,373 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::exec::Run;
+use crate::privileged::{self, Step};
+use crate::{audit, i18n, lock, notify, report, sdnotify, sshguard, tray};
+
+/// The helper's socket: root and members of GROUP may connect. Not under
+/// /run/hulios, which only root can enter.
+pub const SOCKET: &str = "/run/hulios-helper.sock";
+
+/// Members may start and stop HULIOS without sudo, and use the tray socket
+pub const GROUP: &str = "hulios";
+
+pub const SOCKET_UNIT: &str = "/etc/systemd/system/hulios-helper.socket";
+pub const SERVICE_UNIT: &str = "/etc/systemd/system/hulios-helper.service";
+pub const SOCKET_UNIT_LINK: &str = "/etc/systemd/system/sockets.target.wants/hulios-helper.socket";
+
+/// Longest step line the helper reads
+const MAX_REQUEST: u64 = 1024;
+
+/// Ends what a step printed, so its result is only sent after all of it
+const END_OF_OUTPUT: &str = "\u{1e}hulios-end";
+
+/// What the helper sends back, one JSON line each: for the lock, then for
+/// each step, ending with Done or Failed
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    Stdout(String),
+    Stderr(String),
+    Notify { title: String, body: String, urgency: String },
+    Action(String),
+    Warning(String),
+    Data { key: String, value: Value },
+    Done(Value),
+    Failed(String),
+}
+
+// =============================================================================
+// Frontend
+// =============================================================================
+
+/// Whether this process can change the system itself
+pub fn privileged() -> bool {
+    users::get_effective_uid() == 0
+}
+
+/// Whether a helper is installed to ask instead
+pub fn available() -> bool {
+    Path::new(SOCKET).exists()
+}
+
+/// A frontend's connection; the helper holds the HULIOS lock for it until
+/// it is dropped
+pub struct Connection {
+    stream: UnixStream,
+    replies: BufReader<UnixStream>,
+}
+
+/// Connect to the helper, returning once it holds the HULIOS lock
+pub fn connect() -> Result<Connection> {
+    let stream = UnixStream::connect(SOCKET)
+        .with_context(|| i18n::tr("helper-cannot-reach-helper-you", &[("socket", SOCKET), ("group", GROUP)]))?;
+    let replies = BufReader::new(stream.try_clone()?);
+    let mut connection = Connection { stream, replies };
+    connection.receive()?;
+    Ok(connection)
+}
+
+impl Connection {
+    /// Have the helper run `step`; what it prints, notifies and reports
+    /// is passed on here
+    pub fn run(&mut self, step: &Step) -> Result<Value> {
+        writeln!(self.stream, "{}", serde_json::to_string(step)?)?;
+        self.receive()
+    }
+
+    fn receive(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.replies.read_line(&mut line)? == 0 {
+                anyhow::bail!(i18n::tr("helper-closed-connection-before-finishing", &[]));
+            }
+            match serde_json::from_str(&line).with_context(|| i18n::tr("helper-malformed-reply-helper", &[]))? {
+                Reply::Stdout(line) => println!("{}", line),
+                Reply::Stderr(line) => eprintln!("{}", line),
+                Reply::Notify { title, body, urgency } => notify::show(&title, &body, &urgency),
+                Reply::Action(message) => report::action(message),
+                Reply::Warning(message) => report::warning(message),
+                Reply::Data { key, value } => report::data(&key, value),
+                Reply::Done(value) => return Ok(value),
+                Reply::Failed(error) => anyhow::bail!(error),
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Privileged Side
+// =============================================================================
+
+/// Body of `hulios helper serve`: serve frontends on the socket systemd
+/// passes in, or on SOCKET when started by hand. One at a time, as each
+/// holds the HULIOS lock while connected anyway; what a step prints is
+/// captured from this process's own stdout and stderr.
+pub fn serve() -> Result<()> {
+    notify::set_relay(true);
+    report::set_relay(true);
+    let listener = match sdnotify::listen_fd() {
+        Some(fd) => UnixListener::from(fd),
+        None => bind(SOCKET)?,
+    };
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Err(e) = handle(stream) {
+            eprintln!("[!] Helper request failed: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Bind `path` for root and GROUP, as the socket units do
+pub fn bind(path: &str) -> Result<UnixListener> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path).with_context(|| format!("Failed to bind {}", path))?;
+    let gid = users::get_group_by_name(GROUP).map(|group| group.gid());
+    std::os::unix::fs::chown(path, Some(0), gid)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+    Ok(listener)
+}
+
+/// Serve one frontend: take the HULIOS lock for it, then run the steps it
+/// sends, one JSON line each, until it disconnects. A step is all it can
+/// ask for; no `hulios` command is run on its behalf.
+fn handle(stream: UnixStream) -> Result<()> {
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let user = match authorize(&stream) {
+        Ok(user) => user,
+        Err(e) => {
+            send(&writer, &Reply::Failed(format!("{:#}", e)));
+            return Err(e);
+        }
+    };
+    let requester = peer_credentials(&stream).map(|cred| cred.pid).unwrap_or(0);
+    // Set for this connection alone, as there is no other: audited and
+    // notified as the user who asked, like under sudo, and it is their SSH
+    // session a start would cut off
+    std::env::set_var("SUDO_USER", &user);
+    std::env::set_var(sshguard::REQUESTER_PID, requester.to_string());
+    audit::record("helper", &format!("{} connected", user));
+    let result = run_steps(stream, &writer, &user);
+    std::env::remove_var("SUDO_USER");
+    std::env::remove_var(sshguard::REQUESTER_PID);
+    result
+}
+
+fn run_steps(stream: UnixStream, writer: &Arc<Mutex<UnixStream>>, user: &str) -> Result<()> {
+    let _lock = match relayed(writer, lock::acquire)? {
+        Ok(lock) => lock,
+        Err(e) => {
+            send(writer, &Reply::Failed(format!("{:#}", e)));
+            return Err(e);
+        }
+    };
+    send(writer, &Reply::Done(Value::Null));
+
+    let mut steps = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if (&mut steps).take(MAX_REQUEST).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let step: Step = match serde_json::from_str(&line) {
+            Ok(step) => step,
+            Err(e) => {
+                send(writer, &Reply::Failed(format!("Invalid step: {}", e)));
+                return Ok(());
+            }
+        };
+        audit::record("helper", &format!("{} requested {}", user, line.trim()));
+        let reply = match relayed(writer, || privileged::execute(step))? {
+            Ok(value) => Reply::Done(value),
+            Err(e) => Reply::Failed(format!("{:#}", e)),
+        };
+        send(writer, &reply);
+    }
+}
+
+/// Run `f` with this process's stdout and stderr sent to the frontend line
+/// by line: relayed notifications and report entries as what they are,
+/// the rest as output
+fn relayed<T>(writer: &Arc<Mutex<UnixStream>>, f: impl FnOnce() -> T) -> Result<T> {
+    let pipes = [pipe()?, pipe()?];
+    let _ = io::stdout().flush();
+    let mut saved = Vec::new();
+    let mut readers = Vec::new();
+    for (fd, (read, write)) in [libc::STDOUT_FILENO, libc::STDERR_FILENO].into_iter().zip(pipes) {
+        // SAFETY: plain fd duplication on descriptors this process owns
+        let previous = unsafe {
+            let previous = libc::dup(fd);
+            if previous < 0 || libc::dup2(write.as_raw_fd(), fd) < 0 {
+                anyhow::bail!("Failed to redirect output: {}", io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(previous)
+        };
+        saved.push((fd, previous));
+        let writer = writer.clone();
+        readers.push(thread::spawn(move || {
+            for line in BufReader::new(File::from(read)).lines().map_while(Result::ok) {
+                if line == END_OF_OUTPUT {
+                    break;
+                }
+                send(&writer, &relay(line, fd == libc::STDERR_FILENO));
+            }
+        }));
+    }
+
+    let result = f();
+
+    // A child may still hold the pipes, so their end is marked, not awaited
+    println!("{}", END_OF_OUTPUT);
+    let _ = io::stdout().flush();
+    eprintln!("{}", END_OF_OUTPUT);
+    for (fd, previous) in saved {
+        // SAFETY: as above
+        unsafe { libc::dup2(previous.as_raw_fd(), fd) };
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+    Ok(result)
+}
+
+/// The reply for a line a step printed
+fn relay(line: String, stderr: bool) -> Reply {
+    let marked = |marker: &str| line.strip_prefix(marker).and_then(|json| serde_json::from_str::<Value>(json).ok());
+    if let Some(note) = marked(notify::RELAY_MARKER) {
+        let field = |name: &str| note[name].as_str().unwrap_or_default().to_string();
+        return Reply::Notify { title: field("title"), body: field("body"), urgency: field("urgency") };
+    }
+    if let Some(entry) = marked(report::RELAY_MARKER) {
+        if let Some(action) = entry["action"].as_str() {
+            return Reply::Action(action.to_string());
+        }
+        if let Some(warning) = entry["warning"].as_str() {
+            return Reply::Warning(warning.to_string());
+        }
+        if let Some(key) = entry["data"]["key"].as_str() {
+            return Reply::Data { key: key.to_string(), value: entry["data"]["value"].clone() };
+        }
+    }
+    if stderr {
+        Reply::Stderr(line)
+    } else {
+        Reply::Stdout(line)
+    }
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for the two descriptors pipe2 fills in
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error()).with_context(|| i18n::tr("helper-failed-create-pipe", &[]));
+    }
+    // SAFETY: both were just opened and nothing else owns them
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Name of the connecting user, if they may use the helper: root or a
+/// member of GROUP. The socket's mode already says so; this is checked
+/// again in case it was loosened.
+pub fn authorize(stream: &UnixStream) -> Result<String> {
+    let cred = peer_credentials(stream)?;
+    let user = users::get_user_by_uid(cred.uid).with_context(|| i18n::tr("error-unknown-uid", &[("uid", &cred.uid.to_string())]))?;
+    let name = user.name().to_string_lossy().into_owned();
+    let member = users::get_group_by_name(GROUP).is_some_and(|group| {
+        users::get_user_groups(&name, user.primary_group_id())
+            .is_some_and(|groups| groups.iter().any(|g| g.gid() == group.gid()))
+    });
+    if cred.uid != 0 && !member {
+        anyhow::bail!(i18n::tr("helper-not-group", &[("name", &name), ("group", GROUP)]));
+    }
+    Ok(name)
+}
+
+/// The connecting process's PID and user, as the kernel reports them
+fn peer_credentials(stream: &UnixStream) -> Result<libc::ucred> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len describe a buffer of the size SO_PEERCRED fills
+    let rc = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, (&mut cred as *mut libc::ucred).cast(), &mut len)
+    };
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| i18n::tr("helper-cannot-identify-connecting-user", &[]));
+    }
+    Ok(cred)
+}
+
+fn send(writer: &Mutex<UnixStream>, reply: &Reply) {
+    if let Ok(json) = serde_json::to_string(reply) {
+        let _ = writeln!(writer.lock().unwrap(), "{}", json);
+    }
+}
+
+// =============================================================================
+// Installation
+// =============================================================================
+
+/// Install the socket-activated helper and tray units and the GROUP that
+/// may use them
+pub fn install() -> Result<()> {
+    if !privileged() {
+        anyhow::bail!(i18n::tr("helper-installing-helper-needs-root", &[]));
+    }
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+
+    if users::get_group_by_name(GROUP).is_none() {
+        let status = Command::new("groupadd").args(["--system", GROUP]).run()?;
+        if !status.success() {
+            anyhow::bail!(i18n::tr("helper-groupadd-failed-create-group", &[("group", GROUP)]));
+        }
+    }
+    for (path, content) in [
+        (SOCKET_UNIT, format!(
+            "# Installed by `hulios helper install`, removed by `hulios uninstall`\n\
+             [Unit]\nDescription=HULIOS privileged helper socket\n\n\
+             [Socket]\nListenStream={}\nSocketUser=root\nSocketGroup={}\nSocketMode=0660\n\n\
+             [Install]\nWantedBy=sockets.target\n", SOCKET, GROUP)),
+        (SERVICE_UNIT, format!(
+            "# Installed by `hulios helper install`, removed by `hulios uninstall`\n\
+             [Unit]\nDescription=HULIOS privileged helper\nRequires=hulios-helper.socket\n\n\
+             [Service]\nExecStart={} helper serve\n", exe.display())),
+        (tray::SOCKET_UNIT, format!(
+            "# Installed by `hulios helper install`, removed by `hulios uninstall`\n\
+             [Unit]\nDescription=HULIOS tray applet socket\n\n\
+             [Socket]\nListenStream={}\nSocketUser=root\nSocketGroup={}\nSocketMode=0660\n\n\
+             [Install]\nWantedBy=sockets.target\n", tray::SOCKET, GROUP)),
+        (tray::SERVICE_UNIT, format!(
+            "# Installed by `hulios helper install`, removed by `hulios uninstall`\n\
+             [Unit]\nDescription=HULIOS tray applet server\nRequires=hulios-tray.socket\n\n\
+             [Service]\nExecStart={} tray-server\n", exe.display())),
+    ] {
+        audit::record("write", path);
+        fs::write(path, content).with_context(|| i18n::tr("error-write", &[("path", path)]))?;
+    }
+
+    let _ = Command::new("systemctl").arg("daemon-reload").run();
+    let status = Command::new("systemctl").args(["enable", "--now", "hulios-helper.socket", "hulios-tray.socket"]).run()?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("helper-systemctl-failed-enable-helper", &[]));
+    }
+    println!("[+] {}", i18n::tr("helper-installed", &[("socket", SOCKET)]));
+    println!("[+] {}", i18n::tr("helper-tray-applets-can-connect", &[("socket", tray::SOCKET)]));
+    println!("[*] {}", i18n::tr("helper-let-user-start-stop", &[("group", GROUP)]));
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use anyhow::{Result, Context};
+use colored::*;
+use rust_embed::RustEmbed;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use crate::config::Config;
+use crate::{geoip, report, state, stats, status};
+
+/// Translations dropped in here are used without rebuilding, and take
+/// precedence over the built-in ones of the same name
+pub const LOCALES_DIR: &str = "/etc/hulios/locales";
+
+/// The catalog every message exists in
+const SOURCE: &str = "en";
+
+/// The catalogs shipped in the binary, from locales/
+#[derive(RustEmbed)]
+#[folder = "locales/"]
+struct Builtin;
+
+/// Filled in by any message that mentions them, for templates in
+/// `[messages]`: the last verified exit, its country and the session's uptime
+const SESSION_VARIABLES: [&str; 3] = ["exit_ip", "country", "uptime"];
+
+/// The catalogs to look a message up in: `[messages]` from the config,
+/// the chosen language, and SOURCE last. Only SOURCE under `--json`, so
+/// the report reads the same in every language.
+struct Catalog {
+    language: String,
+    chain: Vec<HashMap<String, String>>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+// =============================================================================
+// Lookup
+// =============================================================================
+
+/// Message `id` in the user's language, with each `{ $name }` replaced by
+/// its value from `args` or SESSION_VARIABLES. Falls back to English, then
+/// to the id itself.
+pub fn tr(id: &str, args: &[(&str, &str)]) -> String {
+    let catalog = CATALOG.get_or_init(load);
+    let mut text = catalog.chain.iter()
+        .find_map(|messages| messages.get(id))
+        .cloned()
+        .unwrap_or_else(|| id.to_string());
+    for (name, value) in args {
+        text = substitute(&text, name, value);
+    }
+    for name in SESSION_VARIABLES {
+        if mentions(&text, name) {
+            let value = session_variable(name).unwrap_or_else(|| tr("status-unknown", &[]));
+            text = substitute(&text, name, &value);
+        }
+    }
+    text
+}
+
+/// Whether `id` is a message of the English catalog
+pub fn exists(id: &str) -> bool {
+    read(SOURCE).is_some_and(|messages| messages.contains_key(id))
+}
+
+fn mentions(text: &str, name: &str) -> bool {
+    text.contains(&format!("{{ ${} }}", name)) || text.contains(&format!("{{${}}}", name))
+}
+
+fn substitute(text: &str, name: &str, value: &str) -> String {
+    text.replace(&format!("{{ ${} }}", name), value).replace(&format!("{{${}}}", name), value)
+}
+
+/// Only looked up when a message uses it; none of them cost a request
+fn session_variable(name: &str) -> Option<String> {
+    match name {
+        "exit_ip" => status::last_exit().map(|exit| exit.ip),
+        "country" => {
+            let exit = status::last_exit()?;
+            geoip::lookup(&Config::load().unwrap_or_default().geoip, &exit.ip).ok()?.country
+        }
+        "uptime" => {
+            let started = state::load()?.started_at;
+            Some(stats::format_duration(chrono::Utc::now().timestamp() - started))
+        }
+        _ => None,
+    }
+}
+
+fn load() -> Catalog {
+    let config = Config::load().unwrap_or_default();
+    let language = language(&config);
+    if report::enabled() {
+        let chain = read(SOURCE).into_iter().collect();
+        return Catalog { language, chain };
+    }
+    let mut chain = vec![config.messages.into_iter().collect()];
+    // "pt_BR", then "pt", then English
+    let mut names = vec![language.clone()];
+    if let Some((base, _)) = language.split_once('_') {
+        names.push(base.to_string());
+    }
+    names.push(SOURCE.to_string());
+    names.dedup();
+    for name in names {
+        if let Some(messages) = read(&name) {
+            chain.push(messages);
+        }
+    }
+    Catalog { language, chain }
+}
+
+/// `language` from the config, else the first of LANGUAGE, LC_ALL,
+/// LC_MESSAGES and LANG that names one; "pt_BR" for "pt_BR.UTF-8"
+fn language(config: &Config) -> String {
+    let configured = config.language.clone().filter(|language| language != "auto");
+    let from_env = || {
+        ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|var| std::env::var(var).ok())
+            // LANGUAGE is a list, e.g. "fa:en"
+            .filter_map(|value| value.split(':').next().map(str::to_string))
+            .find(|value| !value.is_empty())
+    };
+    let value = configured.or_else(from_env).unwrap_or_default();
+    let value = value.split(['.', '@']).next().unwrap_or_default().replace('-', "_");
+    match value.as_str() {
+        "" | "C" | "POSIX" => SOURCE.to_string(),
+        _ => value,
+    }
+}
+
+/// The catalog for `language`: LOCALES_DIR's, else the built-in one
+fn read(language: &str) -> Option<HashMap<String, String>> {
+    let file = format!("{}.ftl", language);
+    let text = match fs::read_to_string(Path::new(LOCALES_DIR).join(&file)) {
+        Ok(text) => text,
+        Err(_) => String::from_utf8(Builtin::get(&file)?.data.into_owned()).ok()?,
+    };
+    Some(parse(&text))
+}
+
+/// `id = text` lines; indented lines continue the message above, and `#`
+/// starts a comment
+fn parse(text: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in text.lines() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            last = None;
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some(message) = last.as_ref().and_then(|id| messages.get_mut(id)) {
+                let message: &mut String = message;
+                if !message.is_empty() {
+                    message.push('\n');
+                }
+                message.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((id, value)) = line.split_once('=') {
+            let id = id.trim().to_string();
+            messages.insert(id.clone(), value.trim().to_string());
+            last = Some(id);
+        }
+    }
+    messages
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios locale`: the language in use and how complete each catalog is
+pub fn status() -> Result<()> {
+    let catalog = CATALOG.get_or_init(load);
+    let source = read(SOURCE).with_context(|| tr("i18n-no-english-catalog", &[]))?;
+    println!("[*] {}", tr("i18n-language", &[("language", &catalog.language.bold())]));
+
+    let mut languages: BTreeMap<String, &str> = Builtin::iter()
+        .filter_map(|file| file.strip_suffix(".ftl").map(str::to_string))
+        .map(|language| (language, "built in"))
+        .collect();
+    for entry in fs::read_dir(LOCALES_DIR).into_iter().flatten().flatten() {
+        if let Some(language) = entry.file_name().to_string_lossy().strip_suffix(".ftl") {
+            languages.insert(language.to_string(), LOCALES_DIR);
+        }
+    }
+    let mut listed = Vec::new();
+    for (language, origin) in &languages {
+        let messages = read(language).unwrap_or_default();
+        let translated = source.keys().filter(|id| messages.contains_key(*id)).count();
+        let shown = if *origin == LOCALES_DIR { origin.to_string() } else { tr("i18n-built-in", &[]) };
+        println!("    {:<8} {}", language, tr("i18n-catalog", &[
+            ("translated", &format!("{:>3}", translated)), ("count", &source.len().to_string()), ("origin", &shown),
+        ]));
+        listed.push(serde_json::json!({ "language": language, "translated": translated, "origin": origin }));
+    }
+    report::data("language", &catalog.language);
+    report::data("catalogs", listed);
+    report::data("messages", source.len());
+    Ok(())
+}
+
+/// `hulios locale missing <language>`: the English messages `language`
+/// has no translation for, in catalog format, ready to be translated
+pub fn missing(language: &str) -> Result<()> {
+    let source = Builtin::get(&format!("{}.ftl", SOURCE)).with_context(|| tr("i18n-no-english-catalog", &[]))?;
+    let source = String::from_utf8_lossy(&source.data).into_owned();
+    let translated = read(language).unwrap_or_default();
+    let mut missing = Vec::new();
+    let mut keep = false;
+    for line in source.lines() {
+        if let Some((id, _)) = line.split_once('=').filter(|_| !line.starts_with([' ', '\t', '#'])) {
+            keep = !translated.contains_key(id.trim());
+            if keep {
+                missing.push(id.trim().to_string());
+            }
+        } else if !line.starts_with([' ', '\t']) {
+            keep = false;
+        }
+        if keep {
+            println!("{}", line);
+        }
+    }
+    eprintln!("[*] {}", tr("i18n-missing", &[("count", &missing.len().to_string()), ("language", language)]));
+    report::data("missing", missing);
+    Ok(())
+}
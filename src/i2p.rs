@@ -0,0 +1,102 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::config::I2pConfig;
+use crate::overlay::Overlay;
+use crate::{engine, i18n, procfs};
+
+/// i2pd's router identity and network database, kept across sessions so
+/// it doesn't have to reseed every time
+const DATA_DIR: &str = "/var/lib/hulios/i2p";
+
+const LOG: &str = "/var/lib/hulios/i2p/i2pd.log";
+
+const BINARY_CANDIDATES: &[&str] = &["/usr/bin/i2pd", "/usr/sbin/i2pd", "/usr/local/bin/i2pd"];
+
+/// I2P through i2pd (experimental). i2pd talks to its peers directly, not
+/// through Tor: they learn this machine's address, and the network sees
+/// that it runs I2P.
+pub struct I2p {
+    binary: Option<String>,
+    socks_port: u16,
+}
+
+impl I2p {
+    pub fn from_config(config: &I2pConfig) -> I2p {
+        I2p { binary: config.binary.clone(), socks_port: config.socks_port }
+    }
+
+    fn binary(&self) -> Result<String> {
+        match &self.binary {
+            Some(path) => Ok(path.clone()),
+            None => BINARY_CANDIDATES
+                .iter()
+                .find(|candidate| std::path::Path::new(candidate).is_file())
+                .map(|candidate| candidate.to_string())
+                .with_context(|| i18n::tr("i2p-i2pd-not-found-installed", &[("binary_candidates", &BINARY_CANDIDATES.join(", "))])),
+        }
+    }
+}
+
+impl Overlay for I2p {
+    fn name(&self) -> &'static str {
+        "I2P"
+    }
+
+    fn suffix(&self) -> &'static str {
+        ".i2p"
+    }
+
+    fn socks(&self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], self.socks_port))
+    }
+
+    fn launch(&self) -> Result<u32> {
+        let tor_user = users::get_user_by_name(engine::TOR_USER)
+            .with_context(|| i18n::tr("error-no-tor-user", &[("user", engine::TOR_USER)]))?;
+        fs::create_dir_all(DATA_DIR).with_context(|| i18n::tr("error-create", &[("path", DATA_DIR)]))?;
+        std::os::unix::fs::chown(DATA_DIR, Some(tor_user.uid()), Some(tor_user.primary_group_id()))
+            .with_context(|| i18n::tr("i2p-failed-chown-i2p-data", &[]))?;
+        fs::set_permissions(DATA_DIR, fs::Permissions::from_mode(0o700))?;
+
+        // Only the SOCKS proxy: no web console, HTTP proxy or SAM
+        let child = Command::new(self.binary()?)
+            .arg(format!("--datadir={}", DATA_DIR))
+            .args(["--log=file", "--loglevel=warn"])
+            .arg(format!("--logfile={}", LOG))
+            .args(["--ipv6=false", "--http.enabled=false", "--httpproxy.enabled=false", "--sam.enabled=false"])
+            .args(["--socksproxy.enabled=true", "--socksproxy.address=127.0.0.1"])
+            .arg(format!("--socksproxy.port={}", self.socks_port))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            // The tor user's traffic is what the firewall lets out
+            .uid(tor_user.uid())
+            .gid(tor_user.primary_group_id())
+            // Own process group, so Ctrl-C in the starting terminal doesn't reach it
+            .process_group(0)
+            .spawn()
+            .with_context(|| i18n::tr("i2p-failed-start-i2pd", &[]))?;
+        Ok(child.id())
+    }
+
+    fn halt(&self) {
+        if let Some(pid) = pid() {
+            // SIGTERM, not SIGINT: i2pd would otherwise wait for its transit tunnels to end
+            procfs::terminate(pid as i32, Duration::from_secs(10));
+        }
+    }
+}
+
+/// PID of the i2pd started by HULIOS
+fn pid() -> Option<u32> {
+    let datadir = format!("--datadir={}", DATA_DIR);
+    procfs::find_by_name("i2pd")
+        .into_iter()
+        .find(|info| info.cmdline.contains(&datadir))
+        .map(|info| info.pid as u32)
+}
@@ -0,0 +1,71 @@
+use anyhow::{Result, Context};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use crate::i18n;
+
+pub use libc::{IN_ATTRIB, IN_CLOSE_WRITE, IN_CREATE, IN_DELETE, IN_MOVED_TO};
+
+/// A change inside a watched directory
+pub struct Event {
+    /// File name inside a watched directory; empty for the directory itself
+    pub name: String,
+}
+
+/// Minimal blocking inotify instance
+pub struct Watch {
+    fd: OwnedFd,
+}
+
+impl Watch {
+    pub fn new() -> Result<Watch> {
+        // SAFETY: plain syscall, the returned descriptor is checked below
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| i18n::tr("inotify-init1-failed", &[]));
+        }
+        // SAFETY: fd is a freshly created descriptor we exclusively own
+        Ok(Watch { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+
+    /// Watch `path` for the events in `mask`
+    pub fn add(&self, path: &str, mask: u32) -> Result<()> {
+        let c_path = CString::new(path)?;
+        // SAFETY: valid fd and NUL-terminated path
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| i18n::tr("inotify-cannot-watch", &[("path", path)]));
+        }
+        Ok(())
+    }
+
+    /// Block until at least one event arrives and return everything queued
+    pub fn wait(&self) -> Result<Vec<Event>> {
+        let mut buf = [0u8; 4096];
+        let len = loop {
+            // SAFETY: buf is valid for buf.len() bytes
+            let n = unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err).with_context(|| i18n::tr("inotify-reading-inotify-events-failed", &[]));
+            }
+        };
+
+        // struct inotify_event { int wd; u32 mask; u32 cookie; u32 len; char name[len]; }
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + header <= len {
+            let field = |at: usize| u32::from_ne_bytes(buf[offset + at..offset + at + 4].try_into().unwrap());
+            let name_len = field(12) as usize;
+            let name = &buf[offset + header..(offset + header + name_len).min(len)];
+            let name = String::from_utf8_lossy(name.split(|b| *b == 0).next().unwrap_or_default()).into_owned();
+            events.push(Event { name });
+            offset += header + name_len;
+        }
+        Ok(events)
+    }
+}
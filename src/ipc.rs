@@ -0,0 +1,103 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use crate::i18n;
+
+/// The daemon's control socket; root only
+pub const SOCKET: &str = "/run/hulios/daemon.sock";
+
+/// What a client can ask the daemon, one JSON line per connection
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// Spawn the HULIOS-managed Tor as the daemon's child
+    LaunchTor,
+}
+
+/// Reply to Request::LaunchTor
+#[derive(Deserialize, Serialize)]
+pub struct Launched {
+    pub pid: u32,
+}
+
+/// The daemon's view of the session, answering Request::Status
+#[derive(Deserialize, Serialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub started_at: i64,
+    /// Health at the last monitor tick
+    pub health: String,
+    /// Tor's status/circuit-established; None if the control port didn't answer
+    pub circuit_established: Option<bool>,
+}
+
+// =============================================================================
+// Server
+// =============================================================================
+
+/// Create SOCKET, root only
+pub fn bind() -> Result<UnixListener> {
+    // A socket left by a daemon that was killed blocks the bind
+    let _ = fs::remove_file(SOCKET);
+    let listener = UnixListener::bind(SOCKET).with_context(|| i18n::tr("ipc-failed-bind", &[("socket", SOCKET)]))?;
+    fs::set_permissions(SOCKET, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accept connections on `listener` until the task is dropped, answering
+/// each request with `handler`'s reply as one JSON line
+pub async fn serve<H, F>(listener: UnixListener, handler: H) -> Result<()>
+where
+    H: Fn(Request) -> F + Clone + Send + 'static,
+    F: Future<Output = serde_json::Value> + Send,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let handler = handler.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut line = String::new();
+            if tokio::io::BufReader::new(reader).read_line(&mut line).await.is_err() {
+                return;
+            }
+            let reply = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => handler(request).await,
+                Err(e) => serde_json::json!({ "error": i18n::tr("ipc-invalid-request", &[("error", &e.to_string())]) }),
+            };
+            let _ = writer.write_all(format!("{}\n", reply).as_bytes()).await;
+        });
+    }
+}
+
+/// Remove the socket once the daemon is done with it
+pub fn remove() {
+    let _ = fs::remove_file(SOCKET);
+}
+
+// =============================================================================
+// Client
+// =============================================================================
+
+/// Send one request to the running daemon and return its reply; a reply of
+/// `{"error": ...}` becomes an Err
+pub fn request<T: for<'de> Deserialize<'de>>(request: &Request) -> Result<T> {
+    let mut stream = UnixStream::connect(SOCKET).with_context(|| i18n::tr("ipc-daemon-not-running", &[]))?;
+    stream.set_read_timeout(Some(Duration::from_secs(15)))?;
+    stream.write_all(format!("{}\n", serde_json::to_string(request)?).as_bytes())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).with_context(|| i18n::tr("ipc-daemon-did-not-answer", &[]))?;
+    let reply: serde_json::Value = serde_json::from_str(&reply).with_context(|| i18n::tr("ipc-malformed-reply-daemon", &[]))?;
+    if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+        anyhow::bail!("{}", error);
+    }
+    serde_json::from_value(reply).with_context(|| i18n::tr("ipc-malformed-reply-daemon", &[]))
+}
@@ -1,5 +1,48 @@
-use std::process::Command;
-use anyhow::Result;
+use std::net::SocketAddr;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use anyhow::{Result, Context};
+use crate::config::GuestPolicy;
+use crate::exec::Run;
+use crate::features::{Features, StateMatch};
+use crate::firewall::{self, Attachment, Lan, Policy, RuleChecks, ONION_NETWORK, OVERLAY_NETWORK, TPROXY_MARK};
+use crate::isolate::{self, App};
+use crate::{blocklist, dnsonly, i18n, libvirt, overlay, report};
+
+/// Filter chain holding the blocklist REJECT rules and their counters
+const BLOCK_CHAIN: &str = "HULIOS_BLOCK";
+
+/// Filter chain taking all ICMP outside tracked connections: the allowed
+/// types are accepted, the rest dropped by its last rule, whose counter
+/// `hulios status` reads
+const ICMP_CHAIN: &str = "HULIOS_ICMP";
+
+/// NAT chain redirecting the apps of `hulios run --isolated` to their own ports
+const ISOLATE_CHAIN: &str = "HULIOS_ISOLATE";
+
+/// Mangle chains of TPROXY mode: marking in OUTPUT, diverting in PREROUTING
+const MARK_CHAIN: &str = "HULIOS_MARK";
+const TPROXY_CHAIN: &str = "HULIOS_TPROXY";
+
+/// Chains of gateway mode: NAT for what LAN clients send, and the FORWARD
+/// drop that keeps anything of theirs from being routed around Tor
+const LAN_CHAIN: &str = "HULIOS_LAN";
+const FORWARD_CHAIN: &str = "HULIOS_FORWARD";
+
+/// Chains (in nat and filter) of the libvirt guests' policies: NAT to Tor
+/// for torified bridges, and FORWARD verdicts for each bridge
+const GUESTS_CHAIN: &str = "HULIOS_GUESTS";
+
+/// Chain (in filter, nat and mangle) for the feature probes; OUTPUT never jumps
+/// to it, so its rules never see a packet
+const PROBE_CHAIN: &str = "HULIOS_PROBE";
+
+/// The arguments of one iptables or (true) ip6tables command
+type Rendered = (bool, Vec<String>);
+
+/// While `render` runs, what run_iptables and run_ip6tables are given is
+/// collected here instead of run
+static RENDERING: Mutex<Option<Vec<Rendered>>> = Mutex::new(None);
 
 /// Apply iptables rules for transparent Tor routing.
 /// 
@@ -10,10 +53,36 @@ use anyhow::Result;
 /// 4. All TCP is forced through Tor TransPort  
 /// 5. IPv6 is completely blocked (safest approach)
 /// 6. Private networks are NOT exempt (prevents DNS leaks to router)
-pub fn apply_rules(tor_user: &str) -> Result<()> {
-    flush_rules()?;
+///
+/// In partial mode (`policy.torify_ports`) only the listed TCP ports (and
+/// DNS) are redirected; other traffic is dropped or, with `allow_other`,
+/// leaves directly. In onion-only mode (`policy.onion_only`) only TCP to
+/// the automapped .onion range is redirected and everything else is dropped.
+///
+/// Re-applying over a live ruleset (restart) closes OUTPUT before clearing
+/// the chains, so the firewall fails closed while it is rebuilt.
+pub fn apply_rules(tor_user: &str, policy: &Policy) -> Result<()> {
+    run_iptables(&["-P", "OUTPUT", "DROP"])?;
+    let _ = run_ip6tables(&["-P", "OUTPUT", "DROP"]);
+    clear_chains();
+    let block = blocklist::resolve(&policy.block);
+    ruleset(tor_user, policy, &block, &isolate::active())?;
+    if policy.tproxy {
+        firewall::add_policy_routing()?;
+    } else {
+        firewall::remove_policy_routing();
+    }
 
+    println!("[+] {}", i18n::tr("iptables-rules-applied", &[("policy", &policy.describe()), ("redirect", policy.redirect().name())]));
+    report::action("Firewall rules applied via iptables");
+    Ok(())
+}
+
+/// The rules of apply_rules, into chains already cleared
+fn ruleset(tor_user: &str, policy: &Policy, block: &blocklist::Resolved, apps: &[App]) -> Result<()> {
     let dns_port = "9061";
+    // With overlay networks UDP DNS goes to their router, which passes the rest on
+    let udp_dns_port = if policy.overlays { overlay::DNS_PORT.to_string() } else { dns_port.to_string() };
     let trans_port = "9051";
 
     // ========================================================================
@@ -22,20 +91,80 @@ pub fn apply_rules(tor_user: &str) -> Result<()> {
     
     
     // 1. Established connections (for already-NAT'd traffic)
-    run_iptables(&["-t", "nat", "-A", "OUTPUT", "-m", "state", "--state", "ESTABLISHED", "-j", "RETURN"])?;
+    if let Some(state) = state_match(policy.state_match, "ESTABLISHED") {
+        run_iptables(&[&["-t", "nat", "-A", "OUTPUT"], &state[..], &["-j", "RETURN"]].concat())?;
+    }
+
+    // 1a. The SSH session kept open (`keep_ssh_session`) is never redirected
+    let ssh = ssh_flows(policy, false);
+    if let Some((out, _)) = &ssh {
+        run_iptables(&[&["-t", "nat", "-A", "OUTPUT"], &strs(out)[..], &["-j", "RETURN"]].concat())?;
+    }
+
+    // 1b. Neither are the LAN services let out with `allow_lan`
+    for flow in lan_flows(policy, true) {
+        run_iptables(&[&["-t", "nat", "-A", "OUTPUT"], &strs(&flow)[..], &["-j", "RETURN"]].concat())?;
+    }
     
     // 2. Tor user bypasses NAT (its traffic goes directly out)
     run_iptables(&["-t", "nat", "-A", "OUTPUT", "-m", "owner", "--uid-owner", tor_user, "-j", "RETURN"])?;
     
+    // 2a. Tor's own local proxies (HTTPTunnelPort) are reached directly
+    for port in &policy.proxy_ports {
+        run_iptables(&["-t", "nat", "-A", "OUTPUT", "-d", "127.0.0.1", "-p", "tcp", "--dport", &port.to_string(), "-j", "RETURN"])?;
+    }
+
+    // 2b. TCP to blocklisted destinations is not redirected; the filter table
+    //     rejects it. DNS to them (1.1.1.1 as a DoH provider) still goes to Tor.
+    for &(ip, prefix) in &block.v4 {
+        let dest = format!("{}/{}", blocklist::network_v4(ip, prefix), prefix);
+        run_iptables(&["-t", "nat", "-A", "OUTPUT", "-d", &dest, "-p", "tcp", "!", "--dport", "53", "-j", "RETURN"])?;
+    }
+
+    // 2c. Apps run with `hulios run --isolated` go to listeners of their own
+    run_iptables(&["-t", "nat", "-N", ISOLATE_CHAIN])?;
+    run_iptables(&["-t", "nat", "-A", "OUTPUT", "-j", ISOLATE_CHAIN])?;
+    isolate(policy, apps)?;
+
     // 3. DNS REDIRECT - MUST come before any other destination rules
-    run_iptables(&["-t", "nat", "-A", "OUTPUT", "-p", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", dns_port])?;
+    run_iptables(&["-t", "nat", "-A", "OUTPUT", "-p", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", &udp_dns_port])?;
     run_iptables(&["-t", "nat", "-A", "OUTPUT", "-p", "tcp", "--dport", "53", "-j", "REDIRECT", "--to-ports", dns_port])?;
+
+    // 3a. Overlay networks' virtual addresses go to the overlay router
+    if policy.overlays {
+        let ([a, b, c, d], prefix) = OVERLAY_NETWORK;
+        let network = format!("{}.{}.{}.{}/{}", a, b, c, d, prefix);
+        let port = overlay::TRANS_PORT.to_string();
+        run_iptables(&["-t", "nat", "-A", "OUTPUT", "-d", &network, "-p", "tcp", "-j", "REDIRECT", "--to-ports", &port])?;
+    }
+
+    // 3b. Mesh VPN peers are reached directly (their DNS was redirected above)
+    for peers in mesh_peers(policy) {
+        run_iptables(&[&["-t", "nat", "-A", "OUTPUT"], &strs(&peers)[..], &["-j", "RETURN"]].concat())?;
+    }
     
     // 4. Loopback only - NO private network exceptions
     run_iptables(&["-t", "nat", "-A", "OUTPUT", "-d", "127.0.0.0/8", "-j", "RETURN"])?;
     
-    // 5. ALL other TCP goes to Tor TransPort
-    run_iptables(&["-t", "nat", "-A", "OUTPUT", "-p", "tcp", "-j", "REDIRECT", "--to-ports", trans_port])?;
+    // 5. ALL other TCP goes to Tor TransPort (partial mode: only the listed ports,
+    //    onion-only: only the VirtualAddrNetwork .onion names are mapped into).
+    //    TPROXY mode diverts it in the mangle table instead.
+    if policy.tproxy {
+        apply_tproxy(tor_user, policy, block)?;
+    } else {
+        for selector in torified_tcp(policy) {
+            let selector: Vec<&str> = selector.iter().map(String::as_str).collect();
+            run_iptables(&[&["-t", "nat", "-A", "OUTPUT"], &selector[..], &["-j", "REDIRECT", "--to-ports", trans_port]].concat())?;
+        }
+    }
+
+    // 6. Gateway mode: the LAN's traffic arrives in PREROUTING
+    if let Some(lan) = &policy.lan {
+        apply_lan(lan, policy)?;
+    }
+
+    // 6a. libvirt guests: torified, forwarded or dropped, by bridge
+    apply_guests(policy)?;
 
     // ========================================================================
     // IPv4 FILTER TABLE - Enforce what's allowed to leave
@@ -47,15 +176,58 @@ pub fn apply_rules(tor_user: &str) -> Result<()> {
     
     // 2. Loopback is always allowed
     run_iptables(&["-A", "OUTPUT", "-o", "lo", "-j", "ACCEPT"])?;
+
+    // 2a. So is the SSH session kept open
+    if let Some((out, _)) = &ssh {
+        run_iptables(&[&["-A", "OUTPUT"], &strs(out)[..], &["-j", "ACCEPT"]].concat())?;
+    }
+
+    // 2b. Blocklist: reject outright, with packet counters in its own chain
+    let _ = run_iptables(&["-N", BLOCK_CHAIN]);
+    for &(ip, prefix) in &block.v4 {
+        let dest = format!("{}/{}", blocklist::network_v4(ip, prefix), prefix);
+        run_iptables(&["-A", BLOCK_CHAIN, "-d", &dest, "-j", "REJECT", "--reject-with", "icmp-admin-prohibited"])?;
+    }
+    run_iptables(&["-A", "OUTPUT", "-j", BLOCK_CHAIN])?;
     
     // 3. Allow traffic to localhost (for redirected packets)
     run_iptables(&["-A", "OUTPUT", "-d", "127.0.0.0/8", "-j", "ACCEPT"])?;
     
     // 4. Established/Related connections
-    run_iptables(&["-A", "OUTPUT", "-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"])?;
+    let established = state_match(policy.state_match, "ESTABLISHED,RELATED");
+    if let Some(state) = &established {
+        run_iptables(&[&["-A", "OUTPUT"], &state[..], &["-j", "ACCEPT"]].concat())?;
+    }
+
+    // 4a. ICMP: only the allowed types, the rest dropped and counted (partial
+    //     mode with other traffic allowed lets it out like any other)
+    if !policy.is_partial_allow() {
+        let _ = run_iptables(&["-N", ICMP_CHAIN]);
+        for kind in policy.icmp_allow.iter().filter(|kind| kind.v4().is_some()) {
+            run_iptables(&["-A", ICMP_CHAIN, "-p", "icmp", "--icmp-type", kind.name(), "-j", "ACCEPT"])?;
+        }
+        run_iptables(&["-A", ICMP_CHAIN, "-j", "DROP"])?;
+        run_iptables(&["-A", "OUTPUT", "-p", "icmp", "-j", ICMP_CHAIN])?;
+    }
     
     // 5. Tor user can reach the internet
     run_iptables(&["-A", "OUTPUT", "-m", "owner", "--uid-owner", tor_user, "-j", "ACCEPT"])?;
+
+    // 5a. LAN services (`allow_lan`) reach the local network directly
+    for flow in lan_flows(policy, false) {
+        run_iptables(&[&["-A", "OUTPUT"], &strs(&flow)[..], &["-j", "ACCEPT"]].concat())?;
+    }
+
+    // 5b. So do mesh VPN peers, and the VPN daemon's own UDP
+    for flow in mesh_peers(policy).into_iter().chain(mesh_daemon_udp(policy)) {
+        run_iptables(&[&["-A", "OUTPUT"], &strs(&flow)[..], &["-j", "ACCEPT"]].concat())?;
+    }
+
+    // 5c. TPROXY: the marked TCP is routed back into the local stack, to the TransPort
+    if policy.tproxy {
+        let mark = format!("{:#x}", TPROXY_MARK);
+        run_iptables(&["-A", "OUTPUT", "-p", "tcp", "-m", "mark", "--mark", &mark, "-j", "ACCEPT"])?;
+    }
     
     // 6. Explicitly DROP any DNS that bypassed NAT
     run_iptables(&["-A", "OUTPUT", "-p", "udp", "--dport", "53", "-j", "DROP"])?;
@@ -63,11 +235,22 @@ pub fn apply_rules(tor_user: &str) -> Result<()> {
     run_iptables(&["-A", "OUTPUT", "-p", "tcp", "--dport", "853", "-j", "DROP"])?; // DoT
     run_iptables(&["-A", "OUTPUT", "-p", "udp", "--dport", "443", "-j", "DROP"])?; // QUIC
     
-    // 7. DROP everything else
-    run_iptables(&["-A", "OUTPUT", "-j", "DROP"])?;
+    // 7. Partial mode: torified ports never leave directly, not even over UDP
+    for range in policy.torify_ports.iter().flatten() {
+        let ports = port_arg(*range);
+        run_iptables(&["-A", "OUTPUT", "-p", "tcp", "--dport", &ports, "-j", "DROP"])?;
+        run_iptables(&["-A", "OUTPUT", "-p", "udp", "--dport", &ports, "-j", "DROP"])?;
+    }
+
+    // 8. DROP everything else (partial mode with other_traffic = "allow": let it out)
+    if policy.is_partial_allow() {
+        run_iptables(&["-A", "OUTPUT", "-j", "ACCEPT"])?;
+    } else {
+        run_iptables(&["-A", "OUTPUT", "-j", "DROP"])?;
+    }
 
     // ========================================================================
-    // IPv6 - BLOCK COMPLETELY
+    // IPv6 - BLOCK COMPLETELY (partial allow: block DNS and torified ports)
     // ========================================================================
     
     let _ = run_ip6tables(&["-P", "OUTPUT", "DROP"]);
@@ -76,64 +259,622 @@ pub fn apply_rules(tor_user: &str) -> Result<()> {
     
     let _ = run_ip6tables(&["-A", "OUTPUT", "-o", "lo", "-j", "ACCEPT"]);
     let _ = run_ip6tables(&["-A", "INPUT", "-i", "lo", "-j", "ACCEPT"]);
+
+    if let Some((out, into)) = ssh_flows(policy, true) {
+        run_ip6tables(&[&["-A", "OUTPUT"], &strs(&out)[..], &["-j", "ACCEPT"]].concat())?;
+        run_ip6tables(&[&["-A", "INPUT"], &strs(&into)[..], &["-j", "ACCEPT"]].concat())?;
+    }
+
+    let _ = run_ip6tables(&["-N", BLOCK_CHAIN]);
+    for &(ip, prefix) in &block.v6 {
+        let dest = format!("{}/{}", blocklist::network_v6(ip, prefix), prefix);
+        let _ = run_ip6tables(&["-A", BLOCK_CHAIN, "-d", &dest, "-j", "REJECT", "--reject-with", "icmp6-adm-prohibited"]);
+    }
+    let _ = run_ip6tables(&["-A", "OUTPUT", "-j", BLOCK_CHAIN]);
     
-    let _ = run_ip6tables(&["-A", "OUTPUT", "-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"]);
-    let _ = run_ip6tables(&["-A", "INPUT", "-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"]);
+    if let Some(state) = &established {
+        let _ = run_ip6tables(&[&["-A", "OUTPUT"], &state[..], &["-j", "ACCEPT"]].concat());
+        let _ = run_ip6tables(&[&["-A", "INPUT"], &state[..], &["-j", "ACCEPT"]].concat());
+    }
+
+    // ICMPv6 both ways, as for IPv4 (INPUT drops in every mode)
+    let _ = run_ip6tables(&["-N", ICMP_CHAIN]);
+    for kind in &policy.icmp_allow {
+        let _ = run_ip6tables(&["-A", ICMP_CHAIN, "-p", "ipv6-icmp", "--icmpv6-type", kind.name(), "-j", "ACCEPT"]);
+    }
+    let _ = run_ip6tables(&["-A", ICMP_CHAIN, "-j", "DROP"]);
+    if !policy.is_partial_allow() {
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "ipv6-icmp", "-j", ICMP_CHAIN]);
+    }
+    let _ = run_ip6tables(&["-A", "INPUT", "-p", "ipv6-icmp", "-j", ICMP_CHAIN]);
     
-    let _ = run_ip6tables(&["-A", "OUTPUT", "-j", "DROP"]);
+    if policy.is_partial_allow() {
+        // Tor's TransPort is IPv4-only, so torified traffic can't go out over IPv6
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "udp", "--dport", "53", "-j", "DROP"]);
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "tcp", "--dport", "53", "-j", "DROP"]);
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "tcp", "--dport", "853", "-j", "DROP"]);
+        for range in policy.torify_ports.iter().flatten() {
+            let ports = port_arg(*range);
+            let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "tcp", "--dport", &ports, "-j", "DROP"]);
+            let _ = run_ip6tables(&["-A", "OUTPUT", "-p", "udp", "--dport", &ports, "-j", "DROP"]);
+        }
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-j", "ACCEPT"]);
+    } else {
+        let _ = run_ip6tables(&["-A", "OUTPUT", "-j", "DROP"]);
+    }
     let _ = run_ip6tables(&["-A", "INPUT", "-j", "DROP"]);
+    Ok(())
+}
+
+/// TPROXY mode: mark the TCP that NAT mode would redirect, for policy
+/// routing to send back into the local stack, and hand it to the
+/// TransPort in PREROUTING. The destination is never rewritten.
+fn apply_tproxy(tor_user: &str, policy: &Policy, block: &blocklist::Resolved) -> Result<()> {
+    let mark = format!("{:#x}", TPROXY_MARK);
+
+    run_iptables(&["-t", "mangle", "-N", MARK_CHAIN])?;
+    run_iptables(&["-t", "mangle", "-A", "OUTPUT", "-j", MARK_CHAIN])?;
+    // Tor's own connections, and its replies to the diverted ones
+    run_iptables(&["-t", "mangle", "-A", MARK_CHAIN, "-m", "owner", "--uid-owner", tor_user, "-j", "RETURN"])?;
+    run_iptables(&["-t", "mangle", "-A", MARK_CHAIN, "-d", "127.0.0.0/8", "-j", "RETURN"])?;
+    if let Some((out, _)) = ssh_flows(policy, false) {
+        run_iptables(&[&["-t", "mangle", "-A", MARK_CHAIN], &strs(&out)[..], &["-j", "RETURN"]].concat())?;
+    }
+    for flow in lan_flows(policy, true) {
+        run_iptables(&[&["-t", "mangle", "-A", MARK_CHAIN], &strs(&flow)[..], &["-j", "RETURN"]].concat())?;
+    }
+    // DNS over TCP is NAT-redirected to the DNSPort
+    run_iptables(&["-t", "mangle", "-A", MARK_CHAIN, "-p", "tcp", "--dport", "53", "-j", "RETURN"])?;
+    for peers in mesh_peers(policy) {
+        run_iptables(&[&["-t", "mangle", "-A", MARK_CHAIN], &strs(&peers)[..], &["-j", "RETURN"]].concat())?;
+    }
+    for &(ip, prefix) in &block.v4 {
+        let dest = format!("{}/{}", blocklist::network_v4(ip, prefix), prefix);
+        run_iptables(&["-t", "mangle", "-A", MARK_CHAIN, "-d", &dest, "-j", "RETURN"])?;
+    }
+    for selector in torified_tcp(policy) {
+        let selector: Vec<&str> = selector.iter().map(String::as_str).collect();
+        run_iptables(&[&["-t", "mangle", "-A", MARK_CHAIN], &selector[..], &["-j", "MARK", "--set-mark", &mark]].concat())?;
+    }
+
+    run_iptables(&["-t", "mangle", "-N", TPROXY_CHAIN])?;
+    run_iptables(&["-t", "mangle", "-A", "PREROUTING", "-j", TPROXY_CHAIN])?;
+    run_iptables(&["-t", "mangle", "-A", TPROXY_CHAIN, "-p", "tcp", "-m", "mark", "--mark", &mark,
+        "-j", "TPROXY", "--on-ip", "127.0.0.1", "--on-port", "9051"])
+}
+
+/// Gateway mode: LAN clients' DNS and torified TCP go to Tor's listeners
+/// on the LAN address (REDIRECT picks the address the packet came in on).
+/// Connections to this machine itself are left alone (or, with
+/// `tor_ports_only`, dropped), and nothing from or to the LAN is forwarded,
+/// over IPv4 or IPv6.
+fn apply_lan(lan: &Lan, policy: &Policy) -> Result<()> {
+    let interface = lan.interface.as_str();
+    run_iptables(&["-t", "nat", "-N", LAN_CHAIN])?;
+    run_iptables(&["-t", "nat", "-A", "PREROUTING", "-j", LAN_CHAIN])?;
+    run_iptables(&["-t", "nat", "-A", LAN_CHAIN, "!", "-i", interface, "-j", "RETURN"])?;
+    for proto in ["udp", "tcp"] {
+        run_iptables(&["-t", "nat", "-A", LAN_CHAIN, "-p", proto, "--dport", "53", "-j", "REDIRECT", "--to-ports", "9061"])?;
+    }
+    run_iptables(&["-t", "nat", "-A", LAN_CHAIN, "-m", "addrtype", "--dst-type", "LOCAL", "-j", "RETURN"])?;
+    for selector in torified_tcp(policy) {
+        let selector: Vec<&str> = selector.iter().map(String::as_str).collect();
+        run_iptables(&[&["-t", "nat", "-A", LAN_CHAIN], &selector[..], &["-j", "REDIRECT", "--to-ports", "9051"]].concat())?;
+    }
 
-    println!("[+] Firewall rules applied (default-deny, Tor-only)");
+    run_iptables(&["-N", FORWARD_CHAIN])?;
+    run_iptables(&["-I", "FORWARD", "-j", FORWARD_CHAIN])?;
+    run_iptables(&["-A", FORWARD_CHAIN, "-i", interface, "-j", "DROP"])?;
+    run_iptables(&["-A", FORWARD_CHAIN, "-o", interface, "-j", "DROP"])?;
+    // IPv6 forwarding is dropped outright for the session (see below)
+
+    if lan.tor_ports_only {
+        if let Some((_, into)) = ssh_flows(policy, false) {
+            run_iptables(&[&["-A", "INPUT"], &strs(&into)[..], &["-j", "ACCEPT"]].concat())?;
+        }
+        if let Some(state) = state_match(policy.state_match, "ESTABLISHED,RELATED") {
+            run_iptables(&[&["-A", "INPUT", "-i", interface], &state[..], &["-j", "ACCEPT"]].concat())?;
+        }
+        for port in lan.tor_ports() {
+            run_iptables(&["-A", "INPUT", "-i", interface, "-p", "tcp", "--dport", &port.to_string(), "-j", "ACCEPT"])?;
+        }
+        run_iptables(&["-A", "INPUT", "-i", interface, "-p", "udp", "--dport", "9061", "-j", "ACCEPT"])?;
+        if let Some(port) = lan.remote_control {
+            run_iptables(&["-A", "INPUT", "-i", interface, "-p", "tcp", "--dport", &port.to_string(), "-j", "ACCEPT"])?;
+        }
+        run_iptables(&["-A", "INPUT", "-i", interface, "-j", "DROP"])?;
+    }
+    Ok(())
+}
+
+/// libvirt guests: on torified bridges their DNS and torified TCP go to
+/// Tor's listeners on the bridge address, as in gateway mode, and nothing
+/// is forwarded; clearnet bridges are forwarded (libvirt NATs them), and
+/// the rest dropped. Bridges that appear later are dropped too unless
+/// `libvirt.guests` is clearnet.
+fn apply_guests(policy: &Policy) -> Result<()> {
+    run_iptables(&["-t", "nat", "-N", GUESTS_CHAIN])?;
+    run_iptables(&["-t", "nat", "-A", "PREROUTING", "-j", GUESTS_CHAIN])?;
+    run_iptables(&["-N", GUESTS_CHAIN])?;
+    run_iptables(&["-I", "FORWARD", "-j", GUESTS_CHAIN])?;
+    let established = state_match(policy.state_match, "ESTABLISHED,RELATED");
+    for guests in &policy.guests {
+        let bridge = guests.bridge.as_str();
+        match guests.policy {
+            GuestPolicy::Torify => {
+                for proto in ["udp", "tcp"] {
+                    run_iptables(&["-t", "nat", "-A", GUESTS_CHAIN, "-i", bridge, "-p", proto, "--dport", "53", "-j", "REDIRECT", "--to-ports", "9061"])?;
+                }
+                run_iptables(&["-t", "nat", "-A", GUESTS_CHAIN, "-i", bridge, "-m", "addrtype", "--dst-type", "LOCAL", "-j", "RETURN"])?;
+                for selector in torified_tcp(policy) {
+                    let selector: Vec<&str> = selector.iter().map(String::as_str).collect();
+                    run_iptables(&[&["-t", "nat", "-A", GUESTS_CHAIN, "-i", bridge], &selector[..], &["-j", "REDIRECT", "--to-ports", "9051"]].concat())?;
+                }
+            }
+            GuestPolicy::Clearnet => {
+                run_iptables(&["-A", GUESTS_CHAIN, "-i", bridge, "-j", "ACCEPT"])?;
+                if let Some(state) = &established {
+                    run_iptables(&[&["-A", GUESTS_CHAIN, "-o", bridge], &state[..], &["-j", "ACCEPT"]].concat())?;
+                }
+                continue;
+            }
+            GuestPolicy::Block => {}
+        }
+        run_iptables(&["-A", GUESTS_CHAIN, "-i", bridge, "-j", "DROP"])?;
+        run_iptables(&["-A", GUESTS_CHAIN, "-o", bridge, "-j", "DROP"])?;
+    }
+    if policy.new_guests_dropped {
+        let any = format!("{}+", libvirt::BRIDGE_PREFIX);
+        run_iptables(&["-A", GUESTS_CHAIN, "-i", &any, "-j", "DROP"])?;
+        run_iptables(&["-A", GUESTS_CHAIN, "-o", &any, "-j", "DROP"])?;
+    }
     Ok(())
 }
 
+/// Fill ISOLATE_CHAIN: each app's DNS and torified TCP, told apart by its
+/// cgroup, goes to its own ports. Loopback returns to OUTPUT, which leaves
+/// it alone. TPROXY mode has no such rules: its TransPorts only take
+/// diverted connections.
+pub fn isolate(policy: &Policy, apps: &[App]) -> Result<()> {
+    run_iptables(&["-t", "nat", "-F", ISOLATE_CHAIN])?;
+    if policy.tproxy {
+        return Ok(());
+    }
+    for app in apps {
+        let owner = ["-t", "nat", "-A", ISOLATE_CHAIN, "-m", "cgroup", "--path", app.group.as_str()];
+        let dns_port = app.dns_port().to_string();
+        let trans_port = app.trans_port().to_string();
+        for proto in ["udp", "tcp"] {
+            run_iptables(&[&owner[..], &["-p", proto, "--dport", "53", "-j", "REDIRECT", "--to-ports", &dns_port]].concat())?;
+        }
+        run_iptables(&[&owner[..], &["-d", "127.0.0.0/8", "-j", "RETURN"]].concat())?;
+        for selector in torified_tcp(policy) {
+            let selector: Vec<&str> = selector.iter().map(String::as_str).collect();
+            run_iptables(&[&owner[..], &selector[..], &["-j", "REDIRECT", "--to-ports", &trans_port]].concat())?;
+        }
+    }
+    Ok(())
+}
+
+/// Match arguments for each slice of TCP that goes through Tor
+fn torified_tcp(policy: &Policy) -> Vec<Vec<String>> {
+    let args = |extra: &[&str]| [&["-p", "tcp"], extra].concat().into_iter().map(str::to_string).collect();
+    match &policy.torify_ports {
+        _ if policy.onion_only => {
+            let ([a, b, c, d], prefix) = ONION_NETWORK;
+            vec![args(&["-d", &format!("{}.{}.{}.{}/{}", a, b, c, d, prefix)])]
+        }
+        None => vec![args(&[])],
+        Some(ranges) => ranges.iter().map(|range| args(&["--dport", &port_arg(*range)])).collect(),
+    }
+}
+
 pub fn flush_rules() -> Result<()> {
     // Reset policies
     let _ = run_iptables(&["-P", "OUTPUT", "ACCEPT"]);
     let _ = run_iptables(&["-P", "INPUT", "ACCEPT"]);
     let _ = run_iptables(&["-P", "FORWARD", "ACCEPT"]);
     
-    // Flush chains
-    let _ = run_iptables(&["-t", "nat", "-F", "OUTPUT"]);
-    let _ = run_iptables(&["-t", "filter", "-F", "OUTPUT"]);
-    let _ = run_iptables(&["-t", "filter", "-F", "INPUT"]);
-    
     // Reset IPv6
     let _ = run_ip6tables(&["-P", "OUTPUT", "ACCEPT"]);
     let _ = run_ip6tables(&["-P", "INPUT", "ACCEPT"]);
     let _ = run_ip6tables(&["-P", "FORWARD", "ACCEPT"]);
-    
+
+    clear_chains();
+    println!("[+] {}", i18n::tr("iptables-firewall-rules-flushed-policies", &[]));
+    report::action("iptables rules flushed");
+    Ok(())
+}
+
+/// The ruleset of apply_rules as iptables-restore and ip6tables-restore
+/// files, built by the same code with nothing run
+pub fn render(tor_user: &str, policy: &Policy) -> Result<(String, String)> {
+    *RENDERING.lock().unwrap() = Some(Vec::new());
+    let built = ruleset(tor_user, policy, &blocklist::literal(&policy.block), &[]);
+    let commands = RENDERING.lock().unwrap().take().unwrap_or_default();
+    built?;
+    let (v6, v4): (Vec<_>, Vec<_>) = commands.into_iter().partition(|(v6, _)| *v6);
+    let restore = |binary: &str, commands: Vec<Rendered>| {
+        restore_file(binary, commands.into_iter().map(|(_, args)| args))
+    };
+    Ok((restore("iptables", v4), restore("ip6tables", v6)))
+}
+
+/// iptables-restore input for `commands`: per table, its chains (with the
+/// policies set) and then its rules, in the order they were given.
+/// Flushes are left out, since restoring starts each chain out empty.
+fn restore_file(binary: &str, commands: impl Iterator<Item = Vec<String>>) -> String {
+    let mut tables: Vec<RestoreTable> = Vec::new();
+    for args in commands {
+        let (table, args) = match args.split_first() {
+            Some((flag, rest)) if flag == "-t" && !rest.is_empty() => (rest[0].clone(), &rest[1..]),
+            _ => ("filter".to_string(), &args[..]),
+        };
+        let (Some(command), Some(chain)) = (args.first(), args.get(1)) else {
+            continue;
+        };
+        let position = match tables.iter().position(|known| known.name == table) {
+            Some(position) => position,
+            None => {
+                tables.push(RestoreTable { name: table, chains: Vec::new(), rules: Vec::new() });
+                tables.len() - 1
+            }
+        };
+        let RestoreTable { chains, rules, .. } = &mut tables[position];
+        if !chains.iter().any(|(name, _)| name == chain) && command != "-F" {
+            chains.push((chain.clone(), "-".to_string()));
+        }
+        match command.as_str() {
+            "-P" => {
+                if let (Some(entry), Some(policy)) = (chains.iter_mut().find(|(name, _)| name == chain), args.get(2)) {
+                    entry.1 = policy.clone();
+                }
+            }
+            "-A" | "-I" => rules.push(args.join(" ")),
+            _ => {}
+        }
+    }
+
+    let mut file = format!("# HULIOS ruleset, written by `hulios render`; load with {}-restore\n", binary);
+    for table in tables {
+        file.push_str(&format!("*{}\n", table.name));
+        for (chain, policy) in table.chains {
+            file.push_str(&format!(":{} {} [0:0]\n", chain, policy));
+        }
+        for rule in table.rules {
+            file.push_str(&rule);
+            file.push('\n');
+        }
+        file.push_str("COMMIT\n");
+    }
+    file
+}
+
+/// One table of an iptables-restore file
+struct RestoreTable {
+    name: String,
+    /// Each chain with its policy, "-" for none
+    chains: Vec<(String, String)>,
+    rules: Vec<String>,
+}
+
+/// Collect the command if `render` is running; whether it was
+fn rendering(v6: bool, args: &[&str]) -> bool {
+    match RENDERING.lock().unwrap().as_mut() {
+        Some(commands) => {
+            commands.push((v6, args.iter().map(|arg| arg.to_string()).collect()));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove every HULIOS rule and chain, leaving the policies as they are
+fn clear_chains() {
+    let _ = run_iptables(&["-t", "nat", "-F", "OUTPUT"]);
+    let _ = run_iptables(&["-t", "filter", "-F", "OUTPUT"]);
+    let _ = run_iptables(&["-t", "filter", "-F", "INPUT"]);
+
     let _ = run_ip6tables(&["-t", "nat", "-F", "OUTPUT"]);
     let _ = run_ip6tables(&["-t", "filter", "-F", "OUTPUT"]);
     let _ = run_ip6tables(&["-t", "filter", "-F", "INPUT"]);
+
+    // The isolated apps' chain, no longer referenced by OUTPUT
+    let _ = Command::new("iptables").args(["-t", "nat", "-F", ISOLATE_CHAIN]).stderr(Stdio::null()).run();
+    let _ = Command::new("iptables").args(["-t", "nat", "-X", ISOLATE_CHAIN]).stderr(Stdio::null()).run();
+
+    // TPROXY chains, after unhooking them
+    for (hook, chain) in [("OUTPUT", MARK_CHAIN), ("PREROUTING", TPROXY_CHAIN)] {
+        let _ = Command::new("iptables").args(["-t", "mangle", "-D", hook, "-j", chain]).stderr(Stdio::null()).run();
+        let _ = Command::new("iptables").args(["-t", "mangle", "-F", chain]).stderr(Stdio::null()).run();
+        let _ = Command::new("iptables").args(["-t", "mangle", "-X", chain]).stderr(Stdio::null()).run();
+    }
+
+    // Gateway mode and libvirt guest chains, after unhooking them
+    for (table, hook, chain) in [("nat", "PREROUTING", LAN_CHAIN), ("filter", "FORWARD", FORWARD_CHAIN),
+        ("nat", "PREROUTING", GUESTS_CHAIN), ("filter", "FORWARD", GUESTS_CHAIN)] {
+        let _ = Command::new("iptables").args(["-t", table, "-D", hook, "-j", chain]).stderr(Stdio::null()).run();
+        let _ = Command::new("iptables").args(["-t", table, "-F", chain]).stderr(Stdio::null()).run();
+        let _ = Command::new("iptables").args(["-t", table, "-X", chain]).stderr(Stdio::null()).run();
+    }
+
+    // Blocklist and ICMP chains (OUTPUT and INPUT no longer reference them)
+    for binary in ["iptables", "ip6tables"] {
+        for chain in [BLOCK_CHAIN, ICMP_CHAIN] {
+            let _ = Command::new(binary).args(["-F", chain]).stderr(Stdio::null()).run();
+            let _ = Command::new(binary).args(["-X", chain]).stderr(Stdio::null()).run();
+        }
+    }
     
     // Flush legacy
-    let _ = Command::new("iptables-legacy").args(["-t", "nat", "-F", "OUTPUT"]).status();
-    let _ = Command::new("iptables-legacy").args(["-t", "filter", "-F", "OUTPUT"]).status();
-    let _ = Command::new("ip6tables-legacy").args(["-t", "nat", "-F", "OUTPUT"]).status();
-    let _ = Command::new("ip6tables-legacy").args(["-t", "filter", "-F", "OUTPUT"]).status();
-    
-    println!("[+] Firewall rules flushed, policies reset to ACCEPT");
+    let _ = Command::new("iptables-legacy").args(["-t", "nat", "-F", "OUTPUT"]).run();
+    let _ = Command::new("iptables-legacy").args(["-t", "filter", "-F", "OUTPUT"]).run();
+    let _ = Command::new("ip6tables-legacy").args(["-t", "nat", "-F", "OUTPUT"]).run();
+    let _ = Command::new("ip6tables-legacy").args(["-t", "filter", "-F", "OUTPUT"]).run();
+}
+
+/// Workstation of `hulios attach-gateway`: the gateway is the only host on
+/// its network that can be reached, and everything else leaves through it
+/// as TCP or DNS, which the gateway torifies. No IPv6 at all.
+pub fn attach(attachment: &Attachment, policy: &Policy) -> Result<()> {
+    // Closed first, as in apply_rules
+    run_iptables(&["-P", "OUTPUT", "DROP"])?;
+    clear_chains();
+
+    let gateway = attachment.gateway.to_string();
+    let (network, prefix) = attachment.subnet;
+    let subnet = format!("{}/{}", network, prefix);
+    let interface = attachment.interface.as_str();
+    run_iptables(&["-A", "OUTPUT", "-o", "lo", "-j", "ACCEPT"])?;
+    if let Some(state) = state_match(policy.state_match, "ESTABLISHED,RELATED") {
+        run_iptables(&[&["-A", "OUTPUT"], &state[..], &["-j", "ACCEPT"]].concat())?;
+    }
+    run_iptables(&["-A", "OUTPUT", "-d", &gateway, "-j", "ACCEPT"])?;
+    run_iptables(&["-A", "OUTPUT", "-d", &subnet, "-j", "DROP"])?;
+    run_iptables(&["-A", "OUTPUT", "-o", interface, "-p", "tcp", "-j", "ACCEPT"])?;
+    run_iptables(&["-A", "OUTPUT", "-o", interface, "-p", "udp", "--dport", "53", "-j", "ACCEPT"])?;
+
+    run_ip6tables(&["-A", "OUTPUT", "-o", "lo", "-j", "ACCEPT"])?;
+    run_ip6tables(&["-A", "INPUT", "-i", "lo", "-j", "ACCEPT"])?;
+    for chain in ["OUTPUT", "INPUT", "FORWARD"] {
+        run_ip6tables(&["-P", chain, "DROP"])?;
+    }
+
+    println!("[+] {}", i18n::tr("iptables-rules-applied-gateway", &[("gateway", &gateway)]));
+    report::action("Workstation firewall rules applied via iptables");
     Ok(())
 }
 
-fn run_iptables(args: &[&str]) -> Result<()> {
-    let status = Command::new("iptables").args(args).status();
-    match status {
-        Ok(s) if s.success() => Ok(()),
-        Ok(_) => {
-            eprintln!("[!] iptables {:?} failed", args);
-            Ok(())
+/// `hulios dns-only`: DNS goes to the local encrypted resolver, DNS over
+/// TLS and QUIC and DNS over IPv6 are dropped, and the policies stay ACCEPT
+pub fn dns_only(resolver_uid: Option<u32>) -> Result<()> {
+    clear_chains();
+
+    let port = dnsonly::PORT.to_string();
+    if let Some(uid) = resolver_uid {
+        run_iptables(&["-t", "nat", "-A", "OUTPUT", "-m", "owner", "--uid-owner", &uid.to_string(), "-j", "RETURN"])?;
+    }
+    for proto in ["udp", "tcp"] {
+        run_iptables(&["-t", "nat", "-A", "OUTPUT", "-p", proto, "--dport", "53", "-j", "REDIRECT", "--to-ports", &port])?;
+    }
+    run_iptables(&["-A", "OUTPUT", "-p", "tcp", "--dport", "853", "-j", "REJECT", "--reject-with", "icmp-admin-prohibited"])?;
+    run_iptables(&["-A", "OUTPUT", "-p", "udp", "--dport", "853", "-j", "DROP"])?;
+    for proto in ["udp", "tcp"] {
+        for dport in ["53", "853"] {
+            run_ip6tables(&["-A", "OUTPUT", "-p", proto, "--dport", dport, "-j", "DROP"])?;
         }
-        Err(e) => {
-            eprintln!("[!] Failed to run iptables: {}", e);
-            Ok(())
+    }
+
+    println!("[+] {}", i18n::tr("iptables-rules-applied-dns-only", &[]));
+    report::action("DNS-only firewall rules applied via iptables");
+    Ok(())
+}
+
+/// Check whether the HULIOS redirect to the Tor TransPort (NAT or TPROXY)
+/// is installed. Returns None when the rules cannot be read (e.g. not running as root).
+pub fn rules_present() -> Option<bool> {
+    let output = Command::new("iptables")
+        .args(["-t", "nat", "-S", "OUTPUT"])
+        .run_output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    if String::from_utf8_lossy(&output.stdout).contains("--to-ports 9051") {
+        return Some(true);
+    }
+    let tproxy = Command::new("iptables")
+        .args(["-t", "mangle", "-S", TPROXY_CHAIN])
+        .stderr(Stdio::null())
+        .run_output()
+        .ok()?;
+    Some(String::from_utf8_lossy(&tproxy.stdout).contains("--on-port 9051"))
+}
+
+/// The DNS redirect and the OUTPUT DROP policy, read back from the kernel
+pub fn rule_checks() -> Option<RuleChecks> {
+    let rules = |table: &str| -> Option<String> {
+        let output = Command::new("iptables").args(["-t", table, "-S", "OUTPUT"]).run_output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    let nat = rules("nat")?;
+    let filter = rules("filter")?;
+    Some(RuleChecks {
+        redirect: nat.contains("--to-ports 9061") && rules_present()?,
+        kill_switch: filter.lines().any(|line| line == "-P OUTPUT DROP"),
+    })
+}
+
+/// Total packets rejected by the blocklist chains (IPv4 and IPv6)
+pub fn blocked_packets() -> Option<u64> {
+    let mut total = 0;
+    for binary in ["iptables", "ip6tables"] {
+        let output = Command::new(binary)
+            .args(["-L", BLOCK_CHAIN, "-v", "-n", "-x"])
+            .run_output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // Two header lines, then one line per rule starting with the packet count
+        total += String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(2)
+            .filter_map(|line| line.split_whitespace().next()?.parse::<u64>().ok())
+            .sum::<u64>();
+    }
+    Some(total)
+}
+
+/// Total packets dropped by the last rule of the ICMP chains (IPv4 and IPv6)
+pub fn icmp_dropped() -> Option<u64> {
+    let mut total = 0;
+    for binary in ["iptables", "ip6tables"] {
+        let output = Command::new(binary)
+            .args(["-L", ICMP_CHAIN, "-v", "-n", "-x"])
+            .stderr(Stdio::null())
+            .run_output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
         }
+        // "pkts bytes target ...": only the DROP rule, not the allowed types
+        total += String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip(2)
+            .filter(|line| line.split_whitespace().nth(2) == Some("DROP"))
+            .filter_map(|line| line.split_whitespace().next()?.parse::<u64>().ok())
+            .sum::<u64>();
+    }
+    Some(total)
+}
+
+/// Insert each probe rule into PROBE_CHAIN, check that the kernel kept it,
+/// then remove the chain again
+pub fn probe() -> Features {
+    for table in ["filter", "nat", "mangle"] {
+        let _ = run_iptables(&["-t", table, "-N", PROBE_CHAIN]);
+    }
+    let works = |table: &str, rule: &[&str]| {
+        let append = [&["-t", table, "-A", PROBE_CHAIN], rule].concat();
+        let check = [&["-t", table, "-C", PROBE_CHAIN], rule].concat();
+        run_iptables(&append).is_ok() && run_iptables(&check).is_ok()
+    };
+
+    let owner = works("filter", &["-m", "owner", "--uid-owner", "0", "-j", "RETURN"]);
+    let redirect = works("nat", &["-p", "tcp", "-j", "REDIRECT", "--to-ports", "9051"]);
+    let mark = format!("{:#x}", TPROXY_MARK);
+    let tproxy = works("mangle", &["-p", "tcp", "-j", "MARK", "--set-mark", &mark])
+        && works("mangle", &["-p", "tcp", "-m", "mark", "--mark", &mark, "-j", "TPROXY", "--on-ip", "127.0.0.1", "--on-port", "9051"]);
+    let state_match = [StateMatch::Native, StateMatch::Conntrack]
+        .into_iter()
+        .find(|&candidate| {
+            state_match(candidate, "ESTABLISHED")
+                .is_some_and(|state| works("filter", &[&state[..], &["-j", "RETURN"]].concat()))
+        })
+        .unwrap_or(StateMatch::Unavailable);
+
+    for table in ["filter", "nat", "mangle"] {
+        let _ = Command::new("iptables").args(["-t", table, "-F", PROBE_CHAIN]).stderr(Stdio::null()).run();
+        let _ = Command::new("iptables").args(["-t", table, "-X", PROBE_CHAIN]).stderr(Stdio::null()).run();
     }
+    Features { owner, redirect, tproxy, state_match, route_localnet: false }
+}
+
+/// Match arguments for the given conntrack states; None without state matching
+fn state_match(kind: StateMatch, states: &str) -> Option<[&str; 4]> {
+    match kind {
+        StateMatch::Native => Some(["-m", "state", "--state", states]),
+        StateMatch::Conntrack => Some(["-m", "conntrack", "--ctstate", states]),
+        StateMatch::Unavailable => None,
+    }
+}
+
+/// Matches for the packets of the SSH session kept open, if it is over
+/// IPv6 (`v6`) or IPv4: (sent by sshd, sent by the client)
+fn ssh_flows(policy: &Policy, v6: bool) -> Option<(Vec<String>, Vec<String>)> {
+    let ssh = policy.ssh_session.as_ref().filter(|ssh| ssh.peer.is_ipv6() == v6)?;
+    let flow = |from: SocketAddr, to: SocketAddr| -> Vec<String> {
+        ["-p", "tcp", "-s", &from.ip().to_string(), "--sport", &from.port().to_string(),
+            "-d", &to.ip().to_string(), "--dport", &to.port().to_string()]
+            .iter().map(|arg| arg.to_string()).collect()
+    };
+    Some((flow(ssh.local, ssh.peer), flow(ssh.peer, ssh.local)))
+}
+
+/// Match arguments for each kind of packet the LAN services send, only
+/// their TCP with `tcp_only`
+fn lan_flows(policy: &Policy, tcp_only: bool) -> Vec<Vec<String>> {
+    policy.lan_flows()
+        .into_iter()
+        .filter(|flow| !tcp_only || flow.protocol == "tcp")
+        .map(|flow| {
+            let ([a, b, c, d], prefix) = flow.network;
+            ["-p", flow.protocol, "-d", &format!("{}.{}.{}.{}/{}", a, b, c, d, prefix), "--dport", &port_arg(flow.ports)]
+                .iter().map(|arg| arg.to_string()).collect()
+        })
+        .collect()
+}
+
+/// Match arguments for what goes to each mesh VPN's peers: out its
+/// interface, and to its address range where it has a fixed one
+fn mesh_peers(policy: &Policy) -> Vec<Vec<String>> {
+    policy.mesh.iter().map(|mesh| {
+        let mut args = vec!["-o".to_string(), mesh.vpn.interface().to_string()];
+        if let Some(([a, b, c, d], prefix)) = mesh.vpn.network() {
+            args.extend(["-d".to_string(), format!("{}.{}.{}.{}/{}", a, b, c, d, prefix)]);
+        }
+        args
+    }).collect()
+}
+
+/// Match arguments for the UDP each mesh VPN's daemon sends from and to
+/// its ports, as its own user only
+fn mesh_daemon_udp(policy: &Policy) -> Vec<Vec<String>> {
+    let mut flows = Vec::new();
+    for mesh in &policy.mesh {
+        let uid = mesh.daemon_uid.to_string();
+        let (sports, dports) = mesh.vpn.udp_ports();
+        let ports = sports.iter().map(|port| ("--sport", port)).chain(dports.iter().map(|port| ("--dport", port)));
+        for (direction, port) in ports {
+            flows.push(["-p", "udp", "-m", "owner", "--uid-owner", &uid, direction, &port.to_string()]
+                .iter().map(|arg| arg.to_string()).collect());
+        }
+    }
+    flows
+}
+
+fn strs(args: &[String]) -> Vec<&str> {
+    args.iter().map(String::as_str).collect()
+}
+
+/// `--dport` argument for a port range
+fn port_arg((low, high): (u16, u16)) -> String {
+    if low == high { low.to_string() } else { format!("{}:{}", low, high) }
+}
+
+/// Run one iptables command. A rule that fails to apply is an error, never
+/// a warning: a half-applied ruleset must not be reported as working.
+fn run_iptables(args: &[&str]) -> Result<()> {
+    if rendering(false, args) {
+        return Ok(());
+    }
+    let output = Command::new("iptables")
+        .args(args)
+        .run_output()
+        .with_context(|| i18n::tr("iptables-failed-run-iptables-installed", &[]))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(i18n::tr("iptables-failed-run-doctor", &[("args", &args.join(" ")), ("stderr", stderr.trim())]));
+    }
+    Ok(())
 }
 
 fn run_ip6tables(args: &[&str]) -> Result<()> {
-    let status = Command::new("ip6tables").args(args).status();
+    if rendering(true, args) {
+        return Ok(());
+    }
+    let status = Command::new("ip6tables").args(args).run();
     match status {
         Ok(s) if s.success() => Ok(()),
         Ok(_) => Ok(()),
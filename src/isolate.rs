@@ -0,0 +1,293 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use users::get_current_uid;
+use users::os::unix::UserExt;
+use crate::config::{Config, FirewallMode};
+use crate::control::ControlClient;
+use crate::{cgroup, engine, firewall, i18n, lock, procfs, report};
+
+/// Apps started by `hulios run --isolated`, kept while their `hulios run` waits
+const REGISTRY_PATH: &str = "/run/hulios/isolated.json";
+
+/// First port handed to isolated apps; each gets three in a row for its
+/// SOCKSPort, TransPort and DNSPort
+const FIRST_PORT: u16 = 9070;
+
+/// Apps that can be isolated at once (ports 9070-9099)
+const SLOTS: u16 = 10;
+
+/// SessionGroup of the app in slot 0; Tor never lets streams of different
+/// session groups share a circuit
+const FIRST_SESSION_GROUP: u16 = 100;
+
+/// Parent of the apps' cgroups, which the firewall matches their sockets by
+const PARENT_GROUP: &str = "/hulios-run";
+
+/// Tor options that get one listener per isolated app
+const LISTENER_OPTIONS: [&str; 3] = ["SOCKSPort", "TransPort", "DNSPort"];
+
+/// An app running with Tor listeners of its own
+#[derive(Deserialize, Serialize, Clone)]
+pub struct App {
+    pub slot: u16,
+    /// The `hulios run` that started the app and cleans up after it
+    pub pid: u32,
+    pub command: String,
+    pub user: String,
+    /// The app's cgroup, e.g. /hulios-run/0
+    pub group: String,
+}
+
+impl App {
+    pub fn socks_port(&self) -> u16 {
+        FIRST_PORT + self.slot * 3
+    }
+
+    pub fn trans_port(&self) -> u16 {
+        self.socks_port() + 1
+    }
+
+    pub fn dns_port(&self) -> u16 {
+        self.socks_port() + 2
+    }
+
+    /// The app's listeners as torrc (option, value) pairs. IsolateSOCKSAuth
+    /// also separates SOCKS clients of the app that send distinct credentials.
+    pub fn listeners(&self, config: &Config) -> [(&'static str, String); 3] {
+        let group = FIRST_SESSION_GROUP + self.slot;
+        let onion_flag = if config.firewall.mode == FirewallMode::OnionOnly { " OnionTrafficOnly" } else { "" };
+        [
+            ("SOCKSPort", format!("127.0.0.1:{} IsolateSOCKSAuth SessionGroup={}{}", self.socks_port(), group, onion_flag)),
+            ("TransPort", format!("127.0.0.1:{} SessionGroup={}", self.trans_port(), group)),
+            ("DNSPort", format!("127.0.0.1:{} SessionGroup={}{}", self.dns_port(), group, onion_flag)),
+        ]
+    }
+}
+
+/// Whether a listener value such as "127.0.0.1:9070 SessionGroup=100" is
+/// one of the isolated apps' ports
+fn is_isolated_listener(value: &str) -> bool {
+    value
+        .split_whitespace()
+        .next()
+        .and_then(|addr| addr.rsplit(':').next()?.parse::<u16>().ok())
+        .is_some_and(|port| (FIRST_PORT..FIRST_PORT + SLOTS * 3).contains(&port))
+}
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios run --isolated`: run `command` as the sudo caller with Tor
+/// listeners of its own and its TCP and DNS redirected to them, then
+/// remove them once every process of the app has exited. Returns the
+/// app's exit code.
+pub fn run(command: &[String]) -> Result<i32> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let (program, args) = command.split_first().with_context(|| i18n::tr("isolate-no-command-run", &[]))?;
+    let user = invoking_user()?;
+    let name = user.name().to_string_lossy().into_owned();
+
+    let app = {
+        let _lock = lock::acquire()?;
+        if !engine::is_tor_running() {
+            anyhow::bail!(i18n::tr("error-not-running", &[]));
+        }
+        if engine::tproxy_active() {
+            anyhow::bail!(i18n::tr("isolate-isolated-apps-need-nat", &[]));
+        }
+        let config = Config::load()?;
+        let mut apps = active();
+        let slot = (0..SLOTS)
+            .find(|&slot| {
+                // A group left behind by a killed `hulios run` may still hold processes
+                !apps.iter().any(|app| app.slot == slot) && cgroup::members(&group(slot)).is_empty()
+            })
+            .with_context(|| i18n::tr("isolate-isolated-apps-already-running", &[("slots", &SLOTS.to_string())]))?;
+        let app = App { slot, pid: std::process::id(), command: command.join(" "), user: name.clone(), group: group(slot) };
+        cgroup::create(&app.group)?;
+        apps.push(app.clone());
+        if let Err(e) = register(&config, &apps) {
+            apps.pop();
+            let _ = register(&config, &apps);
+            cgroup::remove(&app.group);
+            return Err(e);
+        }
+        app
+    };
+
+    println!("{}", format!("[+] {}", i18n::tr("isolate-running-own-circuits", &[("program", program)])).green());
+    println!("    {}", i18n::tr("isolate-ports", &[("socks_port", &app.socks_port().to_string()), ("trans_port", &app.trans_port().to_string()), ("dns_port", &app.dns_port().to_string())]));
+    report::action(format!("Isolated {} on ports {}-{}", app.command, app.socks_port(), app.dns_port()));
+
+    let status = spawn(&app, program, args, &user).and_then(|mut child| Ok(child.wait()?));
+    if status.is_ok() && !cgroup::members(&app.group).is_empty() {
+        // Launchers that fork leave the app running in its group
+        println!("[*] {}", i18n::tr("isolate-exited-waiting-processes-left", &[("program", program)]));
+    }
+    while !cgroup::members(&app.group).is_empty() {
+        thread::sleep(Duration::from_secs(1));
+    }
+    unregister(&app)?;
+    println!("[+] {}", i18n::tr("isolate-isolated-listeners-removed", &[("program", program)]));
+
+    let status = status?;
+    Ok(status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+}
+
+/// The app's process, in its group and as `user` before it execs.
+/// Ctrl-C reaches the app, which gets a clean signal mask; `hulios run`
+/// itself ignores it to clean up after the app.
+fn spawn(app: &App, program: &str, args: &[String], user: &users::User) -> Result<std::process::Child> {
+    let procs = cgroup::procs_file(&app.group).with_context(|| i18n::tr("error-no-cgroup2", &[]))?;
+    let procs = CString::new(procs.into_os_string().into_vec())?;
+    let uid = user.uid();
+    let gid = user.primary_group_id();
+    let groups = supplementary_groups(user)?;
+    let proxy = format!("socks5h://127.0.0.1:{}", app.socks_port());
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("HOME", user.home_dir())
+        .env("USER", user.name())
+        .env("LOGNAME", user.name())
+        .env("ALL_PROXY", &proxy)
+        .env("all_proxy", &proxy);
+    // SAFETY: between fork and exec only async-signal-safe calls are made
+    // (open, write, close, setgroups, setgid, setuid), on data prepared before
+    unsafe {
+        command.pre_exec(move || {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // "0" moves the writing process
+            let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+            libc::close(fd);
+            if written != 1
+                || libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                || libc::setgid(gid) != 0
+                || libc::setuid(uid) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    ignore_terminal_signals();
+    command.spawn().with_context(|| i18n::tr("isolate-failed-run", &[("program", program)]))
+}
+
+/// Block SIGINT, SIGQUIT and SIGHUP in this process; the terminal sends
+/// them to the app as well, which exits on its own
+fn ignore_terminal_signals() {
+    // SAFETY: plain signal mask manipulation on a local set
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in [libc::SIGINT, libc::SIGQUIT, libc::SIGHUP] {
+            libc::sigaddset(&mut set, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+    }
+}
+
+/// The groups `user` is a member of. users::get_user_groups can't be used:
+/// it also returns the zeroed, unused end of its buffer, i.e. root's group.
+fn supplementary_groups(user: &users::User) -> Result<Vec<libc::gid_t>> {
+    let name = CString::new(user.name().as_bytes())?;
+    let mut groups: Vec<libc::gid_t> = vec![0; 256];
+    let mut count = groups.len() as libc::c_int;
+    // SAFETY: `groups` has room for `count` entries; getgrouplist sets
+    // `count` to the number it wrote
+    if unsafe { libc::getgrouplist(name.as_ptr(), user.primary_group_id(), groups.as_mut_ptr(), &mut count) } < 0 {
+        anyhow::bail!(i18n::tr("isolate-more-than-groups", &[("name", &user.name().to_string_lossy()), ("count", &groups.len().to_string())]));
+    }
+    groups.truncate(count as usize);
+    Ok(groups)
+}
+
+/// The sudo caller, who the app runs as; root from a root shell
+fn invoking_user() -> Result<users::User> {
+    match std::env::var("SUDO_USER") {
+        Ok(name) => users::get_user_by_name(&name).with_context(|| i18n::tr("error-no-user", &[("user", &name)])),
+        Err(_) => users::get_user_by_uid(0).with_context(|| i18n::tr("isolate-cannot-look-up-root", &[])),
+    }
+}
+
+fn group(slot: u16) -> String {
+    format!("{}/{}", PARENT_GROUP, slot)
+}
+
+// =============================================================================
+// Session
+// =============================================================================
+
+/// The registered apps whose `hulios run` is still waiting on them
+pub fn active() -> Vec<App> {
+    let content = fs::read_to_string(REGISTRY_PATH).unwrap_or_default();
+    let apps: Vec<App> = serde_json::from_str(&content).unwrap_or_default();
+    apps.into_iter()
+        .filter(|app| procfs::inspect(app.pid as i32).is_some() && cgroup::exists(&app.group))
+        .collect()
+}
+
+/// Record `apps` and bring Tor's listeners and the firewall in line with them
+fn register(config: &Config, apps: &[App]) -> Result<()> {
+    let tmp = format!("{}.tmp", REGISTRY_PATH);
+    fs::write(&tmp, serde_json::to_string(apps)?)?;
+    fs::rename(&tmp, REGISTRY_PATH).with_context(|| i18n::tr("error-write", &[("path", REGISTRY_PATH)]))?;
+    apply_listeners(config, apps).with_context(|| i18n::tr("isolate-tor-refused-isolated-listeners", &[]))?;
+    firewall::backend(config).isolate(apps)
+}
+
+/// Drop `app` from the session; the firewall and Tor are only updated
+/// while HULIOS runs (stop already removed both)
+fn unregister(app: &App) -> Result<()> {
+    let _lock = lock::acquire()?;
+    let apps: Vec<App> = active().into_iter().filter(|other| other.slot != app.slot).collect();
+    if engine::is_tor_running() {
+        register(&Config::load()?, &apps)?;
+    } else {
+        fs::write(REGISTRY_PATH, serde_json::to_string(&apps)?)?;
+    }
+    cgroup::remove(&app.group);
+    if apps.is_empty() {
+        cgroup::remove(PARENT_GROUP);
+    }
+    report::action(format!("Isolated listeners of {} removed", app.command));
+    Ok(())
+}
+
+/// Give the running Tor exactly the listeners of `apps`, next to its own.
+/// SETCONF replaces every value of an option, so Tor's current ones are
+/// read back first; listeners that stay are kept open.
+fn apply_listeners(config: &Config, apps: &[App]) -> Result<()> {
+    let mut client = ControlClient::connect()?;
+    let mut settings = Vec::new();
+    for option in LISTENER_OPTIONS {
+        for line in client.command(&format!("GETCONF {}", option))? {
+            match line.split_once('=') {
+                Some((_, value)) if !is_isolated_listener(value) => settings.push((option, value.to_string())),
+                _ => {}
+            }
+        }
+        for app in apps {
+            settings.extend(app.listeners(config).into_iter().filter(|(name, _)| *name == option));
+        }
+    }
+    client.setconf(&settings)
+}
@@ -0,0 +1,94 @@
+use anyhow::{Result, Context};
+use std::os::unix::net::UnixDatagram;
+use std::sync::OnceLock;
+use crate::i18n;
+use crate::privacy;
+
+/// journald's native protocol socket
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Entries are tagged with this, so `journalctl -t hulios` finds them
+const IDENTIFIER: &str = "hulios";
+
+/// Set once the daemon logs to the journal
+static JOURNAL: OnceLock<Journal> = OnceLock::new();
+
+struct Journal {
+    socket: UnixDatagram,
+    scrub: bool,
+}
+
+/// syslog priorities HULIOS logs at
+#[derive(Clone, Copy)]
+pub enum Priority {
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+impl Priority {
+    /// The marker the same line carries in a plain log
+    fn prefix(self) -> &'static str {
+        match self {
+            Priority::Warning => "[!]",
+            Priority::Notice => "[+]",
+            Priority::Info => "[*]",
+        }
+    }
+}
+
+/// Log to journald from now on. `log` sends its fields as they are, and
+/// every other line written to stdout or stderr is forwarded with the
+/// priority its [!]/[+]/[*] marker implies. With `scrub`, messages and
+/// fields go through privacy::scrub first.
+pub fn open(scrub: bool) -> Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(SOCKET).with_context(|| i18n::tr("journal-journald-not-listening", &[("socket", SOCKET)]))?;
+    let _ = JOURNAL.set(Journal { socket, scrub });
+
+    privacy::capture_output(|line| {
+        let (priority, message) = [Priority::Warning, Priority::Notice, Priority::Info]
+            .into_iter()
+            .find_map(|priority| line.strip_prefix(priority.prefix()).map(|rest| (priority, rest.trim_start())))
+            .unwrap_or((Priority::Info, line));
+        log(priority, message, &[]);
+    })
+}
+
+/// Log `message` with extra `fields` (uppercase names, e.g. TOR_PID) that
+/// `journalctl` can filter on. Without the journal the message goes to
+/// stdout as a timestamped plain line and the fields are dropped.
+pub fn log(priority: Priority, message: &str, fields: &[(&str, &str)]) {
+    let Some(journal) = JOURNAL.get() else {
+        let line = format!("{} {} {}", priority.prefix(), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+        match priority {
+            Priority::Warning => eprintln!("{}", line),
+            _ => println!("{}", line),
+        }
+        return;
+    };
+    let clean = |value: &str| if journal.scrub { privacy::scrub(value) } else { value.to_string() };
+
+    let mut entry = Vec::new();
+    append(&mut entry, "PRIORITY", &(priority as u8).to_string());
+    append(&mut entry, "SYSLOG_IDENTIFIER", IDENTIFIER);
+    append(&mut entry, "MESSAGE", &clean(message));
+    for (name, value) in fields {
+        append(&mut entry, name, &clean(value));
+    }
+    let _ = journal.socket.send(&entry);
+}
+
+/// One field in the native format: NAME=value, or for values spanning lines,
+/// NAME, the little-endian 64-bit length and the raw value
+fn append(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use serde::Serialize;
+use crate::appliance;
+use crate::config::{Config, GuestPolicy};
+
+/// Where libvirtd keeps the live definition of each started network
+const NETWORK_STATUS_DIR: &str = "/run/libvirt/network";
+
+/// Name libvirt gives the bridges of the networks it creates
+pub const BRIDGE_PREFIX: &str = "virbr";
+
+/// A bridge of libvirt guests and what they may reach
+#[derive(Clone, Serialize)]
+pub struct Guests {
+    pub bridge: String,
+    pub policy: GuestPolicy,
+}
+
+// =============================================================================
+// Detection
+// =============================================================================
+
+/// The bridges of libvirt's running networks: those named in their live
+/// definitions, plus any virbr* bridge
+pub fn bridges() -> Vec<String> {
+    let mut bridges: Vec<String> = fs::read_dir(NETWORK_STATUS_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "xml"))
+        .filter_map(|entry| bridge_name(&fs::read_to_string(entry.path()).ok()?))
+        .collect();
+    for entry in fs::read_dir("/sys/class/net").into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(BRIDGE_PREFIX) && entry.path().join("bridge").exists() {
+            bridges.push(name);
+        }
+    }
+    // A definition can outlive its bridge briefly
+    bridges.retain(|bridge| Path::new("/sys/class/net").join(bridge).exists());
+    bridges.sort();
+    bridges.dedup();
+    bridges
+}
+
+/// `<bridge name='virbr0' stp='on' delay='0'/>`
+fn bridge_name(xml: &str) -> Option<String> {
+    let tag = &xml[xml.find("<bridge ")?..];
+    let tag = &tag[..tag.find('>')?];
+    let value = &tag[tag.find("name=")? + "name=".len()..];
+    let quote = value.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    value[1..].split(quote).next().map(str::to_string)
+}
+
+// =============================================================================
+// Policy
+// =============================================================================
+
+/// Each detected bridge with its policy from `[libvirt]`
+pub fn guests(config: &Config) -> Vec<Guests> {
+    bridges()
+        .into_iter()
+        .map(|bridge| {
+            let policy = config.libvirt.bridges.get(&bridge).copied().unwrap_or(config.libvirt.guests);
+            Guests { bridge, policy }
+        })
+        .collect()
+}
+
+/// Addresses Tor listens on for torified guests: their bridges' own, which
+/// their connections are redirected to
+pub fn listen_addresses(config: &Config) -> Vec<Ipv4Addr> {
+    guests(config)
+        .iter()
+        .filter(|guests| guests.policy == GuestPolicy::Torify)
+        .filter_map(|guests| appliance::lan_address(&guests.bridge).ok())
+        .collect()
+}
+
+/// Whether bridges that appear after the rules were applied are dropped
+/// (until the next reload) rather than left to libvirt's NAT
+pub fn drops_new_bridges(config: &Config) -> bool {
+    config.libvirt.guests != GuestPolicy::Clearnet
+}
@@ -0,0 +1,47 @@
+use anyhow::{Result, Context};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use crate::i18n;
+
+pub const RUN_DIR: &str = "/run/hulios";
+const LOCK_PATH: &str = "/run/hulios/lock";
+
+/// Exclusive lock serializing state-changing HULIOS invocations.
+/// Released automatically when dropped (or when the process exits).
+pub struct Lock {
+    _file: File,
+}
+
+/// Take the global lock, waiting for any other invocation to finish first
+pub fn acquire() -> Result<Lock> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(RUN_DIR)
+        .with_context(|| i18n::tr("error-create", &[("path", RUN_DIR)]))?;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .mode(0o600)
+        .open(LOCK_PATH)
+        .with_context(|| i18n::tr("error-open", &[("path", LOCK_PATH)]))?;
+
+    // SAFETY: flock on a file descriptor we own
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(err).with_context(|| i18n::tr("lock-failed-lock-state", &[]));
+        }
+
+        println!("[*] {}", i18n::tr("lock-another-command-running-waiting", &[]));
+        // SAFETY: as above, now blocking
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| i18n::tr("lock-failed-lock-state", &[]));
+        }
+    }
+
+    Ok(Lock { _file: file })
+}
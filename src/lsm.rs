@@ -0,0 +1,413 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+use users::get_current_uid;
+use crate::config::Config;
+use crate::engine::{self, TORRC_PATH, TOR_DATA_DIR, TOR_LOG};
+use crate::exec::Run;
+use crate::i18n;
+use crate::preflight::{Finding, Severity};
+use crate::report;
+
+const SELINUX_ENFORCE: &str = "/sys/fs/selinux/enforce";
+const APPARMOR_ENABLED: &str = "/sys/module/apparmor/parameters/enabled";
+const APPARMOR_PROFILES: &str = "/sys/kernel/security/apparmor/profiles";
+const APPARMOR_DIR: &str = "/etc/apparmor.d";
+const AUDIT_LOG: &str = "/var/log/audit/audit.log";
+
+/// The SELinux module's name; `semodule -i` names it after the file
+const MODULE: &str = "hulios";
+
+/// Where semodule keeps installed modules, per policy type
+const SELINUX_STORE: &str = "/var/lib/selinux";
+
+/// Fedora's policy for tor confines it to its own directories and the
+/// tor ports (9050, 9051): label HULIOS's files under /tmp as tor's, and
+/// let it bind the control (9052) and DNS (9061) ports and set up TPROXY
+/// sockets
+const SELINUX_MODULE: &str = r#"(filecon "/tmp/hulios_tor_data(/.*)?" any (system_u object_r tor_var_lib_t ((s0) (s0))))
+(filecon "/tmp/hulios_torrc" file (system_u object_r tor_etc_t ((s0) (s0))))
+(filecon "/tmp/tor_debug.log" file (system_u object_r tor_var_log_t ((s0) (s0))))
+(allow tor_t unreserved_port_t (tcp_socket (name_bind)))
+(allow tor_t unreserved_port_t (udp_socket (name_bind)))
+(allow tor_t self (capability (net_admin)))
+"#;
+
+/// Added to an AppArmor profile's local/ include, between the markers
+const APPARMOR_BEGIN: &str = "# HULIOS begin";
+const APPARMOR_END: &str = "# HULIOS end";
+const APPARMOR_RULES: &str = "/tmp/hulios_tor_data/ rw,
+/tmp/hulios_tor_data/** rwk,
+/tmp/hulios_torrc r,
+/tmp/tor_debug.log w,
+capability net_admin,
+";
+
+/// Denials this old are still shown by `hulios doctor`
+const RECENT: u64 = 3600;
+
+/// A loaded AppArmor profile attached to the tor binary
+struct Profile {
+    name: String,
+    /// "enforce" or "complain"
+    mode: String,
+}
+
+// =============================================================================
+// Detection
+// =============================================================================
+
+/// SELinux's mode: Some(true) enforcing, Some(false) permissive, None off
+fn selinux() -> Option<bool> {
+    Some(fs::read_to_string(SELINUX_ENFORCE).ok()?.trim() == "1")
+}
+
+/// Whether the HULIOS module is installed under any policy type
+fn module_installed() -> bool {
+    fs::read_dir(SELINUX_STORE).into_iter().flatten().flatten()
+        .any(|policy| policy.path().join("active/modules/400").join(MODULE).exists())
+}
+
+/// The SELinux label of a file, e.g. "system_u:object_r:tor_exec_t:s0"
+fn label(path: &str) -> Option<String> {
+    let path = CString::new(path).ok()?;
+    let name = CString::new("security.selinux").ok()?;
+    let mut value = [0u8; 256];
+    // SAFETY: both names are NUL-terminated, the buffer's length is passed
+    let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), value.as_mut_ptr().cast(), value.len()) };
+    if len <= 0 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&value[..len as usize]).trim_end_matches('\0').to_string())
+}
+
+/// The loaded AppArmor profile attached to `binary`; /sys lists them as
+/// "name (mode)", and a profile attaches to the path it is named after
+fn apparmor_profile(binary: &str) -> Option<Profile> {
+    if fs::read_to_string(APPARMOR_ENABLED).ok()?.trim() != "Y" {
+        return None;
+    }
+    fs::read_to_string(APPARMOR_PROFILES).ok()?.lines().find_map(|line| {
+        let (name, mode) = line.rsplit_once(' ')?;
+        (name == binary).then(|| Profile {
+            name: name.to_string(),
+            mode: mode.trim_matches(|c| c == '(' || c == ')').to_string(),
+        })
+    })
+}
+
+/// The profile's file, e.g. /etc/apparmor.d/usr.bin.tor, and its local/ include
+fn apparmor_files(profile: &Profile) -> (PathBuf, PathBuf) {
+    let file = profile.name.trim_start_matches('/').replace('/', ".");
+    (Path::new(APPARMOR_DIR).join(&file), Path::new(APPARMOR_DIR).join("local").join(&file))
+}
+
+fn apparmor_installed(profile: &Profile) -> bool {
+    fs::read_to_string(apparmor_files(profile).1).is_ok_and(|content| content.contains(APPARMOR_BEGIN))
+}
+
+/// For `hulios doctor` and the start: an enforcing SELinux whose policy
+/// confines tor, or an AppArmor profile on the tor binary, without what
+/// HULIOS's Tor needs
+pub fn findings(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let Ok(binary) = engine::resolve_tor_binary(&config.tor) else {
+        return findings;
+    };
+    let confined = label(&binary).is_some_and(|label| label.contains(":tor_exec_t:"));
+    if selinux() == Some(true) && confined && !module_installed() {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            problem: i18n::tr("lsm-selinux-enforcing-policy-confines", &[]),
+            guidance: i18n::tr("lsm-policy-keeps-tor-away", &[("path", TOR_DATA_DIR)]),
+        });
+    }
+    if let Some(profile) = apparmor_profile(&binary).filter(|profile| !apparmor_installed(profile)) {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            problem: i18n::tr("lsm-apparmor-profile-confines-tor", &[("name", &profile.name), ("mode", &profile.mode)]),
+            guidance: i18n::tr("lsm-does-not-allow-so", &[("path", TOR_DATA_DIR)]),
+        });
+    }
+    findings
+}
+
+// =============================================================================
+// Denials
+// =============================================================================
+
+/// One access to tor refused by SELinux or AppArmor
+struct Denial {
+    lsm: &'static str,
+    /// The permission or operation, e.g. "write", "name_bind"
+    operation: String,
+    /// The path, file name, port or capability
+    target: String,
+    /// Whether the target is a file under /tmp
+    in_tmp: bool,
+}
+
+/// The value of `key=` in an audit record, without quotes
+fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_whitespace()
+        .find_map(|word| word.strip_prefix(key)?.strip_prefix('='))
+        .map(|value| value.trim_matches('"'))
+}
+
+/// "avc:  denied  { write } for pid=1 comm="tor" name="hulios_tor_data"
+/// ... tclass=dir" from SELinux, or "apparmor="DENIED" operation="mknod"
+/// profile="/usr/bin/tor" name="/tmp/hulios_tor_data/lock" comm="tor"" from
+/// AppArmor
+fn parse_denial(line: &str) -> Option<Denial> {
+    if field(line, "comm") != Some("tor") {
+        return None;
+    }
+    if line.contains("avc:") && line.contains("denied") {
+        let operation = line.split_once('{')?.1.split_once('}')?.0.trim().to_string();
+        let target = field(line, "name")
+            .map(str::to_string)
+            .or_else(|| field(line, "src").map(|port| i18n::tr("lsm-port", &[("port", port)])))
+            .or_else(|| field(line, "capability").map(|number| match number {
+                "12" => "net_admin".to_string(),
+                other => i18n::tr("lsm-capability", &[("capability", other)]),
+            }))
+            .unwrap_or_else(|| field(line, "tclass").unwrap_or("?").to_string());
+        // SELinux logs only the file name; its label tells where it is
+        let in_tmp = field(line, "tcontext").is_some_and(|context| context.contains("tmp_t"));
+        return Some(Denial { lsm: "SELinux", operation, target, in_tmp });
+    }
+    if field(line, "apparmor") == Some("DENIED") {
+        let operation = field(line, "operation").unwrap_or("?").to_string();
+        let target = field(line, "name").or(field(line, "capname")).unwrap_or("?").to_string();
+        let in_tmp = target.starts_with("/tmp/");
+        return Some(Denial { lsm: "AppArmor", operation, target, in_tmp });
+    }
+    None
+}
+
+/// tor's denials since `since`: from the audit log, or from the kernel log
+/// where auditd doesn't run
+fn denials(since: SystemTime) -> Vec<Denial> {
+    let since = since.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let lines = match fs::read_to_string(AUDIT_LOG) {
+        Ok(log) => log.lines()
+            // "msg=audit(1700000000.123:456):"
+            .filter(|line| field(line, "msg")
+                .and_then(|msg| msg.strip_prefix("audit(")?.split('.').next()?.parse::<u64>().ok())
+                .is_some_and(|time| time >= since))
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+        Err(_) => Command::new("journalctl")
+            .args(["-k", "-q", "--no-pager", "--since", &format!("@{}", since)])
+            .stderr(Stdio::null())
+            .run_output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+            .unwrap_or_default(),
+    };
+    let mut found: Vec<Denial> = Vec::new();
+    for denial in lines.iter().filter_map(|line| parse_denial(line)) {
+        if !found.iter().any(|seen| seen.operation == denial.operation && seen.target == denial.target) {
+            found.push(denial);
+        }
+    }
+    found
+}
+
+/// What a denial means for HULIOS's Tor
+fn explain(denial: &Denial) -> String {
+    if denial.in_tmp {
+        i18n::tr("lsm-denied-tor-keeps-tor", &[("lsm", denial.lsm), ("operation", &denial.operation), ("target", &denial.target)])
+    } else if denial.operation == "name_bind" || denial.operation == "bind" {
+        i18n::tr("lsm-denied-tor-binding-policy", &[("lsm", denial.lsm), ("target", &denial.target)])
+    } else if denial.target == "net_admin" {
+        i18n::tr("lsm-denied-tor-net-admin", &[("lsm", denial.lsm)])
+    } else {
+        i18n::tr("lsm-denied-tor", &[("lsm", denial.lsm), ("operation", &denial.operation), ("target", &denial.target)])
+    }
+}
+
+/// Print tor's denials since `since`, each explained; false if none
+pub fn explain_denials(since: SystemTime) -> bool {
+    let found = denials(since);
+    for denial in &found {
+        let explanation = explain(denial);
+        eprintln!("[!] {}", explanation);
+        report::warning(explanation);
+    }
+    if !found.is_empty() {
+        eprintln!("    {}", i18n::tr("lsm-fix-sudo-lsm-install", &[]));
+    }
+    !found.is_empty()
+}
+
+/// For `hulios doctor`: tor's denials of the last hour
+pub fn recent_denials() {
+    let since = SystemTime::now() - std::time::Duration::from_secs(RECENT);
+    if denials(since).is_empty() {
+        return;
+    }
+    println!("{}", format!("[!] {}", i18n::tr("lsm-tor-was-denied-access", &[])).yellow());
+    explain_denials(since);
+}
+
+// =============================================================================
+// Launch
+// =============================================================================
+
+/// Before Tor starts: files created under /tmp get its label, not the one
+/// the module gives them, until they are relabeled
+pub fn relabel() {
+    if selinux().is_none() || !module_installed() {
+        return;
+    }
+    let status = Command::new("restorecon")
+        .args(["-R", TOR_DATA_DIR, TORRC_PATH, TOR_LOG])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .run();
+    if !status.is_ok_and(|status| status.success()) {
+        eprintln!("[!] {}", i18n::tr("lsm-could-not-relabel-tor", &[]));
+        report::warning("Tor's files could not be relabeled for SELinux");
+    }
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios lsm status`
+pub fn status(config: &Config) -> Result<()> {
+    let binary = engine::resolve_tor_binary(&config.tor)?;
+    match selinux() {
+        Some(enforcing) => {
+            let mode = if enforcing { "enforcing" } else { "permissive" };
+            let label = label(&binary).unwrap_or_else(|| "unlabeled".to_string());
+            println!("[*] {}", i18n::tr("lsm-selinux", &[("mode", mode), ("binary", &binary), ("label", &label)]));
+            if module_installed() {
+                println!("{}", format!("[+] {}", i18n::tr("lsm-policy-module-installed", &[])).green());
+            } else {
+                println!("[*] {}", i18n::tr("lsm-policy-module-not-installed", &[]));
+            }
+        }
+        None => println!("[*] {}", i18n::tr("lsm-selinux-not-enabled", &[])),
+    }
+    match apparmor_profile(&binary) {
+        Some(profile) => {
+            println!("[*] {}", i18n::tr("lsm-apparmor-confined-profile", &[("binary", &binary), ("name", &profile.name), ("mode", &profile.mode)]));
+            if apparmor_installed(&profile) {
+                println!("{}", format!("[+] {}", i18n::tr("lsm-rules-local-include", &[])).green());
+            } else {
+                println!("[*] {}", i18n::tr("lsm-rules-not-local-include", &[]));
+            }
+        }
+        None => println!("[*] {}", i18n::tr("lsm-apparmor-no-profile-confines", &[("binary", &binary)])),
+    }
+    Ok(())
+}
+
+/// `hulios lsm install`: the SELinux module, and HULIOS's rules in the
+/// AppArmor profile confining tor
+pub fn install(config: &Config) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let binary = engine::resolve_tor_binary(&config.tor)?;
+    let mut installed = false;
+    if selinux().is_some() {
+        install_selinux()?;
+        installed = true;
+    }
+    if let Some(profile) = apparmor_profile(&binary) {
+        install_apparmor(&profile)?;
+        installed = true;
+    }
+    if !installed {
+        println!("[*] {}", i18n::tr("lsm-neither-selinux-nor-apparmor", &[("binary", &binary)]));
+    }
+    Ok(())
+}
+
+fn install_selinux() -> Result<()> {
+    // semodule names the module after the file
+    let dir = std::env::temp_dir().join(format!("hulios-lsm-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let file = dir.join(format!("{}.cil", MODULE));
+    fs::write(&file, SELINUX_MODULE)?;
+    let status = Command::new("semodule").arg("-i").arg(&file).run();
+    let _ = fs::remove_dir_all(&dir);
+    if !status.with_context(|| i18n::tr("lsm-failed-run-semodule-policycoreutils", &[]))?.success() {
+        anyhow::bail!(i18n::tr("lsm-semodule-could-not-install", &[]));
+    }
+    println!("{}", format!("[+] {}", i18n::tr("lsm-installed-selinux-policy-module", &[])).green());
+    report::action("Installed the SELinux policy module hulios");
+    Ok(())
+}
+
+fn install_apparmor(profile: &Profile) -> Result<()> {
+    let (file, local) = apparmor_files(profile);
+    let include = format!("local/{}", local.file_name().unwrap_or_default().to_string_lossy());
+    let content = fs::read_to_string(&file)
+        .with_context(|| i18n::tr("lsm-profile-not-add-rules", &[("name", &profile.name), ("file", &file.display().to_string())]))?;
+    if !content.contains(&include) {
+        anyhow::bail!("{}\n{}", i18n::tr("lsm-include-missing", &[("file", &file.display().to_string()), ("include", &include)]), APPARMOR_RULES);
+    }
+    let existing = fs::read_to_string(&local).unwrap_or_default();
+    if !existing.contains(APPARMOR_BEGIN) {
+        let mut content = existing;
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("{}\n{}{}\n", APPARMOR_BEGIN, APPARMOR_RULES, APPARMOR_END));
+        fs::write(&local, content).with_context(|| i18n::tr("error-write", &[("path", &local.display().to_string())]))?;
+    }
+    reload_apparmor(&file)?;
+    println!("{}", format!("[+] {}", i18n::tr("lsm-added-rules", &[("local", &local.display().to_string())])).green());
+    report::action(format!("Added HULIOS's rules to {}", local.display()));
+    Ok(())
+}
+
+fn reload_apparmor(file: &Path) -> Result<()> {
+    let status = Command::new("apparmor_parser").arg("-r").arg(file).run()
+        .with_context(|| i18n::tr("lsm-failed-run-apparmor-parser", &[]))?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("lsm-apparmor-parser-could-not", &[("file", &file.display().to_string())]));
+    }
+    Ok(())
+}
+
+/// `hulios lsm remove`: undo `install`; uninstall calls it too
+pub fn remove(config: &Config) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if module_installed() {
+        let status = Command::new("semodule").args(["-r", MODULE]).run()
+            .with_context(|| i18n::tr("lsm-failed-run-semodule", &[]))?;
+        if !status.success() {
+            anyhow::bail!(i18n::tr("lsm-semodule-could-not-remove", &[]));
+        }
+        println!("[*] {}", i18n::tr("lsm-removed-selinux-policy-module", &[]));
+        report::action("Removed the SELinux policy module hulios");
+    }
+    let Some(profile) = engine::resolve_tor_binary(&config.tor).ok().and_then(|binary| apparmor_profile(&binary)) else {
+        return Ok(());
+    };
+    let (file, local) = apparmor_files(&profile);
+    let Ok(content) = fs::read_to_string(&local) else {
+        return Ok(());
+    };
+    let (Some(begin), Some(end)) = (content.find(APPARMOR_BEGIN), content.find(APPARMOR_END)) else {
+        return Ok(());
+    };
+    let end = end + APPARMOR_END.len() + usize::from(content[end + APPARMOR_END.len()..].starts_with('\n'));
+    fs::write(&local, format!("{}{}", &content[..begin], &content[end..]))
+        .with_context(|| i18n::tr("error-write", &[("path", &local.display().to_string())]))?;
+    reload_apparmor(&file)?;
+    println!("[*] {}", i18n::tr("lsm-removed-rules", &[("local", &local.display().to_string())]));
+    report::action(format!("Removed HULIOS's rules from {}", local.display()));
+    Ok(())
+}
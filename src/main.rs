@@ -2,65 +2,1168 @@ use clap::{Parser, Subcommand};
 use colored::*;
 use std::process;
 
+mod alias;
+mod amnesic;
+mod appliance;
+mod audit;
+mod autostart;
+mod blocklist;
+mod breakage;
+mod browser;
+mod cgroup;
+mod circuits;
+mod confirm;
+mod confirmation;
+mod config;
+mod configtool;
+mod conntrack;
+mod control;
+mod daemon;
+mod debugbundle;
+mod diagnose;
+mod dnsonly;
+mod doh;
 mod engine;
+mod exec;
+mod exitlist;
+mod features;
+mod firewall;
+mod gateway;
+mod geoip;
+mod health;
+mod healthz;
+mod helper;
+mod i18n;
+mod i2p;
+mod inotify;
+mod ipc;
 mod iptables;
+mod isolate;
+mod journal;
+mod libvirt;
+mod lock;
+mod lsm;
+mod meshvpn;
+mod mode;
+mod netprofile;
+mod nftables;
+mod notify;
+mod onion;
+mod output;
+mod overlay;
+mod pkgproxy;
+mod power;
+mod preflight;
+mod privacy;
+mod privileged;
+mod procfs;
+mod proxyenv;
+mod relay;
+mod remote;
+mod render;
+mod report;
+mod resolvers;
+mod scheduling;
+mod sdnotify;
+mod setconf;
+mod sshguard;
+mod state;
+mod stats;
 mod status;
+mod torbrowser;
+mod tordata;
+mod top;
+mod tray;
+mod tuning;
+mod uninstall;
+mod update;
+mod validate;
+mod verify;
+mod version;
+mod workstation;
 
 #[derive(Parser)]
 #[command(name = "hulios")]
 #[command(about = "HULIOS: An engine to make Tor Network your default gateway", long_about = None)]
 struct Cli {
+    /// Don't ask for confirmation before changing the system
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
+
+    /// Never send desktop notifications
+    #[arg(short = 'q', long, global = true)]
+    quiet: bool,
+
+    /// Show external commands' output (-v), and trace each command with its exit status (-vv)
+    #[arg(short = 'v', long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Disable colored output (also honors NO_COLOR)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Plain, log-friendly output: no colors, no progress bars
+    #[arg(long, global = true)]
+    plain: bool,
+
+    /// Print one JSON object with the actions taken, warnings and resulting
+    /// state on stdout; human-readable output goes to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Override one config key for this command only, e.g. --set tor.bootstrap_timeout=120
+    /// (repeatable; applied after HULIOS__SECTION__KEY environment variables)
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    Start {
+        /// Skip the post-start leak check (DNS, exit, blocked direct traffic)
+        #[arg(long)]
+        skip_verify: bool,
+        /// Keep torrc, Tor's data, logs and session state on a private tmpfs
+        /// that is destroyed on stop
+        #[arg(long)]
+        amnesic: bool,
+        /// Only report what would change; exits 0 if nothing, 2 if changes are pending
+        #[arg(long)]
+        check: bool,
+        /// Also stop the distribution's tor.service (by default HULIOS refuses
+        /// to start if it holds Tor's ports, and otherwise leaves it running)
+        #[arg(long)]
+        stop_system_tor: bool,
+        /// Start even though this is an SSH session the firewall would cut
+        /// off (see firewall.keep_ssh_session)
+        #[arg(long)]
+        force_ssh_cutoff: bool,
+        /// Roll everything back unless `hulios confirm` runs within this many
+        /// seconds, for changes made over a connection the firewall may cut
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        confirm_within: Option<u64>,
+        /// Let printing, file sharing, KDE Connect and mDNS reach the local
+        /// network directly this session (see firewall.lan_services)
+        #[arg(long)]
+        allow_lan: bool,
+    },
+    Stop {
+        /// Only report what would change; exits 0 if nothing, 2 if changes are pending
+        #[arg(long)]
+        check: bool,
+        /// Also stop the distribution's tor.service, not just HULIOS's Tor
+        #[arg(long)]
+        stop_system_tor: bool,
+    },
+    Restart,
+    /// Keep a session started with --confirm-within, cancelling its rollback
+    Confirm,
+    /// Apply config changes to the running session (torrc over the control
+    /// port, firewall in place) without restarting Tor
+    Reload,
+    /// Show local state, then verify the exit through check.torproject.org
+    Status {
+        /// Only report local facts, skip the external Tor check
+        #[arg(long)]
+        no_network: bool,
+        /// Reuse a previous external check result for this many seconds (0 disables)
+        #[arg(long, default_value_t = 60)]
+        cache_ttl: u64,
+        /// Download fresh GeoIP databases through Tor
+        #[arg(long)]
+        refresh_geoip: bool,
+        /// Print only the first line's token (tor:<ip>, off, degraded...) for
+        /// prompts and status bars. Either way, exits 0 only when verified
+        /// torified: 1 leak, 2 degraded or failed, 3 off, 4 unverified.
+        #[arg(long)]
+        short: bool,
+    },
+    /// Clear the firewall rules and end the session at once
+    Flush,
+    /// Repair leftovers of an unclean shutdown and restore normal networking
+    Recover,
+    /// Remove every unit, hook, rule and state file HULIOS installed
+    Uninstall {
+        /// Also delete the configuration in /etc/hulios
+        #[arg(long)]
+        purge: bool,
+    },
+    /// Update HULIOS to the latest signed release
+    Update {
+        /// Only report whether an update is available; exits 2 if one is
+        #[arg(long)]
+        check: bool,
+    },
+    /// Limit Tor's bandwidth live, e.g. `hulios limit 2MB` ("off" removes it)
+    Limit {
+        rate: String,
+        /// Allowed burst above the rate, e.g. 4MB
+        #[arg(long)]
+        burst: Option<String>,
+    },
+    /// Get or set whitelisted Tor options live, kept in the config across
+    /// restarts: `hulios setconf MaxCircuitDirtiness=300` (`Name=` resets,
+    /// `Name` shows, no argument lists the options)
+    Setconf {
+        settings: Vec<String>,
+    },
+    /// Move connections to one site onto a fresh exit, e.g. when it blocks
+    /// the current one: `hulios newcircuit example.com`
+    Newcircuit {
+        /// Hostname, address or URL
+        host: String,
+    },
+    /// Exclude the exit a site rejected for the rest of the session:
+    /// `hulios badexit 203.0.113.7`; lists the excluded exits without one
+    Badexit {
+        /// Address the site saw the connection come from
+        address: Option<String>,
+    },
+    /// Show Tor traffic for this session, the last week and all time
+    Stats,
+    /// Past sessions: when, how much traffic, exit countries, blocked
+    /// packets and crashes
+    Report {
+        /// Only sessions started this long ago or later, e.g. 7d, 12h, 2w
+        #[arg(long)]
+        last: Option<String>,
+    },
+    /// Live view of the connections through Tor: process, circuit and
+    /// throughput of each
+    Top {
+        /// Show where each connection goes (hidden by default)
+        #[arg(long)]
+        destinations: bool,
+        /// Print one sample and exit
+        #[arg(long)]
+        once: bool,
+    },
+    /// Show, edit, set and validate the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Write the torrc, firewall ruleset and resolver files a start would
+    /// install, without running anything, for declarative systems
+    Render {
+        /// Directory to write them into
+        #[arg(long)]
+        out: String,
+    },
+    /// Check whether HULIOS can work here: WSL, containers, kernel modules
+    Doctor,
+    /// Let an SELinux policy or AppArmor profile that confines tor run
+    /// HULIOS's Tor
+    Lsm {
+        #[command(subcommand)]
+        action: LsmAction,
+    },
+    /// Show the version; --full adds the tor, iptables, init system and
+    /// distro details a bug report needs
+    Version {
+        #[arg(long)]
+        full: bool,
+    },
+    /// Gather logs, firewall rules, resolv.conf and doctor results into a
+    /// scrubbed tarball to attach to a bug report
+    DebugBundle {
+        /// Where to write it; hulios-debug-<time>.tar.gz by default
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check the running session for leaks, including browsers using DoH
+    Test,
+    /// Install browser policies against DoH, WebRTC UDP and prefetching
+    /// for this session (removed on stop)
+    HardenBrowser,
+    /// Manage the DNS-over-HTTPS provider blocklist and browser policies
+    Doh {
+        #[command(subcommand)]
+        action: DohAction,
+    },
+    /// Manage the cached Tor exit address list
+    Exitlist {
+        #[command(subcommand)]
+        action: ExitlistAction,
+    },
+    /// Print proxy variables for tools that don't follow transparent
+    /// redirection: `eval "$(hulios env)"`
+    Env,
+    /// Route apt, dnf or pacman through Tor's SOCKS port (removed on stop)
+    PkgProxy {
+        #[command(subcommand)]
+        action: PkgProxyAction,
+    },
+    /// Send ssh and git traffic for specific hosts through Tor's SOCKS port
+    Remote {
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+    /// ssh ProxyCommand used by `hulios remote`: relay stdin/stdout to
+    /// host:port through Tor
+    #[command(hide = true)]
+    Connect {
+        host: String,
+        port: u16,
+    },
+    /// Background watcher started by `hulios start`
+    #[command(hide = true)]
+    Daemon,
+    /// Run when a --confirm-within deadline passes
+    #[command(hide = true)]
+    ConfirmExpired {
+        /// Wait for the deadline first; started by `hulios start`
+        #[arg(long)]
+        wait: bool,
+    },
+    /// DNS and connections of the overlay networks (e.g. I2P), started by
+    /// `hulios start` when one is enabled
+    #[command(hide = true)]
+    OverlayRouter,
+    /// Run by the NetworkManager hook of `hulios autostart` when an
+    /// interface comes up
+    #[command(hide = true)]
+    NetworkEvent {
+        interface: String,
+    },
+    /// The DoH client of `hulios dns-only`
+    #[command(hide = true)]
+    DnsOnlyServer {
+        #[arg(long)]
+        url: String,
+        /// An address of the URL's host (repeatable)
+        #[arg(long = "address")]
+        addresses: Vec<String>,
+    },
+    /// Answer tray applets on the tray socket (run by systemd)
+    #[command(hide = true)]
+    TrayServer,
+    /// Print the session's control port and password for tools such as
+    /// nyx or OnionShare: `eval "$(sudo hulios controlsocket)"`
+    Controlsocket,
+    /// Run an app whose streams never share circuits with other traffic:
+    /// `sudo hulios run --isolated firefox`
+    Run {
+        /// Give the app SOCKS, Trans and DNS ports of its own and redirect
+        /// its TCP and DNS to them
+        #[arg(long, required = true)]
+        isolated: bool,
+        /// The app and its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Publish local services as onion services
+    Onion {
+        #[command(subcommand)]
+        action: OnionAction,
+    },
+    /// Run a second Tor as a non-exit relay or bridge for others,
+    /// separate from the session's Tor
+    Relay {
+        #[command(subcommand)]
+        action: RelayAction,
+    },
+    /// Run headless as the Tor gateway of a LAN (e.g. a Raspberry Pi)
+    Appliance {
+        #[command(subcommand)]
+        action: ApplianceAction,
+    },
+    /// Query or control a HULIOS gateway from another machine
+    Gateway {
+        #[command(subcommand)]
+        action: GatewayAction,
+    },
+    /// Use a HULIOS gateway (e.g. a gateway VM) instead of a local session:
+    /// route and resolve through it and let nothing else out
+    AttachGateway {
+        /// The gateway's address, on a network this machine is directly on
+        ip: String,
+    },
+    /// Undo attach-gateway and restore networking
+    DetachGateway,
+    /// Encrypt DNS without Tor: all DNS goes to a local DoH or dnscrypt
+    /// resolver, other traffic is left alone
+    DnsOnly {
+        #[command(subcommand)]
+        action: DnsOnlyAction,
+    },
+    /// Start on untrusted Wi-Fi networks and offer to stop on trusted ones
+    /// (NetworkManager)
+    Autostart {
+        #[command(subcommand)]
+        action: AutostartAction,
+    },
+    /// Show the network, the network profile bound to it and the one in effect
+    NetworkProfile,
+    /// Show whether the machine is on battery or a metered connection and
+    /// what `[power]` changes because of it
+    Power,
+    /// Show or switch the mode (off, full, dns-only); full and dns-only
+    /// switch into each other without restoring the normal network between
+    Mode {
+        #[command(subcommand)]
+        action: ModeAction,
+    },
+    /// Manage friendly names for onion services
+    Alias {
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+    /// Show the message language and the translations available
+    Locale {
+        #[command(subcommand)]
+        action: Option<LocaleAction>,
+    },
+    /// Let members of the 'hulios' group start and stop HULIOS without sudo
+    Helper {
+        #[command(subcommand)]
+        action: HelperAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective configuration (file merged over the defaults)
+    Show,
+    /// Open the config in $EDITOR and save it only if it validates
+    Edit,
+    /// Set one key, e.g. `hulios config set tor.bandwidth_rate "2 MB"`
+    Set {
+        /// Dotted key such as tor.bootstrap_timeout
+        key: String,
+        value: String,
+    },
+    /// Check the config for unknown keys, bad values and contradictions
+    Validate {
+        /// File to check instead of /etc/hulios/config.toml
+        #[arg(long)]
+        file: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExitlistAction {
+    /// Download a fresh exit list through Tor
+    Update,
+}
+
+#[derive(Subcommand)]
+enum DohAction {
+    /// Download a fresh provider list through Tor
+    Update,
+    /// Install Firefox/Chromium policies that turn DoH off
+    InstallPolicies,
+    /// Remove the browser policies again
+    RemovePolicies,
+}
+
+#[derive(Subcommand)]
+enum LsmAction {
+    /// Show whether SELinux or AppArmor confines tor, and what is installed
+    Status,
+    /// Install the SELinux policy module, or add HULIOS's rules to the
+    /// AppArmor profile confining tor
+    Install,
+    /// Remove them again
+    Remove,
+}
+
+#[derive(Subcommand)]
+enum PkgProxyAction {
+    /// Write proxy settings for every detected package manager
+    Enable,
+    /// Remove the proxy settings again
+    Disable,
+}
+
+#[derive(Subcommand)]
+enum RemoteAction {
+    /// Route a host through Tor, e.g. `hulios remote add git@xyz.onion:repo.git`
+    Add {
+        /// Hostname or remote URL (ssh or https)
+        host: String,
+    },
+    /// Remove the ssh and git entries for a host
+    Remove {
+        host: String,
+    },
+    /// Show the hosts routed through Tor
+    List,
+}
+
+#[derive(Subcommand)]
+enum OnionAction {
+    /// Reach this machine's sshd over Tor, for remote administration
+    Ssh {
+        #[command(subcommand)]
+        action: OnionSshAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum OnionSshAction {
+    /// Publish sshd with client authorization and print the client's key
+    Enable {
+        /// Name of the client key to create (replaces one of the same name)
+        #[arg(long, default_value = "admin")]
+        client: String,
+        /// Also make sshd listen on 127.0.0.1 only
+        #[arg(long)]
+        localhost_only: bool,
+    },
+    /// Stop publishing sshd and undo --localhost-only
+    Disable,
+    /// Show the address and authorized clients
+    Status,
+}
+
+#[derive(Subcommand)]
+enum RelayAction {
+    /// Start relaying with the [relay] settings of the config
     Start,
+    /// Stop relaying; the relay's keys are kept
     Stop,
-    Restart,
+    /// Show bootstrap, reachability and relayed traffic
+    Status,
+}
+
+#[derive(Subcommand)]
+enum DnsOnlyAction {
+    /// Start the resolver and lock DNS to it
+    Start,
+    /// Stop and restore normal DNS
+    Stop,
+    /// Show the resolver and whether it answers
     Status,
-    Flush,
+}
+
+#[derive(Subcommand)]
+enum AutostartAction {
+    /// Install the NetworkManager hook and act on connection events
+    Enable,
+    /// Remove the hook; a running session keeps running
+    Disable,
+    /// Never start on a Wi-Fi network
+    Trust {
+        /// Its SSID; the network connected now by default
+        ssid: Option<String>,
+    },
+    /// Start on a Wi-Fi network again
+    Untrust {
+        ssid: String,
+    },
+    /// Show whether autostart is on, the trusted networks and the current one
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ModeAction {
+    /// Print the current mode
+    Show,
+    /// Switch to another mode
+    Set {
+        mode: mode::Mode,
+    },
+}
+
+#[derive(Subcommand)]
+enum ApplianceAction {
+    /// Torify the LAN on an interface, start at boot and alert headlessly
+    Enable {
+        /// Interface facing the LAN, e.g. eth0
+        #[arg(long)]
+        lan: String,
+        /// Also POST alerts as JSON to this URL
+        #[arg(long)]
+        webhook: Option<String>,
+    },
+    /// Back to the desktop profile, no longer started at boot
+    Disable,
+    /// Show the profile, gateway mode and boot start
+    Status,
+}
+
+#[derive(Subcommand)]
+enum GatewayAction {
+    /// Create the key remote clients authenticate with (on the gateway)
+    Init,
+    /// Show the gateway's session health
+    Status {
+        /// The gateway's daemon.remote_control address, e.g. 192.168.1.1:9300
+        host: String,
+        /// Key to authenticate with [default: /etc/hulios/gateway.key]
+        #[arg(long)]
+        key_file: Option<String>,
+    },
+    /// Have the gateway's Tor use new circuits for new connections
+    Newnym {
+        host: String,
+        #[arg(long)]
+        key_file: Option<String>,
+    },
+    /// Restart HULIOS on the gateway
+    Restart {
+        host: String,
+        #[arg(long)]
+        key_file: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum LocaleAction {
+    /// Print the English messages a translation lacks, ready to translate,
+    /// e.g. `hulios locale missing fa >> fa.ftl`
+    Missing {
+        language: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum AliasAction {
+    /// Map a name to an onion, e.g. `hulios alias add mail.local xyz...onion`
+    Add {
+        name: String,
+        target: String,
+    },
+    /// Show the configured aliases
+    List,
+    /// Remove an alias
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum HelperAction {
+    /// Install the socket-activated root helper and the 'hulios' group
+    Install,
+    /// Serve requests from the helper socket (run by systemd)
+    #[command(hide = true)]
+    Serve,
+}
+
+impl Commands {
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Start { .. } => "start",
+            Commands::Stop { .. } => "stop",
+            Commands::Restart => "restart",
+            Commands::Confirm => "confirm",
+            Commands::Reload => "reload",
+            Commands::Status { .. } => "status",
+            Commands::Flush => "flush",
+            Commands::Recover => "recover",
+            Commands::Uninstall { .. } => "uninstall",
+            Commands::Update { .. } => "update",
+            Commands::Limit { .. } => "limit",
+            Commands::Setconf { .. } => "setconf",
+            Commands::Newcircuit { .. } => "newcircuit",
+            Commands::Badexit { .. } => "badexit",
+            Commands::Stats => "stats",
+            Commands::Report { .. } => "report",
+            Commands::Top { .. } => "top",
+            Commands::Config { .. } => "config",
+            Commands::Render { .. } => "render",
+            Commands::Doctor => "doctor",
+            Commands::Lsm { .. } => "lsm",
+            Commands::Version { .. } => "version",
+            Commands::DebugBundle { .. } => "debug-bundle",
+            Commands::Test => "test",
+            Commands::HardenBrowser => "harden-browser",
+            Commands::Doh { .. } => "doh",
+            Commands::Exitlist { .. } => "exitlist",
+            Commands::Env => "env",
+            Commands::PkgProxy { .. } => "pkg-proxy",
+            Commands::Remote { .. } => "remote",
+            Commands::Connect { .. } => "connect",
+            Commands::Daemon => "daemon",
+            Commands::ConfirmExpired { .. } => "confirm-expired",
+            Commands::OverlayRouter => "overlay-router",
+            Commands::NetworkEvent { .. } => "network-event",
+            Commands::DnsOnlyServer { .. } => "dns-only-server",
+            Commands::TrayServer => "tray-server",
+            Commands::Controlsocket => "controlsocket",
+            Commands::Run { .. } => "run",
+            Commands::Onion { .. } => "onion",
+            Commands::Relay { .. } => "relay",
+            Commands::Appliance { .. } => "appliance",
+            Commands::Gateway { .. } => "gateway",
+            Commands::AttachGateway { .. } => "attach-gateway",
+            Commands::DetachGateway => "detach-gateway",
+            Commands::DnsOnly { .. } => "dns-only",
+            Commands::Autostart { .. } => "autostart",
+            Commands::NetworkProfile => "network-profile",
+            Commands::Power => "power",
+            Commands::Mode { .. } => "mode",
+            Commands::Alias { .. } => "alias",
+            Commands::Locale { .. } => "locale",
+            Commands::Helper { .. } => "helper",
+        }
+    }
+
+}
+
+fn parse_override(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(i18n::tr("cli-override-syntax", &[])),
+    }
+}
+
+/// Print the error after message `id`, emit the JSON report when
+/// requested, and exit
+fn fail(id: &str, e: anyhow::Error) -> ! {
+    eprintln!("{} {:#}", format!("[!] {}", i18n::tr(id, &[])).red(), e);
+    report::finish(Some(&e));
+    process::exit(1);
+}
+
+/// Exit code of `--check` when the command would change something
+const CHECK_CHANGES_PENDING: i32 = 2;
+
+/// Report the pending changes of a `--check` run and exit without touching anything
+fn check_mode(command: &str, changes: anyhow::Result<Vec<String>>) -> ! {
+    let changes = changes.unwrap_or_else(|e| fail("cli-error-checking", e));
+    if changes.is_empty() {
+        println!("{}", format!("[+] {}", i18n::tr("cli-check-nothing", &[("command", command)])).green());
+    } else {
+        println!("{}", format!("[*] {}", i18n::tr("cli-check-would", &[("command", command)])).yellow());
+        for change in &changes {
+            println!("    - {}", change);
+        }
+    }
+    report::data("changed", !changes.is_empty());
+    report::data("changes", &changes);
+    report::finish(None);
+    process::exit(if changes.is_empty() { 0 } else { CHECK_CHANGES_PENDING });
 }
 
 fn main() {
     let cli = Cli::parse();
+    output::init(cli.no_color, cli.plain || cli.json);
+    notify::set_quiet(cli.quiet);
+    exec::set_verbosity(cli.verbose);
+    audit::set_command(cli.command.name());
+    config::set_flag_overrides(cli.overrides.clone());
+    if cli.json {
+        if let Err(e) = report::enable(cli.command.name()) {
+            fail("cli-error", e);
+        }
+    }
 
     match &cli.command {
-        Commands::Start => {
-            println!("{}", "[+] Starting HULIOS...".green());
-            if let Err(e) = engine::start() {
-                eprintln!("{} {}", "[!] Error starting HULIOS:".red(), e);
-                process::exit(1);
+        Commands::Start { check: true, .. } => check_mode("start", engine::pending_start_changes()),
+        Commands::Stop { check: true, .. } => check_mode("stop", engine::pending_stop_changes()),
+        Commands::Start { skip_verify, amnesic, stop_system_tor, force_ssh_cutoff, confirm_within, allow_lan, .. } => {
+            if let Err(e) = confirm::confirm(&i18n::tr("confirm-starting", &[]), &confirm::changes(confirm::START_CHANGES), cli.yes) {
+                fail("cli-error", e);
+            }
+            println!("{}", format!("[+] {}", i18n::tr("cli-starting", &[])).green());
+            let opts = engine::StartOptions {
+                skip_verify: *skip_verify,
+                amnesic: *amnesic,
+                stop_system_tor: *stop_system_tor,
+                force_ssh_cutoff: *force_ssh_cutoff,
+                confirm_within: *confirm_within,
+                allow_lan: *allow_lan,
+            };
+            if let Err(e) = engine::start(&opts) {
+                fail("cli-error-starting", e);
             }
-            println!("{}", "[+] HULIOS started successfully.".green());
+            println!("{}", format!("[+] {}", i18n::tr("cli-started", &[])).green());
         }
-        Commands::Stop => {
-            println!("{}", "[+] Stopping HULIOS...".yellow());
-            if let Err(e) = engine::stop() {
-                eprintln!("{} {}", "[!] Error stopping HULIOS:".red(), e);
-                process::exit(1);
+        Commands::Stop { stop_system_tor, .. } => {
+            println!("{}", format!("[+] {}", i18n::tr("cli-stopping", &[])).yellow());
+            if let Err(e) = engine::stop(*stop_system_tor) {
+                fail("cli-error-stopping", e);
             }
-             println!("{}", "[+] HULIOS stopped.".green());
+             println!("{}", format!("[+] {}", i18n::tr("cli-stopped", &[])).green());
         }
         Commands::Restart => {
-            println!("{}", "[+] Restarting HULIOS...".yellow());
+            println!("{}", format!("[+] {}", i18n::tr("cli-restarting", &[])).yellow());
             if let Err(e) = engine::restart() {
-                eprintln!("{} {}", "[!] Error restarting HULIOS:".red(), e);
-                process::exit(1);
+                fail("cli-error-restarting", e);
             }
-             println!("{}", "[+] HULIOS restarted.".green());
+             println!("{}", format!("[+] {}", i18n::tr("cli-restarted", &[])).green());
         }
-        Commands::Status => {
-             status::print_status();
+        Commands::Confirm => {
+            if let Err(e) = confirmation::confirm() {
+                fail("cli-error-confirming-session", e);
+            }
+        }
+        Commands::Reload => {
+            if let Err(e) = engine::reload() {
+                fail("cli-error-reloading", e);
+            }
+        }
+        Commands::Status { no_network, cache_ttl, refresh_geoip, short } => {
+             let opts = status::StatusOptions {
+                 no_network: *no_network,
+                 cache_ttl: *cache_ttl,
+                 refresh_geoip: *refresh_geoip,
+             };
+             // `hulios status | head -1` closes the pipe after the verdict;
+             // end quietly like other tools instead of panicking on the write.
+             // SAFETY: nothing else is running yet to see the handler change
+             unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+             let code = if *short { status::print_short(&opts) } else { status::print_status(&opts) };
+             report::finish(None);
+             process::exit(code);
         }
         Commands::Flush => {
-            println!("{}", "[+] Flushing IPTables rules...".yellow());
+            if let Err(e) = confirm::confirm(&i18n::tr("confirm-flushing", &[]), &confirm::changes(confirm::FLUSH_CHANGES), cli.yes) {
+                fail("cli-error", e);
+            }
+            println!("{}", format!("[+] {}", i18n::tr("cli-flushing", &[])).yellow());
             if let Err(e) = engine::flush() {
-                 eprintln!("{} {}", "[!] Error flushing rules:".red(), e);
-                 process::exit(1);
+                fail("cli-error-flushing-rules", e);
+            }
+             println!("{}", format!("[+] {}", i18n::tr("cli-flushed", &[])).green());
+        }
+        Commands::Recover => {
+            println!("{}", format!("[+] {}", i18n::tr("cli-recovering", &[])).yellow());
+            if let Err(e) = engine::recover() {
+                fail("cli-error-recovering", e);
+            }
+        }
+        Commands::Uninstall { purge } => {
+            if let Err(e) = confirm::confirm(&i18n::tr("confirm-uninstalling", &[]), &confirm::changes(confirm::UNINSTALL_CHANGES), cli.yes) {
+                fail("cli-error", e);
+            }
+            println!("{}", format!("[+] {}", i18n::tr("cli-uninstalling", &[])).yellow());
+            if let Err(e) = uninstall::uninstall(*purge) {
+                fail("cli-error-uninstalling", e);
+            }
+        }
+        Commands::Update { check } => {
+            match update::run(*check, cli.yes) {
+                Ok(true) if *check => {
+                    report::finish(None);
+                    process::exit(CHECK_CHANGES_PENDING);
+                }
+                Ok(_) => {}
+                Err(e) => fail("cli-update-error", e),
+            }
+        }
+        Commands::Limit { rate, burst } => {
+            if let Err(e) = engine::limit(rate, burst.as_deref()) {
+                fail("cli-error-applying-limit", e);
+            }
+        }
+        Commands::Setconf { settings } => {
+            let result = if settings.is_empty() { setconf::list() } else { setconf::run(settings) };
+            if let Err(e) = result {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Newcircuit { host } => {
+            if let Err(e) = circuits::new_circuit(host) {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Badexit { address } => {
+            let result = match address {
+                Some(address) => circuits::bad_exit(address),
+                None => circuits::list_bad_exits(),
+            };
+            if let Err(e) = result {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Stats => {
+            stats::print_stats();
+        }
+        Commands::Report { last } => {
+            if let Err(e) = stats::print_report(last.as_deref()) {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Top { destinations, once } => {
+            // JSON output is one object, so one sample
+            if let Err(e) = top::run(*destinations, *once || cli.json) {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Config { action: ConfigAction::Show } => {
+            if let Err(e) = configtool::show() {
+                fail("cli-error-showing-config", e);
+            }
+        }
+        Commands::Config { action: ConfigAction::Edit } => {
+            if let Err(e) = configtool::edit() {
+                fail("cli-error-editing-config", e);
+            }
+        }
+        Commands::Config { action: ConfigAction::Set { key, value } } => {
+            if let Err(e) = configtool::set(key, value) {
+                fail("cli-error-setting-config", e);
+            }
+        }
+        Commands::Config { action: ConfigAction::Validate { file } } => {
+            if let Err(e) = validate::run(file.as_deref()) {
+                fail("cli-invalid-config", e);
+            }
+        }
+        Commands::Render { out } => {
+            if let Err(e) = validate::load().and_then(|config| render::write(&config, out)) {
+                fail("cli-render-error", e);
+            }
+        }
+        Commands::Doctor => {
+            let config = config::Config::load().unwrap_or_else(|e| fail("cli-error-loading-config", e));
+            let ok = preflight::doctor(&config);
+            report::finish(None);
+            process::exit(if ok { 0 } else { 1 });
+        }
+        Commands::Lsm { action } => {
+            let result = config::Config::load().and_then(|config| match action {
+                LsmAction::Status => lsm::status(&config),
+                LsmAction::Install => lsm::install(&config),
+                LsmAction::Remove => lsm::remove(&config),
+            });
+            if let Err(e) = result {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Version { full } => {
+            version::print(*full);
+        }
+        Commands::DebugBundle { output } => {
+            if let Err(e) = debugbundle::create(output.as_deref(), cli.yes) {
+                fail("cli-debug-bundle-error", e);
+            }
+        }
+        Commands::Test => {
+            let result = config::Config::load().and_then(|config| verify::test(&config));
+            if let Err(e) = result {
+                fail("cli-leak-test-failed", e);
+            }
+            println!("{}", format!("[+] {}", i18n::tr("cli-no-leaks", &[])).green());
+        }
+        Commands::HardenBrowser => {
+            if let Err(e) = browser::harden(cli.yes) {
+                fail("cli-error-hardening-browsers", e);
+            }
+        }
+        Commands::Doh { action } => {
+            let result = match action {
+                DohAction::Update => config::Config::load().and_then(|config| doh::update(&config)),
+                DohAction::InstallPolicies => doh::install_policies(),
+                DohAction::RemovePolicies => doh::remove_policies(),
+            };
+            if let Err(e) = result {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Exitlist { action: ExitlistAction::Update } => {
+            println!("{}", format!("[+] {}", i18n::tr("cli-updating-exit-list", &[])).yellow());
+            let result = config::Config::load().and_then(|config| exitlist::refresh(&config.status));
+            match result {
+                Ok(count) => {
+                    println!("{}", format!("[+] {}", i18n::tr("cli-exit-list-updated", &[("count", &count.to_string())])).green());
+                    report::action(format!("Exit list updated: {} addresses", count));
+                }
+                Err(e) => fail("cli-error-updating-exit-list", e),
+            }
+        }
+        Commands::Env => {
+            if let Err(e) = proxyenv::print_env() {
+                fail("cli-error", e);
+            }
+        }
+        Commands::PkgProxy { action } => {
+            let result = match action {
+                PkgProxyAction::Enable => pkgproxy::enable(),
+                PkgProxyAction::Disable => pkgproxy::disable(),
+            };
+            if let Err(e) = result {
+                fail("cli-error-configuring-package-manager", e);
+            }
+        }
+        Commands::Remote { action } => {
+            let result = match action {
+                RemoteAction::Add { host } => remote::add(host),
+                RemoteAction::Remove { host } => remote::remove(host),
+                RemoteAction::List => remote::list(),
+            };
+            if let Err(e) = result {
+                fail("cli-error-configuring-remotes", e);
+            }
+        }
+        Commands::Connect { host, port } => {
+            if let Err(e) = remote::connect(host, *port) {
+                fail("cli-connect", e);
+            }
+        }
+        Commands::Daemon => {
+            if let Err(e) = daemon::run() {
+                fail("cli-daemon-error", e);
+            }
+        }
+        Commands::ConfirmExpired { wait } => {
+            let result = if *wait { confirmation::wait() } else { confirmation::expire() };
+            if let Err(e) = result {
+                fail("cli-error-rolling-back", e);
+            }
+        }
+        Commands::OverlayRouter => {
+            if let Err(e) = overlay::serve() {
+                fail("cli-overlay-router-error", e);
+            }
+        }
+        Commands::NetworkEvent { interface } => {
+            if let Err(e) = autostart::handle_event(interface) {
+                fail("cli-network-event-error", e);
+            }
+        }
+        Commands::DnsOnlyServer { url, addresses } => {
+            if let Err(e) = dnsonly::serve(url, addresses) {
+                fail("cli-doh-client-error", e);
+            }
+        }
+        Commands::TrayServer => {
+            if let Err(e) = tray::serve() {
+                fail("cli-tray-server-error", e);
+            }
+        }
+        Commands::Controlsocket => {
+            if let Err(e) = control::print_connection() {
+                fail("cli-error", e);
+            }
+        }
+        Commands::Run { command, .. } => {
+            match isolate::run(command) {
+                Ok(code) => {
+                    report::finish(None);
+                    process::exit(code);
+                }
+                Err(e) => fail("cli-error-running-isolated-app", e),
+            }
+        }
+        Commands::Onion { action: OnionAction::Ssh { action } } => {
+            let result = match action {
+                OnionSshAction::Enable { client, localhost_only } => onion::enable_ssh(client, *localhost_only, cli.yes),
+                OnionSshAction::Disable => onion::disable_ssh(),
+                OnionSshAction::Status => onion::ssh_status(),
+            };
+            if let Err(e) = result {
+                fail("cli-onion-service-error", e);
+            }
+        }
+        Commands::Relay { action } => {
+            let result = match action {
+                RelayAction::Start => relay::start(cli.yes),
+                RelayAction::Stop => relay::stop(),
+                RelayAction::Status => relay::status(),
+            };
+            if let Err(e) = result {
+                fail("cli-relay-error", e);
+            }
+        }
+        Commands::Appliance { action } => {
+            let result = match action {
+                ApplianceAction::Enable { lan, webhook } => appliance::enable(lan, webhook.as_deref(), cli.yes),
+                ApplianceAction::Disable => appliance::disable(),
+                ApplianceAction::Status => appliance::status(),
+            };
+            if let Err(e) = result {
+                fail("cli-appliance-error", e);
+            }
+        }
+        Commands::Gateway { action } => {
+            let result = match action {
+                GatewayAction::Init => gateway::init(),
+                GatewayAction::Status { host, key_file } => gateway::run(host, key_file.as_deref(), gateway::Request::Status),
+                GatewayAction::Newnym { host, key_file } => gateway::run(host, key_file.as_deref(), gateway::Request::Newnym),
+                GatewayAction::Restart { host, key_file } => gateway::run(host, key_file.as_deref(), gateway::Request::Restart),
+            };
+            if let Err(e) = result {
+                fail("cli-gateway-error", e);
+            }
+        }
+        Commands::AttachGateway { ip } => {
+            if let Err(e) = workstation::attach(ip, cli.yes) {
+                fail("cli-attach-error", e);
+            }
+        }
+        Commands::DetachGateway => {
+            if let Err(e) = workstation::detach() {
+                fail("cli-detach-error", e);
+            }
+        }
+        Commands::DnsOnly { action } => {
+            let result = match action {
+                DnsOnlyAction::Start => dnsonly::start(cli.yes),
+                DnsOnlyAction::Stop => dnsonly::stop(),
+                DnsOnlyAction::Status => dnsonly::status(),
+            };
+            if let Err(e) = result {
+                fail("cli-dns-only-error", e);
+            }
+        }
+        Commands::Autostart { action } => {
+            let result = match action {
+                AutostartAction::Enable => autostart::enable(cli.yes),
+                AutostartAction::Disable => autostart::disable(),
+                AutostartAction::Trust { ssid } => autostart::trust(ssid.as_deref()),
+                AutostartAction::Untrust { ssid } => autostart::untrust(ssid),
+                AutostartAction::Status => autostart::status(),
+            };
+            if let Err(e) = result {
+                fail("cli-autostart-error", e);
+            }
+        }
+        Commands::NetworkProfile => {
+            if let Err(e) = netprofile::status() {
+                fail("cli-network-profile-error", e);
+            }
+        }
+        Commands::Power => {
+            if let Err(e) = power::status() {
+                fail("cli-power-error", e);
+            }
+        }
+        Commands::Mode { action } => match action {
+            ModeAction::Show => mode::show(),
+            ModeAction::Set { mode } => {
+                if let Err(e) = mode::set(*mode, cli.yes) {
+                    fail("cli-mode-error", e);
+                }
+            }
+        },
+        Commands::Alias { action } => {
+            let result = match action {
+                AliasAction::Add { name, target } => alias::add(name, target),
+                AliasAction::List => alias::list(),
+                AliasAction::Remove { name } => alias::remove(name),
+            };
+            if let Err(e) = result {
+                fail("cli-error-managing-aliases", e);
+            }
+        }
+        Commands::Locale { action } => {
+            let result = match action {
+                None => i18n::status(),
+                Some(LocaleAction::Missing { language }) => i18n::missing(language),
+            };
+            if let Err(e) = result {
+                fail("cli-locale-error", e);
+            }
+        }
+        Commands::Helper { action: HelperAction::Install } => {
+            if let Err(e) = helper::install() {
+                fail("cli-error-installing-helper", e);
+            }
+        }
+        Commands::Helper { action: HelperAction::Serve } => {
+            if let Err(e) = helper::serve() {
+                fail("cli-helper-error", e);
             }
-             println!("{}", "[+] Rules flushed.".green());
         }
     }
+
+    report::finish(None);
 }
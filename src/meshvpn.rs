@@ -0,0 +1,60 @@
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use crate::config::{Config, MeshVpn};
+use crate::{i18n, procfs, report};
+
+/// A mesh VPN let through, with the user its daemon runs as
+#[derive(Clone, Serialize)]
+pub struct Mesh {
+    pub vpn: MeshVpn,
+    /// Only this user's UDP on the VPN's ports leaves directly
+    pub daemon_uid: u32,
+}
+
+/// The mesh VPNs `firewall.mesh_vpns` lets through
+pub fn allowed(config: &Config) -> Vec<Mesh> {
+    config.firewall.mesh_vpns
+        .iter()
+        .map(|&vpn| Mesh { vpn, daemon_uid: daemon_uid(vpn) })
+        .collect()
+}
+
+/// The user the VPN's daemon runs as; root, as both run by default, when
+/// it isn't running yet
+fn daemon_uid(vpn: MeshVpn) -> u32 {
+    procfs::find_by_name(vpn.daemon()).first().map_or(0, |daemon| daemon.uid)
+}
+
+/// The mesh VPNs whose interface is up on this machine
+pub fn detected() -> Vec<MeshVpn> {
+    let interfaces: Vec<String> = fs::read_dir("/sys/class/net")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    MeshVpn::ALL
+        .into_iter()
+        .filter(|vpn| {
+            let interface = vpn.interface();
+            match interface.strip_suffix('+') {
+                Some(prefix) => interfaces.iter().any(|name| name.starts_with(prefix)),
+                None => interfaces.iter().any(|name| name == interface),
+            }
+        })
+        .collect()
+}
+
+/// At start: a mesh VPN that is up but not let through stops working for
+/// the session, which deserves a word on how to keep it
+pub fn check(config: &Config) {
+    for vpn in detected() {
+        if config.firewall.mesh_vpns.contains(&vpn) {
+            println!("[*] {}", i18n::tr("meshvpn-peers-reached-directly-outside", &[("name", vpn.name())]));
+            continue;
+        }
+        println!("{}", format!("[!] {}", i18n::tr("meshvpn-up-but-blocked-while", &[("name", vpn.name()), ("interface", vpn.interface())])).yellow());
+        report::warning(format!("{} is blocked", vpn.name()));
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use colored::*;
+use std::fmt;
+use users::get_current_uid;
+use crate::{confirm, dnsonly, engine, i18n, lock, report, state};
+
+/// What HULIOS does to this machine's traffic
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Mode {
+    /// Nothing: the normal network
+    Off,
+    /// All TCP and DNS through Tor (`hulios start`)
+    Full,
+    /// Encrypted DNS only, no Tor (`hulios dns-only start`)
+    DnsOnly,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Off => "off",
+            Mode::Full => "full",
+            Mode::DnsOnly => "dns-only",
+        })
+    }
+}
+
+/// The mode the recorded session is in
+pub fn current() -> Mode {
+    match state::load() {
+        None => Mode::Off,
+        Some(session) if session.dns_only => Mode::DnsOnly,
+        Some(_) => Mode::Full,
+    }
+}
+
+/// `hulios mode`
+pub fn show() {
+    let mode = current();
+    println!("[*] {}", i18n::tr("mode-mode", &[("mode", &mode.to_string().bold())]));
+    report::data("mode", mode.to_string());
+}
+
+/// `hulios mode set <mode>`. Between full and dns-only the session is
+/// switched in place: the system resolver stays neutralized, resolv.conf
+/// is rewritten rather than restored, and the new firewall ruleset is
+/// swapped over the old one, so the normal network never comes back in
+/// between. Off to anything is a plain start, anything to off a plain stop.
+pub fn set(target: Mode, assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let from = current();
+    if from == target {
+        println!("[*] {}", i18n::tr("mode-already-mode", &[("target", &target.to_string())]));
+        return Ok(());
+    }
+    match (from, target) {
+        (Mode::Off, Mode::Full) => {
+            confirm::confirm(&i18n::tr("confirm-starting", &[]), &confirm::changes(confirm::START_CHANGES), assume_yes)?;
+            engine::start(&engine::StartOptions::default())?;
+        }
+        (Mode::Off, Mode::DnsOnly) => dnsonly::start(assume_yes)?,
+        (Mode::Full, Mode::Off) => engine::stop(false)?,
+        (Mode::DnsOnly, Mode::Off) => dnsonly::stop()?,
+        (Mode::Full, Mode::DnsOnly) => {
+            confirm::confirm(&i18n::tr("confirm-switching-dns-only", &[]), &confirm::changes(confirm::SWITCH_TO_DNS_ONLY_CHANGES), assume_yes)?;
+            let _lock = lock::acquire()?;
+            ensure(Mode::Full)?;
+            dnsonly::switch_from_tor()?;
+        }
+        (Mode::DnsOnly, Mode::Full) => {
+            confirm::confirm(&i18n::tr("confirm-switching-full", &[]), &confirm::changes(confirm::SWITCH_TO_FULL_CHANGES), assume_yes)?;
+            let _lock = lock::acquire()?;
+            ensure(Mode::DnsOnly)?;
+            engine::switch_to_tor()?;
+        }
+        _ => unreachable!(),
+    }
+    println!("{}", format!("[+] {}", i18n::tr("mode-switched", &[("from", &from.to_string()), ("target", &target.to_string())])).green());
+    report::action(format!("Mode switched from {} to {}", from, target));
+    Ok(())
+}
+
+/// The mode may have changed while waiting for the lock
+fn ensure(expected: Mode) -> Result<()> {
+    let mode = current();
+    if mode != expected {
+        anyhow::bail!(i18n::tr("mode-changed-meanwhile-run-command", &[("mode", &mode.to_string())]));
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::process::Command;
+use crate::autostart;
+use crate::config::{Config, NetworkBinding};
+use crate::exec::Run;
+use crate::i18n;
+use crate::report;
+
+/// Name of the network profile in effect; under /run, so every boot
+/// starts from the base config until a network is detected
+pub const ACTIVE: &str = "/run/hulios/network_profile";
+
+/// The network this machine is on, as far as the bindings care
+#[derive(Default)]
+pub struct Network {
+    pub ssid: Option<String>,
+    /// IPv4 addresses of the interfaces, loopback excluded
+    pub addresses: Vec<Ipv4Addr>,
+}
+
+impl Network {
+    pub fn detect() -> Network {
+        Network { ssid: autostart::current_ssid(None), addresses: addresses() }
+    }
+
+    fn describe(&self) -> String {
+        match &self.ssid {
+            Some(ssid) => i18n::tr("netprofile-wi-fi", &[("ssid", ssid)]),
+            None if self.addresses.is_empty() => i18n::tr("netprofile-no-network", &[]),
+            None => self.addresses.iter().map(|address| address.to_string()).collect::<Vec<_>>().join(", "),
+        }
+    }
+}
+
+// =============================================================================
+// Profiles
+// =============================================================================
+
+/// The network profile in effect, if any
+pub fn active() -> Option<String> {
+    fs::read_to_string(ACTIVE).ok().map(|name| name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// The config in `source` with `profile` applied over it: tables are
+/// merged key by key, anything else in the profile replaces the value
+pub fn apply(source: &str, profile: &toml::Table) -> Result<Config> {
+    let mut table: toml::Table = toml::from_str(source)?;
+    merge(&mut table, profile);
+    Ok(toml::Value::Table(table).try_into()?)
+}
+
+fn merge(base: &mut toml::Table, overlay: &toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The profile `networks` binds to `network`: the first binding whose
+/// criteria all match
+pub fn matching<'a>(networks: &'a [NetworkBinding], network: &Network) -> Option<&'a NetworkBinding> {
+    networks.iter().find(|binding| {
+        let ssid = binding.ssid.as_ref().is_none_or(|ssid| network.ssid.as_ref() == Some(ssid));
+        let subnet = binding.subnet.as_deref().is_none_or(|subnet| {
+            parse_subnet(subnet).is_ok_and(|(net, prefix)| network.addresses.iter().any(|address| contains(net, prefix, *address)))
+        });
+        ssid && subnet
+    })
+}
+
+/// Detect the network and make its profile the active one; true if that
+/// changed which profile is in effect
+pub fn select(config: &Config) -> Result<bool> {
+    if config.networks.is_empty() {
+        return Ok(false);
+    }
+    let network = Network::detect();
+    let profile = matching(&config.networks, &network).map(|binding| binding.profile.clone());
+    let previous = active();
+    if profile == previous {
+        return Ok(false);
+    }
+    if let Some(parent) = Path::new(ACTIVE).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match &profile {
+        Some(name) => {
+            fs::write(ACTIVE, name).with_context(|| i18n::tr("error-write", &[("path", ACTIVE)]))?;
+            println!("[*] {}", i18n::tr("netprofile-network-profile", &[("name", name), ("network", &network.describe())]));
+            report::action(format!("Network profile {} selected for {}", name, network.describe()));
+        }
+        None => {
+            let _ = fs::remove_file(ACTIVE);
+            println!("[*] {}", i18n::tr("netprofile-no-network-profile-using", &[("network", &network.describe())]));
+            report::action(format!("No network profile for {}", network.describe()));
+        }
+    }
+    Ok(true)
+}
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios network-profile`: the network, the profile bound to it and the
+/// one in effect
+pub fn status() -> Result<()> {
+    let config = Config::load()?;
+    let network = Network::detect();
+    let bound = matching(&config.networks, &network).map(|binding| binding.profile.clone());
+    let active = active();
+
+    println!("[*] {}", i18n::tr("netprofile-network", &[("network", &network.describe())]));
+    match &bound {
+        Some(name) => println!("[*] {}", i18n::tr("netprofile-bound-profile", &[("name", &name.bold())])),
+        None => println!("[*] {}", i18n::tr("netprofile-bound-profile-none-base", &[])),
+    }
+    match &active {
+        Some(name) => println!("[*] {}", i18n::tr("netprofile-profile-effect", &[("name", &name.bold())])),
+        None => println!("[*] {}", i18n::tr("netprofile-profile-effect-none-base", &[])),
+    }
+    if bound != active {
+        println!("{}", format!("[!] {}", i18n::tr("netprofile-network-changed-since-profile", &[])).yellow());
+    }
+    if config.networks.is_empty() {
+        println!("[*] {}", i18n::tr("netprofile-no-networks-bindings-configured", &[]));
+    }
+    for binding in &config.networks {
+        let mut criteria = Vec::new();
+        if let Some(ssid) = &binding.ssid {
+            criteria.push(i18n::tr("netprofile-ssid", &[("ssid", ssid)]));
+        }
+        if let Some(subnet) = &binding.subnet {
+            criteria.push(i18n::tr("netprofile-subnet", &[("subnet", subnet)]));
+        }
+        let criteria = if criteria.is_empty() { i18n::tr("netprofile-any-network", &[]) } else { criteria.join(", ") };
+        println!("    {} -> {}", criteria, binding.profile);
+    }
+    report::data("network_profile", serde_json::json!({
+        "ssid": network.ssid,
+        "addresses": network.addresses,
+        "bound": bound,
+        "active": active,
+    }));
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// "10.20.0.0/16" as (network, prefix length)
+pub fn parse_subnet(subnet: &str) -> Result<(Ipv4Addr, u8)> {
+    let (address, prefix) = subnet.split_once('/').unwrap_or((subnet, "32"));
+    let address: Ipv4Addr = address.parse().with_context(|| i18n::tr("netprofile-not-ipv4-network", &[("subnet", subnet)]))?;
+    let prefix: u8 = prefix.parse().ok().filter(|prefix| *prefix <= 32)
+        .with_context(|| i18n::tr("netprofile-invalid-prefix-length", &[("subnet", subnet)]))?;
+    Ok((address, prefix))
+}
+
+fn contains(network: Ipv4Addr, prefix: u8, address: Ipv4Addr) -> bool {
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    u32::from(network) & mask == u32::from(address) & mask
+}
+
+fn addresses() -> Vec<Ipv4Addr> {
+    let Ok(output) = Command::new("ip").args(["-4", "-o", "addr", "show"]).run_output() else {
+        return Vec::new();
+    };
+    // "2: wlan0    inet 192.168.1.23/24 brd ... scope global dynamic wlan0"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let cidr = words.windows(2).find(|pair| pair[0] == "inet")?[1];
+            cidr.split('/').next()?.parse::<Ipv4Addr>().ok()
+        })
+        .filter(|address| !address.is_loopback())
+        .collect()
+}
@@ -0,0 +1,1717 @@
+use anyhow::{Result, Context};
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use crate::config::GuestPolicy;
+use crate::features::{Features, StateMatch};
+use crate::firewall::{self, Attachment, FirewallBackend, Lan, Policy, RuleChecks, ONION_NETWORK, OVERLAY_NETWORK, TPROXY_MARK};
+use crate::blocklist::{self, Resolved};
+use crate::isolate::{self, App};
+use crate::{audit, cgroup, dnsonly, i18n, libvirt, overlay, report};
+
+/// Programs nftables directly over netlink (NETLINK_NETFILTER), without the
+/// nft/iptables binaries. All rules live in HULIOS-owned `ip hulios` and
+/// `ip6 hulios` tables, so flushing is just deleting those tables.
+///
+/// The ruleset mirrors iptables::apply_rules exactly.
+#[derive(Default)]
+pub struct NftablesBackend {
+    pub policy: Policy,
+}
+
+const TABLE: &str = "hulios";
+/// Throwaway table for the feature probes, never the live one
+const PROBE_TABLE: &str = "hulios_probe";
+/// Named counter shared by every blocklist rule in a table
+const BLOCK_COUNTER: &str = "blocked";
+/// Named counter of the ICMP drop rules in a table
+const ICMP_COUNTER: &str = "icmp_dropped";
+/// Regular chain redirecting the apps of `hulios run --isolated`
+const ISOLATE_CHAIN: &str = "isolated";
+
+impl FirewallBackend for NftablesBackend {
+    fn name(&self) -> &'static str {
+        "nftables"
+    }
+
+    fn apply_rules(&self, tor_user: &str) -> Result<()> {
+        let tor_uid = users::get_user_by_name(tor_user)
+            .map(|user| user.uid())
+            .with_context(|| i18n::tr("error-no-user", &[("user", tor_user)]))?;
+
+        let block = blocklist::resolve(&self.policy.block);
+        let batch = ruleset(tor_uid, &self.policy, &block, &isolate::active());
+        batch.send().with_context(|| i18n::tr("error-nftables", &[]))?;
+        if self.policy.tproxy {
+            firewall::add_policy_routing()?;
+        } else {
+            firewall::remove_policy_routing();
+        }
+
+        println!("[+] {}", i18n::tr("nftables-rules-applied", &[("policy", &self.policy.describe()), ("redirect", self.policy.redirect().name())]));
+        report::action("Firewall rules applied via nftables");
+        Ok(())
+    }
+
+    fn flush_rules(&self) -> Result<()> {
+        for family in [NFPROTO_IPV4, NFPROTO_IPV6] {
+            let mut batch = Batch::new();
+            batch.delete_table(family);
+            match batch.send() {
+                Ok(()) => {}
+                // Nothing to delete: no table, or no nf_tables to hold one
+                // (module missing, or a container that may not use it)
+                Err(e) if matches!(e.root_cause().downcast_ref::<io::Error>().and_then(io::Error::raw_os_error),
+                    Some(libc::ENOENT | libc::EPROTONOSUPPORT | libc::EAFNOSUPPORT | libc::EOPNOTSUPP | libc::EPERM)) => return Ok(()),
+                Err(e) => return Err(e.context(i18n::tr("nftables-failed-delete-nftables-table", &[]))),
+            }
+        }
+        println!("[+] {}", i18n::tr("nftables-firewall-rules-flushed-nftables", &[]));
+        report::action("nftables tables removed");
+        Ok(())
+    }
+
+    fn blocked_packets(&self) -> Option<u64> {
+        counted_packets(BLOCK_COUNTER)
+    }
+
+    fn icmp_dropped(&self) -> Option<u64> {
+        counted_packets(ICMP_COUNTER)
+    }
+
+    fn rules_present(&self) -> Option<bool> {
+        let socket = Socket::open().ok()?;
+        let mut msg = Message::new(NFT_MSG_GETTABLE, NFPROTO_IPV4, libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16, 1);
+        msg.put_str(NFTA_TABLE_NAME, TABLE);
+        socket.send(&msg.finish()).ok()?;
+        match socket.collect_acks(&[1]) {
+            Ok(()) => Some(true),
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Some(false),
+            Err(_) => None,
+        }
+    }
+
+    fn rule_checks(&self) -> Option<RuleChecks> {
+        let socket = Socket::open().ok()?;
+        // The DNS redirect lives in nat_output in both redirect modes
+        let nat_rules = socket.dump_rules(NFPROTO_IPV4, "nat_output").ok()?;
+        let filter_policy = socket.chain_policy(NFPROTO_IPV4, "filter_output");
+        Some(RuleChecks {
+            redirect: nat_rules > 0,
+            kill_switch: filter_policy == Some(NF_DROP as u32),
+        })
+    }
+
+    fn isolate(&self, apps: &[App]) -> Result<()> {
+        let mut batch = Batch::new();
+        batch.flush_chain(NFPROTO_IPV4, ISOLATE_CHAIN);
+        isolated_rules(&mut batch, &self.policy, apps);
+        batch.send().with_context(|| i18n::tr("nftables-failed-update-isolated-apps", &[]))
+    }
+
+    fn probe(&self) -> Features {
+        let works = |kind: &str, hook: u32, rule: Rule| {
+            probe_batch(|batch| {
+                batch.chain(NFPROTO_IPV4, "probe", kind, hook, -150, None);
+                batch.rule(NFPROTO_IPV4, "probe", "probe", rule);
+            })
+        };
+        let owner = works("filter", NF_INET_LOCAL_OUT, Rule::new().skuid(0).accept());
+        let redirect = works("nat", NF_INET_LOCAL_OUT, Rule::new().l4proto(libc::IPPROTO_TCP).redirect(9051));
+        let state_match = if works("filter", NF_INET_LOCAL_OUT, Rule::new().ct_state(CT_ESTABLISHED).accept()) {
+            StateMatch::Native
+        } else {
+            StateMatch::Unavailable
+        };
+        // Both halves of TPROXY mode: the route chain that marks, the tproxy expression
+        let tproxy = probe_batch(|batch| {
+            batch.chain(NFPROTO_IPV4, "mark", "route", NF_INET_LOCAL_OUT, -150, None);
+            batch.rule(NFPROTO_IPV4, "mark", "probe", Rule::new().l4proto(libc::IPPROTO_TCP).set_mark(TPROXY_MARK));
+            batch.chain(NFPROTO_IPV4, "divert", "filter", NF_INET_PRE_ROUTING, -150, None);
+            let rule = Rule::new().l4proto(libc::IPPROTO_TCP).mark(TPROXY_MARK).tproxy([127, 0, 0, 1], 9051);
+            batch.rule(NFPROTO_IPV4, "divert", "probe", rule);
+        });
+        Features { owner, redirect, tproxy, state_match, route_localnet: false }
+    }
+
+    fn attach(&self, attachment: &Attachment) -> Result<()> {
+        let mut batch = Batch::new();
+        for family in [NFPROTO_IPV4, NFPROTO_IPV6] {
+            batch.table(family);
+            batch.delete_table(family);
+        }
+        attach_ruleset(&mut batch, attachment, &self.policy);
+        batch.send().with_context(|| i18n::tr("error-nftables", &[]))?;
+        println!("[+] {}", i18n::tr("nftables-rules-applied-gateway", &[("gateway", &attachment.gateway.to_string())]));
+        report::action("Workstation firewall rules applied via nftables");
+        Ok(())
+    }
+
+    fn dns_only(&self, resolver_uid: Option<u32>) -> Result<()> {
+        let mut batch = Batch::new();
+        for family in [NFPROTO_IPV4, NFPROTO_IPV6] {
+            batch.table(family);
+            batch.delete_table(family);
+        }
+        dns_only_ruleset(&mut batch, resolver_uid);
+        batch.send().with_context(|| i18n::tr("error-nftables", &[]))?;
+        println!("[+] {}", i18n::tr("nftables-rules-applied-dns-only", &[]));
+        report::action("DNS-only firewall rules applied via nftables");
+        Ok(())
+    }
+
+    fn render(&self, tor_user: &str) -> Result<Vec<(&'static str, String)>> {
+        let tor_uid = users::get_user_by_name(tor_user)
+            .map(|user| user.uid())
+            .with_context(|| i18n::tr("nftables-user-does-not-exist", &[("tor_user", tor_user)]))?;
+        let batch = ruleset(tor_uid, &self.policy, &blocklist::literal(&self.policy.block), &[]);
+        let mut script = "#!/usr/sbin/nft -f\n# HULIOS ruleset, written by `hulios render`\n".to_string();
+        for line in batch.script {
+            script.push_str(&line);
+            script.push('\n');
+        }
+        Ok(vec![("hulios.nft", script)])
+    }
+}
+
+/// The transaction of apply_rules. The old tables are deleted in the same
+/// transaction that builds the new ones, so a live ruleset (restart) is
+/// swapped without a gap.
+fn ruleset(tor_uid: u32, policy: &Policy, block: &Resolved, apps: &[App]) -> Batch {
+    let mut batch = Batch::new();
+    for family in [NFPROTO_IPV4, NFPROTO_IPV6] {
+        batch.table(family);
+        batch.delete_table(family);
+    }
+    ipv4_ruleset(&mut batch, tor_uid, policy, block, apps);
+    ipv6_ruleset(&mut batch, policy, block);
+    batch
+}
+
+/// Build probe chains and rules in PROBE_TABLE and delete the table in the
+/// same transaction: the kernel validates every rule, but none is ever live
+fn probe_batch(build: impl FnOnce(&mut Batch)) -> bool {
+    let mut batch = Batch::for_table(PROBE_TABLE);
+    batch.table(NFPROTO_IPV4);
+    build(&mut batch);
+    batch.delete_table(NFPROTO_IPV4);
+    batch.send().is_ok()
+}
+
+/// Packets of the named counter, summed over the IPv4 and IPv6 tables
+fn counted_packets(counter: &str) -> Option<u64> {
+    let socket = Socket::open().ok()?;
+    let mut total = 0;
+    for family in [NFPROTO_IPV4, NFPROTO_IPV6] {
+        let mut msg = Message::new(NFT_MSG_GETOBJ, family, libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16, 1);
+        msg.put_str(NFTA_OBJ_TABLE, TABLE);
+        msg.put_str(NFTA_OBJ_NAME, counter);
+        msg.attrs.put_u32_be(NFTA_OBJ_TYPE, NFT_OBJECT_COUNTER);
+        socket.send(&msg.finish()).ok()?;
+        let replies = socket.collect_replies(&[1]).ok()?;
+        let data = replies.first().and_then(|reply| find_attr(reply, NFTA_OBJ_DATA))?;
+        let packets = find_attr(data, NFTA_COUNTER_PACKETS)?;
+        total += u64::from_be_bytes(packets.get(..8)?.try_into().ok()?);
+    }
+    Some(total)
+}
+
+// =============================================================================
+// Ruleset
+// =============================================================================
+
+fn ipv4_ruleset(batch: &mut Batch, tor_uid: u32, policy: &Policy, block: &Resolved, apps: &[App]) {
+    let family = NFPROTO_IPV4;
+    batch.table(family);
+    batch.counter(family, BLOCK_COUNTER);
+    batch.counter(family, ICMP_COUNTER);
+
+    // NAT: redirect to Tor (priority -100 = dstnat)
+    batch.chain(family, "nat_output", "nat", NF_INET_LOCAL_OUT, -100, None);
+    let nat = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "nat_output", desc, rule);
+
+    let stateful = policy.state_match != StateMatch::Unavailable;
+    if stateful {
+        nat(batch, "established return", Rule::new().ct_state(CT_ESTABLISHED).accept());
+    }
+    if let Some((out, _)) = ssh_flows(policy, false) {
+        nat(batch, "ssh session return", out.accept());
+    }
+    for flow in lan_flows(policy, true) {
+        nat(batch, "lan service return", flow.accept());
+    }
+    nat(batch, "tor user bypass", Rule::new().skuid(tor_uid).accept());
+    for &port in &policy.proxy_ports {
+        nat(batch, "local proxy return", Rule::new().daddr_v4([127, 0, 0, 1], 32).l4proto(libc::IPPROTO_TCP).dport(port).accept());
+    }
+    // TCP only: DNS to a blocked address (1.1.1.1 as a DoH provider) still goes to Tor
+    for &(ip, prefix) in &block.v4 {
+        let rule = Rule::new().daddr_v4(ip.octets(), prefix as u32).l4proto(libc::IPPROTO_TCP).dport_other_than(53);
+        nat(batch, "blocklist return", rule.accept());
+    }
+    batch.subchain(family, ISOLATE_CHAIN);
+    nat(batch, "isolated apps", Rule::new().jump(ISOLATE_CHAIN));
+    isolated_rules(batch, policy, apps);
+    // With overlay networks UDP DNS goes to their router, which passes the rest on
+    let udp_dns_port = if policy.overlays { overlay::DNS_PORT } else { 9061 };
+    nat(batch, "udp dns redirect", Rule::new().l4proto(libc::IPPROTO_UDP).dport(53).redirect(udp_dns_port));
+    nat(batch, "tcp dns redirect", Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).redirect(9061));
+    if policy.overlays {
+        let (network, prefix) = OVERLAY_NETWORK;
+        let rule = Rule::new().daddr_v4(network, prefix as u32).l4proto(libc::IPPROTO_TCP).redirect(overlay::TRANS_PORT);
+        nat(batch, "overlay tcp redirect", rule);
+    }
+    // Mesh VPN peers are reached directly (their DNS was redirected above)
+    for peers in mesh_peers(policy) {
+        nat(batch, "mesh vpn return", peers.accept());
+    }
+    nat(batch, "loopback return", Rule::new().daddr_v4([127, 0, 0, 0], 8).accept());
+    if policy.tproxy {
+        tproxy_ruleset(batch, tor_uid, policy, block);
+    } else {
+        for rule in torified_tcp(policy) {
+            nat(batch, "tcp redirect", rule.redirect(9051));
+        }
+    }
+
+    if let Some(lan) = &policy.lan {
+        lan_ruleset(batch, lan, policy);
+    }
+    guests_ruleset(batch, policy);
+
+    // FILTER: default-deny
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, Some(NF_DROP));
+    let filter = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "filter_output", desc, rule);
+
+    filter(batch, "loopback accept", Rule::new().oifname("lo").accept());
+    if let Some((out, _)) = ssh_flows(policy, false) {
+        filter(batch, "ssh session accept", out.accept());
+    }
+    for &(ip, prefix) in &block.v4 {
+        filter(batch, "blocklist reject", Rule::new().daddr_v4(ip.octets(), prefix as u32).count(BLOCK_COUNTER).reject(ICMP_PKT_FILTERED));
+    }
+    filter(batch, "localhost accept", Rule::new().daddr_v4([127, 0, 0, 0], 8).accept());
+    if stateful {
+        filter(batch, "established accept", Rule::new().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+    }
+    // ICMP: only the allowed types, the rest dropped and counted (partial
+    // mode with other traffic allowed lets it out like any other)
+    if !policy.is_partial_allow() {
+        let icmp = || Rule::new().l4proto(libc::IPPROTO_ICMP);
+        for kind in policy.icmp_allow.iter().filter_map(|kind| kind.v4()) {
+            filter(batch, "icmp type accept", icmp().icmp_type(kind).accept());
+        }
+        filter(batch, "icmp drop", icmp().count(ICMP_COUNTER).drop());
+    }
+    filter(batch, "tor user accept", Rule::new().skuid(tor_uid).accept());
+    for flow in lan_flows(policy, false) {
+        filter(batch, "lan service accept", flow.accept());
+    }
+    for peers in mesh_peers(policy) {
+        filter(batch, "mesh vpn accept", peers.accept());
+    }
+    for flow in mesh_daemon_udp(policy) {
+        filter(batch, "mesh vpn daemon udp accept", flow.accept());
+    }
+    // TPROXY: the marked TCP is routed back into the local stack, to the TransPort
+    if policy.tproxy {
+        filter(batch, "tproxy mark accept", Rule::new().l4proto(libc::IPPROTO_TCP).mark(TPROXY_MARK).accept());
+    }
+    filter(batch, "udp dns drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport(53).drop());
+    filter(batch, "tcp dns drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).drop());
+    filter(batch, "dot drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport(853).drop());
+    filter(batch, "quic drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport(443).drop());
+    for &(low, high) in policy.torify_ports.iter().flatten() {
+        filter(batch, "torified tcp drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport_range(low, high).drop());
+        filter(batch, "torified udp drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport_range(low, high).drop());
+    }
+    if policy.is_partial_allow() {
+        filter(batch, "final accept", Rule::new().accept());
+    } else {
+        filter(batch, "final drop", Rule::new().drop());
+    }
+}
+
+/// Gateway mode: LAN clients' DNS and torified TCP go to Tor's listeners
+/// on the LAN address (redir picks the address the packet came in on).
+/// Connections to this machine itself are left alone (or, with
+/// `tor_ports_only`, dropped), and nothing from or to the LAN is forwarded,
+/// over IPv4 or IPv6.
+fn lan_ruleset(batch: &mut Batch, lan: &Lan, policy: &Policy) {
+    let family = NFPROTO_IPV4;
+    let interface = lan.interface.as_str();
+    batch.chain(family, "nat_prerouting", "nat", NF_INET_PRE_ROUTING, -100, None);
+    let nat = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "nat_prerouting", desc, rule);
+    let from_lan = || Rule::new().iifname(interface);
+
+    nat(batch, "lan udp dns redirect", from_lan().l4proto(libc::IPPROTO_UDP).dport(53).redirect(9061));
+    nat(batch, "lan tcp dns redirect", from_lan().l4proto(libc::IPPROTO_TCP).dport(53).redirect(9061));
+    nat(batch, "lan local return", from_lan().daddr_local().accept());
+    for rule in torified_tcp(policy) {
+        nat(batch, "lan tcp redirect", from_lan().then(rule).redirect(9051));
+    }
+    lan_forward_drop(batch, family, interface);
+
+    if lan.tor_ports_only {
+        batch.chain(family, "filter_input", "filter", NF_INET_LOCAL_IN, 0, None);
+        let input = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "filter_input", desc, rule);
+        if let Some((_, into)) = ssh_flows(policy, false) {
+            input(batch, "ssh session in", into.accept());
+        }
+        if policy.state_match != StateMatch::Unavailable {
+            input(batch, "lan established in", from_lan().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+        }
+        for port in lan.tor_ports() {
+            input(batch, "lan tor port in", from_lan().l4proto(libc::IPPROTO_TCP).dport(port).accept());
+        }
+        input(batch, "lan dns in", from_lan().l4proto(libc::IPPROTO_UDP).dport(9061).accept());
+        if let Some(port) = lan.remote_control {
+            input(batch, "lan remote control in", from_lan().l4proto(libc::IPPROTO_TCP).dport(port).accept());
+        }
+        input(batch, "lan other in drop", from_lan().drop());
+    }
+}
+
+/// libvirt guests: on torified bridges their DNS and torified TCP go to
+/// Tor's listeners on the bridge address, as in gateway mode, and nothing
+/// is forwarded; clearnet bridges are forwarded (libvirt NATs them), and
+/// the rest dropped. Bridges that appear later are dropped too unless
+/// `libvirt.guests` is clearnet.
+fn guests_ruleset(batch: &mut Batch, policy: &Policy) {
+    let family = NFPROTO_IPV4;
+    batch.chain(family, "guests_prerouting", "nat", NF_INET_PRE_ROUTING, -100, None);
+    batch.chain(family, "guests_forward", "filter", NF_INET_FORWARD, 0, None);
+    let nat = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "guests_prerouting", desc, rule);
+    let forward = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "guests_forward", desc, rule);
+    let stateful = policy.state_match != StateMatch::Unavailable;
+
+    for guests in &policy.guests {
+        let from = || Rule::new().iifname(&guests.bridge);
+        let to = || Rule::new().oifname(&guests.bridge);
+        match guests.policy {
+            GuestPolicy::Torify => {
+                nat(batch, "guests udp dns redirect", from().l4proto(libc::IPPROTO_UDP).dport(53).redirect(9061));
+                nat(batch, "guests tcp dns redirect", from().l4proto(libc::IPPROTO_TCP).dport(53).redirect(9061));
+                nat(batch, "guests local return", from().daddr_local().accept());
+                for rule in torified_tcp(policy) {
+                    nat(batch, "guests tcp redirect", from().then(rule).redirect(9051));
+                }
+            }
+            GuestPolicy::Clearnet => {
+                forward(batch, "guests forward out accept", from().accept());
+                if stateful {
+                    forward(batch, "guests forward in accept", to().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+                }
+                continue;
+            }
+            GuestPolicy::Block => {}
+        }
+        forward(batch, "guests forward out drop", from().drop());
+        forward(batch, "guests forward in drop", to().drop());
+    }
+    if policy.new_guests_dropped {
+        let any = format!("{}+", libvirt::BRIDGE_PREFIX);
+        forward(batch, "new guests forward out drop", Rule::new().iifname(&any).drop());
+        forward(batch, "new guests forward in drop", Rule::new().oifname(&any).drop());
+    }
+}
+
+fn lan_forward_drop(batch: &mut Batch, family: u8, interface: &str) {
+    batch.chain(family, "filter_forward", "filter", NF_INET_FORWARD, 0, None);
+    batch.rule(family, "filter_forward", "lan forward in drop", Rule::new().iifname(interface).drop());
+    batch.rule(family, "filter_forward", "lan forward out drop", Rule::new().oifname(interface).drop());
+}
+
+/// TPROXY mode: mark the TCP that NAT mode would redirect (a route chain
+/// re-routes it into the local stack by the mark) and hand it to the
+/// TransPort in prerouting. The destination is never rewritten.
+fn tproxy_ruleset(batch: &mut Batch, tor_uid: u32, policy: &Policy, block: &Resolved) {
+    let family = NFPROTO_IPV4;
+    batch.chain(family, "mangle_output", "route", NF_INET_LOCAL_OUT, -150, None);
+    let mangle = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "mangle_output", desc, rule);
+
+    // Tor's own connections, and its replies to the diverted ones
+    mangle(batch, "tor user return", Rule::new().skuid(tor_uid).accept());
+    mangle(batch, "loopback return", Rule::new().daddr_v4([127, 0, 0, 0], 8).accept());
+    if let Some((out, _)) = ssh_flows(policy, false) {
+        mangle(batch, "ssh session return", out.accept());
+    }
+    for flow in lan_flows(policy, true) {
+        mangle(batch, "lan service return", flow.accept());
+    }
+    // DNS over TCP is NAT-redirected to the DNSPort
+    mangle(batch, "tcp dns return", Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).accept());
+    for peers in mesh_peers(policy) {
+        mangle(batch, "mesh vpn return", peers.accept());
+    }
+    for &(ip, prefix) in &block.v4 {
+        mangle(batch, "blocklist return", Rule::new().daddr_v4(ip.octets(), prefix as u32).accept());
+    }
+    for rule in torified_tcp(policy) {
+        mangle(batch, "tcp mark", rule.set_mark(TPROXY_MARK));
+    }
+
+    batch.chain(family, "tproxy_prerouting", "filter", NF_INET_PRE_ROUTING, -150, None);
+    let rule = Rule::new().l4proto(libc::IPPROTO_TCP).mark(TPROXY_MARK).tproxy([127, 0, 0, 1], 9051);
+    batch.rule(family, "tproxy_prerouting", "tcp tproxy", rule);
+}
+
+/// Each isolated app's DNS and torified TCP, told apart by its cgroup,
+/// goes to its own ports; its loopback traffic is left alone. TPROXY mode
+/// has no such rules: its TransPorts only take diverted connections.
+fn isolated_rules(batch: &mut Batch, policy: &Policy, apps: &[App]) {
+    if policy.tproxy {
+        return;
+    }
+    let family = NFPROTO_IPV4;
+    for app in apps {
+        let Some(id) = cgroup::id(&app.group) else {
+            continue;
+        };
+        let level = app.group.split('/').filter(|part| !part.is_empty()).count() as u32;
+        let owner = || Rule::new().cgroup(level, id);
+        let rule = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, ISOLATE_CHAIN, desc, rule);
+        rule(batch, "udp dns redirect", owner().l4proto(libc::IPPROTO_UDP).dport(53).redirect(app.dns_port()));
+        rule(batch, "tcp dns redirect", owner().l4proto(libc::IPPROTO_TCP).dport(53).redirect(app.dns_port()));
+        rule(batch, "loopback return", owner().daddr_v4([127, 0, 0, 0], 8).accept());
+        for tcp in torified_tcp(policy) {
+            rule(batch, "tcp redirect", owner().then(tcp).redirect(app.trans_port()));
+        }
+    }
+}
+
+/// A fresh match for each slice of TCP that goes through Tor
+fn torified_tcp(policy: &Policy) -> Vec<Rule> {
+    let tcp = || Rule::new().l4proto(libc::IPPROTO_TCP);
+    match &policy.torify_ports {
+        _ if policy.onion_only => {
+            let (network, prefix) = ONION_NETWORK;
+            vec![tcp().daddr_v4(network, prefix as u32)]
+        }
+        None => vec![tcp()],
+        Some(ranges) => ranges.iter().map(|&(low, high)| tcp().dport_range(low, high)).collect(),
+    }
+}
+
+fn ipv6_ruleset(batch: &mut Batch, policy: &Policy, block: &Resolved) {
+    let family = NFPROTO_IPV6;
+    batch.table(family);
+    batch.counter(family, BLOCK_COUNTER);
+    batch.counter(family, ICMP_COUNTER);
+
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, Some(NF_DROP));
+    batch.rule(family, "filter_output", "ipv6 loopback out", Rule::new().oifname("lo").accept());
+    if let Some((out, _)) = ssh_flows(policy, true) {
+        batch.rule(family, "filter_output", "ipv6 ssh session out", out.accept());
+    }
+    for &(ip, prefix) in &block.v6 {
+        let rule = Rule::new().daddr_v6(ip.octets(), prefix as u32).count(BLOCK_COUNTER).reject(ICMPV6_ADM_PROHIBITED);
+        batch.rule(family, "filter_output", "ipv6 blocklist reject", rule);
+    }
+    let stateful = policy.state_match != StateMatch::Unavailable;
+    if stateful {
+        batch.rule(family, "filter_output", "ipv6 established out", Rule::new().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+    }
+    if !policy.is_partial_allow() {
+        icmpv6_rules(batch, "filter_output", policy);
+    }
+    if policy.is_partial_allow() {
+        // Tor's TransPort is IPv4-only, so torified traffic can't go out over IPv6
+        let out = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "filter_output", desc, rule);
+        out(batch, "ipv6 udp dns drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport(53).drop());
+        out(batch, "ipv6 tcp dns drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).drop());
+        out(batch, "ipv6 dot drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport(853).drop());
+        for &(low, high) in policy.torify_ports.iter().flatten() {
+            out(batch, "ipv6 torified tcp drop", Rule::new().l4proto(libc::IPPROTO_TCP).dport_range(low, high).drop());
+            out(batch, "ipv6 torified udp drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport_range(low, high).drop());
+        }
+        out(batch, "ipv6 final accept", Rule::new().accept());
+    }
+
+    batch.chain(family, "filter_input", "filter", NF_INET_LOCAL_IN, 0, Some(NF_DROP));
+    batch.rule(family, "filter_input", "ipv6 loopback in", Rule::new().iifname("lo").accept());
+    if let Some((_, into)) = ssh_flows(policy, true) {
+        batch.rule(family, "filter_input", "ipv6 ssh session in", into.accept());
+    }
+    if stateful {
+        batch.rule(family, "filter_input", "ipv6 established in", Rule::new().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+    }
+    // The input policy drops in every mode
+    icmpv6_rules(batch, "filter_input", policy);
+    if let Some(lan) = &policy.lan {
+        lan_forward_drop(batch, family, &lan.interface);
+    }
+}
+
+/// Fresh matches for each kind of packet the LAN services send, only their
+/// TCP with `tcp_only`
+fn lan_flows(policy: &Policy, tcp_only: bool) -> Vec<Rule> {
+    policy.lan_flows()
+        .into_iter()
+        .filter(|flow| !tcp_only || flow.protocol == "tcp")
+        .map(|flow| {
+            let proto = if flow.protocol == "tcp" { libc::IPPROTO_TCP } else { libc::IPPROTO_UDP };
+            let (network, prefix) = flow.network;
+            Rule::new().daddr_v4(network, prefix as u32).l4proto(proto).dport_range(flow.ports.0, flow.ports.1)
+        })
+        .collect()
+}
+
+/// Fresh matches for what goes to each mesh VPN's peers: out its
+/// interface, and to its address range where it has a fixed one
+fn mesh_peers(policy: &Policy) -> Vec<Rule> {
+    policy.mesh.iter().map(|mesh| {
+        let rule = Rule::new().oifname(mesh.vpn.interface());
+        match mesh.vpn.network() {
+            Some((network, prefix)) => rule.daddr_v4(network, prefix as u32),
+            None => rule,
+        }
+    }).collect()
+}
+
+/// Fresh matches for the UDP each mesh VPN's daemon sends from and to its
+/// ports, as its own user only
+fn mesh_daemon_udp(policy: &Policy) -> Vec<Rule> {
+    let mut flows = Vec::new();
+    for mesh in &policy.mesh {
+        let daemon = || Rule::new().skuid(mesh.daemon_uid).l4proto(libc::IPPROTO_UDP);
+        let (sports, dports) = mesh.vpn.udp_ports();
+        flows.extend(sports.iter().map(|&port| daemon().sport(port)));
+        flows.extend(dports.iter().map(|&port| daemon().dport(port)));
+    }
+    flows
+}
+
+/// ICMPv6 in `chain`: the allowed types, the rest dropped and counted
+fn icmpv6_rules(batch: &mut Batch, chain: &str, policy: &Policy) {
+    let icmp = || Rule::new().l4proto(libc::IPPROTO_ICMPV6);
+    for kind in &policy.icmp_allow {
+        batch.rule(NFPROTO_IPV6, chain, "ipv6 icmp type accept", icmp().icmp_type(kind.v6()).accept());
+    }
+    batch.rule(NFPROTO_IPV6, chain, "ipv6 icmp drop", icmp().count(ICMP_COUNTER).drop());
+}
+
+/// The SSH session kept open, if it is over IPv6 (`v6`) or IPv4: fresh
+/// matches for what sshd sends and for what the client sends
+fn ssh_flows(policy: &Policy, v6: bool) -> Option<(Rule, Rule)> {
+    let ssh = policy.ssh_session.as_ref().filter(|ssh| ssh.peer.is_ipv6() == v6)?;
+    let flow = |from: SocketAddr, to: SocketAddr| {
+        let rule = match (from.ip(), to.ip()) {
+            (IpAddr::V4(from), IpAddr::V4(to)) => Rule::new().saddr_v4(from.octets()).daddr_v4(to.octets(), 32),
+            (IpAddr::V6(from), IpAddr::V6(to)) => Rule::new().saddr_v6(from.octets()).daddr_v6(to.octets(), 128),
+            _ => return None,
+        };
+        Some(rule.l4proto(libc::IPPROTO_TCP).sport(from.port()).dport(to.port()))
+    };
+    Some((flow(ssh.local, ssh.peer)?, flow(ssh.peer, ssh.local)?))
+}
+
+/// Workstation of `hulios attach-gateway`: the gateway is the only host on
+/// its network that can be reached, and everything else leaves through it
+/// as TCP or DNS, which the gateway torifies. No IPv6 at all.
+fn attach_ruleset(batch: &mut Batch, attachment: &Attachment, policy: &Policy) {
+    let stateful = policy.state_match != StateMatch::Unavailable;
+    let family = NFPROTO_IPV4;
+    batch.table(family);
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, Some(NF_DROP));
+    let filter = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "filter_output", desc, rule);
+    let via_gateway = || Rule::new().oifname(&attachment.interface);
+    let (network, prefix) = attachment.subnet;
+
+    filter(batch, "loopback accept", Rule::new().oifname("lo").accept());
+    if stateful {
+        filter(batch, "established accept", Rule::new().ct_state(CT_ESTABLISHED | CT_RELATED).accept());
+    }
+    filter(batch, "gateway accept", Rule::new().daddr_v4(attachment.gateway.octets(), 32).accept());
+    filter(batch, "local network drop", Rule::new().daddr_v4(network.octets(), prefix as u32).drop());
+    filter(batch, "tcp via gateway accept", via_gateway().l4proto(libc::IPPROTO_TCP).accept());
+    filter(batch, "udp dns via gateway accept", via_gateway().l4proto(libc::IPPROTO_UDP).dport(53).accept());
+
+    let family = NFPROTO_IPV6;
+    batch.table(family);
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, Some(NF_DROP));
+    batch.rule(family, "filter_output", "ipv6 loopback out", Rule::new().oifname("lo").accept());
+    batch.chain(family, "filter_input", "filter", NF_INET_LOCAL_IN, 0, Some(NF_DROP));
+    batch.rule(family, "filter_input", "ipv6 loopback in", Rule::new().iifname("lo").accept());
+    batch.chain(family, "filter_forward", "filter", NF_INET_FORWARD, 0, Some(NF_DROP));
+}
+
+/// `hulios dns-only`: DNS goes to the local encrypted resolver, DNS over
+/// TLS and QUIC and DNS over IPv6 are dropped, and the chains accept
+/// everything else
+fn dns_only_ruleset(batch: &mut Batch, resolver_uid: Option<u32>) {
+    let family = NFPROTO_IPV4;
+    batch.table(family);
+    batch.chain(family, "nat_output", "nat", NF_INET_LOCAL_OUT, -100, None);
+    let nat = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "nat_output", desc, rule);
+    if let Some(uid) = resolver_uid {
+        nat(batch, "resolver bypass", Rule::new().skuid(uid).accept());
+    }
+    nat(batch, "udp dns redirect", Rule::new().l4proto(libc::IPPROTO_UDP).dport(53).redirect(dnsonly::PORT));
+    nat(batch, "tcp dns redirect", Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).redirect(dnsonly::PORT));
+
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, None);
+    let filter = |batch: &mut Batch, desc: &str, rule: Rule| batch.rule(family, "filter_output", desc, rule);
+    filter(batch, "dot reject", Rule::new().l4proto(libc::IPPROTO_TCP).dport(853).reject(ICMP_PKT_FILTERED));
+    filter(batch, "doq drop", Rule::new().l4proto(libc::IPPROTO_UDP).dport(853).drop());
+
+    let family = NFPROTO_IPV6;
+    batch.table(family);
+    batch.chain(family, "filter_output", "filter", NF_INET_LOCAL_OUT, 0, None);
+    for proto in [libc::IPPROTO_UDP, libc::IPPROTO_TCP] {
+        for port in [53, 853] {
+            batch.rule(family, "filter_output", "ipv6 dns drop", Rule::new().l4proto(proto).dport(port).drop());
+        }
+    }
+}
+
+// =============================================================================
+// Rule Expressions
+// =============================================================================
+
+/// A rule as a list of nftables expressions, evaluated left to right,
+/// and the same in nft's syntax for `hulios render`
+struct Rule {
+    exprs: Vec<Vec<u8>>,
+    text: Vec<String>,
+}
+
+impl Rule {
+    fn new() -> Rule {
+        Rule { exprs: Vec::new(), text: Vec::new() }
+    }
+
+    /// Add the nft syntax of what was just appended
+    fn says(mut self, text: impl Into<String>) -> Rule {
+        self.text.push(text.into());
+        self
+    }
+
+    fn expr(mut self, name: &str, build: impl FnOnce(&mut Attrs)) -> Rule {
+        let mut data = Attrs::default();
+        build(&mut data);
+        let mut elem = Attrs::default();
+        elem.put_str(NFTA_EXPR_NAME, name);
+        elem.put_nested(NFTA_EXPR_DATA, data);
+        self.exprs.push(elem.buf);
+        self
+    }
+
+    fn meta(self, key: u32) -> Rule {
+        self.expr("meta", |a| {
+            a.put_u32_be(NFTA_META_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_META_KEY, key);
+        })
+    }
+
+    fn payload(self, base: u32, offset: u32, len: u32) -> Rule {
+        self.expr("payload", |a| {
+            a.put_u32_be(NFTA_PAYLOAD_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_PAYLOAD_BASE, base);
+            a.put_u32_be(NFTA_PAYLOAD_OFFSET, offset);
+            a.put_u32_be(NFTA_PAYLOAD_LEN, len);
+        })
+    }
+
+    fn bitwise(self, mask: &[u8]) -> Rule {
+        self.expr("bitwise", |a| {
+            a.put_u32_be(NFTA_BITWISE_SREG, NFT_REG_1);
+            a.put_u32_be(NFTA_BITWISE_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_BITWISE_LEN, mask.len() as u32);
+            a.put_data(NFTA_BITWISE_MASK, mask);
+            a.put_data(NFTA_BITWISE_XOR, &vec![0; mask.len()]);
+        })
+    }
+
+    fn cmp(self, op: u32, value: &[u8]) -> Rule {
+        self.expr("cmp", |a| {
+            a.put_u32_be(NFTA_CMP_SREG, NFT_REG_1);
+            a.put_u32_be(NFTA_CMP_OP, op);
+            a.put_data(NFTA_CMP_DATA, value);
+        })
+    }
+
+    fn l4proto(self, proto: libc::c_int) -> Rule {
+        let name = match proto {
+            libc::IPPROTO_TCP => "tcp".to_string(),
+            libc::IPPROTO_UDP => "udp".to_string(),
+            libc::IPPROTO_ICMP => "icmp".to_string(),
+            libc::IPPROTO_ICMPV6 => "ipv6-icmp".to_string(),
+            other => other.to_string(),
+        };
+        self.meta(NFT_META_L4PROTO).cmp(NFT_CMP_EQ, &[proto as u8]).says(format!("meta l4proto {}", name))
+    }
+
+    /// Destination port; must follow l4proto (TCP and UDP share the offset)
+    fn dport(self, port: u16) -> Rule {
+        self.payload(NFT_PAYLOAD_TRANSPORT_HEADER, 2, 2).cmp(NFT_CMP_EQ, &port.to_be_bytes())
+            .says(format!("th dport {}", port))
+    }
+
+    /// Any destination port but `port`; must follow l4proto
+    fn dport_other_than(self, port: u16) -> Rule {
+        self.payload(NFT_PAYLOAD_TRANSPORT_HEADER, 2, 2).cmp(NFT_CMP_NEQ, &port.to_be_bytes())
+            .says(format!("th dport != {}", port))
+    }
+
+    /// Inclusive destination port range; must follow l4proto
+    fn dport_range(self, low: u16, high: u16) -> Rule {
+        self.payload(NFT_PAYLOAD_TRANSPORT_HEADER, 2, 2)
+            .cmp(NFT_CMP_GTE, &low.to_be_bytes())
+            .cmp(NFT_CMP_LTE, &high.to_be_bytes())
+            .says(format!("th dport {}-{}", low, high))
+    }
+
+    /// ICMP or ICMPv6 type; must follow l4proto. Written as the raw
+    /// payload match: nft's `icmp type` and `icmpv6 type` differ by family.
+    fn icmp_type(self, kind: u8) -> Rule {
+        self.payload(NFT_PAYLOAD_TRANSPORT_HEADER, 0, 1).cmp(NFT_CMP_EQ, &[kind])
+            .says(format!("@th,0,8 {}", kind))
+    }
+
+    /// Source port; must follow l4proto
+    fn sport(self, port: u16) -> Rule {
+        self.payload(NFT_PAYLOAD_TRANSPORT_HEADER, 0, 2).cmp(NFT_CMP_EQ, &port.to_be_bytes())
+            .says(format!("th sport {}", port))
+    }
+
+    /// One source address
+    fn saddr_v4(self, addr: [u8; 4]) -> Rule {
+        self.payload(NFT_PAYLOAD_NETWORK_HEADER, 12, 4).cmp(NFT_CMP_EQ, &addr)
+            .says(format!("ip saddr {}", Ipv4Addr::from(addr)))
+    }
+
+    fn saddr_v6(self, addr: [u8; 16]) -> Rule {
+        self.payload(NFT_PAYLOAD_NETWORK_HEADER, 8, 16).cmp(NFT_CMP_EQ, &addr)
+            .says(format!("ip6 saddr {}", Ipv6Addr::from(addr)))
+    }
+
+    fn daddr_v4(self, net: [u8; 4], prefix: u32) -> Rule {
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        let net = (u32::from_be_bytes(net) & mask).to_be_bytes();
+        self.payload(NFT_PAYLOAD_NETWORK_HEADER, 16, 4).bitwise(&mask.to_be_bytes()).cmp(NFT_CMP_EQ, &net)
+            .says(format!("ip daddr {}/{}", Ipv4Addr::from(net), prefix))
+    }
+
+    fn daddr_v6(self, net: [u8; 16], prefix: u32) -> Rule {
+        let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+        let net = (u128::from_be_bytes(net) & mask).to_be_bytes();
+        self.payload(NFT_PAYLOAD_NETWORK_HEADER, 24, 16).bitwise(&mask.to_be_bytes()).cmp(NFT_CMP_EQ, &net)
+            .says(format!("ip6 daddr {}/{}", Ipv6Addr::from(net), prefix))
+    }
+
+    /// The destination is an address of this machine (`fib daddr type local`)
+    fn daddr_local(self) -> Rule {
+        self.expr("fib", |a| {
+            a.put_u32_be(NFTA_FIB_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_FIB_RESULT, NFT_FIB_RESULT_ADDRTYPE);
+            a.put_u32_be(NFTA_FIB_FLAGS, NFTA_FIB_F_DADDR);
+        })
+        .cmp(NFT_CMP_EQ, &RTN_LOCAL.to_ne_bytes())
+        .says("fib daddr type local")
+    }
+
+    /// The socket belongs to the cgroup (v2) with `id`, `level` deep
+    fn cgroup(self, level: u32, id: u64) -> Rule {
+        self.expr("socket", |a| {
+            a.put_u32_be(NFTA_SOCKET_KEY, NFT_SOCKET_CGROUPV2);
+            a.put_u32_be(NFTA_SOCKET_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_SOCKET_LEVEL, level);
+        })
+        .cmp(NFT_CMP_EQ, &id.to_ne_bytes())
+        .says(format!("socket cgroupv2 level {} {}", level, id))
+    }
+
+    /// Append the expressions of `rest`
+    fn then(mut self, rest: Rule) -> Rule {
+        self.exprs.extend(rest.exprs);
+        self.text.extend(rest.text);
+        self
+    }
+
+    /// Count the packet in the table's named counter object
+    fn count(self, counter: &str) -> Rule {
+        self.expr("objref", |a| {
+            a.put_u32_be(NFTA_OBJREF_IMM_TYPE, NFT_OBJECT_COUNTER);
+            a.put_str(NFTA_OBJREF_IMM_NAME, counter);
+        })
+        .says(format!("counter name \"{}\"", counter))
+    }
+
+    /// Terminal REJECT with an ICMP unreachable of the given code
+    fn reject(self, icmp_code: u8) -> Rule {
+        self.expr("reject", |a| {
+            a.put_u32_be(NFTA_REJECT_TYPE, NFT_REJECT_ICMP_UNREACH);
+            a.put(NFTA_REJECT_ICMP_CODE, &[icmp_code]);
+        })
+        .says(match icmp_code {
+            ICMP_PKT_FILTERED => "reject with icmp type admin-prohibited".to_string(),
+            ICMPV6_ADM_PROHIBITED => "reject with icmpv6 type admin-prohibited".to_string(),
+            code => format!("reject with icmp code {}", code),
+        })
+    }
+
+    fn oifname(self, name: &str) -> Rule {
+        self.meta(NFT_META_OIFNAME).cmp(NFT_CMP_EQ, &ifname(name))
+            .says(format!("oifname \"{}\"", ifname_pattern(name)))
+    }
+
+    fn iifname(self, name: &str) -> Rule {
+        self.meta(NFT_META_IIFNAME).cmp(NFT_CMP_EQ, &ifname(name))
+            .says(format!("iifname \"{}\"", ifname_pattern(name)))
+    }
+
+    fn skuid(self, uid: u32) -> Rule {
+        self.meta(NFT_META_SKUID).cmp(NFT_CMP_EQ, &uid.to_ne_bytes())
+            .says(format!("meta skuid {}", uid))
+    }
+
+    /// Match any of the given conntrack state bits
+    fn ct_state(self, bits: u32) -> Rule {
+        self.expr("ct", |a| {
+            a.put_u32_be(NFTA_CT_DREG, NFT_REG_1);
+            a.put_u32_be(NFTA_CT_KEY, NFT_CT_STATE);
+        })
+        .bitwise(&bits.to_ne_bytes())
+        .cmp(NFT_CMP_NEQ, &0u32.to_ne_bytes())
+        .says(format!("ct state {}", [(CT_ESTABLISHED, "established"), (CT_RELATED, "related")]
+            .iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>()
+            .join(",")))
+    }
+
+    fn verdict(self, code: i32) -> Rule {
+        self.expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_VERDICT);
+            let mut verdict = Attrs::default();
+            verdict.put_u32_be(NFTA_VERDICT_CODE, code as u32);
+            let mut data = Attrs::default();
+            data.put_nested(NFTA_DATA_VERDICT, verdict);
+            a.put_nested(NFTA_IMMEDIATE_DATA, data);
+        })
+        .says(if code == NF_ACCEPT { "accept" } else { "drop" })
+    }
+
+    /// Continue in a regular chain, coming back after its last rule
+    fn jump(self, chain: &str) -> Rule {
+        self.expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_VERDICT);
+            let mut verdict = Attrs::default();
+            verdict.put_u32_be(NFTA_VERDICT_CODE, NFT_JUMP as u32);
+            verdict.put_str(NFTA_VERDICT_CHAIN, chain);
+            let mut data = Attrs::default();
+            data.put_nested(NFTA_DATA_VERDICT, verdict);
+            a.put_nested(NFTA_IMMEDIATE_DATA, data);
+        })
+        .says(format!("jump {}", chain))
+    }
+
+    fn accept(self) -> Rule {
+        self.verdict(NF_ACCEPT)
+    }
+
+    fn drop(self) -> Rule {
+        self.verdict(NF_DROP)
+    }
+
+    /// REDIRECT to a local port (load the port into a register, then redir)
+    fn redirect(self, port: u16) -> Rule {
+        self.expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_1);
+            a.put_data(NFTA_IMMEDIATE_DATA, &port.to_be_bytes());
+        })
+        .expr("redir", |a| {
+            a.put_u32_be(NFTA_REDIR_REG_PROTO_MIN, NFT_REG_1);
+        })
+        .says(format!("redirect to :{}", port))
+    }
+
+    fn mark(self, mark: u32) -> Rule {
+        self.meta(NFT_META_MARK).cmp(NFT_CMP_EQ, &mark.to_ne_bytes())
+            .says(format!("meta mark {:#x}", mark))
+    }
+
+    /// Set the packet mark (load it into a register, then meta set)
+    fn set_mark(self, mark: u32) -> Rule {
+        self.expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_1);
+            a.put_data(NFTA_IMMEDIATE_DATA, &mark.to_ne_bytes());
+        })
+        .expr("meta", |a| {
+            a.put_u32_be(NFTA_META_KEY, NFT_META_MARK);
+            a.put_u32_be(NFTA_META_SREG, NFT_REG_1);
+        })
+        .says(format!("meta mark set {:#x}", mark))
+    }
+
+    /// TPROXY to a local IPv4 address and port (both loaded into registers)
+    fn tproxy(self, addr: [u8; 4], port: u16) -> Rule {
+        self.expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_1);
+            a.put_data(NFTA_IMMEDIATE_DATA, &addr);
+        })
+        .expr("immediate", |a| {
+            a.put_u32_be(NFTA_IMMEDIATE_DREG, NFT_REG_2);
+            a.put_data(NFTA_IMMEDIATE_DATA, &port.to_be_bytes());
+        })
+        .expr("tproxy", |a| {
+            a.put_u32_be(NFTA_TPROXY_FAMILY, NFPROTO_IPV4 as u32);
+            a.put_u32_be(NFTA_TPROXY_REG_ADDR, NFT_REG_1);
+            a.put_u32_be(NFTA_TPROXY_REG_PORT, NFT_REG_2);
+        })
+        .says(format!("tproxy to {}:{}", Ipv4Addr::from(addr), port))
+    }
+}
+
+/// An interface name as the kernel compares it: NUL-padded, or for a
+/// prefix like "vif+" just the prefix, so only that much is compared
+fn ifname(name: &str) -> Vec<u8> {
+    if let Some(prefix) = name.strip_suffix('+') {
+        return prefix.as_bytes().to_vec();
+    }
+    let mut buf = vec![0u8; libc::IFNAMSIZ];
+    buf[..name.len()].copy_from_slice(name.as_bytes());
+    buf
+}
+
+/// An interface name as nft writes it, a prefix with a trailing "*"
+fn ifname_pattern(name: &str) -> String {
+    match name.strip_suffix('+') {
+        Some(prefix) => format!("{}*", prefix),
+        None => name.to_string(),
+    }
+}
+
+// =============================================================================
+// Netlink Encoding
+// =============================================================================
+
+/// Netlink attributes (TLV, 4-byte aligned)
+#[derive(Default)]
+struct Attrs {
+    buf: Vec<u8>,
+}
+
+impl Attrs {
+    fn put(&mut self, kind: u16, payload: &[u8]) {
+        let len = (4 + payload.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&kind.to_ne_bytes());
+        self.buf.extend_from_slice(payload);
+        while !self.buf.len().is_multiple_of(4) {
+            self.buf.push(0);
+        }
+    }
+
+    fn put_str(&mut self, kind: u16, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.put(kind, &bytes);
+    }
+
+    fn put_u32_be(&mut self, kind: u16, value: u32) {
+        self.put(kind, &value.to_be_bytes());
+    }
+
+    fn put_nested(&mut self, kind: u16, nested: Attrs) {
+        self.put(kind | NLA_F_NESTED, &nested.buf);
+    }
+
+    /// nft_data value wrapper (NFTA_DATA_VALUE inside a nested attribute)
+    fn put_data(&mut self, kind: u16, value: &[u8]) {
+        let mut data = Attrs::default();
+        data.put(NFTA_DATA_VALUE, value);
+        self.put_nested(kind, data);
+    }
+}
+
+/// One netlink message: nlmsghdr + nfgenmsg + attributes
+struct Message {
+    kind: u16,
+    flags: u16,
+    seq: u32,
+    family: u8,
+    res_id: u16,
+    attrs: Attrs,
+}
+
+impl Message {
+    fn new(nft_msg: u16, family: u8, flags: u16, seq: u32) -> Message {
+        Message {
+            kind: (NFNL_SUBSYS_NFTABLES << 8) | nft_msg,
+            flags,
+            seq,
+            family,
+            res_id: 0,
+            attrs: Attrs::default(),
+        }
+    }
+
+    fn put_str(&mut self, kind: u16, value: &str) {
+        self.attrs.put_str(kind, value);
+    }
+
+    fn finish(&self) -> Vec<u8> {
+        let len = 16 + 4 + self.attrs.buf.len();
+        let mut buf = Vec::with_capacity(len);
+        buf.extend_from_slice(&(len as u32).to_ne_bytes());
+        buf.extend_from_slice(&self.kind.to_ne_bytes());
+        buf.extend_from_slice(&self.flags.to_ne_bytes());
+        buf.extend_from_slice(&self.seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.push(self.family);
+        buf.push(NFNETLINK_V0);
+        buf.extend_from_slice(&self.res_id.to_be_bytes());
+        buf.extend_from_slice(&self.attrs.buf);
+        buf
+    }
+}
+
+/// A transaction: every message is applied atomically or not at all. The
+/// same transaction as an `nft -f` script is kept alongside.
+struct Batch {
+    table: &'static str,
+    messages: Vec<Message>,
+    descriptions: Vec<(u32, String)>,
+    script: Vec<String>,
+}
+
+impl Batch {
+    fn new() -> Batch {
+        Batch::for_table(TABLE)
+    }
+
+    /// A batch whose table, chain, counter and rule messages target `table`
+    fn for_table(table: &'static str) -> Batch {
+        Batch { table, messages: Vec::new(), descriptions: Vec::new(), script: Vec::new() }
+    }
+
+    /// `nft` command words for the family's table, e.g. "ip hulios"
+    fn nft_table(&self, family: u8) -> String {
+        format!("{} {}", if family == NFPROTO_IPV6 { "ip6" } else { "ip" }, self.table)
+    }
+
+    fn add(&mut self, nft_msg: u16, family: u8, extra_flags: u16, desc: &str, build: impl FnOnce(&mut Message)) {
+        let seq = self.messages.len() as u32 + 2;
+        let flags = libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16 | extra_flags;
+        let mut msg = Message::new(nft_msg, family, flags, seq);
+        build(&mut msg);
+        self.messages.push(msg);
+        self.descriptions.push((seq, desc.to_string()));
+    }
+
+    fn table(&mut self, family: u8) {
+        let table = self.table;
+        self.add(NFT_MSG_NEWTABLE, family, libc::NLM_F_CREATE as u16, "create table", |msg| {
+            msg.put_str(NFTA_TABLE_NAME, table);
+        });
+        self.script.push(format!("add table {}", self.nft_table(family)));
+    }
+
+    fn delete_table(&mut self, family: u8) {
+        let table = self.table;
+        self.add(NFT_MSG_DELTABLE, family, 0, "delete table", |msg| {
+            msg.put_str(NFTA_TABLE_NAME, table);
+        });
+        self.script.push(format!("delete table {}", self.nft_table(family)));
+    }
+
+    fn chain(&mut self, family: u8, name: &str, kind: &str, hook: u32, priority: i32, policy: Option<i32>) {
+        let table = self.table;
+        self.add(NFT_MSG_NEWCHAIN, family, libc::NLM_F_CREATE as u16, &format!("create chain {}", name), |msg| {
+            msg.attrs.put_str(NFTA_CHAIN_TABLE, table);
+            msg.attrs.put_str(NFTA_CHAIN_NAME, name);
+            let mut hook_attrs = Attrs::default();
+            hook_attrs.put_u32_be(NFTA_HOOK_HOOKNUM, hook);
+            hook_attrs.put_u32_be(NFTA_HOOK_PRIORITY, priority as u32);
+            msg.attrs.put_nested(NFTA_CHAIN_HOOK, hook_attrs);
+            if let Some(policy) = policy {
+                msg.attrs.put_u32_be(NFTA_CHAIN_POLICY, policy as u32);
+            }
+            msg.attrs.put_str(NFTA_CHAIN_TYPE, kind);
+        });
+        let hook = match hook {
+            NF_INET_PRE_ROUTING => "prerouting",
+            NF_INET_LOCAL_IN => "input",
+            NF_INET_FORWARD => "forward",
+            NF_INET_LOCAL_OUT => "output",
+            _ => "postrouting",
+        };
+        let policy = if policy == Some(NF_DROP) { " policy drop;" } else { "" };
+        self.script.push(format!("add chain {} {} {{ type {} hook {} priority {};{} }}",
+            self.nft_table(family), name, kind, hook, priority, policy));
+    }
+
+    /// A regular chain, only entered by a jump
+    fn subchain(&mut self, family: u8, name: &str) {
+        let table = self.table;
+        self.add(NFT_MSG_NEWCHAIN, family, libc::NLM_F_CREATE as u16, &format!("create chain {}", name), |msg| {
+            msg.attrs.put_str(NFTA_CHAIN_TABLE, table);
+            msg.attrs.put_str(NFTA_CHAIN_NAME, name);
+        });
+        self.script.push(format!("add chain {} {}", self.nft_table(family), name));
+    }
+
+    /// Delete every rule in `chain`
+    fn flush_chain(&mut self, family: u8, chain: &str) {
+        let table = self.table;
+        self.add(NFT_MSG_DELRULE, family, 0, &format!("flush chain {}", chain), |msg| {
+            msg.attrs.put_str(NFTA_RULE_TABLE, table);
+            msg.attrs.put_str(NFTA_RULE_CHAIN, chain);
+        });
+        self.script.push(format!("flush chain {} {}", self.nft_table(family), chain));
+    }
+
+    fn counter(&mut self, family: u8, name: &str) {
+        let table = self.table;
+        self.add(NFT_MSG_NEWOBJ, family, libc::NLM_F_CREATE as u16, &format!("create counter {}", name), |msg| {
+            msg.attrs.put_str(NFTA_OBJ_TABLE, table);
+            msg.attrs.put_str(NFTA_OBJ_NAME, name);
+            msg.attrs.put_u32_be(NFTA_OBJ_TYPE, NFT_OBJECT_COUNTER);
+            let mut data = Attrs::default();
+            data.put(NFTA_COUNTER_BYTES, &0u64.to_be_bytes());
+            data.put(NFTA_COUNTER_PACKETS, &0u64.to_be_bytes());
+            msg.attrs.put_nested(NFTA_OBJ_DATA, data);
+        });
+        self.script.push(format!("add counter {} {}", self.nft_table(family), name));
+    }
+
+    fn rule(&mut self, family: u8, chain: &str, desc: &str, rule: Rule) {
+        let flags = (libc::NLM_F_CREATE | libc::NLM_F_APPEND) as u16;
+        let table = self.table;
+        self.script.push(format!("add rule {} {} {}", self.nft_table(family), chain, rule.text.join(" ")));
+        self.add(NFT_MSG_NEWRULE, family, flags, &format!("{}: {}", chain, desc), |msg| {
+            msg.attrs.put_str(NFTA_RULE_TABLE, table);
+            msg.attrs.put_str(NFTA_RULE_CHAIN, chain);
+            let mut exprs = Attrs::default();
+            for expr in rule.exprs {
+                exprs.put(NFTA_LIST_ELEM | NLA_F_NESTED, &expr);
+            }
+            msg.attrs.put_nested(NFTA_RULE_EXPRESSIONS, exprs);
+        });
+    }
+
+    /// Send BATCH_BEGIN + messages + BATCH_END and wait for every ack.
+    /// A failing message is reported by its description.
+    fn send(self) -> Result<()> {
+        let socket = Socket::open().with_context(|| i18n::tr("nftables-failed-open-netlink-socket", &[]))?;
+        let buf = self.encode();
+        if self.table == TABLE {
+            for ((_, desc), msg) in self.descriptions.iter().zip(&self.messages) {
+                let family = if msg.family == NFPROTO_IPV6 { "ip6" } else { "ip" };
+                audit::record("nftables", &format!("{} {}: {}", family, self.table, desc));
+            }
+        }
+        socket.send(&buf)?;
+
+        let seqs: Vec<u32> = self.descriptions.iter().map(|(seq, _)| *seq).collect();
+        socket.collect_acks(&seqs).map_err(|e| {
+            let desc = e.seq
+                .and_then(|seq| self.descriptions.iter().find(|(s, _)| *s == seq))
+                .map(|(_, desc)| desc.as_str())
+                .unwrap_or("batch");
+            anyhow::Error::new(e.error).context(i18n::tr("nftables-rejected", &[("rule", desc)]))
+        })
+    }
+
+    /// The messages framed by BATCH_BEGIN and BATCH_END, as sent
+    fn encode(&self) -> Vec<u8> {
+        let mut begin = Message::new(0, libc::AF_UNSPEC as u8, libc::NLM_F_REQUEST as u16, 1);
+        begin.kind = NFNL_MSG_BATCH_BEGIN;
+        begin.res_id = NFNL_SUBSYS_NFTABLES;
+        let mut end = Message::new(0, libc::AF_UNSPEC as u8, libc::NLM_F_REQUEST as u16, self.messages.len() as u32 + 2);
+        end.kind = NFNL_MSG_BATCH_END;
+        end.res_id = NFNL_SUBSYS_NFTABLES;
+
+        let mut buf = begin.finish();
+        for msg in &self.messages {
+            buf.extend_from_slice(&msg.finish());
+        }
+        buf.extend_from_slice(&end.finish());
+        buf
+    }
+}
+
+/// A netlink error, tied to the sequence number of the failing message
+struct AckError {
+    seq: Option<u32>,
+    error: io::Error,
+}
+
+impl AckError {
+    fn raw_os_error(&self) -> Option<i32> {
+        self.error.raw_os_error()
+    }
+}
+
+impl From<io::Error> for AckError {
+    fn from(error: io::Error) -> Self {
+        AckError { seq: None, error }
+    }
+}
+
+struct Socket {
+    fd: libc::c_int,
+}
+
+impl Socket {
+    fn open() -> io::Result<Socket> {
+        // SAFETY: plain socket creation; the fd is owned by Socket and closed on drop
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_NETFILTER) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = Socket { fd };
+
+        let timeout = libc::timeval { tv_sec: 5, tv_usec: 0 };
+        // SAFETY: timeout is a valid timeval for the duration of the call
+        unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const _ as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            );
+        }
+        Ok(socket)
+    }
+
+    fn send(&self, buf: &[u8]) -> io::Result<()> {
+        // SAFETY: sockaddr_nl is plain data; zeroed means "to the kernel"
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        // SAFETY: buf and addr are valid for the duration of the call
+        let sent = unsafe {
+            libc::sendto(
+                self.fd,
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                0,
+                &addr as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Number of rules in one of our chains (0 if it doesn't exist)
+    fn dump_rules(&self, family: u8, chain: &str) -> std::result::Result<usize, AckError> {
+        let mut msg = Message::new(NFT_MSG_GETRULE, family, (libc::NLM_F_REQUEST | libc::NLM_F_DUMP) as u16, 1);
+        msg.put_str(NFTA_RULE_TABLE, TABLE);
+        msg.put_str(NFTA_RULE_CHAIN, chain);
+        self.send(&msg.finish())?;
+        match self.collect_replies(&[1]) {
+            // The kernel filters by table and chain, older ones may not
+            Ok(replies) => Ok(replies.iter().filter(|rule| {
+                let is = |kind, name: &str| find_attr(rule, kind)
+                    .is_some_and(|value| value.strip_suffix(&[0]).unwrap_or(value) == name.as_bytes());
+                is(NFTA_RULE_TABLE, TABLE) && is(NFTA_RULE_CHAIN, chain)
+            }).count()),
+            Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Default verdict of one of our base chains, None if it doesn't exist
+    fn chain_policy(&self, family: u8, chain: &str) -> Option<u32> {
+        let mut msg = Message::new(NFT_MSG_GETCHAIN, family, libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16, 1);
+        msg.put_str(NFTA_CHAIN_TABLE, TABLE);
+        msg.put_str(NFTA_CHAIN_NAME, chain);
+        self.send(&msg.finish()).ok()?;
+        let replies = self.collect_replies(&[1]).ok()?;
+        let policy = replies.first().and_then(|reply| find_attr(reply, NFTA_CHAIN_POLICY))?;
+        Some(u32::from_be_bytes(policy.get(..4)?.try_into().ok()?))
+    }
+
+    /// Read replies until every sequence number has been acknowledged.
+    /// Returns the first error the kernel reported.
+    fn collect_acks(&self, seqs: &[u32]) -> std::result::Result<(), AckError> {
+        self.collect_replies(seqs).map(|_| ())
+    }
+
+    /// Like collect_acks, but also return the attributes of every data
+    /// reply (e.g. the object answering a GET request)
+    fn collect_replies(&self, seqs: &[u32]) -> std::result::Result<Vec<Vec<u8>>, AckError> {
+        let mut pending: Vec<u32> = seqs.to_vec();
+        let mut first_error: Option<AckError> = None;
+        let mut replies = Vec::new();
+        let mut buf = vec![0u8; 256 * 1024];
+
+        while !pending.is_empty() {
+            // SAFETY: buf is valid and writable for its full length
+            let len = unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+            if len < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            let mut offset = 0;
+            let len = len as usize;
+            while offset + 16 <= len {
+                let msg_len = u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap()) as usize;
+                let kind = u16::from_ne_bytes(buf[offset + 4..offset + 6].try_into().unwrap());
+                let seq = u32::from_ne_bytes(buf[offset + 8..offset + 12].try_into().unwrap());
+                if msg_len < 16 || offset + msg_len > len {
+                    break;
+                }
+
+                if kind == libc::NLMSG_DONE as u16 {
+                    // End of a dump, which is never acknowledged
+                    pending.retain(|s| *s != seq);
+                } else if kind == libc::NLMSG_ERROR as u16 && msg_len >= 20 {
+                    let code = i32::from_ne_bytes(buf[offset + 16..offset + 20].try_into().unwrap());
+                    pending.retain(|s| *s != seq);
+                    if code != 0 && first_error.is_none() {
+                        first_error = Some(AckError { seq: Some(seq), error: io::Error::from_raw_os_error(-code) });
+                    }
+                } else if kind >> 8 == NFNL_SUBSYS_NFTABLES && msg_len >= 20 {
+                    // Skip nlmsghdr and nfgenmsg
+                    replies.push(buf[offset + 20..offset + msg_len].to_vec());
+                }
+                offset += (msg_len + 3) & !3;
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(replies),
+        }
+    }
+}
+
+/// Payload of the first attribute of `kind` in a run of netlink attributes
+fn find_attr(attrs: &[u8], kind: u16) -> Option<&[u8]> {
+    let mut offset = 0;
+    while offset + 4 <= attrs.len() {
+        let len = u16::from_ne_bytes(attrs[offset..offset + 2].try_into().ok()?) as usize;
+        let attr_kind = u16::from_ne_bytes(attrs[offset + 2..offset + 4].try_into().ok()?);
+        if len < 4 || offset + len > attrs.len() {
+            return None;
+        }
+        if attr_kind & NLA_TYPE_MASK == kind {
+            return Some(&attrs[offset + 4..offset + len]);
+        }
+        offset += (len + 3) & !3;
+    }
+    None
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // SAFETY: fd was returned by socket() and is closed exactly once
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// =============================================================================
+// Kernel Constants (linux/netfilter/nf_tables.h, nfnetlink.h)
+// =============================================================================
+
+const NFNETLINK_V0: u8 = 0;
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+const NFNL_MSG_BATCH_BEGIN: u16 = 0x10;
+const NFNL_MSG_BATCH_END: u16 = 0x11;
+const NLA_F_NESTED: u16 = 0x8000;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const NFPROTO_IPV4: u8 = 2;
+const NFPROTO_IPV6: u8 = 10;
+
+const NF_INET_PRE_ROUTING: u32 = 0;
+const NF_INET_LOCAL_IN: u32 = 1;
+const NF_INET_FORWARD: u32 = 2;
+const NF_INET_LOCAL_OUT: u32 = 3;
+
+const NF_DROP: i32 = 0;
+const NF_ACCEPT: i32 = 1;
+
+const NFT_MSG_NEWTABLE: u16 = 0;
+const NFT_MSG_GETTABLE: u16 = 1;
+const NFT_MSG_DELTABLE: u16 = 2;
+const NFT_MSG_NEWCHAIN: u16 = 3;
+const NFT_MSG_GETCHAIN: u16 = 4;
+const NFT_MSG_NEWRULE: u16 = 6;
+const NFT_MSG_GETRULE: u16 = 7;
+const NFT_MSG_DELRULE: u16 = 8;
+const NFT_MSG_NEWOBJ: u16 = 18;
+const NFT_MSG_GETOBJ: u16 = 19;
+
+const NFTA_TABLE_NAME: u16 = 1;
+
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_POLICY: u16 = 5;
+const NFTA_CHAIN_TYPE: u16 = 7;
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+
+const NFTA_RULE_TABLE: u16 = 1;
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_EXPRESSIONS: u16 = 4;
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+
+const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_DATA_VERDICT: u16 = 2;
+const NFTA_VERDICT_CODE: u16 = 1;
+const NFTA_VERDICT_CHAIN: u16 = 2;
+const NFT_JUMP: i32 = -3;
+
+const NFT_REG_VERDICT: u32 = 0;
+const NFT_REG_1: u32 = 1;
+const NFT_REG_2: u32 = 2;
+
+const NFTA_META_DREG: u16 = 1;
+const NFTA_META_KEY: u16 = 2;
+const NFTA_META_SREG: u16 = 3;
+const NFT_META_MARK: u32 = 3;
+const NFT_META_IIFNAME: u32 = 6;
+const NFT_META_OIFNAME: u32 = 7;
+const NFT_META_SKUID: u32 = 10;
+const NFT_META_L4PROTO: u32 = 16;
+
+const NFTA_CMP_SREG: u16 = 1;
+const NFTA_CMP_OP: u16 = 2;
+const NFTA_CMP_DATA: u16 = 3;
+const NFT_CMP_EQ: u32 = 0;
+const NFT_CMP_NEQ: u32 = 1;
+const NFT_CMP_LTE: u32 = 3;
+const NFT_CMP_GTE: u32 = 5;
+
+const NFTA_PAYLOAD_DREG: u16 = 1;
+const NFTA_PAYLOAD_BASE: u16 = 2;
+const NFTA_PAYLOAD_OFFSET: u16 = 3;
+const NFTA_PAYLOAD_LEN: u16 = 4;
+const NFT_PAYLOAD_NETWORK_HEADER: u32 = 1;
+const NFT_PAYLOAD_TRANSPORT_HEADER: u32 = 2;
+
+const NFTA_BITWISE_SREG: u16 = 1;
+const NFTA_BITWISE_DREG: u16 = 2;
+const NFTA_BITWISE_LEN: u16 = 3;
+const NFTA_BITWISE_MASK: u16 = 4;
+const NFTA_BITWISE_XOR: u16 = 5;
+
+const NFTA_IMMEDIATE_DREG: u16 = 1;
+const NFTA_IMMEDIATE_DATA: u16 = 2;
+
+const NFTA_CT_DREG: u16 = 1;
+const NFTA_CT_KEY: u16 = 2;
+const NFT_CT_STATE: u32 = 0;
+const CT_ESTABLISHED: u32 = 1 << 1;
+const CT_RELATED: u32 = 1 << 2;
+
+const NFTA_REDIR_REG_PROTO_MIN: u16 = 1;
+
+const NFTA_SOCKET_KEY: u16 = 1;
+const NFTA_SOCKET_DREG: u16 = 2;
+const NFTA_SOCKET_LEVEL: u16 = 3;
+const NFT_SOCKET_CGROUPV2: u32 = 3;
+
+const NFTA_FIB_DREG: u16 = 1;
+const NFTA_FIB_RESULT: u16 = 2;
+const NFTA_FIB_FLAGS: u16 = 3;
+const NFT_FIB_RESULT_ADDRTYPE: u32 = 3;
+const NFTA_FIB_F_DADDR: u32 = 1 << 1;
+/// rtnetlink route type of an address of this machine
+const RTN_LOCAL: u32 = 2;
+
+const NFTA_TPROXY_FAMILY: u16 = 1;
+const NFTA_TPROXY_REG_ADDR: u16 = 2;
+const NFTA_TPROXY_REG_PORT: u16 = 3;
+
+const NFTA_OBJ_TABLE: u16 = 1;
+const NFTA_OBJ_NAME: u16 = 2;
+const NFTA_OBJ_TYPE: u16 = 3;
+const NFTA_OBJ_DATA: u16 = 4;
+const NFT_OBJECT_COUNTER: u32 = 1;
+const NFTA_COUNTER_BYTES: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
+const NFTA_OBJREF_IMM_TYPE: u16 = 1;
+const NFTA_OBJREF_IMM_NAME: u16 = 2;
+
+const NFTA_REJECT_TYPE: u16 = 1;
+const NFTA_REJECT_ICMP_CODE: u16 = 2;
+const NFT_REJECT_ICMP_UNREACH: u32 = 0;
+/// ICMP "communication administratively prohibited" codes
+const ICMP_PKT_FILTERED: u8 = 13;
+const ICMPV6_ADM_PROHIBITED: u8 = 1;
+
+// Expected bytes follow the layouts in linux/netfilter/nf_tables.h and
+// linux/netlink.h, as `nft --debug=mnl` prints them: headers and attribute
+// lengths in host order (little-endian here), attribute values big-endian
+#[cfg(all(test, target_endian = "little"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_padded_to_four_bytes() {
+        let mut attrs = Attrs::default();
+        attrs.put(1, &[0xaa]);
+        attrs.put_str(2, "ip");
+        attrs.put_u32_be(3, 0x0102_0304);
+        assert_eq!(attrs.buf, [
+            5, 0, 1, 0, 0xaa, 0, 0, 0,
+            7, 0, 2, 0, b'i', b'p', 0, 0,
+            8, 0, 3, 0, 1, 2, 3, 4,
+        ]);
+    }
+
+    #[test]
+    fn nested_attributes_carry_the_nested_flag() {
+        let mut inner = Attrs::default();
+        inner.put_u32_be(NFTA_HOOK_HOOKNUM, NF_INET_LOCAL_OUT);
+        let mut outer = Attrs::default();
+        outer.put_nested(NFTA_CHAIN_HOOK, inner);
+        assert_eq!(outer.buf, [12, 0, 4, 0x80, 8, 0, 1, 0, 0, 0, 0, 3]);
+
+        let mut data = Attrs::default();
+        data.put_data(NFTA_CMP_DATA, &[0, 53]);
+        assert_eq!(data.buf, [12, 0, 3, 0x80, 6, 0, 1, 0, 0, 53, 0, 0]);
+    }
+
+    #[test]
+    fn table_message() {
+        let mut batch = Batch::new();
+        batch.table(NFPROTO_IPV4);
+        assert_eq!(batch.messages[0].finish(), [
+            // nlmsghdr: length 32, NFT_MSG_NEWTABLE, REQUEST|ACK|CREATE, seq 2, pid 0
+            32, 0, 0, 0, 0x00, 0x0a, 0x05, 0x04, 2, 0, 0, 0, 0, 0, 0, 0,
+            // nfgenmsg: NFPROTO_IPV4, NFNETLINK_V0, res_id 0
+            2, 0, 0, 0,
+            // NFTA_TABLE_NAME "hulios"
+            11, 0, 1, 0, b'h', b'u', b'l', b'i', b'o', b's', 0, 0,
+        ]);
+        assert_eq!(batch.script, ["add table ip hulios"]);
+    }
+
+    #[test]
+    fn base_chain_message() {
+        let mut batch = Batch::new();
+        batch.chain(NFPROTO_IPV4, "prerouting", "nat", NF_INET_PRE_ROUTING, -100, None);
+        assert_eq!(batch.messages[0].finish(), [
+            // nlmsghdr: length 76, NFT_MSG_NEWCHAIN, REQUEST|ACK|CREATE, seq 2
+            76, 0, 0, 0, 0x03, 0x0a, 0x05, 0x04, 2, 0, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0,
+            // NFTA_CHAIN_TABLE "hulios"
+            11, 0, 1, 0, b'h', b'u', b'l', b'i', b'o', b's', 0, 0,
+            // NFTA_CHAIN_NAME "prerouting"
+            15, 0, 3, 0, b'p', b'r', b'e', b'r', b'o', b'u', b't', b'i', b'n', b'g', 0, 0,
+            // NFTA_CHAIN_HOOK { HOOKNUM 0, PRIORITY -100 }
+            20, 0, 4, 0x80, 8, 0, 1, 0, 0, 0, 0, 0, 8, 0, 2, 0, 0xff, 0xff, 0xff, 0x9c,
+            // NFTA_CHAIN_TYPE "nat"
+            8, 0, 7, 0, b'n', b'a', b't', 0,
+        ]);
+        assert_eq!(batch.script, ["add chain ip hulios prerouting { type nat hook prerouting priority -100; }"]);
+    }
+
+    #[test]
+    fn chain_policy_is_encoded() {
+        let mut batch = Batch::new();
+        batch.chain(NFPROTO_IPV6, "output", "filter", NF_INET_LOCAL_OUT, 0, Some(NF_DROP));
+        let message = batch.messages[0].finish();
+        // nfgenmsg family
+        assert_eq!(message[16], NFPROTO_IPV6);
+        // NFTA_CHAIN_POLICY NF_DROP, after the table, name and hook
+        assert_eq!(&message[20 + 12 + 12 + 20..][..8], [8, 0, 5, 0, 0, 0, 0, 0]);
+        assert_eq!(batch.script, ["add chain ip6 hulios output { type filter hook output priority 0; policy drop; }"]);
+    }
+
+    #[test]
+    fn rule_message() {
+        let mut batch = Batch::new();
+        let rule = Rule::new().l4proto(libc::IPPROTO_TCP).dport(53).accept();
+        batch.rule(NFPROTO_IPV4, "output", "dns", rule);
+
+        let mut expected = vec![
+            // nlmsghdr: length 272, NFT_MSG_NEWRULE, REQUEST|ACK|CREATE|APPEND, seq 2
+            0x10, 0x01, 0, 0, 0x06, 0x0a, 0x05, 0x0c, 2, 0, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0,
+            // NFTA_RULE_TABLE "hulios", NFTA_RULE_CHAIN "output"
+            11, 0, 1, 0, b'h', b'u', b'l', b'i', b'o', b's', 0, 0,
+            11, 0, 2, 0, b'o', b'u', b't', b'p', b'u', b't', 0, 0,
+            // NFTA_RULE_EXPRESSIONS
+            228, 0, 4, 0x80,
+        ];
+        expected.extend([
+            // meta load l4proto => reg 1
+            36, 0, 1, 0x80,
+            9, 0, 1, 0, b'm', b'e', b't', b'a', 0, 0, 0, 0,
+            20, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 1, 8, 0, 2, 0, 0, 0, 0, 16,
+            // cmp eq reg 1 0x06
+            44, 0, 1, 0x80,
+            8, 0, 1, 0, b'c', b'm', b'p', 0,
+            32, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 1, 8, 0, 2, 0, 0, 0, 0, 0,
+            12, 0, 3, 0x80, 5, 0, 1, 0, 6, 0, 0, 0,
+            // payload load 2b @ transport header + 2 => reg 1
+            52, 0, 1, 0x80,
+            12, 0, 1, 0, b'p', b'a', b'y', b'l', b'o', b'a', b'd', 0,
+            36, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 1, 8, 0, 2, 0, 0, 0, 0, 2,
+            8, 0, 3, 0, 0, 0, 0, 2, 8, 0, 4, 0, 0, 0, 0, 2,
+            // cmp eq reg 1 0x0035
+            44, 0, 1, 0x80,
+            8, 0, 1, 0, b'c', b'm', b'p', 0,
+            32, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 1, 8, 0, 2, 0, 0, 0, 0, 0,
+            12, 0, 3, 0x80, 6, 0, 1, 0, 0, 53, 0, 0,
+            // immediate reg 0 accept
+            48, 0, 1, 0x80,
+            14, 0, 1, 0, b'i', b'm', b'm', b'e', b'd', b'i', b'a', b't', b'e', 0, 0, 0,
+            28, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 0,
+            16, 0, 2, 0x80, 12, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 1,
+        ]);
+        assert_eq!(batch.messages[0].finish(), expected);
+        assert_eq!(batch.script, ["add rule ip hulios output meta l4proto tcp th dport 53 accept"]);
+    }
+
+    #[test]
+    fn daddr_prefix_is_masked() {
+        let rule = Rule::new().daddr_v4([10, 66, 7, 9], 16);
+        assert_eq!(rule.text, ["ip daddr 10.66.0.0/16"]);
+        // payload, then bitwise with the mask, then cmp against the network
+        let bitwise = &rule.exprs[1];
+        assert!(bitwise.windows(8).any(|w| w == [8, 0, 1, 0, 255, 255, 0, 0]));
+        let cmp = &rule.exprs[2];
+        assert!(cmp.ends_with(&[8, 0, 1, 0, 10, 66, 0, 0]));
+
+        let any = Rule::new().daddr_v4([1, 2, 3, 4], 0);
+        assert_eq!(any.text, ["ip daddr 0.0.0.0/0"]);
+    }
+
+    #[test]
+    fn jump_names_its_chain() {
+        let rule = Rule::new().jump("isolated");
+        assert_eq!(rule.exprs[0][16..], [
+            // NFTA_EXPR_DATA { DREG verdict, DATA { VERDICT { CODE NFT_JUMP, CHAIN "isolated" } } }
+            44, 0, 2, 0x80, 8, 0, 1, 0, 0, 0, 0, 0,
+            32, 0, 2, 0x80, 28, 0, 2, 0x80,
+            8, 0, 1, 0, 0xff, 0xff, 0xff, 0xfd,
+            13, 0, 2, 0, b'i', b's', b'o', b'l', b'a', b't', b'e', b'd', 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn interface_names_are_padded_or_prefixes() {
+        assert_eq!(ifname("eth0").len(), libc::IFNAMSIZ);
+        assert_eq!(&ifname("eth0")[..5], b"eth0\0");
+        assert_eq!(ifname("vif+"), b"vif");
+        assert_eq!(ifname_pattern("vif+"), "vif*");
+    }
+
+    #[test]
+    fn batch_is_framed() {
+        let mut batch = Batch::new();
+        batch.table(NFPROTO_IPV4);
+        let buf = batch.encode();
+        assert_eq!(buf.len(), 20 + 32 + 20);
+        // BATCH_BEGIN, REQUEST, seq 1, AF_UNSPEC, res_id NFNL_SUBSYS_NFTABLES
+        assert_eq!(buf[..20], [20, 0, 0, 0, 0x10, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10]);
+        // BATCH_END, seq one past the last message
+        assert_eq!(buf[52..], [20, 0, 0, 0, 0x11, 0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10]);
+    }
+}
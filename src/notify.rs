@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::Path;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::exec::Run;
+use crate::tray;
+use crate::config::{Config, NotificationConfig, Profile};
+
+pub use crate::config::NotifyEvent as Event;
+
+/// Set by `--quiet`: never invoke notify-send, whatever the config says
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Set when the privileged helper runs this command for a user: notifications
+/// are passed back to that user's own `hulios` on stderr, marked with
+/// RELAY_MARKER, instead of being shown from root
+static RELAY: AtomicBool = AtomicBool::new(false);
+
+pub const RELAY_MARKER: &str = "\u{1e}hulios-notify ";
+
+pub fn set_relay(relay: bool) {
+    RELAY.store(relay, Ordering::Relaxed);
+}
+
+/// Through Tor, a webhook can take a while; longer and it holds up the
+/// command or check that raised the alert
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Notify about `event` if the notification preferences allow it: to the
+/// webhook if one is set, and to the desktop unless quiet mode or the
+/// appliance profile rule that out
+pub fn send(event: Event, title: &str, body: &str) {
+    let config = Config::load().unwrap_or_default();
+    let Some(urgency) = urgency_for(&config.notifications, event) else {
+        return;
+    };
+    if let Some(url) = &config.notifications.webhook {
+        post_webhook(url, event, title, body, urgency);
+    }
+    tray::publish(event, title, body, urgency);
+    // Headless: there is no desktop to show it on
+    if QUIET.load(Ordering::Relaxed) || config.profile == Profile::Appliance {
+        return;
+    }
+    if RELAY.load(Ordering::Relaxed) {
+        eprintln!("{}{}", RELAY_MARKER, serde_json::json!({ "title": title, "body": body, "urgency": urgency }));
+        return;
+    }
+    send_notification(title, body, urgency);
+}
+
+/// Show a notification the helper relayed; this process already runs as
+/// the user it is meant for
+pub fn show(title: &str, body: &str, urgency: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        send_notification(title, body, urgency);
+    }
+}
+
+/// Urgency to use for `event`, or None if it shouldn't notify at all
+fn urgency_for(prefs: &NotificationConfig, event: Event) -> Option<&'static str> {
+    if !prefs.enabled || !prefs.events.contains(&event) {
+        return None;
+    }
+    let urgency = prefs.urgency.get(&event).copied().unwrap_or_else(|| event.default_urgency());
+    Some(urgency.as_str())
+}
+
+// =============================================================================
+// Webhook
+// =============================================================================
+
+/// POST the notification as JSON. Runs on a thread of its own, since the
+/// daemon also notifies from async tasks, where a blocking client can't run.
+/// Failures are only logged: an alert must never break what raised it.
+fn post_webhook(url: &str, event: Event, title: &str, body: &str, urgency: &str) {
+    let payload = serde_json::json!({
+        "event": event.as_str(),
+        "title": title,
+        "body": body,
+        "urgency": urgency,
+        "host": fs::read_to_string("/etc/hostname").unwrap_or_default().trim(),
+    });
+    let url = url.to_string();
+    let sent = thread::spawn(move || -> reqwest::Result<()> {
+        reqwest::blocking::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?
+            .post(url)
+            .json(&payload)
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }).join();
+    if let Ok(Err(e)) = sent {
+        eprintln!("[!] Notification webhook failed: {}", e);
+    }
+}
+
+// =============================================================================
+// Notifications - Works on both X11 and Wayland (Hyprland, Sway, etc.)
+// =============================================================================
+
+/// Send desktop notification using notify-send
+/// Works on both X11 and Wayland by detecting the environment
+fn send_notification(title: &str, body: &str, urgency: &str) {
+    // Get the original user (before sudo)
+    let sudo_user = std::env::var("SUDO_USER").unwrap_or_default();
+    if sudo_user.is_empty() {
+        // Running as root directly without sudo, try anyway
+        let _ = Command::new("notify-send")
+            .args(["-u", urgency, "-a", "HULIOS", title, body])
+            .run();
+        return;
+    }
+    
+    let mut cmd = as_desktop_user(&sudo_user);
+    cmd.arg("notify-send")
+        .args(["-u", urgency, "-a", "HULIOS", "-i", "network-vpn", title, body]);
+    
+    let _ = cmd.run();
+}
+
+/// Ask the desktop user a yes/no question as a notification with one
+/// action button; true only if they click it within `timeout`. False
+/// without anyone to ask: in quiet mode, on an appliance, or with no
+/// graphical session (e.g. from a NetworkManager hook).
+pub fn ask(title: &str, body: &str, action: &str, timeout: Duration) -> bool {
+    let config = Config::load().unwrap_or_default();
+    if QUIET.load(Ordering::Relaxed) || config.profile == Profile::Appliance {
+        return false;
+    }
+    let Some(user) = std::env::var("SUDO_USER").ok().filter(|user| !user.is_empty()).or_else(desktop_user) else {
+        return false;
+    };
+    let mut cmd = as_desktop_user(&user);
+    cmd.arg("notify-send")
+        .args(["-u", "normal", "-a", "HULIOS", "-i", "network-vpn", "--wait"])
+        .arg(format!("--expire-time={}", timeout.as_millis()))
+        .arg(format!("--action=yes={}", action))
+        .args([title, body])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let Ok(mut child) = cmd.spawn() else {
+        return false;
+    };
+    // Not every notification server honors the expiry time
+    let deadline = Instant::now() + timeout;
+    while child.try_wait().ok().flatten().is_none() {
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return false;
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    // notify-send prints the name of the action clicked, if any
+    let mut clicked = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut clicked);
+    }
+    clicked.trim() == "yes"
+}
+
+/// The user of a graphical session, found by their session bus in /run/user
+fn desktop_user() -> Option<String> {
+    fs::read_dir("/run/user")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|uid| *uid >= 1000 && Path::new(&format!("/run/user/{}/bus", uid)).exists())
+        .min()
+        .and_then(users::get_user_by_uid)
+        .map(|user| user.name().to_string_lossy().into_owned())
+}
+
+/// `sudo -u <user>` with what a notification needs to reach their desktop;
+/// the program and its arguments are added by the caller
+fn as_desktop_user(sudo_user: &str) -> Command {
+    // Get the user's UID for XDG_RUNTIME_DIR
+    let uid = get_user_uid(sudo_user).unwrap_or(1000);
+    let xdg_runtime = format!("/run/user/{}", uid);
+    
+    // Try to detect Wayland first (common for Hyprland/Sway)
+    let wayland_display = find_wayland_display(&xdg_runtime);
+    
+    // Also get X11 display if available
+    let x11_display = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+    
+    // Build environment for the notification command
+    let mut env_vars = vec![
+        ("XDG_RUNTIME_DIR", xdg_runtime.clone()),
+        ("HOME", format!("/home/{}", sudo_user)),
+    ];
+    
+    // Add Wayland-specific vars if detected
+    if let Some(ref wd) = wayland_display {
+        env_vars.push(("WAYLAND_DISPLAY", wd.clone()));
+        // Hyprland instance signature (if available)
+        if let Ok(his) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+            env_vars.push(("HYPRLAND_INSTANCE_SIGNATURE", his));
+        }
+    }
+    
+    // Always add DISPLAY for X11 fallback
+    env_vars.push(("DISPLAY", x11_display.clone()));
+    
+    // Also need DBUS for notifications on most systems
+    let dbus_addr = format!("unix:path={}/bus", xdg_runtime);
+    env_vars.push(("DBUS_SESSION_BUS_ADDRESS", dbus_addr));
+    
+    // Run as the original user with proper environment
+    let mut cmd = Command::new("sudo");
+    cmd.arg("-u").arg(sudo_user);
+    
+    // Set environment variables
+    for (key, val) in &env_vars {
+        cmd.arg(format!("{}={}", key, val));
+    }
+    cmd
+}
+
+/// Get the UID of a user by name
+fn get_user_uid(username: &str) -> Option<u32> {
+    users::get_user_by_name(username).map(|user| user.uid())
+}
+
+/// Find the Wayland display socket in XDG_RUNTIME_DIR
+fn find_wayland_display(xdg_runtime: &str) -> Option<String> {
+    // Check for common Wayland socket names
+    let candidates = ["wayland-0", "wayland-1", "wayland-2"];
+    
+    for candidate in candidates {
+        let path = format!("{}/{}", xdg_runtime, candidate);
+        if std::path::Path::new(&path).exists() {
+            return Some(candidate.to_string());
+        }
+    }
+    
+    // Also check WAYLAND_DISPLAY from current environment
+    std::env::var("WAYLAND_DISPLAY").ok()
+}
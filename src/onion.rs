@@ -0,0 +1,278 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use users::get_current_uid;
+use crate::config::{Config, CONFIG_PATH};
+use crate::control::ControlClient;
+use crate::exec::Run;
+use crate::{configtool, confirm, engine, i18n, report};
+
+/// HiddenServiceDir of the ssh onion service. Outside Tor's data
+/// directory, which every session starts afresh, so the address stays.
+const SSH_SERVICE_DIR: &str = "/var/lib/hulios/onion-ssh";
+
+/// Keeps sshd on localhost, where only the onion service reaches it
+pub const SSHD_DROPIN: &str = "/etc/ssh/sshd_config.d/90-hulios-onion.conf";
+
+const SSHD_CONFIG: &str = "/etc/ssh/sshd_config";
+
+/// RFC 4648 base32, which Tor's client authorization keys are written in
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub const LOCALHOST_CHANGES: &[&str] = &[
+    "confirm-localhost-make-sshd-listen",
+    "confirm-localhost-while-stopped-onion",
+];
+
+/// The torrc options publishing the configured onion services
+pub fn torrc_options(config: &Config) -> Vec<(&'static str, String)> {
+    if !config.onion.ssh {
+        return Vec::new();
+    }
+    vec![
+        ("HiddenServiceDir", SSH_SERVICE_DIR.to_string()),
+        ("HiddenServicePort", format!("22 127.0.0.1:{}", config.onion.ssh_port)),
+    ]
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios onion ssh enable`: publish sshd as an onion service that only
+/// holders of `client`'s key can even see, and print what the client needs.
+/// A new key is made on every call; an existing client of that name is
+/// replaced.
+pub fn enable_ssh(client: &str, localhost_only: bool, assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if client.is_empty() || client.len() > 32 || !client.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!(i18n::tr("onion-invalid-client-name", &[("client", client)]));
+    }
+    // The address comes from Tor, and the client's key is never stored here
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("onion-not-running-use-start", &[]));
+    }
+    if localhost_only {
+        confirm::confirm(&i18n::tr("confirm-locking-sshd", &[]), &confirm::changes(LOCALHOST_CHANGES), assume_yes)?;
+    }
+
+    prepare_service_dir()?;
+    let (private, public) = client_keypair()?;
+    let auth = format!("{}/authorized_clients/{}.auth", SSH_SERVICE_DIR, client);
+    fs::write(&auth, format!("descriptor:x25519:{}\n", base32(&public)))
+        .with_context(|| i18n::tr("error-write", &[("path", &auth)]))?;
+    chown_tor(Path::new(&auth))?;
+
+    let mut config = Config::load()?;
+    config.onion.ssh = true;
+    let mut control = ControlClient::connect()?;
+    // Tor reads authorized_clients when the service is (re)configured
+    control.setconf(&torrc_options(&config)).with_context(|| i18n::tr("onion-tor-refused-onion-service", &[]))?;
+    let address = wait_for_hostname()?;
+    // Saved once Tor took it, so later sessions publish it too
+    set_config("true")?;
+
+    if localhost_only {
+        lock_sshd(config.onion.ssh_port)?;
+    }
+
+    println!("{}", format!("[+] {}", i18n::tr("onion-sshd-published-port", &[("address", &address)])).green());
+    println!("[*] {}", i18n::tr("onion-client-point-tor-directory", &[]));
+    println!("        ClientOnionAuthDir /var/lib/tor/onion_auth");
+    println!("    {}", i18n::tr("onion-save-line-var-lib", &[("client", client)]));
+    println!("        {}:descriptor:x25519:{}", address.trim_end_matches(".onion"), base32(&private));
+    println!("    {}", i18n::tr("onion-shown-only-once-then", &[("address", &address)]));
+    println!("    {}", i18n::tr("onion-followed-ssh-user", &[("address", &address)]));
+    report::action(format!("sshd published as an onion service for client {}", client));
+    report::data("onion", serde_json::json!({ "address": address, "client": client }));
+    Ok(())
+}
+
+/// `hulios onion ssh disable`: stop publishing sshd and let it listen
+/// where it did before. The service's keys are kept, so enabling it again
+/// brings back the same address and clients.
+pub fn disable_ssh() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    set_config("false")?;
+    if engine::is_tor_running() {
+        ControlClient::connect()?.command("RESETCONF HiddenServiceDir HiddenServicePort")?;
+    }
+    if Path::new(SSHD_DROPIN).exists() {
+        fs::remove_file(SSHD_DROPIN).with_context(|| i18n::tr("error-remove", &[("path", SSHD_DROPIN)]))?;
+        reload_sshd()?;
+        println!("[+] {}", i18n::tr("onion-sshd-listens-configured-addresses", &[]));
+    }
+    println!("{}", format!("[+] {}", i18n::tr("onion-sshd-no-longer-published", &[])).green());
+    report::action("sshd onion service removed");
+    Ok(())
+}
+
+/// `hulios onion ssh status`: address, authorized clients and whether
+/// sshd is locked to localhost
+pub fn ssh_status() -> Result<()> {
+    let config = Config::load()?;
+    let address = fs::read_to_string(format!("{}/hostname", SSH_SERVICE_DIR)).ok().map(|name| name.trim().to_string());
+    let clients: Vec<String> = fs::read_dir(format!("{}/authorized_clients", SSH_SERVICE_DIR))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".auth").map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    let localhost_only = Path::new(SSHD_DROPIN).exists();
+
+    if !config.onion.ssh {
+        println!("[*] {}", i18n::tr("onion-sshd-not-published-onion", &[]));
+    } else if engine::is_tor_running() {
+        println!("{}", format!("[+] {}", i18n::tr("onion-sshd-published", &[("address", address.as_deref().unwrap_or("(address not known yet)"))])).green());
+    } else {
+        println!("[*] {}", i18n::tr("onion-sshd-published-while-runs", &[("address", address.as_deref().unwrap_or("(address not known yet)"))]));
+    }
+    if !clients.is_empty() {
+        println!("    {}", i18n::tr("onion-authorized-clients", &[("clients", &clients.join(", "))]));
+    }
+    if localhost_only {
+        println!("    {}", i18n::tr("onion-sshd-listens-loopback", &[("path", SSHD_DROPIN)]));
+    }
+    report::data("onion", serde_json::json!({
+        "enabled": config.onion.ssh,
+        "address": address,
+        "clients": clients,
+        "localhost_only": localhost_only,
+    }));
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn set_config(enabled: &str) -> Result<()> {
+    if !Path::new(CONFIG_PATH).exists() && enabled == "false" {
+        return Ok(());
+    }
+    configtool::set("onion.ssh", enabled)
+}
+
+/// Tor refuses a HiddenServiceDir anyone but its user can read
+fn prepare_service_dir() -> Result<()> {
+    let clients = format!("{}/authorized_clients", SSH_SERVICE_DIR);
+    fs::create_dir_all(&clients).with_context(|| i18n::tr("error-create", &[("path", &clients)]))?;
+    for dir in [SSH_SERVICE_DIR, clients.as_str()] {
+        chown_tor(Path::new(dir))?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+fn chown_tor(path: &Path) -> Result<()> {
+    let tor_user = users::get_user_by_name(engine::TOR_USER)
+        .with_context(|| i18n::tr("error-no-tor-user", &[("user", engine::TOR_USER)]))?;
+    std::os::unix::fs::chown(path, Some(tor_user.uid()), Some(tor_user.primary_group_id()))
+        .with_context(|| i18n::tr("onion-failed-chown", &[("path", &path.display().to_string())]))
+}
+
+/// Tor writes the service's address once it has its keys
+fn wait_for_hostname() -> Result<String> {
+    let path = format!("{}/hostname", SSH_SERVICE_DIR);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Ok(name) = fs::read_to_string(&path) {
+            return Ok(name.trim().to_string());
+        }
+        if Instant::now() > deadline {
+            anyhow::bail!(i18n::tr("onion-tor-did-not-create", &[("path", &path), ("log", engine::TOR_LOG)]));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// A new x25519 key pair (private, public) for client authorization,
+/// from `openssl genpkey -text`: "priv:" and "pub:" each followed by
+/// colon-separated hex lines
+fn client_keypair() -> Result<(Vec<u8>, Vec<u8>)> {
+    let output = Command::new("openssl")
+        .args(["genpkey", "-algorithm", "x25519", "-text"])
+        .run_output()
+        .with_context(|| i18n::tr("onion-failed-run-openssl-installed", &[]))?;
+    if !output.status.success() {
+        anyhow::bail!(i18n::tr("onion-openssl-could-not-generate", &[]));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut keys: [Vec<u8>; 2] = Default::default();
+    let mut current = None;
+    for line in text.lines() {
+        match line.trim() {
+            "priv:" => current = Some(0),
+            "pub:" => current = Some(1),
+            hex if line.starts_with(' ') => {
+                if let Some(key) = current {
+                    keys[key].extend(hex.split(':').filter_map(|byte| u8::from_str_radix(byte, 16).ok()));
+                }
+            }
+            _ => current = None,
+        }
+    }
+    let [private, public] = keys;
+    if private.len() != 32 || public.len() != 32 {
+        anyhow::bail!(i18n::tr("onion-unexpected-key-output-openssl", &[]));
+    }
+    Ok((private, public))
+}
+
+fn base32(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    out
+}
+
+/// Have sshd listen on localhost only, checked with `sshd -t` before it
+/// is reloaded so a bad drop-in never takes sshd down
+fn lock_sshd(port: u16) -> Result<()> {
+    let main = fs::read_to_string(SSHD_CONFIG).with_context(|| i18n::tr("error-read", &[("path", SSHD_CONFIG)]))?;
+    if !main.lines().any(|line| line.trim_start().starts_with("Include") && line.contains("sshd_config.d")) {
+        anyhow::bail!(i18n::tr("onion-does-not-include-sshd", &[("sshd_config", SSHD_CONFIG)]));
+    }
+    fs::write(SSHD_DROPIN, format!("# Written by `hulios onion ssh enable --localhost-only`\nListenAddress 127.0.0.1:{}\n", port))
+        .with_context(|| i18n::tr("error-write", &[("path", SSHD_DROPIN)]))?;
+    let valid = Command::new("sshd").arg("-t").run().is_ok_and(|status| status.success());
+    if !valid {
+        let _ = fs::remove_file(SSHD_DROPIN);
+        anyhow::bail!(i18n::tr("onion-sshd-rejected-configuration-sshd", &[]));
+    }
+    reload_sshd()?;
+    println!("[+] {}", i18n::tr("onion-sshd-now-listens-loopback", &[("port", &port.to_string())]));
+    report::action("sshd locked to localhost");
+    Ok(())
+}
+
+/// Debian names the unit ssh, most others sshd
+fn reload_sshd() -> Result<()> {
+    let reloaded = ["ssh", "sshd"].iter().any(|unit| {
+        Command::new("systemctl").args(["reload", unit]).run().is_ok_and(|status| status.success())
+    });
+    if !reloaded {
+        anyhow::bail!(i18n::tr("onion-could-not-reload-sshd", &[]));
+    }
+    Ok(())
+}
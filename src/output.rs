@@ -0,0 +1,38 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Line-oriented output: no colors, no animated progress bars
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Decide once, before any output, how the CLI should render.
+/// Colors are dropped for `--no-color`, `--plain`, a non-empty NO_COLOR, or
+/// when stdout or stderr is not a terminal (cron, systemd, pipes).
+/// Progress bars are replaced by plain lines under `--plain` or without a tty.
+pub fn init(no_color: bool, plain: bool) {
+    let interactive = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+
+    if no_color || plain || no_color_env || !interactive {
+        colored::control::set_override(false);
+    }
+    PLAIN.store(plain || !interactive, Ordering::Relaxed);
+}
+
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Progress bar with the given template, hidden in plain mode
+pub fn progress_bar(len: u64, template: &str) -> ProgressBar {
+    if plain() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len);
+    bar.set_style(
+        ProgressStyle::with_template(template)
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("=> "),
+    );
+    bar
+}
@@ -0,0 +1,389 @@
+use anyhow::{Result, Context};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket};
+use std::os::fd::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use crate::config::Config;
+use crate::firewall::OVERLAY_NETWORK;
+use crate::{engine, i18n, i2p, procfs, report};
+
+/// The overlay router: UDP DNS is redirected here instead of to Tor
+pub const DNS_PORT: u16 = 9062;
+
+/// The overlay router's transparent port, for TCP to OVERLAY_NETWORK
+pub const TRANS_PORT: u16 = 9063;
+
+/// Where the router passes every name no overlay handles
+const TOR_DNS: &str = "127.0.0.1:9061";
+
+const LOG: &str = "/tmp/hulios_overlay.log";
+
+const DNS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Virtual addresses are handed out for this long in DNS answers
+const TTL: u32 = 60;
+
+const QTYPE_A: u16 = 1;
+
+/// An anonymizing network besides Tor, for the destinations under its own
+/// DNS suffix. Tor stays the backend for everything else: the overlay
+/// router answers the network's names with addresses in OVERLAY_NETWORK and
+/// hands connections to them to the network's SOCKS proxy by name.
+pub trait Overlay: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Names ending in this (e.g. ".i2p") go to this network
+    fn suffix(&self) -> &'static str;
+
+    /// The network's SOCKS5 proxy, which takes hostnames
+    fn socks(&self) -> SocketAddr;
+
+    /// Start the network's router for the session, as the tor user (whose
+    /// traffic the firewall lets out); returns its PID
+    fn launch(&self) -> Result<u32>;
+
+    /// Stop the network's router, if it runs
+    fn halt(&self);
+}
+
+/// Every overlay network HULIOS supports, and whether the config enables it
+fn supported(config: &Config) -> Vec<(Box<dyn Overlay>, bool)> {
+    vec![(Box::new(i2p::I2p::from_config(&config.i2p)), config.i2p.enabled)]
+}
+
+/// The overlay networks the config enables
+pub fn enabled(config: &Config) -> Vec<Box<dyn Overlay>> {
+    supported(config).into_iter().filter_map(|(overlay, enabled)| enabled.then_some(overlay)).collect()
+}
+
+/// The overlay that handles `host`, None for Tor
+pub fn route<'a>(overlays: &'a [Box<dyn Overlay>], host: &str) -> Option<&'a dyn Overlay> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    overlays.iter().find(|overlay| host.ends_with(overlay.suffix())).map(|overlay| overlay.as_ref())
+}
+
+// =============================================================================
+// Session
+// =============================================================================
+
+/// Start the enabled networks and the overlay router, before the firewall
+/// sends DNS to it. A network that fails to start only loses its own
+/// names; the router is needed for all DNS.
+pub fn launch_all(config: &Config) -> Result<()> {
+    halt_all(config);
+    let overlays = enabled(config);
+    if overlays.is_empty() {
+        return Ok(());
+    }
+    for overlay in &overlays {
+        match overlay.launch() {
+            Ok(pid) => {
+                println!("[*] {}", i18n::tr("overlay-starting-pid-experimental", &[("name", overlay.name()), ("pid", &pid.to_string())]));
+                report::action(format!("{} started (PID {})", overlay.name(), pid));
+            }
+            Err(e) => {
+                eprintln!("[!] {}", i18n::tr("overlay-did-not-start-names", &[("name", overlay.name()), ("error", &format!("{:#}", e))]));
+                report::warning(format!("{} did not start: {:#}", overlay.name(), e));
+            }
+        }
+    }
+    launch_router()
+}
+
+/// Stop the overlay router and every network's router, enabled or not
+pub fn halt_all(config: &Config) {
+    for pid in router_pids() {
+        procfs::terminate(pid as i32, Duration::from_secs(5));
+    }
+    for (overlay, _) in supported(config) {
+        overlay.halt();
+    }
+}
+
+fn launch_router() -> Result<()> {
+    let exe = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG)
+        .with_context(|| i18n::tr("error-open", &[("path", LOG)]))?;
+    let child = Command::new(exe)
+        .arg("overlay-router")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Own process group, so Ctrl-C in the starting terminal doesn't reach it
+        .process_group(0)
+        .spawn()
+        .with_context(|| i18n::tr("overlay-failed-start-overlay-router", &[]))?;
+
+    // Ready once its DNS port is taken
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while StdUdpSocket::bind(("127.0.0.1", DNS_PORT)).is_ok() {
+        if Instant::now() > deadline || !procfs::is_alive(child.id() as i32) {
+            anyhow::bail!(i18n::tr("overlay-router-did-not-start", &[("log", LOG)]));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    println!("[+] {}", i18n::tr("overlay-router-listening", &[("dns_port", &DNS_PORT.to_string()), ("trans_port", &TRANS_PORT.to_string())]));
+    report::action(format!("Overlay router started (PID {})", child.id()));
+    Ok(())
+}
+
+fn router_pids() -> Vec<u32> {
+    procfs::find_by_name("hulios")
+        .into_iter()
+        .filter(|info| info.cmdline.iter().any(|arg| arg == "overlay-router"))
+        .map(|info| info.pid as u32)
+        .collect()
+}
+
+// =============================================================================
+// Router (`hulios overlay-router`)
+// =============================================================================
+
+/// The names answered so far and their virtual addresses
+#[derive(Default)]
+struct Names {
+    by_name: HashMap<String, Ipv4Addr>,
+    by_address: HashMap<Ipv4Addr, String>,
+    next: u32,
+}
+
+impl Names {
+    /// The name's address, handing out the next one if it has none; once
+    /// the range is used up the oldest addresses are reused
+    fn address(&mut self, name: &str) -> Ipv4Addr {
+        if let Some(address) = self.by_name.get(name) {
+            return *address;
+        }
+        let (network, prefix) = OVERLAY_NETWORK;
+        let hosts = (1u32 << (32 - prefix as u32)) - 2;
+        let address = Ipv4Addr::from(u32::from(Ipv4Addr::from(network)) + 1 + self.next % hosts);
+        self.next += 1;
+        if let Some(old) = self.by_address.insert(address, name.to_string()) {
+            self.by_name.remove(&old);
+        }
+        self.by_name.insert(name.to_string(), address);
+        address
+    }
+}
+
+/// Run the overlay router until killed: DNS for the overlays' names on
+/// DNS_PORT (the rest is passed to Tor's DNSPort), and their connections
+/// on TRANS_PORT
+pub fn serve() -> Result<()> {
+    let config = Config::load()?;
+    let overlays: Arc<Vec<Box<dyn Overlay>>> = Arc::new(enabled(&config));
+    let names = Arc::new(Mutex::new(Names::default()));
+    println!("[*] {} overlay router started (pid {})", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), std::process::id());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the overlay router's runtime")?;
+    runtime.block_on(async {
+        let dns = Arc::new(UdpSocket::bind(("127.0.0.1", DNS_PORT)).await.context("Failed to bind the overlay DNS port")?);
+        let listener = TcpListener::bind(("127.0.0.1", TRANS_PORT)).await.context("Failed to bind the overlay TCP port")?;
+        // Root was only needed to read the config; the tor user's traffic
+        // is what the firewall lets reach Tor and the overlays unredirected
+        drop_privileges(engine::TOR_USER)?;
+        tokio::select! {
+            result = serve_dns(dns, overlays.clone(), names.clone()) => result,
+            result = serve_tcp(listener, overlays, names) => result,
+        }
+    })
+}
+
+async fn serve_dns(socket: Arc<UdpSocket>, overlays: Arc<Vec<Box<dyn Overlay>>>, names: Arc<Mutex<Names>>) -> Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let query = buf[..len].to_vec();
+        let Some((name, qtype, end)) = question(&query) else {
+            continue;
+        };
+        if route(&overlays, &name).is_some() {
+            let address = (qtype == QTYPE_A).then(|| names.lock().unwrap().address(&name));
+            let _ = socket.send_to(&answer(&query, end, address), peer).await;
+            continue;
+        }
+        let socket = socket.clone();
+        tokio::spawn(async move {
+            match forward(&query).await {
+                Ok(reply) => {
+                    let _ = socket.send_to(&reply, peer).await;
+                }
+                Err(e) => eprintln!("[!] DNS for {} not answered by Tor: {:#}", name, e),
+            }
+        });
+    }
+}
+
+/// Pass a query on to Tor's DNSPort and return its reply
+async fn forward(query: &[u8]) -> Result<Vec<u8>> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    socket.connect(TOR_DNS).await?;
+    socket.send(query).await?;
+    let mut buf = vec![0u8; 4096];
+    let len = tokio::time::timeout(DNS_TIMEOUT, socket.recv(&mut buf)).await.context("timed out")??;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+async fn serve_tcp(listener: TcpListener, overlays: Arc<Vec<Box<dyn Overlay>>>, names: Arc<Mutex<Names>>) -> Result<()> {
+    loop {
+        let (client, _) = listener.accept().await?;
+        let overlays = overlays.clone();
+        let names = names.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay(client, &overlays, &names).await {
+                eprintln!("[!] Overlay connection failed: {:#}", e);
+            }
+        });
+    }
+}
+
+/// Connect a redirected client to the name its virtual address stands for,
+/// through the network's SOCKS proxy
+async fn relay(mut client: TcpStream, overlays: &[Box<dyn Overlay>], names: &Mutex<Names>) -> Result<()> {
+    let destination = original_destination(&client)?;
+    let name = names
+        .lock()
+        .unwrap()
+        .by_address
+        .get(destination.ip())
+        .cloned()
+        .with_context(|| format!("{} was not handed out (stale DNS cache?)", destination.ip()))?;
+    let overlay = route(overlays, &name).with_context(|| format!("No overlay handles {}", name))?;
+    let mut upstream = TcpStream::connect(overlay.socks())
+        .await
+        .with_context(|| format!("{}'s SOCKS proxy is not reachable", overlay.name()))?;
+    socks_connect(&mut upstream, &name, destination.port())
+        .await
+        .with_context(|| format!("{} via {}", name, overlay.name()))?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// SOCKS5 CONNECT by hostname, no authentication (RFC 1928)
+async fn socks_connect(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [5, 0] {
+        anyhow::bail!("the SOCKS proxy wants authentication");
+    }
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend(host.as_bytes());
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        anyhow::bail!("refused (SOCKS reply {})", head[1]);
+    }
+    // The bound address, which nobody needs
+    let rest = match head[3] {
+        1 => 4 + 2,
+        4 => 16 + 2,
+        3 => stream.read_u8().await? as usize + 2,
+        other => anyhow::bail!("malformed SOCKS reply (address type {})", other),
+    };
+    let mut bound = vec![0u8; rest];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Switch the whole process to `name` for good, once root is no longer needed
+pub fn drop_privileges(name: &str) -> Result<()> {
+    let user = users::get_user_by_name(name).with_context(|| format!("User '{}' does not exist", name))?;
+    // SAFETY: plain syscalls; glibc applies setgid/setuid to every thread
+    let ok = unsafe {
+        libc::setgroups(0, std::ptr::null()) == 0
+            && libc::setgid(user.primary_group_id()) == 0
+            && libc::setuid(user.uid()) == 0
+    };
+    if !ok {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("Failed to switch to the {} user", name));
+    }
+    Ok(())
+}
+
+/// Where a connection redirected by NAT was headed (SO_ORIGINAL_DST)
+fn original_destination(stream: &TcpStream) -> Result<SocketAddrV4> {
+    // SAFETY: sockaddr_in is plain data, and getsockopt writes at most `len` bytes into it
+    let mut address: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    // SAFETY: a valid socket fd and a pointer/length pair describing `address`
+    let ret = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_IP, libc::SO_ORIGINAL_DST,
+            &mut address as *mut libc::sockaddr_in as *mut libc::c_void, &mut len)
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error()).context("No original destination (not redirected?)");
+    }
+    Ok(SocketAddrV4::new(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)), u16::from_be(address.sin_port)))
+}
+
+// =============================================================================
+// DNS Messages
+// =============================================================================
+
+/// The question of a standard query: its name (lowercase, without the
+/// trailing dot), type, and where the question ends
+fn question(packet: &[u8]) -> Option<(String, u16, usize)> {
+    let header = packet.get(..12)?;
+    // A query (QR clear) with exactly one question
+    if header[2] & 0x80 != 0 || u16::from_be_bytes([header[4], header[5]]) != 1 {
+        return None;
+    }
+    let mut labels = Vec::new();
+    let mut i = 12;
+    loop {
+        let len = *packet.get(i)? as usize;
+        i += 1;
+        if len == 0 {
+            break;
+        }
+        // Questions are never compressed
+        if len > 63 {
+            return None;
+        }
+        labels.push(std::str::from_utf8(packet.get(i..i + len)?).ok()?.to_ascii_lowercase());
+        i += len;
+    }
+    let qtype = u16::from_be_bytes(packet.get(i..i + 2)?.try_into().ok()?);
+    packet.get(i + 2..i + 4)?;
+    Some((labels.join("."), qtype, i + 4))
+}
+
+/// The reply to a query answered here: `address` as its A record, or no
+/// records (for the types an overlay has no answer of)
+fn answer(query: &[u8], end: usize, address: Option<Ipv4Addr>) -> Vec<u8> {
+    let mut reply = query[..end].to_vec();
+    // QR and AA, keeping the opcode and RD; RA and NOERROR
+    reply[2] = 0x84 | (query[2] & 0x79);
+    reply[3] = 0x80;
+    reply[6..8].copy_from_slice(&(address.is_some() as u16).to_be_bytes());
+    // No authority or additional records (the query's EDNS OPT is dropped)
+    reply[8..12].fill(0);
+    if let Some(address) = address {
+        // The name, as a pointer to the question's
+        reply.extend([0xc0, 0x0c]);
+        reply.extend(QTYPE_A.to_be_bytes());
+        reply.extend(1u16.to_be_bytes());
+        reply.extend(TTL.to_be_bytes());
+        reply.extend(4u16.to_be_bytes());
+        reply.extend(address.octets());
+    }
+    reply
+}
@@ -0,0 +1,184 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::path::Path;
+use crate::{audit, engine, i18n, report};
+
+/// Tor's SOCKSPort; package managers resolve hostnames through it too
+const SOCKS_PROXY: &str = "socks5h://127.0.0.1:9050";
+
+/// Precedes every line HULIOS adds to a shared config file, so the line
+/// after it can be found and removed again
+const MARKER: &str = "# Added by hulios pkg-proxy, removed on hulios stop";
+
+/// apt reads every file in apt.conf.d, so it gets a file of its own
+const APT_SNIPPET: &str = "/etc/apt/apt.conf.d/99hulios-proxy";
+const DNF_CONF: &str = "/etc/dnf/dnf.conf";
+const PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// A package manager HULIOS knows how to point at Tor
+#[derive(Clone, Copy)]
+enum Manager {
+    Apt,
+    Dnf,
+    Pacman,
+}
+
+impl Manager {
+    const ALL: [Manager; 3] = [Manager::Apt, Manager::Dnf, Manager::Pacman];
+
+    fn name(self) -> &'static str {
+        match self {
+            Manager::Apt => "apt",
+            Manager::Dnf => "dnf",
+            Manager::Pacman => "pacman",
+        }
+    }
+
+    /// The file the proxy setting is written to
+    fn path(self) -> &'static str {
+        match self {
+            Manager::Apt => APT_SNIPPET,
+            Manager::Dnf => DNF_CONF,
+            Manager::Pacman => PACMAN_CONF,
+        }
+    }
+
+    fn installed(self) -> bool {
+        match self {
+            Manager::Apt => Path::new("/etc/apt/apt.conf.d").is_dir(),
+            Manager::Dnf => Path::new(DNF_CONF).exists(),
+            Manager::Pacman => Path::new(PACMAN_CONF).exists(),
+        }
+    }
+
+    fn enabled(self) -> bool {
+        match self {
+            Manager::Apt => Path::new(APT_SNIPPET).exists(),
+            Manager::Dnf => has_marker(DNF_CONF),
+            Manager::Pacman => has_marker(PACMAN_CONF),
+        }
+    }
+
+    fn enable(self) -> Result<()> {
+        audit::record("write", self.path());
+        match self {
+            Manager::Apt => fs::write(APT_SNIPPET, format!(
+                "// Added by hulios pkg-proxy, removed on hulios stop\n\
+                 Acquire::http::Proxy \"{0}\";\nAcquire::https::Proxy \"{0}\";\n", SOCKS_PROXY))
+                .with_context(|| i18n::tr("error-write", &[("path", APT_SNIPPET)])),
+            Manager::Dnf => insert_into_section(DNF_CONF, "main", &format!("proxy={}", SOCKS_PROXY)),
+            Manager::Pacman => insert_into_section(PACMAN_CONF, "options",
+                "XferCommand = /usr/bin/curl --socks5-hostname 127.0.0.1:9050 -L -C - -f -o %o %u"),
+        }
+    }
+
+    fn disable(self) -> Result<()> {
+        audit::record("restore", self.path());
+        match self {
+            Manager::Apt => fs::remove_file(APT_SNIPPET)
+                .with_context(|| i18n::tr("error-remove", &[("path", APT_SNIPPET)])),
+            Manager::Dnf => remove_marked(DNF_CONF),
+            Manager::Pacman => remove_marked(PACMAN_CONF),
+        }
+    }
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// Point every detected package manager at Tor's SOCKS port
+pub fn enable() -> Result<()> {
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("pkgproxy-not-running-start-first", &[]));
+    }
+
+    let managers: Vec<Manager> = Manager::ALL.into_iter().filter(|m| m.installed()).collect();
+    if managers.is_empty() {
+        anyhow::bail!(i18n::tr("pkgproxy-no-supported-package-manager", &[]));
+    }
+    for manager in managers {
+        if manager.enabled() {
+            println!("[*] {}", i18n::tr("pkgproxy-already-uses-tor", &[("name", manager.name())]));
+            continue;
+        }
+        manager.enable()?;
+        println!("{}", format!("[+] {}", i18n::tr("pkgproxy-now-downloads-through-tor", &[("name", manager.name())])).green());
+        report::action(format!("Package proxy enabled for {}", manager.name()));
+    }
+    Ok(())
+}
+
+/// Remove every proxy snippet HULIOS wrote
+pub fn disable() -> Result<()> {
+    if !is_enabled() {
+        println!("[*] {}", i18n::tr("pkgproxy-no-package-manager-proxy", &[]));
+        return Ok(());
+    }
+    for manager in Manager::ALL.into_iter().filter(|m| m.enabled()) {
+        manager.disable()?;
+        println!("[+] {}", i18n::tr("pkgproxy-proxy-removed", &[("name", manager.name())]));
+        report::action(format!("Package proxy removed for {}", manager.name()));
+    }
+    Ok(())
+}
+
+/// Whether any package manager still points at Tor
+pub fn is_enabled() -> bool {
+    Manager::ALL.into_iter().any(Manager::enabled)
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn has_marker(path: &str) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| content.lines().any(|line| line == MARKER))
+}
+
+/// Append `line` (after the marker) to the end of the `[section]` of an INI-style file
+fn insert_into_section(path: &str, section: &str, line: &str) -> Result<()> {
+    let content = fs::read_to_string(path).with_context(|| i18n::tr("error-read", &[("path", path)]))?;
+    let mut lines: Vec<&str> = content.lines().collect();
+
+    let header = format!("[{}]", section);
+    let start = lines
+        .iter()
+        .position(|l| l.trim() == header)
+        .with_context(|| i18n::tr("pkgproxy-no-section", &[("path", path), ("header", &header)]))?;
+    // At the end of the section, above its trailing blank lines
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    while end > start + 1 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    lines.splice(end..end, [MARKER, line]);
+
+    write_lines(path, &lines)
+}
+
+/// Drop every marker line and the line following it
+fn remove_marked(path: &str) -> Result<()> {
+    let content = fs::read_to_string(path).with_context(|| i18n::tr("error-read", &[("path", path)]))?;
+    let mut lines = Vec::new();
+    let mut skip_next = false;
+    for line in content.lines() {
+        if line == MARKER {
+            skip_next = true;
+        } else if skip_next {
+            skip_next = false;
+        } else {
+            lines.push(line);
+        }
+    }
+    write_lines(path, &lines)
+}
+
+fn write_lines(path: &str, lines: &[&str]) -> Result<()> {
+    let tmp = format!("{}.hulios-tmp", path);
+    fs::write(&tmp, lines.join("\n") + "\n")?;
+    fs::rename(&tmp, path).with_context(|| i18n::tr("error-write", &[("path", path)]))
+}
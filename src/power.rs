@@ -0,0 +1,175 @@
+use anyhow::Result;
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::config::{Config, PowerProfile};
+use crate::control::AsyncControlClient;
+use crate::engine;
+use crate::exec::Run;
+use crate::i18n;
+use crate::report;
+
+/// What the machine runs on, as far as `[power]` cares
+#[derive(Serialize, Default, Clone, Copy, PartialEq)]
+pub struct PowerState {
+    pub on_battery: bool,
+    pub metered: bool,
+}
+
+impl PowerState {
+    pub fn detect() -> PowerState {
+        PowerState { on_battery: on_battery(), metered: metered() }
+    }
+
+    pub fn describe(self) -> String {
+        let power = if self.on_battery { "on battery" } else { "on AC power" };
+        let connection = if self.metered { "metered connection" } else { "unmetered connection" };
+        format!("{}, {}", power, connection)
+    }
+}
+
+/// What `[power]` changes in a power state
+pub struct Adjustments {
+    pub bandwidth_rate: Option<String>,
+    pub bandwidth_burst: Option<String>,
+    pub rotate_identity: bool,
+    pub monitor_interval: Option<Duration>,
+}
+
+// =============================================================================
+// Adjustments
+// =============================================================================
+
+/// Whether any of `[power]` is set; otherwise nothing is detected or watched
+pub fn is_configured(config: &Config) -> bool {
+    config.power.battery.is_set() || config.power.metered.is_set()
+}
+
+/// The power state, or the default (AC, unmetered) when `[power]` is unset
+pub fn current(config: &Config) -> PowerState {
+    if is_configured(config) { PowerState::detect() } else { PowerState::default() }
+}
+
+/// The adjustments for `state`. When both apply, the metered bandwidth
+/// limits win (they are about data, not energy), rotation pauses if
+/// either pauses it and the longer monitor interval is used.
+pub fn adjustments(config: &Config, state: PowerState) -> Adjustments {
+    let profiles: Vec<&PowerProfile> = [(state.metered, &config.power.metered), (state.on_battery, &config.power.battery)]
+        .into_iter()
+        .filter_map(|(applies, profile)| applies.then_some(profile))
+        .collect();
+    let limits = profiles.iter().find(|profile| profile.bandwidth_rate.is_some());
+    Adjustments {
+        bandwidth_rate: limits.and_then(|profile| profile.bandwidth_rate.clone()),
+        bandwidth_burst: limits.and_then(|profile| profile.bandwidth_burst.clone()),
+        rotate_identity: profiles.iter().all(|profile| profile.rotate_identity),
+        monitor_interval: profiles.iter().filter_map(|profile| profile.monitor_interval).max().map(Duration::from_secs),
+    }
+}
+
+/// Tor's bandwidth rate and burst in `state`: the power state's limits,
+/// else tor.bandwidth_rate/bandwidth_burst
+pub fn bandwidth(config: &Config, state: PowerState) -> (Option<String>, Option<String>) {
+    let adjustments = adjustments(config, state);
+    match adjustments.bandwidth_rate {
+        Some(rate) => (Some(rate), adjustments.bandwidth_burst),
+        None => (config.tor.bandwidth_rate.clone(), config.tor.bandwidth_burst.clone()),
+    }
+}
+
+/// Give the running Tor the bandwidth limits for `state`. SETCONF without
+/// a value puts an option back to Tor's default.
+pub async fn apply_bandwidth(config: &Config, state: PowerState) -> Result<()> {
+    let command = match bandwidth(config, state) {
+        (None, _) => "RESETCONF BandwidthRate BandwidthBurst".to_string(),
+        (Some(rate), burst) => {
+            let mut command = format!("SETCONF BandwidthRate=\"{}\"", engine::parse_bandwidth(&rate)?);
+            match burst {
+                Some(burst) => command.push_str(&format!(" BandwidthBurst=\"{}\"", engine::parse_bandwidth(&burst)?)),
+                None => command.push_str(" BandwidthBurst"),
+            }
+            command
+        }
+    };
+    AsyncControlClient::connect().await?.command(&command).await?;
+    Ok(())
+}
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios power`: the power state and what it changes
+pub fn status() -> Result<()> {
+    let config = Config::load()?;
+    let state = PowerState::detect();
+    let adjustments = adjustments(&config, state);
+
+    let power = i18n::tr(if state.on_battery { "power-on-battery" } else { "power-on-ac" }, &[]);
+    let connection = i18n::tr(if state.metered { "power-metered" } else { "power-unmetered" }, &[]);
+    println!("[*] {}", i18n::tr("power-state", &[("state", &format!("{}, {}", power, connection).bold().to_string())]));
+    if !is_configured(&config) {
+        println!("[*] {}", i18n::tr("power-not-configured", &[]));
+    }
+    let mut changes = Vec::new();
+    if let Some(rate) = &adjustments.bandwidth_rate {
+        match &adjustments.bandwidth_burst {
+            Some(burst) => changes.push(i18n::tr("power-bandwidth-limited-burst", &[("rate", rate), ("burst", burst)])),
+            None => changes.push(i18n::tr("power-bandwidth-limited", &[("rate", rate)])),
+        }
+    }
+    if !adjustments.rotate_identity && config.daemon.rotate_identity.is_some() {
+        changes.push(i18n::tr("power-rotation-paused", &[]));
+    }
+    if let Some(interval) = adjustments.monitor_interval {
+        changes.push(i18n::tr("power-health-checks-every", &[("seconds", &interval.as_secs().to_string())]));
+    }
+    if changes.is_empty() {
+        println!("[*] {}", i18n::tr("power-adjustments-none", &[]));
+    } else {
+        println!("{}", format!("[!] {}", i18n::tr("power-adjustments", &[("changes", &changes.join(", "))])).yellow());
+    }
+    report::data("power", serde_json::json!({
+        "on_battery": state.on_battery,
+        "metered": state.metered,
+        "bandwidth_rate": adjustments.bandwidth_rate,
+        "bandwidth_burst": adjustments.bandwidth_burst,
+        "rotate_identity": adjustments.rotate_identity && config.daemon.rotate_identity.is_some(),
+        "monitor_interval": adjustments.monitor_interval.map(|interval| interval.as_secs()),
+    }));
+    Ok(())
+}
+
+// =============================================================================
+// Detection
+// =============================================================================
+
+fn on_battery() -> bool {
+    if let Some(value) = property("org.freedesktop.UPower", "/org/freedesktop/UPower", "org.freedesktop.UPower", "OnBattery") {
+        return value == "b true";
+    }
+    // Without UPower: a battery that is discharging
+    fs::read_dir("/sys/class/power_supply").into_iter().flatten().flatten().any(|supply| {
+        let read = |name: &str| fs::read_to_string(supply.path().join(name)).unwrap_or_default();
+        read("type").trim() == "Battery" && read("status").trim() == "Discharging"
+    })
+}
+
+fn metered() -> bool {
+    // NMMetered: 1 is yes, 3 a guess (a phone's hotspot); 2 and 4 are no,
+    // 0 unknown
+    property("org.freedesktop.NetworkManager", "/org/freedesktop/NetworkManager", "org.freedesktop.NetworkManager", "Metered")
+        .is_some_and(|value| value == "u 1" || value == "u 3")
+}
+
+/// A property on the system bus as busctl prints it, e.g. "b true"
+fn property(service: &str, path: &str, interface: &str, name: &str) -> Option<String> {
+    let output = Command::new("busctl")
+        .args(["--system", "get-property", service, path, interface, name])
+        .stderr(Stdio::null())
+        .run_output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
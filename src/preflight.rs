@@ -0,0 +1,232 @@
+use anyhow::Result;
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::config::{Backend, Config};
+use crate::exec::Run;
+use crate::features::{self, Features};
+use crate::{i18n, lsm, report};
+
+/// CAP_NET_ADMIN, needed for every firewall change
+const CAP_NET_ADMIN: u32 = 12;
+
+/// Kernel modules providing each backend's tables; alternatives are
+/// separated by `|` (either one is enough). The matches and targets inside
+/// the rules are probed directly (see features.rs).
+const IPTABLES_MODULES: &[&str] = &["iptable_nat", "iptable_filter"];
+const NFTABLES_MODULES: &[&str] = &["nf_tables", "nft_chain_nat"];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// HULIOS can't work here; start refuses
+    Fatal,
+    /// Works, with a caveat the user should know about
+    Warning,
+}
+
+/// Something about this environment that affects HULIOS, and what to do
+#[derive(Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub problem: String,
+    pub guidance: String,
+}
+
+/// Inspect the environment for things that make the rules half-apply:
+/// WSL, containers without NET_ADMIN, missing kernel modules or tools
+pub fn check(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut add = |severity, problem: String, guidance: String| {
+        findings.push(Finding { severity, problem, guidance });
+    };
+
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    let release = release.trim();
+    if release.to_ascii_lowercase().contains("microsoft") {
+        if release.contains("WSL2") || release.contains("microsoft-standard") {
+            add(Severity::Warning, i18n::tr("preflight-running-under-wsl2", &[]),
+                i18n::tr("preflight-wsl2-guidance", &[]));
+        } else {
+            add(Severity::Fatal, i18n::tr("preflight-running-under-wsl1-which", &[]),
+                i18n::tr("preflight-convert-distribution-wsl-set", &[]));
+        }
+    }
+
+    let container = container();
+    let net_admin = has_capability(CAP_NET_ADMIN);
+    match (&container, net_admin) {
+        (Some(kind), false) => add(Severity::Fatal,
+            i18n::tr("preflight-running-container-without-cap", &[("kind", kind)]),
+            i18n::tr("preflight-container-without-cap-guidance", &[])),
+        (Some(kind), true) => add(Severity::Warning,
+            i18n::tr("preflight-running-container", &[("kind", kind)]),
+            i18n::tr("preflight-only-container-network-namespace", &[])),
+        (None, false) => add(Severity::Fatal, i18n::tr("preflight-cap-net-admin-not", &[]),
+            i18n::tr("preflight-run-root-without-capability", &[])),
+        (None, true) => {}
+    }
+
+    let modules = match config.firewall.backend {
+        Backend::Iptables => IPTABLES_MODULES,
+        Backend::Nftables => NFTABLES_MODULES,
+    };
+    let mut unknown = false;
+    for alternatives in modules {
+        let states: Vec<Option<bool>> = alternatives.split('|').map(|m| module_available(release, m)).collect();
+        if states.contains(&Some(true)) {
+            continue;
+        }
+        if states.contains(&None) {
+            unknown = true;
+            continue;
+        }
+        add(Severity::Fatal,
+            i18n::tr("preflight-kernel-module-not-available", &[("module", &alternatives.replace('|', " or "))]),
+            if container.is_some() {
+                i18n::tr("preflight-load-host-modprobe-containers", &[])
+            } else {
+                i18n::tr("preflight-kernel-module-guidance", &[])
+            });
+    }
+    if unknown && container.is_none() {
+        add(Severity::Warning, i18n::tr("preflight-cannot-verify-kernel-modules", &[("release", release)]),
+            i18n::tr("preflight-reboot-into-installed-kernel", &[]));
+    }
+
+    if config.firewall.backend == Backend::Iptables && Command::new("iptables").arg("--version").run().is_err() {
+        add(Severity::Fatal, i18n::tr("preflight-iptables-not-installed", &[]),
+            i18n::tr("preflight-install-iptables-set-firewall", &[]));
+    }
+    findings.extend(lsm::findings(config));
+
+    findings
+}
+
+/// The environment checks, then, if nothing so far rules HULIOS out, the
+/// kernel feature probes and their degradation findings
+fn inspect(config: &Config) -> (Vec<Finding>, Option<Features>) {
+    let mut findings = check(config);
+    if findings.iter().any(|f| f.severity == Severity::Fatal) {
+        return (findings, None);
+    }
+    let features = features::probe(config);
+    findings.extend(features::findings(config, &features));
+    (findings, Some(features))
+}
+
+/// Refuse to start where the rules could only half-apply; print warnings.
+/// Returns the probed features the ruleset has to be adapted to.
+pub fn enforce(config: &Config) -> Result<Features> {
+    let (findings, features) = inspect(config);
+    for finding in findings.iter().filter(|f| f.severity == Severity::Warning) {
+        eprintln!("[!] {}. {}", finding.problem, finding.guidance);
+        report::warning(format!("{}. {}", finding.problem, finding.guidance));
+    }
+    if let Some(fatal) = findings.iter().find(|f| f.severity == Severity::Fatal) {
+        anyhow::bail!(i18n::tr("preflight-see-doctor", &[("problem", &fatal.problem), ("guidance", &fatal.guidance)]));
+    }
+    if let Some(features) = &features {
+        report::data("kernel_features", features);
+    }
+    features.ok_or_else(|| anyhow::anyhow!(i18n::tr("preflight-kernel-features-could-not", &[])))
+}
+
+/// `hulios doctor`: report every finding. Returns false if any is fatal.
+pub fn doctor(config: &Config) -> bool {
+    let (findings, features) = inspect(config);
+    println!("[*] {}", i18n::tr("preflight-checking-environment", &[]));
+    if let Some(features) = &features {
+        for (feature, works) in features::summary(features) {
+            if works {
+                println!("[+] {}", i18n::tr("preflight-works", &[("feature", &feature)]));
+            } else {
+                println!("{}", format!("[!] {}", i18n::tr("preflight-not-supported", &[("feature", &feature)])).yellow());
+            }
+        }
+        report::data("kernel_features", features);
+    }
+    for finding in &findings {
+        let line = match finding.severity {
+            Severity::Fatal => format!("[!] {}", finding.problem).red(),
+            Severity::Warning => format!("[!] {}", finding.problem).yellow(),
+        };
+        println!("{}", line);
+        println!("    {}", finding.guidance);
+    }
+    lsm::recent_denials();
+    let fatal = findings.iter().any(|f| f.severity == Severity::Fatal);
+    if findings.is_empty() {
+        println!("{}", format!("[+] {}", i18n::tr("preflight-no-problems-found", &[])).green());
+    } else if !fatal {
+        println!("{}", format!("[+] {}", i18n::tr("preflight-can-run-here-caveats", &[])).green());
+    }
+    report::data("findings", &findings);
+    !fatal
+}
+
+// =============================================================================
+// Probes
+// =============================================================================
+
+/// Container runtime we are in, if any
+pub fn container() -> Option<String> {
+    if Path::new("/.dockerenv").exists() {
+        return Some("Docker".to_string());
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("Podman".to_string());
+    }
+    // systemd-nspawn, LXC and others set container= for PID 1
+    let environ = fs::read("/proc/1/environ").unwrap_or_default();
+    let declared = environ
+        .split(|b| *b == 0)
+        .find_map(|var| var.strip_prefix(b"container="))
+        .map(|kind| String::from_utf8_lossy(kind).into_owned());
+    if declared.is_some() {
+        return declared;
+    }
+    let cgroup = fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+    ["docker", "lxc", "kubepods", "containerd"]
+        .into_iter()
+        .find(|runtime| cgroup.contains(runtime))
+        .map(str::to_string)
+}
+
+/// Whether `cap` is in this process's effective capability set
+fn has_capability(cap: u32) -> bool {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let hex = status.lines().find_map(|line| line.strip_prefix("CapEff:"))?.trim().to_string();
+            u64::from_str_radix(&hex, 16).ok()
+        })
+        .is_some_and(|caps| caps & (1 << cap) != 0)
+}
+
+/// Loaded, built in or loadable: Some(true); known missing: Some(false);
+/// None if the kernel's module directory can't be read
+fn module_available(release: &str, module: &str) -> Option<bool> {
+    let loaded = fs::read_to_string("/proc/modules").unwrap_or_default();
+    if loaded.lines().any(|line| line.split(' ').next() == Some(module)) {
+        return Some(true);
+    }
+
+    let dir = Path::new("/lib/modules").join(release);
+    let builtin = fs::read_to_string(dir.join("modules.builtin")).ok();
+    let dep = fs::read_to_string(dir.join("modules.dep")).ok();
+    if builtin.is_none() && dep.is_none() {
+        return None;
+    }
+    // Entries look like kernel/net/netfilter/xt_owner.ko(.zst|.xz|.gz)
+    let listed = |content: Option<String>| {
+        content.unwrap_or_default().lines().any(|line| {
+            let path = line.split(':').next().unwrap_or_default();
+            let file = path.rsplit('/').next().unwrap_or_default();
+            file.split(".ko").next() == Some(module)
+        })
+    };
+    Some(listed(builtin) || listed(dep))
+}
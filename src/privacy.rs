@@ -0,0 +1,142 @@
+use anyhow::{Result, Context};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::thread;
+use crate::i18n;
+
+/// Stands in for every address removed from a log line
+pub const SCRUBBED: &str = "[scrubbed]";
+
+// =============================================================================
+// Log Scrubbing
+// =============================================================================
+
+/// Remove IP addresses, host:port pairs, URLs and onion names from a log
+/// line, so a kept log doesn't record where traffic went
+pub fn scrub(line: &str) -> String {
+    scrub_where(line, is_sensitive)
+}
+
+/// Like `scrub`, but keep loopback, private and link-local addresses,
+/// which say how the machine is set up rather than where traffic went
+pub fn scrub_public(line: &str) -> String {
+    scrub_where(line, |token| is_sensitive(token) && !is_local(token))
+}
+
+fn scrub_where(line: &str, sensitive: impl Fn(&str) -> bool) -> String {
+    line.split(' ')
+        .map(|word| {
+            let token = word
+                .trim_matches(|c: char| "(){}<>,;'\"".contains(c))
+                .trim_end_matches(['.', ':']);
+            if token.is_empty() || !sensitive(token) {
+                return word.to_string();
+            }
+            let replacement = match token.split_once("://") {
+                Some((scheme, _)) => format!("{}://{}", scheme, SCRUBBED),
+                None => SCRUBBED.to_string(),
+            };
+            word.replacen(token, &replacement, 1)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_sensitive(token: &str) -> bool {
+    token.contains("://")
+        || token.parse::<SocketAddr>().is_ok()
+        || token.trim_matches(['[', ']']).parse::<IpAddr>().is_ok()
+        || token.rsplit_once(':').map_or(token, |(host, _)| host).ends_with(".onion")
+}
+
+fn is_local(token: &str) -> bool {
+    let ip = match token.parse::<SocketAddr>() {
+        Ok(address) => address.ip(),
+        Err(_) => match token.trim_matches(['[', ']']).parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return false,
+        },
+    };
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast(),
+        // Loopback, unspecified, fc00::/7 (unique local) and fe80::/10
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unspecified()
+            || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// Redirect this process's stdout and stderr through `scrub` into `log`.
+/// Used by the daemon, whose output goes nowhere but the log file.
+pub fn scrub_output(log: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .with_context(|| i18n::tr("error-open", &[("path", log)]))?;
+    capture_output(move |line| {
+        let _ = writeln!(file, "{}", scrub(line));
+    })
+}
+
+/// Point this process's stdout and stderr at a pipe and hand every line
+/// written to it to `sink`, on a thread of its own
+pub fn capture_output(mut sink: impl FnMut(&str) + Send + 'static) -> Result<()> {
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for the two descriptors pipe() returns
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error()).with_context(|| i18n::tr("privacy-pipe2-failed", &[]));
+    }
+    // SAFETY: fds[0] is a fresh descriptor owned by nothing else
+    let reader = unsafe { File::from_raw_fd(fds[0]) };
+    // SAFETY: fds[1] is a fresh descriptor owned by nothing else
+    let writer = unsafe { File::from_raw_fd(fds[1]) };
+    for target in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both descriptors are valid; dup2 replaces the target atomically
+        if unsafe { libc::dup2(writer.as_raw_fd(), target) } < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| i18n::tr("privacy-dup2-failed", &[]));
+        }
+    }
+    drop(writer);
+
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            sink(&line);
+        }
+    });
+    Ok(())
+}
+
+// =============================================================================
+// Secure Deletion
+// =============================================================================
+
+/// Overwrite a file with zeros, flush it to disk and unlink it; directories
+/// are handled recursively. Missing paths are fine. On SSDs and copy-on-write
+/// filesystems the old blocks may survive, so this is a best effort.
+pub fn shred(path: &Path) -> Result<()> {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            shred(&entry?.path())?;
+        }
+        return fs::remove_dir(path).with_context(|| i18n::tr("error-remove", &[("path", &path.display().to_string())]));
+    }
+    if meta.is_file() {
+        let mut file = OpenOptions::new().write(true).open(path)
+            .with_context(|| i18n::tr("error-open", &[("path", &path.display().to_string())]))?;
+        let zeros = [0u8; 64 * 1024];
+        let mut left = meta.len();
+        while left > 0 {
+            let n = left.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all().with_context(|| i18n::tr("privacy-failed-sync", &[("path", &path.display().to_string())]))?;
+    }
+    fs::remove_file(path).with_context(|| i18n::tr("error-remove", &[("path", &path.display().to_string())]))
+}
@@ -0,0 +1,292 @@
+use anyhow::{Result, Context};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use crate::config::{self, Config};
+use crate::engine::{self, ROUTE_LOCALNET, TOR_RESOLV, TOR_USER};
+use crate::{amnesic, confirmation, conntrack, daemon, dnsonly, features, firewall, helper, i18n, lock, lsm, netprofile, preflight, sshguard, state, stats, validate, workstation};
+
+/// The parts of a session that need root, and all the helper runs for an
+/// unprivileged `hulios`. That frontend runs the rest itself: the checks,
+/// the bootstrap wait, the leak check, the output and the notifications.
+/// Every field is a flag or a number; the helper reads the config, the
+/// session and the requester's SSH connection itself.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// The recorded session, if any
+    Session,
+    /// Make the current network's profile the active one
+    SelectProfile,
+    /// Refuse where the rules could only half-apply, after probing the kernel
+    Preflight,
+    /// Whether the configured backend's rules are installed
+    RulesPresent,
+    /// Record a new session before anything is changed
+    BeginSession { amnesic: bool, force_ssh_cutoff: bool, confirm_within: Option<u64>, allow_lan: bool },
+    /// Stop the distribution's tor.service
+    StopSystemTor,
+    /// Stop a leftover Tor, neutralize the system resolver, enable route_localnet
+    PrepareSystem,
+    /// A fresh data directory and control password; true if kept data was restored
+    PrepareTor,
+    /// Write the torrc and spawn Tor, over `tor.bridges` if `bridges`; its PID
+    SpawnTor { bridges: bool },
+    /// Tor bootstrapped after `millis`
+    Bootstrapped { millis: u64, cached: bool },
+    /// Explain SELinux or AppArmor denials since `since` (UNIX time); true if any
+    ExplainDenials { since: u64 },
+    StopTor,
+    /// Stop Tor and what only makes sense while traffic goes through it
+    ReleaseTor,
+    /// Give the running Tor the re-rendered torrc and limits; true if they changed
+    ReloadTor,
+    /// Apply the ruleset for the current config over the live one; true if
+    /// applied, which `only_if_changed` skips when its policy is the same
+    ApplyFirewall { only_if_changed: bool },
+    FlushFirewall,
+    /// Point /etc/resolv.conf at the Tor DNSPort, keeping the original
+    TakeDns,
+    /// Rewrite the current mode's /etc/resolv.conf
+    ReassertDns,
+    /// Restore /etc/resolv.conf and the system resolver
+    RestoreDns,
+    /// Put route_localnet back as it was before the session
+    RestoreSysctls,
+    /// Warn about connections the ESTABLISHED rule lets through
+    CheckConntrack,
+    StartDaemon,
+    StopDaemon,
+    /// Record the session's final traffic totals
+    EndStats,
+    ScheduleConfirm { seconds: u64 },
+    Confirm,
+    /// What an unclean shutdown left behind (engine::Leftovers)
+    Leftovers,
+    DetachGateway,
+    /// Delete the torrc and Tor's data directory
+    ForgetTor,
+    /// Clear the session state and the amnesic tmpfs; `secure_delete`
+    /// shreds the session's files first if `privacy.secure_delete` says so
+    EndSession { secure_delete: bool },
+}
+
+// =============================================================================
+// Frontend
+// =============================================================================
+
+/// The helper connection of this command while it holds the HULIOS lock
+static HELPER: Mutex<Option<helper::Connection>> = Mutex::new(None);
+
+/// The HULIOS lock, held in this process or by the helper for as long as
+/// this command stays connected
+pub struct Lock {
+    _local: Option<lock::Lock>,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        HELPER.lock().unwrap().take();
+    }
+}
+
+/// Take the HULIOS lock: here when root, else through the helper, which
+/// then runs this command's steps until the lock is dropped
+pub fn lock() -> Result<Lock> {
+    if helper::privileged() {
+        return Ok(Lock { _local: Some(lock::acquire()?) });
+    }
+    if !helper::available() {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    // The helper reads the config itself
+    if !config::overrides().is_empty() {
+        anyhow::bail!(i18n::tr("privileged-config-overrides-not-passed", &[]));
+    }
+    *HELPER.lock().unwrap() = Some(helper::connect()?);
+    Ok(Lock { _local: None })
+}
+
+/// Run `step` here when root, else have the helper run it
+pub fn run(step: Step) -> Result<()> {
+    query::<Value>(step).map(drop)
+}
+
+/// Run `step` like `run` and return its result
+pub fn query<T: DeserializeOwned>(step: Step) -> Result<T> {
+    let value = if helper::privileged() {
+        execute(step)?
+    } else {
+        let mut connection = HELPER.lock().unwrap();
+        let connection = connection.as_mut().with_context(|| i18n::tr("error-not-root", &[]))?;
+        connection.run(&step)?
+    };
+    serde_json::from_value(value).with_context(|| i18n::tr("privileged-unexpected-result", &[]))
+}
+
+// =============================================================================
+// Privileged Side
+// =============================================================================
+
+/// Run `step` as root. The caller holds the HULIOS lock.
+pub fn execute(step: Step) -> Result<Value> {
+    let done = Value::Null;
+    Ok(match step {
+        Step::Session => serde_json::to_value(state::load())?,
+        Step::SelectProfile => {
+            netprofile::select(&Config::load()?)?;
+            done
+        }
+        Step::Preflight => {
+            preflight::enforce(&validate::load()?)?;
+            done
+        }
+        Step::RulesPresent => serde_json::to_value(firewall::backend(&validate::load()?).rules_present())?,
+        Step::BeginSession { amnesic, force_ssh_cutoff, confirm_within, allow_lan } => {
+            begin_session(amnesic, force_ssh_cutoff, confirm_within, allow_lan)?;
+            done
+        }
+        Step::StopSystemTor => {
+            engine::stop_system_tor();
+            done
+        }
+        Step::PrepareSystem => {
+            engine::prepare_system()?;
+            done
+        }
+        Step::PrepareTor => serde_json::to_value(engine::prepare_tor(&validate::load()?)?)?,
+        Step::SpawnTor { bridges } => {
+            let config = validate::load()?;
+            let tproxy = features::redirect(&config, &features::probe(&config)) == Some(config::Redirect::Tproxy);
+            serde_json::to_value(engine::spawn_attempt(&config, tproxy, bridges)?)?
+        }
+        Step::Bootstrapped { millis, cached } => {
+            stats::record_bootstrap(Duration::from_millis(millis), cached);
+            done
+        }
+        Step::ExplainDenials { since } => {
+            serde_json::to_value(lsm::explain_denials(SystemTime::UNIX_EPOCH + Duration::from_secs(since)))?
+        }
+        Step::StopTor => {
+            engine::stop_tor_service()?;
+            if let Some(mut session) = state::load() {
+                session.tor_pid = None;
+                session.tor_cgroup = None;
+                state::save(&session)?;
+            }
+            done
+        }
+        Step::ReleaseTor => {
+            engine::release_tor()?;
+            done
+        }
+        Step::ReloadTor => serde_json::to_value(engine::reload_tor(&validate::load()?)?)?,
+        Step::ApplyFirewall { only_if_changed } => {
+            serde_json::to_value(engine::apply_firewall(&validate::load()?, only_if_changed)?)?
+        }
+        Step::FlushFirewall => {
+            firewall::flush_all()?;
+            done
+        }
+        Step::TakeDns => {
+            engine::take_dns_ownership(TOR_RESOLV)?;
+            done
+        }
+        Step::ReassertDns => {
+            engine::reassert_dns()?;
+            done
+        }
+        Step::RestoreDns => {
+            dnsonly::halt();
+            engine::restore_dns()?;
+            engine::restore_system_resolver()?;
+            done
+        }
+        Step::RestoreSysctls => {
+            if let Some(previous) = state::load().and_then(|s| s.route_localnet) {
+                engine::write_sysctl(ROUTE_LOCALNET, &previous);
+            }
+            done
+        }
+        Step::CheckConntrack => {
+            conntrack::check_existing(&Config::load().unwrap_or_default(), TOR_USER);
+            done
+        }
+        Step::StartDaemon => {
+            daemon::spawn()?;
+            done
+        }
+        Step::StopDaemon => {
+            daemon::stop();
+            done
+        }
+        Step::EndStats => {
+            stats::end_session();
+            done
+        }
+        Step::ScheduleConfirm { seconds } => {
+            confirmation::schedule(seconds)?;
+            done
+        }
+        Step::Confirm => {
+            if let Some(mut session) = state::load() {
+                session.confirm_by = None;
+                state::save(&session)?;
+            }
+            done
+        }
+        Step::Leftovers => serde_json::to_value(engine::leftovers())?,
+        Step::DetachGateway => {
+            workstation::release()?;
+            done
+        }
+        Step::ForgetTor => {
+            let _ = fs::remove_file(engine::TORRC_PATH);
+            let _ = fs::remove_dir_all(engine::TOR_DATA_DIR);
+            done
+        }
+        Step::EndSession { secure_delete } => {
+            engine::end_session(secure_delete);
+            done
+        }
+    })
+}
+
+/// Record the session before touching anything, for `hulios recover`. The
+/// SSH session exempt from the firewall is the requester's, found from
+/// its process here rather than taken from it.
+fn begin_session(amnesic: bool, force_ssh_cutoff: bool, confirm_within: Option<u64>, allow_lan: bool) -> Result<()> {
+    let config = validate::load()?;
+    let ssh_session = sshguard::guard(&config, force_ssh_cutoff)?;
+
+    // Before anything is written, so nothing of the session reaches the disk
+    if amnesic && !amnesic::is_active() {
+        amnesic::mount()?;
+    }
+
+    let mut session = state::load().unwrap_or_else(|| state::State {
+        route_localnet: engine::read_sysctl(ROUTE_LOCALNET),
+        ..Default::default()
+    });
+    session.started_at = chrono::Local::now().timestamp();
+    session.firewall_backend = firewall::backend(&config).name().to_string();
+    // Exits reported in an earlier session may be fine by now
+    session.bad_exits.clear();
+    // Read back by the firewall policy, which exempts it
+    session.ssh_session = ssh_session;
+    session.allow_lan = allow_lan;
+    // Counted from here, and moved once the session is up: the firewall
+    // most likely cuts the SSH session off while it comes up
+    session.confirm_by = confirm_within.map(|seconds| session.started_at + seconds as i64);
+    state::save(&session)?;
+    if confirm_within.is_some() {
+        if let Err(e) = confirmation::arm() {
+            state::clear();
+            return Err(e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A process found by scanning /proc
+pub struct ProcessInfo {
+    pub pid: i32,
+    pub uid: u32,
+    pub comm: String,
+    pub cmdline: Vec<String>,
+}
+
+/// Read a single process from /proc, None if it has exited
+pub fn inspect(pid: i32) -> Option<ProcessInfo> {
+    let dir = format!("/proc/{}", pid);
+    let uid = fs::metadata(&dir).ok()?.uid();
+    let comm = fs::read_to_string(format!("{}/comm", dir)).ok()?.trim().to_string();
+    let cmdline = fs::read(format!("{}/cmdline", dir))
+        .ok()?
+        .split(|b| *b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    Some(ProcessInfo { pid, uid, comm, cmdline })
+}
+
+/// All processes whose command name matches exactly (like `pgrep -x`)
+pub fn find_by_name(name: &str) -> Vec<ProcessInfo> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .filter_map(inspect)
+        .filter(|info| info.comm == name)
+        .collect()
+}
+
+/// Signal 0 probes for existence; EPERM still means the process exists
+/// (it just belongs to another user, e.g. tor after dropping privileges)
+pub fn is_alive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    // SAFETY: kill with signal 0 performs no action besides the permission check
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// SIGTERM a process, escalating to SIGKILL if it is still alive after `grace`
+pub fn terminate(pid: i32, grace: Duration) {
+    if pid <= 0 {
+        return;
+    }
+    // SAFETY: plain signal delivery to a specific, positive PID
+    unsafe { libc::kill(pid, libc::SIGTERM) };
+
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if !is_alive(pid) || is_zombie(pid) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    // SAFETY: as above
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+}
+
+/// A pidfd for `pid`: a handle that can't be confused with a later process
+/// reusing the PID, and becomes readable once the process exits
+pub fn pidfd(pid: i32) -> io::Result<OwnedFd> {
+    // SAFETY: plain syscall, the returned descriptor is checked below
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, libc::PIDFD_NONBLOCK) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is a freshly created descriptor we exclusively own
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+/// An exited child nobody has waited on yet still answers signal 0
+fn is_zombie(pid: i32) -> bool {
+    fs::read_to_string(format!("/proc/{}/stat", pid))
+        .ok()
+        .and_then(|stat| stat.rsplit(')').next().map(|rest| rest.trim_start().starts_with('Z')))
+        .unwrap_or(false)
+}
+
+/// Map socket inodes to the PID holding them, from the `socket:[inode]`
+/// links in /proc/*/fd. Only processes we may inspect are included.
+pub fn socket_owners() -> HashMap<u64, i32> {
+    let mut owners = HashMap::new();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return owners;
+    };
+    for pid in entries.flatten().filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok()) {
+        let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:["))
+                .and_then(|t| t.strip_suffix(']'))
+                .and_then(|t| t.parse().ok());
+            if let Some(inode) = inode {
+                owners.insert(inode, pid);
+            }
+        }
+    }
+    owners
+}
+
+/// An "ADDR:PORT" field of /proc/net/{tcp,udp}{,6}, both in hex. IPv4
+/// addresses mapped into IPv6 come back as IPv4.
+pub fn parse_address(field: &str) -> Option<SocketAddr> {
+    let (addr, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    // The kernel prints each 32-bit word in host byte order
+    let words: Vec<u32> = (0..addr.len() / 8)
+        .map(|i| u32::from_str_radix(&addr[i * 8..i * 8 + 8], 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let addr = match words.as_slice() {
+        [a] => IpAddr::V4(Ipv4Addr::from(a.to_ne_bytes())),
+        [a, b, c, d] => {
+            let mut octets = [0u8; 16];
+            for (chunk, word) in octets.chunks_mut(4).zip([a, b, c, d]) {
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            let v6 = Ipv6Addr::from(octets);
+            v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4)
+        }
+        _ => return None,
+    };
+    Some(SocketAddr::new(addr, port))
+}
+
+/// Local address and inode of every TCP socket, from /proc/net/tcp{,6}
+pub fn tcp_sockets() -> Vec<(SocketAddr, u64)> {
+    sockets(["/proc/net/tcp", "/proc/net/tcp6"])
+}
+
+/// Local address and inode of every UDP socket, from /proc/net/udp{,6}
+pub fn udp_sockets() -> Vec<(SocketAddr, u64)> {
+    sockets(["/proc/net/udp", "/proc/net/udp6"])
+}
+
+fn sockets(tables: [&str; 2]) -> Vec<(SocketAddr, u64)> {
+    tables
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| {
+            table.lines().skip(1).filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                Some((parse_address(fields.get(1)?)?, fields.get(9)?.parse().ok()?))
+            }).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Local address, remote address and inode of every established TCP
+/// connection, from /proc/net/tcp{,6}
+pub fn established_tcp() -> Vec<(SocketAddr, SocketAddr, u64)> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|table| {
+            table.lines().skip(1).filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                // TCP_ESTABLISHED
+                if *fields.get(3)? != "01" {
+                    return None;
+                }
+                Some((parse_address(fields.get(1)?)?, parse_address(fields.get(2)?)?, fields.get(9)?.parse().ok()?))
+            }).collect::<Vec<_>>()
+        })
+        .collect()
+}
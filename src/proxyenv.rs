@@ -0,0 +1,42 @@
+use anyhow::Result;
+use colored::*;
+use crate::config::Config;
+use crate::{engine, i18n, report};
+
+/// Tor's SOCKSPort, with hostnames resolved by Tor (socks5h)
+const SOCKS_PROXY: &str = "socks5h://127.0.0.1:9050";
+
+/// Print shell exports pointing proxy-aware tools at Tor, for
+/// `eval "$(hulios env)"`. Tools that only speak HTTP CONNECT get the
+/// HTTPTunnelPort when `tor.http_tunnel_port` is set.
+pub fn print_env() -> Result<()> {
+    let config = Config::load()?;
+
+    let mut vars = vec![
+        ("ALL_PROXY", SOCKS_PROXY.to_string()),
+        ("all_proxy", SOCKS_PROXY.to_string()),
+    ];
+    match config.tor.http_tunnel_port {
+        Some(port) => {
+            let http = format!("http://127.0.0.1:{}", port);
+            println!("# HTTPTunnelPort: 127.0.0.1:{}", port);
+            println!("# Java: -Dhttp.proxyHost=127.0.0.1 -Dhttp.proxyPort={0} -Dhttps.proxyHost=127.0.0.1 -Dhttps.proxyPort={0}", port);
+            for name in ["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY"] {
+                vars.push((name, http.clone()));
+            }
+        }
+        None => println!("# HTTPTunnelPort disabled; set tor.http_tunnel_port to enable it"),
+    }
+    vars.push(("NO_PROXY", "localhost,127.0.0.1".to_string()));
+    vars.push(("no_proxy", "localhost,127.0.0.1".to_string()));
+
+    for (name, value) in &vars {
+        println!("export {}={}", name, value);
+    }
+    if !engine::is_tor_running() {
+        eprintln!("{}", format!("[!] {}", i18n::tr("proxyenv-not-running-these-proxies", &[])).yellow());
+        report::warning("HULIOS is not running");
+    }
+    report::data("env", vars.into_iter().collect::<std::collections::BTreeMap<_, _>>());
+    Ok(())
+}
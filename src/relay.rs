@@ -0,0 +1,331 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use users::get_current_uid;
+use crate::config::Config;
+use crate::control::ControlClient;
+use crate::exec::Run;
+use crate::{cgroup, confirm, engine, i18n, procfs, report, sdnotify, validate};
+
+/// The relay's keys live here, so it keeps its identity (and the trust
+/// the network has built up in it) across restarts
+const DATA_DIR: &str = "/var/lib/hulios/relay";
+
+/// Written on `hulios relay start` and removed on stop; while it exists
+/// the relay is meant to be running
+const TORRC_PATH: &str = "/var/lib/hulios/relay-torrc";
+
+const LOG: &str = "/var/lib/hulios/relay/notices.log";
+
+/// The relay's control port, next to the session Tor's 9052
+const CONTROL_ADDR: &str = "127.0.0.1:9053";
+
+/// Transient unit the relay runs in on systemd machines; systemd restarts it
+const UNIT: &str = "hulios-relay";
+
+/// Without systemd, the relay's own cgroup, and the daemon restarts it
+const GROUP: &str = "/hulios-relay";
+
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What `hulios relay status` reports
+#[derive(Serialize)]
+struct RelayStatus {
+    pid: u32,
+    bridge: bool,
+    fingerprint: Option<String>,
+    bootstrap: Option<u8>,
+    /// Whether Tor's self-test reached the ORPort from outside
+    reachable: Option<bool>,
+    uptime: Option<u64>,
+    read_bytes: Option<u64>,
+    written_bytes: Option<u64>,
+}
+
+/// The torrc of the relay. It has no SOCKSPort and never exits, and it
+/// shares nothing with the session's Tor but the binary.
+fn render_torrc(config: &Config) -> Result<String> {
+    let relay = &config.relay;
+    let mut torrc = format!(r#"RunAsDaemon 0
+User {}
+DataDirectory {}
+Log notice file {}
+SOCKSPort 0
+ControlPort {}
+CookieAuthentication 1
+ExitRelay 0
+ExitPolicy reject *:*
+"#, engine::TOR_USER, DATA_DIR, LOG, CONTROL_ADDR);
+    // Sessions drop all IPv6, which would fail the reachability self-test
+    torrc.push_str(&format!("ORPort {} IPv4Only\n", relay.or_port));
+    torrc.push_str(&format!("SafeLogging {}\n", if config.privacy.log_scrub { 1 } else { 0 }));
+    if let Some(nickname) = &relay.nickname {
+        torrc.push_str(&format!("Nickname {}\n", nickname));
+    }
+    if let Some(contact) = &relay.contact_info {
+        torrc.push_str(&format!("ContactInfo {}\n", contact));
+    }
+    if let Some(rate) = &relay.bandwidth_rate {
+        torrc.push_str(&format!("RelayBandwidthRate {}\n", engine::parse_bandwidth(rate)?));
+    }
+    if let Some(burst) = &relay.bandwidth_burst {
+        torrc.push_str(&format!("RelayBandwidthBurst {}\n", engine::parse_bandwidth(burst)?));
+    }
+    if relay.bridge {
+        torrc.push_str("BridgeRelay 1\n");
+        if let (Some(plugin), Some(port)) = (&relay.transport_plugin, relay.transport_port) {
+            torrc.push_str(&format!("ServerTransportPlugin {}\n", plugin));
+            let transport = plugin.split_whitespace().next().unwrap_or_default();
+            torrc.push_str(&format!("ServerTransportListenAddr {} 0.0.0.0:{}\n", transport, port));
+        }
+    }
+    Ok(torrc)
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios relay start`: run the relay after spelling out what it means
+/// for this machine, and wait for it to bootstrap
+pub fn start(assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let config = validate::load()?;
+    if let Some(pid) = pid() {
+        println!("[*] {}", i18n::tr("relay-already-running-pid", &[("pid", &pid.to_string())]));
+        return Ok(());
+    }
+
+    println!("{}", format!("[!] {}", i18n::tr("relay-mode-separate-anonymity-gives", &[])).yellow());
+    for note in ["relay-note-second-tor", "relay-note-public-address", "relay-note-not-anonymous"] {
+        println!("    - {}", i18n::tr(note, &[]));
+    }
+    confirm::confirm(&i18n::tr("confirm-starting-relay", &[]), &confirm::changes(confirm::RELAY_CHANGES), assume_yes)?;
+
+    prepare_data_dir()?;
+    fs::write(TORRC_PATH, render_torrc(&config)?)?;
+    // Fresh log, so bootstrap progress isn't read from the last run
+    let _ = fs::remove_file(LOG);
+    let pid = launch(&config)?;
+    println!("[*] {}", i18n::tr("relay-starting-pid", &[("pid", &pid.to_string())]));
+    if let Err(e) = wait_for_bootstrap() {
+        halt();
+        return Err(e);
+    }
+
+    let running = if config.relay.bridge { "relay-bridge-running-orport" } else { "relay-running-orport" };
+    println!("{}", format!("[+] {}", i18n::tr(running, &[("port", &config.relay.or_port.to_string())])).green());
+    println!("[*] {}", i18n::tr("relay-reachability-check", &[]));
+    let kind = if config.relay.bridge { "Bridge" } else { "Relay" };
+    report::action(format!("{} started on ORPort {} (PID {})", kind, config.relay.or_port, pid));
+    Ok(())
+}
+
+/// `hulios relay stop`: stop the relay; its keys are kept for next time
+pub fn stop() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if pid().is_none() && !Path::new(TORRC_PATH).exists() {
+        println!("[*] {}", i18n::tr("relay-not-running", &[]));
+        return Ok(());
+    }
+    halt();
+    println!("{}", format!("[+] {}", i18n::tr("relay-stopped", &[])).green());
+    report::action("Relay stopped");
+    Ok(())
+}
+
+/// `hulios relay status`: bootstrap, reachability and traffic, from the
+/// relay's control port
+pub fn status() -> Result<()> {
+    let Some(pid) = pid() else {
+        if Path::new(TORRC_PATH).exists() {
+            println!("{}", format!("[!] {}", i18n::tr("relay-should-running-but-not", &[])).red());
+        } else {
+            println!("[*] {}", i18n::tr("relay-not-running", &[]));
+        }
+        report::data("relay", None::<RelayStatus>);
+        return Ok(());
+    };
+    let config = Config::load()?;
+    let mut client = ControlClient::connect_with_cookie(CONTROL_ADDR, &format!("{}/control_auth_cookie", DATA_DIR))
+        .with_context(|| i18n::tr("relay-cannot-reach-relay-control", &[]))?;
+    let mut number = |key: &str| client.getinfo(key).ok().and_then(|value| value.trim().parse::<u64>().ok());
+    let (uptime, read_bytes, written_bytes) = (number("uptime"), number("traffic/read"), number("traffic/written"));
+    let bootstrap = client
+        .getinfo("status/bootstrap-phase")
+        .ok()
+        .and_then(|phase| phase.split(' ').find_map(|word| word.strip_prefix("PROGRESS="))?.parse().ok());
+    // "OR=1 DIR=0"
+    let reachable = client
+        .getinfo("status/reachability-succeeded")
+        .ok()
+        .and_then(|value| value.split(' ').find_map(|word| word.strip_prefix("OR=")).map(|or| or == "1"));
+    let status = RelayStatus {
+        pid,
+        bridge: config.relay.bridge,
+        fingerprint: client.getinfo("fingerprint").ok(),
+        bootstrap,
+        reachable,
+        uptime,
+        read_bytes,
+        written_bytes,
+    };
+
+    let running = if status.bridge { "relay-bridge-running-pid" } else { "relay-running-pid" };
+    println!("{}", format!("[+] {}", i18n::tr(running, &[("pid", &status.pid.to_string())])).green());
+    if let Some(fingerprint) = &status.fingerprint {
+        println!("    {}", i18n::tr("relay-fingerprint", &[("fingerprint", fingerprint)]));
+    }
+    match (status.bootstrap, status.reachable) {
+        (Some(progress), _) if progress < 100 => println!("    {}", i18n::tr("relay-bootstrapping", &[("progress", &progress.to_string())])),
+        (_, Some(true)) => println!("    {}", i18n::tr("relay-orport-reachable-internet", &[("or_port", &config.relay.or_port.to_string())])),
+        _ => println!("{}", format!("    {}", i18n::tr("relay-orport-not-confirmed-reachable", &[("or_port", &config.relay.or_port.to_string())])).yellow()),
+    }
+    if let Some(uptime) = status.uptime {
+        println!("    {}", i18n::tr("relay-uptime", &[("hours", &(uptime / 3600).to_string()), ("minutes", &(uptime % 3600 / 60).to_string())]));
+    }
+    if let (Some(read), Some(written)) = (status.read_bytes, status.written_bytes) {
+        println!("    {}", i18n::tr("relay-relayed", &[("read", &(read >> 20).to_string()), ("written", &(written >> 20).to_string())]));
+    }
+    if let Some(line) = bridge_line().filter(|_| status.bridge) {
+        println!("    {}", i18n::tr("relay-bridge-line-share", &[("line", &line)]));
+    }
+    report::data("relay", Some(&status));
+    Ok(())
+}
+
+// =============================================================================
+// Supervision
+// =============================================================================
+
+/// PID of the running relay: the tor started from its torrc
+fn pid() -> Option<u32> {
+    procfs::find_by_name("tor")
+        .into_iter()
+        .find(|info| info.cmdline.iter().any(|arg| arg == TORRC_PATH))
+        .map(|info| info.pid as u32)
+}
+
+/// Start the relay's Tor: in a transient unit systemd restarts when it
+/// fails, or detached in a cgroup of its own
+fn launch(config: &Config) -> Result<u32> {
+    let binary = engine::resolve_tor_binary(&config.tor)?;
+    if sdnotify::booted() {
+        let status = Command::new("systemd-run")
+            .args(["--unit", UNIT, "--collect", "--quiet"])
+            .arg("--property=Restart=on-failure")
+            .arg("--property=RestartSec=30")
+            .arg("--property=NoNewPrivileges=yes")
+            .arg("--property=PrivateTmp=yes")
+            .arg("--property=ProtectHome=yes")
+            .args([binary.as_str(), "-f", TORRC_PATH])
+            .run()
+            .with_context(|| i18n::tr("relay-failed-run-systemd-run", &[]))?;
+        if !status.success() {
+            anyhow::bail!(i18n::tr("relay-systemd-run-failed-start", &[("unit", UNIT)]));
+        }
+        let output = Command::new("systemctl")
+            .args(["show", "--property", "MainPID", "--value", UNIT])
+            .run_output()
+            .with_context(|| i18n::tr("relay-failed-query-relay-unit", &[]))?;
+        return String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|pid| *pid != 0)
+            .with_context(|| i18n::tr("relay-exited-immediately-check", &[("log", LOG)]));
+    }
+
+    let child = Command::new(binary)
+        .args(["-f", TORRC_PATH])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Own process group, so Ctrl-C in the starting terminal doesn't reach it
+        .process_group(0)
+        .spawn()
+        .with_context(|| i18n::tr("relay-failed-start-relay-tor", &[]))?;
+    cgroup::create(GROUP)?;
+    let procs = cgroup::procs_file(GROUP).with_context(|| i18n::tr("error-no-cgroup2", &[]))?;
+    fs::write(procs, child.id().to_string()).with_context(|| i18n::tr("relay-failed-move-relay-into", &[]))?;
+    Ok(child.id())
+}
+
+/// Start the relay again if it died while it was meant to run. systemd
+/// restarts its unit by itself, so this is only for machines without it,
+/// where the HULIOS daemon calls it. Returns the new PID.
+pub fn revive() -> Option<Result<u32>> {
+    if sdnotify::booted() || !Path::new(TORRC_PATH).exists() || pid().is_some() {
+        return None;
+    }
+    Some(Config::load().and_then(|config| launch(&config)))
+}
+
+/// Stop the relay however it was started, and forget that it should run
+pub fn halt() {
+    let _ = Command::new("systemctl")
+        .args(["stop", UNIT])
+        .stderr(Stdio::null())
+        .run();
+    if cgroup::exists(GROUP) {
+        cgroup::stop(GROUP, Duration::from_secs(5));
+        cgroup::remove(GROUP);
+    }
+    if let Some(pid) = pid() {
+        procfs::terminate(pid as i32, Duration::from_secs(5));
+    }
+    let _ = fs::remove_file(TORRC_PATH);
+}
+
+/// Tor refuses a DataDirectory anyone but its user can read
+fn prepare_data_dir() -> Result<()> {
+    fs::create_dir_all(DATA_DIR).with_context(|| i18n::tr("error-create", &[("path", DATA_DIR)]))?;
+    let tor_user = users::get_user_by_name(engine::TOR_USER)
+        .with_context(|| i18n::tr("error-no-tor-user", &[("user", engine::TOR_USER)]))?;
+    std::os::unix::fs::chown(DATA_DIR, Some(tor_user.uid()), Some(tor_user.primary_group_id()))
+        .with_context(|| i18n::tr("relay-failed-chown-relay-data", &[]))?;
+    fs::set_permissions(DATA_DIR, fs::Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
+/// Wait for the relay's Tor to reach 100% bootstrap
+fn wait_for_bootstrap() -> Result<()> {
+    let started = Instant::now();
+    loop {
+        let progress = engine::bootstrap_progress_in(LOG);
+        if progress.as_ref().is_some_and(|(percent, _)| *percent >= 100) {
+            println!("[+] {}", i18n::tr("relay-bootstrapped", &[("seconds", &started.elapsed().as_secs().to_string())]));
+            return Ok(());
+        }
+        let (percent, phase) = progress.unwrap_or((0, "Starting".to_string()));
+        if pid().is_none() {
+            anyhow::bail!(i18n::tr("relay-tor-died-during-bootstrap", &[("percent", &percent.to_string()), ("phase", &phase), ("log", LOG)]));
+        }
+        if started.elapsed() > BOOTSTRAP_TIMEOUT {
+            anyhow::bail!(i18n::tr("relay-bootstrap-timed-out-after", &[("seconds", &BOOTSTRAP_TIMEOUT.as_secs().to_string()), ("percent", &percent.to_string()), ("phase", &phase), ("log", LOG)]));
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+}
+
+/// The line Tor users add to use this bridge over its pluggable transport,
+/// as the transport wrote it (with the address left for the user to fill in)
+fn bridge_line() -> Option<String> {
+    let content = fs::read_to_string(format!("{}/pt_state/obfs4_bridgeline.txt", DATA_DIR)).ok()?;
+    content
+        .lines()
+        .rev()
+        .find(|line| line.starts_with("Bridge "))
+        .map(str::to_string)
+}
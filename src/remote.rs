@@ -0,0 +1,275 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use users::os::unix::UserExt;
+use crate::exec::Run;
+use crate::i18n;
+use crate::report;
+
+/// Tor's SOCKSPort, used by `hulios connect`
+const SOCKS_ADDR: &str = "127.0.0.1:9050";
+
+/// Tor's SOCKSPort as a git proxy URL (hostnames resolved by Tor)
+const SOCKS_PROXY: &str = "socks5h://127.0.0.1:9050";
+
+/// ssh_config file owned by HULIOS, pulled into ~/.ssh/config by an Include
+const SSH_INCLUDE: &str = "hulios_config";
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// Route ssh and git traffic for `host` through Tor's SOCKS port
+pub fn add(host: &str) -> Result<()> {
+    let host = normalize(host)?;
+    let user = User::invoking()?;
+    let mut hosts = user.hosts();
+    if hosts.contains(&host) {
+        println!("[*] {}", i18n::tr("remote-already-goes-through-tor", &[("host", &host)]));
+        return Ok(());
+    }
+    hosts.push(host.clone());
+    user.write_ssh(&hosts)?;
+    user.git(&["--replace-all", &git_key(&host), SOCKS_PROXY])?;
+
+    println!("{}", format!("[+] {}", i18n::tr("remote-ssh-git-now-go", &[("host", &host)])).green());
+    println!("[*] {}", i18n::tr("remote-ssh-proxycommand-connect", &[("ssh_include", &user.ssh_include().display().to_string())]));
+    println!("[*] {}", i18n::tr("remote-git", &[("proxy", &git_key(&host)), ("gitconfig", &user.gitconfig().display().to_string())]));
+    report::action(format!("Remote added: {}", host));
+    Ok(())
+}
+
+/// Remove the ssh and git entries for `host`
+pub fn remove(host: &str) -> Result<()> {
+    let host = normalize(host)?;
+    let user = User::invoking()?;
+    let mut hosts = user.hosts();
+    if !hosts.contains(&host) {
+        anyhow::bail!(i18n::tr("remote-not-configured-see-remote", &[("host", &host)]));
+    }
+    hosts.retain(|h| *h != host);
+    user.write_ssh(&hosts)?;
+    // The section only ever holds our proxy setting
+    let _ = user.git(&["--remove-section", &format!("http.https://{}/", host)]);
+
+    println!("{}", format!("[+] {}", i18n::tr("remote-removed", &[("host", &host)])).green());
+    report::action(format!("Remote removed: {}", host));
+    Ok(())
+}
+
+pub fn list() -> Result<()> {
+    let hosts = User::invoking()?.hosts();
+    if hosts.is_empty() {
+        println!("[*] {}", i18n::tr("remote-no-remotes-configured-add", &[]));
+    }
+    for host in &hosts {
+        println!("{}", host);
+    }
+    report::data("remotes", &hosts);
+    Ok(())
+}
+
+/// ssh ProxyCommand: connect to `host:port` through Tor's SOCKS port and
+/// relay stdin/stdout, so no netcat with SOCKS support is needed
+pub fn connect(host: &str, port: u16) -> Result<()> {
+    let stream = socks_connect(host, port)?;
+
+    let mut upstream = stream.try_clone()?;
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin().lock(), &mut upstream);
+        let _ = upstream.shutdown(Shutdown::Write);
+    });
+    let mut downstream = stream;
+    io::copy(&mut downstream, &mut io::stdout().lock())?;
+    Ok(())
+}
+
+// =============================================================================
+// SOCKS5
+// =============================================================================
+
+/// Open a stream to `host:port` with a SOCKS5 CONNECT; Tor resolves the name
+fn socks_connect(host: &str, port: u16) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect(SOCKS_ADDR)
+        .with_context(|| i18n::tr("remote-tor-socks-port-not", &[("socks_addr", SOCKS_ADDR)]))?;
+
+    // Greeting: version 5, one method, no authentication
+    stream.write_all(&[5, 1, 0])?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice != [5, 0] {
+        anyhow::bail!(i18n::tr("remote-socks-server-refused-no", &[]));
+    }
+
+    let name = host.as_bytes();
+    if name.len() > 255 {
+        anyhow::bail!(i18n::tr("remote-hostname-too-long-socks", &[("host", host)]));
+    }
+    let mut request = vec![5, 1, 0, 3, name.len() as u8];
+    request.extend_from_slice(name);
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        anyhow::bail!(i18n::tr("remote-tor-could-not-connect", &[("host", host), ("port", &port.to_string()), ("error", &socks_error(reply[1]))]));
+    }
+    // Skip the bound address: IPv4, domain or IPv6, then the port
+    let skip = match reply[3] {
+        1 => 4,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        4 => 16,
+        other => anyhow::bail!(i18n::tr("remote-malformed-socks-reply-address", &[("kind", &other.to_string())])),
+    };
+    let mut bound = vec![0u8; skip + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(stream)
+}
+
+fn socks_error(code: u8) -> String {
+    let id = match code {
+        1 => "remote-general-failure",
+        2 => "remote-not-allowed-ruleset",
+        3 => "remote-network-unreachable",
+        4 => "remote-host-unreachable",
+        5 => "remote-connection-refused",
+        6 => "remote-ttl-expired-onion-service",
+        7 => "remote-command-not-supported",
+        8 => "remote-address-type-not-supported",
+        _ => "remote-unknown-error",
+    };
+    i18n::tr(id, &[])
+}
+
+// =============================================================================
+// Per-User Files
+// =============================================================================
+
+/// The user whose ssh/git config is edited: the sudo caller if any
+struct User {
+    home: PathBuf,
+}
+
+impl User {
+    /// Under sudo, this process becomes the caller for good: nothing here
+    /// needs root, and with their rights a symlink planted in their home
+    /// leads nowhere they couldn't write themselves
+    fn invoking() -> Result<User> {
+        let user = match std::env::var("SUDO_USER") {
+            Ok(name) if users::get_current_uid() == 0 => users::get_user_by_name(&name)
+                .with_context(|| i18n::tr("error-no-user", &[("user", &name)]))?,
+            _ => users::get_user_by_uid(users::get_current_uid()).with_context(|| i18n::tr("remote-cannot-look-up-current", &[]))?,
+        };
+        if users::get_current_uid() == 0 && user.uid() != 0 {
+            let name = CString::new(std::os::unix::ffi::OsStrExt::as_bytes(user.name()))?;
+            // SAFETY: plain credential syscalls; groups first, while still root
+            let dropped = unsafe {
+                libc::initgroups(name.as_ptr(), user.primary_group_id()) == 0
+                    && libc::setgid(user.primary_group_id()) == 0
+                    && libc::setuid(user.uid()) == 0
+            };
+            if !dropped {
+                return Err(io::Error::last_os_error()).with_context(|| i18n::tr("remote-failed-switch-invoking-user", &[]));
+            }
+        }
+        Ok(User { home: user.home_dir().to_path_buf() })
+    }
+
+    fn ssh_config(&self) -> PathBuf {
+        self.home.join(".ssh/config")
+    }
+
+    fn ssh_include(&self) -> PathBuf {
+        self.home.join(".ssh").join(SSH_INCLUDE)
+    }
+
+    fn gitconfig(&self) -> PathBuf {
+        self.home.join(".gitconfig")
+    }
+
+    /// Hosts listed in the HULIOS ssh include
+    fn hosts(&self) -> Vec<String> {
+        fs::read_to_string(self.ssh_include())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.strip_prefix("Host "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Rewrite the include with one block per host and make sure
+    /// ~/.ssh/config pulls it in (Include must precede any Host block)
+    fn write_ssh(&self, hosts: &[String]) -> Result<()> {
+        let hulios = std::env::current_exe().with_context(|| i18n::tr("error-no-binary", &[]))?;
+        let mut include = String::from("# Managed by `hulios remote`, do not edit\n");
+        for host in hosts {
+            include.push_str(&format!("\nHost {}\n    ProxyCommand {} connect %h %p\n", host, hulios.display()));
+        }
+        let ssh_dir = self.home.join(".ssh");
+        if !ssh_dir.exists() {
+            fs::create_dir_all(&ssh_dir)?;
+            fs::set_permissions(&ssh_dir, std::os::unix::fs::PermissionsExt::from_mode(0o700))?;
+        }
+        self.write(&self.ssh_include(), &include)?;
+
+        let config = fs::read_to_string(self.ssh_config()).unwrap_or_default();
+        let directive = format!("Include {}", SSH_INCLUDE);
+        if !config.lines().any(|line| line.trim() == directive) {
+            self.write(&self.ssh_config(), &format!("{}\n{}", directive, config))?;
+        }
+        Ok(())
+    }
+
+    /// Run `git config --file ~/.gitconfig` with the given arguments
+    fn git(&self, args: &[&str]) -> Result<()> {
+        let path = self.gitconfig();
+        // The user may not be able to enter the directory sudo was started from
+        let status = Command::new("git")
+            .current_dir(&self.home)
+            .arg("config")
+            .arg("--file")
+            .arg(&path)
+            .args(args)
+            .run()
+            .with_context(|| i18n::tr("remote-failed-run-git", &[]))?;
+        if !status.success() {
+            anyhow::bail!(i18n::tr("remote-git-config-failed", &[("args", &args.join(" "))]));
+        }
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, content: &str) -> Result<()> {
+        fs::write(path, content).with_context(|| i18n::tr("error-write", &[("path", &path.display().to_string())]))
+    }
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Accept a bare host or a remote URL like `git@host:repo` / `https://host/repo`
+fn normalize(host: &str) -> Result<String> {
+    let host = host.split_once("://").map_or(host, |(_, rest)| rest);
+    let host = host.rsplit_once('@').map_or(host, |(_, rest)| rest);
+    let host = host.split([':', '/']).next().unwrap_or_default().to_ascii_lowercase();
+    if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-') {
+        anyhow::bail!(i18n::tr("remote-not-valid-hostname", &[("host", &host)]));
+    }
+    Ok(host)
+}
+
+/// git config key routing HTTPS fetches and pushes for `host` through Tor
+fn git_key(host: &str) -> String {
+    format!("http.https://{}/.proxy", host)
+}
@@ -0,0 +1,57 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::fs;
+use std::path::Path;
+use crate::blocklist::{self, Entry};
+use crate::config::{Config, Redirect};
+use crate::engine::{self, RESOLVED_CONSTRAINTS, ROUTE_LOCALNET, TOR_RESOLV, TOR_USER};
+use crate::{doh, firewall, i18n, report};
+
+/// Write what `hulios start` would install into `out`, as files a
+/// declarative system (NixOS, an image build) puts in place through its
+/// own activation. Nothing is run and nothing outside `out` is touched.
+pub fn write(config: &Config, out: &str) -> Result<()> {
+    let out = Path::new(out);
+    let backend = firewall::rendered_backend(config);
+    let mut files: Vec<(&str, String)> = vec![("torrc", engine::standalone_torrc(config)?)];
+    files.extend(backend.render(TOR_USER)?);
+    files.push(("resolved.conf.d/90-hulios.conf", RESOLVED_CONSTRAINTS.to_string()));
+    files.push(("resolv.conf", TOR_RESOLV.to_string()));
+    // The DNS redirect to 127.0.0.1 is dropped as martian without it
+    files.push(("sysctl.d/90-hulios.conf", format!("{} = 1\n", ROUTE_LOCALNET)));
+    if config.firewall.redirect == Redirect::Tproxy {
+        let mut routing = "# TPROXY: marked packets are routed back into the local stack\n".to_string();
+        for args in firewall::policy_routing() {
+            routing.push_str(&format!("ip {}\n", args.join(" ")));
+        }
+        files.push(("policy-routing", routing));
+    }
+
+    for (name, content) in &files {
+        let path = out.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| i18n::tr("error-create", &[("path", &parent.display().to_string())]))?;
+        }
+        fs::write(&path, content).with_context(|| i18n::tr("error-write", &[("path", &path.display().to_string())]))?;
+        println!("[+] {}", i18n::tr("render-wrote", &[("path", &path.display().to_string())]));
+    }
+    report::action(format!("Rendered {} file(s) into {}", files.len(), out.display()));
+    report::data("rendered", files.iter().map(|(name, _)| name).collect::<Vec<_>>());
+
+    let domains = blocked_domains(config);
+    if domains > 0 {
+        println!("{}", format!("[!] {}", i18n::tr("render-blocked-domain-left-out", &[("domains", &domains.to_string())])).yellow());
+        report::warning(format!("{} blocked domain(s) not rendered", domains));
+    }
+    println!("[*] {}", i18n::tr("render-ruleset-without-session-kept", &[("name", backend.name())]));
+    Ok(())
+}
+
+/// Entries of the blocklist (with the DoH providers) that are domains
+fn blocked_domains(config: &Config) -> usize {
+    let mut block = config.firewall.block.clone();
+    if config.firewall.block_doh {
+        block.extend(doh::providers());
+    }
+    block.iter().filter(|entry| matches!(blocklist::parse(entry), Ok(Entry::Domain(_)))).count()
+}
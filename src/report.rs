@@ -0,0 +1,135 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::FromRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use crate::config::Config;
+use crate::{engine, firewall, health, i18n};
+
+/// Collected outcome of one command for `--json`
+struct Report {
+    command: &'static str,
+    /// The real stdout; fd 1 points at stderr while the command runs
+    stdout: File,
+    actions: Vec<String>,
+    warnings: Vec<String>,
+    data: Map<String, Value>,
+}
+
+/// Present only in `--json` mode
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+/// Set in the privileged helper: what a step records is passed back to the
+/// frontend's report on stderr, marked with RELAY_MARKER
+static RELAY: AtomicBool = AtomicBool::new(false);
+
+pub const RELAY_MARKER: &str = "\u{1e}hulios-report ";
+
+pub fn set_relay(relay: bool) {
+    RELAY.store(relay, Ordering::Relaxed);
+}
+
+/// Switch to JSON mode: human-readable output moves to stderr, and stdout
+/// carries exactly one JSON object written by `finish`
+pub fn enable(command: &'static str) -> Result<()> {
+    // SAFETY: plain fd duplication; nothing has been written to stdout yet
+    let stdout = unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            anyhow::bail!(i18n::tr("report-failed-redirect-stdout", &[("error", &std::io::Error::last_os_error().to_string())]));
+        }
+        File::from_raw_fd(fd)
+    };
+
+    *REPORT.lock().unwrap() = Some(Report {
+        command,
+        stdout,
+        actions: Vec::new(),
+        warnings: Vec::new(),
+        data: Map::new(),
+    });
+    Ok(())
+}
+
+/// Whether this command reports in JSON
+pub fn enabled() -> bool {
+    REPORT.lock().unwrap().is_some()
+}
+
+/// Record something the command changed
+pub fn action(message: impl Into<String>) {
+    if RELAY.load(Ordering::Relaxed) {
+        eprintln!("{}{}", RELAY_MARKER, json!({ "action": message.into() }));
+        return;
+    }
+    if let Some(report) = REPORT.lock().unwrap().as_mut() {
+        report.actions.push(message.into());
+    }
+}
+
+/// Record a non-fatal problem
+pub fn warning(message: impl Into<String>) {
+    if RELAY.load(Ordering::Relaxed) {
+        eprintln!("{}{}", RELAY_MARKER, json!({ "warning": message.into() }));
+        return;
+    }
+    if let Some(report) = REPORT.lock().unwrap().as_mut() {
+        report.warnings.push(message.into());
+    }
+}
+
+/// Attach command-specific results, e.g. the exit IP for `status`
+pub fn data(key: &str, value: impl Serialize) {
+    if RELAY.load(Ordering::Relaxed) {
+        eprintln!("{}{}", RELAY_MARKER, json!({ "data": { "key": key, "value": value } }));
+        return;
+    }
+    if let Some(report) = REPORT.lock().unwrap().as_mut() {
+        if let Ok(value) = serde_json::to_value(value) {
+            report.data.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Write the final JSON object, with the resulting state, to the real stdout
+pub fn finish(error: Option<&anyhow::Error>) {
+    let Some(mut report) = REPORT.lock().unwrap().take() else {
+        return;
+    };
+    let _ = std::io::stdout().flush();
+
+    let mut object = Map::new();
+    object.insert("command".into(), json!(report.command));
+    object.insert("ok".into(), json!(error.is_none()));
+    if let Some(e) = error {
+        object.insert("error".into(), json!(format!("{:#}", e)));
+    }
+    object.insert("actions".into(), json!(report.actions));
+    object.insert("warnings".into(), json!(report.warnings));
+    object.insert("state".into(), current_state());
+    object.append(&mut report.data);
+
+    let _ = writeln!(report.stdout, "{}", Value::Object(object));
+}
+
+fn current_state() -> Value {
+    let config = Config::load().unwrap_or_default();
+    let assessment = health::assess(&config);
+    let backend = firewall::backend(&config);
+    json!({
+        "health": assessment.health.to_string(),
+        "problems": assessment.problems,
+        "tor_running": engine::is_tor_running(),
+        "tor_pid": engine::tor_pid(),
+        "tor_cgroup": engine::tor_cgroup(),
+        "dns_owned": engine::dns_owned(),
+        "firewall_backend": backend.name(),
+        "firewall_rules": backend.rules_present(),
+        "blocked_packets": backend.blocked_packets(),
+        "icmp_dropped": backend.icmp_dropped(),
+        "bootstrap": engine::bootstrap_progress().map(|(pct, _)| pct),
+    })
+}
@@ -0,0 +1,166 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::exec::Run;
+use crate::{audit, engine, i18n, report};
+
+const NSSWITCH: &str = "/etc/nsswitch.conf";
+const NSSWITCH_BACKUP: &str = "/var/lib/hulios/nsswitch.conf.backup";
+const UNBOUND_CONF_DIR: &str = "/etc/unbound/unbound.conf.d";
+pub const UNBOUND_DROPIN: &str = "/etc/unbound/unbound.conf.d/hulios.conf";
+
+/// On systems that resolve through nss-resolve, nscd or a local unbound,
+/// rewriting resolv.conf alone doesn't move resolution onto Tor. Adapt each
+/// one for the session; every step is best effort and only warns.
+pub fn adapt(resolved_masked: bool) {
+    // With resolved masked, `hosts: files resolve` would resolve nothing
+    if resolved_masked {
+        step("resolvers-could-not-bypass-nss-resolve", bypass_nss_resolve);
+    }
+    if engine::unit_is_active("unbound") {
+        step("resolvers-could-not-forward-unbound", forward_unbound);
+    }
+    if engine::unit_is_active("nscd") {
+        step("resolvers-could-not-flush-nscd", flush_nscd);
+    }
+}
+
+/// Undo `adapt`, driven by what is actually left on disk
+pub fn restore() {
+    if Path::new(NSSWITCH_BACKUP).exists() {
+        step("resolvers-could-not-restore-nsswitch", || {
+            audit::record("restore", NSSWITCH);
+            fs::copy(NSSWITCH_BACKUP, NSSWITCH).with_context(|| i18n::tr("resolvers-failed-restore", &[("nsswitch", NSSWITCH)]))?;
+            fs::remove_file(NSSWITCH_BACKUP)?;
+            println!("[+] {}", i18n::tr("resolvers-restored", &[("nsswitch", NSSWITCH)]));
+            Ok(())
+        });
+    }
+    if fs::remove_file(UNBOUND_DROPIN).is_ok() {
+        audit::record("remove", UNBOUND_DROPIN);
+        step("resolvers-could-not-restart-unbound", || restart("unbound"));
+    }
+    if engine::unit_is_active("nscd") {
+        // Drop answers that were resolved through Tor
+        step("resolvers-could-not-flush-nscd", flush_nscd);
+    }
+}
+
+/// Session changes still in place, for `recover` and `stop --check`
+pub fn leftovers() -> Vec<String> {
+    let mut found = Vec::new();
+    if Path::new(NSSWITCH_BACKUP).exists() {
+        found.push(i18n::tr("resolvers-restore-nss-resolve-bypassed", &[("nsswitch", NSSWITCH)]));
+    }
+    if Path::new(UNBOUND_DROPIN).exists() {
+        found.push(i18n::tr("resolvers-remove-restart-unbound", &[("path", UNBOUND_DROPIN)]));
+    }
+    found
+}
+
+// =============================================================================
+// Steps
+// =============================================================================
+
+/// Replace `resolve [...]` in the nsswitch hosts line with plain `dns`
+fn bypass_nss_resolve() -> Result<()> {
+    let content = fs::read_to_string(NSSWITCH).with_context(|| i18n::tr("error-read", &[("path", NSSWITCH)]))?;
+    let Some(hosts) = content.lines().find(|line| line.trim_start().starts_with("hosts:")) else {
+        return Ok(());
+    };
+    let sources: Vec<&str> = hosts.trim_start().trim_start_matches("hosts:").split_whitespace().collect();
+    if !sources.contains(&"resolve") {
+        return Ok(());
+    }
+
+    let mut rewritten = Vec::new();
+    let mut skip_action = false;
+    for source in sources {
+        if source == "resolve" {
+            // Keep the position, dns takes over unless it is listed already
+            rewritten.push("dns");
+            skip_action = true;
+        } else if skip_action && source.starts_with('[') {
+            // resolve's own [!UNAVAIL=return] action no longer applies
+        } else {
+            skip_action = false;
+            rewritten.push(source);
+        }
+    }
+    let mut seen = Vec::new();
+    rewritten.retain(|source| {
+        let first = !seen.contains(source) || source.starts_with('[');
+        seen.push(*source);
+        first
+    });
+    let line = format!("hosts: {}", rewritten.join(" "));
+
+    if !Path::new(NSSWITCH_BACKUP).exists() {
+        fs::copy(NSSWITCH, NSSWITCH_BACKUP).with_context(|| i18n::tr("resolvers-failed-back-up-nsswitch", &[]))?;
+    }
+    let updated: Vec<&str> = content.lines().map(|l| if l == hosts { line.as_str() } else { l }).collect();
+    audit::record("write", NSSWITCH);
+    fs::write(NSSWITCH, updated.join("\n") + "\n").with_context(|| i18n::tr("error-write", &[("path", NSSWITCH)]))?;
+
+    println!("[+] {}", i18n::tr("resolvers-nss-resolve-bypassed-session", &[("line", &line)]));
+    report::action(format!("nss-resolve bypassed ({})", line));
+    Ok(())
+}
+
+/// Send every unbound query to the Tor DNSPort instead of the roots or a
+/// DoT upstream (port 853 is dropped by the firewall)
+fn forward_unbound() -> Result<()> {
+    if !Path::new(UNBOUND_CONF_DIR).is_dir() {
+        anyhow::bail!(i18n::tr("resolvers-does-not-exist-add", &[("path", UNBOUND_CONF_DIR)]));
+    }
+    audit::record("write", UNBOUND_DROPIN);
+    fs::write(UNBOUND_DROPIN, r#"# HULIOS - removed on hulios stop
+server:
+    do-not-query-localhost: no
+forward-zone:
+    name: "."
+    forward-addr: 127.0.0.1@9061
+"#).with_context(|| i18n::tr("error-write", &[("path", UNBOUND_DROPIN)]))?;
+
+    if let Err(e) = restart("unbound") {
+        // Most likely a forward-zone for "." already exists; don't leave unbound down
+        audit::record("remove", UNBOUND_DROPIN);
+        let _ = fs::remove_file(UNBOUND_DROPIN);
+        let _ = restart("unbound");
+        return Err(e.context(i18n::tr("resolvers-unbound-rejected-tor-forward", &[])));
+    }
+    println!("[+] {}", i18n::tr("resolvers-unbound-now-forwards-tor", &[]));
+    report::action("unbound forwards to the Tor DNSPort");
+    Ok(())
+}
+
+fn flush_nscd() -> Result<()> {
+    let status = Command::new("nscd").args(["-i", "hosts"]).run();
+    if !status.is_ok_and(|s| s.success()) {
+        restart("nscd")?;
+    }
+    println!("[+] {}", i18n::tr("resolvers-nscd-hosts-cache-flushed", &[]));
+    report::action("nscd hosts cache flushed");
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn restart(unit: &str) -> Result<()> {
+    let status = Command::new("systemctl").args(["restart", unit]).run()?;
+    if !status.success() {
+        anyhow::bail!(i18n::tr("resolvers-systemctl-restart-failed", &[("unit", unit)]));
+    }
+    Ok(())
+}
+
+fn step(id: &str, action: impl FnOnce() -> Result<()>) {
+    if let Err(e) = action() {
+        let warning = i18n::tr(id, &[("error", &format!("{:#}", e))]);
+        eprintln!("[!] {}", warning);
+        report::warning(warning);
+    }
+}
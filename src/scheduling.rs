@@ -0,0 +1,115 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use crate::config::{IoClass, SchedPolicy, TorConfig};
+
+/// ioprio_set(2): the target is one process, and the class sits above
+/// the 13 bits of priority data
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+/// The kernel's default best-effort priority
+const DEFAULT_IO_PRIORITY: u8 = 4;
+
+/// How the managed Tor is scheduled, from `[tor]`, so heavy Tor use
+/// doesn't make an interactive desktop sluggish
+#[derive(Clone, Copy, Default)]
+pub struct Scheduling {
+    pub nice: Option<i32>,
+    pub io_class: Option<IoClass>,
+    pub io_priority: Option<u8>,
+    pub policy: Option<SchedPolicy>,
+}
+
+impl Scheduling {
+    pub fn from_config(tor: &TorConfig) -> Scheduling {
+        Scheduling {
+            nice: tor.nice,
+            io_class: tor.io_class,
+            io_priority: tor.io_priority,
+            policy: tor.sched_policy,
+        }
+    }
+
+    /// Whether everything is left as the kernel has it
+    pub fn is_default(&self) -> bool {
+        self.nice.is_none() && self.io_class.is_none() && self.io_priority.is_none() && self.policy.is_none()
+    }
+
+    /// The I/O class, best-effort if only a priority is given
+    fn io_class(&self) -> Option<IoClass> {
+        self.io_class.or(self.io_priority.map(|_| IoClass::BestEffort))
+    }
+
+    /// Have `command` set them on itself before it execs. Tor's threads
+    /// inherit them, and so does a tor systemd-run execs in its scope.
+    pub fn apply(self, command: &mut Command) {
+        if self.is_default() {
+            return;
+        }
+        // SAFETY: between fork and exec only sched_setscheduler,
+        // setpriority and ioprio_set are called, on plain values
+        unsafe {
+            command.pre_exec(move || self.set());
+        }
+    }
+
+    /// Set them on the calling process
+    fn set(&self) -> io::Result<()> {
+        if let Some(policy) = self.policy {
+            let policy = match policy {
+                SchedPolicy::Other => libc::SCHED_OTHER,
+                SchedPolicy::Batch => libc::SCHED_BATCH,
+                SchedPolicy::Idle => libc::SCHED_IDLE,
+            };
+            let param = libc::sched_param { sched_priority: 0 };
+            // SAFETY: pid 0 is the calling process, param outlives the call
+            if unsafe { libc::sched_setscheduler(0, policy, &param) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(nice) = self.nice {
+            // SAFETY: who 0 is the calling process
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(class) = self.io_class() {
+            let value = match class {
+                IoClass::BestEffort => 2 << IOPRIO_CLASS_SHIFT | self.io_priority.unwrap_or(DEFAULT_IO_PRIORITY) as libc::c_int,
+                IoClass::Idle => 3 << IOPRIO_CLASS_SHIFT,
+            };
+            // SAFETY: ioprio_set takes three integers; who 0 is the calling process
+            if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, value) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// As systemd service properties, for the confined unit
+    pub fn properties(&self) -> Vec<String> {
+        let mut properties = Vec::new();
+        if let Some(policy) = self.policy {
+            let policy = match policy {
+                SchedPolicy::Other => "other",
+                SchedPolicy::Batch => "batch",
+                SchedPolicy::Idle => "idle",
+            };
+            properties.push(format!("CPUSchedulingPolicy={}", policy));
+        }
+        if let Some(nice) = self.nice {
+            properties.push(format!("Nice={}", nice));
+        }
+        if let Some(class) = self.io_class() {
+            properties.push(format!("IOSchedulingClass={}", match class {
+                IoClass::BestEffort => "best-effort",
+                IoClass::Idle => "idle",
+            }));
+        }
+        if let Some(priority) = self.io_priority {
+            properties.push(format!("IOSchedulingPriority={}", priority));
+        }
+        properties
+    }
+}
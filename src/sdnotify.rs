@@ -0,0 +1,51 @@
+use std::env;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+/// Send a state line (READY=1, WATCHDOG=1, STATUS=...) to the service
+/// manager; a no-op when not started by systemd with Type=notify
+pub fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    // A leading @ names a socket in the abstract namespace
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&path),
+    };
+    if let (Ok(socket), Ok(addr)) = (UnixDatagram::unbound(), addr) {
+        let _ = socket.send_to_addr(state.as_bytes(), &addr);
+    }
+}
+
+/// How often systemd expects WATCHDOG=1, if the watchdog is enabled for
+/// this process: half of WatchdogSec, as sd_watchdog_enabled() advises
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// The first socket systemd passed to this process by socket activation
+/// (LISTEN_FDS), if any
+pub fn listen_fd() -> Option<OwnedFd> {
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let count: u32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != std::process::id() || count == 0 {
+        return None;
+    }
+    // SAFETY: systemd hands passed sockets over starting at fd 3 (SD_LISTEN_FDS_START),
+    // owned by nothing else in this process
+    Some(unsafe { OwnedFd::from_raw_fd(3) })
+}
+
+/// Whether the machine runs systemd as its init
+pub fn booted() -> bool {
+    std::path::Path::new("/run/systemd/system").is_dir()
+}
@@ -0,0 +1,280 @@
+use anyhow::{Result, Context};
+use colored::*;
+use toml_edit::DocumentMut;
+use users::get_current_uid;
+use crate::config::{Config, CONFIG_PATH};
+use crate::control::ControlClient;
+use crate::{circuits, configtool, engine, i18n, report, state};
+
+/// What a value must look like
+#[derive(Clone, Copy)]
+enum Kind {
+    /// 0 or 1
+    Bool,
+    /// 0, 1 or auto
+    AutoBool,
+    /// A whole number in a range
+    Count(u32, u32),
+    /// Seconds, optionally with a unit: "300", "10 minutes"
+    Interval,
+    /// Relays by fingerprint, nickname, address or {country code}
+    Nodes,
+    /// Comma-separated port numbers
+    Ports,
+}
+
+impl Kind {
+    fn describe(self) -> String {
+        match self {
+            Kind::Bool => i18n::tr("setconf-bool", &[]),
+            Kind::AutoBool => i18n::tr("setconf-auto-bool", &[]),
+            Kind::Count(min, max) => i18n::tr("setconf-count", &[("min", &min.to_string()), ("max", &max.to_string())]),
+            Kind::Interval => i18n::tr("setconf-interval", &[]),
+            Kind::Nodes => i18n::tr("setconf-nodes", &[]),
+            Kind::Ports => i18n::tr("setconf-ports", &[]),
+        }
+    }
+}
+
+/// Client options that are safe to change at runtime. Everything HULIOS
+/// itself depends on (ports, DataDirectory, User, control auth) and what
+/// has a config key of its own (bandwidth, circuit build timeout) is not.
+const OPTIONS: &[(&str, Kind)] = &[
+    ("MaxCircuitDirtiness", Kind::Interval),
+    ("NewCircuitPeriod", Kind::Interval),
+    ("CircuitStreamTimeout", Kind::Interval),
+    ("SocksTimeout", Kind::Interval),
+    ("KeepalivePeriod", Kind::Interval),
+    ("LongLivedPorts", Kind::Ports),
+    ("EntryNodes", Kind::Nodes),
+    ("ExitNodes", Kind::Nodes),
+    ("ExcludeNodes", Kind::Nodes),
+    ("ExcludeExitNodes", Kind::Nodes),
+    ("StrictNodes", Kind::Bool),
+    ("NumEntryGuards", Kind::Count(1, 10)),
+    ("EnforceDistinctSubnets", Kind::Bool),
+    ("ConnectionPadding", Kind::AutoBool),
+    ("ReducedConnectionPadding", Kind::Bool),
+    ("CircuitPadding", Kind::Bool),
+    ("ClientUseIPv6", Kind::Bool),
+    ("ClientPreferIPv6ORPort", Kind::AutoBool),
+];
+
+/// Units Tor accepts after an interval
+const INTERVAL_UNITS: &[&str] = &[
+    "sec", "secs", "second", "seconds", "min", "mins", "minute", "minutes",
+    "hour", "hours", "day", "days", "week", "weeks",
+];
+
+// =============================================================================
+// Validation
+// =============================================================================
+
+/// The option's canonical name, if `hulios setconf` may change it
+/// (Tor's option names are case-insensitive)
+fn lookup(name: &str) -> Result<(&'static str, Kind)> {
+    OPTIONS
+        .iter()
+        .find(|(option, _)| option.eq_ignore_ascii_case(name))
+        .copied()
+        .with_context(|| i18n::tr("setconf-unknown-option", &[("name", name)]))
+}
+
+/// Check `value` for option `name`, returning it in the form written to
+/// the torrc. Nothing that could end a torrc line or a SETCONF argument
+/// gets through.
+pub fn check(name: &str, value: &str) -> Result<String> {
+    let (name, kind) = lookup(name)?;
+    let value = value.trim();
+    let invalid = || anyhow::anyhow!(i18n::tr("setconf-must-not", &[("name", name), ("expected", &kind.describe()), ("value", value)]));
+    match kind {
+        Kind::Bool => matches!(value, "0" | "1").then(|| value.to_string()).ok_or_else(invalid),
+        Kind::AutoBool => {
+            let value = value.to_ascii_lowercase();
+            matches!(value.as_str(), "0" | "1" | "auto").then_some(value).ok_or_else(invalid)
+        }
+        Kind::Count(min, max) => value
+            .parse::<u32>()
+            .ok()
+            .filter(|count| (min..=max).contains(count))
+            .map(|count| count.to_string())
+            .ok_or_else(invalid),
+        Kind::Interval => {
+            let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+            let (amount, unit) = value.split_at(split);
+            let unit = unit.trim().to_ascii_lowercase();
+            match amount.parse::<u32>() {
+                Ok(amount) if amount > 0 && unit.is_empty() => Ok(amount.to_string()),
+                Ok(amount) if amount > 0 && INTERVAL_UNITS.contains(&unit.as_str()) => Ok(format!("{} {}", amount, unit)),
+                _ => Err(invalid()),
+            }
+        }
+        Kind::Nodes => {
+            let nodes: Vec<&str> = value.split(',').map(str::trim).collect();
+            if nodes.iter().all(|node| is_node(node)) {
+                Ok(nodes.join(","))
+            } else {
+                Err(invalid())
+            }
+        }
+        Kind::Ports => {
+            let ports: Option<Vec<u16>> = value
+                .split(',')
+                .map(|port| port.trim().parse::<u16>().ok().filter(|port| *port > 0))
+                .collect();
+            let ports = ports.ok_or_else(invalid)?;
+            Ok(ports.iter().map(u16::to_string).collect::<Vec<_>>().join(","))
+        }
+    }
+}
+
+/// One entry of a node list: $FINGERPRINT (optionally ~ or = nickname),
+/// {cc}, a nickname or an IPv4 address/network
+fn is_node(node: &str) -> bool {
+    let nickname = |name: &str| (1..=19).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric());
+    if let Some(rest) = node.strip_prefix('$') {
+        let (fingerprint, name) = rest.split_once(['~', '=']).unwrap_or((rest, "x"));
+        return fingerprint.len() == 40 && fingerprint.chars().all(|c| c.is_ascii_hexdigit()) && nickname(name);
+    }
+    if let Some(code) = node.strip_prefix('{').and_then(|rest| rest.strip_suffix('}')) {
+        return code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic());
+    }
+    if let Some((address, bits)) = node.split_once('/') {
+        return address.parse::<std::net::Ipv4Addr>().is_ok() && bits.parse::<u8>().is_ok_and(|bits| bits <= 32);
+    }
+    node.parse::<std::net::Ipv4Addr>().is_ok() || nickname(node)
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios setconf` with no arguments: the options it may change, with the
+/// values kept in the config
+pub fn list() -> Result<()> {
+    let config = Config::load()?;
+    for (name, kind) in OPTIONS {
+        match config.tor.options.get(*name) {
+            Some(value) => println!("{} = {}", name.bold(), value),
+            None => println!("{} ({})", name, kind.describe()),
+        }
+    }
+    report::data("options", &config.tor.options);
+    Ok(())
+}
+
+/// Handle each `Name=value` (set), `Name=` (back to Tor's default) or
+/// `Name` (show) argument in turn
+pub fn run(arguments: &[String]) -> Result<()> {
+    for argument in arguments {
+        match argument.split_once('=') {
+            None => get(argument)?,
+            Some((name, "")) => reset(name)?,
+            Some((name, value)) => set(name, value)?,
+        }
+    }
+    Ok(())
+}
+
+fn get(name: &str) -> Result<()> {
+    let (name, _) = lookup(name)?;
+    let value = if engine::is_tor_running() {
+        let mut client = ControlClient::connect()?;
+        let reply = client.command(&format!("GETCONF {}", name))?;
+        reply.first().and_then(|line| line.split_once('=')).map(|(_, value)| value.to_string())
+    } else {
+        Config::load()?.tor.options.get(name).cloned()
+    };
+    match &value {
+        Some(value) => println!("{}={}", name, value),
+        None => println!("{}", i18n::tr("setconf-tor-default", &[("name", name)])),
+    }
+    report::data(name, &value);
+    Ok(())
+}
+
+/// Apply to the running Tor first, so a value Tor refuses is never saved
+fn set(name: &str, value: &str) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let (name, _) = lookup(name)?;
+    let value = check(name, value)?;
+    if engine::is_tor_running() {
+        let mut client = ControlClient::connect()?;
+        let live = live_value(name, Some(&value)).unwrap_or_else(|| value.clone());
+        client.setconf(&[(name, live)]).with_context(|| i18n::tr("error-tor-refused", &[("name", name), ("value", &value)]))?;
+    }
+    update(|options| {
+        options.insert(name, toml_edit::value(value.as_str()));
+        Ok(())
+    })?;
+
+    println!("{}", format!("[+] {}={}", name, value).green());
+    report::action(format!("Tor option set: {}={}", name, value));
+    Ok(())
+}
+
+fn reset(name: &str) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let (name, _) = lookup(name)?;
+    if engine::is_tor_running() {
+        let mut client = ControlClient::connect()?;
+        match live_value(name, None) {
+            Some(live) => client.setconf(&[(name, live)])?,
+            None => {
+                client.command(&format!("RESETCONF {}", name))?;
+            }
+        }
+    }
+    update(|options| {
+        options.remove(name);
+        Ok(())
+    })?;
+
+    println!("{}", format!("[+] {}", i18n::tr("setconf-back-tor-default", &[("name", name)])).green());
+    report::action(format!("Tor option reset: {}", name));
+    Ok(())
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// What the running Tor gets for `value`: the session's bad exits
+/// (`hulios badexit`) stay excluded whatever ExcludeExitNodes is set to
+fn live_value(name: &str, value: Option<&str>) -> Option<String> {
+    if name != "ExcludeExitNodes" {
+        return value.map(str::to_string);
+    }
+    let bad_exits = state::load().map(|session| session.bad_exits).unwrap_or_default();
+    circuits::excluded_exits(value, &bad_exits)
+}
+
+/// Edit the `[tor.options]` table of the config file, validating before writing
+fn update(change: impl FnOnce(&mut toml_edit::Table) -> Result<()>) -> Result<()> {
+    let source = configtool::read_existing()?;
+    let mut doc: DocumentMut = source.parse().with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+    let tor = doc
+        .entry("tor")
+        .or_insert_with(|| {
+            // Only the [tor.options] header is written for a new table
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .with_context(|| i18n::tr("setconf-tor-not-table", &[]))?;
+    let options = tor
+        .entry("options")
+        .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()))
+        .as_table_mut()
+        .with_context(|| i18n::tr("setconf-tor-options-not-table", &[]))?;
+    change(options)?;
+
+    let updated = doc.to_string();
+    configtool::check(&updated)?;
+    configtool::write(&updated)
+}
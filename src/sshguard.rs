@@ -0,0 +1,110 @@
+use anyhow::Result;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::net::SocketAddr;
+use crate::config::Config;
+use crate::{i18n, procfs, report};
+
+/// Set by the helper to the PID of the `hulios` that asked it, whose
+/// session is the one that would be cut off
+pub const REQUESTER_PID: &str = "HULIOS_REQUESTER_PID";
+
+/// The SSH connection HULIOS is being started from
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct SshSession {
+    /// The client's end
+    pub peer: SocketAddr,
+    /// sshd's end on this machine
+    pub local: SocketAddr,
+}
+
+// =============================================================================
+// Guard
+// =============================================================================
+
+/// Before a start applies the DROP policies: if this is an SSH session,
+/// keep its connection open when `firewall.keep_ssh_session` says so, go
+/// ahead with `force` (the session is cut off), and refuse otherwise.
+/// Returns the session to exempt from the firewall.
+pub fn guard(config: &Config, force: bool) -> Result<Option<SshSession>> {
+    let Some(session) = detect() else {
+        return Ok(None);
+    };
+    if config.firewall.keep_ssh_session {
+        println!("[*] {}", i18n::tr("sshguard-started-over-ssh-connection", &[("peer", &session.peer.to_string())]));
+        report::action(format!("SSH session from {} exempt from the firewall", session.peer));
+        return Ok(Some(session));
+    }
+    if force {
+        println!("{}", format!("[!] {}", i18n::tr("sshguard-started-over-ssh-firewall", &[("peer", &session.peer.to_string())])).yellow());
+        report::warning(format!("SSH session from {} cut off (--force-ssh-cutoff)", session.peer));
+        return Ok(None);
+    }
+    anyhow::bail!(i18n::tr("sshguard-ssh-session-which-firewall", &[("peer", &session.peer.to_string())]))
+}
+
+// =============================================================================
+// Detection
+// =============================================================================
+
+/// The SSH connection this command runs in: SSH_CONNECTION when sudo kept
+/// it, else the established connection of the nearest sshd among the
+/// process's ancestors (or, through the helper, the requester's)
+pub fn detect() -> Option<SshSession> {
+    from_env().or_else(|| {
+        let start = std::env::var(REQUESTER_PID).ok().and_then(|pid| pid.parse().ok())
+            .unwrap_or(std::process::id() as i32);
+        from_ancestors(start)
+    })
+}
+
+/// "client_ip client_port server_ip server_port"
+fn from_env() -> Option<SshSession> {
+    let value = std::env::var("SSH_CONNECTION").ok()?;
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let [peer_ip, peer_port, local_ip, local_port] = fields[..] else {
+        return None;
+    };
+    Some(SshSession {
+        peer: SocketAddr::new(peer_ip.parse().ok()?, peer_port.parse().ok()?),
+        local: SocketAddr::new(local_ip.parse().ok()?, local_port.parse().ok()?),
+    })
+}
+
+fn from_ancestors(mut pid: i32) -> Option<SshSession> {
+    while pid > 1 {
+        let info = procfs::inspect(pid)?;
+        // "sshd", or "sshd-session" since OpenSSH 9.8
+        if info.comm.starts_with("sshd") {
+            if let Some(session) = connection_of(pid) {
+                return Some(session);
+            }
+        }
+        pid = parent(pid)?;
+    }
+    None
+}
+
+/// The established TCP connection held by `pid`
+fn connection_of(pid: i32) -> Option<SshSession> {
+    let inodes: HashSet<u64> = fs::read_dir(format!("/proc/{}/fd", pid))
+        .ok()?
+        .flatten()
+        .filter_map(|fd| {
+            let target = fs::read_link(fd.path()).ok()?;
+            target.to_str()?.strip_prefix("socket:[")?.strip_suffix(']')?.parse().ok()
+        })
+        .collect();
+    procfs::established_tcp()
+        .into_iter()
+        .find(|(_, _, inode)| inodes.contains(inode))
+        .map(|(local, peer, _)| SshSession { peer, local })
+}
+
+/// The PPID field of /proc/<pid>/stat, after the parenthesized command name
+fn parent(pid: i32) -> Option<i32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat.rsplit(')').next()?.split_whitespace().nth(1)?.parse().ok()
+}
@@ -0,0 +1,69 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::amnesic;
+use crate::circuits::BadExit;
+use crate::i18n;
+use crate::sshguard::SshSession;
+
+/// Kept under /var/lib so it survives a crash or reboot mid-session
+pub const STATE_PATH: &str = "/var/lib/hulios/state.json";
+
+/// What an active HULIOS session changed on the system, so it can be
+/// undone even if HULIOS never got the chance to stop cleanly
+#[derive(Deserialize, Serialize, Default)]
+pub struct State {
+    pub started_at: i64,
+    pub tor_pid: Option<u32>,
+    /// Tor's own cgroup (cgroup::dedicated), e.g. /system.slice/hulios-tor.scope
+    #[serde(default)]
+    pub tor_cgroup: Option<String>,
+    pub firewall_backend: String,
+    /// net.ipv4.conf.all.route_localnet before HULIOS set it to 1
+    pub route_localnet: Option<String>,
+    /// The applied firewall policy (firewall::fingerprint), so `reload`
+    /// only re-applies the rules when it changed
+    pub firewall_policy: Option<String>,
+    /// Tor fell back to `tor.bridges` after failing to bootstrap directly
+    #[serde(default)]
+    pub bridges: bool,
+    /// Exits reported with `hulios badexit`, excluded until the session ends
+    #[serde(default)]
+    pub bad_exits: Vec<BadExit>,
+    /// `hulios dns-only`: encrypted DNS, no Tor
+    #[serde(default)]
+    pub dns_only: bool,
+    /// The SSH connection exempt from the firewall (`keep_ssh_session`)
+    #[serde(default)]
+    pub ssh_session: Option<SshSession>,
+    /// When the session is rolled back unless `hulios confirm` runs first
+    /// (`start --confirm-within`)
+    #[serde(default)]
+    pub confirm_by: Option<i64>,
+    /// `start --allow-lan`: the LAN services leave directly this session
+    #[serde(default)]
+    pub allow_lan: bool,
+}
+
+/// The persisted session state, if a session is (or was) active
+pub fn load() -> Option<State> {
+    let content = fs::read_to_string(amnesic::redirect(STATE_PATH)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+pub fn save(state: &State) -> Result<()> {
+    let path = amnesic::redirect(STATE_PATH);
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&tmp, &path).with_context(|| i18n::tr("error-write", &[("path", &path)]))?;
+    Ok(())
+}
+
+/// Forget the session once everything it changed has been restored
+pub fn clear() {
+    let _ = fs::remove_file(amnesic::redirect(STATE_PATH));
+}
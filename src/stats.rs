@@ -0,0 +1,368 @@
+use anyhow::{Result, Context};
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use colored::*;
+use crate::config::Config;
+use crate::control::ControlClient;
+use crate::{amnesic, circuits, firewall, i18n, report, tuning};
+
+pub const STATS_PATH: &str = "/var/lib/hulios/stats.json";
+
+/// Traffic carried by one Tor process, from `hulios start` to `stop`
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Session {
+    pub started: i64,
+    pub ended: Option<i64>,
+    pub tor_pid: u32,
+    pub read: u64,
+    pub written: u64,
+    /// Countries of the exits its circuits used, as ISO codes
+    #[serde(default)]
+    pub exit_countries: BTreeSet<String>,
+    /// Packets the firewall dropped instead of letting them leave outside Tor
+    #[serde(default)]
+    pub blocked: u64,
+    /// The firewall's drop counter when `blocked` was last updated; it
+    /// starts over whenever the rules are applied again
+    #[serde(default)]
+    pub blocked_counter: u64,
+    /// Tor exited without being stopped
+    #[serde(default)]
+    pub crashed: bool,
+    /// Seconds from spawn to 100% bootstrap
+    #[serde(default)]
+    pub bootstrap_secs: Option<u64>,
+    /// Tor bootstrapped from a consensus kept by `tor.persistent`
+    #[serde(default)]
+    pub cached_consensus: bool,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+struct StatsFile {
+    sessions: Vec<Session>,
+}
+
+// =============================================================================
+// Session Lifecycle
+// =============================================================================
+
+/// Open a new session for a freshly spawned Tor
+pub fn begin_session(tor_pid: u32) {
+    let mut stats = load();
+    close_open_sessions(&mut stats);
+    stats.sessions.push(Session {
+        started: Local::now().timestamp(),
+        ended: None,
+        tor_pid,
+        read: 0,
+        written: 0,
+        exit_countries: BTreeSet::new(),
+        blocked: 0,
+        blocked_counter: 0,
+        crashed: false,
+        bootstrap_secs: None,
+        cached_consensus: false,
+    });
+    let _ = save(&stats);
+}
+
+/// Refresh the open session's totals from Tor's traffic counters.
+/// Tor reports cumulative bytes for its lifetime, which is the session.
+/// The exits in use and the firewall's drops are added on the way.
+pub fn update_session() -> Result<Option<Session>> {
+    let mut stats = load();
+    let Some(session) = stats.sessions.last_mut().filter(|s| s.ended.is_none()) else {
+        return Ok(None);
+    };
+
+    let mut client = ControlClient::connect()?;
+    session.read = client.getinfo("traffic/read")?.trim().parse().with_context(|| i18n::tr("stats-invalid-traffic-read", &[]))?;
+    session.written = client.getinfo("traffic/written")?.trim().parse().with_context(|| i18n::tr("stats-invalid-traffic-written", &[]))?;
+    if let Ok(countries) = circuits::exit_countries(&mut client) {
+        session.exit_countries.extend(countries);
+    }
+    if let Some(counter) = firewall::backend(&Config::load().unwrap_or_default()).blocked_packets() {
+        session.blocked += counter.checked_sub(session.blocked_counter).unwrap_or(counter);
+        session.blocked_counter = counter;
+    }
+
+    let current = session.clone();
+    let _ = save(&stats);
+    Ok(Some(current))
+}
+
+/// Record final totals and close the session (called before Tor is stopped)
+pub fn end_session() {
+    let _ = update_session();
+    let mut stats = load();
+    close_open_sessions(&mut stats);
+    let _ = save(&stats);
+}
+
+/// Tor `tor_pid` exited without being stopped; the daemon saw it go
+pub fn record_crash(tor_pid: u32) {
+    let mut stats = load();
+    let Some(session) = stats.sessions.iter_mut().rev().find(|s| s.tor_pid == tor_pid) else {
+        return;
+    };
+    session.crashed = true;
+    session.ended.get_or_insert(Local::now().timestamp());
+    let _ = save(&stats);
+}
+
+/// The open session's Tor reached 100% after `took`, from a kept
+/// consensus if `cached`
+pub fn record_bootstrap(took: std::time::Duration, cached: bool) {
+    let mut stats = load();
+    let Some(session) = stats.sessions.last_mut().filter(|s| s.ended.is_none()) else {
+        return;
+    };
+    session.bootstrap_secs = Some(took.as_secs());
+    session.cached_consensus = cached;
+    let _ = save(&stats);
+}
+
+fn close_open_sessions(stats: &mut StatsFile) {
+    let now = Local::now().timestamp();
+    for session in stats.sessions.iter_mut().filter(|s| s.ended.is_none()) {
+        session.ended = Some(now);
+    }
+}
+
+// =============================================================================
+// Reporting
+// =============================================================================
+
+/// The open session as last recorded, without asking Tor
+pub fn current_session() -> Option<Session> {
+    load().sessions.pop().filter(|s| s.ended.is_none())
+}
+
+/// "4s, from the kept consensus"
+pub fn describe_bootstrap(session: &Session) -> Option<String> {
+    let secs = session.bootstrap_secs?;
+    let id = if session.cached_consensus { "stats-bootstrap-kept" } else { "stats-bootstrap" };
+    Some(i18n::tr(id, &[("secs", &secs.to_string())]))
+}
+
+/// One-line summary of the current session for `hulios status`
+pub fn current_session_summary() -> Option<String> {
+    let session = update_session().ok()??;
+    Some(format!("↓ {}  ↑ {}", format_bytes(session.read), format_bytes(session.written)))
+}
+
+/// Print current session, daily totals for the last week, and all-time totals
+pub fn print_stats() {
+    // Pull live counters into the open session first (root only)
+    let _ = update_session();
+    let stats = load();
+
+    if stats.sessions.is_empty() {
+        println!("[*] {}", i18n::tr("stats-no-sessions-recorded-yet", &[]));
+        return;
+    }
+
+    let current = stats.sessions.last().filter(|s| s.ended.is_none());
+    report::data("current_session", current);
+    if let Some(session) = current {
+        println!("\n[+] {}", i18n::tr("stats-current-session-since", &[("since", &format_time(session.started))]));
+        println!("    ↓ {}  ↑ {}", format_bytes(session.read).cyan(), format_bytes(session.written).cyan());
+        if let Some(bootstrap) = describe_bootstrap(session) {
+            println!("    {}", i18n::tr("stats-bootstrapped", &[("bootstrap", &bootstrap)]));
+        }
+        tuning::print_analysis();
+    }
+
+    print_bootstrap_times(&stats);
+
+    let today = Local::now().date_naive();
+    println!("\n[+] {}", i18n::tr("stats-last-7-days", &[]));
+    let mut week = (0, 0);
+    for offset in (0..7).rev() {
+        let day = today - Duration::days(offset);
+        let (read, written) = totals_for_day(&stats, day);
+        week.0 += read;
+        week.1 += written;
+        println!("    {}  ↓ {:>10}  ↑ {:>10}", day, format_bytes(read), format_bytes(written));
+    }
+    println!("    {}  ↓ {:>10}  ↑ {:>10}", format!("{}      ", i18n::tr("stats-week", &[])).bold(), format_bytes(week.0), format_bytes(week.1));
+    report::data("week", json!({ "read": week.0, "written": week.1 }));
+
+    let all = stats.sessions.iter().fold((0, 0), |acc, s| (acc.0 + s.read, acc.1 + s.written));
+    println!("\n[+] {}\n", i18n::tr("stats-all-time-sessions", &[("count", &stats.sessions.len().to_string()), ("read", &format_bytes(all.0)), ("written", &format_bytes(all.1))]));
+    report::data("all_time", json!({ "sessions": stats.sessions.len(), "read": all.0, "written": all.1 }));
+}
+
+/// Median bootstrap time with a kept consensus against without one, so
+/// what `tor.persistent` saves shows
+fn print_bootstrap_times(stats: &StatsFile) {
+    let median = |cached: bool| -> Option<(u64, usize)> {
+        let mut times: Vec<u64> = stats.sessions.iter()
+            .filter(|s| s.cached_consensus == cached)
+            .filter_map(|s| s.bootstrap_secs)
+            .collect();
+        times.sort_unstable();
+        Some((*times.get(times.len() / 2)?, times.len()))
+    };
+    let (cached, downloaded) = (median(true), median(false));
+    if cached.is_none() && downloaded.is_none() {
+        return;
+    }
+    println!("\n[+] {}", i18n::tr("stats-bootstrap-median", &[]));
+    for (label, times) in [("stats-kept-consensus", cached), ("stats-downloaded-consensus", downloaded)] {
+        let label = i18n::tr(label, &[]);
+        match times {
+            Some((secs, count)) => {
+                let id = if count == 1 { "stats-median-session" } else { "stats-median-sessions" };
+                println!("    {:<20}  {}", label, i18n::tr(id, &[("secs", &format!("{:>4}", secs)), ("count", &count.to_string())]));
+            }
+            None => println!("    {:<20}     -", label),
+        }
+    }
+    report::data("bootstrap", json!({
+        "cached": cached.map(|(secs, count)| json!({ "median_secs": secs, "sessions": count })),
+        "downloaded": downloaded.map(|(secs, count)| json!({ "median_secs": secs, "sessions": count })),
+    }));
+}
+
+/// `hulios report [--last 7d]`: one line per session that started within
+/// `last` (all of them without it), then the totals
+pub fn print_report(last: Option<&str>) -> Result<()> {
+    let since = match last {
+        Some(period) => Some(Local::now().timestamp() - parse_period(period)?.num_seconds()),
+        None => None,
+    };
+    let _ = update_session();
+    let stats = load();
+    let sessions: Vec<&Session> = stats.sessions.iter().filter(|s| since.is_none_or(|since| s.started >= since)).collect();
+
+    match (last, since) {
+        (Some(period), Some(since)) => println!("[+] {}", i18n::tr("stats-sessions-last-since", &[("period", period), ("since", &format_time(since)), ("count", &sessions.len().to_string())])),
+        _ => println!("[+] {}", i18n::tr("stats-all-sessions", &[("count", &sessions.len().to_string())])),
+    }
+    let now = Local::now().timestamp();
+    for session in &sessions {
+        let end = session.ended.map_or(i18n::tr("stats-running", &[]), |ended| {
+            Local.timestamp_opt(ended, 0).single().map(|t| t.format("%H:%M").to_string()).unwrap_or_default()
+        });
+        let duration = session.ended.unwrap_or(now) - session.started;
+        let exits = if session.exit_countries.is_empty() {
+            "-".to_string()
+        } else {
+            session.exit_countries.iter().cloned().collect::<Vec<_>>().join(",")
+        };
+        let mut line = format!("    {} - {:<7} {:>7}  ↓ {:>10}  ↑ {:>10}  {}",
+            format_time(session.started), end, format_duration(duration),
+            format_bytes(session.read), format_bytes(session.written),
+            i18n::tr("stats-session-blocked-exits", &[("blocked", &format!("{:>6}", session.blocked)), ("exits", &exits)]));
+        if session.crashed {
+            line.push_str(&format!("  {}", i18n::tr("stats-crashed", &[]).red()));
+        }
+        println!("{}", line);
+    }
+
+    let countries: BTreeSet<&String> = sessions.iter().flat_map(|s| &s.exit_countries).collect();
+    let time: i64 = sessions.iter().map(|s| s.ended.unwrap_or(now) - s.started).sum();
+    let read: u64 = sessions.iter().map(|s| s.read).sum();
+    let written: u64 = sessions.iter().map(|s| s.written).sum();
+    let blocked: u64 = sessions.iter().map(|s| s.blocked).sum();
+    let crashes = sessions.iter().filter(|s| s.crashed).count();
+    let id = if crashes == 1 { "stats-total-crash" } else { "stats-total-crashes" };
+    println!("\n[+] {}", i18n::tr(id, &[
+        ("time", &format_duration(time)), ("read", &format_bytes(read)), ("written", &format_bytes(written)),
+        ("blocked", &blocked.to_string()), ("crashes", &crashes.to_string()),
+    ]));
+    if !countries.is_empty() {
+        println!("    {}", i18n::tr("stats-exit-countries", &[("countries", &countries.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", "))]));
+    }
+    report::data("sessions", &sessions);
+    report::data("totals", json!({
+        "sessions": sessions.len(),
+        "seconds": time,
+        "read": read,
+        "written": written,
+        "blocked": blocked,
+        "crashes": crashes,
+        "exit_countries": countries,
+    }));
+    Ok(())
+}
+
+/// "7d", "12h", "30m" or "2w"
+fn parse_period(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().with_context(|| i18n::tr("stats-invalid-period-expected-e", &[("period", value)]))?;
+    Ok(match unit {
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" | "" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        other => anyhow::bail!(i18n::tr("stats-unknown-period-unit-use", &[("unit", other)])),
+    })
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    match minutes {
+        0..=59 => format!("{}m", minutes),
+        _ => format!("{}h{:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+/// Sessions are attributed to the day they started
+fn totals_for_day(stats: &StatsFile, day: NaiveDate) -> (u64, u64) {
+    stats.sessions
+        .iter()
+        .filter(|s| Local.timestamp_opt(s.started, 0).single().map(|t| t.date_naive()) == Some(day))
+        .fold((0, 0), |acc, s| (acc.0 + s.read, acc.1 + s.written))
+}
+
+fn format_time(timestamp: i64) -> String {
+    Local.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| i18n::tr("status-unknown", &[]))
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+// =============================================================================
+// Persistence
+// =============================================================================
+
+fn load() -> StatsFile {
+    fs::read_to_string(amnesic::redirect(STATS_PATH))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(stats: &StatsFile) -> Result<()> {
+    let path = amnesic::redirect(STATS_PATH);
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_string_pretty(stats)?)?;
+    fs::rename(&tmp, &path).with_context(|| i18n::tr("error-write", &[("path", &path)]))?;
+    Ok(())
+}
@@ -1,40 +1,392 @@
 use anyhow::{Result, Context};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use colored::*;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::config::{CheckEndpoint, Config, EndpointKind, FirewallMode, GeoipConfig, StatusConfig, TorBrowserMode};
+use crate::exec::Run;
+use crate::health::{self, Health};
+use crate::mode::{self, Mode};
+use crate::{cgroup, confirmation, engine, exitlist, firewall, geoip, i18n, ipc, isolate, report, state, stats, torbrowser};
 
-#[derive(Deserialize)]
-struct TorStatus {
+pub const STATUS_CACHE: &str = "/tmp/hulios_status_cache.json";
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TorStatus {
     #[serde(rename = "IsTor")]
-    is_tor: bool,
+    pub is_tor: bool,
     #[serde(rename = "IP")]
-    ip: String,
+    pub ip: String,
+}
+
+/// Last external check result, tied to the Tor instance that produced it
+#[derive(Deserialize, Serialize)]
+struct CachedStatus {
+    checked_at: u64,
+    tor_pid: Option<u32>,
+    status: TorStatus,
+}
+
+pub struct StatusOptions {
+    /// Only report local facts, never contact check.torproject.org
+    pub no_network: bool,
+    /// Seconds a previous external check result stays valid
+    pub cache_ttl: u64,
+    /// Download fresh GeoIP databases through Tor before the lookup
+    pub refresh_geoip: bool,
+}
+
+/// Exit codes of `hulios status`, with and without --short
+pub const STATUS_TORIFIED: i32 = 0;
+/// The exit check saw an address that isn't a Tor exit
+pub const STATUS_LEAK: i32 = 1;
+pub const STATUS_DEGRADED: i32 = 2;
+/// HULIOS isn't routing traffic through Tor (off, or in dns-only mode)
+pub const STATUS_STOPPED: i32 = 3;
+/// Running, but the exit couldn't be checked (--no-network, or the check failed)
+pub const STATUS_UNVERIFIED: i32 = 4;
+
+/// What the exit check found, and how old the result is if it was cached
+struct ExitCheck {
+    status: TorStatus,
+    cached_age: Option<u64>,
+}
+
+/// The verdict `hulios status` starts with: one token and its exit code.
+/// `tor:<exit ip>` (or `tor:onion-only`, which never uses an exit) is the
+/// only torified one; then `leak:<ip>`, `degraded`, `failed`, `off`,
+/// `dns-only` and `unverified`.
+fn verdict(config: &Config, health: Health, exit: Option<&ExitCheck>) -> (String, i32) {
+    if mode::current() == Mode::DnsOnly {
+        return ("dns-only".to_string(), STATUS_STOPPED);
+    }
+    match health {
+        Health::Stopped => ("off".to_string(), STATUS_STOPPED),
+        Health::Degraded => ("degraded".to_string(), STATUS_DEGRADED),
+        Health::Failed => ("failed".to_string(), STATUS_DEGRADED),
+        Health::Active if config.firewall.mode == FirewallMode::OnionOnly => ("tor:onion-only".to_string(), STATUS_TORIFIED),
+        Health::Active => match exit {
+            Some(exit) if exit.status.is_tor => (format!("tor:{}", exit.status.ip), STATUS_TORIFIED),
+            Some(exit) => (format!("leak:{}", exit.status.ip), STATUS_LEAK),
+            None => ("unverified".to_string(), STATUS_UNVERIFIED),
+        },
+    }
+}
+
+/// The exit check: cached, else asked for. None with `no_network` and in
+/// onion-only mode, which never uses an exit.
+fn check_exit(config: &Config, opts: &StatusOptions) -> Result<Option<ExitCheck>> {
+    if opts.no_network || config.firewall.mode == FirewallMode::OnionOnly {
+        return Ok(None);
+    }
+    let tor_pid = engine::tor_pid();
+    if let Some((status, age)) = load_cached(tor_pid, opts.cache_ttl) {
+        return Ok(Some(ExitCheck { status, cached_age: Some(age) }));
+    }
+    let status = check_status(&config.status)?;
+    store_cached(tor_pid, status.clone());
+    Ok(Some(ExitCheck { status, cached_age: None }))
 }
 
-pub fn print_status() {
-    match check_status() {
-        Ok(status) => {
-            println!("\n[+] Status: {}", if status.is_tor { "The shadows are calm".green() } else { "The shadows whisper".red() });
-            println!("[+] Ip: {}\n", status.ip.cyan());
+/// `hulios status`: the verdict on the first line, always in the same
+/// form for scripts (`HULIOS <token>`), then the details. Returns the
+/// verdict's exit code.
+pub fn print_status(opts: &StatusOptions) -> i32 {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} {:#}", format!("[!] {}", i18n::tr("status-ignoring-invalid-config", &[])).red(), e);
+            report::warning(format!("Ignoring invalid config: {:#}", e));
+            Config::default()
+        }
+    };
+    let health = health::assess(&config).health;
+    // With HULIOS off, Tor Browser's own Tor is checked instead
+    let browser = (config.tor.browser == TorBrowserMode::Integrate && !engine::is_tor_running())
+        .then(torbrowser::detect)
+        .flatten();
+    let exit = if browser.is_some() { Ok(None) } else { check_exit(&config, opts) };
+    let (token, code) = verdict(&config, health, exit.as_ref().ok().and_then(Option::as_ref));
+    println!("HULIOS {}", token);
+    report::data("short", &token);
+
+    print_local_status(&config);
+
+    if opts.refresh_geoip {
+        match geoip::refresh(&config.geoip) {
+            Ok(()) => {
+                println!("{}", format!("[+] {}", i18n::tr("status-geoip-databases-updated", &[])).green());
+                report::action("GeoIP databases updated");
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", format!("[!] {}", i18n::tr("status-error-updating-geoip-databases", &[])).red(), e);
+                report::warning(format!("Error updating GeoIP databases: {:#}", e));
+            }
+        }
+    }
+
+    if opts.no_network {
+        println!();
+        return code;
+    }
+    if config.firewall.mode == FirewallMode::OnionOnly {
+        println!("[*] {}", i18n::tr("status-onion-only-skip", &[]));
+        println!();
+        return code;
+    }
+    if let Some(browser) = browser {
+        println!("[*] {}", i18n::tr("status-checking-tor-browser", &[("socks_port", &browser.socks_port.to_string())]));
+        match check_status_via(&config.status, Some(&browser.socks_proxy())) {
+            Ok(status) => print_tor_status(&status, None, &config.geoip),
+            Err(e) => eprintln!("{} {:#}", format!("[!] {}", i18n::tr("status-error-checking-tor-browser", &[])).red(), e),
         }
+        return code;
+    }
+
+    match exit {
+        Ok(Some(exit)) => print_tor_status(&exit.status, exit.cached_age, &config.geoip),
+        Ok(None) => println!(),
         Err(e) => {
-            eprintln!("{} {}", "[!] Error checking status:".red(), e);
-             println!("[*] Trying simple IP check via ifconfig.me...");
-             // Fallback
-             let _ = std::process::Command::new("curl").arg("ifconfig.me").status();
-             println!("");
+            eprintln!("{} {}", format!("[!] {}", i18n::tr("status-error-checking-status", &[])).red(), e);
+            report::warning(format!("Error checking status: {:#}", e));
+            println!("[*] {}", i18n::tr("status-trying-ifconfig", &[]));
+            // Fallback
+            if let Ok(output) = std::process::Command::new("curl").arg("ifconfig.me").run_output() {
+                println!("{}", String::from_utf8_lossy(&output.stdout).trim());
+            }
+            println!();
         }
     }
+    code
 }
 
-fn check_status() -> Result<TorStatus> {
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()?;
-        
-    let resp = client.get("https://check.torproject.org/api/ip")
+/// Print only the verdict token, for shell prompts and status bars, and
+/// return its exit code
+pub fn print_short(opts: &StatusOptions) -> i32 {
+    let config = Config::load().unwrap_or_default();
+    let health = health::assess(&config).health;
+    // Only a running session's exit says anything about the verdict
+    let exit = if health == Health::Active { check_exit(&config, opts).ok().flatten() } else { None };
+    let (token, code) = verdict(&config, health, exit.as_ref());
+    println!("{}", token);
+    report::data("short", &token);
+    code
+}
+
+// =============================================================================
+// Local Facts
+// =============================================================================
+
+fn print_local_status(config: &Config) {
+    let backend = firewall::backend(config);
+    let rules = match backend.rules_present() {
+        Some(true) => i18n::tr("status-present", &[]).green(),
+        Some(false) => i18n::tr("status-absent", &[]).red(),
+        None => i18n::tr("status-unknown-not-root", &[]).yellow(),
+    };
+    println!("\n[+] {}: {}", i18n::tr("status-firewall-rules", &[("backend", backend.name())]), rules);
+
+    if !config.firewall.block.is_empty() {
+        let rejected = match backend.blocked_packets() {
+            Some(packets) => i18n::tr("status-blocklist-rejected", &[("packets", &packets.to_string())]),
+            None => i18n::tr("status-counter-unavailable", &[]),
+        };
+        println!("[+] {}", i18n::tr("status-blocklist", &[("count", &config.firewall.block.len().to_string()), ("rejected", &rejected)]));
+    }
+
+    if let Some(dropped) = backend.icmp_dropped() {
+        let allowed = match config.firewall.icmp_allow.as_slice() {
+            [] => i18n::tr("status-icmp-none-allowed", &[]),
+            types => i18n::tr("status-icmp-allowed", &[("types", &types.iter().map(|kind| kind.name()).collect::<Vec<_>>().join(", "))]),
+        };
+        println!("[+] {}", i18n::tr("status-icmp-dropped", &[("dropped", &dropped.to_string()), ("allowed", &allowed)]));
+    }
+
+    let tor = match engine::tor_pid() {
+        Some(pid) if engine::is_tor_running() => i18n::tr("status-running-pid", &[("pid", &pid.to_string())]).green(),
+        _ if engine::is_tor_running() => i18n::tr("status-running", &[]).green(),
+        _ => i18n::tr("status-not-running", &[]).red(),
+    };
+    println!("[+] {}: {}", i18n::tr("status-tor-process", &[]), tor);
+    if let Some(session) = stats::current_session() {
+        if let Some(bootstrap) = stats::describe_bootstrap(&session) {
+            println!("[+] {}", i18n::tr("status-tor-bootstrapped", &[("duration", &bootstrap)]));
+        }
+        report::data("bootstrap", json!({ "secs": session.bootstrap_secs, "cached_consensus": session.cached_consensus }));
+    }
+
+    if let Some((group, usage)) = engine::tor_cgroup().and_then(|group| cgroup::usage(&group).map(|usage| (group, usage))) {
+        let processes = if usage.processes == 1 { "status-cgroup-process" } else { "status-cgroup-processes" };
+        let mut detail = vec![i18n::tr(processes, &[("count", &usage.processes.to_string())])];
+        match (usage.memory, usage.memory_max) {
+            (Some(memory), Some(max)) => detail.push(i18n::tr("status-cgroup-memory", &[("memory", &stats::format_bytes(memory)), ("max", &stats::format_bytes(max))])),
+            (Some(memory), None) => detail.push(stats::format_bytes(memory)),
+            _ => {}
+        }
+        if let Some(cpu) = usage.cpu {
+            detail.push(i18n::tr("status-cgroup-cpu", &[("seconds", &cpu.as_secs().to_string())]));
+        }
+        if let Some(kills @ 1..) = usage.oom_kills {
+            detail.push(i18n::tr("status-cgroup-oom-kills", &[("kills", &kills.to_string())]).yellow().to_string());
+        }
+        println!("[+] {}", i18n::tr("status-tor-cgroup", &[("group", &group), ("detail", &detail.join(", "))]));
+    }
+
+    for app in isolate::active() {
+        println!("[+] {}", i18n::tr("status-isolated-app", &[("command", &app.command), ("user", &app.user), ("socks_port", &app.socks_port().to_string()), ("trans_port", &app.trans_port().to_string()), ("dns_port", &app.dns_port().to_string())]));
+    }
+
+    if let Some(browser) = torbrowser::detect() {
+        let route = i18n::tr(if engine::is_tor_running() { "status-browser-tor-over-tor" } else { "status-browser-own-tor" }, &[]);
+        let running = i18n::tr("status-browser-running", &[("pid", &browser.pid.to_string()), ("user", &browser.user), ("route", &route)]);
+        println!("[+] {}: {}", i18n::tr("status-tor-browser", &[]), running.yellow());
+    }
+
+    let dns = if engine::dns_owned() { i18n::tr("status-dns-owned", &[]).green() } else { i18n::tr("status-dns-system", &[]).red() };
+    println!("[+] {}: {}", i18n::tr("status-dns", &[]), dns);
+
+    let bootstrap = match engine::bootstrap_progress() {
+        Some((100, _)) => "100%".green(),
+        Some((pct, summary)) => format!("{}% ({})", pct, summary).yellow(),
+        None => i18n::tr("status-unknown", &[]).yellow(),
+    };
+    println!("[+] {}: {}", i18n::tr("status-bootstrap", &[]), bootstrap);
+
+    match ipc::request::<ipc::DaemonStatus>(&ipc::Request::Status) {
+        Ok(daemon) => {
+            let uptime = (chrono::Utc::now().timestamp() - daemon.started_at).max(0) / 60;
+            let watching = match daemon.circuit_established {
+                Some(true) => "status-daemon-watching-circuits",
+                Some(false) => "status-daemon-watching-no-circuits",
+                None => "status-daemon-watching",
+            };
+            let watching = i18n::tr(watching, &[("pid", &daemon.pid.to_string()), ("uptime", &uptime.to_string()),
+                ("health", &daemon.health)]);
+            println!("[+] {}: {}", i18n::tr("status-daemon", &[]), watching.green());
+        }
+        Err(_) if state::load().is_some() => println!("[+] {}: {}", i18n::tr("status-daemon", &[]), i18n::tr("status-not-running", &[]).red()),
+        Err(_) => {}
+    }
+
+    if let Some(traffic) = stats::current_session_summary() {
+        println!("[+] {}: {}", i18n::tr("status-session-traffic", &[]), traffic.cyan());
+    }
+
+    if let Some(seconds) = confirmation::remaining() {
+        let rollback = i18n::tr("status-unconfirmed-rollback", &[("seconds", &seconds.to_string())]);
+        println!("[+] {}: {}", i18n::tr("status-unconfirmed", &[]), rollback.yellow());
+        report::data("confirm_within", seconds);
+    }
+
+    let assessment = health::assess(config);
+    let label = assessment.health.to_string();
+    let label = match assessment.health {
+        Health::Active => label.green(),
+        Health::Stopped => label.normal(),
+        Health::Degraded => label.yellow(),
+        Health::Failed => label.red(),
+    };
+    println!("[+] {}: {}", i18n::tr("status-state", &[]), label);
+    for problem in &assessment.problems {
+        println!("    {} {} ({})", "[!]".red(), problem.description, i18n::tr("status-fix", &[("remedy", &problem.remedy.cyan().to_string())]));
+    }
+}
+
+// =============================================================================
+// External Check
+// =============================================================================
+
+fn print_tor_status(status: &TorStatus, cached_age: Option<u64>, geoip_config: &GeoipConfig) {
+    let location = geoip::lookup(geoip_config, &status.ip).ok().filter(|l| !l.is_empty());
+    let country = location.as_ref().and_then(|location| location.country.clone()).unwrap_or_else(|| i18n::tr("status-unknown", &[]));
+    let args = [("exit_ip", status.ip.as_str()), ("country", country.as_str())];
+    let shadows = if status.is_tor { i18n::tr("status-shadows-calm", &args).green() } else { i18n::tr("status-shadows-whisper", &args).red() };
+    println!("\n[+] {}: {}", i18n::tr("status-status", &[]), shadows);
+    match cached_age {
+        Some(age) => println!("[+] {}: {} ({})", i18n::tr("status-ip", &[]), status.ip.cyan(), i18n::tr("status-ip-cached", &[("seconds", &age.to_string())])),
+        None => println!("[+] {}: {}", i18n::tr("status-ip", &[]), status.ip.cyan()),
+    }
+    if let Some(location) = &location {
+        println!("[+] {}: {}", i18n::tr("status-location", &[]), location.to_string().cyan());
+    }
+    report::data("exit", json!({
+        "ip": status.ip,
+        "is_tor": status.is_tor,
+        "cached_age": cached_age,
+        "location": location.map(|l| l.to_string()),
+    }));
+    println!();
+}
+
+/// Try each configured endpoint in order, returning the first answer
+pub fn check_status(config: &StatusConfig) -> Result<TorStatus> {
+    check_status_via(config, None)
+}
+
+/// Like `check_status`, through a SOCKS `proxy` instead of the system route
+fn check_status_via(config: &StatusConfig, proxy: Option<&str>) -> Result<TorStatus> {
+    let mut builder = reqwest::blocking::Client::builder().timeout(std::time::Duration::from_secs(10));
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    let client = builder.build()?;
+
+    for endpoint in &config.endpoints {
+        match query_endpoint(&client, endpoint, config) {
+            Ok(status) => return Ok(status),
+            Err(e) => eprintln!("[!] {}", i18n::tr("status-endpoint-failed", &[("url", &endpoint.url), ("error", &format!("{:#}", e))])),
+        }
+    }
+
+    anyhow::bail!(i18n::tr("status-no-endpoint-answered", &[]))
+}
+
+fn query_endpoint(client: &reqwest::blocking::Client, endpoint: &CheckEndpoint, config: &StatusConfig) -> Result<TorStatus> {
+    let resp = client.get(&endpoint.url)
         .send()
-        .context("Failed to connect to check.torproject.org")?;
-        
-    let status: TorStatus = resp.json().context("Failed to parse JSON")?;
-    Ok(status)
+        .with_context(|| i18n::tr("status-failed-connect", &[("url", &endpoint.url)]))?;
+
+    match endpoint.kind {
+        EndpointKind::TorCheck => {
+            let status: TorStatus = resp.json().with_context(|| i18n::tr("status-failed-parse-json", &[]))?;
+            Ok(status)
+        }
+        EndpointKind::ExitList => {
+            let ip = resp.text().with_context(|| i18n::tr("status-failed-read-response", &[]))?.trim().to_string();
+            let exits = exitlist::load(config)?;
+            Ok(TorStatus { is_tor: exits.contains(&ip), ip })
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The exit the running Tor was last verified through, however long ago
+pub fn last_exit() -> Option<TorStatus> {
+    let tor_pid = engine::tor_pid()?;
+    load_cached(Some(tor_pid), u64::MAX).map(|(status, _)| status)
+}
+
+/// Return a cached result if it is fresh and from the same Tor process
+fn load_cached(tor_pid: Option<u32>, ttl: u64) -> Option<(TorStatus, u64)> {
+    let content = fs::read_to_string(STATUS_CACHE).ok()?;
+    let cached: CachedStatus = serde_json::from_str(&content).ok()?;
+    let age = now_secs().saturating_sub(cached.checked_at);
+    if ttl == 0 || age > ttl || cached.tor_pid != tor_pid {
+        return None;
+    }
+    Some((cached.status, age))
+}
+
+fn store_cached(tor_pid: Option<u32>, status: TorStatus) {
+    let cached = CachedStatus { checked_at: now_secs(), tor_pid, status };
+    if let Ok(json) = serde_json::to_string(&cached) {
+        let _ = fs::write(STATUS_CACHE, json);
+    }
 }
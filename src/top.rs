@@ -0,0 +1,275 @@
+use anyhow::{Result, Context};
+use colored::*;
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::time::{self, MissedTickBehavior};
+use users::get_current_uid;
+use crate::control::AsyncControlClient;
+use crate::{conntrack, engine, i18n, output, privacy, procfs, report, stats};
+
+/// How often the view is redrawn; the rates are per this interval
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Tor's TransPort, which the firewall redirects connections to
+const TRANS_PORT: u16 = 9051;
+
+/// An open stream through the session's Tor
+#[derive(Default)]
+struct Connection {
+    id: String,
+    /// "host:port" as Tor sees it
+    target: String,
+    circuit: String,
+    /// The app's end of the connection, from SOURCE_ADDR or conntrack
+    source: Option<SocketAddr>,
+    pid: Option<i32>,
+    process: Option<String>,
+    /// Whether its process was looked for; once per connection
+    looked_up: bool,
+    /// Bytes the app received and sent over the stream's lifetime
+    received: u64,
+    sent: u64,
+    /// The same over the last REFRESH
+    received_now: u64,
+    sent_now: u64,
+}
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios top`: the connections going through Tor right now, with their
+/// process, destination (only with `destinations`), circuit and throughput.
+/// Redrawn every second until Ctrl-C; `once` prints a single sample.
+pub fn run(destinations: bool, once: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("error-not-running", &[]));
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .with_context(|| i18n::tr("top-failed-start-runtime", &[]))?;
+    runtime.block_on(watch(destinations, once))
+}
+
+async fn watch(destinations: bool, once: bool) -> Result<()> {
+    let mut events = AsyncControlClient::connect().await.with_context(|| i18n::tr("error-control-unreachable", &[]))?;
+    events.command("SETEVENTS STREAM STREAM_BW").await?;
+    // Streams opened before now, from a second connection: the first one
+    // now carries events, which can't be told apart from a reply
+    let mut connections = existing().await?;
+
+    let mut frames = time::interval_at(time::Instant::now() + REFRESH, REFRESH);
+    frames.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = Instant::now();
+    loop {
+        tokio::select! {
+            event = events.next_event() => record(&mut connections, &event?),
+            _ = frames.tick() => {
+                identify(&mut connections);
+                let elapsed = last.elapsed().as_secs_f64().max(0.001);
+                last = Instant::now();
+                if once {
+                    print(&connections, destinations, elapsed);
+                    let listed: Vec<serde_json::Value> = connections.values().map(|connection| serde_json::json!({
+                        "id": connection.id,
+                        "destination": destination(connection, destinations),
+                        "circuit": connection.circuit,
+                        "pid": connection.pid,
+                        "process": connection.process,
+                        "received": connection.received,
+                        "sent": connection.sent,
+                    })).collect();
+                    report::data("connections", listed);
+                    return Ok(());
+                }
+                if !output::plain() {
+                    // Home and clear, like `top`
+                    print!("\x1b[H\x1b[2J");
+                }
+                print(&connections, destinations, elapsed);
+                for connection in connections.values_mut() {
+                    connection.received_now = 0;
+                    connection.sent_now = 0;
+                }
+            }
+        }
+    }
+}
+
+/// Open streams at startup; "ID STATUS CIRCUIT host:port" each
+async fn existing() -> Result<BTreeMap<String, Connection>> {
+    let mut client = AsyncControlClient::connect().await?;
+    let lines = client.command("GETINFO stream-status").await?;
+    Ok(lines
+        .iter()
+        .map(|line| line.strip_prefix("stream-status=").unwrap_or(line))
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split(' ').collect();
+            let (id, circuit, target) = (*words.first()?, *words.get(2)?, *words.get(3)?);
+            // Lookups through the DNSPort have no port
+            if target.ends_with(":0") {
+                return None;
+            }
+            Some((id.to_string(), Connection {
+                id: id.to_string(),
+                target: target.to_string(),
+                circuit: circuit.to_string(),
+                ..Default::default()
+            }))
+        })
+        .collect())
+}
+
+/// Take in one STREAM or STREAM_BW event
+fn record(connections: &mut BTreeMap<String, Connection>, event: &str) {
+    let words: Vec<&str> = event.split(' ').collect();
+    let field = |name: &str| words.iter().find_map(|word| word.strip_prefix(name)?.strip_prefix('='));
+    match words.as_slice() {
+        // "STREAM_BW ID WRITTEN READ TIME": from Tor's side, so what it
+        // read the app sent
+        ["STREAM_BW", id, sent, received, ..] => {
+            if let Some(connection) = connections.get_mut(*id) {
+                let sent: u64 = sent.parse().unwrap_or(0);
+                let received: u64 = received.parse().unwrap_or(0);
+                connection.sent += sent;
+                connection.sent_now += sent;
+                connection.received += received;
+                connection.received_now += received;
+            }
+        }
+        ["STREAM", id, "CLOSED" | "FAILED", ..] => {
+            connections.remove(*id);
+        }
+        ["STREAM", id, _, circuit, target, ..] => {
+            // Only the apps' own streams: not lookups or directory fetches
+            if field("PURPOSE").is_some_and(|purpose| purpose != "USER") || target.ends_with(":0") {
+                return;
+            }
+            let connection = connections.entry(id.to_string()).or_insert_with(|| Connection {
+                id: id.to_string(),
+                ..Default::default()
+            });
+            connection.target = target.to_string();
+            if *circuit != "0" {
+                connection.circuit = circuit.to_string();
+            }
+            if let Some(source) = field("SOURCE_ADDR").and_then(|source| source.parse().ok()) {
+                connection.source = Some(source);
+            }
+        }
+        _ => {}
+    }
+}
+
+// =============================================================================
+// Processes
+// =============================================================================
+
+/// Find the process behind each connection not identified yet: its socket
+/// is the one bound to the connection's source address. Streams open
+/// before `top` started carry no source, which conntrack knows for
+/// redirected connections.
+fn identify(connections: &mut BTreeMap<String, Connection>) {
+    let unknown: Vec<&mut Connection> = connections.values_mut().filter(|connection| !connection.looked_up).collect();
+    if unknown.is_empty() {
+        return;
+    }
+    let mut redirected = if unknown.iter().any(|connection| connection.source.is_none()) { redirected() } else { Vec::new() };
+    let sockets = procfs::tcp_sockets();
+    let owners = procfs::socket_owners();
+    let mut claimed: HashSet<SocketAddr> = HashSet::new();
+
+    for connection in unknown {
+        connection.looked_up = true;
+        if connection.source.is_none() {
+            // Several connections to one destination are told apart by
+            // taking each source only once
+            let target: Option<SocketAddr> = connection.target.parse().ok();
+            if let Some(i) = redirected.iter().position(|(source, destination)| Some(*destination) == target && !claimed.contains(source)) {
+                connection.source = Some(redirected.remove(i).0);
+            }
+        }
+        let Some(source) = connection.source else {
+            continue;
+        };
+        claimed.insert(source);
+        let inode = sockets
+            .iter()
+            .find(|(local, _)| *local == source)
+            .or_else(|| sockets.iter().find(|(local, _)| local.port() == source.port() && local.ip().is_unspecified()))
+            .map(|(_, inode)| *inode);
+        if let Some(info) = inode.and_then(|inode| owners.get(&inode)).and_then(|&pid| procfs::inspect(pid)) {
+            connection.pid = Some(info.pid);
+            connection.process = Some(info.comm);
+        }
+    }
+}
+
+/// (source, original destination) of the TCP connections the firewall
+/// redirected to the TransPort, from the conntrack table
+fn redirected() -> Vec<(SocketAddr, SocketAddr)> {
+    conntrack::entries()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.protocol == "tcp" && entry.reply_source.port() == TRANS_PORT)
+        .map(|entry| (entry.source, entry.destination))
+        .collect()
+}
+
+// =============================================================================
+// Display
+// =============================================================================
+
+fn print(connections: &BTreeMap<String, Connection>, destinations: bool, elapsed: f64) {
+    let rate = |bytes: u64| format!("{}/s", stats::format_bytes((bytes as f64 / elapsed) as u64));
+    let received: u64 = connections.values().map(|connection| connection.received_now).sum();
+    let sent: u64 = connections.values().map(|connection| connection.sent_now).sum();
+    let id = if connections.len() == 1 { "top-connection" } else { "top-connections" };
+    println!("{} {}", i18n::tr("top-title", &[]).bold(), i18n::tr(id, &[
+        ("count", &connections.len().to_string()), ("down", &rate(received)), ("up", &rate(sent)),
+    ]));
+    println!();
+    println!("{:<16} {:>7}  {:<32} {:>7} {:>12} {:>12} {:>10}",
+        i18n::tr("top-process", &[]), i18n::tr("top-pid", &[]), i18n::tr("top-destination", &[]), i18n::tr("top-circuit", &[]),
+        i18n::tr("top-down", &[]), i18n::tr("top-up", &[]), i18n::tr("top-total", &[]));
+
+    let mut sorted: Vec<&Connection> = connections.values().collect();
+    sorted.sort_by_key(|connection| std::cmp::Reverse((connection.received_now + connection.sent_now, connection.received + connection.sent)));
+    for connection in &sorted {
+        println!("{:<16} {:>7}  {:<32} {:>7} {:>12} {:>12} {:>10}",
+            truncate(connection.process.as_deref().unwrap_or("?"), 16),
+            connection.pid.map_or("-".to_string(), |pid| pid.to_string()),
+            truncate(&destination(connection, destinations), 32),
+            if connection.circuit.is_empty() { "-" } else { &connection.circuit },
+            rate(connection.received_now),
+            rate(connection.sent_now),
+            stats::format_bytes(connection.received + connection.sent));
+    }
+    if sorted.is_empty() {
+        println!("    {}", i18n::tr("top-no-connections", &[]));
+    }
+}
+
+/// Only the port, unless destinations were asked for: the screen may be
+/// recorded or shared
+fn destination(connection: &Connection, destinations: bool) -> String {
+    if destinations {
+        return connection.target.clone();
+    }
+    let port = connection.target.rsplit_once(':').map_or("", |(_, port)| port);
+    format!("{}:{}", privacy::SCRUBBED, port)
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(width - 1).collect();
+    short.push('…');
+    short
+}
@@ -0,0 +1,40 @@
+use crate::{engine, procfs};
+
+/// Tor Browser's SOCKS port unless its launcher was told otherwise
+const DEFAULT_SOCKS_PORT: u16 = 9150;
+
+/// A running Tor Browser, seen through the tor it bundles
+pub struct TorBrowser {
+    pub pid: i32,
+    pub user: String,
+    pub socks_port: u16,
+}
+
+impl TorBrowser {
+    pub fn socks_proxy(&self) -> String {
+        format!("socks5h://127.0.0.1:{}", self.socks_port)
+    }
+}
+
+/// Find Tor Browser's own tor: a tor that isn't HULIOS's and runs from a
+/// Tor Browser install (tor-browser/Browser/TorBrowser/Tor/tor)
+pub fn detect() -> Option<TorBrowser> {
+    let info = procfs::find_by_name("tor").into_iter().find(|info| {
+        !engine::is_hulios_tor(info)
+            && info.cmdline.iter().any(|arg| arg.contains("TorBrowser") || arg.contains("tor-browser"))
+    })?;
+    let user = users::get_user_by_uid(info.uid)
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| info.uid.to_string());
+    Some(TorBrowser { pid: info.pid, user, socks_port: socks_port(&info.cmdline) })
+}
+
+/// The port of a `+__SocksPort`/`SocksPort` option, e.g. "127.0.0.1:9150 IPv6Traffic"
+fn socks_port(cmdline: &[String]) -> u16 {
+    cmdline
+        .windows(2)
+        .find(|pair| pair[0].trim_start_matches(['+', '_']).eq_ignore_ascii_case("SocksPort"))
+        .and_then(|pair| pair[1].split_whitespace().next())
+        .and_then(|addr| addr.rsplit(':').next()?.parse().ok())
+        .unwrap_or(DEFAULT_SOCKS_PORT)
+}
@@ -0,0 +1,109 @@
+use anyhow::{Result, Context};
+use chrono::NaiveDateTime;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use crate::config::Config;
+use crate::engine::TOR_DATA_DIR;
+use crate::{amnesic, i18n, report};
+
+/// Where `tor.persistent` keeps Tor's files between sessions
+pub const STORE: &str = "/var/lib/hulios/tor-data";
+
+/// What is carried over: the guards in `state`, and the directory cache
+/// a bootstrap otherwise downloads in full. Keys, the control cookie and
+/// the lock belong to one Tor.
+const FILES: &[&str] = &[
+    "state",
+    "cached-certs",
+    "cached-microdesc-consensus",
+    "cached-microdescs",
+    "cached-microdescs.new",
+];
+
+const CONSENSUS: &str = "cached-microdesc-consensus";
+
+/// Tor still bootstraps from a consensus this long past its valid-until
+const REASONABLY_LIVE: i64 = 24 * 3600;
+
+/// Whether this session's Tor files are kept; never in an amnesic session
+pub fn enabled(config: &Config) -> bool {
+    config.tor.persistent && !amnesic::is_active()
+}
+
+/// Before Tor starts: copy the kept files into its fresh data directory,
+/// owned by `user`. Returns whether their consensus is recent enough for
+/// Tor to bootstrap from without downloading a new one.
+pub fn restore(config: &Config, user: &users::User) -> Result<bool> {
+    if !enabled(config) {
+        return Ok(false);
+    }
+    let store = Path::new(STORE);
+    if !store.is_dir() {
+        println!("[*] {}", i18n::tr("tordata-no-tor-data-kept", &[]));
+        return Ok(false);
+    }
+    for file in FILES {
+        let source = store.join(file);
+        if !source.exists() {
+            continue;
+        }
+        let target = Path::new(TOR_DATA_DIR).join(file);
+        fs::copy(&source, &target).with_context(|| i18n::tr("tordata-failed-restore", &[("source", &source.display().to_string())]))?;
+        std::os::unix::fs::chown(&target, Some(user.uid()), Some(user.primary_group_id()))
+            .with_context(|| i18n::tr("tordata-failed-chown", &[("target", &target.display().to_string())]))?;
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    match valid_until(&store.join(CONSENSUS)) {
+        Some(until) if now < until + REASONABLY_LIVE => {
+            let age = if now < until { "valid".to_string() } else { i18n::tr("tordata-expired-m-ago-still", &[("minutes", &((now - until) / 60).to_string())]) };
+            println!("[*] {}", i18n::tr("tordata-reusing-kept-guards-directory", &[("age", &age)]));
+            report::action("Reused the kept Tor consensus");
+            Ok(true)
+        }
+        Some(_) => {
+            println!("[*] {}", i18n::tr("tordata-reusing-kept-guards-kept", &[]));
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Copy Tor's files into the store, each only if Tor wrote it since it
+/// was last kept. The daemon calls this while Tor runs, so the kept
+/// consensus stays as fresh as Tor's, and Tor's stop calls it once more.
+pub fn save(config: &Config) -> Result<()> {
+    if !enabled(config) {
+        return Ok(());
+    }
+    fs::create_dir_all(STORE).with_context(|| i18n::tr("error-create", &[("path", STORE)]))?;
+    fs::set_permissions(STORE, fs::Permissions::from_mode(0o700))?;
+    for file in FILES {
+        let source = Path::new(TOR_DATA_DIR).join(file);
+        let Ok(written) = fs::metadata(&source).and_then(|meta| meta.modified()) else {
+            continue;
+        };
+        let target = Path::new(STORE).join(file);
+        // A copy is as new as the moment it was made
+        if fs::metadata(&target).and_then(|meta| meta.modified()).is_ok_and(|kept| kept >= written) {
+            continue;
+        }
+        // Through a temporary file, so a crash never leaves half a consensus
+        let temporary = Path::new(STORE).join(format!("{}.tmp", file));
+        fs::copy(&source, &temporary).with_context(|| i18n::tr("tordata-failed-keep", &[("source", &source.display().to_string())]))?;
+        fs::rename(&temporary, &target)?;
+    }
+    Ok(())
+}
+
+/// A consensus's `valid-until`, as a Unix timestamp; it is in the header
+fn valid_until(path: &Path) -> Option<i64> {
+    let reader = BufReader::new(File::open(path).ok()?);
+    let line = reader.lines()
+        .take(20)
+        .map_while(Result::ok)
+        .find_map(|line| line.strip_prefix("valid-until ").map(str::to_string))?;
+    NaiveDateTime::parse_from_str(line.trim(), "%Y-%m-%d %H:%M:%S").ok().map(|time| time.and_utc().timestamp())
+}
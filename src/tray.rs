@@ -0,0 +1,313 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use crate::config::Config;
+use crate::control::ControlClient;
+use crate::exec::Run;
+use crate::health::{self, Health};
+use crate::mode::{self, Mode};
+use crate::notify::Event;
+use crate::{audit, dnsonly, engine, helper, netprofile, sdnotify};
+
+/// The tray socket: root and members of helper::GROUP may connect. Next
+/// to the helper's, and installed with it.
+pub const SOCKET: &str = "/run/hulios-tray.sock";
+
+pub const SOCKET_UNIT: &str = "/etc/systemd/system/hulios-tray.socket";
+pub const SERVICE_UNIT: &str = "/etc/systemd/system/hulios-tray.service";
+pub const SOCKET_UNIT_LINK: &str = "/etc/systemd/system/sockets.target.wants/hulios-tray.socket";
+
+/// Raised when a message changes incompatibly; new messages and fields
+/// don't raise it, so clients ignore what they don't know
+pub const PROTOCOL: u32 = 1;
+
+/// Notifications, one JSON line each, for the tray server to pass on
+const EVENTS: &str = "/run/hulios/events.jsonl";
+
+/// EVENTS starts over once it grows past this
+const MAX_EVENTS: u64 = 64 * 1024;
+
+/// How often subscribers' state is checked for changes
+const POLL: Duration = Duration::from_secs(3);
+
+/// Longest request line the server reads
+const MAX_REQUEST: u64 = 1024;
+
+/// What a tray applet can send, one JSON line each, any number per
+/// connection
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// The current state, once
+    State,
+    /// The current state now and again on every change, plus notifications
+    Subscribe,
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    /// New circuits for new connections
+    Newnym,
+    SetMode { mode: TrayMode },
+}
+
+/// Mode names on the wire, as `hulios mode set` takes them
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum TrayMode {
+    Off,
+    Full,
+    DnsOnly,
+}
+
+/// What the server sends, one JSON line each
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Message {
+    /// First on every connection
+    Hello { protocol: u32, version: &'static str },
+    State(TrayState),
+    /// Something HULIOS would show as a desktop notification
+    Notification { kind: String, title: String, body: String, urgency: String },
+    /// An action finished; `error` is its last error line
+    Done { request: &'static str, ok: bool, error: Option<String> },
+    Error { message: String },
+}
+
+/// The session as an applet shows it
+#[derive(Serialize, Clone, PartialEq)]
+pub struct TrayState {
+    /// off, full or dns-only
+    pub mode: String,
+    /// active, degraded, stopped or failed
+    pub health: String,
+    pub problems: Vec<String>,
+    /// Tor's bootstrap percentage while in full mode
+    pub bootstrap: Option<u8>,
+    pub network_profile: Option<String>,
+}
+
+impl TrayState {
+    pub fn current() -> TrayState {
+        let mode = mode::current();
+        let (health, problems) = match mode {
+            Mode::Off => (Health::Stopped, Vec::new()),
+            Mode::Full => {
+                let assessment = health::assess(&Config::load().unwrap_or_default());
+                (assessment.health, assessment.problems.into_iter().map(|problem| problem.description).collect())
+            }
+            Mode::DnsOnly if dnsonly::is_active() => (Health::Active, Vec::new()),
+            Mode::DnsOnly => (Health::Failed, vec!["The dns-only resolver is not running".to_string()]),
+        };
+        TrayState {
+            mode: mode.to_string(),
+            health: health.to_string().to_lowercase(),
+            problems,
+            bootstrap: (mode == Mode::Full).then(engine::bootstrap_progress).flatten().map(|(pct, _)| pct),
+            network_profile: netprofile::active(),
+        }
+    }
+}
+
+type Client = Arc<Mutex<UnixStream>>;
+
+// =============================================================================
+// Events
+// =============================================================================
+
+/// Queue a notification for tray applets. Only root can write EVENTS;
+/// anyone else's notifications reach the desktop by other means.
+pub fn publish(event: Event, title: &str, body: &str, urgency: &str) {
+    if fs::metadata(EVENTS).is_ok_and(|meta| meta.len() > MAX_EVENTS) {
+        let _ = fs::remove_file(EVENTS);
+    }
+    let line = serde_json::json!({ "kind": event.as_str(), "title": title, "body": body, "urgency": urgency });
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(EVENTS) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// EVENTS lines past `offset`, which is moved to the end; back to the
+/// start if the file was started over
+fn read_events(offset: &mut u64) -> Vec<Message> {
+    let Ok(mut file) = fs::File::open(EVENTS) else {
+        *offset = 0;
+        return Vec::new();
+    };
+    let len = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    if len < *offset {
+        *offset = 0;
+    }
+    let mut text = String::new();
+    if file.seek(SeekFrom::Start(*offset)).is_err() || file.read_to_string(&mut text).is_err() {
+        return Vec::new();
+    }
+    *offset += text.len() as u64;
+    text.lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .map(|note| {
+            let field = |name: &str| note[name].as_str().unwrap_or_default().to_string();
+            Message::Notification { kind: field("kind"), title: field("title"), body: field("body"), urgency: field("urgency") }
+        })
+        .collect()
+}
+
+// =============================================================================
+// Server
+// =============================================================================
+
+/// Body of `hulios tray-server`: answer applets on the socket systemd
+/// passes in, or on SOCKET when started by hand
+pub fn serve() -> Result<()> {
+    let listener = match sdnotify::listen_fd() {
+        Some(fd) => UnixListener::from(fd),
+        None => helper::bind(SOCKET)?,
+    };
+    let subscribers: Arc<Mutex<Vec<Client>>> = Arc::default();
+    {
+        let subscribers = subscribers.clone();
+        thread::spawn(move || broadcast(&subscribers));
+    }
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let subscribers = subscribers.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &subscribers) {
+                eprintln!("[!] Tray client failed: {:#}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Send state changes and new notifications to every subscriber, dropping
+/// the ones that went away
+fn broadcast(subscribers: &Mutex<Vec<Client>>) {
+    // Only what happens from now on
+    let mut offset = fs::metadata(EVENTS).map(|meta| meta.len()).unwrap_or(0);
+    let mut last: Option<TrayState> = None;
+    loop {
+        thread::sleep(POLL);
+        let mut messages = read_events(&mut offset);
+        if subscribers.lock().unwrap().is_empty() {
+            continue;
+        }
+        let state = TrayState::current();
+        if last.as_ref() != Some(&state) {
+            last = Some(state.clone());
+            messages.insert(0, Message::State(state));
+        }
+        for message in &messages {
+            subscribers.lock().unwrap().retain(|client| send(client, message));
+        }
+    }
+}
+
+fn handle(stream: UnixStream, subscribers: &Mutex<Vec<Client>>) -> Result<()> {
+    let user = helper::authorize(&stream);
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let client: Client = Arc::new(Mutex::new(stream));
+    let user = match user {
+        Ok(user) => user,
+        Err(e) => {
+            send(&client, &Message::Error { message: format!("{:#}", e) });
+            return Err(e);
+        }
+    };
+    send(&client, &Message::Hello { protocol: PROTOCOL, version: env!("CARGO_PKG_VERSION") });
+
+    loop {
+        let mut line = String::new();
+        if (&mut reader).take(MAX_REQUEST).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request: Request = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                send(&client, &Message::Error { message: format!("Invalid request: {}", e) });
+                continue;
+            }
+        };
+        match request {
+            Request::State => {
+                send(&client, &Message::State(TrayState::current()));
+            }
+            Request::Subscribe => {
+                send(&client, &Message::State(TrayState::current()));
+                subscribers.lock().unwrap().push(client.clone());
+            }
+            action => {
+                let done = perform(&action, &user);
+                send(&client, &done);
+            }
+        }
+    }
+}
+
+/// Run an action as root for `user`: its own `hulios` process like the
+/// helper's, except for NEWNYM, which is one control port command
+fn perform(action: &Request, user: &str) -> Message {
+    let args: Vec<&str> = match action {
+        Request::Start => vec!["start"],
+        Request::Stop => vec!["stop"],
+        Request::Restart => vec!["restart"],
+        Request::Reload => vec!["reload"],
+        Request::SetMode { mode } => vec!["mode", "set", match mode {
+            TrayMode::Off => "off",
+            TrayMode::Full => "full",
+            TrayMode::DnsOnly => "dns-only",
+        }],
+        Request::Newnym => {
+            audit::record("tray", &format!("{} requested: newnym", user));
+            let result = ControlClient::connect().and_then(|mut client| client.command("SIGNAL NEWNYM"));
+            return Message::Done { request: "newnym", ok: result.is_ok(), error: result.err().map(|e| format!("{:#}", e)) };
+        }
+        Request::State | Request::Subscribe => unreachable!(),
+    };
+    let request = match action {
+        Request::SetMode { .. } => "set_mode",
+        _ => args[0],
+    };
+    audit::record("tray", &format!("{} requested: hulios {}", user, args.join(" ")));
+
+    let output = std::env::current_exe()
+        .context("Cannot locate the hulios binary")
+        .and_then(|exe| {
+            Command::new(exe)
+                .arg("--yes")
+                .args(&args)
+                // Audited and notified as the user who asked, like under sudo
+                .env("SUDO_USER", user)
+                .stdin(Stdio::null())
+                .run_output()
+                .context("Failed to run hulios")
+        });
+    match output {
+        Ok(output) if output.status.success() => Message::Done { request, ok: true, error: None },
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let error = stderr.lines().chain(stdout.lines()).rfind(|line| line.contains("[!]"))
+                .map(|line| line.trim().to_string())
+                .unwrap_or_else(|| format!("hulios {} failed", args.join(" ")));
+            Message::Done { request, ok: false, error: Some(error) }
+        }
+        Err(e) => Message::Done { request, ok: false, error: Some(format!("{:#}", e)) },
+    }
+}
+
+/// False once the client is gone
+fn send(client: &Mutex<UnixStream>, message: &Message) -> bool {
+    match serde_json::to_string(message) {
+        Ok(json) => writeln!(client.lock().unwrap(), "{}", json).is_ok(),
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::{Result, Context};
+use chrono::{NaiveDateTime, Utc};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::config::Config;
+use crate::control::AsyncControlClient;
+use crate::journal::{self, Priority};
+use crate::{amnesic, engine, i18n, report, state};
+
+/// Circuit statistics of the running Tor, kept by the daemon
+pub const CIRCUITS_PATH: &str = "/var/lib/hulios/circuits.json";
+
+/// Latest circuit outcomes the analysis looks at
+const WINDOW: usize = 100;
+
+/// Outcomes needed before performance counts as consistently poor
+const MIN_SAMPLES: usize = 30;
+
+/// Share of failed circuits that counts as poor
+const POOR_FAILURE_RATE: f64 = 0.3;
+
+/// Share of failed circuits at which the direct connection is given up
+/// for `tor.bridges`
+const BRIDGE_FAILURE_RATE: f64 = 0.5;
+
+/// Median build time that counts as slow (Tor's typical median is 1-2 s)
+const SLOW_BUILD: Duration = Duration::from_secs(5);
+
+/// Wait before following a Tor that isn't up (yet) or just restarted
+const RECONNECT: Duration = Duration::from_secs(10);
+
+/// Circuits built by one Tor process
+#[derive(Deserialize, Serialize, Default)]
+pub struct Circuits {
+    pub tor_pid: u32,
+    pub built: u32,
+    pub failed: u32,
+    pub timed_out: u32,
+    /// The last WINDOW outcomes, oldest first: build time in ms, or None
+    /// for a circuit that failed
+    recent: VecDeque<Option<u32>>,
+    /// Tor's current CircuitBuildTimeout, learned or fixed, in ms
+    pub timeout_ms: Option<u32>,
+    /// Adjustments tor.auto_tune already made to this Tor
+    pub applied: Vec<Tuning>,
+}
+
+/// An adjustment for consistently poor circuit performance
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tuning {
+    /// Let Tor learn its circuit build timeout instead of the fixed one
+    LearnTimeout,
+    /// Spread circuits over two guards instead of one slow one
+    MoreGuards,
+    /// Connect through tor.bridges instead of directly
+    Bridges,
+}
+
+impl Tuning {
+    fn describe(self) -> String {
+        let id = match self {
+            Tuning::LearnTimeout => "tuning-learn-timeout",
+            Tuning::MoreGuards => "tuning-more-guards",
+            Tuning::Bridges => "tuning-bridges",
+        };
+        i18n::tr(id, &[])
+    }
+
+    /// How to make the change by hand
+    fn hint(self) -> String {
+        let id = match self {
+            Tuning::LearnTimeout => "tuning-learn-timeout-hint",
+            Tuning::MoreGuards => "tuning-more-guards-hint",
+            Tuning::Bridges => "tuning-bridges-hint",
+        };
+        i18n::tr(id, &[])
+    }
+
+    /// Make the change on the running Tor for the rest of its session
+    async fn apply(self, config: &Config) -> Result<()> {
+        let command = match self {
+            Tuning::LearnTimeout => "SETCONF LearnCircuitBuildTimeout=1 CircuitBuildTimeout".to_string(),
+            Tuning::MoreGuards => "SETCONF NumEntryGuards=2".to_string(),
+            Tuning::Bridges => {
+                let mut command = "SETCONF UseBridges=1".to_string();
+                for bridge in &config.tor.bridges {
+                    command.push_str(&format!(" Bridge={}", quote(bridge)));
+                }
+                if let Some(plugin) = &config.tor.transport_plugin {
+                    command.push_str(&format!(" ClientTransportPlugin={}", quote(plugin)));
+                }
+                command
+            }
+        };
+        // A connection of its own: the one following events can't take replies
+        AsyncControlClient::connect().await?.command(&command).await?;
+        if self == Tuning::Bridges {
+            // So `reload` keeps them, like after a bootstrap fallback
+            if let Some(mut session) = state::load() {
+                session.bridges = true;
+                state::save(&session)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Circuits {
+    /// Take in one CIRC or BUILDTIMEOUT_SET event; false if it says
+    /// nothing about circuit performance
+    fn record(&mut self, event: &str) -> bool {
+        let mut words = event.split(' ');
+        let field = |name: &str| {
+            event.split(' ').find_map(|word| word.strip_prefix(name)?.strip_prefix('='))
+        };
+        match (words.next(), words.next(), words.next()) {
+            (Some("BUILDTIMEOUT_SET"), _, _) => {
+                self.timeout_ms = field("TIMEOUT_MS").and_then(|ms| ms.parse().ok());
+                true
+            }
+            // Circuits for exits; onion service and directory circuits behave differently
+            (Some("CIRC"), _, Some("BUILT")) if field("PURPOSE") == Some("GENERAL") => {
+                let Some(created) = field("TIME_CREATED")
+                    .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.f").ok())
+                else {
+                    return false;
+                };
+                let ms = (Utc::now().naive_utc() - created).num_milliseconds().max(0);
+                self.built += 1;
+                self.push(Some(ms.min(u32::MAX as i64) as u32));
+                true
+            }
+            // Timed-out circuits are kept a while to measure, under their own purpose
+            (Some("CIRC"), _, Some("FAILED")) if matches!(field("PURPOSE"), Some("GENERAL" | "MEASURE_TIMEOUT")) => {
+                self.failed += 1;
+                if field("REASON") == Some("TIMEOUT") {
+                    self.timed_out += 1;
+                }
+                self.push(None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, outcome: Option<u32>) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(outcome);
+    }
+
+    /// Share of the recent circuits that failed
+    fn failure_rate(&self) -> f64 {
+        if self.recent.is_empty() {
+            return 0.0;
+        }
+        self.recent.iter().filter(|outcome| outcome.is_none()).count() as f64 / self.recent.len() as f64
+    }
+
+    /// Build time at `quantile` (0.5 for the median) of the recent circuits
+    fn build_time(&self, quantile: f64) -> Option<Duration> {
+        let mut times: Vec<u32> = self.recent.iter().flatten().copied().collect();
+        if times.is_empty() {
+            return None;
+        }
+        times.sort_unstable();
+        let index = ((times.len() - 1) as f64 * quantile).round() as usize;
+        Some(Duration::from_millis(times[index] as u64))
+    }
+
+    /// Adjustments for consistently poor performance, leaving out the ones
+    /// already made. Nothing is suggested on too few circuits.
+    fn suggestions(&self, config: &Config, bridges_active: bool) -> Vec<Tuning> {
+        if self.recent.len() < MIN_SAMPLES {
+            return Vec::new();
+        }
+        let failing = self.failure_rate();
+        let slow = self.build_time(0.5).is_some_and(|median| median >= SLOW_BUILD);
+        let mut suggestions = Vec::new();
+        if config.tor.circuit_build_timeout.is_some() && failing >= POOR_FAILURE_RATE {
+            suggestions.push(Tuning::LearnTimeout);
+        }
+        if (slow || failing >= POOR_FAILURE_RATE) && !config.tor.options.contains_key("NumEntryGuards") {
+            suggestions.push(Tuning::MoreGuards);
+        }
+        if failing >= BRIDGE_FAILURE_RATE && !config.tor.bridges.is_empty() && !bridges_active {
+            suggestions.push(Tuning::Bridges);
+        }
+        suggestions.retain(|tuning| !self.applied.contains(tuning));
+        suggestions
+    }
+}
+
+// =============================================================================
+// Daemon
+// =============================================================================
+
+/// Follow the session's Tor over the control port, collecting circuit
+/// build times, and with tor.auto_tune apply what the analysis suggests
+pub async fn watch() {
+    loop {
+        // Fails whenever Tor isn't up, or was restarted and needs following anew
+        let _ = follow().await;
+        tokio::time::sleep(RECONNECT).await;
+    }
+}
+
+async fn follow() -> Result<()> {
+    let tor_pid = engine::tor_pid().with_context(|| i18n::tr("tuning-no-tor-session", &[]))?;
+    let config = Config::load().unwrap_or_default();
+    let mut client = AsyncControlClient::connect().await?;
+    client.command("SETEVENTS CIRC BUILDTIMEOUT_SET").await?;
+    let mut circuits = load()
+        .filter(|circuits| circuits.tor_pid == tor_pid)
+        .unwrap_or_else(|| Circuits { tor_pid, ..Default::default() });
+
+    loop {
+        let event = client.next_event().await?;
+        if !circuits.record(&event) {
+            continue;
+        }
+        if config.tor.auto_tune {
+            let bridges = state::load().is_some_and(|session| session.bridges);
+            for tuning in circuits.suggestions(&config, bridges) {
+                if let Err(e) = tuning.apply(&config).await {
+                    journal::log(Priority::Warning, &format!("Could not tune Tor: {:#}", e), &[]);
+                    continue;
+                }
+                journal::log(Priority::Notice, &format!("Tuned Tor: {}", tuning.describe()),
+                    &[("HULIOS_EVENT", "tuned")]);
+                circuits.applied.push(tuning);
+                // Judge the change on the circuits built after it
+                circuits.recent.clear();
+            }
+        }
+        let _ = save(&circuits);
+    }
+}
+
+// =============================================================================
+// Reporting
+// =============================================================================
+
+/// The circuit section of `hulios stats`, for the running Tor
+pub fn print_analysis() {
+    let Some(circuits) = load().filter(|circuits| Some(circuits.tor_pid) == engine::tor_pid()) else {
+        return;
+    };
+    let config = Config::load().unwrap_or_default();
+    let bridges = state::load().is_some_and(|session| session.bridges);
+
+    println!("    {}", i18n::tr("tuning-circuits-built-failed-timed", &[("built", &circuits.built.to_string()), ("failed", &circuits.failed.to_string()), ("timed_out", &circuits.timed_out.to_string())]));
+    if let (Some(median), Some(slowest)) = (circuits.build_time(0.5), circuits.build_time(0.9)) {
+        let median = format!("{:.1}", median.as_secs_f64());
+        let slowest = format!("{:.1}", slowest.as_secs_f64());
+        let line = match circuits.timeout_ms {
+            Some(ms) => i18n::tr("tuning-build-time-timeout", &[
+                ("median", &median), ("slowest", &slowest), ("timeout", &format!("{:.1}", ms as f64 / 1000.0)),
+            ]),
+            None => i18n::tr("tuning-build-time", &[("median", &median), ("slowest", &slowest)]),
+        };
+        println!("    {}", line);
+    }
+    let suggestions = circuits.suggestions(&config, bridges);
+    for tuning in &suggestions {
+        println!("{} {}", format!("[!] {}", i18n::tr("tuning-suggestion", &[])).yellow(), tuning.describe());
+        println!("    {}", i18n::tr("tuning-fix", &[("hint", &tuning.hint())]));
+    }
+    if !suggestions.is_empty() && !config.tor.auto_tune {
+        println!("[*] {}", i18n::tr("tuning-set-tor-auto-tune", &[]));
+    }
+    for tuning in &circuits.applied {
+        println!("[+] {}", i18n::tr("tuning-tuned", &[("tuning", &tuning.describe())]));
+    }
+
+    report::data("circuits", serde_json::json!({
+        "built": circuits.built,
+        "failed": circuits.failed,
+        "timed_out": circuits.timed_out,
+        "failure_rate": circuits.failure_rate(),
+        "median_build_ms": circuits.build_time(0.5).map(|time| time.as_millis() as u64),
+        "timeout_ms": circuits.timeout_ms,
+        "suggestions": suggestions,
+        "applied": circuits.applied,
+    }));
+}
+
+// =============================================================================
+// Persistence
+// =============================================================================
+
+fn load() -> Option<Circuits> {
+    let content = fs::read_to_string(amnesic::redirect(CIRCUITS_PATH)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save(circuits: &Circuits) -> Result<()> {
+    let path = amnesic::redirect(CIRCUITS_PATH);
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_string(circuits)?)?;
+    fs::rename(&tmp, &path).with_context(|| i18n::tr("error-write", &[("path", &path)]))?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::config::Config;
+use crate::{audit, doh, engine, helper, i18n, lsm, onion, relay, tray};
+use crate::exec::Run;
+use crate::{report, resolvers};
+
+/// Files HULIOS may install into the system. Anything that installs a new
+/// unit, hook or rules file must be listed here so uninstall removes it.
+const INSTALLED_FILES: &[&str] = &[
+    "/etc/systemd/system/hulios.service",
+    "/etc/systemd/system/multi-user.target.wants/hulios.service",
+    "/etc/NetworkManager/dispatcher.d/90-hulios",
+    "/etc/iptables/hulios.rules",
+    "/etc/nftables.d/hulios.nft",
+    engine::RESOLVED_DROPIN,
+    resolvers::UNBOUND_DROPIN,
+    onion::SSHD_DROPIN,
+    helper::SOCKET_UNIT_LINK,
+    helper::SOCKET_UNIT,
+    helper::SERVICE_UNIT,
+    tray::SOCKET_UNIT_LINK,
+    tray::SOCKET_UNIT,
+    tray::SERVICE_UNIT,
+];
+
+/// Directories holding HULIOS state and caches
+const STATE_DIRS: &[&str] = &["/var/lib/hulios", "/run/hulios"];
+
+/// Configuration, only removed with --purge
+const CONFIG_DIR: &str = "/etc/hulios";
+
+/// Return the system to stock: stop everything and undo every change
+/// (via recover), then delete installed files and HULIOS state
+pub fn uninstall(purge: bool) -> Result<()> {
+    engine::recover()?;
+    // Its keys go with /var/lib/hulios
+    relay::halt();
+
+    if Path::new(helper::SOCKET_UNIT).exists() {
+        let _ = Command::new("systemctl")
+            .args(["disable", "--now", "hulios-helper.socket", "hulios-helper.service", "hulios-tray.socket", "hulios-tray.service"])
+            .run();
+    }
+    let mut units_removed = false;
+    for path in INSTALLED_FILES {
+        if remove(path) && path.starts_with("/etc/systemd/") {
+            units_removed = true;
+        }
+    }
+    if units_removed {
+        let _ = Command::new("systemctl").arg("daemon-reload").run();
+    }
+    // Browser policies, only where HULIOS wrote them itself
+    doh::remove_policies()?;
+    // The SELinux module and AppArmor rules of `hulios lsm install`
+    lsm::remove(&Config::load().unwrap_or_default())?;
+
+    for dir in STATE_DIRS {
+        remove(dir);
+    }
+    if purge {
+        remove(CONFIG_DIR);
+    } else if Path::new(CONFIG_DIR).exists() {
+        println!("[*] {}", i18n::tr("uninstall-kept-use-purge-remove", &[("path", CONFIG_DIR)]));
+    }
+    // The record of what HULIOS did to this machine outlives HULIOS itself
+    if Path::new(audit::AUDIT_LOG).exists() {
+        println!("[*] {}", i18n::tr("uninstall-kept-audit-log", &[("path", audit::AUDIT_LOG)]));
+    }
+
+    println!("[+] {}", i18n::tr("uninstall-removed-system-binary-itself", &[]));
+    Ok(())
+}
+
+fn remove(path: &str) -> bool {
+    let path = Path::new(path);
+    let removed = if path.is_dir() {
+        fs::remove_dir_all(path).is_ok()
+    } else {
+        fs::remove_file(path).is_ok()
+    };
+    if removed {
+        println!("[*] {}", i18n::tr("doh-removed", &[("path", &path.display().to_string())]));
+        report::action(format!("Removed {}", path.display()));
+    }
+    removed
+}
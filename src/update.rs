@@ -0,0 +1,194 @@
+use anyhow::{Result, Context};
+use colored::*;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use users::get_current_uid;
+use crate::config::Config;
+use crate::exec::Run;
+use crate::{audit, confirm, engine, i18n, report};
+
+/// Where the release feed is published, unless update.feed_url says otherwise
+const DEFAULT_FEED_URL: &str = "https://github.com/ghaziwali/hulios/releases/latest/download/latest.json";
+
+/// The minisign key releases are signed with, built in by release builds
+const RELEASE_KEY: Option<&str> = option_env!("HULIOS_RELEASE_KEY");
+
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The latest release, as the feed describes it
+#[derive(Deserialize)]
+struct Release {
+    version: String,
+    /// Whether it fixes a security issue
+    #[serde(default)]
+    security: bool,
+    #[serde(default)]
+    notes: Option<String>,
+    /// Binary URL per architecture, e.g. "x86_64"; each is signed in
+    /// "<url>.minisig"
+    binaries: BTreeMap<String, String>,
+}
+
+// =============================================================================
+// Command
+// =============================================================================
+
+/// `hulios update [--check]`: look for a newer release and, unless only
+/// checking, install it. Returns whether there was one.
+pub fn run(check: bool, assume_yes: bool) -> Result<bool> {
+    let config = Config::load()?;
+    let current = env!("CARGO_PKG_VERSION");
+    let client = http_client()?;
+
+    let url = config.update.feed_url.as_deref().unwrap_or(DEFAULT_FEED_URL);
+    println!("[*] {}", i18n::tr("update-checking", &[("url", url)]));
+    let release: Release = client.get(url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.json())
+        .with_context(|| i18n::tr("update-failed-read-release-feed", &[("url", url)]))?;
+
+    let newer = parse_version(&release.version)? > parse_version(current)?;
+    report::data("update", serde_json::json!({
+        "current": current,
+        "latest": release.version,
+        "available": newer,
+        "security": newer && release.security,
+    }));
+    if !newer {
+        println!("{}", format!("[+] {}", i18n::tr("update-up-date", &[("current", current)])).green());
+        return Ok(false);
+    }
+    let id = if release.security { "update-security-available" } else { "update-available" };
+    println!("{}", format!("[!] {}", i18n::tr(id, &[("version", &release.version), ("current", current)])).yellow());
+    if let Some(notes) = &release.notes {
+        for line in notes.lines() {
+            println!("    {}", line);
+        }
+    }
+    if check {
+        println!("[*] {}", i18n::tr("update-install-sudo-update", &[]));
+        return Ok(true);
+    }
+
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let key = public_key(&config)?;
+    let arch = std::env::consts::ARCH;
+    let binary_url = release.binaries.get(arch)
+        .with_context(|| i18n::tr("update-release-no-build", &[("version", &release.version), ("arch", arch)]))?;
+    let exe = std::env::current_exe()
+        .and_then(fs::canonicalize)
+        .with_context(|| i18n::tr("error-no-binary", &[]))?;
+    confirm::confirm(&i18n::tr("confirm-updating", &[("path", &exe.display().to_string()), ("version", &release.version)]), &confirm::changes(confirm::UPDATE_CHANGES), assume_yes)?;
+
+    println!("[*] {}", i18n::tr("update-downloading", &[("binary_url", binary_url)]));
+    let binary = download(&client, binary_url)?;
+    let signature = String::from_utf8(download(&client, &format!("{}.minisig", binary_url))?)
+        .with_context(|| i18n::tr("update-signature-not-text", &[]))?;
+    verify(&key, &binary, &signature, &release.version, arch)?;
+    println!("{}", format!("[+] {}", i18n::tr("update-signature-verified", &[])).green());
+
+    install(&exe, &binary)?;
+    println!("{}", format!("[+] {}", i18n::tr("update-updated", &[("version", &release.version)])).green());
+    if exe.components().any(|part| part.as_os_str() == "target") {
+        println!("[*] {}", i18n::tr("update-binary-was-built-git", &[("current", current)]));
+    }
+    if engine::is_tor_running() {
+        println!("[*] {}", i18n::tr("update-run-sudo-restart-session", &[]));
+    }
+    report::action(format!("Updated HULIOS from {} to {}", current, release.version));
+    Ok(true)
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Through Tor while it runs; nothing else is allowed out then
+fn http_client() -> Result<reqwest::blocking::Client> {
+    if engine::is_tor_running() {
+        return engine::tor_http_client(TIMEOUT);
+    }
+    println!("{}", format!("[!] {}", i18n::tr("update-not-running-checking-updates", &[])).yellow());
+    Ok(reqwest::blocking::Client::builder().timeout(TIMEOUT).build()?)
+}
+
+fn download(client: &reqwest::blocking::Client, url: &str) -> Result<Vec<u8>> {
+    let bytes = client.get(url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.bytes())
+        .with_context(|| i18n::tr("error-download", &[("url", url)]))?;
+    Ok(bytes.to_vec())
+}
+
+fn public_key(config: &Config) -> Result<PublicKey> {
+    let key = config.update.public_key.as_deref().or(RELEASE_KEY).with_context(|| i18n::tr("update-build-no-release-signing", &[]))?;
+    PublicKey::from_base64(key.trim()).map_err(|e| anyhow::anyhow!(i18n::tr("update-invalid-release-signing-key", &[("error", &e.to_string())])))
+}
+
+/// Check the signature, and that its trusted comment names this release,
+/// so an older signed binary can't be passed off as the new one
+fn verify(key: &PublicKey, binary: &[u8], signature: &str, version: &str, arch: &str) -> Result<()> {
+    let signature = Signature::decode(signature).map_err(|e| anyhow::anyhow!(i18n::tr("update-invalid-signature-file", &[("error", &e.to_string())])))?;
+    key.verify(binary, &signature, false)
+        .map_err(|e| anyhow::anyhow!(i18n::tr("update-new-binary-signature-does", &[("error", &e.to_string())])))?;
+    let expected = format!("hulios {} {}", version, arch);
+    if signature.trusted_comment() != expected {
+        anyhow::bail!(i18n::tr("update-signature-not-not-installing", &[("trusted_comment", signature.trusted_comment()), ("expected", &expected)]));
+    }
+    Ok(())
+}
+
+/// Write the new binary next to `exe` and rename it over it, so `exe` is
+/// always one whole binary. It has to run before it replaces anything.
+fn install(exe: &Path, binary: &[u8]) -> Result<()> {
+    let tmp: PathBuf = exe.with_file_name(".hulios.update");
+    let mode = fs::metadata(exe).map(|meta| meta.permissions().mode() & 0o7777).unwrap_or(0o755);
+    let _ = fs::remove_file(&tmp);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o700)
+        .open(&tmp)
+        .with_context(|| i18n::tr("error-create", &[("path", &tmp.display().to_string())]))?;
+    file.write_all(binary)?;
+    file.sync_all()?;
+    drop(file);
+
+    let runs = Command::new(&tmp)
+        .arg("--help")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .run_timeout(Duration::from_secs(10))
+        .is_ok_and(|status| status.success());
+    if !runs {
+        let _ = fs::remove_file(&tmp);
+        anyhow::bail!(i18n::tr("update-new-binary-does-not", &[("exe", &exe.display().to_string())]));
+    }
+
+    fs::set_permissions(&tmp, fs::Permissions::from_mode(mode))?;
+    audit::record("write", &exe.display().to_string());
+    fs::rename(&tmp, exe).with_context(|| i18n::tr("update-failed-replace", &[("exe", &exe.display().to_string())]))?;
+    Ok(())
+}
+
+/// "1.2.3" or "v1.2.3" as numbers, for comparing; anything after '-' or
+/// '+' is ignored
+fn parse_version(version: &str) -> Result<Vec<u64>> {
+    let core = version.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next().unwrap_or(core);
+    core.split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| i18n::tr("update-invalid-version", &[("version", version)]))
+}
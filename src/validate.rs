@@ -0,0 +1,555 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use crate::config::{self, Config, DnsOnlyResolver, FirewallMode, GuestPolicy, IoClass, OtherTraffic, Profile, Redirect, SchedPolicy, CONFIG_PATH};
+use crate::{alias, appliance, blocklist, cgroup, engine, i18n, netprofile, overlay, report, setconf};
+
+/// One problem found in the config file
+#[derive(Serialize)]
+pub struct Issue {
+    /// Dotted path of the offending key, e.g. `tor.bandwidth_burst`
+    pub field: String,
+    /// 1-based line in the file, when the key could be located
+    pub line: Option<usize>,
+    pub message: String,
+    /// Warnings are reported but don't make the config invalid
+    pub warning: bool,
+}
+
+/// `hulios config validate`: report every problem in the file
+pub fn run(path: Option<&str>) -> Result<()> {
+    let path = path.unwrap_or(CONFIG_PATH);
+    if !Path::new(path).exists() {
+        println!("[*] {}", i18n::tr("validate-does-not-exist-built", &[("path", path)]));
+        return Ok(());
+    }
+
+    let source = fs::read_to_string(path).with_context(|| i18n::tr("error-read", &[("path", path)]))?;
+    let config: Config = toml::from_str(&source).with_context(|| i18n::tr("error-parse", &[("path", path)]))?;
+    let issues = check(&config, &source);
+    report::data("issues", &issues);
+
+    for issue in &issues {
+        let location = issue.line.map(|l| format!("{} ", i18n::tr("configtool-line", &[("line", &l.to_string())]))).unwrap_or_default();
+        let tag = if issue.warning { "[*]".yellow() } else { "[!]".red() };
+        println!("{} {}{}: {}", tag, location, issue.field.bold(), issue.message);
+    }
+
+    let errors = issues.iter().filter(|i| !i.warning).count();
+    if errors > 0 {
+        anyhow::bail!(i18n::tr("validate-error", &[("path", path), ("errors", &errors.to_string())]));
+    }
+    println!("{}", format!("[+] {}", i18n::tr("validate-valid", &[("path", path)])).green());
+    Ok(())
+}
+
+/// Load the config and refuse it if it has any errors (used by start)
+pub fn load() -> Result<Config> {
+    let source = match fs::read_to_string(CONFIG_PATH) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| i18n::tr("error-read", &[("path", CONFIG_PATH)])),
+    };
+    let config = Config::parse(&source).with_context(|| i18n::tr("error-parse", &[("path", CONFIG_PATH)]))?;
+    let config = config::with_overrides(config)?;
+
+    let errors: Vec<String> = check(&config, &source)
+        .into_iter()
+        .filter(|issue| !issue.warning)
+        .map(|issue| format!("{}: {}", issue.field, issue.message))
+        .collect();
+    if !errors.is_empty() {
+        anyhow::bail!("{}\n  {}", i18n::tr("validate-invalid-config", &[("path", CONFIG_PATH)]), errors.join("\n  "));
+    }
+    Ok(config)
+}
+
+// =============================================================================
+// Checks
+// =============================================================================
+
+/// Semantic checks on top of what parsing already enforces (types, unknown keys)
+pub fn check(config: &Config, source: &str) -> Vec<Issue> {
+    let mut issues = Issues { source, list: Vec::new() };
+
+    // [status]
+    let status = &config.status;
+    if status.endpoints.is_empty() {
+        issues.error("status", 0, "endpoints", &i18n::tr("validate-least-one-verification-endpoint", &[]));
+    }
+    for (i, endpoint) in status.endpoints.iter().enumerate() {
+        if let Err(e) = check_url(&endpoint.url) {
+            issues.error("status.endpoints", i, "url", &e);
+        }
+    }
+    if let Err(e) = check_url(&status.exit_list_url) {
+        issues.error("status", 0, "exit_list_url", &e);
+    }
+    if !status.exit_list.starts_with('/') {
+        issues.error("status", 0, "exit_list", &i18n::tr("validate-must-absolute-path", &[]));
+    }
+    if status.exit_list_max_age == 0 {
+        issues.error("status", 0, "exit_list_max_age", &i18n::tr("validate-must-be-positive-seconds", &[]));
+    }
+
+    // [geoip]
+    let geoip = &config.geoip;
+    for (key, path) in [("database", &geoip.database), ("asn_database", &geoip.asn_database)] {
+        if !path.starts_with('/') {
+            issues.error("geoip", 0, key, &i18n::tr("validate-must-absolute-path", &[]));
+        }
+    }
+    for (key, url) in [("database_url", &geoip.database_url), ("asn_database_url", &geoip.asn_database_url)] {
+        if let Err(e) = check_url(&url.replace("{month}", "2000-01")) {
+            issues.error("geoip", 0, key, &e);
+        }
+    }
+
+    // [tor]
+    let tor = &config.tor;
+    let rate = tor.bandwidth_rate.as_deref().map(bandwidth_bytes);
+    let burst = tor.bandwidth_burst.as_deref().map(bandwidth_bytes);
+    if let Some(Err(e)) = &rate {
+        issues.error("tor", 0, "bandwidth_rate", &format!("{:#}", e));
+    }
+    if let Some(Err(e)) = &burst {
+        issues.error("tor", 0, "bandwidth_burst", &format!("{:#}", e));
+    }
+    if let (Some(Ok(rate)), Some(Ok(burst))) = (&rate, &burst) {
+        if burst < rate {
+            issues.error("tor", 0, "bandwidth_burst", &i18n::tr("validate-must-least-bandwidth-rate", &[]));
+        }
+    }
+    if let Some(pending) = tor.max_client_circuits_pending {
+        if !(1..=1024).contains(&pending) {
+            issues.error("tor", 0, "max_client_circuits_pending", &i18n::tr("validate-must-be-1-to-1024", &[]));
+        }
+    }
+    if tor.circuit_build_timeout == Some(0) {
+        issues.error("tor", 0, "circuit_build_timeout", &i18n::tr("validate-must-be-positive-seconds", &[]));
+    }
+    if tor.bootstrap_timeout == Some(0) {
+        issues.error("tor", 0, "bootstrap_timeout", &i18n::tr("validate-must-be-positive-seconds", &[]));
+    }
+    let memory_max = tor.memory_max.as_deref().map(cgroup::memory_bytes);
+    let memory_high = tor.memory_high.as_deref().map(cgroup::memory_bytes);
+    for (key, value) in [("memory_max", &memory_max), ("memory_high", &memory_high)] {
+        match value {
+            Some(Err(e)) => issues.error("tor", 0, key, &format!("{:#}", e)),
+            Some(Ok(0)) => issues.error("tor", 0, key, &i18n::tr("validate-must-be-positive", &[])),
+            _ => {}
+        }
+    }
+    if let (Some(Ok(max)), Some(Ok(high))) = (&memory_max, &memory_high) {
+        if high >= max {
+            issues.warning("tor", 0, "memory_high", &i18n::tr("validate-no-effect-above-memory", &[]));
+        }
+    }
+    if tor.cpu_quota == Some(0) {
+        issues.error("tor", 0, "cpu_quota", &i18n::tr("validate-must-be-positive-percent", &[]));
+    }
+    if tor.nice.is_some_and(|nice| !(-20..=19).contains(&nice)) {
+        issues.error("tor", 0, "nice", &i18n::tr("validate-must-be-nice", &[]));
+    }
+    if tor.io_priority.is_some_and(|priority| priority > 7) {
+        issues.error("tor", 0, "io_priority", &i18n::tr("validate-must-be-io-priority", &[]));
+    }
+    if tor.io_priority.is_some() && tor.io_class == Some(IoClass::Idle) {
+        issues.warning("tor", 0, "io_priority", &i18n::tr("validate-no-effect-io-class", &[]));
+    }
+    if tor.nice.is_some() && tor.sched_policy == Some(SchedPolicy::Idle) {
+        issues.warning("tor", 0, "nice", &i18n::tr("validate-no-effect-sched-policy", &[]));
+    }
+    if tor.persistent && config.privacy.secure_delete {
+        issues.warning("tor", 0, "persistent", &i18n::tr("validate-keeps-tor-guards-directory", &[]));
+    }
+    if tor.restart_on_oom && tor.confine {
+        issues.warning("tor", 0, "restart_on_oom", &i18n::tr("validate-no-effect-tor-confine", &[]));
+    }
+    if tor.use_bridges && tor.bridges.is_empty() {
+        issues.error("tor", 0, "use_bridges", &i18n::tr("validate-needs-tor-bridges", &[]));
+    }
+    if tor.transport_plugin.is_some() && tor.bridges.is_empty() {
+        issues.warning("tor", 0, "transport_plugin", &i18n::tr("validate-no-effect-without-tor", &[]));
+    }
+    if tor.no_exec && tor.transport_plugin.is_some() {
+        issues.error("tor", 0, "no_exec", &i18n::tr("validate-stops-tor-running-tor", &[]));
+    }
+    if let Some(binary) = &tor.binary {
+        if !binary.starts_with('/') {
+            issues.error("tor", 0, "binary", &i18n::tr("validate-must-absolute-path-path", &[]));
+        }
+    }
+    if let Some(port) = tor.http_tunnel_port {
+        if port == 0 || [9050, 9051, 9052, 9061].contains(&port) {
+            issues.error("tor", 0, "http_tunnel_port", &i18n::tr("validate-must-be-free-port", &[]));
+        }
+    }
+    if let Some(hash) = &tor.binary_sha256 {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            issues.error("tor", 0, "binary_sha256", &i18n::tr("validate-must-64-hexadecimal-characters", &[]));
+        }
+    }
+
+    for (name, value) in &tor.options {
+        if let Err(e) = setconf::check(name, value) {
+            issues.error("tor.options", 0, name, &format!("{:#}", e));
+        }
+    }
+
+    // [daemon]
+    if let Some(port) = config.daemon.health_port {
+        if port == 0 || [9050, 9051, 9052, 9061].contains(&port) || Some(port) == tor.http_tunnel_port {
+            issues.error("daemon", 0, "health_port", &i18n::tr("validate-must-be-free-port-health", &[]));
+        }
+    }
+    if let Some(address) = &config.daemon.remote_control {
+        match address.parse::<std::net::SocketAddr>() {
+            Ok(address) if address.port() == 0 => issues.error("daemon", 0, "remote_control", "needs a port, e.g. 192.168.1.1:9300"),
+            Ok(_) => {}
+            Err(_) => issues.error("daemon", 0, "remote_control", &i18n::tr("validate-not-address-port", &[("address", address)])),
+        }
+    }
+    if config.daemon.rotate_identity == Some(0) {
+        issues.error("daemon", 0, "rotate_identity", &i18n::tr("validate-must-least-1-minute", &[]));
+    }
+
+    // [power.battery] and [power.metered]
+    for (table, profile) in [("power.battery", &config.power.battery), ("power.metered", &config.power.metered)] {
+        let rate = profile.bandwidth_rate.as_deref().map(bandwidth_bytes);
+        let burst = profile.bandwidth_burst.as_deref().map(bandwidth_bytes);
+        if let Some(Err(e)) = &rate {
+            issues.error(table, 0, "bandwidth_rate", &format!("{:#}", e));
+        }
+        if let Some(Err(e)) = &burst {
+            issues.error(table, 0, "bandwidth_burst", &format!("{:#}", e));
+        }
+        if let (Some(Ok(rate)), Some(Ok(burst))) = (&rate, &burst) {
+            if burst < rate {
+                issues.error(table, 0, "bandwidth_burst", &i18n::tr("validate-must-least-bandwidth-rate", &[]));
+            }
+        }
+        if profile.bandwidth_burst.is_some() && profile.bandwidth_rate.is_none() {
+            issues.warning(table, 0, "bandwidth_burst", &i18n::tr("validate-no-effect-without-bandwidth", &[]));
+        }
+        if profile.monitor_interval == Some(0) {
+            issues.error(table, 0, "monitor_interval", &i18n::tr("validate-must-be-positive-seconds", &[]));
+        }
+        if !profile.rotate_identity && config.daemon.rotate_identity.is_none() {
+            issues.warning(table, 0, "rotate_identity", &i18n::tr("validate-no-effect-without-daemon", &[]));
+        }
+    }
+
+    // [messages]
+    for id in config.messages.keys() {
+        if !i18n::exists(id) {
+            issues.warning("messages", 0, id, &i18n::tr("validate-not-message-id-see", &[]));
+        }
+    }
+
+    // [update]
+    if let Some(key) = &config.update.public_key {
+        if minisign_verify::PublicKey::from_base64(key).is_err() {
+            issues.error("update", 0, "public_key", &i18n::tr("validate-not-minisign-public-key", &[]));
+        }
+    }
+
+    // [onion]
+    if config.onion.ssh_port == 0 {
+        issues.error("onion", 0, "ssh_port", &i18n::tr("validate-must-sshd-port-e", &[]));
+    }
+
+    // [relay]
+    let relay = &config.relay;
+    let own_ports = [9050, 9051, 9052, 9053, 9061];
+    if relay.or_port == 0 || own_ports.contains(&relay.or_port) {
+        issues.error("relay", 0, "or_port", &i18n::tr("validate-must-be-free-port-relay", &[]));
+    }
+    if let Some(nickname) = &relay.nickname {
+        if !(1..=19).contains(&nickname.len()) || !nickname.chars().all(|c| c.is_ascii_alphanumeric()) {
+            issues.error("relay", 0, "nickname", &i18n::tr("validate-must-1-19-letters", &[]));
+        }
+    }
+    if relay.contact_info.as_deref().is_some_and(|contact| contact.contains(['\n', '\r'])) {
+        issues.error("relay", 0, "contact_info", &i18n::tr("validate-must-single-line", &[]));
+    }
+    let relay_rate = relay.bandwidth_rate.as_deref().map(bandwidth_bytes);
+    let relay_burst = relay.bandwidth_burst.as_deref().map(bandwidth_bytes);
+    if let Some(Err(e)) = &relay_rate {
+        issues.error("relay", 0, "bandwidth_rate", &format!("{:#}", e));
+    }
+    if let Some(Err(e)) = &relay_burst {
+        issues.error("relay", 0, "bandwidth_burst", &format!("{:#}", e));
+    }
+    if let (Some(Ok(rate)), Some(Ok(burst))) = (&relay_rate, &relay_burst) {
+        if burst < rate {
+            issues.error("relay", 0, "bandwidth_burst", &i18n::tr("validate-must-least-bandwidth-rate", &[]));
+        }
+    }
+    if relay.transport_plugin.is_some() && !relay.bridge {
+        issues.warning("relay", 0, "transport_plugin", &i18n::tr("validate-no-effect-unless-bridge", &[]));
+    }
+    if relay.transport_plugin.is_some() != relay.transport_port.is_some() {
+        issues.error("relay", 0, "transport_port", &i18n::tr("validate-transport-plugin-transport-port", &[]));
+    }
+    if relay.transport_port.is_some_and(|port| port == 0 || port == relay.or_port || own_ports.contains(&port)) {
+        issues.error("relay", 0, "transport_port", &i18n::tr("validate-must-be-free-port-transport", &[]));
+    }
+
+    // [gateway]
+    let gateway = &config.gateway;
+    if let Some(interface) = &gateway.lan_interface {
+        let name = interface.strip_suffix('+').unwrap_or(interface);
+        if name.is_empty() || name.len() > 15 || name.contains(['/', ' ', ':', '+']) {
+            issues.error("gateway", 0, "lan_interface", &i18n::tr("validate-not-interface-name-prefix", &[("interface", interface)]));
+        } else if name == "lo" {
+            issues.error("gateway", 0, "lan_interface", &i18n::tr("validate-must-interface-facing-lan", &[]));
+        }
+        if interface.ends_with('+') && gateway.listen_address.is_none() {
+            issues.error("gateway", 0, "listen_address", &i18n::tr("validate-needed-interface-prefix-e", &[]));
+        }
+        // Tor's TransProxyType is global, and the LAN is redirected by NAT
+        if config.firewall.redirect == Redirect::Tproxy {
+            issues.error("gateway", 0, "lan_interface", &i18n::tr("validate-gateway-mode-needs-firewall", &[]));
+        }
+    } else if config.profile == Profile::Appliance {
+        issues.error("gateway", 0, "lan_interface", &i18n::tr("validate-appliance-profile-needs-interface", &[]));
+    } else if gateway.listen_address.is_some() || gateway.socks || gateway.tor_ports_only {
+        issues.warning("gateway", 0, "lan_interface", &i18n::tr("validate-not-set-so-other", &[]));
+    }
+    if let Some(address) = &gateway.listen_address {
+        match address.parse::<std::net::Ipv4Addr>() {
+            Ok(address) if address.is_loopback() || address.is_unspecified() => {
+                issues.error("gateway", 0, "listen_address", &i18n::tr("validate-must-address-clients-reach", &[]));
+            }
+            Ok(_) => {}
+            Err(_) => issues.error("gateway", 0, "listen_address", &i18n::tr("validate-not-ipv4-address", &[("address", address)])),
+        }
+    }
+    // The firewall lets the clients reach the remote control on the LAN interface only
+    let remote_control = config.daemon.remote_control.as_deref().and_then(|address| address.parse::<SocketAddr>().ok());
+    if let (Some(interface), Some(control)) = (&gateway.lan_interface, remote_control) {
+        let mut addresses: Vec<Ipv4Addr> = gateway.listen_address.iter().filter_map(|address| address.parse().ok()).collect();
+        if !interface.ends_with('+') {
+            addresses.extend(appliance::lan_addresses(interface).unwrap_or_default());
+        }
+        match control.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() || ip.is_loopback() || addresses.contains(&ip) => {}
+            _ if addresses.is_empty() => issues.warning("daemon", 0, "remote_control",
+                &i18n::tr("validate-can-checked-against-which", &[("interface", interface)])),
+            ip => issues.error("daemon", 0, "remote_control",
+                &i18n::tr("validate-not-address-lan-interface", &[("ip", &ip.to_string()), ("interface", interface)])),
+        }
+    }
+
+    // [libvirt]
+    let libvirt = &config.libvirt;
+    for bridge in libvirt.bridges.keys() {
+        if bridge.is_empty() || bridge.len() > 15 || bridge.contains(['/', ' ', ':', '+']) {
+            issues.error("libvirt.bridges", 0, bridge, &i18n::tr("validate-not-interface-name", &[]));
+        }
+    }
+    // Torified guests are redirected by NAT, like the LAN of gateway mode
+    let torified = libvirt.guests == GuestPolicy::Torify || libvirt.bridges.values().any(|policy| *policy == GuestPolicy::Torify);
+    if torified && config.firewall.redirect == Redirect::Tproxy {
+        issues.error("libvirt", 0, "guests", &i18n::tr("validate-torifying-guests-needs-firewall", &[]));
+    }
+
+    // [i2p]
+    let i2p = &config.i2p;
+    if i2p.enabled {
+        if i2p.socks_port == 0 || own_ports.contains(&i2p.socks_port) || [overlay::DNS_PORT, overlay::TRANS_PORT].contains(&i2p.socks_port) {
+            issues.error("i2p", 0, "socks_port", &i18n::tr("validate-must-be-free-port-i2p", &[]));
+        }
+        // The overlay router is reached by NAT, like the LAN of gateway mode
+        if config.firewall.redirect == Redirect::Tproxy {
+            issues.error("i2p", 0, "enabled", &i18n::tr("validate-i2p-needs-firewall-redirect", &[]));
+        }
+    }
+    if i2p.binary.as_deref().is_some_and(|path| !path.starts_with('/')) {
+        issues.error("i2p", 0, "binary", &i18n::tr("validate-must-absolute-path", &[]));
+    }
+
+    // [network_profiles] and [[networks]]
+    for (name, profile) in &config.network_profiles {
+        for key in ["network_profiles", "networks", "profile"] {
+            if profile.contains_key(key) {
+                issues.error("network_profiles", 0, name, &i18n::tr("validate-network-profile-can-set", &[("key", key)]));
+            }
+        }
+        if let Err(e) = netprofile::apply(source, profile) {
+            issues.error("network_profiles", 0, name, &i18n::tr("validate-does-not-fit-config", &[("error", &format!("{:#}", e).trim().replace('\n', " "))]));
+        }
+    }
+    for (i, binding) in config.networks.iter().enumerate() {
+        if !config.network_profiles.contains_key(&binding.profile) {
+            issues.error("networks", i, "profile", &i18n::tr("validate-no-network-profiles", &[("profile", &binding.profile)]));
+        }
+        if let Some(Err(e)) = binding.subnet.as_deref().map(netprofile::parse_subnet) {
+            issues.error("networks", i, "subnet", &format!("{:#}", e));
+        }
+    }
+
+    // [dns_only]
+    let dns_only = &config.dns_only;
+    if dns_only.resolver == DnsOnlyResolver::Doh {
+        if !dns_only.doh_url.starts_with("https://") {
+            issues.error("dns_only", 0, "doh_url", &i18n::tr("validate-must-https-url", &[]));
+        }
+        // Looking the DoH host up would go through the resolver itself
+        if dns_only.doh_addresses.is_empty() {
+            issues.error("dns_only", 0, "doh_addresses", &i18n::tr("validate-must-list-addresses-doh", &[]));
+        }
+    }
+    for address in &dns_only.doh_addresses {
+        if address.parse::<std::net::IpAddr>().is_err() {
+            issues.error("dns_only", 0, "doh_addresses", &i18n::tr("error-not-ip", &[("address", address)]));
+        }
+    }
+    if dns_only.dnscrypt_binary.as_deref().is_some_and(|path| !path.starts_with('/')) {
+        issues.error("dns_only", 0, "dnscrypt_binary", &i18n::tr("validate-must-absolute-path", &[]));
+    }
+
+    // [firewall]
+    let firewall = &config.firewall;
+    let mut ranges = Vec::new();
+    for port in &firewall.torify_ports {
+        match port.range() {
+            Ok(range) => ranges.push(range),
+            Err(e) => issues.error("firewall", 0, "torify_ports", &format!("{:#}", e)),
+        }
+    }
+    match firewall.mode {
+        FirewallMode::Partial if firewall.torify_ports.is_empty() => {
+            issues.error("firewall", 0, "torify_ports", &i18n::tr("validate-partial-mode-needs-least", &[]));
+        }
+        FirewallMode::Full | FirewallMode::OnionOnly if !firewall.torify_ports.is_empty() => {
+            issues.warning("firewall", 0, "torify_ports", &i18n::tr("validate-no-effect-unless-mode", &[]));
+        }
+        _ => {}
+    }
+    if firewall.mode == FirewallMode::OnionOnly && firewall.other_traffic == OtherTraffic::Allow {
+        issues.warning("firewall", 0, "other_traffic", &i18n::tr("validate-ignored-onion-only-mode", &[]));
+    }
+    ranges.sort();
+    for pair in ranges.windows(2) {
+        if pair[1].0 <= pair[0].1 {
+            issues.warning("firewall", 0, "torify_ports", &i18n::tr("validate-ranges-overlap", &[("low", &pair[0].0.to_string()), ("high", &pair[0].1.to_string()), ("other_low", &pair[1].0.to_string()), ("other_high", &pair[1].1.to_string())]));
+        }
+    }
+
+    for entry in &firewall.block {
+        if let Err(e) = blocklist::parse(entry) {
+            issues.error("firewall", 0, "block", &format!("{:#}", e));
+        }
+    }
+
+    if let Some(url) = &firewall.doh_list_url {
+        if let Err(e) = check_url(url) {
+            issues.error("firewall", 0, "doh_list_url", &e);
+        }
+    }
+    if firewall.block_doh && firewall.mode == FirewallMode::OnionOnly {
+        issues.warning("firewall", 0, "block_doh", &i18n::tr("validate-no-effect-onion-only", &[]));
+    }
+
+    // [aliases]
+    for (name, target) in &config.aliases {
+        if let Err(e) = alias::check_name(name).and_then(|()| alias::check_target(target)) {
+            issues.error("aliases", 0, name, &format!("{:#}", e));
+        }
+    }
+
+    // [notifications]
+    let notifications = &config.notifications;
+    if let Some(url) = &notifications.webhook {
+        if let Err(e) = check_url(url) {
+            issues.error("notifications", 0, "webhook", &e);
+        }
+    }
+    if notifications.enabled {
+        for event in notifications.urgency.keys() {
+            if !notifications.events.contains(event) {
+                issues.warning("notifications.urgency", 0, event.as_str(), &i18n::tr("validate-no-effect-event-not", &[]));
+            }
+        }
+    }
+
+    issues.list
+}
+
+/// Collects issues and locates their line in the source
+struct Issues<'a> {
+    source: &'a str,
+    list: Vec<Issue>,
+}
+
+impl Issues<'_> {
+    fn error(&mut self, table: &str, nth: usize, key: &str, message: &str) {
+        self.push(table, nth, key, message, false);
+    }
+
+    fn warning(&mut self, table: &str, nth: usize, key: &str, message: &str) {
+        self.push(table, nth, key, message, true);
+    }
+
+    fn push(&mut self, table: &str, nth: usize, key: &str, message: &str, warning: bool) {
+        let field = if table == "status.endpoints" || table == "networks" {
+            format!("{}[{}].{}", table, nth, key)
+        } else {
+            format!("{}.{}", table, key)
+        };
+        self.list.push(Issue {
+            field,
+            line: line_of(self.source, table, nth, key),
+            message: message.to_string(),
+            warning,
+        });
+    }
+}
+
+/// 1-based line of `key` in the `nth` occurrence of `[table]` / `[[table]]`
+fn line_of(source: &str, table: &str, nth: usize, key: &str) -> Option<usize> {
+    let mut current = "";
+    let mut occurrence = 0;
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            let header = line.split('#').next().unwrap_or("").trim();
+            current = header.trim_matches(|c| c == '[' || c == ']').trim();
+            if current == table {
+                occurrence += 1;
+            }
+            continue;
+        }
+        let name = line.split('=').next().unwrap_or("").trim().trim_matches('"');
+        if current == table && occurrence == nth + 1 && name == key && line.contains('=') {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+fn check_url(url: &str) -> std::result::Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| i18n::tr("validate-invalid-url", &[("url", url), ("error", &e.to_string())]))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(i18n::tr("validate-must-http-https-url", &[("url", url)]));
+    }
+    Ok(())
+}
+
+/// Bandwidth setting in bytes, using the same parser as the torrc
+fn bandwidth_bytes(value: &str) -> Result<u64> {
+    let normalized = engine::parse_bandwidth(value)?;
+    let (amount, unit) = normalized.split_once(' ').with_context(|| i18n::tr("validate-invalid-bandwidth", &[]))?;
+    let multiplier: u64 = match unit {
+        "KBytes" => 1 << 10,
+        "MBytes" => 1 << 20,
+        "GBytes" => 1 << 30,
+        _ => 1,
+    };
+    Ok(amount.parse::<u64>()?.saturating_mul(multiplier))
+}
@@ -0,0 +1,195 @@
+use anyhow::{Result, Context};
+use std::net::{Ipv4Addr, TcpStream, UdpSocket};
+use std::time::Duration;
+use crate::config::Config;
+use crate::firewall::{self, Policy};
+use crate::{doh, engine, i18n, report, status};
+
+/// Hostname resolved by the DNS checks
+const PROBE_HOST: &str = "check.torproject.org";
+
+/// Minimal leak check run after the rules are in place. Each check prints
+/// its result; the first failure is returned as the error.
+pub fn run(config: &Config) -> Result<()> {
+    println!("[*] {}", i18n::tr("verify-verifying-tor-routing", &[]));
+
+    let policy = Policy::from_config(config);
+    if policy.onion_only {
+        check("verify-check-direct-blocked", direct_udp_blocked)?;
+        check("verify-check-clearnet-blocked", clearnet_tcp_blocked)?;
+        println!("[*] {}", i18n::tr("verify-dns-http-exit-checks", &[]));
+        return Ok(());
+    }
+
+    check("verify-check-dns-through-tor", || resolve("127.0.0.1:9061"))?;
+    check("verify-check-outside-dns-redirected", || resolve("1.1.1.1:53"))?;
+    if endpoints_torified(config, &policy) {
+        check("verify-check-http-through-tor", || {
+            let status = status::check_status(&config.status)?;
+            if !status.is_tor {
+                anyhow::bail!(i18n::tr("verify-exit-ip-not-tor", &[("ip", &status.ip)]));
+            }
+            Ok(())
+        })?;
+    } else {
+        println!("[*] {}", i18n::tr("verify-http-exit-check-skipped", &[]));
+    }
+    if !policy.is_partial_allow() {
+        check("verify-check-direct-blocked", direct_udp_blocked)?;
+    }
+
+    Ok(())
+}
+
+/// `hulios test`: the leak check against the running session, then the
+/// DoH blocklist and browsers that would bypass the DNS redirect
+pub fn test(config: &Config) -> Result<()> {
+    if !engine::is_tor_running() {
+        anyhow::bail!(i18n::tr("verify-not-running-start-first", &[]));
+    }
+    run(config)?;
+
+    let policy = Policy::from_config(config);
+    if config.firewall.block_doh && !policy.onion_only {
+        check("verify-check-doh-blocked", doh::blocked)?;
+    }
+    let findings = doh::detect();
+    for finding in &findings {
+        eprintln!("[!] {}", finding);
+        report::warning(finding.clone());
+    }
+    if !findings.is_empty() {
+        println!("[*] {}", i18n::tr("verify-their-lookups-skip-dns", &[]));
+    }
+    icmp_notice(config, &policy);
+    Ok(())
+}
+
+/// Tor carries TCP only, so ping can't go through it: say where it goes
+/// instead, before a failed ping is taken for a broken session
+fn icmp_notice(config: &Config, policy: &Policy) {
+    if policy.is_partial_allow() {
+        eprintln!("[!] {}", i18n::tr("verify-ping-traceroute-leave-directly", &[]));
+        report::warning("ICMP leaves outside Tor");
+        return;
+    }
+    let dropped = match firewall::backend(config).icmp_dropped() {
+        Some(packets) => i18n::tr("verify-icmp-dropped-count", &[("packets", &packets.to_string())]),
+        None => i18n::tr("verify-icmp-dropped", &[]),
+    };
+    eprintln!("[!] {}", dropped);
+    println!("[*] {}", i18n::tr("verify-test-reachability-over-tcp", &[]));
+    report::warning("ICMP is dropped; ping won't traverse Tor");
+}
+
+/// In partial mode the exit check only means something if its ports go via Tor
+fn endpoints_torified(config: &Config, policy: &Policy) -> bool {
+    config.status.endpoints.iter().all(|endpoint| {
+        reqwest::Url::parse(&endpoint.url)
+            .ok()
+            .and_then(|url| url.port_or_known_default())
+            .is_some_and(|port| policy.torifies(port))
+    })
+}
+
+fn check(id: &str, test: impl FnOnce() -> Result<()>) -> Result<()> {
+    let name = i18n::tr(id, &[]);
+    match test() {
+        Ok(()) => {
+            println!("[+] {}", i18n::tr("verify-ok", &[("name", &name)]));
+            report::action(format!("Verified: {}", name));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("[!] {}", i18n::tr("verify-failed", &[("name", &name), ("error", &format!("{:#}", e))]));
+            Err(e.context(i18n::tr("verify-verification-failed", &[("name", &name)])))
+        }
+    }
+}
+
+/// Send an A query for PROBE_HOST and require at least one answer
+pub fn resolve(server: &str) -> Result<()> {
+    lookup(server, PROBE_HOST).map(|_| ())
+}
+
+/// Resolve `host` to its IPv4 addresses through the DNS server at `server`
+pub fn lookup(server: &str, host: &str) -> Result<Vec<Ipv4Addr>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let id = (std::process::id() & 0xffff) as u16;
+    let mut query = Vec::new();
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00]); // recursion desired
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // 1 question
+    for label in host.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.extend_from_slice(&[0, 0, 1, 0, 1]); // root, type A, class IN
+
+    socket.send_to(&query, server).with_context(|| i18n::tr("verify-failed-query", &[("server", server)]))?;
+    let mut reply = [0u8; 512];
+    let len = socket.recv(&mut reply).with_context(|| i18n::tr("verify-no-dns-answer", &[("server", server)]))?;
+    let reply = &reply[..len];
+
+    if len < 12 || reply[0..2] != id.to_be_bytes() {
+        anyhow::bail!(i18n::tr("verify-malformed-dns-reply", &[("server", server)]));
+    }
+    let rcode = reply[3] & 0x0f;
+    let answers = u16::from_be_bytes([reply[6], reply[7]]);
+    if rcode != 0 || answers == 0 {
+        anyhow::bail!(i18n::tr("verify-returned-no-records-rcode", &[("server", server), ("host", host), ("rcode", &rcode.to_string())]));
+    }
+
+    // Skip the echoed question, then collect the A records
+    let mut offset = skip_name(reply, 12).with_context(|| i18n::tr("verify-malformed-dns-reply-answer", &[]))? + 4;
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(reply, offset).with_context(|| i18n::tr("verify-malformed-dns-reply-answer", &[]))?;
+        let header = reply.get(offset..offset + 10).with_context(|| i18n::tr("error-truncated-dns", &[]))?;
+        let kind = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data = reply.get(offset + 10..offset + 10 + length).with_context(|| i18n::tr("error-truncated-dns", &[]))?;
+        if kind == 1 && length == 4 {
+            addresses.push(Ipv4Addr::new(data[0], data[1], data[2], data[3]));
+        }
+        offset += 10 + length;
+    }
+    Ok(addresses)
+}
+
+/// Offset just past the (possibly compressed) name starting at `offset`
+fn skip_name(msg: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(offset)? as usize;
+        if len == 0 {
+            return Some(offset + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(offset + 2);
+        }
+        offset += 1 + len;
+    }
+}
+
+/// Onion-only mode must drop TCP to anything outside the automapped range
+fn clearnet_tcp_blocked() -> Result<()> {
+    let target = "198.51.100.1:80".parse()?;
+    match TcpStream::connect_timeout(&target, Duration::from_secs(5)) {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => Ok(()),
+        Err(e) => anyhow::bail!(i18n::tr("error-unexpected", &[("error", &e.to_string())])),
+        Ok(_) => anyhow::bail!(i18n::tr("verify-tcp-connection-left-machine", &[])),
+    }
+}
+
+/// UDP that isn't DNS has no Tor path, so the firewall must drop it
+pub fn direct_udp_blocked() -> Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    // TEST-NET-2, never routed on the real internet either way
+    match socket.send_to(b"hulios", "198.51.100.1:9") {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => Ok(()),
+        Err(e) => anyhow::bail!(i18n::tr("error-unexpected", &[("error", &e.to_string())])),
+        Ok(_) => anyhow::bail!(i18n::tr("verify-udp-packet-left-machine", &[])),
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Serialize;
+use std::fs;
+use std::process::{Command, Stdio};
+use crate::config::Config;
+use crate::exec::Run;
+use crate::{engine, firewall, preflight, report, sdnotify};
+
+/// The commit HULIOS was built from, when built from a git checkout
+const COMMIT: Option<&str> = option_env!("HULIOS_GIT_COMMIT");
+
+/// What a bug report needs to know about this build and machine
+#[derive(Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub commit: Option<&'static str>,
+    pub target: String,
+    pub tor: Option<String>,
+    /// "v1.8.10 (nf_tables)", or "v1.8.7 (legacy)"
+    pub iptables: Option<String>,
+    pub firewall_backend: String,
+    pub init: String,
+    pub distro: Option<String>,
+    pub kernel: Option<String>,
+    pub container: Option<String>,
+}
+
+impl BuildInfo {
+    pub fn gather() -> BuildInfo {
+        let config = Config::load().unwrap_or_default();
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: COMMIT,
+            target: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            tor: tor_version(&config),
+            iptables: first_line("iptables", &["--version"]).map(|line| line.trim_start_matches("iptables ").to_string()),
+            firewall_backend: firewall::backend(&config).name().to_string(),
+            init: init_system(),
+            distro: distro(),
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease").ok().map(|release| release.trim().to_string()),
+            container: preflight::container(),
+        }
+    }
+
+    /// "key: value" lines to paste into a bug report
+    pub fn lines(&self) -> Vec<String> {
+        let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "not found".to_string());
+        vec![
+            format!("hulios: {} ({})", self.version, self.commit.unwrap_or("unknown commit")),
+            format!("target: {}", self.target),
+            format!("tor: {}", unknown(&self.tor)),
+            format!("iptables: {}", unknown(&self.iptables)),
+            format!("firewall backend: {}", self.firewall_backend),
+            format!("init: {}", self.init),
+            format!("distro: {}", unknown(&self.distro)),
+            format!("kernel: {}", unknown(&self.kernel)),
+            format!("container: {}", self.container.as_deref().unwrap_or("none")),
+        ]
+    }
+}
+
+/// `hulios version [--full]`
+pub fn print(full: bool) {
+    if !full {
+        match COMMIT {
+            Some(commit) => println!("hulios {} ({})", env!("CARGO_PKG_VERSION"), commit),
+            None => println!("hulios {}", env!("CARGO_PKG_VERSION")),
+        }
+        report::data("version", env!("CARGO_PKG_VERSION"));
+        report::data("commit", COMMIT);
+        return;
+    }
+    let info = BuildInfo::gather();
+    for line in info.lines() {
+        println!("{}", line);
+    }
+    report::data("build", &info);
+}
+
+/// "0.4.8.13", from the tor HULIOS would run; only one that passes its
+/// ownership checks is run as root
+fn tor_version(config: &Config) -> Option<String> {
+    let binary = engine::resolve_tor_binary(&config.tor).ok()?;
+    // "Tor version 0.4.8.13."
+    let line = first_line(&binary, &["--version"])?;
+    let version = line.strip_prefix("Tor version ")?.trim_end_matches('.');
+    Some(version.to_string())
+}
+
+fn init_system() -> String {
+    if sdnotify::booted() {
+        return "systemd".to_string();
+    }
+    // OpenRC, runit, s6, sysvinit...
+    let comm = fs::read_to_string("/proc/1/comm").unwrap_or_default();
+    if std::path::Path::new("/run/openrc").is_dir() {
+        return format!("openrc ({})", comm.trim());
+    }
+    match comm.trim() {
+        "" => "unknown".to_string(),
+        comm => comm.to_string(),
+    }
+}
+
+/// PRETTY_NAME from os-release, e.g. "Debian GNU/Linux 12 (bookworm)"
+fn distro() -> Option<String> {
+    let release = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    release.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        Some(value.trim_matches('"').to_string())
+    })
+}
+
+fn first_line(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).stderr(Stdio::null()).run_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).lines().next().map(|line| line.trim().to_string())
+}
@@ -0,0 +1,199 @@
+use anyhow::{Result, Context};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use users::get_current_uid;
+use crate::config::Config;
+use crate::exec::Run;
+use crate::firewall::{self, Attachment};
+use crate::{audit, confirm, features, i18n, lock, report, state};
+
+/// Kept under /var/lib so `hulios recover` can still detach after a reboot
+const ATTACHED_PATH: &str = "/var/lib/hulios/attached.json";
+const RESOLV_BACKUP: &str = "/var/lib/hulios/attached.resolv.conf";
+const RESOLV_PATH: &str = "/etc/resolv.conf";
+
+/// What `hulios attach-gateway` changed, so it can be undone
+#[derive(Deserialize, Serialize)]
+struct Attached {
+    #[serde(flatten)]
+    attachment: Attachment,
+    /// `ip -4 route show default` before attaching, e.g.
+    /// "default via 10.0.2.2 dev eth0 proto dhcp metric 100"
+    previous_route: Option<String>,
+}
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+/// `hulios attach-gateway <ip>`: use a HULIOS gateway (another VM, or the
+/// router of this LAN) instead of a local session. Routes and DNS go to the
+/// gateway and the firewall lets nothing else out, so this machine needs no
+/// Tor of its own.
+pub fn attach(gateway: &str, assume_yes: bool) -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let _lock = lock::acquire()?;
+    let gateway: Ipv4Addr = gateway.parse().with_context(|| i18n::tr("workstation-not-ipv4-address", &[("gateway", gateway)]))?;
+    if load().is_some() {
+        anyhow::bail!(i18n::tr("workstation-already-attached-gateway-run", &[]));
+    }
+    if state::load().is_some() {
+        anyhow::bail!(i18n::tr("workstation-session-active-here-stop", &[]));
+    }
+    let interface = direct_interface(gateway)?;
+    let subnet = subnet(&interface)?;
+    confirm::confirm(&i18n::tr("confirm-attaching", &[("gateway", &gateway.to_string())]), &confirm::changes(confirm::ATTACH_CHANGES), assume_yes)?;
+
+    let attached = Attached {
+        attachment: Attachment { gateway, interface, subnet },
+        previous_route: default_route(),
+    };
+    save(&attached)?;
+    let attachment = &attached.attachment;
+
+    // The firewall first, so nothing leaves around the gateway meanwhile
+    let config = Config::load()?;
+    let features = features::probe(&config);
+    firewall::adapted_backend(&config, &features).attach(attachment)?;
+
+    Command::new("ip")
+        .args(["-4", "route", "replace", "default", "via", &gateway.to_string(), "dev", &attachment.interface, "onlink"])
+        .run()
+        .with_context(|| i18n::tr("workstation-failed-route-through-gateway", &[]))?;
+    println!("[+] {}", i18n::tr("workstation-default-route-through", &[("gateway", &gateway.to_string()), ("interface", &attachment.interface)]));
+
+    let _ = Command::new("chattr").args(["-i", RESOLV_PATH]).run();
+    // fs::copy follows symlinks, like cp -L
+    let _ = fs::copy(RESOLV_PATH, RESOLV_BACKUP);
+    let _ = fs::remove_file(RESOLV_PATH);
+    audit::record("write", RESOLV_PATH);
+    fs::write(RESOLV_PATH, format!("# HULIOS - gateway DNS\n# DO NOT MODIFY - This file is managed by HULIOS\nnameserver {}\n", gateway))
+        .with_context(|| i18n::tr("engine-failed-write-resolv-conf", &[]))?;
+    let _ = Command::new("chattr").args(["+i", RESOLV_PATH]).run();
+    println!("[+] {}", i18n::tr("workstation-dns-now-points-gateway", &[]));
+
+    println!("{}", format!("[+] {}", i18n::tr("workstation-attached-gateway", &[("gateway", &gateway.to_string())])).green());
+    println!("[*] {}", i18n::tr("workstation-check-or-undo", &[]));
+    report::action(format!("Attached to the gateway {}", gateway));
+    report::data("attachment", &attached.attachment);
+    Ok(())
+}
+
+/// `hulios detach-gateway`: undo `attach-gateway`
+pub fn detach() -> Result<()> {
+    if get_current_uid() != 0 {
+        anyhow::bail!(i18n::tr("error-not-root", &[]));
+    }
+    let _lock = lock::acquire()?;
+    if load().is_none() {
+        println!("[*] {}", i18n::tr("workstation-not-attached-gateway", &[]));
+        return Ok(());
+    }
+    release()?;
+    println!("{}", format!("[+] {}", i18n::tr("workstation-detached-gateway-networking-restored", &[])).green());
+    report::action("Detached from the gateway");
+    Ok(())
+}
+
+/// Undo an attachment, if there is one: open the firewall and restore the
+/// route and resolv.conf. Also used by `hulios recover`.
+pub fn release() -> Result<()> {
+    let Some(attached) = load() else {
+        return Ok(());
+    };
+    firewall::flush_all()?;
+
+    let attachment = &attached.attachment;
+    let _ = Command::new("ip")
+        .args(["-4", "route", "del", "default", "via", &attachment.gateway.to_string(), "dev", &attachment.interface])
+        .stderr(Stdio::null())
+        .run();
+    if let Some(route) = &attached.previous_route {
+        let _ = Command::new("ip").args(["-4", "route", "replace"]).args(route.split_whitespace()).run();
+    }
+    println!("[+] {}", i18n::tr("workstation-default-route-restored", &[]));
+
+    let _ = Command::new("chattr").args(["-i", RESOLV_PATH]).run();
+    audit::record("restore", RESOLV_PATH);
+    if fs::metadata(RESOLV_BACKUP).is_ok() {
+        let _ = fs::remove_file(RESOLV_PATH);
+        let _ = fs::copy(RESOLV_BACKUP, RESOLV_PATH);
+        let _ = fs::remove_file(RESOLV_BACKUP);
+    }
+    println!("[+] {}", i18n::tr("workstation-dns-configuration-restored", &[]));
+
+    let _ = fs::remove_file(ATTACHED_PATH);
+    Ok(())
+}
+
+/// Whether this machine is attached to a gateway
+pub fn is_attached() -> bool {
+    load().is_some()
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn load() -> Option<Attached> {
+    let content = fs::read_to_string(ATTACHED_PATH).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn save(attached: &Attached) -> Result<()> {
+    if let Some(parent) = Path::new(ATTACHED_PATH).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(ATTACHED_PATH, serde_json::to_string_pretty(attached)?)
+        .with_context(|| i18n::tr("error-write", &[("path", ATTACHED_PATH)]))
+}
+
+/// The interface `gateway` is reached on without another router in
+/// between, as a gateway must be
+fn direct_interface(gateway: Ipv4Addr) -> Result<String> {
+    let output = Command::new("ip")
+        .args(["-4", "route", "get", &gateway.to_string()])
+        .run_output()
+        .with_context(|| i18n::tr("error-no-iproute2", &[]))?;
+    // "10.137.0.1 dev eth0 src 10.137.0.5 uid 0"
+    let route = String::from_utf8_lossy(&output.stdout);
+    let words: Vec<&str> = route.split_whitespace().collect();
+    if words.contains(&"via") {
+        anyhow::bail!(i18n::tr("workstation-not-machine-network-reached", &[("gateway", &gateway.to_string())]));
+    }
+    words
+        .windows(2)
+        .find(|pair| pair[0] == "dev")
+        .map(|pair| pair[1].to_string())
+        .with_context(|| i18n::tr("workstation-no-route", &[("gateway", &gateway.to_string())]))
+}
+
+/// The IPv4 network of `interface`, e.g. (10.137.0.0, 24)
+fn subnet(interface: &str) -> Result<(Ipv4Addr, u8)> {
+    let output = Command::new("ip")
+        .args(["-4", "-o", "addr", "show", "dev", interface])
+        .run_output()
+        .with_context(|| i18n::tr("error-no-iproute2", &[]))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let (address, prefix) = text
+        .split_whitespace()
+        .skip_while(|word| *word != "inet")
+        .nth(1)
+        .and_then(|cidr| cidr.split_once('/'))
+        .and_then(|(address, prefix)| Some((address.parse::<Ipv4Addr>().ok()?, prefix.parse::<u8>().ok()?)))
+        .with_context(|| i18n::tr("workstation-no-ipv4-address", &[("interface", interface)]))?;
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    Ok((Ipv4Addr::from(u32::from(address) & mask), prefix))
+}
+
+fn default_route() -> Option<String> {
+    let output = Command::new("ip").args(["-4", "route", "show", "default"]).run_output().ok()?;
+    let route = String::from_utf8_lossy(&output.stdout);
+    route.lines().next().map(|line| line.trim().to_string()).filter(|line| !line.is_empty())
+}